@@ -11,5 +11,20 @@ fn main() {
     println!("cargo:rustc-link-search=native={}", manifest_dir);
     println!("cargo:rustc-link-arg=-T{}/memory.x", manifest_dir);
     println!("cargo:rustc-link-arg=-T{}/link.x", manifest_dir);
-    println!("cargo:rustc-link-arg=--no-relax");
+
+    // gp is the software shadow-stack pointer unless `sw-ss-s11` moves it
+    // to s11; only then may the linker relax.  s11 is only safe if LLVM
+    // never allocates it, which the s11 target spec arranges.
+    if std::env::var_os("CARGO_FEATURE_SW_SS_S11").is_some() {
+        let target = std::env::var("TARGET").unwrap();
+        if !target.contains("-s11-") {
+            panic!(
+                "the sw-ss-s11 feature needs s11 reserved from the compiler: \
+                 build with --target rv32imac-cfi-s11-none-elf.json (got {})",
+                target
+            );
+        }
+    } else {
+        println!("cargo:rustc-link-arg=--no-relax");
+    }
 }
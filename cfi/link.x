@@ -31,6 +31,12 @@ SECTIONS
         _bss_end = .;
     } > RAM
 
+    /* HTIF mailbox (`htif` feature, Spike).  fesvr finds tohost and
+     * fromhost by symbol; they get a 64-byte line to themselves */
+    .tohost : ALIGN(64) {
+        *(.tohost)
+    } > RAM
+
     /* Regular stack (grows down) */
     .stack (NOLOAD) : ALIGN(16) {
         _stack_bottom = .;
@@ -52,6 +58,11 @@ SECTIONS
         _sw_shadow_stack_top = .;
     } > RAM
 
+    /* A push with the pointer here would exceed the maximum depth */
+    _sw_shadow_stack_limit = _sw_shadow_stack_bottom + 4 * _sw_shadow_stack_max_depth;
+    ASSERT(_sw_shadow_stack_limit <= _sw_shadow_stack_top,
+           "_sw_shadow_stack_max_depth exceeds the software shadow stack's capacity")
+
     /* Discard debug-related sections that cause issues */
     /DISCARD/ : {
         *(.eh_frame)
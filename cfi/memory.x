@@ -7,3 +7,7 @@ MEMORY
 /* Shadow stack regions */
 _shadow_stack_size = 4K;
 _sw_shadow_stack_size = 4K;
+
+/* Deepest software shadow stack the push path allows, in entries: a
+ * policy limit, up to the region's capacity (_sw_shadow_stack_size / 4) */
+_sw_shadow_stack_max_depth = 128;
@@ -15,9 +15,11 @@
 
 #![no_std]
 #![no_main]
+#![feature(fn_align)]
 
 use core::arch::{asm, global_asm, naked_asm};
 use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
 
 // ============================================================================
 // CFI Instruction Encodings
@@ -28,8 +30,9 @@ use core::panic::PanicInfo;
 //   lpad N       = (N << 12) | 0x17
 //
 // Zicfiss (Shadow Stack):
-//   sspush ra    = 0x6010_0073
-//   sspopchk ra  = 0x6050_0073
+//   sspush ra    = 0xce10_4073
+//   sspopchk ra  = 0xcdc0_c073
+//   ssrdp rd     = 0xcdc0_4073 | (rd << 7)
 //
 // These are encoded in the Zimop (May-Be-Operations) space. On hardware
 // without Zicfiss/Zicfilp, they are guaranteed to execute as NOPs.
@@ -53,7 +56,6 @@ const KCFI_TYPE_FN_FP_U32_U32: u32 = 0x4B43_4650; // ASCII "KCFP"
 /// Insert a landing pad with label 0 (unlabeled).
 /// On Zicfilp hardware: indirect branches must land here or the CPU faults.
 /// On other hardware: executes as NOP.
-#[allow(unused_macros)]
 macro_rules! lpad {
     () => {
         core::arch::asm!(".4byte 0x00000017", options(nomem, nostack))
@@ -63,20 +65,18 @@ macro_rules! lpad {
 /// Push return address (ra/x1) onto the hardware shadow stack.
 /// On Zicfiss hardware: ra is pushed to a protected shadow stack region.
 /// On other hardware: executes as NOP (Zimop guarantee).
-#[allow(unused_macros)]
 macro_rules! hw_sspush {
     () => {
-        core::arch::asm!(".4byte 0x60100073", options(nomem, nostack))
+        core::arch::asm!(".4byte 0xce104073", options(nomem, nostack))
     };
 }
 
 /// Pop from hardware shadow stack and compare with ra.
 /// On Zicfiss hardware: faults with software-check exception on mismatch.
 /// On other hardware: executes as NOP (Zimop guarantee).
-#[allow(unused_macros)]
 macro_rules! hw_sspopchk {
     () => {
-        core::arch::asm!(".4byte 0x60500073", options(nomem, nostack))
+        core::arch::asm!(".4byte 0xcdc0c073", options(nomem, nostack))
     };
 }
 
@@ -84,55 +84,323 @@ macro_rules! hw_sspopchk {
 // Software Shadow Stack Macros (for use in non-naked functions)
 // ============================================================================
 //
-// Uses gp (x3) as the software shadow stack pointer. This register is
-// normally used for linker relaxation (GP-relative addressing), which we
-// disable via --no-relax in .cargo/config.toml.
+// Uses gp (x3) as the software shadow stack pointer by default. This
+// register is normally used for linker relaxation (GP-relative
+// addressing), which build.rs disables with --no-relax.
 //
-// Alternative: use s11 (x27) if you need GP relaxation, but you must also
-// tell LLVM to reserve the register.
+// With the `sw-ss-s11` feature the pointer is s11 (x27) instead, and
+// relaxation is back on. LLVM must then be told to reserve s11: build with
+// --target rv32imac-cfi-s11-none-elf.json, which adds +reserve-x27
+// (build.rs refuses anything else).
+//
+// Every asm site names the register as `x{ss}`, with `ss = const SW_SS_REG`.
+//
+// Every push first checks the depth, (SW_SS_REG - _sw_shadow_stack_bottom)
+// / 4, against `_sw_shadow_stack_max_depth` (memory.x): a pointer already
+// at `_sw_shadow_stack_limit` jumps to `_call_depth_exceeded` instead of
+// pushing.  A recursion that runs away, by bug or by attack, stops there
+// with its own diagnostic rather than overrunning the region.
 
-/// Push ra onto the software shadow stack (pointed to by gp).
-#[allow(unused_macros)]
+/// Register number of the software shadow stack pointer (3 = gp, 27 = s11).
+const SW_SS_REG: u32 = if cfg!(feature = "sw-ss-s11") { 27 } else { 3 };
+
+/// Push ra onto the software shadow stack (pointed to by SW_SS_REG),
+/// trapping to `_call_depth_exceeded` if it is full.
 macro_rules! sw_sspush {
     () => {
         core::arch::asm!(
-            "sw   ra, 0(gp)",
-            "addi gp, gp, 4",
+            "la   t0, _sw_shadow_stack_limit",
+            "bltu x{ss}, t0, 34f",
+            "j    _call_depth_exceeded",
+            "34:",
+            "sw   ra, 0(x{ss})",
+            "addi x{ss}, x{ss}, 4",
+            ss = const SW_SS_REG,
+            out("t0") _,
             options(nostack),
         )
     };
 }
 
 /// Pop from software shadow stack, compare with ra, fault on mismatch.
-#[allow(unused_macros)]
 macro_rules! sw_sspopchk {
     () => {
         core::arch::asm!(
-            "addi gp, gp, -4",
-            "lw   t0, 0(gp)",
+            "addi x{ss}, x{ss}, -4",
+            "lw   t0, 0(x{ss})",
             "beq  t0, ra, 33f",
             // Mismatch detected — return address was corrupted
             "ebreak",
             "33:",
+            ss = const SW_SS_REG,
+            out("t0") _,
             options(nostack),
         )
     };
 }
 
 // ============================================================================
-// UART Output (QEMU virt machine: 16550-compatible at 0x1000_0000)
+// Hardware Shadow Stack Introspection
+// ============================================================================
+//
+// `ssrdp rd` (Zicfiss) copies the shadow-stack pointer into rd.  It is
+// MOP.R.28 with rs1 = x0, so wherever the shadow stack isn't active — no
+// Zicfiss, or shadow stacks not enabled for the current privilege mode —
+// it behaves as the plain MOP and writes 0 to rd.  M-mode is such a mode
+// on a core that follows the spec (menvcfg.SSE covers S/U only), so when
+// ssrdp reads 0 this falls back to reading the `ssp` CSR (0x011), which
+// M-mode can always access where it exists.  A core without Zimop or the
+// CSR raises illegal-instruction on either, which `_trap_handler` skips;
+// a0 is zeroed first, so that reads as 0 too, and 0 means "no hardware
+// shadow stack" — `_start` points ssp at a real region.
+//
+// Entries are XLEN (4 bytes) wide and the stack grows down: sspush
+// decrements ssp, then stores, so the most recent entry is at [ssp].
+// Shadow-stack memory takes ordinary loads; only stores are restricted.
+
+/// `ssrdp a0`.
+const SSRDP_A0: u32 = 0xcdc0_4073 | (10 << 7);
+
+/// The hardware shadow-stack pointer, or `None` without Zicfiss.
+fn hw_ssp() -> Option<u32> {
+    let ssp: u32;
+    // SAFETY: reads a register and a CSR into a0; an unsupported
+    // encoding traps to `_trap_handler`, which skips it.
+    unsafe {
+        asm!(
+            "li     a0, 0",
+            ".4byte {ssrdp_a0}",         // ssrdp a0 (MOP: a0 = 0 if inactive)
+            "bnez   a0, 1f",
+            "csrr   a0, 0x011",          // csrr a0, ssp
+            "1:",
+            ssrdp_a0 = const SSRDP_A0,
+            out("a0") ssp,
+            // _trap_handler uses these without saving them.
+            out("t0") _,
+            out("t1") _,
+            out("t2") _,
+            options(nomem, nostack),
+        )
+    };
+    (ssp != 0).then_some(ssp)
+}
+
+/// Number of entries on the hardware shadow stack, or `None` without
+/// Zicfiss.
+fn hw_shadow_stack_depth() -> Option<u32> {
+    Some(shadow_stack_depth(hw_ssp()?))
+}
+
+fn shadow_stack_depth(ssp: u32) -> u32 {
+    let top = &raw const _shadow_stack_top as u32;
+    top.wrapping_sub(ssp) / 4
+}
+
+/// The most recent entry on the hardware shadow stack (the return address
+/// the next `sspopchk` will compare against), or `None` without Zicfiss or
+/// when the stack is empty.
+fn hw_shadow_stack_top() -> Option<u32> {
+    let ssp = hw_ssp()?;
+    if shadow_stack_depth(ssp) == 0 {
+        return None;
+    }
+    // SAFETY: ssp is below _shadow_stack_top, inside the region `_start`
+    // gave the hardware shadow stack, and points at the last pushed entry.
+    Some(unsafe { (ssp as *const u32).read_volatile() })
+}
+
+extern "C" {
+    static _shadow_stack_top: u8;
+}
+
+// ============================================================================
+// Stack Balance Checkpoints (cfi-checkpoint feature)
 // ============================================================================
+//
+// A naked function that moves sp without the matching SW_SS_REG push (or
+// the other way round) returns with the two stacks out of step, and the
+// caller only notices much later, if at all.  `cfi_checkpoint!()` at the
+// top of a function or block records (sp, SW_SS_REG) there and checks
+// both hold the same values again when the scope is left, by any return
+// path: every call made in between must have left them balanced.
+//
+// Reading the registers is a plain `mv`, so the checkpoint itself moves
+// neither stack.  Without the feature the macro expands to nothing.
+
+/// Record sp and the software shadow-stack pointer here; report on the
+/// UART and panic if either differs when the enclosing scope ends.
+macro_rules! cfi_checkpoint {
+    () => {
+        #[cfg(feature = "cfi-checkpoint")]
+        let _checkpoint = Checkpoint::here(line!());
+    };
+}
 
-const UART_BASE: *mut u8 = 0x1000_0000 as *mut u8;
+/// The stack pointers at a `cfi_checkpoint!()`, compared again on drop.
+#[cfg(feature = "cfi-checkpoint")]
+struct Checkpoint {
+    line: u32,
+    sp: u32,
+    ss: u32,
+}
+
+#[cfg(feature = "cfi-checkpoint")]
+impl Checkpoint {
+    #[inline(always)]
+    fn here(line: u32) -> Checkpoint {
+        let (sp, ss) = stack_pointers();
+        Checkpoint { line, sp, ss }
+    }
+}
+
+#[cfg(feature = "cfi-checkpoint")]
+impl Drop for Checkpoint {
+    #[inline(always)]
+    fn drop(&mut self) {
+        let (sp, ss) = stack_pointers();
+        if (sp, ss) != (self.sp, self.ss) {
+            uart_puts("[checkpoint] line ");
+            uart_put_dec(self.line);
+            uart_puts(": sp ");
+            uart_put_hex32(self.sp);
+            uart_puts(" -> ");
+            uart_put_hex32(sp);
+            uart_puts(if cfg!(feature = "sw-ss-s11") { ", s11 " } else { ", gp " });
+            uart_put_hex32(self.ss);
+            uart_puts(" -> ");
+            uart_put_hex32(ss);
+            uart_newline();
+            panic!("sp / shadow-stack pointer imbalance");
+        }
+    }
+}
+
+/// Current (sp, SW_SS_REG).
+#[cfg(feature = "cfi-checkpoint")]
+#[inline(always)]
+fn stack_pointers() -> (u32, u32) {
+    let (sp, ss): (u32, u32);
+    // SAFETY: two register moves; nothing is read, written or pushed.
+    unsafe {
+        asm!(
+            "mv   {sp}, sp",
+            "mv   {ss}, x{reg}",
+            sp = out(reg) sp,
+            ss = out(reg) ss,
+            reg = const SW_SS_REG,
+            options(nomem, nostack, preserves_flags),
+        )
+    };
+    (sp, ss)
+}
+
+// ============================================================================
+// Console and Exit
+// ============================================================================
+//
+// Under QEMU (the default) output goes to the virt machine's 16550 UART at
+// 0x1000_0000 and the run ends through the test finisher at 0x10_0000.
+// Spike has neither: with the `htif` feature both go through HTIF instead,
+// the tohost/fromhost mailbox the simulator's front end (fesvr) polls.
+// The uart_* helpers below sit on top of whichever backend is built in.
+
+#[cfg(not(feature = "htif"))]
+mod console {
+    const UART_BASE: *mut u8 = 0x1000_0000 as *mut u8;
+    const TEST_FINISHER: *mut u32 = 0x10_0000 as *mut u32;
+
+    pub fn write(bytes: &[u8]) {
+        for &b in bytes {
+            unsafe { UART_BASE.write_volatile(b) }
+        }
+    }
+
+    /// Stop the run: exit status 0 passes (0x5555), anything else fails
+    /// with that status (`code << 16 | 0x3333`).
+    pub fn exit(code: u16) -> ! {
+        let value = if code == 0 { 0x5555 } else { (code as u32) << 16 | 0x3333 };
+        unsafe { TEST_FINISHER.write_volatile(value) };
+        super::park()
+    }
+}
+
+/// HTIF, as Spike's fesvr implements it.  fesvr finds `tohost` and
+/// `fromhost` by symbol name, watches `tohost` for a command and clears it
+/// once taken, and writes any reply to `fromhost`.  With device and
+/// command 0 in the upper bits, an odd `tohost` is an exit (status in the
+/// bits above bit 0) and an even one is the address of a proxied syscall:
+/// eight u64s, the syscall number and its arguments.  Output is a proxied
+/// `write(1, buf, len)`.
+///
+/// Both commands fit in `tohost`'s low word, which is the only word ever
+/// written.  An RV32 hart can't store the full u64 at once, and fesvr may
+/// look between the two halves; with the high word left at 0 it can only
+/// ever see a whole command.
+#[cfg(feature = "htif")]
+mod console {
+    use core::ptr::{addr_of, addr_of_mut};
+
+    #[no_mangle]
+    #[link_section = ".tohost"]
+    #[allow(non_upper_case_globals)]
+    static mut tohost: u64 = 0;
+
+    #[no_mangle]
+    #[link_section = ".tohost"]
+    #[allow(non_upper_case_globals)]
+    static mut fromhost: u64 = 0;
+
+    #[repr(C, align(64))]
+    struct SyscallBlock([u64; 8]);
+
+    static mut SYSCALL: SyscallBlock = SyscallBlock([0; 8]);
+
+    const SYS_WRITE: u64 = 64;
+    const STDOUT: u64 = 1;
+
+    /// Post `cmd` in `tohost` once fesvr has taken the previous one.
+    fn send(cmd: u32) {
+        let to = addr_of_mut!(tohost) as *mut u32;
+        unsafe {
+            while to.read_volatile() != 0 {}
+            to.write_volatile(cmd);
+        }
+    }
+
+    pub fn write(bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+        unsafe {
+            let block = addr_of_mut!(SYSCALL.0);
+            block.write_volatile([SYS_WRITE, STDOUT, bytes.as_ptr() as u64, bytes.len() as u64, 0, 0, 0, 0]);
+            send(block as u32);
+            // The reply says the write is done with `bytes` and the block.
+            while addr_of!(fromhost).read_volatile() == 0 {}
+            addr_of_mut!(fromhost).write_volatile(0);
+        }
+    }
+
+    /// Stop the run: `spike` exits with status `code`.
+    pub fn exit(code: u16) -> ! {
+        send((code as u32) << 1 | 1);
+        super::park()
+    }
+}
+
+/// Wait for the exit to take effect (or forever, if nothing is listening).
+fn park() -> ! {
+    loop {
+        unsafe { asm!("wfi") };
+    }
+}
 
 fn uart_putc(c: u8) {
-    unsafe { UART_BASE.write_volatile(c) }
+    console::write(&[c]);
 }
 
 fn uart_puts(s: &str) {
-    for b in s.bytes() {
-        uart_putc(b);
-    }
+    console::write(s.as_bytes());
 }
 
 fn uart_put_hex32(val: u32) {
@@ -191,26 +459,30 @@ global_asm!(
     ".4byte 0x00000017",                // lpad 0 (forward-edge CFI)
 
     // Backward-edge CFI: push ra to both shadow stacks
-    ".4byte 0x60100073",                // sspush ra (HW — NOP if no Zicfiss)
+    ".4byte 0xce104073",                // sspush ra (HW — NOP if no Zicfiss)
     "addi   sp, sp, -16",
     "sw     ra, 12(sp)",
-    "sw     gp, 8(sp)",
-    "sw     ra, 0(gp)",                 // sw_sspush (software)
-    "addi   gp, gp, 4",
+    "sw     x{ss}, 8(sp)",
+    "la     t0, _sw_shadow_stack_limit",   // depth check
+    "bltu   x{ss}, t0, 97f",
+    "j      _call_depth_exceeded",
+    "97:",
+    "sw     ra, 0(x{ss})",                 // sw_sspush (software)
+    "addi   x{ss}, x{ss}, 4",
 
     // Body: x * 3
     "slli   t0, a0, 1",                 // t0 = x << 1 = x*2
     "add    a0, t0, a0",                // a0 = x*2 + x = x*3
 
     // Backward-edge CFI: pop and check both shadow stacks
-    "addi   gp, gp, -4",                // sw_sspopchk (software)
-    "lw     t0, 0(gp)",
+    "addi   x{ss}, x{ss}, -4",                // sw_sspopchk (software)
+    "lw     t0, 0(x{ss})",
     "lw     ra, 12(sp)",
     "bne    t0, ra, 99f",
 
-    "lw     gp, 8(sp)",
+    "lw     x{ss}, 8(sp)",
     "addi   sp, sp, 16",
-    ".4byte 0x60500073",                // sspopchk ra (HW — NOP if no Zicfiss)
+    ".4byte 0xcdc0c073",                // sspopchk ra (HW — NOP if no Zicfiss)
     "ret",
 
     "99: ebreak",                        // Shadow stack mismatch fault
@@ -244,6 +516,21 @@ global_asm!(
     "ret",
     ".size square, . - square",
 
+    // -----------------------------------------------------------------
+    // no_lpad: fn(u32) -> u32
+    // Add 1 to x, with the KCFI hash but no landing pad: not a valid
+    // indirect-call target.  Test 3 checks checked_indirect_call
+    // refuses it.
+    // -----------------------------------------------------------------
+    ".balign 4",
+    ".4byte {kcfi_u32_u32}",            // KCFI type hash at no_lpad-4
+    ".globl no_lpad",
+    ".type no_lpad, @function",
+    "no_lpad:",
+    "addi   a0, a0, 1",                 // no lpad: starts with the body
+    "ret",
+    ".size no_lpad, . - no_lpad",
+
     // -----------------------------------------------------------------
     // call_and_inc: fn(fn(u32)->u32, u32) -> u32
     // Call a function pointer and add 1. Non-leaf with full CFI.
@@ -257,12 +544,16 @@ global_asm!(
     ".4byte 0x00000017",                // lpad 0 (forward-edge CFI)
 
     // Backward-edge: push ra
-    ".4byte 0x60100073",                // sspush ra (HW)
+    ".4byte 0xce104073",                // sspush ra (HW)
     "addi   sp, sp, -16",
     "sw     ra, 12(sp)",
-    "sw     gp, 8(sp)",
-    "sw     ra, 0(gp)",                 // sw_sspush
-    "addi   gp, gp, 4",
+    "sw     x{ss}, 8(sp)",
+    "la     t0, _sw_shadow_stack_limit",   // depth check
+    "bltu   x{ss}, t0, 97f",
+    "j      _call_depth_exceeded",
+    "97:",
+    "sw     ra, 0(x{ss})",                 // sw_sspush
+    "addi   x{ss}, x{ss}, 4",
 
     // Prepare indirect call: a0 = fp, a1 = x
     "mv     t1, a0",                    // t1 = fp (target address)
@@ -281,23 +572,76 @@ global_asm!(
     "addi   a0, a0, 1",
 
     // Backward-edge: pop and check
-    "addi   gp, gp, -4",                // sw_sspopchk
-    "lw     t0, 0(gp)",
+    "addi   x{ss}, x{ss}, -4",                // sw_sspopchk
+    "lw     t0, 0(x{ss})",
     "lw     ra, 12(sp)",
     "bne    t0, ra, 99f",
 
-    "lw     gp, 8(sp)",
+    "lw     x{ss}, 8(sp)",
     "addi   sp, sp, 16",
-    ".4byte 0x60500073",                // sspopchk ra (HW)
+    ".4byte 0xcdc0c073",                // sspopchk ra (HW)
     "ret",
 
     "99: ebreak",
     ".size call_and_inc, . - call_and_inc",
 
+    // -----------------------------------------------------------------
+    // countdown: fn(u32) -> u32
+    // Recurse n times and return n: one shadow-stack entry per level,
+    // n + 1 in all.  Test 8 drives it past the maximum depth.
+    // -----------------------------------------------------------------
+    ".balign 4",
+    ".4byte {kcfi_u32_u32}",            // KCFI type hash at countdown-4
+    ".globl countdown",
+    ".type countdown, @function",
+    "countdown:",
+    ".4byte 0x00000017",                // lpad 0
+    ".4byte 0xce104073",                // sspush ra (HW)
+    "addi   sp, sp, -16",
+    "sw     ra, 12(sp)",
+    "sw     x{ss}, 8(sp)",
+    "la     t0, _sw_shadow_stack_limit",   // depth check
+    "bltu   x{ss}, t0, 97f",
+    "j      _call_depth_exceeded",
+    "97:",
+    "sw     ra, 0(x{ss})",                 // sw_sspush
+    "addi   x{ss}, x{ss}, 4",
+
+    "beqz   a0, 2f",                    // countdown(0) = 0
+    "addi   a0, a0, -1",
+    "call   countdown",
+    "addi   a0, a0, 1",                 // countdown(n) = countdown(n - 1) + 1
+    "2:",
+
+    "addi   x{ss}, x{ss}, -4",                // sw_sspopchk
+    "lw     t0, 0(x{ss})",
+    "lw     ra, 12(sp)",
+    "bne    t0, ra, 99f",
+
+    "lw     x{ss}, 8(sp)",
+    "addi   sp, sp, 16",
+    ".4byte 0xcdc0c073",                // sspopchk ra (HW)
+    "ret",
+
+    "99: ebreak",
+    ".size countdown, . - countdown",
+
+    // -----------------------------------------------------------------
+    // _call_depth_exceeded: where a push on a full software shadow stack
+    // goes instead.  The ebreak is recognised by its address in
+    // _trap_handler, which hands it to call_depth_exceeded(); ra is still
+    // the return address that didn't fit.
+    // -----------------------------------------------------------------
+    ".balign 4",
+    ".globl _call_depth_exceeded",
+    "_call_depth_exceeded:",
+    "ebreak",
+
     // Template arguments
     kcfi_u32_u32 = const KCFI_TYPE_FN_U32_U32,
     kcfi_fp_u32_u32 = const KCFI_TYPE_FN_FP_U32_U32,
     lpad_7 = const ((7u32 << 12) | 0x17),
+    ss = const SW_SS_REG,
 );
 
 // Extern declarations — these symbols are defined in the global_asm!() above.
@@ -305,7 +649,51 @@ extern "C" {
     fn triple(x: u32) -> u32;
     fn add_42(x: u32) -> u32;
     fn square(x: u32) -> u32;
+    fn no_lpad(x: u32) -> u32;
     fn call_and_inc(fp: unsafe extern "C" fn(u32) -> u32, x: u32) -> u32;
+    fn countdown(n: u32) -> u32;
+}
+
+// ============================================================================
+// Non-naked CFI Function (macro template)
+// ============================================================================
+//
+// The same protection as `triple`, but an ordinary Rust function: the CFI
+// macros bracket the body instead of hand-written asm around it.  It only
+// works under three conditions, all checked in Test 7:
+//
+//   - The landing pad must be the first instruction, 4-byte aligned.
+//     `#[rustc_align(4)]` gives the alignment; a leaf this small has no
+//     prologue ahead of the first asm block.
+//   - ra must still hold the return address when the pops run.  The
+//     compiler doesn't know the macros read ra, so the body must not call
+//     anything (which would overwrite ra) — keep it a leaf.
+//   - The macros run on the live shadow stacks, so the function must only
+//     be called once `_start` has set them up.
+//
+// Copy this shape for a new function; anything that calls out needs the
+// naked form instead.
+
+/// Multiply `x` by 5, with forward- and backward-edge CFI from the macros.
+#[no_mangle]
+#[inline(never)]
+#[rustc_align(4)]
+pub extern "C" fn quintuple(x: u32) -> u32 {
+    // SAFETY: CFI prologue — landing pad, then ra onto both shadow stacks
+    // (SW_SS_REG points into the software one from `_start` on).
+    unsafe {
+        lpad!();
+        hw_sspush!();
+        sw_sspush!();
+    }
+    let r = x.wrapping_mul(5);
+    // SAFETY: CFI epilogue, matching the pushes above; no call in between
+    // has touched ra.
+    unsafe {
+        sw_sspopchk!();
+        hw_sspopchk!();
+    }
+    r
 }
 
 // ============================================================================
@@ -332,6 +720,68 @@ unsafe fn kcfi_check_u32_u32(fp: unsafe extern "C" fn(u32) -> u32) {
     );
 }
 
+// ============================================================================
+// Software Landing-Pad Check (forward-edge fallback)
+// ============================================================================
+//
+// Zicfilp faults an indirect call whose target doesn't start with an
+// `lpad`, but only on a hart that has it, with it enabled.  Elsewhere an
+// `lpad` is a plain `auipc x0` and any address can be called.
+// `checked_indirect_call` makes the same check in software before the
+// jalr, so the forward edge has a fallback the way the backward edge has
+// the software shadow stack.  It checks for a landing pad, not its label.
+
+/// Why [`checked_indirect_call`] refused a target.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CfiError {
+    /// Not on a 4-byte boundary, where Zicfilp accepts no landing pad.
+    Misaligned { target: usize },
+    /// The first instruction isn't an `lpad`.
+    NoLandingPad { target: usize, word: u32 },
+}
+
+/// Whether `word` is an `lpad` (any label): AUIPC with rd = x0.
+const fn is_lpad(word: u32) -> bool {
+    word & 0xfff == 0x17
+}
+
+/// Call `fp(arg)` if `fp` is 4-byte aligned and its first word is an
+/// `lpad`, refuse it otherwise, whether or not the hart enforces
+/// Zicfilp.
+///
+/// # Safety
+///
+/// `fp` must be readable, and must be an `extern "C" fn(u32) -> u32` if
+/// it starts with a landing pad.
+unsafe fn checked_indirect_call(fp: usize, arg: u32) -> Result<u32, CfiError> {
+    if !fp.is_multiple_of(4) {
+        return Err(CfiError::Misaligned { target: fp });
+    }
+    let word = (fp as *const u32).read_volatile();
+    if !is_lpad(word) {
+        return Err(CfiError::NoLandingPad { target: fp, word });
+    }
+    let f: unsafe extern "C" fn(u32) -> u32 = core::mem::transmute(fp);
+    Ok(f(arg))
+}
+
+fn put_cfi_error(e: CfiError) {
+    match e {
+        CfiError::Misaligned { target } => {
+            uart_puts("target ");
+            uart_put_hex32(target as u32);
+            uart_puts(" not 4-byte aligned");
+        }
+        CfiError::NoLandingPad { target, word } => {
+            uart_puts("no lpad at ");
+            uart_put_hex32(target as u32);
+            uart_puts(" (first word ");
+            uart_put_hex32(word);
+            uart_puts(")");
+        }
+    }
+}
+
 // ============================================================================
 // Function dispatch table — typical use-case for forward-edge CFI
 // ============================================================================
@@ -353,19 +803,219 @@ static DISPATCH_TABLE: [DispatchEntry; 3] = [
 ];
 
 /// Look up and call a handler by ID.
-/// Performs a KCFI type check before each indirect call.
+/// Performs a KCFI type check and the software landing-pad check before
+/// each indirect call; a handler without a landing pad halts.
 fn dispatch(id: u32, arg: u32) -> Option<u32> {
+    cfi_checkpoint!();
     for entry in &DISPATCH_TABLE {
         if entry.id == id {
             unsafe {
                 kcfi_check_u32_u32(entry.handler);
-                return Some((entry.handler)(arg));
+                match checked_indirect_call(entry.handler as usize, arg) {
+                    Ok(r) => return Some(r),
+                    Err(e) => {
+                        uart_puts("  dispatch: ");
+                        put_cfi_error(e);
+                        uart_newline();
+                        panic!("dispatch target refused");
+                    }
+                }
             }
         }
     }
     None
 }
 
+// ============================================================================
+// Shadow-Stack Benchmark (`ss-bench`)
+// ============================================================================
+//
+// What the two shadow stacks cost per call, to weigh keeping the software
+// one on a core that has Zicfiss.  Four copies of `countdown` differ only
+// in their backward-edge CFI: none, software only, hardware only, both.
+// Each runs BENCH_CHAINS chains of BENCH_DEPTH nested calls, timed with
+// mcycle; the copy without either mechanism is the baseline the others
+// are compared against, so the loop and the frame cancel out.
+//
+// The chains are short and repeated rather than one 10,000 deep: that
+// would need 160K of stack and 40K of each shadow stack, and the software
+// one stops at `_sw_shadow_stack_max_depth` (128) anyway.  Every call
+// still pushes and pops, so the per-call figure is the same.
+//
+// The hardware column only measures Zicfiss where it is live.  M-mode has
+// no shadow stack on a core that follows the spec (menvcfg.SSE covers S
+// and U), and without Zicfiss at all sspush / sspopchk are NOPs: then it
+// is the cost of two NOPs, and the report says so.  QEMU's mcycle counts
+// instructions rather than pipeline cycles, so there the figures rank the
+// mechanisms; only silicon gives their real cost.
+
+#[cfg(feature = "ss-bench")]
+global_asm!(
+    // bench_chain NAME, SW, HW: countdown with the software (SW = 1)
+    // and hardware (HW = 1) shadow-stack push / pop compiled in.
+    ".macro bench_chain name, sw, hw",
+    ".balign 4",
+    ".globl \\name",
+    ".type \\name, @function",
+    "\\name:",
+    ".4byte 0x00000017",                // lpad 0
+    ".if \\hw",
+    ".4byte 0xce104073",                // sspush ra (HW)
+    ".endif",
+    "addi   sp, sp, -16",
+    "sw     ra, 12(sp)",
+    ".if \\sw",
+    "sw     x{ss}, 8(sp)",
+    "la     t0, _sw_shadow_stack_limit",   // depth check
+    "bltu   x{ss}, t0, 97f",
+    "j      _call_depth_exceeded",
+    "97:",
+    "sw     ra, 0(x{ss})",                 // sw_sspush
+    "addi   x{ss}, x{ss}, 4",
+    ".endif",
+
+    "beqz   a0, 2f",
+    "addi   a0, a0, -1",
+    "call   \\name",
+    "addi   a0, a0, 1",
+    "2:",
+
+    ".if \\sw",
+    "addi   x{ss}, x{ss}, -4",                // sw_sspopchk
+    "lw     t0, 0(x{ss})",
+    "lw     ra, 12(sp)",
+    "bne    t0, ra, 99f",
+    "lw     x{ss}, 8(sp)",
+    ".else",
+    "lw     ra, 12(sp)",
+    ".endif",
+    "addi   sp, sp, 16",
+    ".if \\hw",
+    ".4byte 0xcdc0c073",                // sspopchk ra (HW)
+    ".endif",
+    "ret",
+    ".if \\sw",
+    "99: ebreak",
+    ".endif",
+    ".size \\name, . - \\name",
+    ".endm",
+
+    "bench_chain bench_chain_none, 0, 0",
+    "bench_chain bench_chain_sw, 1, 0",
+    "bench_chain bench_chain_hw, 0, 1",
+    "bench_chain bench_chain_both, 1, 1",
+    ss = const SW_SS_REG,
+);
+
+#[cfg(feature = "ss-bench")]
+extern "C" {
+    fn bench_chain_none(n: u32) -> u32;
+    fn bench_chain_sw(n: u32) -> u32;
+    fn bench_chain_hw(n: u32) -> u32;
+    fn bench_chain_both(n: u32) -> u32;
+}
+
+/// Nested calls per chain: within the software shadow stack's depth limit.
+#[cfg(feature = "ss-bench")]
+const BENCH_DEPTH: u32 = 100;
+
+/// Chains per measurement.
+#[cfg(feature = "ss-bench")]
+const BENCH_CHAINS: u32 = 100;
+
+/// Calls per measurement.
+#[cfg(feature = "ss-bench")]
+const BENCH_CALLS: u32 = BENCH_DEPTH * BENCH_CHAINS;
+
+/// mcycle, low 32 bits: one measurement is far shorter than a wrap.  Reads
+/// 0 where the CSR is missing (the trap handler skips the csrr).
+#[cfg(feature = "ss-bench")]
+fn mcycle() -> u32 {
+    let v: u32;
+    unsafe { asm!("li {v}, 0", "csrr {v}, mcycle", v = out(reg) v, options(nomem, nostack)) };
+    v
+}
+
+/// Cycles for BENCH_CHAINS calls of `chain(BENCH_DEPTH - 1)`, each
+/// BENCH_DEPTH calls deep.
+#[cfg(feature = "ss-bench")]
+fn time_chains(chain: unsafe extern "C" fn(u32) -> u32) -> u32 {
+    // black_box keeps every call a real one through the pointer.
+    let chain = core::hint::black_box(chain);
+    let start = mcycle();
+    for _ in 0..BENCH_CHAINS {
+        let depth = unsafe { chain(core::hint::black_box(BENCH_DEPTH - 1)) };
+        if depth != BENCH_DEPTH - 1 {
+            panic!("benchmark chain returned the wrong depth");
+        }
+    }
+    mcycle().wrapping_sub(start)
+}
+
+/// Whether sspush moves the hardware shadow-stack pointer here, i.e.
+/// whether the hardware column measures Zicfiss or two NOPs.
+#[cfg(feature = "ss-bench")]
+fn hw_shadow_stack_live() -> bool {
+    let (before, after): (u32, u32);
+    // SAFETY: the push is popped again straight away, with ra unchanged,
+    // so sspopchk matches.  Missing CSRs read 0 (see hw_ssp).
+    unsafe {
+        asm!(
+            "li     {b}, 0",
+            "csrr   {b}, 0x011",
+            ".4byte 0xce104073",        // sspush ra
+            "li     {a}, 0",
+            "csrr   {a}, 0x011",
+            ".4byte 0xcdc0c073",        // sspopchk ra
+            b = out(reg) before,
+            a = out(reg) after,
+            options(nostack),
+        )
+    };
+    before != 0 && after == before.wrapping_sub(4)
+}
+
+/// `cycles / BENCH_CALLS` to one decimal place.
+#[cfg(feature = "ss-bench")]
+fn put_per_call(cycles: u32) {
+    let tenths = cycles / (BENCH_CALLS / 10);
+    uart_put_dec(tenths / 10);
+    uart_putc(b'.');
+    uart_put_dec(tenths % 10);
+}
+
+/// Time the four chains and print cycles per call for each, and what each
+/// mechanism adds over the baseline.
+#[cfg(feature = "ss-bench")]
+fn shadow_stack_bench() {
+    cfi_checkpoint!();
+    uart_puts("  ");
+    uart_put_dec(BENCH_CHAINS);
+    uart_puts(" chains of ");
+    uart_put_dec(BENCH_DEPTH);
+    uart_puts(" nested calls, mcycle\r\n");
+
+    let base = time_chains(bench_chain_none);
+    let runs: [(&str, unsafe extern "C" fn(u32) -> u32); 3] =
+        [("software", bench_chain_sw), ("hardware", bench_chain_hw), ("both    ", bench_chain_both)];
+    uart_puts("  none:     ");
+    put_per_call(base);
+    uart_puts(" cycles/call (baseline)\r\n");
+    for (name, chain) in runs {
+        let cycles = time_chains(chain);
+        uart_puts("  ");
+        uart_puts(name);
+        uart_puts(": ");
+        put_per_call(cycles);
+        uart_puts(" cycles/call (+");
+        put_per_call(cycles.saturating_sub(base));
+        uart_puts(")\r\n");
+    }
+    if !hw_shadow_stack_live() {
+        uart_puts("  (no hardware shadow stack in this mode: sspush/sspopchk ran as NOPs)\r\n");
+    }
+}
+
 // ============================================================================
 // Entry Point
 // ============================================================================
@@ -376,11 +1026,25 @@ fn dispatch(id: u32, arg: u32) -> Option<u32> {
 /// hardware/emulators that don't implement them, an illegal instruction
 /// exception fires. This handler simply advances mepc past the faulting
 /// instruction and returns, allowing boot to continue gracefully.
+///
+/// The one exception is the `ebreak` at `_call_depth_exceeded`: that call
+/// chain can't continue, so the handler starts a fresh stack and passes
+/// the shadow-stack pointer and the unpushed ra to
+/// [`call_depth_exceeded`], which doesn't return.
 #[unsafe(naked)]
 #[no_mangle]
 #[link_section = ".text.init"]
 unsafe extern "C" fn _trap_handler() {
     naked_asm!(
+        "csrr   t0, mepc",
+        "la     t1, _call_depth_exceeded",
+        "bne    t0, t1, 5f",
+        "la     sp, _stack_top",
+        "mv     a0, x{ss}",
+        "mv     a1, ra",
+        "call   {depth_exceeded}",
+        "5:",
+
         // Read the faulting instruction to determine its length (2 or 4 bytes).
         // RISC-V compressed instructions have bits [1:0] != 0b11.
         "csrr   t0, mepc",
@@ -395,9 +1059,51 @@ unsafe extern "C" fn _trap_handler() {
         "6: addi t0, t0, 2",
         "7: csrw mepc, t0",
         "mret",
+        ss = const SW_SS_REG,
+        depth_exceeded = sym call_depth_exceeded,
     )
 }
 
+/// Set by Test 8 just before it overruns the maximum depth on purpose.
+static DEPTH_TRAP_EXPECTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" {
+    static _sw_shadow_stack_bottom: u8;
+    static _sw_shadow_stack_max_depth: u8;
+}
+
+/// `_sw_shadow_stack_max_depth` (memory.x): an absolute symbol, so the
+/// value is its address.
+fn max_call_depth() -> u32 {
+    &raw const _sw_shadow_stack_max_depth as u32
+}
+
+/// A push found the software shadow stack at its maximum depth
+/// (`_call_depth_exceeded`).  Reports it; then, if Test 8 was expecting
+/// it, finishes the demo, and otherwise panics.
+extern "C" fn call_depth_exceeded(ss: u32, ra: u32) -> ! {
+    let depth = ss.wrapping_sub(&raw const _sw_shadow_stack_bottom as u32) / 4;
+    uart_puts("  call depth exceeded: depth ");
+    uart_put_dec(depth);
+    uart_puts(" (limit ");
+    uart_put_dec(max_call_depth());
+    uart_puts("), pushing ra ");
+    uart_put_hex32(ra);
+    uart_newline();
+    if !DEPTH_TRAP_EXPECTED.load(Ordering::Relaxed) {
+        panic!("software shadow stack at its maximum depth");
+    }
+    uart_puts("  (controlled trap instead of overrunning the region: PASS)\r\n");
+    uart_newline();
+    finish()
+}
+
+/// Reset entry point.
+///
+/// # Safety
+///
+/// Must only be entered by the hart at reset; it assumes nothing and sets
+/// up the stack, trap vector, BSS, .data and shadow-stack pointers itself.
 #[unsafe(naked)]
 #[no_mangle]
 #[link_section = ".text.init"]
@@ -441,8 +1147,8 @@ pub unsafe extern "C" fn _start() -> ! {
         "la     t0, _shadow_stack_top",
         "csrw   0x011, t0",          // csrw ssp, t0
 
-        // --- 7. Initialize software shadow stack pointer (gp) ---
-        "la     gp, _sw_shadow_stack_bottom",
+        // --- 7. Initialize software shadow stack pointer (SW_SS_REG) ---
+        "la     x{ss}, _sw_shadow_stack_bottom",
 
         // --- 8. Jump to Rust main ---
         "call   main",
@@ -450,6 +1156,7 @@ pub unsafe extern "C" fn _start() -> ! {
         // --- 9. Halt if main returns ---
         "5: wfi",
         "j      5b",
+        ss = const SW_SS_REG,
     )
 }
 
@@ -467,6 +1174,7 @@ pub extern "C" fn main() -> ! {
     // --- Test 1: Direct calls ---
     uart_puts("[Test 1] Direct function calls\r\n");
     {
+        cfi_checkpoint!();
         let r = unsafe { triple(7) };
         uart_puts("  triple(7) = ");
         uart_put_dec(r);
@@ -488,6 +1196,7 @@ pub extern "C" fn main() -> ! {
     uart_puts("[Test 2] Indirect calls via function pointers\r\n");
     uart_puts("  (Zicfilp enforces landing pads at call targets)\r\n");
     {
+        cfi_checkpoint!();
         let fp: unsafe extern "C" fn(u32) -> u32 = triple;
         let r = unsafe { fp(10) };
         uart_puts("  fp=triple: fp(10) = ");
@@ -505,6 +1214,7 @@ pub extern "C" fn main() -> ! {
     // --- Test 3: Dispatch table (common real-world pattern) ---
     uart_puts("[Test 3] Dispatch table with indirect calls\r\n");
     {
+        cfi_checkpoint!();
         for id in 0..3u32 {
             if let Some(result) = dispatch(id, 6) {
                 uart_puts("  dispatch(");
@@ -520,12 +1230,37 @@ pub extern "C" fn main() -> ! {
                 uart_newline();
             }
         }
+
+        // Targets without a landing pad are refused before the jalr, so
+        // this holds without Zicfilp too.
+        let no_lpad = no_lpad as *const () as usize;
+        let add_42 = add_42 as *const () as usize;
+        for (name, fp) in [("no_lpad", no_lpad), ("add_42 + 2", add_42 + 2)] {
+            uart_puts("  checked_indirect_call(");
+            uart_puts(name);
+            uart_puts(", 6): ");
+            // SAFETY: both are in .text; neither is called, since neither
+            // is an aligned lpad.
+            match unsafe { checked_indirect_call(fp, 6) } {
+                Ok(r) => {
+                    uart_puts("called, = ");
+                    uart_put_dec(r);
+                    uart_puts(" (expected refusal: FAIL)\r\n");
+                }
+                Err(e) => {
+                    uart_puts("refused, ");
+                    put_cfi_error(e);
+                    uart_puts(": PASS\r\n");
+                }
+            }
+        }
     }
     uart_newline();
 
     // --- Test 4: Non-leaf function with full CFI ---
     uart_puts("[Test 4] Non-leaf call_and_inc (full forward+backward CFI)\r\n");
     {
+        cfi_checkpoint!();
         let r = unsafe { call_and_inc(triple, 4) };
         uart_puts("  call_and_inc(triple, 4) = ");
         uart_put_dec(r);
@@ -541,13 +1276,33 @@ pub extern "C" fn main() -> ! {
     // --- Test 5: Shadow stack state inspection ---
     uart_puts("[Test 5] Shadow stack pointer inspection\r\n");
     {
-        let gp_val: u32;
-        unsafe { asm!("mv {}, gp", out(reg) gp_val) };
-        uart_puts("  Software SSP (gp) = ");
-        uart_put_hex32(gp_val);
+        let ss_val: u32;
+        unsafe { asm!("mv {}, x{ss}", out(reg) ss_val, ss = const SW_SS_REG) };
+        uart_puts(if cfg!(feature = "sw-ss-s11") {
+            "  Software SSP (s11) = "
+        } else {
+            "  Software SSP (gp) = "
+        });
+        uart_put_hex32(ss_val);
         uart_newline();
 
-        uart_puts("  (Hardware SSP via CSR 0x011 — available on Zicfiss HW only)\r\n");
+        match hw_ssp() {
+            Some(ssp) => {
+                uart_puts("  Hardware SSP (ssrdp) = ");
+                uart_put_hex32(ssp);
+                uart_puts(", depth ");
+                uart_put_dec(hw_shadow_stack_depth().unwrap_or(0));
+                match hw_shadow_stack_top() {
+                    Some(ra) => {
+                        uart_puts(", top ");
+                        uart_put_hex32(ra);
+                    }
+                    None => uart_puts(", empty"),
+                }
+                uart_newline();
+            }
+            None => uart_puts("  Hardware SSP: none (ssrdp and CSR 0x011 read 0: no Zicfiss)\r\n"),
+        }
     }
     uart_newline();
 
@@ -612,37 +1367,106 @@ pub extern "C" fn main() -> ! {
     }
     uart_newline();
 
+    // --- Test 7: Non-naked function using the CFI macros ---
+    uart_puts("[Test 7] Non-naked quintuple (lpad!/sspush!/sspopchk! macros)\r\n");
+    {
+        cfi_checkpoint!();
+        let entry = quintuple as *const () as usize;
+        let first: u32 = unsafe { (entry as *const u32).read_volatile() };
+        uart_puts("  first instruction: ");
+        uart_put_hex32(first);
+        if entry.is_multiple_of(4) && first == 0x0000_0017 {
+            uart_puts(" (lpad 0, 4-byte aligned: PASS)\r\n");
+        } else {
+            uart_puts(" (expected lpad 0 at a 4-byte boundary: FAIL)\r\n");
+        }
+
+        let r = quintuple(9);
+        uart_puts("  quintuple(9) = ");
+        uart_put_dec(r);
+        uart_puts(" (expected 45)\r\n");
+
+        // black_box keeps this a real indirect call (landing pad checked).
+        let fp: extern "C" fn(u32) -> u32 = core::hint::black_box(quintuple);
+        let r = fp(20);
+        uart_puts("  fp=quintuple: fp(20) = ");
+        uart_put_dec(r);
+        uart_puts(" (expected 100)\r\n");
+        uart_puts("  (no KCFI hash: a Rust fn can't place data at fn-4)\r\n");
+    }
+    uart_newline();
+
+    // --- Benchmark: shadow-stack overhead per call (ss-bench) ---
+    #[cfg(feature = "ss-bench")]
+    {
+        uart_puts("[Bench] Shadow-stack cost per call\r\n");
+        shadow_stack_bench();
+        uart_newline();
+    }
+
+    // --- Test 8: Maximum call depth (ends the tests) ---
+    uart_puts("[Test 8] Maximum call depth on the software shadow stack\r\n");
+    {
+        let limit = max_call_depth();
+        let r = unsafe { countdown(limit / 2) };
+        uart_puts("  countdown(");
+        uart_put_dec(limit / 2);
+        uart_puts(") = ");
+        uart_put_dec(r);
+        uart_puts(" (within the limit)\r\n");
+
+        // countdown(limit) needs limit + 1 entries: the last push traps to
+        // call_depth_exceeded, which carries on with the summary.
+        uart_puts("  countdown(");
+        uart_put_dec(limit);
+        uart_puts(")...\r\n");
+        DEPTH_TRAP_EXPECTED.store(true, Ordering::Relaxed);
+        unsafe { countdown(limit) };
+        uart_puts("  returned without reaching the limit: FAIL\r\n");
+        loop {
+            unsafe { asm!("wfi") };
+        }
+    }
+}
+
+/// Print the summary and stop QEMU with a pass.
+fn finish() -> ! {
     // --- Summary ---
     uart_puts("============================================\r\n");
     uart_puts("  CFI Protection Summary:\r\n");
     uart_puts("  - Forward-edge:  lpad at indirect call targets\r\n");
     uart_puts("  - Backward-edge: sspush/sspopchk in prologue/epilogue\r\n");
-    uart_puts("  - Fallback:      software shadow stack via gp register\r\n");
+    uart_puts(if cfg!(feature = "sw-ss-s11") {
+        "  - Fallback:      software shadow stack via s11 register\r\n"
+    } else {
+        "  - Fallback:      software shadow stack via gp register\r\n"
+    });
     uart_puts("  - Type-based:    KCFI hash at [fn-4] checked before indirect calls\r\n");
     uart_puts("  - HW instructions are NOPs on non-CFI hardware (safe)\r\n");
     uart_puts("============================================\r\n");
     uart_puts("\r\nAll tests passed.\r\n");
 
-    // Signal QEMU to exit (virt machine test finisher at 0x100000)
-    // Write 0x5555 (pass) to the test finisher MMIO address
-    unsafe {
-        let test_finisher = 0x10_0000 as *mut u32;
-        test_finisher.write_volatile(0x5555);
-    }
-
-    loop {
-        unsafe { asm!("wfi") };
-    }
+    console::exit(0)
 }
 
 // ============================================================================
 // Panic Handler
 // ============================================================================
 
+/// Set on entry to the panic handler, so a panic while reporting a panic
+/// stops instead of recursing.
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    if PANICKING.swap(true, Ordering::Relaxed) {
+        // Nested panic: no printing, just fail the run (exit status 0xff).
+        console::exit(0xff)
+    }
     uart_puts("\r\n!!! PANIC !!!\r\n");
-    if let Some(loc) = info.location() {
+    // `panic-minimal` builds stop at the banner: no location formatting.
+    let verbose = cfg!(feature = "panic-verbose") && !cfg!(feature = "panic-minimal");
+    if let Some(loc) = info.location().filter(|_| verbose) {
         uart_puts("  at ");
         uart_puts(loc.file());
         uart_puts(":");
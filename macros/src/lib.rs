@@ -0,0 +1,370 @@
+//! `#[cfi_target(label = LABEL)]`: an indirect-call target written as
+//! ordinary Rust.  `#[cfi_target(sig)]` takes the label from the
+//! function's signature instead: `cfi_labels::sig_label` of its parameter
+//! and return types, the same label `sig_label!(fn(..) -> ..)` gives its
+//! call sites.
+//!
+//! Hand-written CFI targets repeat the same frame in every naked function:
+//! `lpad`, the hardware `sspush`, the software shadow-stack push, and the
+//! matching pop-and-compare before each `ret`.  The attribute writes that
+//! frame once.  The function it annotates becomes a naked trampoline:
+//!
+//! ```text
+//!   name:            lpad LABEL
+//!                    sspush ra                     (HW shadow stack)
+//!                    sw ra / x{ss} to the frame; push ra (SW shadow stack)
+//!                    [stack-canary] canary to the frame, below ra
+//!                    call __cfi_body_name          ← the Rust body
+//!                    [stack-canary] check the canary
+//!                    pop the SW shadow stack, compare with the frame's ra
+//!                    sspopchk ra
+//!                    ret
+//! ```
+//!
+//! and the body moves, unchanged, into a separate `extern "C"` function
+//! that only the trampoline calls.  Every way out of the body — `return`,
+//! `?`, falling off the end — returns to the trampoline, so there is one
+//! epilogue however many return paths the body has.
+//!
+//! The trampoline touches only `sp`, `ra`, `t0`/`t1` and the software
+//! shadow-stack register, so the arguments reach the body in `a0`-`a7`
+//! and the result comes back in `a0`/`a1` as they were.  Arguments passed
+//! on the stack would not: the trampoline's frame sits in between, so at
+//! most eight parameters are accepted, and each must fit in its register.
+//!
+//! What is protected is the target's entry (forward edge) and its return
+//! to its caller (backward edge).  The body is compiled like any other
+//! Rust function: its own calls and spills get no more than that.
+//!
+//! The expansion uses the kernel's own pieces, so the attribute works
+//! where they are in scope, in `rot/src/main.rs`: `cfi_target_asm!` emits
+//! the landing pad, and `SW_SS_REG`, `cfi_encoding` and `cfi_labels` are
+//! taken from the crate root.  A function with
+//! `#[link_section = ".u_text"]` is U-mode code: its body gets the same
+//! section, its landing pad counts towards the firmware header's, and a
+//! mismatch under `shadow-trace` reports through `_u_shadow_trace`
+//! instead of `_m_shadow_trace`.  Under `ss-crosscheck` a U-mode target's
+//! `sspopchk` becomes a call to `_u_ss_crosscheck`, which checks the
+//! hardware shadow stack against the software one first.  Under
+//! `stack-canary` the frame holds `_m_stack_canary` (`_u_stack_canary`
+//! for U-mode) below the saved ra and x{ss}.  The check runs before the
+//! shadow-stack compare, and a changed canary goes to `_m_canary_fail`
+//! (`_u_canary_fail`), which reports it as such and halts.
+
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+/// Most parameters a target may take: one per argument register.
+const MAX_PARAMS: usize = 8;
+
+/// Landing pad plus HW and SW shadow-stack frame around a Rust body.
+/// See the crate documentation.
+#[proc_macro_attribute]
+pub fn cfi_target(attr: TokenStream, item: TokenStream) -> TokenStream {
+    match expand(attr, item) {
+        Ok(out) => out,
+        Err((span, msg)) => compile_error(span, &msg),
+    }
+}
+
+type Error = (Span, String);
+
+/// The parts of the annotated function the expansion needs.
+struct Target {
+    attrs: Vec<TokenTree>,
+    /// `link_section` attributes, which the body shares.
+    sections: Vec<TokenTree>,
+    umode: bool,
+    vis: Vec<TokenTree>,
+    unsafety: Option<TokenTree>,
+    name: Ident,
+    params: Group,
+    ret: Vec<TokenTree>,
+    body: Group,
+}
+
+/// What the attribute's argument asks for.
+enum Label {
+    /// `label = EXPR`.
+    Expr(TokenStream),
+    /// `sig`: derived from the signature.
+    Signature,
+}
+
+fn expand(attr: TokenStream, item: TokenStream) -> Result<TokenStream, Error> {
+    let label = parse_label(attr)?;
+    let t = parse_fn(item)?;
+    let label = match label {
+        Label::Expr(e) => e,
+        Label::Signature => {
+            let mut lit = Literal::string(&signature(&t));
+            lit.set_span(t.name.span());
+            let mut e = parse("crate::cfi_labels::sig_label");
+            e.extend([TokenTree::Group(Group::new(Delimiter::Parenthesis, TokenTree::Literal(lit).into()))]);
+            e
+        }
+    };
+    let body_name = Ident::new(&format!("__cfi_body_{}", t.name), t.name.span());
+    let trace = if t.umode { "_u_shadow_trace" } else { "_m_shadow_trace" };
+    let mode = if t.umode { "u" } else { "m" };
+
+    let umode = t.umode;
+    let mut asm = TokenStream::new();
+    asm.extend(parse(&format!(
+        r#"
+        ".4byte {{sspush}}",
+        "addi   sp, sp, -16",
+        "sw     ra, 12(sp)",
+        "sw     x{{ss}}, 8(sp)",
+        ".if {{canary}}",
+        "la     t0, _{mode}_stack_canary",
+        "lw     t0, 0(t0)",
+        "sw     t0, 4(sp)",
+        ".endif",
+        "sw     ra, 0(x{{ss}})",
+        "addi   x{{ss}}, x{{ss}}, 4",
+        "call   {{body}}",
+        ".if {{canary}}",
+        "lw     t0, 4(sp)",
+        "la     t1, _{mode}_stack_canary",
+        "lw     t1, 0(t1)",
+        "bne    t0, t1, 97f",
+        ".endif",
+        "addi   x{{ss}}, x{{ss}}, -4",
+        "lw     t0, 0(x{{ss}})",
+        "lw     ra, 12(sp)",
+        "bne    t0, ra, 99f",
+        "98:",
+        "lw     x{{ss}}, 8(sp)",
+        "addi   sp, sp, 16",
+        ".if {{ss_xcheck}}",
+        "jal    t0, _u_ss_crosscheck",
+        ".else",
+        ".4byte {{sspopchk}}",
+        ".endif",
+        "ret",
+        "99:",
+        ".if {{shadow_trace}}",
+        "mv     t1, t0",
+        "jal    t0, {trace}",
+        "j      98b",
+        ".else",
+        "ebreak",
+        ".endif",
+        ".if {{canary}}",
+        "97:",
+        "mv     a0, t0",
+        "lw     a1, 12(sp)",
+        "j      _{mode}_canary_fail",
+        ".endif",
+        ss = const crate::SW_SS_REG,
+        sspush = const crate::cfi_encoding::SSPUSH_RA,
+        sspopchk = const crate::cfi_encoding::SSPOPCHK_RA,
+        shadow_trace = const cfg!(feature = "shadow-trace") as u32,
+        ss_xcheck = const (cfg!(feature = "ss-crosscheck") && {umode}) as u32,
+        canary = const cfg!(feature = "stack-canary") as u32,
+        body = sym
+        "#
+    )));
+    asm.extend([TokenTree::Ident(body_name.clone()), punct(',')]);
+
+    // Only U_CODE's landing pads count towards the firmware header's.
+    let mut call = if t.umode { TokenStream::new() } else { parse("@outside_u_code") };
+    call.extend(label);
+    call.extend([punct(';')]);
+    call.extend(asm);
+
+    let mut out = TokenStream::new();
+    out.extend(t.attrs);
+    out.extend(parse("#[unsafe(naked)]"));
+    out.extend(t.vis);
+    out.extend(t.unsafety.clone());
+    out.extend(parse("extern \"C\" fn"));
+    out.extend([TokenTree::Ident(t.name), TokenTree::Group(t.params.clone())]);
+    out.extend(t.ret.iter().cloned());
+    let mut invocation = parse("cfi_target_asm!");
+    invocation.extend([TokenTree::Group(Group::new(Delimiter::Parenthesis, call))]);
+    out.extend([TokenTree::Group(Group::new(Delimiter::Brace, invocation))]);
+
+    out.extend(t.sections);
+    out.extend(t.unsafety);
+    out.extend(parse("extern \"C\" fn"));
+    out.extend([TokenTree::Ident(body_name), TokenTree::Group(t.params)]);
+    out.extend(t.ret);
+    out.extend([TokenTree::Group(t.body)]);
+    Ok(out)
+}
+
+/// `label = EXPR`, or `sig`.
+fn parse_label(attr: TokenStream) -> Result<Label, Error> {
+    let mut tokens = attr.into_iter();
+    let expected = "expected `label = LABEL` or `sig`";
+    match tokens.next() {
+        Some(TokenTree::Ident(i)) if i.to_string() == "label" => {}
+        Some(TokenTree::Ident(i)) if i.to_string() == "sig" => {
+            return match tokens.next() {
+                None => Ok(Label::Signature),
+                Some(t) => Err((t.span(), expected.into())),
+            };
+        }
+        Some(t) => return Err((t.span(), expected.into())),
+        None => return Err((Span::call_site(), expected.into())),
+    }
+    match tokens.next() {
+        Some(TokenTree::Punct(p)) if p.as_char() == '=' => {}
+        Some(t) => return Err((t.span(), expected.into())),
+        None => return Err((Span::call_site(), expected.into())),
+    }
+    let label: TokenStream = tokens.collect();
+    if label.is_empty() {
+        return Err((Span::call_site(), expected.into()));
+    }
+    Ok(Label::Expr(label))
+}
+
+/// `fn(TYPES) [-> RET]`, the function-pointer type `sig_label!` is given
+/// at the call sites.  The qualifiers and ABI are left out: every target
+/// is `extern "C"`.
+fn signature(t: &Target) -> String {
+    let types: Vec<String> = split_params(&t.params)
+        .iter()
+        .map(|param| {
+            // The type follows the first `:` that isn't half of a `::`.
+            let colon = param.iter().enumerate().position(|(i, tt)| {
+                let is_colon = |tt: Option<&TokenTree>| matches!(tt, Some(TokenTree::Punct(p)) if p.as_char() == ':');
+                is_colon(Some(tt)) && !is_colon(param.get(i + 1)) && (i == 0 || !is_colon(param.get(i - 1)))
+            });
+            let ty = &param[colon.map_or(0, |c| c + 1)..];
+            ty.iter().cloned().collect::<TokenStream>().to_string()
+        })
+        .collect();
+    let ret: TokenStream = t.ret.iter().cloned().collect();
+    format!("fn({}) {}", types.join(", "), ret)
+}
+
+/// `ATTRS [VIS] [unsafe] extern "C" fn NAME(PARAMS) [-> RET] BODY`.
+fn parse_fn(item: TokenStream) -> Result<Target, Error> {
+    let mut tokens = item.into_iter().peekable();
+    let mut attrs = Vec::new();
+    let mut sections = Vec::new();
+    let mut umode = false;
+    while let Some(TokenTree::Punct(p)) = tokens.peek() {
+        if p.as_char() != '#' {
+            break;
+        }
+        let hash = tokens.next().unwrap();
+        let Some(TokenTree::Group(g)) = tokens.next() else {
+            return Err((hash.span(), "expected an attribute".into()));
+        };
+        if is_link_section(&g) {
+            umode |= g.stream().to_string().contains("\".u_text\"");
+            sections.extend([hash.clone(), TokenTree::Group(g.clone())]);
+        }
+        attrs.extend([hash, TokenTree::Group(g)]);
+    }
+
+    let mut vis = Vec::new();
+    if let Some(TokenTree::Ident(i)) = tokens.peek() {
+        if i.to_string() == "pub" {
+            vis.push(tokens.next().unwrap());
+            if let Some(TokenTree::Group(g)) = tokens.peek() {
+                if g.delimiter() == Delimiter::Parenthesis {
+                    vis.push(tokens.next().unwrap());
+                }
+            }
+        }
+    }
+    let unsafety = match tokens.peek() {
+        Some(TokenTree::Ident(i)) if i.to_string() == "unsafe" => tokens.next(),
+        _ => None,
+    };
+
+    let not_extern_c = "#[cfi_target] needs an `extern \"C\" fn`";
+    match (tokens.next(), tokens.next(), tokens.next()) {
+        (Some(TokenTree::Ident(e)), Some(TokenTree::Literal(abi)), Some(TokenTree::Ident(f)))
+            if e.to_string() == "extern" && abi.to_string() == "\"C\"" && f.to_string() == "fn" => {}
+        (Some(t), ..) => return Err((t.span(), not_extern_c.into())),
+        _ => return Err((Span::call_site(), not_extern_c.into())),
+    }
+    let Some(TokenTree::Ident(name)) = tokens.next() else {
+        return Err((Span::call_site(), "expected the function's name".into()));
+    };
+    let params = match tokens.next() {
+        Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::Parenthesis => g,
+        Some(t) => return Err((t.span(), "#[cfi_target] functions can't be generic".into())),
+        None => return Err((name.span(), "expected a parameter list".into())),
+    };
+    if split_params(&params).len() > MAX_PARAMS {
+        return Err((
+            params.span(),
+            format!("#[cfi_target] takes at most {} parameters, all passed in a0-a7", MAX_PARAMS),
+        ));
+    }
+
+    let mut rest: Vec<TokenTree> = tokens.collect();
+    let body = match rest.pop() {
+        Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::Brace => g,
+        _ => return Err((name.span(), "expected a function body".into())),
+    };
+    if let Some(w) = rest.iter().find(|t| matches!(t, TokenTree::Ident(i) if i.to_string() == "where")) {
+        return Err((w.span(), "#[cfi_target] functions can't have a where clause".into()));
+    }
+    Ok(Target { attrs, sections, umode, vis, unsafety, name, params, ret: rest, body })
+}
+
+fn is_link_section(attr: &Group) -> bool {
+    matches!(attr.stream().into_iter().next(), Some(TokenTree::Ident(i)) if i.to_string() == "link_section")
+}
+
+/// The parameters' tokens.  Top-level commas split them; those between
+/// `<` `>` are inside a type.  The `>` of `->` (a function-pointer
+/// parameter's return type) closes nothing.
+fn split_params(params: &Group) -> Vec<Vec<TokenTree>> {
+    let (mut out, mut depth, mut current) = (Vec::new(), 0usize, Vec::new());
+    let mut arrow = false;
+    for t in params.stream() {
+        let was_arrow = arrow;
+        arrow = false;
+        if let TokenTree::Punct(p) = &t {
+            match p.as_char() {
+                ',' if depth == 0 => {
+                    if !current.is_empty() {
+                        out.push(core::mem::take(&mut current));
+                    }
+                    continue;
+                }
+                '<' => depth += 1,
+                '>' if !was_arrow => depth = depth.saturating_sub(1),
+                '-' => arrow = p.spacing() == Spacing::Joint,
+                _ => {}
+            }
+        }
+        current.push(t);
+    }
+    if !current.is_empty() {
+        out.push(current);
+    }
+    out
+}
+
+fn punct(c: char) -> TokenTree {
+    TokenTree::Punct(Punct::new(c, Spacing::Alone))
+}
+
+fn parse(src: &str) -> TokenStream {
+    src.parse().expect("expansion template is valid Rust")
+}
+
+/// `compile_error!("msg")` at `span`.
+fn compile_error(span: Span, msg: &str) -> TokenStream {
+    let mut lit = Literal::string(msg);
+    lit.set_span(span);
+    let mut args = TokenStream::from(TokenTree::Literal(lit));
+    args = TokenStream::from(TokenTree::Group(Group::new(Delimiter::Parenthesis, args)));
+    let mut out = parse("::core::compile_error!");
+    out.extend(args.into_iter().map(|mut t| {
+        t.set_span(span);
+        t
+    }));
+    out.extend([punct(';')]);
+    out
+}
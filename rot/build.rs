@@ -6,9 +6,31 @@ fn main() {
     // Add the crate root to the linker search path and pass linker scripts.
     // These used to live in .cargo/config.toml rustflags, but in a workspace
     // each crate needs its own linker scripts so we emit them from build.rs.
+    //
+    // Host builds (library unit tests) link with the host toolchain and must
+    // not see the bare-metal memory map.
+    if std::env::var("CARGO_CFG_TARGET_ARCH").as_deref() != Ok("riscv32") {
+        return;
+    }
     let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
     println!("cargo:rustc-link-search=native={}", manifest_dir);
     println!("cargo:rustc-link-arg=-T{}/memory.x", manifest_dir);
     println!("cargo:rustc-link-arg=-T{}/link.x", manifest_dir);
-    println!("cargo:rustc-link-arg=--no-relax");
+
+    // gp is the software shadow-stack pointer unless `sw-ss-s11` moves it
+    // to s11; only then may the linker relax (GP-relative addressing
+    // would otherwise clobber the shadow stack).  s11 is only safe if
+    // LLVM never allocates it, which the s11 target spec arranges.
+    if std::env::var_os("CARGO_FEATURE_SW_SS_S11").is_some() {
+        let target = std::env::var("TARGET").unwrap();
+        if !target.contains("-s11-") {
+            panic!(
+                "the sw-ss-s11 feature needs s11 reserved from the compiler: \
+                 build with --target rv32imac-cfi-s11-none-elf.json (got {})",
+                target
+            );
+        }
+    } else {
+        println!("cargo:rustc-link-arg=--no-relax");
+    }
 }
@@ -15,6 +15,11 @@
 
 ENTRY(_start)
 
+/* U-mode entry point that launch_umode() hands control to via mret.
+ * Defaults to the built-in application; override at link time with
+ * `-C link-arg=--defsym=_u_entry_point=<symbol>`. */
+PROVIDE(_u_entry_point = _u_entry);
+
 SECTIONS
 {
     /* ==================================================================
@@ -26,10 +31,20 @@ SECTIONS
     .text : ALIGN(4) {
         _m_text_start = .;
         KEEP(*(.text.init))
+        /* mtvec's low two bits are its MODE field, so the trap entry must
+         * be 4-byte aligned (the vector table 64, see MTVEC_VECTOR_ALIGN)
+         * or the core enters somewhere else.  With RVC a function need
+         * only be 2-byte aligned, so align here rather than relying on
+         * the code before it. */
+        . = ALIGN(4);
         KEEP(*(.text.trap))
+        . = ALIGN(64);
+        KEEP(*(.text.trap_vector))
         *(.text .text.*)
         _m_text_end = .;
     } > ROM
+    ASSERT(_trap_handler % 4 == 0, "_trap_handler is not 4-byte aligned for mtvec")
+    ASSERT(!DEFINED(_trap_vector) || _trap_vector % 64 == 0, "_trap_vector is not 64-byte aligned for mtvec")
 
     /* M-mode read-only data (in ROM, after code) */
     .rodata : ALIGN(4) {
@@ -54,10 +69,16 @@ SECTIONS
         _m_bss_end = .;
     } > M_RAM
 
+    /* M-mode state that survives a warm reset (the boot record): neither
+     * loaded nor zeroed by _start */
+    .noinit (NOLOAD) : ALIGN(4) {
+        *(.noinit .noinit.*)
+    } > M_RAM
+
     /* M-mode stack (in M_RAM, grows down) */
     .m_stack (NOLOAD) : ALIGN(16) {
         _m_stack_bottom = .;
-        . += 4K;
+        . += _m_stack_size;
         _m_stack_top = .;
     } > M_RAM
 
@@ -91,9 +112,27 @@ SECTIONS
      * Landing pads enforced by Zicfilp here. */
     .u_text : ALIGN(4) {
         _u_text_start = .;
+        KEEP(*(.u_text.header))   /* firmware header: must come first */
         *(.u_text .u_text.*)
         _u_text_end = .;
     } > U_CODE
+    _u_text_size = _u_text_end - _u_text_start;
+
+    /* Address of every landing pad cfi_target_asm! put in .u_text, one
+     * word each: the count goes in the firmware header, which the RoT
+     * checks against the pads it finds in the image.  Kept in ROM, out of
+     * the measured image. */
+    .u_lpad_sites : ALIGN(4) {
+        _u_lpad_sites_start = .;
+        KEEP(*(.u_lpad_sites))
+        _u_lpad_sites_end = .;
+    } > ROM
+    _u_lpad_count = (_u_lpad_sites_end - _u_lpad_sites_start) / 4;
+
+    /* quarantine-policy recovery entry — RX, its own PMP entry (10) */
+    .u_recovery : ALIGN(4) {
+        *(.u_recovery .u_recovery.*)
+    } > U_RECOVERY
 
     /* U-mode read-only data */
     .u_rodata : ALIGN(4) {
@@ -102,6 +141,15 @@ SECTIONS
         _u_rodata_end = .;
     } > U_RODATA
 
+    /* Boot info handed to U-mode in a2 (src/boot_info.rs): written by
+     * M-mode just before launch, read-only to U-mode.  At the very top of
+     * U_RODATA, above anything linked or net-loaded there. */
+    .u_boot_info ORIGIN(U_RODATA) + LENGTH(U_RODATA) - _u_boot_info_size (NOLOAD) : {
+        _u_boot_info = .;
+        . += _u_boot_info_size;
+    } > U_RODATA
+    ASSERT(_u_rodata_end <= _u_boot_info, "U-mode rodata runs into the boot info page")
+
     /* U-mode read-write data */
     .u_data : ALIGN(4) {
         _u_data_start = .;
@@ -116,8 +164,13 @@ SECTIONS
         _u_bss_end = .;
     } > U_RAM
 
-    /* U-mode stack (in U_RAM, grows down) */
-    .u_stack (NOLOAD) : ALIGN(16) {
+    /* U-mode heap: the rest of U_RAM up to the stack, handed out by sbrk
+     * (syscall 22, src/heap.rs) */
+    _u_heap_start = ALIGN(_u_bss_end, 16);
+
+    /* U-mode stack (in U_RAM, grows down).  Pinned to the top of U_RAM
+     * so the U_GUARD page sits directly above it. */
+    .u_stack ORIGIN(U_RAM) + LENGTH(U_RAM) - _u_stack_size (NOLOAD) : ALIGN(16) {
         _u_stack_bottom = .;
         . += _u_stack_size;
         _u_stack_top = .;
@@ -38,9 +38,16 @@ MEMORY
      * PMP: M=RW, U=R.  No execute, no write. */
     U_RODATA    : ORIGIN = 0x80040000, LENGTH = 32K
 
-    /* U-mode read-write data (heap, stack, BSS).
+    /* U-mode read-write data (heap, stack, BSS).  The stack sits at the
+     * top, just below U_GUARD.
      * PMP: M=RW, U=RW.  No execute (W^X enforcement). */
-    U_RAM       : ORIGIN = 0x80048000, LENGTH = 64K
+    U_RAM       : ORIGIN = 0x80048000, LENGTH = 60K
+
+    /* Guard page between the U-mode stack and the shadow stacks: a write
+     * running up off the top of the stack faults here instead of landing
+     * in U_SHADOW.  Nothing is linked into it.
+     * PMP: M=RW, U=none. */
+    U_GUARD     : ORIGIN = 0x80057000, LENGTH = 4K
 
     /* U-mode shadow stack (Zicfiss hardware shadow stack region).
      * On Zicfiss hardware: pages have SS PTE attribute (only sspush/sspop
@@ -51,6 +58,18 @@ MEMORY
      * PMP: M=RW, U=RW. */
     U_SW_SHADOW : ORIGIN = 0x80059000, LENGTH = 4K
 
+    /* Second U-mode application's RAM (32K) and shadow stacks (8K), for
+     * `app-isolation-test` and `sched-demo` builds.  Nothing is linked
+     * into it.
+     * PMP: U=RW only while that application runs (switch_to_app). */
+    U_APP1      : ORIGIN = 0x80060000, LENGTH = 64K
+
+    /* Recovery entry for the `quarantine-policy` CFI violation handler:
+     * U-mode resumes here once a code region has been quarantined.  Kept
+     * out of U_CODE so it stays executable whatever was quarantined.
+     * PMP: U=RX in quarantine-policy builds, none otherwise. */
+    U_RECOVERY  : ORIGIN = 0x80070000, LENGTH = 4K
+
     /* ── Shared / MMIO regions ─────────────────────────────────────────── */
 
     /* UART (QEMU virt 16550 at 0x10000000).
@@ -64,6 +83,13 @@ MEMORY
 
 /* ── Region boundary symbols (used by PMP setup & linker sections) ──── */
 
+/* Stack sizes.  This is the single source of truth: link.x reserves each
+ * stack with `. += <size>`, and the kernel reads the same symbols (by
+ * address, see `layout` in src/main.rs) to report them at boot. */
+
+/* M-mode stack */
+_m_stack_size = 4K;
+
 /* Shadow stack sizes */
 _m_shadow_stack_size    = 4K;
 _m_sw_shadow_stack_size = 4K;
@@ -72,3 +98,14 @@ _u_sw_shadow_stack_size = 4K;
 
 /* U-mode stack */
 _u_stack_size = 8K;
+
+/* U_CODE bounds: the range measured at boot, checked against PMP entry 3 */
+_u_code_start = ORIGIN(U_CODE);
+_u_code_end   = ORIGIN(U_CODE) + LENGTH(U_CODE);
+
+/* U_RAM bounds, for validating buffers U-mode passes to M-mode services */
+_u_ram_start = ORIGIN(U_RAM);
+_u_ram_end   = ORIGIN(U_RAM) + LENGTH(U_RAM);
+
+/* Boot info page at the top of U_RODATA (src/boot_info.rs) */
+_u_boot_info_size = 256;
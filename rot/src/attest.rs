@@ -0,0 +1,396 @@
+//! Attestation quotes.
+//!
+//! A quote binds the PCRs to a verifier-chosen nonce and is signed with a
+//! key derived from the device secret.  It is a message of
+//! [`tlv`](crate::tlv) records, in this order:
+//!
+//! ```text
+//!   tag  len  field
+//!   1    4    magic        "RTQ2"
+//!   2    1    alg          QuoteAlg
+//!   3    4    measurement  XOR check word over U_CODE, big-endian, see
+//!                          [`wire`](crate::wire)
+//!   6    32   pcr digest   SHA-256 over every PCR in order
+//!   4    32   nonce
+//!   5    …    signature    over every byte before this record: 32-byte
+//!                          HMAC-SHA256 tag, or 64-byte ECDSA P-256 `r || s`
+//! ```
+//!
+//! The 32-bit check word is easy to collide, so it identifies nothing on
+//! its own; the PCR digest
+//! ([`MeasurementLog::pcr_digest`](crate::measure::MeasurementLog::pcr_digest))
+//! is what ties a quote to the firmware.  A verifier replays the
+//! measurement log, checks that the PCRs it gets hash to the quote's
+//! digest, then compares the log's entries with the digests it expects.
+//!
+//! The signature is the last record.  [`parse_quote`] skips records it
+//! doesn't know before it, so a later format can add signed fields.
+//!
+//! The HMAC form needs the verifier to hold the same derived key; with the
+//! `ecdsa-attest` feature the verifier only needs the device public key.
+
+#[cfg(feature = "ecdsa-attest")]
+use crate::erase::secure_zero;
+#[cfg(feature = "ecdsa-attest")]
+use crate::hmac::{hkdf_expand, hkdf_extract};
+use crate::hmac::{hkdf_sha256, hmac_sha256, TAG_LEN};
+#[cfg(feature = "ecdsa-attest")]
+use crate::p256::{SigningKey, SIGNATURE_LEN};
+use crate::sha256::DIGEST_LEN;
+use crate::tlv::{TlvReader, TlvWriter, HEADER_LEN};
+
+/// Quote start marker (and format version).
+pub const QUOTE_MAGIC: [u8; 4] = *b"RTQ2";
+
+/// Verifier nonce length.
+pub const NONCE_LEN: usize = 32;
+
+/// Record tags.
+pub const TAG_MAGIC: u8 = 1;
+pub const TAG_ALG: u8 = 2;
+pub const TAG_MEASUREMENT: u8 = 3;
+pub const TAG_NONCE: u8 = 4;
+pub const TAG_SIGNATURE: u8 = 5;
+pub const TAG_PCR_DIGEST: u8 = 6;
+
+/// Length of the signed part of a quote: every record but the signature.
+pub const BODY_LEN: usize = 5 * HEADER_LEN + QUOTE_MAGIC.len() + 1 + 4 + DIGEST_LEN + NONCE_LEN;
+
+/// HKDF `info` for the HMAC quote key.
+pub const HMAC_KEY_INFO: &[u8] = b"rot-quote-hmac-v1";
+
+/// HKDF `info` for the ECDSA quote key.
+#[cfg(feature = "ecdsa-attest")]
+pub const ECDSA_KEY_INFO: &[u8] = b"rot-quote-p256-v1";
+
+/// Signature algorithm, as recorded in the quote's `alg` byte.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum QuoteAlg {
+    HmacSha256 = 1,
+    EcdsaP256 = 2,
+}
+
+impl QuoteAlg {
+    /// The algorithm an `alg` byte names.
+    pub const fn from_u8(alg: u8) -> Option<QuoteAlg> {
+        match alg {
+            1 => Some(QuoteAlg::HmacSha256),
+            2 => Some(QuoteAlg::EcdsaP256),
+            _ => None,
+        }
+    }
+
+    /// Signature length for this algorithm.
+    pub const fn sig_len(self) -> usize {
+        match self {
+            QuoteAlg::HmacSha256 => TAG_LEN,
+            QuoteAlg::EcdsaP256 => 64,
+        }
+    }
+
+    /// Total quote length (body + signature record).
+    pub const fn quote_len(self) -> usize {
+        BODY_LEN + HEADER_LEN + self.sig_len()
+    }
+}
+
+/// The signed part of a quote.
+pub fn quote_body(
+    alg: QuoteAlg,
+    measurement: u32,
+    pcr_digest: &[u8; DIGEST_LEN],
+    nonce: &[u8; NONCE_LEN],
+) -> [u8; BODY_LEN] {
+    let mut body = [0u8; BODY_LEN];
+    let mut w = TlvWriter::new(&mut body);
+    // BODY_LEN is exactly these records, so none of the pushes fails.
+    let _ = w.push(TAG_MAGIC, &QUOTE_MAGIC);
+    let _ = w.push(TAG_ALG, &[alg as u8]);
+    let _ = w.push_u32(TAG_MEASUREMENT, measurement);
+    let _ = w.push(TAG_PCR_DIGEST, pcr_digest);
+    let _ = w.push(TAG_NONCE, nonce);
+    body
+}
+
+/// A quote's fields, as [`parse_quote`] reads them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quote<'a> {
+    pub alg: QuoteAlg,
+    pub measurement: u32,
+    pub pcr_digest: &'a [u8; DIGEST_LEN],
+    pub nonce: &'a [u8; NONCE_LEN],
+    /// The bytes the signature covers.
+    pub body: &'a [u8],
+    pub signature: &'a [u8],
+}
+
+/// Read a quote, for a verifier.  The signature isn't checked: that needs
+/// the key, and is the verifier's job, over [`Quote::body`].
+///
+/// `None` unless the quote starts with the magic record, has the alg,
+/// measurement, PCR digest and nonce records once each, and ends with a signature
+/// record of the alg's length.  Other records before the signature are
+/// skipped.
+pub fn parse_quote(quote: &[u8]) -> Option<Quote<'_>> {
+    fn once<T>(slot: &mut Option<T>, value: Option<T>) -> Option<()> {
+        slot.replace(value?).is_none().then_some(())
+    }
+
+    let (mut alg, mut measurement, mut pcr_digest, mut nonce) = (None, None, None, None);
+    let mut body_len = 0;
+    for (i, rec) in TlvReader::new(quote).enumerate() {
+        let rec = rec.ok()?;
+        match rec.tag {
+            TAG_MAGIC if i == 0 && rec.value == QUOTE_MAGIC => {}
+            _ if i == 0 => return None,
+            TAG_MAGIC => return None,
+            TAG_ALG => once(&mut alg, match rec.value {
+                [alg] => QuoteAlg::from_u8(*alg),
+                _ => None,
+            })?,
+            TAG_MEASUREMENT => once(&mut measurement, rec.as_u32())?,
+            TAG_PCR_DIGEST => once(&mut pcr_digest, rec.value.try_into().ok())?,
+            TAG_NONCE => once(&mut nonce, rec.value.try_into().ok())?,
+            TAG_SIGNATURE => {
+                let alg = alg?;
+                // Nothing may follow the signature: it wouldn't be covered.
+                let signed = body_len + HEADER_LEN + rec.value.len() == quote.len();
+                return (signed && rec.value.len() == alg.sig_len()).then_some(Quote {
+                    alg,
+                    measurement: measurement?,
+                    pcr_digest: pcr_digest?,
+                    nonce: nonce?,
+                    body: &quote[..body_len],
+                    signature: rec.value,
+                });
+            }
+            _ => {}
+        }
+        body_len += HEADER_LEN + rec.value.len();
+    }
+    None
+}
+
+/// Derive the HMAC quote key from the device secret.
+pub fn hmac_quote_key(device_secret: &[u8]) -> [u8; TAG_LEN] {
+    hkdf_sha256(&[], device_secret, HMAC_KEY_INFO)
+}
+
+/// Write an HMAC-SHA256 quote into `out`, returning its length, or `None`
+/// if `out` is too small.
+pub fn hmac_quote(
+    key: &[u8; TAG_LEN],
+    measurement: u32,
+    pcr_digest: &[u8; DIGEST_LEN],
+    nonce: &[u8; NONCE_LEN],
+    out: &mut [u8],
+) -> Option<usize> {
+    let len = QuoteAlg::HmacSha256.quote_len();
+    let out = out.get_mut(..len)?;
+    let body = quote_body(QuoteAlg::HmacSha256, measurement, pcr_digest, nonce);
+    out[..BODY_LEN].copy_from_slice(&body);
+    // `out` is cut to fit the record.
+    let _ = TlvWriter::new(&mut out[BODY_LEN..]).push(TAG_SIGNATURE, &hmac_sha256(key, &body));
+    Some(len)
+}
+
+/// Derive the ECDSA quote key from the device secret.
+///
+/// HKDF output is used as the private scalar; in the (2^-32) case that it
+/// is not in `1..n`, a counter byte is appended to `info` and it retries.
+#[cfg(feature = "ecdsa-attest")]
+pub fn ecdsa_quote_key(device_secret: &[u8]) -> SigningKey {
+    let mut prk = hkdf_extract(&[], device_secret);
+    let mut info = [0u8; ECDSA_KEY_INFO.len() + 1];
+    info[..ECDSA_KEY_INFO.len()].copy_from_slice(ECDSA_KEY_INFO);
+    for counter in 0..=u8::MAX {
+        info[ECDSA_KEY_INFO.len()] = counter;
+        let mut d = [0u8; 32];
+        hkdf_expand(&prk, &info, &mut d);
+        let key = SigningKey::from_bytes(&d);
+        secure_zero(&mut d);
+        if let Some(key) = key {
+            secure_zero(&mut prk);
+            return key;
+        }
+    }
+    unreachable!("256 consecutive out-of-range HKDF outputs")
+}
+
+/// Write an ECDSA P-256 quote into `out`, returning its length, or `None`
+/// if `out` is too small.
+#[cfg(feature = "ecdsa-attest")]
+pub fn ecdsa_quote(
+    key: &SigningKey,
+    measurement: u32,
+    pcr_digest: &[u8; DIGEST_LEN],
+    nonce: &[u8; NONCE_LEN],
+    out: &mut [u8],
+) -> Option<usize> {
+    let len = QuoteAlg::EcdsaP256.quote_len();
+    let out = out.get_mut(..len)?;
+    let body = quote_body(QuoteAlg::EcdsaP256, measurement, pcr_digest, nonce);
+    out[..BODY_LEN].copy_from_slice(&body);
+    // `out` is cut to fit the record.
+    let _ = TlvWriter::new(&mut out[BODY_LEN..]).push(TAG_SIGNATURE, &key.sign(&body));
+    Some(len)
+}
+
+#[cfg(feature = "ecdsa-attest")]
+const _: () = assert!(QuoteAlg::EcdsaP256.sig_len() == SIGNATURE_LEN);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"test-device-secret";
+    const NONCE: [u8; NONCE_LEN] = [0x5a; NONCE_LEN];
+    const PCRS: [u8; DIGEST_LEN] = [0xc3; DIGEST_LEN];
+
+    #[test]
+    fn body_layout() {
+        let body = quote_body(QuoteAlg::HmacSha256, 0x1234_5678, &PCRS, &NONCE);
+        assert_eq!(&body[..18], b"\x01\0\x04RTQ2\x02\0\x01\x01\x03\0\x04\x12\x34\x56\x78");
+        assert_eq!(body[18..21], [TAG_PCR_DIGEST, 0, 32]);
+        assert_eq!(body[21..53], PCRS);
+        assert_eq!(body[53..56], [TAG_NONCE, 0, 32]);
+        assert_eq!(body[56..], NONCE);
+    }
+
+    #[test]
+    fn hmac_quote_verifies() {
+        let key = hmac_quote_key(SECRET);
+        let mut out = [0u8; 160];
+        let n = hmac_quote(&key, 7, &PCRS, &NONCE, &mut out).unwrap();
+        assert_eq!(n, BODY_LEN + HEADER_LEN + TAG_LEN);
+        assert_eq!(out[BODY_LEN..BODY_LEN + HEADER_LEN], [TAG_SIGNATURE, 0, 32]);
+
+        let q = parse_quote(&out[..n]).unwrap();
+        assert_eq!((q.alg, q.measurement), (QuoteAlg::HmacSha256, 7));
+        assert_eq!((q.pcr_digest, q.nonce), (&PCRS, &NONCE));
+        assert_eq!(q.body, &out[..BODY_LEN]);
+        assert_eq!(q.signature, hmac_sha256(&key, q.body));
+    }
+
+    #[test]
+    fn the_signature_covers_the_pcr_digest() {
+        let key = hmac_quote_key(SECRET);
+        let mut out = [0u8; 160];
+        let n = hmac_quote(&key, 7, &PCRS, &NONCE, &mut out).unwrap();
+        let mut other = [0u8; 160];
+        let mut pcrs = PCRS;
+        pcrs[DIGEST_LEN - 1] ^= 1;
+        hmac_quote(&key, 7, &pcrs, &NONCE, &mut other).unwrap();
+        // Same check word, different PCRs: the quotes differ only there,
+        // and in the tag.
+        let (q, o) = (parse_quote(&out[..n]).unwrap(), parse_quote(&other[..n]).unwrap());
+        assert_eq!((q.measurement, q.nonce), (o.measurement, o.nonce));
+        assert_eq!(o.pcr_digest, &pcrs);
+        assert_ne!(q.signature, o.signature);
+    }
+
+    #[test]
+    fn parse_refuses_malformed_quotes() {
+        let key = hmac_quote_key(SECRET);
+        let mut out = [0u8; 160];
+        let n = hmac_quote(&key, 7, &PCRS, &NONCE, &mut out).unwrap();
+        for len in 0..n {
+            assert_eq!(parse_quote(&out[..len]), None, "cut to {len}");
+        }
+        // A record after the signature isn't covered by it.
+        out[n..n + HEADER_LEN].copy_from_slice(&crate::tlv::header(9, 0));
+        assert_eq!(parse_quote(&out[..n + HEADER_LEN]), None);
+
+        let mut bad = out;
+        bad[3..7].copy_from_slice(b"RTQ1");
+        assert_eq!(parse_quote(&bad[..n]), None);
+        let mut bad = out;
+        bad[10] = 3; // no such alg
+        assert_eq!(parse_quote(&bad[..n]), None);
+        let mut bad = out;
+        bad[10] = QuoteAlg::EcdsaP256 as u8; // signature too short for it
+        assert_eq!(parse_quote(&bad[..n]), None);
+
+        let m = 7u32.to_be_bytes();
+        let good = [
+            (TAG_MAGIC, &QUOTE_MAGIC[..]),
+            (TAG_ALG, &[1]),
+            (TAG_MEASUREMENT, &m),
+            (TAG_PCR_DIGEST, &PCRS),
+            (TAG_NONCE, &NONCE),
+        ];
+        assert!(parse_quote(&message(&good)).is_some());
+        for records in [
+            &[good[0], good[1], good[2], good[3]][..],                   // no nonce
+            &[good[0], good[1], good[2], good[4]][..],                   // no PCR digest
+            &[good[0], good[1], good[2], good[3], good[4], good[4]][..], // the nonce twice
+            &[good[0], good[1], good[2], good[3], good[3], good[4]][..], // the PCR digest twice
+            &[good[1], good[0], good[2], good[3], good[4]][..],          // magic not first
+            &[good[0], good[1], good[2], good[3], (TAG_NONCE, &NONCE[1..])][..],
+            &[good[0], good[1], good[2], (TAG_PCR_DIGEST, &PCRS[1..]), good[4]][..],
+            &[good[0], good[1], (TAG_MEASUREMENT, &m[1..]), good[3], good[4]][..],
+        ] {
+            assert_eq!(parse_quote(&message(records)), None, "{records:?}");
+        }
+    }
+
+    /// `records`, then a zero HMAC-length signature.
+    fn message(records: &[(u8, &[u8])]) -> std::vec::Vec<u8> {
+        let mut buf = [0u8; 256];
+        let mut w = TlvWriter::new(&mut buf);
+        for (tag, value) in records {
+            w.push(*tag, value).unwrap();
+        }
+        w.push(TAG_SIGNATURE, &[0; TAG_LEN]).unwrap();
+        w.finish().to_vec()
+    }
+
+    #[test]
+    fn unknown_records_are_skipped_and_signed() {
+        let key = hmac_quote_key(SECRET);
+        let mut msg = [0u8; 192];
+        msg[..BODY_LEN].copy_from_slice(&quote_body(QuoteAlg::HmacSha256, 7, &PCRS, &NONCE));
+        let mut w = TlvWriter::new(&mut msg[BODY_LEN..]);
+        w.push(0x40, b"a field from a later format").unwrap();
+        let body_len = BODY_LEN + w.len();
+        let tag = hmac_sha256(&key, &msg[..body_len]);
+        let mut w = TlvWriter::new(&mut msg[body_len..]);
+        w.push(TAG_SIGNATURE, &tag).unwrap();
+        let len = body_len + w.len();
+
+        let q = parse_quote(&msg[..len]).unwrap();
+        assert_eq!((q.measurement, q.pcr_digest), (7, &PCRS));
+        assert_eq!(q.body, &msg[..body_len]);
+        assert_eq!(q.signature, tag);
+    }
+
+    #[test]
+    fn short_buffer_rejected() {
+        let key = hmac_quote_key(SECRET);
+        let mut out = [0u8; QuoteAlg::HmacSha256.quote_len() - 1];
+        assert_eq!(hmac_quote(&key, 7, &PCRS, &NONCE, &mut out), None);
+    }
+
+    #[cfg(feature = "ecdsa-attest")]
+    #[test]
+    fn ecdsa_quote_verifies() {
+        let key = ecdsa_quote_key(SECRET);
+        let mut out = [0u8; 192];
+        let n = ecdsa_quote(&key, 7, &PCRS, &NONCE, &mut out).unwrap();
+        assert_eq!(n, QuoteAlg::EcdsaP256.quote_len());
+        let q = parse_quote(&out[..n]).unwrap();
+        assert_eq!((q.alg, q.measurement, q.pcr_digest), (QuoteAlg::EcdsaP256, 7, &PCRS));
+
+        let sig: [u8; SIGNATURE_LEN] = q.signature.try_into().unwrap();
+        let pk = key.public_key();
+        assert!(pk.verify(q.body, &sig));
+        for i in [17, 21 + 5] {
+            // The measurement's low byte, or one of the PCR digest's.
+            let mut bad = out;
+            bad[i] ^= 1;
+            assert!(parse_quote(&bad[..n]).is_some());
+            assert!(!pk.verify(&bad[..BODY_LEN], &sig), "byte {i}");
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! Ecall audit trail.
+//!
+//! With `ecall-audit`, the trap handler records every ecall from U-mode
+//! in an [`AuditLog`] before dispatching it: a sequence number, the call
+//! number (a7), its first argument (a0) and the low word of mcycle.  The
+//! log is a ring that keeps the latest [`AuditLog::capacity`] records, so
+//! after a suspected compromise it holds what U-mode asked for last.
+//!
+//! The log lives in M_RAM, which no U-mode PMP entry covers, so U-mode
+//! can't rewrite or erase its own trail.  It reads the trail only through
+//! the `dump_audit` monitor call, which copies records out.  Sequence
+//! numbers start at 0 and count every record ever made.  A gap between
+//! consecutive records, or a first record above 0, shows how many were
+//! overwritten.
+
+use crate::collections::RingBuffer;
+
+/// One recorded ecall.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct EcallRecord {
+    pub seq: u32,
+    /// a7: the service number, or a monitor number with bit 31 set.
+    pub number: u32,
+    /// a0 as the caller passed it.
+    pub arg0: u32,
+    /// mcycle bits 31:0 when the trap was taken.
+    pub time: u32,
+}
+
+impl EcallRecord {
+    /// Size of [`to_bytes`](EcallRecord::to_bytes).
+    pub const LEN: usize = 16;
+
+    /// `seq`, `number`, `arg0`, `time`: four little-endian words, the
+    /// form the dump copies to U-mode.
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut out = [0; Self::LEN];
+        for (i, word) in [self.seq, self.number, self.arg0, self.time].into_iter().enumerate() {
+            out[4 * i..4 * i + 4].copy_from_slice(&word.to_le_bytes());
+        }
+        out
+    }
+}
+
+/// The latest `N` ecalls.
+pub struct AuditLog<const N: usize> {
+    ring: RingBuffer<EcallRecord, N>,
+    /// Records ever made: the next sequence number.
+    recorded: u32,
+}
+
+impl<const N: usize> AuditLog<N> {
+    pub const fn new() -> Self {
+        AuditLog { ring: RingBuffer::new(), recorded: 0 }
+    }
+
+    /// Record an ecall, overwriting the oldest record if the log is full.
+    /// Returns the record's sequence number.
+    pub fn record(&mut self, number: u32, arg0: u32, time: u32) -> u32 {
+        let seq = self.recorded;
+        self.ring.push_overwrite(EcallRecord { seq, number, arg0, time });
+        self.recorded = seq.wrapping_add(1);
+        seq
+    }
+
+    /// The latest `n` records (all of them, if fewer), oldest first.
+    pub fn latest(&self, n: usize) -> impl Iterator<Item = EcallRecord> + '_ {
+        self.ring.iter().skip(self.ring.len().saturating_sub(n))
+    }
+
+    /// Records held now.
+    pub const fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    /// Records ever made, held or overwritten (wraps at 2^32).
+    pub const fn recorded(&self) -> u32 {
+        self.recorded
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<const N: usize> Default for AuditLog<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn records_in_order() {
+        let mut log: AuditLog<8> = AuditLog::new();
+        assert!(log.is_empty());
+        for (i, number) in [1u32, 11, 24, 0x8000_0002].into_iter().enumerate() {
+            assert_eq!(log.record(number, 0xa0d1_0000 + i as u32, 100 * i as u32), i as u32);
+        }
+        let all: Vec<_> = log.latest(usize::MAX).collect();
+        assert_eq!(all.len(), 4);
+        assert_eq!(all.iter().map(|r| r.number).collect::<Vec<_>>(), [1, 11, 24, 0x8000_0002]);
+        assert_eq!(all.iter().map(|r| r.seq).collect::<Vec<_>>(), [0, 1, 2, 3]);
+        assert_eq!(all[2], EcallRecord { seq: 2, number: 24, arg0: 0xa0d1_0002, time: 200 });
+        // The latest two, still oldest first.
+        assert_eq!(log.latest(2).map(|r| r.seq).collect::<Vec<_>>(), [2, 3]);
+        assert_eq!(log.latest(0).count(), 0);
+    }
+
+    #[test]
+    fn a_full_log_keeps_the_latest() {
+        let mut log: AuditLog<4> = AuditLog::new();
+        for i in 0..10 {
+            log.record(i, i, i);
+        }
+        assert_eq!((log.len(), log.recorded()), (4, 10));
+        // Six records overwritten: the oldest held is seq 6.
+        assert_eq!(log.latest(usize::MAX).map(|r| r.seq).collect::<Vec<_>>(), [6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn wire_form() {
+        let r = EcallRecord { seq: 1, number: 0x8000_0002, arg0: 0xdead_beef, time: 0x0102_0304 };
+        assert_eq!(
+            r.to_bytes(),
+            [1, 0, 0, 0, 2, 0, 0, 0x80, 0xef, 0xbe, 0xad, 0xde, 4, 3, 2, 1]
+        );
+    }
+}
@@ -0,0 +1,145 @@
+//! The boot context U-mode is entered with.
+//!
+//! `launch_umode` enters the firmware with three arguments, the
+//! [`EntryArgs`] convention:
+//!
+//! ```text
+//!   a0  monitor-call token (see crate::monitor)
+//!   a1  hartid (mhartid)
+//!   a2  &BootInfo, in U_RODATA
+//! ```
+//!
+//! A [`BootInfo`] describes where the firmware's regions are and holds the
+//! PCR values at launch.  M-mode writes it just before the `mret`, at
+//! `_u_boot_info` at the top of U_RODATA, where U-mode's PMP entry allows
+//! reads but not writes.  The firmware can therefore trust it as much as
+//! it trusts M-mode.  A net-loaded image can't place rodata over it,
+//! because the loader's rodata region stops below it.
+//!
+//! The layout is `repr(C)`, all little-endian words and bytes, so U-mode
+//! code in assembly can read it at fixed offsets.  [`BOOT_INFO_MAGIC`] and
+//! [`BOOT_INFO_VERSION`] lead it.  A later version only appends fields and
+//! grows `size`.
+
+use crate::measure::PCR_COUNT;
+use crate::memory_map::{UART_MMIO, U_CODE, U_RAM, U_RODATA};
+use crate::region::Region;
+
+/// "BOOT", little-endian.
+pub const BOOT_INFO_MAGIC: u32 = u32::from_le_bytes(*b"BOOT");
+pub const BOOT_INFO_VERSION: u32 = 1;
+
+/// Indices into [`BootInfo::regions`].
+pub const REGION_U_CODE: usize = 0;
+pub const REGION_U_RODATA: usize = 1;
+pub const REGION_U_RAM: usize = 2;
+pub const REGION_UART: usize = 3;
+pub const REGION_COUNT: usize = 4;
+
+/// `base .. base + size`, as U-mode reads it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct BootRegion {
+    pub base: u32,
+    pub size: u32,
+}
+
+impl BootRegion {
+    pub const fn from_region(r: Region) -> BootRegion {
+        BootRegion { base: r.base as u32, size: r.size as u32 }
+    }
+}
+
+/// The boot-info page.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct BootInfo {
+    pub magic: u32,
+    pub version: u32,
+    /// `size_of::<BootInfo>()` for this version.
+    pub size: u32,
+    pub hartid: u32,
+    /// The regions U-mode may use, by `REGION_*` index.
+    pub regions: [BootRegion; REGION_COUNT],
+    /// PCR0 .. PCR3 at launch.  The boot PCRs (ROM, firmware) are locked
+    /// by then, so these are the values a quote over them reports.
+    pub pcrs: [[u8; 32]; PCR_COUNT],
+}
+
+impl BootInfo {
+    /// Boot info for `hartid` with the given PCR values and the fixed
+    /// memory map ([`crate::memory_map`]).
+    pub const fn new(hartid: u32, pcrs: [[u8; 32]; PCR_COUNT]) -> BootInfo {
+        BootInfo {
+            magic: BOOT_INFO_MAGIC,
+            version: BOOT_INFO_VERSION,
+            size: size_of::<BootInfo>() as u32,
+            hartid,
+            regions: [
+                BootRegion::from_region(U_CODE),
+                BootRegion::from_region(U_RODATA),
+                BootRegion::from_region(U_RAM),
+                BootRegion::from_region(UART_MMIO),
+            ],
+            pcrs,
+        }
+    }
+}
+
+/// The registers U-mode is entered with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntryArgs {
+    pub token: u32,
+    pub hartid: u32,
+    pub boot_info: usize,
+}
+
+impl EntryArgs {
+    /// a0, a1, a2.
+    pub const fn regs(&self) -> [usize; 3] {
+        [self.token as usize, self.hartid as usize, self.boot_info]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::mem::offset_of;
+
+    #[test]
+    fn layout_is_fixed() {
+        // U-mode reads these at fixed offsets: a change here is a new
+        // version.
+        assert_eq!(offset_of!(BootInfo, magic), 0);
+        assert_eq!(offset_of!(BootInfo, version), 4);
+        assert_eq!(offset_of!(BootInfo, size), 8);
+        assert_eq!(offset_of!(BootInfo, hartid), 12);
+        assert_eq!(offset_of!(BootInfo, regions), 16);
+        assert_eq!(offset_of!(BootInfo, pcrs), 48);
+        assert_eq!(size_of::<BootInfo>(), 176);
+        assert_eq!(BOOT_INFO_MAGIC.to_le_bytes(), *b"BOOT");
+    }
+
+    #[test]
+    fn describes_the_memory_map() {
+        let mut pcrs = [[0; 32]; PCR_COUNT];
+        pcrs[1] = [0xa5; 32];
+        let info = BootInfo::new(3, pcrs);
+        assert_eq!((info.magic, info.version, info.size, info.hartid), (BOOT_INFO_MAGIC, 1, 176, 3));
+        assert_eq!(info.regions[REGION_U_RAM], BootRegion { base: 0x8004_8000, size: 60 * 1024 });
+        assert_eq!(info.regions[REGION_UART], BootRegion { base: 0x1000_0000, size: 4096 });
+        assert_eq!(info.pcrs[1], [0xa5; 32]);
+        // Every region it hands over is one U-mode's PMP entries grant.
+        let config = crate::pmp_map::compute_pmp_config(false);
+        for r in info.regions {
+            let entry = config.matching_entry(r.base as u64).unwrap();
+            assert!(entry.cfg.readable(), "{r:x?} not readable by U-mode");
+        }
+    }
+
+    #[test]
+    fn entry_registers() {
+        let args = EntryArgs { token: 0xdead_beef, hartid: 0, boot_info: 0x8004_7f00 };
+        assert_eq!(args.regs(), [0xdead_beef, 0, 0x8004_7f00]);
+    }
+}
@@ -0,0 +1,186 @@
+//! The boot phases, and the order `rot_main` must run them in.
+//!
+//! Boot is five phases, each building on the one before:
+//!
+//! ```text
+//!   CfiInit -> PmpConfig -> Measure -> Seal -> Launch
+//! ```
+//!
+//! The kernel keeps a [`BootFlow`] in M_RAM and goes through it at every
+//! phase boundary.  [`BootFlow::enter`] accepts only the phase after the
+//! current one, and only once the current one has been
+//! [`complete`](BootFlow::complete)d.  So a phase can't be skipped,
+//! repeated or reordered: a refactor that launched U-mode before the
+//! measurement, or after a measurement that failed, would be refused at
+//! boot instead of running.  The kernel halts on a refusal.
+//!
+//! The flow also records how far boot got.  [`BootFlow::reached`] is the
+//! last phase entered and whether it finished, which is what a recovery
+//! path needs to decide where to pick up.
+
+/// One phase of `rot_main`, in boot order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum BootPhase {
+    /// Phase 1: Zicfilp / Zicfiss enabled and probed.
+    CfiInit = 1,
+    /// Phase 2: the boot PMP map written and checked.
+    PmpConfig,
+    /// Phase 3: the U-mode firmware located, verified and measured.
+    Measure,
+    /// Phase 4: the boot secret sealed.
+    Seal,
+    /// Phase 5: U-mode entered.  Never completed: the `mret` doesn't
+    /// come back.
+    Launch,
+}
+
+impl BootPhase {
+    /// The phase that must come before this one, `None` for the first.
+    pub const fn predecessor(self) -> Option<BootPhase> {
+        match self {
+            BootPhase::CfiInit => None,
+            BootPhase::PmpConfig => Some(BootPhase::CfiInit),
+            BootPhase::Measure => Some(BootPhase::PmpConfig),
+            BootPhase::Seal => Some(BootPhase::Measure),
+            BootPhase::Launch => Some(BootPhase::Seal),
+        }
+    }
+
+    /// Its number in the boot banner, 1-5.
+    pub const fn number(self) -> u8 {
+        self as u8
+    }
+}
+
+/// Why [`BootFlow::enter`] refused a phase.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransitionError {
+    /// `to` doesn't follow `from`: a phase skipped, repeated or run out of
+    /// order.  `from` is `None` before the first phase.
+    OutOfOrder { from: Option<BootPhase>, to: BootPhase },
+    /// `to` does follow, but `from` never completed.
+    Incomplete { from: BootPhase, to: BootPhase },
+}
+
+/// Where boot is: the phase running, and whether it has completed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BootFlow {
+    current: Option<BootPhase>,
+    done: bool,
+}
+
+impl BootFlow {
+    /// Before Phase 1.
+    pub const fn new() -> BootFlow {
+        BootFlow { current: None, done: false }
+    }
+
+    /// Start `to`.  Refused, leaving the flow as it was, unless `to` is
+    /// the phase after the current one and the current one completed.
+    pub fn enter(&mut self, to: BootPhase) -> Result<(), TransitionError> {
+        let from = self.current;
+        if to.predecessor() != from {
+            return Err(TransitionError::OutOfOrder { from, to });
+        }
+        if let Some(from) = from {
+            if !self.done {
+                return Err(TransitionError::Incomplete { from, to });
+            }
+        }
+        self.current = Some(to);
+        self.done = false;
+        Ok(())
+    }
+
+    /// Mark the current phase completed, so the next may be entered.  A
+    /// phase that failed doesn't call this.
+    pub fn complete(&mut self) {
+        self.done = self.current.is_some();
+    }
+
+    /// The last phase entered and whether it completed.  `None` before
+    /// Phase 1.
+    pub const fn reached(&self) -> Option<(BootPhase, bool)> {
+        match self.current {
+            Some(phase) => Some((phase, self.done)),
+            None => None,
+        }
+    }
+}
+
+impl Default for BootFlow {
+    fn default() -> BootFlow {
+        BootFlow::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use BootPhase::*;
+
+    const ORDER: [BootPhase; 5] = [CfiInit, PmpConfig, Measure, Seal, Launch];
+
+    #[test]
+    fn the_boot_order_is_accepted() {
+        let mut flow = BootFlow::new();
+        assert_eq!(flow.reached(), None);
+        for phase in ORDER {
+            flow.enter(phase).unwrap();
+            assert_eq!(flow.reached(), Some((phase, false)));
+            if phase != Launch {
+                flow.complete();
+                assert_eq!(flow.reached(), Some((phase, true)));
+            }
+        }
+        assert_eq!(ORDER.map(BootPhase::number), [1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn skipped_repeated_and_reordered_phases_are_refused() {
+        let mut flow = BootFlow::new();
+        assert_eq!(flow.enter(Measure), Err(TransitionError::OutOfOrder { from: None, to: Measure }));
+        flow.enter(CfiInit).unwrap();
+        flow.complete();
+        flow.enter(PmpConfig).unwrap();
+        flow.complete();
+        for to in [CfiInit, PmpConfig, Seal, Launch] {
+            assert_eq!(
+                flow.enter(to),
+                Err(TransitionError::OutOfOrder { from: Some(PmpConfig), to })
+            );
+        }
+        // Refusals leave boot where it was.
+        assert_eq!(flow.reached(), Some((PmpConfig, true)));
+        flow.enter(Measure).unwrap();
+    }
+
+    #[test]
+    fn no_launch_after_a_failed_measurement() {
+        let mut flow = BootFlow::new();
+        for phase in [CfiInit, PmpConfig] {
+            flow.enter(phase).unwrap();
+            flow.complete();
+        }
+        flow.enter(Measure).unwrap();
+        // The measurement failed: Measure never completes.
+        assert_eq!(
+            flow.enter(Seal),
+            Err(TransitionError::Incomplete { from: Measure, to: Seal })
+        );
+        assert_eq!(
+            flow.enter(Launch),
+            Err(TransitionError::OutOfOrder { from: Some(Measure), to: Launch })
+        );
+        assert_eq!(flow.reached(), Some((Measure, false)));
+    }
+
+    #[test]
+    fn completing_before_the_first_phase_does_nothing() {
+        let mut flow = BootFlow::new();
+        flow.complete();
+        assert_eq!(flow, BootFlow::new());
+        assert!(flow.enter(CfiInit).is_ok());
+    }
+}
@@ -0,0 +1,102 @@
+//! Warm-reset loop counter.
+//!
+//! With `reset-on-panic`, a fatal error resets the system instead of
+//! halting it.  A fault that comes back on every boot would then reset
+//! the RoT as fast as it can boot, forever.  To catch that, the kernel
+//! keeps a [`BootRecord`] in RAM that `_start` neither loads nor zeroes
+//! (`.noinit`), so it survives a warm reset.  Each boot counts itself in
+//! early; after [`MAX_FAILED_BOOTS`] consecutive attempts that never
+//! reached a clean shutdown, the next boot halts in recovery instead of
+//! running (and failing, and resetting) again.
+//!
+//! RAM does not survive a power cycle.  Whatever a cold boot finds there
+//! fails the magic/check test and counts as "no failures yet".
+
+/// `b"BOOT"` read as a little-endian word.
+pub const MAGIC: u32 = u32::from_le_bytes(*b"BOOT");
+
+/// Consecutive failed boots tolerated before halting in recovery.
+pub const MAX_FAILED_BOOTS: u32 = 3;
+
+/// The persistent record.  `check` is `!attempts`, so a stray write or
+/// leftover RAM contents can't pass for a valid count.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BootRecord {
+    magic: u32,
+    attempts: u32,
+    check: u32,
+}
+
+/// What this boot should do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BootMode {
+    /// Boot normally; this is attempt `attempt` (1 after a cold boot or
+    /// clean shutdown).
+    Normal { attempt: u32 },
+    /// `failed` boots in a row never finished: stop here.
+    Recovery { failed: u32 },
+}
+
+impl BootRecord {
+    /// No boot attempts recorded.
+    pub const fn cleared() -> BootRecord {
+        BootRecord::with_attempts(0)
+    }
+
+    const fn with_attempts(attempts: u32) -> BootRecord {
+        BootRecord { magic: MAGIC, attempts, check: !attempts }
+    }
+
+    /// Boot attempts since the last cold boot or clean shutdown; 0 if the
+    /// record isn't valid.
+    pub const fn attempts(&self) -> u32 {
+        if self.magic == MAGIC && self.check == !self.attempts {
+            self.attempts
+        } else {
+            0
+        }
+    }
+
+    /// Count a new boot: the updated record to store back, and the mode
+    /// to boot in.  Every earlier attempt counts as failed, since a clean
+    /// shutdown would have cleared the record.
+    pub const fn next_boot(&self) -> (BootRecord, BootMode) {
+        let failed = self.attempts();
+        if failed >= MAX_FAILED_BOOTS {
+            return (*self, BootMode::Recovery { failed });
+        }
+        let attempt = failed + 1;
+        (BootRecord::with_attempts(attempt), BootMode::Normal { attempt })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cold_boot_starts_at_one() {
+        let garbage = BootRecord { magic: 0, attempts: 7, check: 0 };
+        assert_eq!(garbage.attempts(), 0);
+        assert_eq!(garbage.next_boot().1, BootMode::Normal { attempt: 1 });
+        // A valid magic with a bad check is still garbage.
+        let torn = BootRecord { magic: MAGIC, attempts: 2, check: 0 };
+        assert_eq!(torn.attempts(), 0);
+    }
+
+    #[test]
+    fn consecutive_failures_reach_recovery() {
+        let mut rec = BootRecord::cleared();
+        for n in 1..=MAX_FAILED_BOOTS {
+            let (next, mode) = rec.next_boot();
+            assert_eq!(mode, BootMode::Normal { attempt: n });
+            rec = next;
+        }
+        let (next, mode) = rec.next_boot();
+        assert_eq!(mode, BootMode::Recovery { failed: MAX_FAILED_BOOTS });
+        // Recovery doesn't count further; it stays there until cleared.
+        assert_eq!(next.next_boot().1, mode);
+        assert_eq!(BootRecord::cleared().next_boot().1, BootMode::Normal { attempt: 1 });
+    }
+}
@@ -0,0 +1,99 @@
+//! Hardware CFI status, as detected at boot.
+//!
+//! Zicfilp and Zicfiss are separate extensions, and a core (or emulator)
+//! may implement one without the other.  Their U-mode enables live in
+//! menvcfg, whose bits are WARL: writing an unimplemented one is ignored,
+//! so the only trustworthy answer is what reads back after the write.
+
+use core::fmt;
+
+/// menvcfg / senvcfg bit 2 — Landing Pad Enable (Zicfilp).
+pub const ENVCFG_LPE: u32 = 1 << 2;
+
+/// menvcfg / senvcfg bit 3 — Shadow Stack Enable (Zicfiss).
+pub const ENVCFG_SSE: u32 = 1 << 3;
+
+/// Which CFI mechanisms are actually enforced for U-mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CfiStatus {
+    /// menvcfg.LPE read back as set: landing pads are enforced.
+    pub landing_pads: bool,
+    /// menvcfg.SSE read back as set: the hardware shadow stack is enforced.
+    pub shadow_stack: bool,
+    /// The `ssp` CSR accepted a write and read it back.
+    pub ssp: bool,
+}
+
+impl CfiStatus {
+    /// Status from the menvcfg value read back after setting LPE and SSE,
+    /// and whether `ssp` responded.
+    pub const fn from_menvcfg(menvcfg: u32, ssp: bool) -> CfiStatus {
+        CfiStatus {
+            landing_pads: menvcfg & ENVCFG_LPE != 0,
+            shadow_stack: menvcfg & ENVCFG_SSE != 0,
+            ssp,
+        }
+    }
+
+    /// Both extensions enforced.
+    pub const fn is_full(&self) -> bool {
+        self.landing_pads && self.shadow_stack
+    }
+
+    /// `ssp` exists but SSE didn't stick: Zicfiss is half-present and the
+    /// shadow stack is *not* being enforced, which is easy to mistake for
+    /// working CFI.
+    pub const fn ssp_without_sse(&self) -> bool {
+        self.ssp && !self.shadow_stack
+    }
+}
+
+impl fmt::Display for CfiStatus {
+    /// e.g. `Zicfilp: on, Zicfiss: off (software fallback active)`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let on_off = |on| if on { "on" } else { "off" };
+        write!(
+            f,
+            "Zicfilp: {}, Zicfiss: {}",
+            on_off(self.landing_pads),
+            on_off(self.shadow_stack)
+        )?;
+        if !self.shadow_stack {
+            f.write_str(" (software fallback active)")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::string::ToString;
+
+    #[test]
+    fn decodes_each_bit_independently() {
+        let s = CfiStatus::from_menvcfg(ENVCFG_LPE, true);
+        assert!(s.landing_pads && !s.shadow_stack);
+        assert!(s.ssp_without_sse());
+        assert!(!s.is_full());
+
+        let s = CfiStatus::from_menvcfg(ENVCFG_LPE | ENVCFG_SSE, true);
+        assert!(s.is_full() && !s.ssp_without_sse());
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(
+            CfiStatus::from_menvcfg(ENVCFG_LPE, false).to_string(),
+            "Zicfilp: on, Zicfiss: off (software fallback active)"
+        );
+        assert_eq!(
+            CfiStatus::from_menvcfg(ENVCFG_LPE | ENVCFG_SSE, true).to_string(),
+            "Zicfilp: on, Zicfiss: on"
+        );
+        assert_eq!(
+            CfiStatus::from_menvcfg(0, false).to_string(),
+            "Zicfilp: off, Zicfiss: off (software fallback active)"
+        );
+    }
+}
@@ -0,0 +1,142 @@
+//! Zicfiss instruction encodings.
+//!
+//! The assembler doesn't know the CFI extensions, so the kernel emits
+//! `sspush` / `sspopchk` / `ssrdp` as `.4byte` words.  They are built here from the
+//! instruction fields rather than copied in by hand: a wrong word doesn't
+//! fail to assemble, it silently becomes some other instruction.
+//!
+//! Both are Zimop "may-be-operations" (`SYSTEM` opcode, funct3 = 100), so
+//! a core with Zimop but no Zicfiss, or with the shadow stack disabled,
+//! runs them as writes of 0 to `x0`:
+//!
+//! ```text
+//!   MOP.R.n   1 n[4] 00 n[3:2] 0111 n[1:0] | rs1 | 100 | rd | 1110011
+//!   MOP.RR.n  1 n[2] 00 n[1:0] 1    rs2    | rs1 | 100 | rd | 1110011
+//!
+//!   sspush rs2    = MOP.RR.7 with rs1 = rd = x0
+//!   sspopchk rs1  = MOP.R.28 with rd = x0
+//!   ssrdp rd      = MOP.R.28 with rs1 = x0
+//! ```
+//!
+//! `ssrdp` is the one that writes a register: the plain MOP leaves 0 in
+//! `rd`, which reads as "no active shadow stack".
+//!
+//! `lpad` is `AUIPC x0, label` and lives with the label allocation in
+//! [`crate::cfi_labels`].
+
+/// x1, the return address.
+pub const RA: u32 = 1;
+/// x5, the alternate link register.
+pub const T0: u32 = 5;
+/// x6.
+pub const T1: u32 = 6;
+
+const SYSTEM: u32 = 0b111_0011;
+const FUNCT3_MOP: u32 = 0b100;
+
+/// `MOP.R.n rd, rs1`.
+const fn mop_r(n: u32, rs1: u32, rd: u32) -> u32 {
+    1 << 31
+        | (n >> 4 & 1) << 30
+        | (n >> 2 & 0b11) << 26
+        | 0b0111 << 22
+        | (n & 0b11) << 20
+        | rs1 << 15
+        | FUNCT3_MOP << 12
+        | rd << 7
+        | SYSTEM
+}
+
+/// `MOP.RR.n rd, rs1, rs2`.
+const fn mop_rr(n: u32, rs1: u32, rs2: u32, rd: u32) -> u32 {
+    1 << 31
+        | (n >> 2 & 1) << 30
+        | (n & 0b11) << 26
+        | 1 << 25
+        | rs2 << 20
+        | rs1 << 15
+        | FUNCT3_MOP << 12
+        | rd << 7
+        | SYSTEM
+}
+
+/// Encode `sspush reg`.  Zicfiss only defines it for `ra` and `t0`;
+/// anything else is a compile error in a const context.
+pub const fn sspush(reg: u32) -> u32 {
+    assert!(reg == RA || reg == T0, "sspush takes ra or t0");
+    mop_rr(7, 0, reg, 0)
+}
+
+/// Encode `sspopchk reg` (`ra` or `t0`, as for [`sspush`]).
+pub const fn sspopchk(reg: u32) -> u32 {
+    assert!(reg == RA || reg == T0, "sspopchk takes ra or t0");
+    mop_r(28, reg, 0)
+}
+
+/// Encode `ssrdp rd`: read the shadow-stack pointer into `rd`, which
+/// can't be x0.
+pub const fn ssrdp(rd: u32) -> u32 {
+    assert!(rd != 0 && rd < 32, "ssrdp needs a destination register");
+    mop_r(28, 0, rd)
+}
+
+/// `sspush ra`: every prologue's hardware shadow-stack push.
+pub const SSPUSH_RA: u32 = sspush(RA);
+
+/// `sspopchk ra`: the matching epilogue check.
+pub const SSPOPCHK_RA: u32 = sspopchk(RA);
+
+/// `sspopchk t0`.
+pub const SSPOPCHK_T0: u32 = sspopchk(T0);
+
+/// `ssrdp t1`.
+pub const SSRDP_T1: u32 = ssrdp(T1);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cfi_labels::{lpad, MAX_LABEL};
+
+    #[test]
+    fn shadow_stack_words() {
+        // As `llvm-objdump --mattr=+zicfiss` disassembles them.
+        assert_eq!(SSPUSH_RA, 0xce10_4073);
+        assert_eq!(SSPOPCHK_RA, 0xcdc0_c073);
+        assert_eq!(sspush(T0), 0xce50_4073);
+        assert_eq!(sspopchk(T0), 0xcdc2_c073);
+        assert_eq!(SSRDP_T1, 0xcdc0_4373);
+        assert_eq!(ssrdp(10), 0xcdc0_4573); // ssrdp a0, as the CFI demo spells it
+    }
+
+    #[test]
+    fn fields_round_trip() {
+        for word in [SSPUSH_RA, SSPOPCHK_RA, sspush(T0), sspopchk(T0)] {
+            assert_eq!(word & 0x7f, SYSTEM);
+            assert_eq!(word >> 12 & 0b111, FUNCT3_MOP);
+            assert_eq!(word >> 7 & 0x1f, 0, "rd = x0: a NOP without Zicfiss");
+        }
+        assert_eq!(SSPUSH_RA >> 20 & 0x1f, RA);
+        assert_eq!(SSPUSH_RA >> 15 & 0x1f, 0);
+        assert_eq!(SSPOPCHK_RA >> 15 & 0x1f, RA);
+        // n is scattered over the word; read it back as the spec lays it out.
+        for n in 0..32 {
+            let w = mop_r(n, 0, 0);
+            assert_eq!(w >> 22 & 0xf, 0b0111);
+            assert_eq!((w >> 30 & 1) << 4 | (w >> 26 & 0b11) << 2 | (w >> 20 & 0b11), n);
+        }
+        for n in 0..8 {
+            let w = mop_rr(n, 0, 0, 0);
+            assert_eq!(w >> 25 & 1, 1);
+            assert_eq!((w >> 30 & 1) << 2 | (w >> 26 & 0b11), n);
+        }
+    }
+
+    #[test]
+    fn lpad_is_auipc_x0() {
+        let auipc = |rd: u32, imm: u32| imm << 12 | rd << 7 | 0b001_0111;
+        for label in [0, 1, 2, 0x1234, MAX_LABEL] {
+            assert_eq!(lpad(label), auipc(0, label));
+        }
+        assert_eq!(lpad(0), 0x0000_0017);
+    }
+}
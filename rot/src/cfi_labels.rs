@@ -0,0 +1,218 @@
+//! Landing-pad labels.
+//!
+//! Zicfilp's `lpad` carries a 20-bit label.  An indirect call or jump
+//! through any register other than `ra`/`t0`/`t2` must land on an `lpad`
+//! whose label is 0 (the wildcard) or equals `t2[31:12]`, so giving each
+//! *class* of call site its own label stops a corrupted pointer from one
+//! class reaching targets of another:
+//!
+//! ```text
+//!   caller                         target
+//!   lui   t2, {label}              .4byte {lpad}    // lpad(label)
+//!   jalr  ra, t1, 0
+//! ```
+//!
+//! Both sides take their constant from this module, so they can't drift
+//! apart; in the kernel, targets emit their `lpad` through
+//! `cfi_target_asm!` (directly or from `#[cfi_target]`), so it can't be
+//! left out either.  Allocation policy:
+//!
+//!   - [`UNLABELED`] (0) is kept for entry points reached by `mret` or
+//!     from code we don't control; it accepts any caller.
+//!   - Every other class gets the next free number here, never reused or
+//!     renumbered — a label is part of the binary's CFI policy.
+//!   - A class is "every function reachable from the same set of indirect
+//!     call sites", typically one table.  Services callable by pointer
+//!     from outside (e.g. M-mode crypto) get a class of their own.
+//!   - Alternatively a class is "every function of one signature":
+//!     [`sig_label`] hashes the parameter and return types into the upper
+//!     half of the label space, bit 19 set, so it never meets an allocated
+//!     label.  Targets take it from `#[cfi_target(sig)]`, call sites from
+//!     [`sig_label!`](crate::sig_label), and a pointer can then only reach
+//!     functions it could have been typed as.
+//!
+//! [`ALL`] lists the classes so a const assertion can reject duplicates.
+//!
+//! A signature label is a hash of how the types are *written*, whitespace
+//! aside: `fn(u32) -> u32` and `fn(core::primitive::u32) -> u32` get
+//! different labels, so spell a signature the same way on both sides.
+//! Nineteen bits leave room for collisions; two signatures that share a
+//! label form one class, which is no worse than allocating one by hand.
+
+/// Any caller (label 0).
+pub const UNLABELED: u32 = 0;
+
+/// M-mode crypto services (`rot_measure_firmware`, `rot_seal_secret`).
+pub const CRYPTO: u32 = 1;
+
+/// U-mode function-pointer dispatch (`u_add_100`, `u_double`).
+pub const DISPATCH: u32 = 2;
+
+/// U-mode timer upcall handlers, entered by M-mode's mret
+/// (`timer-upcall`).
+pub const TIMER_UPCALL: u32 = 3;
+
+/// Every allocated label.
+pub const ALL: [u32; 4] = [UNLABELED, CRYPTO, DISPATCH, TIMER_UPCALL];
+
+/// Largest encodable label (20 bits).
+pub const MAX_LABEL: u32 = (1 << 20) - 1;
+
+/// Set in every [`sig_label`], clear in every allocated label.
+pub const SIG_LABEL_BIT: u32 = 1 << 19;
+
+/// The landing-pad label for functions of signature `sig`, written as a
+/// function-pointer type (`"fn(u32, u32) -> u32"`).  FNV-1a over the
+/// bytes that aren't whitespace, folded to 19 bits, with
+/// [`SIG_LABEL_BIT`] set.
+pub const fn sig_label(sig: &str) -> u32 {
+    let bytes = sig.as_bytes();
+    let mut hash: u32 = 0x811c_9dc5;
+    let mut i = 0;
+    while i < bytes.len() {
+        if !bytes[i].is_ascii_whitespace() {
+            hash ^= bytes[i] as u32;
+            hash = hash.wrapping_mul(0x0100_0193);
+        }
+        i += 1;
+    }
+    SIG_LABEL_BIT | ((hash ^ (hash >> 19)) & (SIG_LABEL_BIT - 1))
+}
+
+/// `sig_label!(fn(u32) -> u32)`: [`sig_label`] of the signature as
+/// written, for the `lui t2` of an indirect call to a
+/// `#[cfi_target(sig)]` target.
+#[macro_export]
+macro_rules! sig_label {
+    ($($sig:tt)*) => {
+        $crate::cfi_labels::sig_label(stringify!($($sig)*))
+    };
+}
+
+/// Encode `lpad label` (`AUIPC x0, label`).  Compile error in a const
+/// context if `label` doesn't fit.
+pub const fn lpad(label: u32) -> u32 {
+    assert!(label <= MAX_LABEL, "landing-pad label exceeds 20 bits");
+    (label << 12) | 0x17
+}
+
+/// Whether `word` is an `lpad` (any label): `AUIPC` with `rd` = `x0`.
+pub const fn is_lpad(word: u32) -> bool {
+    word & 0xfff == 0x17
+}
+
+/// Count the landing pads in `code`, a run of instructions starting on a
+/// word boundary.
+///
+/// A linear sweep: each instruction's low two bits give its length (`11`
+/// for 32 bits, anything else for a 16-bit compressed one), so the scan
+/// follows the same boundaries the hart decodes.  Only word-aligned
+/// `lpad`s count — Zicfilp faults on an unaligned one anyway.  Data mixed
+/// into the code would throw the sweep off, which is fine for U_CODE:
+/// apart from the firmware header, which the caller skips, it only holds
+/// instructions.
+pub fn count_lpads(code: &[u8]) -> u32 {
+    let mut count = 0;
+    let mut i = 0;
+    while i + 2 <= code.len() {
+        if code[i] & 0b11 != 0b11 {
+            i += 2;
+            continue;
+        }
+        if i + 4 > code.len() {
+            break;
+        }
+        let word = u32::from_le_bytes([code[i], code[i + 1], code[i + 2], code[i + 3]]);
+        if i % 4 == 0 && is_lpad(word) {
+            count += 1;
+        }
+        i += 4;
+    }
+    count
+}
+
+const fn all_distinct(labels: &[u32]) -> bool {
+    let mut i = 0;
+    while i < labels.len() {
+        let mut j = i + 1;
+        while j < labels.len() {
+            if labels[i] == labels[j] {
+                return false;
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    true
+}
+
+const fn all_allocated(labels: &[u32]) -> bool {
+    let mut i = 0;
+    while i < labels.len() {
+        if labels[i] & SIG_LABEL_BIT != 0 {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+const _: () = assert!(all_distinct(&ALL), "duplicate landing-pad label");
+const _: () = assert!(all_allocated(&ALL), "allocated label in the signature-label range");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodings() {
+        assert_eq!(lpad(UNLABELED), 0x0000_0017);
+        assert_eq!(lpad(CRYPTO), 0x0000_1017);
+        assert_eq!(lpad(MAX_LABEL), 0xffff_f017);
+    }
+
+    #[test]
+    fn counts_lpads_on_instruction_boundaries() {
+        let mut code = Vec::new();
+        code.extend_from_slice(&lpad(DISPATCH).to_le_bytes());
+        code.extend_from_slice(&0x0506u16.to_le_bytes()); // c.slli a0, 1
+        code.extend_from_slice(&0x0506u16.to_le_bytes());
+        code.extend_from_slice(&lpad(UNLABELED).to_le_bytes());
+        code.extend_from_slice(&0x0000_0317u32.to_le_bytes()); // auipc t1, 0: not an lpad
+        assert_eq!(count_lpads(&code), 2);
+
+        // An lpad that isn't word-aligned doesn't count.
+        let mut skewed = 0x0506u16.to_le_bytes().to_vec();
+        skewed.extend_from_slice(&lpad(CRYPTO).to_le_bytes());
+        assert_eq!(count_lpads(&skewed), 0);
+        assert_eq!(count_lpads(&skewed[..3]), 0);
+        assert_eq!(count_lpads(&[]), 0);
+    }
+
+    #[test]
+    fn signature_labels() {
+        let unary = sig_label!(fn(u32) -> u32);
+        let binary = sig_label!(fn(u32, u32) -> u32);
+        assert_ne!(unary, binary);
+        assert_ne!(unary, sig_label("fn(u32) -> u64"));
+        assert_ne!(unary, sig_label("fn(u32)"));
+        // Spacing doesn't matter, spelling does.
+        assert_eq!(unary, sig_label("fn(u32)->u32"));
+        assert_eq!(binary, sig_label(" fn ( u32 , u32 ) -> u32 "));
+        assert_ne!(unary, sig_label("fn(core::primitive::u32) -> u32"));
+
+        for sig in ["", "fn()", "fn(usize) -> usize", "fn(*const u8, usize) -> isize"] {
+            let label = sig_label(sig);
+            assert!(label & SIG_LABEL_BIT != 0 && label <= MAX_LABEL, "{sig}: {label:#x}");
+            assert!(!ALL.contains(&label));
+        }
+        // The hash is part of the binary's CFI policy: it must not change.
+        assert_eq!(sig_label("fn(u32) -> u32"), 0xa_faa4);
+    }
+
+    #[test]
+    fn duplicates_detected() {
+        assert!(all_distinct(&ALL));
+        assert!(!all_distinct(&[1, 2, 1]));
+    }
+}
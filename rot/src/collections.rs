@@ -0,0 +1,293 @@
+//! Fixed-capacity containers.
+//!
+//! No allocator in the RoT, so anything that grows — the measurement log,
+//! dispatch tables built at boot, the UART transmit queue — gets a
+//! compile-time capacity instead.
+//! Running out of room is reported to the caller, never a panic.
+
+use core::mem::MaybeUninit;
+use core::slice;
+
+/// A vector with inline storage for at most `N` elements.
+///
+/// Elements are `Copy` (log entries, function-pointer table entries), so
+/// there is no drop glue to get wrong and the container itself is `Copy`.
+#[derive(Clone, Copy)]
+pub struct FixedVec<T: Copy, const N: usize> {
+    buf: [MaybeUninit<T>; N],
+    /// `buf[..len]` is initialised.
+    len: usize,
+}
+
+impl<T: Copy, const N: usize> FixedVec<T, N> {
+    /// An empty vector.  `const`, so it can initialise a `static`.
+    pub const fn new() -> Self {
+        FixedVec { buf: [const { MaybeUninit::uninit() }; N], len: 0 }
+    }
+
+    /// A full vector holding `items` — e.g. to turn a `static [T; N]`
+    /// dispatch table into a `FixedVec` without changing its initialiser.
+    pub const fn from_array(items: [T; N]) -> Self {
+        let mut buf = [const { MaybeUninit::uninit() }; N];
+        let mut i = 0;
+        while i < N {
+            buf[i] = MaybeUninit::new(items[i]);
+            i += 1;
+        }
+        FixedVec { buf, len: N }
+    }
+
+    /// Append `item`, or hand it back if the vector is full.
+    pub fn push(&mut self, item: T) -> Result<(), T> {
+        match self.buf.get_mut(self.len) {
+            Some(slot) => {
+                slot.write(item);
+                self.len += 1;
+                Ok(())
+            }
+            None => Err(item),
+        }
+    }
+
+    /// Remove and return the last element.
+    pub fn pop(&mut self) -> Option<T> {
+        let last = *self.as_slice().last()?;
+        self.len -= 1;
+        Some(last)
+    }
+
+    /// Element `index`, if it exists.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.as_slice().get(index)
+    }
+
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub const fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// The initialised elements.
+    pub fn as_slice(&self) -> &[T] {
+        // SAFETY: buf[..len] is initialised, and MaybeUninit<T> has the
+        // same layout as T.
+        unsafe { slice::from_raw_parts(self.buf.as_ptr().cast(), self.len) }
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, T> {
+        self.as_slice().iter()
+    }
+}
+
+impl<T: Copy, const N: usize> Default for FixedVec<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Copy, const N: usize> From<[T; N]> for FixedVec<T, N> {
+    fn from(items: [T; N]) -> Self {
+        Self::from_array(items)
+    }
+}
+
+impl<'a, T: Copy, const N: usize> IntoIterator for &'a FixedVec<T, N> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T: Copy + core::fmt::Debug, const N: usize> core::fmt::Debug for FixedVec<T, N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// A FIFO ring with inline storage for at most `N` elements.
+///
+/// Used for the interrupt-driven UART: thread code pushes, the ISR pops.
+/// A trail that should keep the latest entries, like the ecall audit log,
+/// pushes with [`push_overwrite`](RingBuffer::push_overwrite) instead.
+/// The ring itself is not synchronised — callers serialise access (on
+/// this single-hart RoT, by masking interrupts).
+#[derive(Clone, Copy)]
+pub struct RingBuffer<T: Copy, const N: usize> {
+    buf: [MaybeUninit<T>; N],
+    /// Index of the oldest element.
+    head: usize,
+    len: usize,
+}
+
+impl<T: Copy, const N: usize> RingBuffer<T, N> {
+    pub const fn new() -> Self {
+        RingBuffer { buf: [const { MaybeUninit::uninit() }; N], head: 0, len: 0 }
+    }
+
+    /// Append `item` at the tail, or hand it back if the ring is full.
+    pub fn push(&mut self, item: T) -> Result<(), T> {
+        if self.len == N {
+            return Err(item);
+        }
+        self.buf[(self.head + self.len) % N].write(item);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Append `item` at the tail, evicting and returning the oldest
+    /// element if the ring is full.
+    pub fn push_overwrite(&mut self, item: T) -> Option<T> {
+        let evicted = if self.len == N { self.pop() } else { None };
+        // Room now, unless N is 0, in which case there never is.
+        self.push(item).err().or(evicted)
+    }
+
+    /// The elements, oldest first, without removing them.
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        // SAFETY: as in `pop`: these `len` slots are initialised.
+        (0..self.len).map(move |i| unsafe { self.buf[(self.head + i) % N].assume_init() })
+    }
+
+    /// Remove the oldest element.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        // SAFETY: the `len` slots from `head` (mod N) are initialised.
+        let item = unsafe { self.buf[self.head].assume_init() };
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        Some(item)
+    }
+
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub const fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<T: Copy, const N: usize> Default for RingBuffer<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_until_full() {
+        let mut v: FixedVec<u32, 3> = FixedVec::new();
+        assert!(v.is_empty());
+        assert_eq!(v.push(1), Ok(()));
+        assert_eq!(v.push(2), Ok(()));
+        assert_eq!(v.push(3), Ok(()));
+        assert!(v.is_full());
+        assert_eq!(v.push(4), Err(4));
+        assert_eq!(v.len(), 3);
+        assert_eq!(v.as_slice(), &[1, 2, 3]);
+        assert_eq!(v.get(2), Some(&3));
+        assert_eq!(v.get(3), None);
+    }
+
+    #[test]
+    fn pop_and_iter() {
+        let mut v = FixedVec::from([10u8, 20, 30]);
+        assert_eq!(v.pop(), Some(30));
+        assert_eq!(v.iter().copied().sum::<u8>(), 30);
+        assert_eq!(v.push(5), Ok(()));
+        assert_eq!((&v).into_iter().collect::<std::vec::Vec<_>>(), [&10, &20, &5]);
+        v.pop();
+        v.pop();
+        v.pop();
+        assert_eq!(v.pop(), None);
+    }
+
+    #[test]
+    fn const_table() {
+        fn double(x: u32) -> u32 {
+            x * 2
+        }
+        fn square(x: u32) -> u32 {
+            x * x
+        }
+        static TABLE: FixedVec<fn(u32) -> u32, 2> = FixedVec::from_array([double, square]);
+        assert_eq!(TABLE.iter().map(|f| f(3)).collect::<std::vec::Vec<_>>(), [6, 9]);
+    }
+
+    #[test]
+    fn ring_fifo_order_across_wrap() {
+        let mut r: RingBuffer<u8, 4> = RingBuffer::new();
+        for round in 0..5u8 {
+            for i in 0..3 {
+                assert_eq!(r.push(round * 10 + i), Ok(()));
+            }
+            for i in 0..3 {
+                assert_eq!(r.pop(), Some(round * 10 + i));
+            }
+        }
+        assert_eq!(r.pop(), None);
+    }
+
+    #[test]
+    fn ring_rejects_when_full() {
+        let mut r: RingBuffer<u8, 2> = RingBuffer::new();
+        assert_eq!(r.push(1), Ok(()));
+        assert_eq!(r.push(2), Ok(()));
+        assert!(r.is_full());
+        assert_eq!(r.push(3), Err(3));
+        assert_eq!(r.pop(), Some(1));
+        assert_eq!(r.push(3), Ok(()));
+        assert_eq!((r.pop(), r.pop(), r.pop()), (Some(2), Some(3), None));
+    }
+
+    #[test]
+    fn ring_overwrite_keeps_the_latest() {
+        let mut r: RingBuffer<u8, 3> = RingBuffer::new();
+        assert_eq!(r.push_overwrite(1), None);
+        assert_eq!(r.push_overwrite(2), None);
+        assert_eq!(r.push_overwrite(3), None);
+        assert_eq!(r.push_overwrite(4), Some(1));
+        assert_eq!(r.push_overwrite(5), Some(2));
+        assert_eq!(r.iter().collect::<std::vec::Vec<_>>(), [3, 4, 5]);
+        // iter() leaves the ring as it was.
+        assert_eq!(r.len(), 3);
+        assert_eq!(r.pop(), Some(3));
+        assert_eq!(r.iter().collect::<std::vec::Vec<_>>(), [4, 5]);
+
+        let mut none: RingBuffer<u8, 0> = RingBuffer::new();
+        assert_eq!(none.push_overwrite(7), Some(7));
+        assert_eq!(none.iter().count(), 0);
+    }
+
+    #[test]
+    fn zero_capacity() {
+        let mut v: FixedVec<u8, 0> = FixedVec::new();
+        assert!(v.is_full() && v.is_empty());
+        assert_eq!(v.push(1), Err(1));
+    }
+}
@@ -0,0 +1,114 @@
+//! CRC-32 (the IEEE 802.3 / zlib one, CRC-32/ISO-HDLC).
+//!
+//! Reflected polynomial 0xEDB88320, initial value and final XOR
+//! 0xFFFFFFFF.  It catches line noise, not tampering: anyone can compute
+//! a matching CRC for bytes of their choosing.  The framing uses it only
+//! for frames whose authenticity something else vouches for (see
+//! [`crate::frame`]).
+//!
+//! Table-driven, a byte at a time.  The 1K table is built at compile time
+//! and only links into images that call it.
+
+/// The reflected generator polynomial.
+pub const POLY: u32 = 0xedb8_8320;
+
+/// CRC length in bytes.
+pub const CRC_LEN: usize = 4;
+
+const TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { crc >> 1 ^ POLY } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Incremental CRC-32 state.
+#[derive(Clone, Copy, Debug)]
+pub struct Crc32 {
+    state: u32,
+}
+
+impl Crc32 {
+    pub const fn new() -> Self {
+        Crc32 { state: !0 }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for &b in data {
+            self.state = TABLE[(self.state as u8 ^ b) as usize] ^ self.state >> 8;
+        }
+    }
+
+    pub const fn finalize(self) -> u32 {
+        !self.state
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// One-shot CRC-32 of `data`.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_vectors() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"a"), 0xe8b7_be43);
+        assert_eq!(crc32(b"abc"), 0x3524_41c2);
+        // The catalogue check value.
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b"The quick brown fox jumps over the lazy dog"), 0x414f_a339);
+        assert_eq!(crc32(&[0u8; 32]), 0x190a_55ad);
+        assert_eq!(crc32(&[0xffu8; 32]), 0xff6c_ab0b);
+    }
+
+    #[test]
+    fn table_matches_the_bitwise_definition() {
+        assert_eq!(TABLE[0], 0);
+        assert_eq!(TABLE[1], 0x7707_3096);
+        assert_eq!(TABLE[128], POLY);
+        assert_eq!(TABLE[255], 0x2d02_ef8d);
+    }
+
+    #[test]
+    fn incremental_matches_one_shot() {
+        let data: std::vec::Vec<u8> = (0..=255u8).cycle().take(1000).collect();
+        for split in [0, 1, 7, 500, 999, 1000] {
+            let mut crc = Crc32::new();
+            crc.update(&data[..split]);
+            crc.update(&data[split..]);
+            assert_eq!(crc.finalize(), crc32(&data), "split at {split}");
+        }
+    }
+
+    #[test]
+    fn single_bit_flips_are_caught() {
+        let msg = *b"ELF upload chunk";
+        let good = crc32(&msg);
+        for i in 0..msg.len() * 8 {
+            let mut bad = msg;
+            bad[i / 8] ^= 1 << (i % 8);
+            assert_ne!(crc32(&bad), good, "bit {i}");
+        }
+    }
+}
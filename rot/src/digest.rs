@@ -0,0 +1,77 @@
+//! Digest-length-agnostic hashing.
+//!
+//! [`Hasher`] is the interface the measurement log hashes through, and
+//! [`Digest`] the value it produces.  Both are parameterized over the digest
+//! length, so the log isn't tied to SHA-256: [`Sha256`](crate::sha256::Sha256)
+//! is `Hasher<32>`, and a longer hash would only need its own impl.
+
+/// An `N`-byte digest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Digest<const N: usize>(pub [u8; N]);
+
+impl<const N: usize> Digest<N> {
+    /// All-zero, the value a PCR starts from.
+    pub const ZERO: Self = Digest([0; N]);
+
+    pub const fn as_bytes(&self) -> &[u8; N] {
+        &self.0
+    }
+}
+
+impl<const N: usize> Default for Digest<N> {
+    fn default() -> Self {
+        Self::ZERO
+    }
+}
+
+impl<const N: usize> From<[u8; N]> for Digest<N> {
+    fn from(bytes: [u8; N]) -> Self {
+        Digest(bytes)
+    }
+}
+
+impl<const N: usize> AsRef<[u8]> for Digest<N> {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// An incremental hash producing an `N`-byte digest.
+pub trait Hasher<const N: usize> {
+    /// Absorb `data`.
+    fn update(&mut self, data: &[u8]);
+
+    /// Write the digest of everything absorbed to `out`.
+    fn finalize(self, out: &mut [u8; N]);
+
+    /// One-shot digest of `data`.
+    fn digest(data: &[u8]) -> Digest<N>
+    where
+        Self: Default + Sized,
+    {
+        let mut h = Self::default();
+        h.update(data);
+        let mut out = [0; N];
+        h.finalize(&mut out);
+        Digest(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sha256::{sha256, Sha256};
+
+    #[test]
+    fn sha256_trait_path_matches_direct_call() {
+        let data: [u8; 100] = core::array::from_fn(|i| i as u8);
+        assert_eq!(<Sha256 as Hasher<32>>::digest(&data), Digest(sha256(&data)));
+
+        let mut h = Sha256::new();
+        Hasher::update(&mut h, &data[..37]);
+        Hasher::update(&mut h, &data[37..]);
+        let mut out = [0; 32];
+        Hasher::finalize(h, &mut out);
+        assert_eq!(out, sha256(&data));
+    }
+}
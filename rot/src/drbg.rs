@@ -0,0 +1,235 @@
+//! HMAC-DRBG (NIST SP 800-90A, SHA-256) and its boot-time seed.
+//!
+//! The virt board has no hardware RNG, so the kernel seeds the DRBG at
+//! boot from what little unpredictability it can find, through a
+//! [`SeedPool`]:
+//!
+//!   - jitter: how many cycles the same short loop takes, sampled many
+//!     times;
+//!   - the power-up contents of `.bss`, folded by `_start` just before it
+//!     zeroes them.
+//!
+//! Each source also earns a crude entropy credit.  This is not an
+//! estimator: it only catches the obvious failures, such as counters that
+//! tick in lockstep under an emulator or SRAM that reads back as zeroes.
+//! Below [`MIN_SEED_BITS`] the kernel warns.  [`HmacDrbg::reseed`] lets a
+//! later stage mix in real hardware entropy once there is some.
+
+use crate::erase::secure_zero;
+use crate::hmac::{hmac_sha256, HmacSha256};
+use crate::sha256::{Sha256, DIGEST_LEN};
+
+/// Seed credit below which the boot warns: the DRBG's 128-bit security
+/// strength.
+pub const MIN_SEED_BITS: u32 = 128;
+
+/// Most bits the SRAM fold can be credited with: `_start` folds `.bss`
+/// down to one 32-bit word.
+pub const SRAM_FOLD_BITS: u32 = 32;
+
+/// HMAC_DRBG with SHA-256, without prediction resistance.
+pub struct HmacDrbg {
+    k: [u8; DIGEST_LEN],
+    v: [u8; DIGEST_LEN],
+}
+
+impl HmacDrbg {
+    /// Instantiate from `seed` (entropy input, nonce and personalization,
+    /// concatenated).
+    pub fn new(seed: &[u8]) -> HmacDrbg {
+        let mut d = HmacDrbg { k: [0x00; DIGEST_LEN], v: [0x01; DIGEST_LEN] };
+        d.update(seed);
+        d
+    }
+
+    /// Mix `extra` into the state.  Everything generated afterwards depends
+    /// on it as well as on the boot seed.
+    pub fn reseed(&mut self, extra: &[u8]) {
+        self.update(extra);
+    }
+
+    /// Fill `out` with output bits, then step the state past them so a
+    /// later compromise of the state can't recover them.
+    pub fn generate(&mut self, out: &mut [u8]) {
+        for chunk in out.chunks_mut(DIGEST_LEN) {
+            self.v = hmac_sha256(&self.k, &self.v);
+            chunk.copy_from_slice(&self.v[..chunk.len()]);
+        }
+        self.update(&[]);
+    }
+
+    /// HMAC_DRBG_Update: one round per separator byte, the second only if
+    /// there is data to mix in.
+    fn update(&mut self, data: &[u8]) {
+        for sep in [0x00u8, 0x01] {
+            let mut mac = HmacSha256::new(&self.k);
+            mac.update(&self.v);
+            mac.update(&[sep]);
+            mac.update(data);
+            self.k = mac.finalize();
+            self.v = hmac_sha256(&self.k, &self.v);
+            if data.is_empty() {
+                break;
+            }
+        }
+    }
+}
+
+/// Boot-time seed material and its entropy credit.
+pub struct SeedPool {
+    hash: Sha256,
+    bits: u32,
+    last_delta: Option<u32>,
+}
+
+impl Default for SeedPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SeedPool {
+    pub fn new() -> SeedPool {
+        SeedPool { hash: Sha256::new(), bits: 0, last_delta: None }
+    }
+
+    /// One timing sample: the cycles a fixed piece of work took.  Credited
+    /// one bit when it differs from the previous sample.  A counter that
+    /// advances by the same step every time earns nothing.
+    pub fn add_jitter(&mut self, delta: u32) {
+        self.hash.update(&delta.to_le_bytes());
+        if self.last_delta.is_some_and(|last| last != delta) {
+            self.bits += 1;
+        }
+        self.last_delta = Some(delta);
+    }
+
+    /// The `.bss` fold from `_start`: a hash of the power-up contents, and
+    /// how many words differed from the word before them.  Credited one
+    /// bit per such word, up to [`SRAM_FOLD_BITS`].  RAM that comes up
+    /// zeroed (an emulator, or SRAM cleared by a boot ROM) earns nothing.
+    pub fn add_sram(&mut self, fold: u32, varied: u32) {
+        self.hash.update(&fold.to_le_bytes());
+        self.bits += varied.min(SRAM_FOLD_BITS);
+    }
+
+    /// The entropy credited so far, in bits.
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// Whether the credit falls short of [`MIN_SEED_BITS`].
+    pub fn is_low(&self) -> bool {
+        self.bits < MIN_SEED_BITS
+    }
+
+    /// Instantiate a DRBG from everything added.  `personalization` keeps
+    /// devices or uses with the same weak seed apart, but adds no entropy.
+    pub fn instantiate(self, personalization: &[u8]) -> HmacDrbg {
+        let mut seed = [0u8; DIGEST_LEN + 64];
+        let n = personalization.len().min(64);
+        seed[..DIGEST_LEN].copy_from_slice(&self.hash.finalize());
+        seed[DIGEST_LEN..DIGEST_LEN + n].copy_from_slice(&personalization[..n]);
+        let drbg = HmacDrbg::new(&seed[..DIGEST_LEN + n]);
+        secure_zero(&mut seed);
+        drbg
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::hex;
+
+    #[test]
+    fn cavp_no_reseed() {
+        // CAVP HMAC_DRBG SHA-256, no prediction resistance, no reseed,
+        // no personalization or additional input: COUNT = 0.  Generate is
+        // called twice and only the second output is checked.
+        let entropy = hex::<32>("ca851911349384bffe89de1cbdc46e6831e44d34a4fb935ee285dd14b71a7488");
+        let nonce = hex::<16>("659ba96c601dc69fc902940805ec0ca8");
+        let mut seed = [0u8; 48];
+        seed[..32].copy_from_slice(&entropy);
+        seed[32..].copy_from_slice(&nonce);
+        let mut d = HmacDrbg::new(&seed);
+        let mut out = [0u8; 128];
+        d.generate(&mut out);
+        d.generate(&mut out);
+        assert_eq!(
+            out,
+            hex::<128>(
+                "e528e9abf2dece54d47c7e75e5fe302149f817ea9fb4bee6f4199697d04d5b89\
+                 d54fbb978a15b5c443c9ec21036d2460b6f73ebad0dc2aba6e624abf07745bc1\
+                 07694bb7547bb0995f70de25d6b29e2d3011bb19d27676c07162c8b5ccde0668\
+                 961df86803482cb37ed6d5c0bb8d50cf1f50d476aa0458bdaba806f48be9dcb8"
+            )
+        );
+    }
+
+    #[test]
+    fn reseed_changes_the_stream() {
+        let mut a = HmacDrbg::new(b"seed");
+        let mut b = HmacDrbg::new(b"seed");
+        b.reseed(b"hardware entropy");
+        let (mut x, mut y) = ([0u8; 40], [0u8; 40]);
+        a.generate(&mut x);
+        b.generate(&mut y);
+        assert_ne!(x, y);
+
+        // The same reseed on the same state is deterministic, and an empty
+        // one still steps the state.
+        a.reseed(b"hardware entropy");
+        let mut c = HmacDrbg::new(b"seed");
+        c.generate(&mut [0u8; 40]);
+        c.reseed(b"hardware entropy");
+        a.generate(&mut x);
+        c.generate(&mut y);
+        assert_eq!(x, y);
+        c.reseed(&[]);
+        c.generate(&mut y);
+        assert_ne!(x, y);
+    }
+
+    #[test]
+    fn seed_credit() {
+        // Lockstep counters and zeroed RAM: nothing credited.
+        let mut p = SeedPool::new();
+        for _ in 0..300 {
+            p.add_jitter(17);
+        }
+        p.add_sram(0x811c_9dc5, 0);
+        assert_eq!(p.bits(), 0);
+        assert!(p.is_low());
+
+        // Every sample differing from the last earns one bit.
+        let mut p = SeedPool::new();
+        for d in [5, 6, 6, 7, 5] {
+            p.add_jitter(d);
+        }
+        assert_eq!(p.bits(), 3);
+        p.add_sram(0, 1000);
+        assert_eq!(p.bits(), 3 + SRAM_FOLD_BITS);
+        for d in 0..MIN_SEED_BITS {
+            p.add_jitter(d);
+        }
+        assert!(!p.is_low());
+    }
+
+    #[test]
+    fn seed_feeds_the_drbg() {
+        let pool = |delta| {
+            let mut p = SeedPool::new();
+            p.add_jitter(delta);
+            p
+        };
+        let mut out = [[0u8; 32]; 3];
+        pool(1).instantiate(b"rot").generate(&mut out[0]);
+        pool(2).instantiate(b"rot").generate(&mut out[1]);
+        pool(1).instantiate(b"other").generate(&mut out[2]);
+        assert_ne!(out[0], out[1]);
+        assert_ne!(out[0], out[2]);
+        let mut again = [0u8; 32];
+        pool(1).instantiate(b"rot").generate(&mut again);
+        assert_eq!(again, out[0]);
+    }
+}
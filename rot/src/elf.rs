@@ -0,0 +1,380 @@
+//! Minimal ELF32 reader for loading U-mode firmware.
+//!
+//! Reads just enough of a little-endian RISC-V ELF32 executable to load it
+//! without section headers: the ELF header (entry point, program header
+//! table) and its `PT_LOAD` program headers.  [`Image::place`] checks every
+//! segment against the regions it may be loaded into, with the access
+//! U-mode's PMP entries grant there, so a writable code segment or one
+//! aimed at M-mode memory is refused before a byte is copied.
+//!
+//! Images arrive as a stream, so only the first [`HEAD_LEN`] bytes are
+//! needed up front and they must hold both header tables.  [`Image::copy`]
+//! then places any later run of file bytes.
+
+use core::fmt;
+
+use crate::collections::FixedVec;
+
+/// Bytes at the start of the file that must hold the ELF header and the
+/// program header table.
+pub const HEAD_LEN: usize = 512;
+
+/// Most `PT_LOAD` segments an image may have.
+pub const MAX_SEGMENTS: usize = 8;
+
+/// Segment / region permission bits (`p_flags`).
+pub const PF_X: u32 = 1;
+pub const PF_W: u32 = 2;
+pub const PF_R: u32 = 4;
+
+const EHDR_LEN: usize = 52;
+const PHDR_LEN: usize = 32;
+const ELFCLASS32: u8 = 1;
+const ELFDATA2LSB: u8 = 1;
+const ET_EXEC: u16 = 2;
+const EM_RISCV: u16 = 0xF3;
+const PT_LOAD: u32 = 1;
+
+/// One `PT_LOAD` program header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Segment {
+    pub offset: u32,
+    pub vaddr: u32,
+    pub filesz: u32,
+    pub memsz: u32,
+    /// `PF_*` bits.
+    pub flags: u32,
+}
+
+/// Memory a segment may be loaded into, and the `PF_*` access U-mode has
+/// there.  A segment fits if it lies inside the region and asks for no
+/// access the region doesn't grant.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub start: u32,
+    pub len: u32,
+    pub flags: u32,
+}
+
+/// Why an image was refused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ElfError {
+    /// Shorter than the ELF header, or than its program headers claim.
+    Truncated,
+    /// No `\x7fELF` magic.
+    NotElf,
+    /// Not a little-endian ELF32 RISC-V executable.
+    Unsupported,
+    /// The program header table doesn't end within [`HEAD_LEN`] bytes.
+    HeadersTooLarge,
+    /// More than [`MAX_SEGMENTS`] `PT_LOAD` segments.
+    TooManySegments,
+    /// Segment `n` (among the `PT_LOAD`s) is malformed: file size above
+    /// memory size, a range that wraps, or overlap with an earlier one.
+    BadSegment(usize),
+    /// No region holds the segment at this address with its permissions.
+    Unplaced(u32),
+    /// The entry point isn't inside an executable segment.
+    BadEntry(u32),
+}
+
+/// A parsed executable: entry point and load segments.
+#[derive(Clone, Copy, Debug)]
+pub struct Image {
+    pub entry: u32,
+    segments: FixedVec<Segment, MAX_SEGMENTS>,
+}
+
+fn u16_at(b: &[u8], i: usize) -> u16 {
+    u16::from_le_bytes([b[i], b[i + 1]])
+}
+
+fn u32_at(b: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes([b[i], b[i + 1], b[i + 2], b[i + 3]])
+}
+
+impl Segment {
+    fn file_end(&self) -> Option<u32> {
+        self.offset.checked_add(self.filesz)
+    }
+
+    fn mem_end(&self) -> Option<u32> {
+        self.vaddr.checked_add(self.memsz)
+    }
+}
+
+impl Image {
+    /// Parse the ELF header and `PT_LOAD` program headers in `head`, the
+    /// start of the file (up to [`HEAD_LEN`] bytes of it).
+    pub fn parse(head: &[u8]) -> Result<Image, ElfError> {
+        if head.len() < EHDR_LEN {
+            return Err(ElfError::Truncated);
+        }
+        if head[..4] != *b"\x7fELF" {
+            return Err(ElfError::NotElf);
+        }
+        if head[4] != ELFCLASS32
+            || head[5] != ELFDATA2LSB
+            || u16_at(head, 16) != ET_EXEC
+            || u16_at(head, 18) != EM_RISCV
+            || u16_at(head, 42) as usize != PHDR_LEN
+        {
+            return Err(ElfError::Unsupported);
+        }
+        let entry = u32_at(head, 24);
+        let phoff = u32_at(head, 28) as usize;
+        let phnum = u16_at(head, 44) as usize;
+        let table_end = phoff.saturating_add(phnum * PHDR_LEN);
+        if table_end > HEAD_LEN {
+            return Err(ElfError::HeadersTooLarge);
+        }
+        if table_end > head.len() {
+            return Err(ElfError::Truncated);
+        }
+
+        let mut segments = FixedVec::new();
+        for ph in head[phoff..table_end].as_chunks::<PHDR_LEN>().0 {
+            if u32_at(ph, 0) != PT_LOAD {
+                continue;
+            }
+            let seg = Segment {
+                offset: u32_at(ph, 4),
+                vaddr: u32_at(ph, 8),
+                filesz: u32_at(ph, 16),
+                memsz: u32_at(ph, 20),
+                flags: u32_at(ph, 24) & (PF_R | PF_W | PF_X),
+            };
+            let n = segments.len();
+            let overlaps = |s: &Segment| seg.vaddr < s.vaddr + s.memsz && s.vaddr < seg.vaddr + seg.memsz;
+            if seg.filesz > seg.memsz
+                || seg.file_end().is_none()
+                || seg.mem_end().is_none()
+                || segments.iter().any(overlaps)
+            {
+                return Err(ElfError::BadSegment(n));
+            }
+            segments.push(seg).map_err(|_| ElfError::TooManySegments)?;
+        }
+        Ok(Image { entry, segments })
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        self.segments.as_slice()
+    }
+
+    /// Bytes the file must have for every segment's contents to be in it.
+    pub fn file_len(&self) -> u32 {
+        self.segments().iter().filter_map(Segment::file_end).max().unwrap_or(0)
+    }
+
+    /// Check every segment fits a region (see [`Region`]) and the entry
+    /// point is in an executable segment.
+    pub fn place(&self, regions: &[Region]) -> Result<(), ElfError> {
+        for seg in self.segments() {
+            let fits = |r: &Region| {
+                seg.flags & !r.flags == 0
+                    && seg.vaddr >= r.start
+                    && seg.vaddr - r.start <= r.len
+                    && seg.memsz <= r.len - (seg.vaddr - r.start)
+            };
+            if !regions.iter().any(fits) {
+                return Err(ElfError::Unplaced(seg.vaddr));
+            }
+        }
+        let executes = |s: &Segment| {
+            s.flags & PF_X != 0 && self.entry >= s.vaddr && self.entry - s.vaddr < s.memsz
+        };
+        if !self.segments().iter().any(executes) {
+            return Err(ElfError::BadEntry(self.entry));
+        }
+        Ok(())
+    }
+
+    /// Hand `write` every part of `bytes` — file bytes starting at file
+    /// offset `offset` — that belongs to a segment, with its load address.
+    /// Bytes outside every segment (headers, padding) are dropped.
+    pub fn copy(&self, offset: u32, bytes: &[u8], mut write: impl FnMut(u32, &[u8])) {
+        let end = offset as u64 + bytes.len() as u64;
+        for seg in self.segments() {
+            let start = offset.max(seg.offset) as u64;
+            let stop = end.min(seg.offset as u64 + seg.filesz as u64);
+            if start < stop {
+                let from = (start - offset as u64) as usize;
+                let to = (stop - offset as u64) as usize;
+                write(seg.vaddr + (start as u32 - seg.offset), &bytes[from..to]);
+            }
+        }
+    }
+}
+
+impl fmt::Display for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ElfError::Truncated => f.write_str("truncated"),
+            ElfError::NotElf => f.write_str("not an ELF file"),
+            ElfError::Unsupported => f.write_str("not an ELF32 LE RISC-V executable"),
+            ElfError::HeadersTooLarge => write!(f, "program headers beyond the first {} bytes", HEAD_LEN),
+            ElfError::TooManySegments => write!(f, "more than {} load segments", MAX_SEGMENTS),
+            ElfError::BadSegment(n) => write!(f, "malformed load segment {}", n),
+            ElfError::Unplaced(vaddr) => write!(f, "segment at {:#010x} outside its region", vaddr),
+            ElfError::BadEntry(entry) => write!(f, "entry {:#010x} not in an executable segment", entry),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::test_util::Rng;
+    use std::vec::Vec;
+
+    pub const CODE: Region = Region { start: 0x8002_0000, len: 0x2_0000, flags: PF_R | PF_X };
+    pub const RAM: Region = Region { start: 0x8004_8000, len: 0x8000, flags: PF_R | PF_W };
+
+    /// An ELF32 RISC-V executable: headers, then each segment's contents
+    /// (filled with its index + 1) at the offsets given.
+    pub fn elf(entry: u32, segs: &[Segment]) -> Vec<u8> {
+        let phoff = EHDR_LEN;
+        let mut f = vec![0u8; phoff + segs.len() * PHDR_LEN];
+        f[..4].copy_from_slice(b"\x7fELF");
+        f[4] = ELFCLASS32;
+        f[5] = ELFDATA2LSB;
+        f[6] = 1;
+        f[16..18].copy_from_slice(&ET_EXEC.to_le_bytes());
+        f[18..20].copy_from_slice(&EM_RISCV.to_le_bytes());
+        f[24..28].copy_from_slice(&entry.to_le_bytes());
+        f[28..32].copy_from_slice(&(phoff as u32).to_le_bytes());
+        f[42..44].copy_from_slice(&(PHDR_LEN as u16).to_le_bytes());
+        f[44..46].copy_from_slice(&(segs.len() as u16).to_le_bytes());
+        for (i, s) in segs.iter().enumerate() {
+            let ph = &mut f[phoff + i * PHDR_LEN..][..PHDR_LEN];
+            ph[0..4].copy_from_slice(&PT_LOAD.to_le_bytes());
+            for (j, v) in [s.offset, s.vaddr, s.vaddr, s.filesz, s.memsz, s.flags].iter().enumerate() {
+                ph[4 + 4 * j..8 + 4 * j].copy_from_slice(&v.to_le_bytes());
+            }
+        }
+        for (i, s) in segs.iter().enumerate() {
+            let end = (s.offset + s.filesz) as usize;
+            if f.len() < end {
+                f.resize(end, 0);
+            }
+            f[s.offset as usize..end].fill(i as u8 + 1);
+        }
+        f
+    }
+
+    pub fn text(offset: u32, len: u32) -> Segment {
+        Segment { offset, vaddr: CODE.start, filesz: len, memsz: len, flags: PF_R | PF_X }
+    }
+
+    fn data(offset: u32, filesz: u32, memsz: u32) -> Segment {
+        Segment { offset, vaddr: RAM.start, filesz, memsz, flags: PF_R | PF_W }
+    }
+
+    #[test]
+    fn parses_and_places_load_segments() {
+        let segs = [text(0x100, 0x40), data(0x140, 0x10, 0x100)];
+        let f = elf(CODE.start + 8, &segs);
+        let img = Image::parse(&f).unwrap();
+        assert_eq!(img.entry, CODE.start + 8);
+        assert_eq!(img.segments(), &segs);
+        assert_eq!(img.file_len(), 0x150);
+        assert_eq!(img.place(&[CODE, RAM]), Ok(()));
+    }
+
+    #[test]
+    fn refuses_what_it_cannot_load() {
+        let f = elf(CODE.start, &[text(0x100, 0x40)]);
+        assert_eq!(Image::parse(&f[..40]).err(), Some(ElfError::Truncated));
+        assert_eq!(Image::parse(&f[..60]).err(), Some(ElfError::Truncated));
+        let mut bad = f.clone();
+        bad[0] = 0;
+        assert_eq!(Image::parse(&bad).err(), Some(ElfError::NotElf));
+        let mut bad = f.clone();
+        bad[4] = 2; // ELFCLASS64
+        assert_eq!(Image::parse(&bad).err(), Some(ElfError::Unsupported));
+        let mut bad = f.clone();
+        bad[28..32].copy_from_slice(&(HEAD_LEN as u32).to_le_bytes());
+        assert_eq!(Image::parse(&bad).err(), Some(ElfError::HeadersTooLarge));
+
+        let segs = [data(0x100, 0x20, 0x10)];
+        assert_eq!(Image::parse(&elf(0, &segs)).err(), Some(ElfError::BadSegment(0)));
+        let segs = [text(0x100, 0x40), text(0x140, 0x40)];
+        assert_eq!(Image::parse(&elf(0, &segs)).err(), Some(ElfError::BadSegment(1)));
+        let segs: Vec<_> =
+            (0..=MAX_SEGMENTS as u32).map(|i| Segment { vaddr: CODE.start + 0x100 * i, ..text(0x200, 4) }).collect();
+        assert_eq!(Image::parse(&elf(0, &segs)).err(), Some(ElfError::TooManySegments));
+    }
+
+    #[test]
+    fn placement_follows_region_permissions() {
+        let regions = [CODE, RAM];
+        let place = |entry, seg| Image::parse(&elf(entry, &[seg])).unwrap().place(&regions);
+        // Writable code, executable data, out of range, running off the end.
+        let seg = Segment { flags: PF_R | PF_W | PF_X, ..text(0x100, 0x40) };
+        assert_eq!(place(CODE.start, seg), Err(ElfError::Unplaced(CODE.start)));
+        let seg = Segment { flags: PF_R | PF_X, ..data(0x100, 0x10, 0x10) };
+        assert_eq!(place(RAM.start, seg), Err(ElfError::Unplaced(RAM.start)));
+        let seg = Segment { vaddr: 0x8001_0000, ..text(0x100, 0x40) };
+        assert_eq!(place(0x8001_0000, seg), Err(ElfError::Unplaced(0x8001_0000)));
+        let seg = Segment { vaddr: CODE.start + CODE.len - 0x20, ..text(0x100, 0x40) };
+        assert_eq!(place(seg.vaddr, seg), Err(ElfError::Unplaced(seg.vaddr)));
+        // The entry point must be in the code.
+        assert_eq!(place(CODE.start + 0x40, text(0x100, 0x40)), Err(ElfError::BadEntry(CODE.start + 0x40)));
+    }
+
+    #[test]
+    fn copy_maps_file_offsets_to_load_addresses() {
+        let segs = [text(0x100, 0x40), data(0x140, 0x10, 0x100)];
+        let f = elf(CODE.start, &segs);
+        let img = Image::parse(&f).unwrap();
+        let mut writes = Vec::new();
+        // A chunk straddling the end of the text and the start of .data;
+        // the headers before 0x100 belong to no segment.
+        img.copy(0xF0, &f[0xF0..0x148], |addr, b| writes.push((addr, b.to_vec())));
+        assert_eq!(
+            writes,
+            [(CODE.start, vec![1; 0x40]), (RAM.start, vec![2; 8])]
+        );
+        writes.clear();
+        img.copy(0x148, &f[0x148..], |addr, b| writes.push((addr, b.to_vec())));
+        assert_eq!(writes, [(RAM.start + 8, vec![2; 8])]);
+    }
+
+    /// Random and corrupted files never panic the parser: each is refused,
+    /// or parses to segments that passed every check, which placement and
+    /// copying can then use without overflowing.
+    #[test]
+    fn fuzz_parse_never_panics() {
+        let mut rng = Rng(0x2545_f491);
+        let valid = elf(CODE.start + 8, &[text(0x100, 0x40), data(0x140, 0x10, 0x100)]);
+        for i in 0..20_000 {
+            let mut f = if i % 4 == 0 {
+                let len = rng.below(HEAD_LEN + 64);
+                rng.bytes(len)
+            } else {
+                valid.clone()
+            };
+            rng.mutate(&mut f);
+            if i % 3 == 0 {
+                f.truncate(rng.below(f.len() + 1));
+            }
+            let Ok(img) = Image::parse(&f) else { continue };
+
+            let segs = img.segments();
+            assert!(segs.len() <= MAX_SEGMENTS);
+            for (n, s) in segs.iter().enumerate() {
+                assert!(s.filesz <= s.memsz && s.mem_end().is_some());
+                assert!(s.file_end().is_some_and(|end| end <= img.file_len()));
+                assert!(segs[..n].iter().all(|t| s.mem_end() <= Some(t.vaddr) || t.mem_end() <= Some(s.vaddr)));
+            }
+            let _ = img.place(&[CODE, RAM]);
+            let offset = rng.next_u32();
+            let len = rng.below(0x200);
+            img.copy(offset, &rng.bytes(len), |addr, b| {
+                let inside = |s: &Segment| addr >= s.vaddr && (addr - s.vaddr) as u64 + b.len() as u64 <= s.filesz as u64;
+                assert!(segs.iter().any(inside), "write {:#x}+{} outside every segment", addr, b.len());
+            });
+        }
+    }
+}
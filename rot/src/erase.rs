@@ -0,0 +1,70 @@
+//! Wiping secrets from memory.
+//!
+//! A key left in a dead stack slot or a dropped buffer stays readable
+//! until something overwrites it, which could then be a later bug that
+//! leaks memory (an out-of-bounds copy to U-mode, a fault dump).  So key
+//! material is zeroed as soon as the operation that used it is done.
+//!
+//! A plain `buf.fill(0)` is not enough for that.  When the buffer is never
+//! read again, which is the case for every buffer worth wiping, the
+//! zeroing is a dead store, and LLVM removes dead stores in release
+//! builds.  A local array the optimiser can see the end of goes for sure;
+//! a `Drop` that fills a field just before the memory is freed goes too.
+//! [`secure_zero`] writes through `write_volatile`, which the compiler
+//! must emit as written, then puts a `compiler_fence` after the writes so
+//! they can't be moved past later code either.
+//!
+//! This only clears the buffer it is given.  Copies the compiler made
+//! along the way (a key moved into another stack slot, or spilled from
+//! registers) aren't reached, so secrets should be passed by reference
+//! and kept in one place.
+
+use core::ptr;
+use core::sync::atomic::{compiler_fence, Ordering};
+
+/// Zero `buf` in a way the optimiser can't remove, even though nothing
+/// reads it afterwards.
+pub fn secure_zero(buf: &mut [u8]) {
+    wipe(buf);
+}
+
+/// [`secure_zero`] for word-sized secrets, e.g. a private scalar's limbs.
+pub fn secure_zero_words(buf: &mut [u32]) {
+    wipe(buf);
+}
+
+fn wipe<T: Copy + Default>(buf: &mut [T]) {
+    for x in buf.iter_mut() {
+        // SAFETY: `x` is a valid, aligned reference into `buf`.
+        unsafe { ptr::write_volatile(x, T::default()) };
+    }
+    compiler_fence(Ordering::SeqCst);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Whether the stores survive optimisation can only be seen in the
+    // generated code; these check what the calls leave behind.
+
+    #[test]
+    fn zeroes_the_whole_buffer() {
+        let mut key = [0xa5u8; 37];
+        secure_zero(&mut key);
+        assert_eq!(key, [0; 37]);
+
+        let mut limbs = [u32::MAX; 8];
+        secure_zero_words(&mut limbs);
+        assert_eq!(limbs, [0; 8]);
+
+        secure_zero(&mut []);
+    }
+
+    #[test]
+    fn only_the_given_slice() {
+        let mut buf = [0xffu8; 8];
+        secure_zero(&mut buf[2..6]);
+        assert_eq!(buf, [0xff, 0xff, 0, 0, 0, 0, 0xff, 0xff]);
+    }
+}
@@ -0,0 +1,200 @@
+//! U-mode firmware image header.
+//!
+//! The image in U_CODE starts with a fixed header that the RoT checks
+//! before launching it:
+//!
+//! ```text
+//!   offset  size  field
+//!   0       4     magic    b"RTFW"
+//!   4       4     version  anti-rollback version, compared against the
+//!                          RoT's minimum (a fused monotonic counter on a
+//!                          real device)
+//!   8       4     length   image size in bytes, header included
+//!   12      4     lpads    landing pads (`lpad`) the toolchain emitted
+//!                          in the image
+//! ```
+//!
+//! All fields are little-endian.  The header sits inside the measured
+//! region, so its version is covered by the firmware measurement too.
+//!
+//! The landing-pad count is an audit cross-check: the RoT counts the
+//! `lpad`s actually in the image ([`cfi_labels::count_lpads`]) and refuses
+//! it if the two disagree.  An image patched after linking — a landing pad
+//! removed to break a call path, or one added to open a new indirect
+//! target — no longer matches what its build declared.  In the linked
+//! image the count comes from link.x, which counts the pads
+//! `cfi_target_asm!` recorded.
+
+use core::fmt;
+
+use crate::cfi_labels;
+
+/// `b"RTFW"` read as a little-endian word.
+pub const MAGIC: u32 = u32::from_le_bytes(*b"RTFW");
+
+/// Size of the header in bytes.
+pub const HEADER_LEN: usize = 16;
+
+/// A parsed header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FirmwareHeader {
+    pub version: u32,
+    pub length: u32,
+    pub lpad_count: u32,
+}
+
+/// Why an image was refused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeaderError {
+    /// The region is shorter than [`HEADER_LEN`].
+    Truncated,
+    /// The first word isn't [`MAGIC`].
+    BadMagic(u32),
+    /// `length` is shorter than the header or longer than the region.
+    BadLength(u32),
+    /// `version` is below the minimum allowed: a downgrade.
+    Rollback { version: u32, min: u32 },
+    /// The image holds a different number of landing pads than declared.
+    LpadCount { declared: u32, found: u32 },
+}
+
+impl FirmwareHeader {
+    /// Parse the header at the start of `region`, the whole memory region
+    /// the image was loaded into.  The declared length must fit inside it.
+    pub fn parse(region: &[u8]) -> Result<FirmwareHeader, HeaderError> {
+        if region.len() < HEADER_LEN {
+            return Err(HeaderError::Truncated);
+        }
+        let word = |i: usize| {
+            u32::from_le_bytes([region[4 * i], region[4 * i + 1], region[4 * i + 2], region[4 * i + 3]])
+        };
+        let magic = word(0);
+        if magic != MAGIC {
+            return Err(HeaderError::BadMagic(magic));
+        }
+        let version = word(1);
+        let length = word(2);
+        if (length as usize) < HEADER_LEN || length as usize > region.len() {
+            return Err(HeaderError::BadLength(length));
+        }
+        let lpad_count = word(3);
+        Ok(FirmwareHeader { version, length, lpad_count })
+    }
+
+    /// Refuse versions below `min`.
+    pub fn check_version(&self, min: u32) -> Result<(), HeaderError> {
+        if self.version < min {
+            return Err(HeaderError::Rollback { version: self.version, min });
+        }
+        Ok(())
+    }
+
+    /// Count the landing pads in the image after the header (`region` as
+    /// given to [`parse`](Self::parse)) and refuse a count other than the
+    /// declared one.
+    pub fn check_lpads(&self, region: &[u8]) -> Result<(), HeaderError> {
+        let found = cfi_labels::count_lpads(&region[HEADER_LEN..self.length as usize]);
+        if found != self.lpad_count {
+            return Err(HeaderError::LpadCount { declared: self.lpad_count, found });
+        }
+        Ok(())
+    }
+}
+
+/// Parse the header at the start of `region`, check it against the
+/// minimum version and cross-check its landing-pad count — the whole
+/// launch gate.
+pub fn verify(region: &[u8], min_version: u32) -> Result<FirmwareHeader, HeaderError> {
+    let header = FirmwareHeader::parse(region)?;
+    header.check_version(min_version)?;
+    header.check_lpads(region)?;
+    Ok(header)
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            HeaderError::Truncated => f.write_str("region too small for a header"),
+            HeaderError::BadMagic(m) => write!(f, "bad magic {:#010x}", m),
+            HeaderError::BadLength(len) => write!(f, "bad length {:#x}", len),
+            HeaderError::Rollback { version, min } => {
+                write!(f, "version {} below minimum {} (rollback)", version, min)
+            }
+            HeaderError::LpadCount { declared, found } => {
+                write!(f, "{} landing pads, header declares {}", found, declared)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(magic: u32, version: u32, length: u32) -> [u8; 64] {
+        let mut img = [0u8; 64];
+        img[0..4].copy_from_slice(&magic.to_le_bytes());
+        img[4..8].copy_from_slice(&version.to_le_bytes());
+        img[8..12].copy_from_slice(&length.to_le_bytes());
+        img
+    }
+
+    /// A header declaring three landing pads, then code holding them.
+    fn image_with_lpads() -> [u8; 64] {
+        let mut img = image(MAGIC, 1, 40);
+        img[12..16].copy_from_slice(&3u32.to_le_bytes());
+        img[16..20].copy_from_slice(&cfi_labels::lpad(cfi_labels::UNLABELED).to_le_bytes());
+        img[20..24].copy_from_slice(&0x0640_0513u32.to_le_bytes()); // li a0, 100
+        img[24..28].copy_from_slice(&cfi_labels::lpad(cfi_labels::DISPATCH).to_le_bytes());
+        img[28..30].copy_from_slice(&0x8082u16.to_le_bytes()); // ret
+        img[30..32].copy_from_slice(&0x0001u16.to_le_bytes()); // nop
+        img[32..36].copy_from_slice(&cfi_labels::lpad(cfi_labels::DISPATCH).to_le_bytes());
+        img
+    }
+
+    #[test]
+    fn accepts_current_and_newer() {
+        let img = image(MAGIC, 3, 40);
+        assert_eq!(verify(&img, 3), Ok(FirmwareHeader { version: 3, length: 40, lpad_count: 0 }));
+        assert_eq!(verify(&img, 1).map(|h| h.version), Ok(3));
+        assert_eq!(&img[..4], b"RTFW");
+    }
+
+    #[test]
+    fn too_old_version_blocks_launch() {
+        let img = image(MAGIC, 1, 64);
+        assert_eq!(verify(&img, 2), Err(HeaderError::Rollback { version: 1, min: 2 }));
+    }
+
+    #[test]
+    fn malformed_headers() {
+        assert_eq!(verify(&[0; 8], 0), Err(HeaderError::Truncated));
+        assert_eq!(verify(&image(0, 1, 64), 0), Err(HeaderError::BadMagic(0)));
+        assert_eq!(verify(&image(MAGIC, 1, 65), 0), Err(HeaderError::BadLength(65)));
+        assert_eq!(verify(&image(MAGIC, 1, 4), 0), Err(HeaderError::BadLength(4)));
+        assert_eq!(verify(&image(MAGIC, 1, 12), 0), Err(HeaderError::BadLength(12)));
+    }
+
+    #[test]
+    fn declared_lpads_match() {
+        let img = image_with_lpads();
+        assert_eq!(verify(&img, 1).map(|h| h.lpad_count), Ok(3));
+    }
+
+    #[test]
+    fn removed_lpad_is_refused() {
+        let mut img = image_with_lpads();
+        // Patch the middle landing pad into a nop (`addi x0, x0, 0`).
+        img[24..28].copy_from_slice(&0x0000_0013u32.to_le_bytes());
+        assert_eq!(verify(&img, 1), Err(HeaderError::LpadCount { declared: 3, found: 2 }));
+    }
+
+    #[test]
+    fn lpads_past_the_declared_length_are_not_counted() {
+        let mut img = image_with_lpads();
+        img[40..44].copy_from_slice(&cfi_labels::lpad(cfi_labels::UNLABELED).to_le_bytes());
+        assert!(verify(&img, 1).is_ok());
+        img[12..16].copy_from_slice(&4u32.to_le_bytes());
+        assert_eq!(verify(&img, 1), Err(HeaderError::LpadCount { declared: 4, found: 3 }));
+    }
+}
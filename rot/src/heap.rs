@@ -0,0 +1,130 @@
+//! The U-mode heap break behind the `sbrk` ecall.
+//!
+//! The heap is the part of U_RAM between the linked image's `.u_bss` and
+//! the bottom of the U-mode stack:
+//!
+//! ```text
+//!   _u_heap_start          break                 _u_stack_bottom   _u_stack_top
+//!   |<---- allocated ---->|<------- free ------->|<---- stack ----->|
+//! ```
+//!
+//! M-mode keeps the break in a [`Break`].  U-mode asks to move it with
+//! `sbrk(increment)` and gets the old break back, as with POSIX `sbrk`.
+//! A move that would take the break below the heap start or past the
+//! limit is refused, and the break stays where it was.  So U-mode gets
+//! an error instead of a heap that runs into its stack.  A U-mode
+//! allocator (bump or free-list) can build on the memory it is handed.
+//!
+//! The whole heap lies inside U_RAM's PMP grant, so no break value can
+//! give U-mode memory it couldn't already reach.  The break decides which
+//! part of that memory is the heap, not what PMP allows.
+
+/// A heap break confined to `start..=limit`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Break {
+    start: usize,
+    limit: usize,
+    brk: usize,
+}
+
+impl Break {
+    /// An empty heap at `start` that may grow up to `limit`.
+    pub const fn new(start: usize, limit: usize) -> Break {
+        assert!(start <= limit, "heap start above its limit");
+        Break { start, limit, brk: start }
+    }
+
+    /// No heap at all: every non-zero move is refused.
+    pub const fn none() -> Break {
+        Break { start: 0, limit: 0, brk: 0 }
+    }
+
+    /// Move the break by `increment` bytes.  Returns the old break, or
+    /// `None`, leaving the break unchanged, if the new one would fall
+    /// outside `start..=limit`.
+    pub fn sbrk(&mut self, increment: isize) -> Option<usize> {
+        let old = self.brk;
+        let new = old.checked_add_signed(increment)?;
+        if new < self.start || new > self.limit {
+            return None;
+        }
+        self.brk = new;
+        Some(old)
+    }
+
+    pub const fn current(&self) -> usize {
+        self.brk
+    }
+
+    pub const fn start(&self) -> usize {
+        self.start
+    }
+
+    pub const fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Bytes the break can still grow by.
+    pub const fn remaining(&self) -> usize {
+        self.limit - self.brk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: usize = 0x8004_9000;
+    const LIMIT: usize = 0x8005_5000;
+
+    #[test]
+    fn grows_and_shrinks() {
+        let mut b = Break::new(START, LIMIT);
+        assert_eq!(b.sbrk(0), Some(START));
+        assert_eq!(b.sbrk(64), Some(START));
+        assert_eq!(b.sbrk(100), Some(START + 64));
+        assert_eq!(b.current(), START + 164);
+        assert_eq!(b.sbrk(-100), Some(START + 164));
+        assert_eq!(b.sbrk(-64), Some(START + 64));
+        assert_eq!(b.current(), START);
+    }
+
+    #[test]
+    fn exhaustion_is_an_error_not_an_overlap() {
+        let mut b = Break::new(START, LIMIT);
+        let mut pages = 0;
+        while b.sbrk(4096).is_some() {
+            pages += 1;
+        }
+        assert_eq!(pages, (LIMIT - START) / 4096);
+        assert_eq!(b.current(), LIMIT);
+        assert_eq!(b.remaining(), 0);
+        // Refusals leave the break unchanged.
+        assert_eq!(b.sbrk(1), None);
+        assert_eq!(b.sbrk(isize::MAX), None);
+        assert_eq!(b.current(), LIMIT);
+        // What's left can still be taken exactly.
+        b.sbrk(-10).unwrap();
+        assert_eq!(b.sbrk(10), Some(LIMIT - 10));
+    }
+
+    #[test]
+    fn never_below_start_or_wrapping() {
+        let mut b = Break::new(START, LIMIT);
+        assert_eq!(b.sbrk(-1), None);
+        assert_eq!(b.sbrk(isize::MIN), None);
+        assert_eq!(b.current(), START);
+
+        let mut top = Break::new(usize::MAX - 8, usize::MAX);
+        assert_eq!(top.sbrk(8), Some(usize::MAX - 8));
+        assert_eq!(top.sbrk(1), None);
+    }
+
+    #[test]
+    fn no_heap() {
+        let mut b = Break::none();
+        assert_eq!(b.sbrk(0), Some(0));
+        assert_eq!(b.sbrk(1), None);
+        assert_eq!(b.sbrk(-1), None);
+    }
+}
@@ -0,0 +1,154 @@
+//! Lowercase hex formatting for digests, keys and quotes.
+//!
+//! A SHA-256 digest is 64 hex characters, too long to read at a glance or
+//! to fit next to a label on one console line, so [`HexBytes`] can split
+//! the output into space-separated groups and wrap it onto indented lines.
+//!
+//! [`decode_hex`] goes the other way, for a host reading back what the
+//! console printed [`PLAIN`](HexLayout::PLAIN).
+
+use core::fmt;
+
+/// How [`HexBytes`] lays out its output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HexLayout {
+    /// Bytes per space-separated group; 0 = no spaces.
+    pub group: usize,
+    /// Bytes per line; 0 = everything on one line.
+    pub line: usize,
+    /// Written at the start of every line after the first.
+    pub indent: &'static str,
+}
+
+impl HexLayout {
+    /// One unbroken run of hex digits.
+    pub const PLAIN: HexLayout = HexLayout { group: 0, line: 0, indent: "" };
+
+    /// Space-separated groups of `group` bytes, on one line.
+    pub const fn grouped(group: usize) -> HexLayout {
+        HexLayout { group, line: 0, indent: "" }
+    }
+
+    /// The same layout, wrapped every `line` bytes onto a new line that
+    /// starts with `indent`.
+    pub const fn wrapped(self, line: usize, indent: &'static str) -> HexLayout {
+        HexLayout { line, indent, ..self }
+    }
+}
+
+/// A byte slice that displays as hex in a [`HexLayout`].
+#[derive(Clone, Copy, Debug)]
+pub struct HexBytes<'a> {
+    bytes: &'a [u8],
+    layout: HexLayout,
+}
+
+impl<'a> HexBytes<'a> {
+    pub const fn new(bytes: &'a [u8], layout: HexLayout) -> HexBytes<'a> {
+        HexBytes { bytes, layout }
+    }
+}
+
+impl fmt::Display for HexBytes<'_> {
+    /// e.g. `9f86d081 884c7d65` for 8 bytes grouped by 4.  Lines end in
+    /// `\r\n`, as everything on the console does; there is none after the
+    /// last one.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let HexLayout { group, line, indent } = self.layout;
+        for (i, b) in self.bytes.iter().enumerate() {
+            // Groups restart on every line.
+            let column = if line != 0 { i % line } else { i };
+            if i > 0 && column == 0 {
+                f.write_str("\r\n")?;
+                f.write_str(indent)?;
+            } else if column > 0 && group != 0 && column % group == 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decode the unbroken hex string `s` into `out`, which must be exactly
+/// `s.len() / 2` bytes.  Either case is accepted.  `false` if the length
+/// doesn't match or `s` holds anything but hex digits; `out` may then be
+/// partly written.
+pub fn decode_hex(s: &str, out: &mut [u8]) -> bool {
+    let s = s.as_bytes();
+    if s.len() != 2 * out.len() {
+        return false;
+    }
+    for (&[hi, lo], b) in s.as_chunks::<2>().0.iter().zip(out.iter_mut()) {
+        let (Some(hi), Some(lo)) = (nibble(hi), nibble(lo)) else {
+            return false;
+        };
+        *b = hi << 4 | lo;
+    }
+    true
+}
+
+fn nibble(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::hex;
+
+    const DIGEST: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+    #[test]
+    fn plain_is_the_hex_string() {
+        let d = hex::<32>(DIGEST);
+        assert_eq!(HexBytes::new(&d, HexLayout::PLAIN).to_string(), DIGEST);
+        assert_eq!(HexBytes::new(&[], HexLayout::grouped(4)).to_string(), "");
+        assert_eq!(HexBytes::new(&[0x0a], HexLayout::PLAIN).to_string(), "0a");
+    }
+
+    #[test]
+    fn groups_and_wraps() {
+        let d = hex::<32>(DIGEST);
+        assert_eq!(
+            HexBytes::new(&d[..10], HexLayout::grouped(4)).to_string(),
+            "9f86d081 884c7d65 9a2f"
+        );
+        assert_eq!(
+            HexBytes::new(&d, HexLayout::grouped(4).wrapped(16, "  ")).to_string(),
+            "9f86d081 884c7d65 9a2feaa0 c55ad015\r\n  a3bf4f1b 2b0b822c d15d6c15 b0f00a08"
+        );
+        // Wrapping without grouping; with a line that isn't a multiple of
+        // the group, each line still starts a fresh group.
+        assert_eq!(
+            HexBytes::new(&d[..5], HexLayout::PLAIN.wrapped(2, "")).to_string(),
+            "9f86\r\nd081\r\n88"
+        );
+        assert_eq!(
+            HexBytes::new(&d[..6], HexLayout::grouped(2).wrapped(3, "> ")).to_string(),
+            "9f86 d0\r\n> 8188 4c"
+        );
+    }
+
+    #[test]
+    fn decode_reverses_plain() {
+        let d = hex::<32>(DIGEST);
+        let mut out = [0u8; 32];
+        assert!(decode_hex(DIGEST, &mut out));
+        assert_eq!(out, d);
+        assert!(decode_hex(&DIGEST.to_uppercase(), &mut out));
+        assert_eq!(out, d);
+        assert!(decode_hex("", &mut []));
+
+        // Wrong length, grouping, signs and non-hex are refused.
+        assert!(!decode_hex(&DIGEST[..62], &mut out));
+        assert!(!decode_hex("9f 86d", &mut [0; 3]));
+        assert!(!decode_hex("+a", &mut [0]));
+        assert!(!decode_hex("0g", &mut [0]));
+    }
+}
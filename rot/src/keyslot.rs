@@ -0,0 +1,164 @@
+//! Write-once device key slots.
+//!
+//! A production RoT gets its keys once, at manufacturing, by burning them
+//! into OTP fuses that can't be changed afterwards.  [`KeySlots`] mimics
+//! that in RAM.  Each slot has a written flag, and [`KeySlots::provision`]
+//! refuses any slot whose flag is already set.  Once a key is in, nothing
+//! can replace or clear it until the next reset.
+//!
+//! What the slots are for:
+//!
+//! ```text
+//!   slot 0 (SLOT_ATTEST)   device key: quote, session and DRBG keys
+//!                          and the monitor token derive from it
+//!   slot 1..SLOT_COUNT     sealing keys, by seal key_id
+//! ```
+//!
+//! M-mode writes slot 0 first thing at boot, from OTP (a stub in this
+//! build), so the kernel's secrets all come out of the slots rather than
+//! a constant.
+//!
+//! The sealing path never hands out the attestation key, whatever key_id
+//! it is given ([`KeySlots::seal_key`]).  Nor can U-mode write it
+//! ([`KeySlots::provision_user`]): the firmware being attested would get
+//! to choose the key its own quotes are signed with.
+//!
+//! A slot that was never written reads as `None`.
+
+/// Bytes in one key.
+pub const KEY_LEN: usize = 32;
+
+/// Number of slots.
+pub const SLOT_COUNT: usize = 4;
+
+/// Slot holding the attestation key.
+pub const SLOT_ATTEST: usize = 0;
+
+/// Why [`KeySlots::provision`] refused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProvisionError {
+    /// No such slot.
+    BadSlot,
+    /// The slot already holds a key.
+    AlreadyWritten,
+    /// The slot is M-mode's ([`SLOT_ATTEST`]), and the write came from U-mode.
+    Reserved,
+}
+
+/// The key slots and their written flags.
+///
+/// There is deliberately no `Clone` or `Debug`: copying the keys out, or
+/// printing them, has no legitimate use.
+pub struct KeySlots {
+    keys: [[u8; KEY_LEN]; SLOT_COUNT],
+    /// Bit `n` set: slot `n` is written.
+    written: u8,
+}
+
+impl KeySlots {
+    /// All slots blank.
+    pub const fn new() -> Self {
+        KeySlots { keys: [[0; KEY_LEN]; SLOT_COUNT], written: 0 }
+    }
+
+    /// Write `key` into `slot`.  This works once per slot.
+    pub fn provision(&mut self, slot: usize, key: &[u8; KEY_LEN]) -> Result<(), ProvisionError> {
+        if slot >= SLOT_COUNT {
+            return Err(ProvisionError::BadSlot);
+        }
+        if self.is_written(slot) {
+            return Err(ProvisionError::AlreadyWritten);
+        }
+        self.keys[slot] = *key;
+        self.written |= 1 << slot;
+        Ok(())
+    }
+
+    /// [`provision`](KeySlots::provision) on U-mode's behalf: the same,
+    /// except that [`SLOT_ATTEST`] is refused.
+    pub fn provision_user(&mut self, slot: usize, key: &[u8; KEY_LEN]) -> Result<(), ProvisionError> {
+        if slot == SLOT_ATTEST {
+            return Err(ProvisionError::Reserved);
+        }
+        self.provision(slot, key)
+    }
+
+    /// Whether `slot` holds a key.
+    pub const fn is_written(&self, slot: usize) -> bool {
+        slot < SLOT_COUNT && self.written & (1 << slot) != 0
+    }
+
+    /// The key in `slot`, or `None` if it is blank or out of range.
+    pub fn key(&self, slot: usize) -> Option<&[u8; KEY_LEN]> {
+        if self.is_written(slot) {
+            Some(&self.keys[slot])
+        } else {
+            None
+        }
+    }
+
+    /// The sealing key for `key_id`: the key in that slot, except for
+    /// [`SLOT_ATTEST`], which never seals.
+    pub fn seal_key(&self, key_id: usize) -> Option<&[u8; KEY_LEN]> {
+        if key_id == SLOT_ATTEST {
+            None
+        } else {
+            self.key(key_id)
+        }
+    }
+}
+
+impl Default for KeySlots {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots_are_write_once() {
+        let mut slots = KeySlots::new();
+        assert_eq!(slots.key(1), None);
+        assert_eq!(slots.provision(1, &[0xa5; KEY_LEN]), Ok(()));
+        assert_eq!(slots.key(1), Some(&[0xa5; KEY_LEN]));
+        // The second write fails, and the first key stays.
+        assert_eq!(slots.provision(1, &[0x5a; KEY_LEN]), Err(ProvisionError::AlreadyWritten));
+        assert_eq!(slots.key(1), Some(&[0xa5; KEY_LEN]));
+        // Other slots aren't affected.
+        assert!(!slots.is_written(2));
+        assert_eq!(slots.provision(2, &[0; KEY_LEN]), Ok(()));
+        assert_eq!(slots.key(2), Some(&[0; KEY_LEN]));
+    }
+
+    #[test]
+    fn out_of_range_slots() {
+        let mut slots = KeySlots::new();
+        assert_eq!(slots.provision(SLOT_COUNT, &[1; KEY_LEN]), Err(ProvisionError::BadSlot));
+        assert_eq!(slots.provision(usize::MAX, &[1; KEY_LEN]), Err(ProvisionError::BadSlot));
+        assert!(!slots.is_written(SLOT_COUNT));
+        assert_eq!(slots.key(SLOT_COUNT), None);
+    }
+
+    #[test]
+    fn attestation_key_never_seals() {
+        let mut slots = KeySlots::new();
+        slots.provision(SLOT_ATTEST, &[7; KEY_LEN]).unwrap();
+        assert_eq!(slots.key(SLOT_ATTEST), Some(&[7; KEY_LEN]));
+        assert_eq!(slots.seal_key(SLOT_ATTEST), None);
+        slots.provision(3, &[9; KEY_LEN]).unwrap();
+        assert_eq!(slots.seal_key(3), Some(&[9; KEY_LEN]));
+    }
+
+    #[test]
+    fn user_mode_never_writes_the_attestation_key() {
+        let mut slots = KeySlots::new();
+        assert_eq!(slots.provision_user(SLOT_ATTEST, &[7; KEY_LEN]), Err(ProvisionError::Reserved));
+        assert!(!slots.is_written(SLOT_ATTEST));
+        assert_eq!(slots.provision_user(1, &[8; KEY_LEN]), Ok(()));
+        assert_eq!(slots.provision_user(1, &[8; KEY_LEN]), Err(ProvisionError::AlreadyWritten));
+        assert_eq!(slots.provision_user(SLOT_COUNT, &[8; KEY_LEN]), Err(ProvisionError::BadSlot));
+    }
+}
@@ -0,0 +1,102 @@
+//! RISC-V Root of Trust — support library
+//!
+//! Target-independent pieces of the RoT kernel: decoders, crypto and the
+//! host-link framing.  Nothing in here touches CSRs or MMIO, so the
+//! whole library builds for the host as well as for `rv32imac-cfi-none-elf`
+//! so it can be unit-tested off-target.
+//!
+//! The bare-metal kernel (`src/main.rs`) links against this crate and keeps
+//! all of the privileged, `asm!`-heavy code to itself.
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod attest;
+pub mod audit;
+pub mod boot_info;
+pub mod boot_phase;
+pub mod boot_record;
+pub mod cfi;
+pub mod cfi_encoding;
+pub mod cfi_labels;
+pub mod collections;
+pub mod crc32;
+pub mod digest;
+pub mod drbg;
+pub mod elf;
+pub mod erase;
+pub mod firmware;
+pub mod frame;
+pub mod heap;
+pub mod hex;
+pub mod hmac;
+pub mod keyslot;
+pub mod measure;
+pub mod memory_map;
+pub mod misalign;
+pub mod monitor;
+pub mod netload;
+#[cfg(feature = "ecdsa-attest")]
+pub mod p256;
+pub mod pages;
+pub mod perf;
+pub mod pmp;
+pub mod pmp_map;
+pub mod privilege;
+pub mod region;
+pub mod sbi;
+pub mod seal;
+pub mod sha256;
+pub mod ss_emulate;
+pub mod syscall;
+pub mod tlv;
+pub mod trap;
+pub mod wire;
+
+#[cfg(test)]
+mod test_util {
+    /// Decode a hex string literal into a fixed-size array.
+    pub fn hex<const N: usize>(s: &str) -> [u8; N] {
+        assert_eq!(s.len(), 2 * N, "hex literal length");
+        core::array::from_fn(|i| u8::from_str_radix(&s[2 * i..2 * i + 2], 16).unwrap())
+    }
+
+    /// xorshift32: reproducible "random" inputs for the parser fuzz tests.
+    pub struct Rng(pub u32);
+
+    impl Rng {
+        pub fn next_u32(&mut self) -> u32 {
+            let mut x = self.0;
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            self.0 = x;
+            x
+        }
+
+        /// Uniform-ish in `0..n`.
+        pub fn below(&mut self, n: usize) -> usize {
+            self.next_u32() as usize % n
+        }
+
+        pub fn bytes(&mut self, len: usize) -> std::vec::Vec<u8> {
+            (0..len).map(|_| self.next_u32() as u8).collect()
+        }
+
+        /// Corrupt `buf`: overwrite a few bytes, some with a boundary
+        /// value, so mutated headers hit the edge cases.
+        pub fn mutate(&mut self, buf: &mut [u8]) {
+            if buf.is_empty() {
+                return;
+            }
+            for _ in 0..1 + self.below(8) {
+                let i = self.below(buf.len());
+                buf[i] = match self.below(4) {
+                    0 => 0,
+                    1 => 0xff,
+                    2 => buf[i] ^ (1 << self.below(8)),
+                    _ => self.next_u32() as u8,
+                };
+            }
+        }
+    }
+}
@@ -24,16 +24,88 @@
 //!   0x8001_9000 .. 0x8001_9FFF  M_SW_SHADOW (4K)   M-mode SW shadow stack
 //!   0x8002_0000 .. 0x8003_FFFF  U_CODE      (128K) U-mode code (RX)
 //!   0x8004_0000 .. 0x8004_7FFF  U_RODATA    (32K)  U-mode rodata (R)
-//!   0x8004_8000 .. 0x8005_7FFF  U_RAM       (64K)  U-mode data + stack (RW)
+//!   0x8004_8000 .. 0x8005_6FFF  U_RAM       (60K)  U-mode data + stack (RW)
+//!   0x8005_7000 .. 0x8005_7FFF  U_GUARD     (4K)   guard page (no U access)
 //!   0x8005_8000 .. 0x8005_8FFF  U_SHADOW    (4K)   U-mode HW shadow stack
 //!   0x8005_9000 .. 0x8005_9FFF  U_SW_SHADOW (4K)   U-mode SW shadow stack
 //!   0x1000_0000 .. 0x1000_0FFF  UART MMIO   (4K)   16550 UART
+//!   0x0200_0000 .. 0x0200_FFFF  CLINT       (64K)  machine timer (M only)
+//!   0x0C00_0000 .. 0x0FFF_FFFF  PLIC        (64M)  interrupt controller (M only)
 
 #![no_std]
 #![no_main]
+// cfi_target_asm! recurses once per template string; the regsave test
+// body has more than the default 128.
+#![recursion_limit = "512"]
 
-use core::arch::{asm, naked_asm};
+use core::arch::{asm, global_asm, naked_asm};
+use riscv_cfi_macros::cfi_target;
+use core::mem::offset_of;
+use core::fmt::{self, Write};
 use core::panic::PanicInfo;
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+#[cfg(any(feature = "breakpoint-test", feature = "ss-emulate", feature = "wx-selftest"))]
+use core::sync::atomic::AtomicUsize;
+
+#[cfg(any(feature = "secure-session", feature = "net-load"))]
+use riscv_rot_cfi::frame::{ByteIo, Role, Session};
+#[cfg(feature = "net-load")]
+use riscv_rot_cfi::{
+    elf::{self, PF_R, PF_W, PF_X},
+    frame::MAX_PAYLOAD,
+    netload::{LoadError, LoadRequest, Loader},
+};
+use riscv_rot_cfi::pmp::{with_cfg, AppRegions, PmpCfg, PmpConfig, PmpEntry, PMP_ENTRY_COUNT};
+#[cfg(any(feature = "app-isolation-test", feature = "sched-demo"))]
+use riscv_rot_cfi::pmp::{Napot, PMP_R, PMP_W};
+use riscv_rot_cfi::pmp_map::{compute_pmp_config, APP_MAIN, APP_PMP_ENTRIES};
+use riscv_rot_cfi::digest::Digest;
+use riscv_rot_cfi::measure::{xor_words, MeasurementLog, PCR_COUNT, PCR_FIRMWARE, PCR_ROM, PCR_RUNTIME};
+use riscv_rot_cfi::heap::Break;
+use riscv_rot_cfi::hex::{HexBytes, HexLayout};
+use riscv_rot_cfi::hmac::{hkdf_sha256, hmac_sha256};
+use riscv_rot_cfi::keyslot::{self, KeySlots, KEY_LEN};
+use riscv_rot_cfi::monitor;
+use riscv_rot_cfi::syscall::{self, Syscall};
+use riscv_rot_cfi::sha256::{sha256, DIGEST_LEN};
+use riscv_rot_cfi::attest::{self, NONCE_LEN};
+#[cfg(feature = "ecall-audit")]
+use riscv_rot_cfi::audit::{AuditLog, EcallRecord};
+use riscv_rot_cfi::boot_info::{self, BootInfo, BootRegion, EntryArgs};
+use riscv_rot_cfi::cfi_encoding;
+use riscv_rot_cfi::cfi_labels;
+use riscv_rot_cfi::collections::{FixedVec, RingBuffer};
+use riscv_rot_cfi::cfi::{CfiStatus, ENVCFG_LPE, ENVCFG_SSE};
+use riscv_rot_cfi::trap::TrapCause;
+#[cfg(feature = "cfi-contain")]
+use riscv_rot_cfi::trap::{self, Containment};
+use riscv_rot_cfi::privilege::{self, PrivMode};
+use riscv_rot_cfi::sbi::{self, SbiCall, STimer, STimerPath};
+use riscv_rot_cfi::firmware;
+use riscv_rot_cfi::perf::{self, Sample};
+use riscv_rot_cfi::drbg::{self, HmacDrbg, SeedPool};
+use riscv_rot_cfi::erase::secure_zero;
+#[cfg(feature = "secure-session")]
+use riscv_rot_cfi::wire::write_u32_be;
+use riscv_rot_cfi::boot_record::{BootMode, BootRecord, MAX_FAILED_BOOTS};
+use riscv_rot_cfi::boot_phase::{BootFlow, BootPhase};
+#[cfg(feature = "misalign-fixup")]
+use riscv_rot_cfi::misalign::{self, Access};
+#[cfg(feature = "ss-emulate")]
+use riscv_rot_cfi::{
+    misalign::insn_len,
+    region::Region,
+    ss_emulate::{self, CsrSrc, ShadowStack, SsFault, SsInsn},
+};
+#[cfg(feature = "page-measure")]
+use riscv_rot_cfi::pages;
+#[cfg(any(feature = "page-measure", feature = "measure-rodata"))]
+use riscv_rot_cfi::sha256::Sha256;
+#[cfg(feature = "measure-rodata")]
+use riscv_rot_cfi::measure::{digest_with_hole, PCR_RODATA};
+#[cfg(feature = "policy-seal")]
+use riscv_rot_cfi::seal::{self, SealError};
 
 // ============================================================================
 // CFI Instruction Encodings (Zicfilp + Zicfiss)
@@ -44,37 +116,526 @@ use core::panic::PanicInfo;
 //
 //   lpad 0       = 0x0000_0017   (AUIPC x0, 0)
 //   lpad N       = (N << 12) | 0x17
-//   sspush ra    = 0x6010_0073
-//   sspopchk ra  = 0x6050_0073
+//                  (labels are allocated in riscv_rot_cfi::cfi_labels)
+//   sspush ra    = 0xce10_4073   (MOP.RR.7)
+//   sspopchk ra  = 0xcdc0_c073   (MOP.R.28)
+//
+// Asm sites take the words as `const` operands, from cfi_labels::lpad
+// (via cfi_target_asm!) and riscv_rot_cfi::cfi_encoding, where they are
+// built from the instruction fields and unit-tested.
+
+/// Register number of the software shadow-stack pointer: x3 (`gp`), or
+/// x27 (`s11`) in `sw-ss-s11` builds.
+///
+/// Every asm site names it as `x{ss}` with `ss = const SW_SS_REG`, so the
+/// choice is made here once.  `gp` is never allocated by LLVM but rules
+/// out GP-relative relaxation (hence `--no-relax`).  `s11` is a normal
+/// callee-saved register, so it has to be reserved from the compiler: the
+/// `rv32imac-cfi-s11-none-elf` target spec adds `+reserve-x27` (build.rs
+/// insists on it), and relaxation is back on.
+const SW_SS_REG: u32 = if cfg!(feature = "sw-ss-s11") { 27 } else { 3 };
+
+/// `naked_asm!` for an indirect-call target: emits `lpad LABEL` as the
+/// function's first instruction, then the given template and operands.
+/// A target whose body can be ordinary Rust uses the [`cfi_target`]
+/// attribute instead, which expands to this macro.
+///
+/// A target whose body is written with this macro cannot lose its
+/// landing pad — forgetting it would otherwise only show up as a
+/// landing-pad fault the first time the function is called through a
+/// pointer on Zicfilp hardware.  Each template string must be followed
+/// by a comma (the usual style here); operands go after the templates as
+/// in `naked_asm!`.
+///
+/// Each landing pad is also listed in `.u_lpad_sites`, one word per pad,
+/// which link.x turns into the count the firmware header declares
+/// (`_u_lpad_count`, see [`riscv_rot_cfi::firmware`]).  Only pads in U_CODE
+/// belong in that count: a target placed anywhere else (M-mode code, the
+/// quarantine recovery entry) starts with `@outside_u_code`.
+///
+/// ```ignore
+/// pub unsafe extern "C" fn u_add_100(x: u32) -> u32 {
+///     cfi_target_asm!(cfi_labels::DISPATCH;
+///         "addi   a0, a0, 100",
+///         "ret",
+///     )
+/// }
+/// ```
+macro_rules! cfi_target_asm {
+    (@outside_u_code $label:expr; $($body:tt)*) => {
+        cfi_target_asm!(@templates 0, $label; [] $($body)*)
+    };
+    ($label:expr; $($body:tt)*) => {
+        cfi_target_asm!(@templates 1, $label; [] $($body)*)
+    };
+    // Split the template strings from the operands so the lpad operand
+    // can go between them.
+    (@templates $listed:literal, $label:expr; [$($t:literal,)*] $next:literal, $($rest:tt)*) => {
+        cfi_target_asm!(@templates $listed, $label; [$($t,)* $next,] $($rest)*)
+    };
+    (@templates $listed:literal, $label:expr; [$($t:literal,)*] $($operands:tt)*) => {
+        naked_asm!(
+            "7770:",
+            ".4byte {__cfi_lpad}",
+            ".if {__cfi_listed}",
+            ".pushsection .u_lpad_sites, \"a\"",
+            ".4byte 7770b",
+            ".popsection",
+            ".endif",
+            $($t,)*
+            __cfi_lpad = const cfi_labels::lpad($label),
+            __cfi_listed = const $listed,
+            $($operands)*
+        )
+    };
+}
 
 // ============================================================================
-// PMP Constants
+// CSR Access
 // ============================================================================
 
-/// PMP address mode: NAPOT (Naturally Aligned Power-Of-Two)
-const PMP_NAPOT: u32 = 0x18; // A field = 0b11
+/// Control and status registers: the addresses the kernel touches, by
+/// name, and one accessor per CSR instruction.
+///
+/// Rust code reaches CSRs only through here, so every access names its
+/// register: `csr::write::<{ csr::MTVEC }>(base)`, not `csrw 0x305`.
+/// The address is a const generic because CSR instructions encode it as
+/// a 12-bit immediate; a value that isn't known at compile time can't be
+/// passed.  Naked trap/boot code uses the same names through `const`
+/// operands where the assembler has no mnemonic (e.g. `ssp`).
+///
+/// A CSR the core doesn't implement raises illegal-instruction; the trap
+/// handler skips the instruction, and every read zeroes its destination
+/// first, so a missing CSR reads as 0 and writes to it are dropped.
+#[allow(dead_code)] // a registry: not every CSR is accessed from Rust
+mod csr {
+    use core::arch::asm;
+
+    // ── Unprivileged ────────────────────────────────────────────────
+    /// FP control and status (F extension).
+    pub const FCSR: u16 = 0x003;
+    /// Shadow-stack pointer (Zicfiss).
+    pub const SSP: u16 = 0x011;
+
+    // ── Supervisor ──────────────────────────────────────────────────
+    /// Supervisor environment configuration (LPE/SSE for U-mode under S).
+    pub const SENVCFG: u16 = 0x10A;
+    /// Supervisor timer compare (Sstc), low and high 32 bits (RV32).
+    pub const STIMECMP: u16 = 0x14D;
+    pub const STIMECMPH: u16 = 0x15D;
+
+    // ── Machine information ─────────────────────────────────────────
+    /// This hart's ID.
+    pub const MHARTID: u16 = 0xF14;
+
+    // ── Machine trap setup ──────────────────────────────────────────
+    pub const MSTATUS: u16 = 0x300;
+    /// Upper half of mstatus (RV32): MPELP, the interrupted code's
+    /// landing-pad state (Zicfilp).
+    pub const MSTATUSH: u16 = 0x310;
+    /// ISA and extensions.
+    pub const MISA: u16 = 0x301;
+    /// Interrupt enables.
+    pub const MIE: u16 = 0x304;
+    /// Trap vector base address and mode.
+    pub const MTVEC: u16 = 0x305;
+    /// Counters U-mode may read (CY, TM, IR bits).
+    pub const MCOUNTEREN: u16 = 0x306;
+    /// Machine environment configuration (LPE/SSE for U-mode).
+    pub const MENVCFG: u16 = 0x30A;
+    /// Upper half of menvcfg (RV32): STCE, which enables Sstc.
+    pub const MENVCFGH: u16 = 0x31A;
+
+    // ── Machine trap handling ───────────────────────────────────────
+    pub const MSCRATCH: u16 = 0x340;
+    /// PC of the trapping (or interrupted) instruction.
+    pub const MEPC: u16 = 0x341;
+    pub const MCAUSE: u16 = 0x342;
+    /// Faulting address or instruction bits.
+    pub const MTVAL: u16 = 0x343;
+    /// Pending interrupts.
+    pub const MIP: u16 = 0x344;
+
+    // ── Physical memory protection ──────────────────────────────────
+    // Four config bytes per pmpcfg register (RV32), see the `pmp` module.
+    pub const PMPCFG0: u16 = 0x3A0;
+    pub const PMPCFG1: u16 = 0x3A1;
+    pub const PMPCFG2: u16 = 0x3A2;
+    pub const PMPCFG3: u16 = 0x3A3;
+    pub const PMPADDR0: u16 = 0x3B0;
+    pub const PMPADDR1: u16 = 0x3B1;
+    pub const PMPADDR2: u16 = 0x3B2;
+    pub const PMPADDR3: u16 = 0x3B3;
+    pub const PMPADDR4: u16 = 0x3B4;
+    pub const PMPADDR5: u16 = 0x3B5;
+    pub const PMPADDR6: u16 = 0x3B6;
+    pub const PMPADDR7: u16 = 0x3B7;
+    pub const PMPADDR8: u16 = 0x3B8;
+    pub const PMPADDR9: u16 = 0x3B9;
+    pub const PMPADDR10: u16 = 0x3BA;
+    pub const PMPADDR11: u16 = 0x3BB;
+    pub const PMPADDR12: u16 = 0x3BC;
+    pub const PMPADDR13: u16 = 0x3BD;
+    pub const PMPADDR14: u16 = 0x3BE;
+    pub const PMPADDR15: u16 = 0x3BF;
+
+    // ── Machine counters ────────────────────────────────────────────
+    /// Cycle counter, low 32 bits.
+    pub const MCYCLE: u16 = 0xB00;
+    /// Instructions retired, low 32 bits.
+    pub const MINSTRET: u16 = 0xB02;
+    /// Cycle counter, high 32 bits (RV32).
+    pub const MCYCLEH: u16 = 0xB80;
+    /// Instructions retired, high 32 bits (RV32).
+    pub const MINSTRETH: u16 = 0xB82;
+
+    /// `csrr`: the CSR's value.
+    #[inline(always)]
+    pub fn read<const ADDR: u16>() -> usize {
+        let value: usize;
+        // SAFETY: reading a CSR has no side effects on any CSR used here.
+        unsafe {
+            asm!(
+                "li    {v}, 0",
+                "csrr  {v}, {csr}",
+                csr = const ADDR,
+                v = out(reg) value,
+            );
+        }
+        value
+    }
+
+    /// `csrw`.
+    ///
+    /// # Safety
+    ///
+    /// CSRs hold the protection, trap-routing and interrupt state the
+    /// rest of the kernel relies on; the caller must keep it consistent.
+    #[inline(always)]
+    pub unsafe fn write<const ADDR: u16>(value: usize) {
+        asm!("csrw  {csr}, {v}", csr = const ADDR, v = in(reg) value);
+    }
+
+    /// `csrs`: set `bits`.
+    ///
+    /// # Safety
+    ///
+    /// As for [`write`].
+    #[inline(always)]
+    pub unsafe fn set<const ADDR: u16>(bits: usize) {
+        asm!("csrs  {csr}, {b}", csr = const ADDR, b = in(reg) bits);
+    }
+
+    /// `csrc`: clear `bits`.
+    ///
+    /// # Safety
+    ///
+    /// As for [`write`].
+    #[inline(always)]
+    pub unsafe fn clear<const ADDR: u16>(bits: usize) {
+        asm!("csrc  {csr}, {b}", csr = const ADDR, b = in(reg) bits);
+    }
+
+    /// `csrrc`: clear `bits` and return the previous value, in one step.
+    ///
+    /// # Safety
+    ///
+    /// As for [`write`].
+    #[inline(always)]
+    pub unsafe fn read_clear<const ADDR: u16>(bits: usize) -> usize {
+        let old: usize;
+        asm!("csrrc {old}, {csr}, {b}", csr = const ADDR, b = in(reg) bits, old = out(reg) old);
+        old
+    }
+
+    /// `csrs`, then read the CSR back: which of `bits` stuck.  WARL
+    /// fields drop bits the core doesn't support, and a missing CSR
+    /// reads back 0, so every bit reads as "didn't stick".
+    ///
+    /// # Safety
+    ///
+    /// As for [`write`].
+    pub unsafe fn set_readback<const ADDR: u16>(bits: usize) -> usize {
+        set::<ADDR>(bits);
+        read::<ADDR>()
+    }
+
+    /// `csrw`, then read the CSR back (0 if the CSR is missing).
+    ///
+    /// # Safety
+    ///
+    /// As for [`write`].
+    pub unsafe fn write_readback<const ADDR: u16>(value: usize) -> usize {
+        write::<ADDR>(value);
+        read::<ADDR>()
+    }
+}
 
-/// PMP permission bits
-const PMP_R: u32 = 0x01;
-const PMP_W: u32 = 0x02;
-const PMP_X: u32 = 0x04;
+// ============================================================================
+// PMP Constants
+// ============================================================================
 
-/// PMP lock bit — locks entry and makes it apply to M-mode too
-const PMP_L: u32 = 0x80;
+// Config-byte bits (PMP_R/W/X/L, address modes) live in the library's
+// `pmp` module alongside the decoder used by the boot-time checks.
 
 // ============================================================================
-// UART (QEMU virt machine: 16550 at 0x1000_0000)
+// Serial Console
 // ============================================================================
 
-const UART_BASE: *mut u8 = 0x1000_0000 as *mut u8;
+/// Boot-console UART drivers.
+///
+/// A driver is [`init`], [`putc`] and [`try_getc`] over a register block
+/// at a fixed base, plus the two hooks the buffered console needs
+/// ([`tx_ready`], [`set_tx_interrupt`]).  The console is a `static` of
+/// one concrete type chosen at build time (see [`Console`]); nothing
+/// needs a vtable.
+///
+/// [`init`]: SerialDevice::init
+/// [`putc`]: SerialDevice::putc
+/// [`try_getc`]: SerialDevice::try_getc
+/// [`tx_ready`]: SerialDevice::tx_ready
+/// [`set_tx_interrupt`]: SerialDevice::set_tx_interrupt
+#[allow(dead_code)] // only one driver is selected per build
+mod serial {
+    /// A polled UART.
+    pub trait SerialDevice {
+        /// Put the device into a known state (8N1, FIFOs / TX+RX enabled,
+        /// interrupts off).  The baud rate is left as the platform set it.
+        fn init(&self);
+        /// Blocking write of one byte.
+        fn putc(&self, c: u8);
+        /// Read one byte if the receiver has one.
+        fn try_getc(&self) -> Option<u8>;
+        /// Blocking read of one byte.
+        fn getc(&self) -> u8 {
+            loop {
+                if let Some(c) = self.try_getc() {
+                    return c;
+                }
+            }
+        }
+        /// The transmitter can take a byte without [`putc`](Self::putc)
+        /// spinning.
+        fn tx_ready(&self) -> bool;
+        /// Raise the device's interrupt line while the transmitter can
+        /// take a byte (16550 THR-empty, SiFive TX watermark).
+        fn set_tx_interrupt(&self, enabled: bool);
+        /// Raise the device's interrupt line while a received byte is
+        /// waiting (16550 RX-data-available, SiFive RX watermark).
+        fn set_rx_interrupt(&self, enabled: bool);
+    }
+
+    /// NS16550-compatible UART (QEMU `virt`), byte-wide registers.
+    pub struct Ns16550 {
+        base: usize,
+    }
+
+    impl Ns16550 {
+        const RBR_THR: usize = 0; // receive buffer / transmit holding
+        const IER: usize = 1;
+        const DLL: usize = 0; // divisor latch low / high, while LCR.DLAB = 1
+        const DLM: usize = 1;
+        const FCR: usize = 2;
+        const LCR: usize = 3;
+        const LSR: usize = 5;
+        const LCR_8N1: u8 = 0x03;
+        const LCR_DLAB: u8 = 0x80;
+        // 115200 baud from QEMU virt's 3.6864 MHz UART clock (16x
+        // oversampling).
+        const DIVISOR: u16 = (3_686_400 / (16 * 115_200)) as u16;
+        // Between LCR and divisor-latch writes, in mcycle counts.  The
+        // registers sit in the UART's own, slower clock domain on many
+        // real 16550 cores (the DesignWare APB UART, for one), and a write
+        // that follows too closely is lost; QEMU doesn't care.
+        const LATCH_DELAY: u64 = 100;
+        const FCR_ENABLE_CLEAR: u8 = 0x07; // enable, clear RX + TX FIFOs
+        const LSR_DR: u8 = 0x01; // data ready
+        const LSR_THRE: u8 = 0x20; // THR empty
+        const IER_ERBFI: u8 = 0x01; // RX-data-available interrupt
+        const IER_ETBEI: u8 = 0x02; // THR-empty interrupt
+
+        pub const fn new(base: usize) -> Self {
+            Ns16550 { base }
+        }
+
+        fn reg(&self, off: usize) -> *mut u8 {
+            (self.base + off) as *mut u8
+        }
+    }
+
+    impl SerialDevice for Ns16550 {
+        fn init(&self) {
+            // SAFETY: `base` is the device's MMIO block (set at construction).
+            unsafe {
+                self.reg(Self::IER).write_volatile(0);
+                self.reg(Self::LCR).write_volatile(Self::LCR_DLAB);
+                super::delay_cycles(Self::LATCH_DELAY);
+                self.reg(Self::DLL).write_volatile(Self::DIVISOR as u8);
+                super::delay_cycles(Self::LATCH_DELAY);
+                self.reg(Self::DLM).write_volatile((Self::DIVISOR >> 8) as u8);
+                super::delay_cycles(Self::LATCH_DELAY);
+                // Also clears DLAB: offsets 0 and 1 are RBR/THR and IER again.
+                self.reg(Self::LCR).write_volatile(Self::LCR_8N1);
+                self.reg(Self::FCR).write_volatile(Self::FCR_ENABLE_CLEAR);
+            }
+        }
+
+        fn putc(&self, c: u8) {
+            unsafe {
+                while self.reg(Self::LSR).read_volatile() & Self::LSR_THRE == 0 {}
+                self.reg(Self::RBR_THR).write_volatile(c);
+            }
+        }
+
+        fn try_getc(&self) -> Option<u8> {
+            unsafe {
+                (self.reg(Self::LSR).read_volatile() & Self::LSR_DR != 0)
+                    .then(|| self.reg(Self::RBR_THR).read_volatile())
+            }
+        }
+
+        fn tx_ready(&self) -> bool {
+            unsafe { self.reg(Self::LSR).read_volatile() & Self::LSR_THRE != 0 }
+        }
+
+        fn set_tx_interrupt(&self, enabled: bool) {
+            self.set_ier(Self::IER_ETBEI, enabled);
+        }
+
+        fn set_rx_interrupt(&self, enabled: bool) {
+            self.set_ier(Self::IER_ERBFI, enabled);
+        }
+    }
+
+    impl Ns16550 {
+        fn set_ier(&self, bit: u8, enabled: bool) {
+            unsafe {
+                let ier = self.reg(Self::IER).read_volatile();
+                let ier = if enabled { ier | bit } else { ier & !bit };
+                self.reg(Self::IER).write_volatile(ier);
+            }
+        }
+    }
+
+    /// SiFive UART (FU540/FE310, QEMU `sifive_u`/`sifive_e`), word-wide
+    /// registers.
+    pub struct SifiveUart {
+        base: usize,
+    }
+
+    impl SifiveUart {
+        const TXDATA: usize = 0x00; // bit 31: FIFO full
+        const RXDATA: usize = 0x04; // bit 31: FIFO empty
+        const TXCTRL: usize = 0x08; // bit 0: txen, bits 18:16: txcnt
+        const RXCTRL: usize = 0x0C; // bit 0: rxen
+        const IE: usize = 0x10; // bit 0: txwm, bit 1: rxwm
+        const FULL_EMPTY: u32 = 1 << 31;
+        // TX watermark pending while the FIFO holds fewer than 1 entry,
+        // i.e. when it is empty — the same condition as 16550 THRE.
+        const TXCTRL_EN_TXCNT1: u32 = 1 | (1 << 16);
+        const IE_TXWM: u32 = 1 << 0;
+        // RX watermark pending while the FIFO holds more than rxcnt = 0
+        // entries; RXCTRL is initialised with rxcnt = 0.
+        const IE_RXWM: u32 = 1 << 1;
+
+        pub const fn new(base: usize) -> Self {
+            SifiveUart { base }
+        }
+
+        fn reg(&self, off: usize) -> *mut u32 {
+            (self.base + off) as *mut u32
+        }
+    }
+
+    impl SerialDevice for SifiveUart {
+        fn init(&self) {
+            // SAFETY: `base` is the device's MMIO block (set at construction).
+            unsafe {
+                self.reg(Self::IE).write_volatile(0);
+                self.reg(Self::TXCTRL).write_volatile(Self::TXCTRL_EN_TXCNT1);
+                self.reg(Self::RXCTRL).write_volatile(1);
+            }
+        }
+
+        fn putc(&self, c: u8) {
+            unsafe {
+                while self.reg(Self::TXDATA).read_volatile() & Self::FULL_EMPTY != 0 {}
+                self.reg(Self::TXDATA).write_volatile(c as u32);
+            }
+        }
+
+        fn try_getc(&self) -> Option<u8> {
+            // One read both tests and pops the FIFO.
+            let rx = unsafe { self.reg(Self::RXDATA).read_volatile() };
+            (rx & Self::FULL_EMPTY == 0).then_some(rx as u8)
+        }
+
+        fn tx_ready(&self) -> bool {
+            unsafe { self.reg(Self::TXDATA).read_volatile() & Self::FULL_EMPTY == 0 }
+        }
+
+        fn set_tx_interrupt(&self, enabled: bool) {
+            self.set_ie(Self::IE_TXWM, enabled);
+        }
+
+        fn set_rx_interrupt(&self, enabled: bool) {
+            self.set_ie(Self::IE_RXWM, enabled);
+        }
+    }
+
+    impl SifiveUart {
+        fn set_ie(&self, bit: u32, enabled: bool) {
+            unsafe {
+                let ie = self.reg(Self::IE).read_volatile();
+                let ie = if enabled { ie | bit } else { ie & !bit };
+                self.reg(Self::IE).write_volatile(ie);
+            }
+        }
+    }
+}
+
+use serial::SerialDevice;
+
+/// The boot console's driver: `sifive-uart` selects the SiFive UART,
+/// otherwise the 16550 on QEMU `virt`.
+#[cfg(not(feature = "sifive-uart"))]
+type Console = serial::Ns16550;
+#[cfg(feature = "sifive-uart")]
+type Console = serial::SifiveUart;
 
+/// Console MMIO base.
+#[cfg(not(feature = "sifive-uart"))]
+const CONSOLE_BASE: usize = 0x1000_0000;
+#[cfg(feature = "sifive-uart")]
+const CONSOLE_BASE: usize = 0x1001_0000;
+
+/// Console interrupt source on the PLIC.
+#[cfg(not(feature = "sifive-uart"))]
+const CONSOLE_IRQ: u32 = 10;
+#[cfg(feature = "sifive-uart")]
+const CONSOLE_IRQ: u32 = 4;
+
+static CONSOLE: Console = Console::new(CONSOLE_BASE);
+
+/// Blocking write of one byte.  Anything still queued by
+/// [`uart_write_buffered`] goes out first, so output stays in order.
 fn uart_putc(c: u8) {
-    unsafe { UART_BASE.write_volatile(c) }
+    // If the ring is held further up the stack (panic or fault mid-print)
+    // this skips the drain and writes straight through.
+    uart_flush();
+    CONSOLE.putc(c);
 }
 
+/// Write a string: queued for the UART interrupt while interrupts are on,
+/// blocking otherwise (before [`enable_console_irq`], and in trap context,
+/// where the ISR can't run until mret).
 fn uart_puts(s: &str) {
-    for b in s.bytes() {
-        uart_putc(b);
+    if tx_irq_active() {
+        uart_write_buffered(s.as_bytes());
+    } else {
+        for b in s.bytes() {
+            uart_putc(b);
+        }
     }
 }
 
@@ -86,612 +647,8629 @@ fn uart_put_hex32(val: u32) {
     }
 }
 
+/// Print `bytes` as lowercase hex, grouped and wrapped per `layout`.
+fn uart_put_hex_bytes(bytes: &[u8], layout: HexLayout) {
+    let _ = write!(UartWriter, "{}", HexBytes::new(bytes, layout));
+}
+
 fn uart_newline() {
     uart_puts("\r\n");
 }
 
+/// Blocking read of one byte from the console.
+#[cfg(any(feature = "secure-session", feature = "net-load"))]
+fn uart_getc() -> u8 {
+    CONSOLE.getc()
+}
+
+/// `core::fmt` sink for the boot console, so `write!` can format values
+/// (e.g. a decoded [`TrapCause`]) straight onto the UART.
+struct UartWriter;
+
+impl fmt::Write for UartWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        uart_puts(s);
+        Ok(())
+    }
+}
+
 // ============================================================================
-// PMP Configuration
+// Buffered Console TX (interrupt-driven)
 // ============================================================================
+//
+// Thread code queues bytes in TX_RING (M_RAM) and enables the UART's
+// TX-ready interrupt; the external-interrupt arm of _trap_handler drains
+// the ring into the FIFO and disables the interrupt again once it is
+// empty.  Boot logging no longer waits on the UART for every byte.
+//
+// Single hart, so the only concurrency is thread code vs. the ISR, and a
+// critical section is just mstatus.MIE cleared (see critical_section).
 
-/// Calculate the NAPOT address encoding for a region.
+/// mstatus.MIE (bit 3): M-mode interrupts enabled.
+const MSTATUS_MIE: usize = 1 << 3;
+/// mie.MSIE (bit 3): machine software interrupt enabled.
+const MIE_MSIE: usize = 1 << 3;
+/// mie.MEIE (bit 11): machine external interrupt enabled.
+const MIE_MEIE: usize = 1 << 11;
+/// mie.MTIE (bit 7): machine timer interrupt enabled.
+const MIE_MTIE: usize = 1 << 7;
+
+/// An M-mode interrupt source, as gated by its enable bit in mie.
 ///
-/// For NAPOT mode, pmpaddr = (base >> 2) | ((size >> 3) - 1)
-/// where `size` must be a power-of-2 and `base` must be size-aligned.
-const fn pmp_napot_addr(base: u32, size: u32) -> u32 {
-    // pmpaddr = (base + (size/2 - 1)) >> 2
-    //         = (base >> 2) | ((size >> 3) - 1)   for naturally-aligned regions
-    (base >> 2) | ((size >> 3).wrapping_sub(1))
+/// An interrupt is taken in M-mode only while both its mie bit and
+/// mstatus.MIE are set; U-mode takes them whatever mstatus.MIE says.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum IrqSource {
+    /// MSIE: the CLINT msip doorbell.
+    #[allow(dead_code)] // nothing raises msip on one hart yet
+    Software,
+    /// MTIE: CLINT mtime reaching mtimecmp.
+    #[allow(dead_code)] // only the nested-trap test and sched-demo take timer interrupts
+    Timer,
+    /// MEIE: the PLIC.
+    External,
 }
 
-/// Configure all PMP entries to establish memory isolation.
-///
-/// RISC-V PMP rules (RV32, 16 entries available):
-///   - Entries are checked in priority order (0 = highest)
-///   - First matching entry wins
-///   - M-mode bypasses PMP unless the entry is Locked (L bit)
-///   - With no matching entry: M-mode = full access, U-mode = no access
-///
-/// Our strategy:
-///   - Lock M-mode code as RX (prevents runtime code injection into RoT)
-///   - Leave M-mode data/shadow stacks unlocked (M-mode needs RW, U-mode
-///     gets no access by default since no PMP entry grants it)
-///   - Grant U-mode specific permissions via unlocked entries
-///   - Deny-all catch-all entry last (locked, no permissions)
-fn configure_pmp() {
-    uart_puts("[PMP] Configuring Physical Memory Protection...\r\n");
+impl IrqSource {
+    const fn mie_bit(self) -> usize {
+        match self {
+            IrqSource::Software => MIE_MSIE,
+            IrqSource::Timer => MIE_MTIE,
+            IrqSource::External => MIE_MEIE,
+        }
+    }
+}
 
-    // ── Entry 0: M-mode code (ROM) — Locked RX ──────────────────────
-    // Lock prevents M-mode from writing its own code at runtime.
-    // 64K at 0x8000_0000
-    let pmp0_addr = pmp_napot_addr(0x8000_0000, 64 * 1024);
-    let pmp0_cfg = PMP_L | PMP_NAPOT | PMP_R | PMP_X; // Locked R+X
-
-    // ── Entry 1: M-mode data (M_RAM) — NOT locked ───────────────────
-    // M-mode can RW.  U-mode has no access (no PMP entry grants it).
-    // 32K at 0x8001_0000
-    let pmp1_addr = pmp_napot_addr(0x8001_0000, 32 * 1024);
-    let pmp1_cfg: u32 = 0; // No permissions = deny for U-mode.
-    // M-mode bypasses PMP (unlocked entry), so M-mode still has full access.
-
-    // ── Entry 2: M-mode shadow stacks — NOT locked ──────────────────
-    // Covers both M_SHADOW (4K) + M_SW_SHADOW (4K) = 8K at 0x8001_8000
-    let pmp2_addr = pmp_napot_addr(0x8001_8000, 8 * 1024);
-    let pmp2_cfg: u32 = 0; // Deny U-mode
-
-    // ── Entry 3: U-mode code — RX for U-mode ────────────────────────
-    // 128K at 0x8002_0000
-    let pmp3_addr = pmp_napot_addr(0x8002_0000, 128 * 1024);
-    let pmp3_cfg = PMP_NAPOT | PMP_R | PMP_X; // U-mode: R+X (W^X enforced)
-
-    // ── Entry 4: U-mode rodata — R for U-mode ───────────────────────
-    // 32K at 0x8004_0000
-    let pmp4_addr = pmp_napot_addr(0x8004_0000, 32 * 1024);
-    let pmp4_cfg = PMP_NAPOT | PMP_R; // U-mode: R only
-
-    // ── Entry 5: U-mode data/stack — RW for U-mode ──────────────────
-    // 64K at 0x8004_8000
-    let pmp5_addr = pmp_napot_addr(0x8004_8000, 64 * 1024);
-    let pmp5_cfg = PMP_NAPOT | PMP_R | PMP_W; // U-mode: R+W (no X = W^X)
-
-    // ── Entry 6: U-mode shadow stacks — RW for U-mode ──────────────
-    // Covers U_SHADOW (4K) + U_SW_SHADOW (4K) = 8K at 0x8005_8000
-    // On real Zicfiss hardware, the HW shadow stack pages would have the
-    // SS PTE attribute so only sspush/sspop can write them.  With PMP-only
-    // (no MMU), we grant RW and rely on spatial isolation + CFI enforcement.
-    let pmp6_addr = pmp_napot_addr(0x8005_8000, 8 * 1024);
-    let pmp6_cfg = PMP_NAPOT | PMP_R | PMP_W;
-
-    // ── Entry 7: UART MMIO — RW for U-mode ─────────────────────────
-    // 4K at 0x1000_0000 — allows U-mode to write to UART directly.
-    // In a stricter RoT, UART access would be M-mode only via ecall.
-    let pmp7_addr = pmp_napot_addr(0x1000_0000, 4 * 1024);
-    let pmp7_cfg = PMP_NAPOT | PMP_R | PMP_W;
-
-    // ── Entries 8-14: Reserved (unused, deny-all) ───────────────────
-    // Left as zero — no access.
-
-    // ── Entry 15: Deny-all catch-all — Locked, no permissions ───────
-    // Matches any address not covered above.  Prevents U-mode from
-    // accessing unmapped memory.  Locked so even M-mode can't bypass.
-    // Use TOR (Top Of Range) mode with addr = 0xFFFF_FFFF to cover
-    // the entire address space above entry 14.
-    // NOTE: The catch-all must be LAST (lowest priority).
-
-    // Write PMP address registers (CSRs 0x3B0 – 0x3BF)
-    unsafe {
-        asm!(
-            // pmpaddr0..7
-            "csrw  0x3B0, {a0}",
-            "csrw  0x3B1, {a1}",
-            "csrw  0x3B2, {a2}",
-            "csrw  0x3B3, {a3}",
-            "csrw  0x3B4, {a4}",
-            "csrw  0x3B5, {a5}",
-            "csrw  0x3B6, {a6}",
-            "csrw  0x3B7, {a7}",
-            a0 = in(reg) pmp0_addr,
-            a1 = in(reg) pmp1_addr,
-            a2 = in(reg) pmp2_addr,
-            a3 = in(reg) pmp3_addr,
-            a4 = in(reg) pmp4_addr,
-            a5 = in(reg) pmp5_addr,
-            a6 = in(reg) pmp6_addr,
-            a7 = in(reg) pmp7_addr,
-        );
+/// Let `source` interrupt (once mstatus.MIE is also set).  The timer and
+/// external interrupts have handlers; a software interrupt would still
+/// end up in the fatal-trap report.
+fn enable_irq_source(source: IrqSource) {
+    // SAFETY: an interrupt only ever runs a handler on the trap path.
+    unsafe { csr::set::<{ csr::MIE }>(source.mie_bit()) };
+}
 
-        // Pack PMP config for entries 0-3 into pmpcfg0 (4 x 8-bit fields)
-        let pmpcfg0: u32 = (pmp0_cfg)
-            | (pmp1_cfg << 8)
-            | (pmp2_cfg << 16)
-            | (pmp3_cfg << 24);
+#[allow(dead_code)] // only the nested-trap test and sched-demo turn a source back off
+fn disable_irq_source(source: IrqSource) {
+    unsafe { csr::clear::<{ csr::MIE }>(source.mie_bit()) };
+}
 
-        // Pack PMP config for entries 4-7 into pmpcfg1
-        let pmpcfg1: u32 = (pmp4_cfg)
-            | (pmp5_cfg << 8)
-            | (pmp6_cfg << 16)
-            | (pmp7_cfg << 24);
+/// Set mstatus.MIE: M-mode takes the interrupts enabled in mie.
+fn enable_interrupts() {
+    // SAFETY: as for enable_irq_source.  IrqCell borrows only exist
+    // inside critical_section, which re-enables only on the way out.
+    unsafe { csr::set::<{ csr::MSTATUS }>(MSTATUS_MIE) };
+}
 
-        asm!(
-            "csrw  0x3A0, {cfg0}",  // pmpcfg0
-            "csrw  0x3A1, {cfg1}",  // pmpcfg1
-            cfg0 = in(reg) pmpcfg0,
-            cfg1 = in(reg) pmpcfg1,
-        );
+/// Clear mstatus.MIE, returning whether it was set.
+fn disable_interrupts() -> bool {
+    // Read and clear in one step, so an interrupt can't slip in between.
+    let mstatus = unsafe { csr::read_clear::<{ csr::MSTATUS }>(MSTATUS_MIE) };
+    mstatus & MSTATUS_MIE != 0
+}
+
+/// M-mode interrupts are on.  Always false in trap context, which is
+/// entered with mstatus.MIE clear.
+fn interrupts_enabled() -> bool {
+    csr::read::<{ csr::MSTATUS }>() & MSTATUS_MIE != 0
+}
+
+/// Run `f` with M-mode interrupts masked, then put mstatus.MIE back the
+/// way it was: a critical section entered with interrupts already off
+/// (nested, or in trap context) leaves them off.
+fn critical_section<R>(f: impl FnOnce() -> R) -> R {
+    let was_enabled = disable_interrupts();
+    let r = f();
+    if was_enabled {
+        enable_interrupts();
     }
+    r
+}
 
-    // Report PMP configuration
-    uart_puts("  Entry 0: ROM (M-mode code)     Locked R-X  64K @ 0x80000000\r\n");
-    uart_puts("  Entry 1: M_RAM (M-mode data)    Deny U     32K @ 0x80010000\r\n");
-    uart_puts("  Entry 2: M_SHADOW (M-mode SS)   Deny U      8K @ 0x80018000\r\n");
-    uart_puts("  Entry 3: U_CODE (U-mode code)   U: R-X    128K @ 0x80020000\r\n");
-    uart_puts("  Entry 4: U_RODATA               U: R--     32K @ 0x80040000\r\n");
-    uart_puts("  Entry 5: U_RAM (U-mode data)    U: RW-     64K @ 0x80048000\r\n");
-    uart_puts("  Entry 6: U_SHADOW (U-mode SS)   U: RW-      8K @ 0x80058000\r\n");
-    uart_puts("  Entry 7: UART MMIO              U: RW-      4K @ 0x10000000\r\n");
-    uart_puts("[PMP] Configuration complete.\r\n\r\n");
+/// Capacity of the console transmit queue.
+const TX_RING_LEN: usize = 512;
+
+/// Data shared between thread code and interrupt handlers.
+struct IrqCell<T> {
+    held: AtomicBool,
+    inner: UnsafeCell<T>,
 }
 
-// ============================================================================
-// CFI Initialization
-// ============================================================================
+// SAFETY: one hart, and `with` gives out the `&mut` only with interrupts
+// masked and the cell not already held.
+unsafe impl<T> Sync for IrqCell<T> {}
 
-/// Enable hardware CFI extensions via menvcfg and senvcfg CSRs.
-///
-/// menvcfg (0x30A) controls CFI for S/U-mode:
-///   Bit 2 (LPE) — Landing Pad Enable (Zicfilp)
-///   Bit 3 (SSE) — Shadow Stack Enable (Zicfiss)
-///
-/// On hardware without these CSRs, the trap handler skips the writes.
-fn enable_cfi() {
-    uart_puts("[CFI] Enabling hardware CFI extensions...\r\n");
+impl<T> IrqCell<T> {
+    const fn new(value: T) -> Self {
+        IrqCell { held: AtomicBool::new(false), inner: UnsafeCell::new(value) }
+    }
 
-    unsafe {
-        // Enable LPE + SSE in menvcfg (affects S/U-mode)
-        let cfi_bits: u32 = (1 << 2) | (1 << 3); // LPE | SSE
-        asm!(
-            "csrs  0x30A, {bits}",   // csrs menvcfg, bits
-            bits = in(reg) cfi_bits,
-        );
-        uart_puts("  menvcfg: set LPE (bit 2) + SSE (bit 3)\r\n");
+    /// Run `f` on the contents with interrupts masked.  `None` if the
+    /// cell is already held further up this hart's stack (a panic or
+    /// fault while printing); the caller must then do without.
+    fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        critical_section(|| {
+            if self.held.swap(true, Ordering::Acquire) {
+                return None;
+            }
+            // SAFETY: interrupts are off and `held` was clear, so this is
+            // the only reference.
+            let r = f(unsafe { &mut *self.inner.get() });
+            self.held.store(false, Ordering::Release);
+            Some(r)
+        })
+    }
+}
 
-        // Also enable in senvcfg (0x10A) for U-mode if running S-mode software
-        // (In our M-mode-only RoT, menvcfg is sufficient for U-mode, but
-        //  we set senvcfg too for forward-compatibility with S-mode kernels)
-        asm!(
-            "csrs  0x10A, {bits}",   // csrs senvcfg, bits
-            bits = in(reg) cfi_bits,
-        );
-        uart_puts("  senvcfg: set LPE (bit 2) + SSE (bit 3)\r\n");
+static TX_RING: IrqCell<RingBuffer<u8, TX_RING_LEN>> = IrqCell::new(RingBuffer::new());
 
-        // Initialize M-mode hardware shadow stack pointer
-        // (HW SSP CSR 0x011 — ssp)
-        asm!(
-            "la    {tmp}, _m_shadow_stack_top",
-            "csrw  0x011, {tmp}",
-            tmp = out(reg) _,
-        );
-        uart_puts("  ssp: initialized to _m_shadow_stack_top (M-mode)\r\n");
+/// Set once the console interrupt is routed through the PLIC.
+static TX_IRQ_READY: AtomicBool = AtomicBool::new(false);
+
+/// Buffered output is usable: the interrupt is routed and M-mode
+/// interrupts are on (they are off in trap context).
+fn tx_irq_active() -> bool {
+    TX_IRQ_READY.load(Ordering::Relaxed) && interrupts_enabled()
+}
+
+/// Queue `bytes` for interrupt-driven transmission.
+///
+/// While the ring is full this waits for the ISR to make room (interrupts
+/// are briefly re-enabled between attempts).  Falls back to blocking
+/// output if the console interrupt isn't available.
+fn uart_write_buffered(bytes: &[u8]) {
+    if !tx_irq_active() {
+        bytes.iter().for_each(|&b| uart_putc(b));
+        return;
+    }
+    for &b in bytes {
+        loop {
+            match TX_RING.with(|tx| tx.push(b)) {
+                Some(Ok(())) => break,
+                // Full: make sure the ISR is armed and let it run.
+                Some(Err(_)) => CONSOLE.set_tx_interrupt(true),
+                None => {
+                    CONSOLE.putc(b);
+                    break;
+                }
+            }
+        }
     }
+    CONSOLE.set_tx_interrupt(true);
+}
+
+/// Write out everything queued, polling the UART.
+fn uart_flush() {
+    let _ = TX_RING.with(drain_polled);
+}
+
+fn drain_polled(tx: &mut RingBuffer<u8, TX_RING_LEN>) {
+    while let Some(b) = tx.pop() {
+        CONSOLE.putc(b);
+    }
+}
 
-    uart_puts("[CFI] Hardware CFI enabled (or NOPs on unsupported HW).\r\n\r\n");
+/// Console TX interrupt: move queued bytes into the UART while it can
+/// take them; once the ring is empty, stop asking for interrupts.
+fn uart_tx_isr() {
+    let _ = TX_RING.with(|tx| {
+        while CONSOLE.tx_ready() {
+            match tx.pop() {
+                Some(b) => CONSOLE.putc(b),
+                None => break,
+            }
+        }
+        if tx.is_empty() {
+            CONSOLE.set_tx_interrupt(false);
+        }
+    });
 }
 
 // ============================================================================
-// M-Mode Trap Handler
+// Interrupt Controller (PLIC)
 // ============================================================================
 
-/// Unified M-mode trap handler.
-///
-/// Handles:
-///   - **Ecalls from U-mode** (mcause = 8): service requests from application
-///   - **Illegal instructions** (mcause = 2): skip faulting instruction
-///     (graceful degradation for unsupported CSR accesses during boot)
-///   - **CFI violations**:
-///     - Software-check exception (mcause = 18): Zicfiss shadow stack mismatch
-///     - Instruction access fault (mcause = 1): Zicfilp landing pad violation
+/// Platform-Level Interrupt Controller, hart 0 M-mode context.
 ///
-/// Ecall ABI:
-///   a7 = syscall number
-///     0 = uart_putc(a0 = char)
-///     1 = uart_puts(a0 = ptr, a1 = len)
-///     2 = exit(a0 = code)
-///     3 = get_random(a0 = &buf, a1 = len)  [stub: fills with 0xAA]
-///   Return value in a0.
-#[unsafe(naked)]
-#[no_mangle]
-#[link_section = ".text.trap"]
-unsafe extern "C" fn _trap_handler() {
-    naked_asm!(
-        // Save minimal context on M-mode stack
-        "addi   sp, sp, -64",
-        "sw     ra,  0(sp)",
-        "sw     t0,  4(sp)",
-        "sw     t1,  8(sp)",
-        "sw     t2, 12(sp)",
-        "sw     a0, 16(sp)",
-        "sw     a1, 20(sp)",
-        "sw     a2, 24(sp)",
-        "sw     a7, 28(sp)",
-
-        // Read cause
-        "csrr   t0, mcause",
+/// Same layout on QEMU `virt` and `sifive_u`: per-source priority words
+/// at the base, an enable bitmap per context at +0x2000, and a
+/// threshold / claim-complete pair per context at +0x20_0000.
+mod plic {
+    const BASE: usize = 0x0C00_0000;
+    /// Hart 0, M-mode.
+    const CONTEXT: usize = 0;
+    const ENABLE: usize = BASE + 0x2000 + 0x80 * CONTEXT;
+    const THRESHOLD: usize = BASE + 0x20_0000 + 0x1000 * CONTEXT;
+    const CLAIM_COMPLETE: usize = THRESHOLD + 4;
 
-        // Check for environment call from U-mode (cause = 8)
-        "li     t1, 8",
-        "beq    t0, t1, _handle_ecall",
+    fn reg(addr: usize) -> *mut u32 {
+        addr as *mut u32
+    }
 
-        // Check for illegal instruction (cause = 2) — skip it
-        "li     t1, 2",
-        "beq    t0, t1, _handle_illegal",
+    /// Give `id` a non-zero `priority` and enable it for this context.
+    pub fn enable_source(id: u32, priority: u32) {
+        // SAFETY: PLIC MMIO; M-mode has no PMP entry covering it, so it's
+        // reachable, and U-mode isn't granted it.
+        unsafe {
+            reg(BASE + 4 * id as usize).write_volatile(priority);
+            let en = reg(ENABLE + 4 * (id as usize / 32));
+            en.write_volatile(en.read_volatile() | 1 << (id % 32));
+            reg(THRESHOLD).write_volatile(0);
+        }
+    }
 
-        // Check for software-check exception (cause = 18) — CFI violation
-        "li     t1, 18",
-        "beq    t0, t1, _handle_cfi_violation",
+    /// Highest-priority pending source (0 = none), now marked in service.
+    pub fn claim() -> u32 {
+        unsafe { reg(CLAIM_COMPLETE).read_volatile() }
+    }
 
-        // Check for instruction access fault (cause = 1) — landing pad violation
-        "li     t1, 1",
-        "beq    t0, t1, _handle_cfi_violation",
+    /// Signal that `id`, returned by [`claim`], has been handled.
+    pub fn complete(id: u32) {
+        unsafe { reg(CLAIM_COMPLETE).write_volatile(id) }
+    }
+}
 
-        // Unknown trap — halt
-        "j      _handle_unknown_trap",
+/// Most interrupt handlers registered at once.
+const MAX_IRQ_HANDLERS: usize = 8;
 
-        // ── Ecall handler ──────────────────────────────────────────
-        "_handle_ecall:",
-        // Advance mepc past the 4-byte ecall instruction
-        "csrr   t0, mepc",
-        "addi   t0, t0, 4",
-        "csrw   mepc, t0",
+/// A PLIC source and the handler [`irq_external`] runs for it.
+#[derive(Clone, Copy)]
+struct IrqHandler {
+    source: u32,
+    handler: fn(),
+}
 
-        // Dispatch on a7 (syscall number)
-        "lw     a7, 28(sp)",
-        "lw     a0, 16(sp)",
-        "lw     a1, 20(sp)",
+static IRQ_HANDLERS: IrqCell<FixedVec<IrqHandler, MAX_IRQ_HANDLERS>> =
+    IrqCell::new(FixedVec::new());
 
-        // syscall 0: uart_putc(a0 = char)
-        "li     t1, 0",
-        "bne    a7, t1, 10f",
-        "li     t0, 0x10000000",
-        "sb     a0, 0(t0)",
-        "j      _trap_return",
+/// Run `handler` for every interrupt from PLIC `source`, and enable the
+/// source at `priority` (1 = lowest).  Errors if the table is full or the
+/// source already has a handler; nothing is enabled then.
+fn register_irq(source: u32, priority: u32, handler: fn()) -> Result<(), ()> {
+    IRQ_HANDLERS
+        .with(|table| {
+            if table.iter().any(|h| h.source == source) {
+                return Err(());
+            }
+            table.push(IrqHandler { source, handler }).map_err(|_| ())
+        })
+        .unwrap_or(Err(()))?;
+    plic::enable_source(source, priority);
+    Ok(())
+}
 
-        // syscall 1: uart_puts(a0 = ptr, a1 = len)
-        "10:",
-        "li     t1, 1",
-        "bne    a7, t1, 20f",
-        "li     t0, 0x10000000",
-        "11:",
-        "beqz   a1, _trap_return",
-        "lb     t1, 0(a0)",
-        "sb     t1, 0(t0)",
-        "addi   a0, a0, 1",
-        "addi   a1, a1, -1",
-        "j      11b",
-
-        // syscall 2: exit(a0 = code)
-        "20:",
-        "li     t1, 2",
-        "bne    a7, t1, 30f",
-        // Write to QEMU test finisher
-        "li     t0, 0x100000",
-        "li     t1, 0x5555",      // PASS
-        "sw     t1, 0(t0)",
-        "21: wfi",
-        "j      21b",
-
-        // syscall 3: get_random(a0 = &buf, a1 = len) — stub
-        "30:",
-        "li     t1, 3",
-        "bne    a7, t1, _trap_return",
-        "li     t2, 0xAA",        // stub: fill with 0xAA
-        "31:",
-        "beqz   a1, _trap_return",
-        "sb     t2, 0(a0)",
-        "addi   a0, a0, 1",
-        "addi   a1, a1, -1",
-        "j      31b",
+/// Machine external interrupt, from `_trap_handler`: claim, dispatch to
+/// the registered handler, complete — until the PLIC has nothing more
+/// pending.  A source with no handler is just completed.
+#[no_mangle]
+extern "C" fn irq_external() {
+    loop {
+        let source = plic::claim();
+        if source == 0 {
+            break;
+        }
+        let handler = IRQ_HANDLERS
+            .with(|table| table.iter().find(|h| h.source == source).map(|h| h.handler))
+            .flatten();
+        if let Some(handler) = handler {
+            handler();
+        }
+        plic::complete(source);
+    }
+}
 
-        // ── Illegal instruction handler ────────────────────────────
-        // Skip 2-byte (compressed) or 4-byte instruction
-        "_handle_illegal:",
-        "csrr   t0, mepc",
-        "lhu    t1, 0(t0)",
-        "andi   t1, t1, 0x3",
-        "li     t2, 0x3",
-        "bne    t1, t2, 40f",
-        "addi   t0, t0, 4",       // 4-byte instruction
-        "j      41f",
-        "40: addi t0, t0, 2",     // 2-byte compressed
-        "41: csrw mepc, t0",
-        "j      _trap_return",
+// ============================================================================
+// Test Finisher (QEMU sifive_test)
+// ============================================================================
 
-        // ── CFI violation handler ──────────────────────────────────
-        // On real hardware this is a security-critical event.
-        // Options: halt, reset, log + quarantine, etc.
-        "_handle_cfi_violation:",
-        // Print violation notice via UART
-        "li     t0, 0x10000000",
-        // "!" = 0x21, "C" = 0x43, "F" = 0x46, "I" = 0x49
-        "li     t1, 0x43",        // 'C'
-        "sb     t1, 0(t0)",
-        "li     t1, 0x46",        // 'F'
-        "sb     t1, 0(t0)",
-        "li     t1, 0x49",        // 'I'
-        "sb     t1, 0(t0)",
-        "li     t1, 0x21",        // '!'
-        "sb     t1, 0(t0)",
-        "li     t1, 0x0A",        // '\n'
-        "sb     t1, 0(t0)",
-        // Hard fault — halt the system
-        "50: wfi",
-        "j      50b",
+/// QEMU's `sifive_test` device at 0x10_0000, which ends the run or resets
+/// the machine.  Every exit path goes through here, so a CI run's exit
+/// status always means the same thing.
+///
+/// One 32-bit register.  The low 16 bits pick the action, the high 16
+/// carry the exit code:
+///
+/// ```text
+///   FINISHER_PASS   0x5555               QEMU exits with status 0
+///   FINISHER_FAIL   code << 16 | 0x3333  QEMU exits with status `code`
+///   FINISHER_RESET  0x7777               machine reset; RAM (.noinit) survives
+/// ```
+///
+/// QEMU passes a FAIL code straight to `exit()`, so a shell sees its low
+/// 8 bits.  An exit code of 0 would read as a pass and is never sent.
+mod test_device {
+    const BASE: usize = 0x10_0000;
 
-        // ── Unknown trap ───────────────────────────────────────────
-        "_handle_unknown_trap:",
-        "51: wfi",
-        "j      51b",
+    pub const FINISHER_FAIL: u32 = 0x3333;
+    pub const FINISHER_PASS: u32 = 0x5555;
+    pub const FINISHER_RESET: u32 = 0x7777;
 
-        // ── Trap return ────────────────────────────────────────────
-        "_trap_return:",
-        "lw     ra,  0(sp)",
-        "lw     t0,  4(sp)",
-        "lw     t1,  8(sp)",
-        "lw     t2, 12(sp)",
-        "lw     a0, 16(sp)",
-        "lw     a1, 20(sp)",
-        "lw     a2, 24(sp)",
-        "lw     a7, 28(sp)",
-        "addi   sp, sp, 64",
-        "mret",
-    )
+    /// FAIL code for a panic or fatal trap, kept apart from the small codes
+    /// the U-mode tests exit with.
+    pub const FATAL_CODE: u16 = 0xff;
+
+    fn finish(value: u32) -> ! {
+        // SAFETY: the finisher's one register; M-mode only (no U-mode PMP
+        // entry covers it).
+        unsafe { (BASE as *mut u32).write_volatile(value) };
+        // A reset takes effect asynchronously, and without the device the
+        // write does nothing: park until either happens.
+        loop {
+            unsafe { core::arch::asm!("wfi") };
+        }
+    }
+
+    pub fn pass() -> ! {
+        finish(FINISHER_PASS)
+    }
+
+    /// Fail with `code`.  0 would look like a pass, so it is sent as 1.
+    pub fn fail(code: u16) -> ! {
+        finish((code.max(1) as u32) << 16 | FINISHER_FAIL)
+    }
+
+    pub fn reset() -> ! {
+        finish(FINISHER_RESET)
+    }
 }
 
 // ============================================================================
-// M-Mode Protected Functions (with full CFI)
+// Machine Timer (CLINT)
+// ============================================================================
+
+/// Core-local interruptor: the free-running `mtime` counter and hart 0's
+/// `mtimecmp`.  The timer interrupt is pending while mtime >= mtimecmp.
+#[allow(dead_code)] // only the nested-trap test and sched-demo arm deadlines
+mod clint {
+    const BASE: usize = 0x0200_0000;
+    /// Hart 0.
+    const MTIMECMP: usize = BASE + 0x4000;
+    const MTIME: usize = BASE + 0xBFF8;
+
+    fn reg(addr: usize) -> *mut u32 {
+        addr as *mut u32
+    }
+
+    /// Current mtime.  The halves are read hi-lo-hi so a carry between
+    /// them can't produce a torn value.
+    pub fn now() -> u64 {
+        // SAFETY: CLINT MMIO; M-mode only, like the PLIC.
+        unsafe {
+            loop {
+                let hi = reg(MTIME + 4).read_volatile();
+                let lo = reg(MTIME).read_volatile();
+                if reg(MTIME + 4).read_volatile() == hi {
+                    return (hi as u64) << 32 | lo as u64;
+                }
+            }
+        }
+    }
+
+    /// Fire once mtime reaches `deadline`.  The high half is parked at
+    /// its maximum while the low half changes, so no intermediate value
+    /// raises a spurious interrupt.
+    pub fn arm(deadline: u64) {
+        unsafe {
+            reg(MTIMECMP + 4).write_volatile(u32::MAX);
+            reg(MTIMECMP).write_volatile(deadline as u32);
+            reg(MTIMECMP + 4).write_volatile((deadline >> 32) as u32);
+        }
+    }
+
+    /// Push the deadline out of reach, which clears the pending interrupt.
+    pub fn disarm() {
+        unsafe {
+            reg(MTIMECMP + 4).write_volatile(u32::MAX);
+            reg(MTIMECMP).write_volatile(u32::MAX);
+        }
+    }
+}
+
+/// Machine timer interrupts taken.
+static TIMER_TICKS: AtomicU32 = AtomicU32::new(0);
+
+/// Machine timer interrupt, from `_trap_handler`.  Each deadline is
+/// one-shot: disarm and count it, and raise S-mode's timer interrupt if
+/// its SBI deadline ([`S_TIMER`]) has passed.  The `sched-demo` time
+/// slice runs this first, then arms the next tick itself (`sched_tick`).
+#[no_mangle]
+extern "C" fn irq_timer() {
+    clint::disarm();
+    TIMER_TICKS.fetch_add(1, Ordering::Relaxed);
+    if S_TIMER.with(|t| t.expire(clint::now())) == Some(true) {
+        // SAFETY: STIP is S-mode's; nothing in M-mode reads it.
+        unsafe { csr::set::<{ csr::MIP }>(sbi::MIP_STIP) };
+    }
+}
+
+/// Whether menvcfg.STCE stuck at boot ([`enable_sstc`]): S-mode has
+/// `stimecmp` of its own.
+static SSTC: AtomicBool = AtomicBool::new(false);
+
+/// S-mode's `set_timer` deadline when there is no Sstc.
+static S_TIMER: IrqCell<STimer> = IrqCell::new(STimer::new());
+
+/// Set menvcfg.STCE, so an S-mode kernel can program `stimecmp` itself,
+/// and report which timer path S-mode gets ([`riscv_rot_cfi::sbi`]).
+/// On a hart without Sstc the bit reads back clear, or menvcfgh is
+/// missing and reads 0, and S-mode's timer goes through `set_timer`.
+fn enable_sstc() {
+    // SAFETY: STCE only changes what S-mode's timer CSRs do; M-mode's
+    // timer and U-mode are unaffected.
+    let menvcfgh = unsafe { csr::set_readback::<{ csr::MENVCFGH }>(sbi::ENVCFGH_STCE as usize) } as u32;
+    let path = STimerPath::from_menvcfgh(menvcfgh);
+    SSTC.store(path == STimerPath::Sstc, Ordering::Relaxed);
+    uart_puts(match path {
+        STimerPath::Sstc => "[TIMER] menvcfgh.STCE on: S-mode timer is stimecmp (Sstc)\r\n",
+        STimerPath::Sbi => "[TIMER] No Sstc: S-mode timer is SBI set_timer on mtimecmp\r\n",
+    });
+}
+
+/// SBI `set_timer(deadline)` for S-mode.  With Sstc, write `stimecmp` on
+/// its behalf; the hart raises and clears STIP itself.  Without, keep
+/// the deadline in [`S_TIMER`], clear STIP and arm `mtimecmp` for it:
+/// [`irq_timer`] sets STIP once it passes.  `mtimecmp` has one deadline,
+/// so on that path S-mode's timer and M-mode's own users of it (the
+/// `sched-demo` tick, `timer-upcall`) replace each other's.
+fn sbi_set_timer(deadline: u64) {
+    if SSTC.load(Ordering::Relaxed) {
+        // SAFETY: S-mode's CSRs.  The high half is parked at its maximum
+        // while the low half changes, as in `clint::arm`.
+        unsafe {
+            csr::write::<{ csr::STIMECMPH }>(u32::MAX as usize);
+            csr::write::<{ csr::STIMECMP }>(deadline as u32 as usize);
+            csr::write::<{ csr::STIMECMPH }>((deadline >> 32) as usize);
+        }
+        return;
+    }
+    S_TIMER.with(|t| t.set(deadline));
+    // SAFETY: as in `irq_timer`.
+    unsafe { csr::clear::<{ csr::MIP }>(sbi::MIP_STIP) };
+    if deadline == u64::MAX {
+        clint::disarm();
+    } else {
+        clint::arm(deadline);
+    }
+}
+
+/// RX interrupt armed: [`console_isr`] owns received bytes.  Until then
+/// they are left in the FIFO for polled readers (the host link).
+static CONSOLE_RX_IRQ: AtomicBool = AtomicBool::new(false);
+
+/// Capacity of the console receive queue.
+const RX_RING_LEN: usize = 64;
+
+/// Received bytes waiting for the getc syscall.
+static RX_RING: IrqCell<RingBuffer<u8, RX_RING_LEN>> = IrqCell::new(RingBuffer::new());
+
+/// Console interrupt handler: queue received bytes, then service TX.
+///
+/// The FIFO is always emptied, dropping bytes once the queue is full —
+/// leaving them would keep the RX interrupt asserted.
+fn console_isr() {
+    if CONSOLE_RX_IRQ.load(Ordering::Relaxed) {
+        let _ = RX_RING.with(|rx| {
+            while let Some(c) = CONSOLE.try_getc() {
+                let _ = rx.push(c);
+            }
+        });
+    }
+    uart_tx_isr();
+}
+
+/// Register the console with the PLIC and turn on M-mode interrupts,
+/// switching [`uart_puts`] to buffered output.
+fn enable_console_irq() {
+    if register_irq(CONSOLE_IRQ, 1, console_isr).is_err() {
+        uart_puts("[IRQ] console handler not registered; output stays polled\r\n\r\n");
+        return;
+    }
+    TX_IRQ_READY.store(true, Ordering::Relaxed);
+    enable_irq_source(IrqSource::External);
+    enable_interrupts();
+    uart_puts("[IRQ] Console TX interrupt-driven: PLIC source ");
+    uart_put_hex32(CONSOLE_IRQ);
+    uart_puts(", ring buffer in M_RAM\r\n\r\n");
+}
+
+/// Deliver console input by interrupt: from here on [`console_isr`]
+/// queues received bytes for U-mode's getc syscall, with no polling.
+fn enable_console_rx_irq() {
+    CONSOLE_RX_IRQ.store(true, Ordering::Relaxed);
+    CONSOLE.set_rx_interrupt(true);
+    uart_puts("[IRQ] UART RX interrupt enabled — input queued for getc\r\n");
+}
+
+// ============================================================================
+// Host Link (authenticated UART session)
+// ============================================================================
+
+/// What the OTP stub ([`otp_device_key`]) reads out as the device key.
+///
+/// Placeholder for a per-device value burnt into fuses.  As a
+/// compile-time constant it ships in the ROM image, so it is only fit for
+/// exercising the protocols, not for protecting a real device.
+const OTP_DEVICE_KEY: [u8; KEY_LEN] = *b"rot-demo-device-secret-not-fused";
+
+/// The boot UART as the raw byte transport under [`Session`].
+#[cfg(any(feature = "secure-session", feature = "net-load"))]
+struct UartIo;
+
+#[cfg(any(feature = "secure-session", feature = "net-load"))]
+impl ByteIo for UartIo {
+    fn write_byte(&mut self, b: u8) {
+        uart_putc(b);
+    }
+    fn read_byte(&mut self) -> u8 {
+        uart_getc()
+    }
+}
+
+/// Open an authenticated session with the host and send it the firmware
+/// measurement and the measurement log.
+///
+/// The host starts the session by sending a 16-byte nonce, used as the
+/// HKDF salt so every session gets a fresh key; the reply is two frames:
+/// the big-endian measurement, then the log in its TLV wire form
+/// ([`MeasurementLog::encode`]).
+#[cfg(feature = "secure-session")]
+fn host_session_report(measurement: u32) {
+    uart_puts("[SESSION] Waiting for 16-byte host nonce...\r\n");
+    let mut nonce = [0u8; 16];
+    for b in nonce.iter_mut() {
+        *b = uart_getc();
+    }
+    let mut secret = device_secret();
+    let mut session = Session::new(&secret, &nonce, Role::Device);
+    secure_zero(&mut secret);
+    let mut payload = [0u8; 4];
+    write_u32_be(&mut payload, measurement);
+    let sent = session.send_frame(&mut UartIo, &payload);
+
+    const _: () = assert!(<MeasurementLog>::WIRE_MAX <= riscv_rot_cfi::frame::MAX_PAYLOAD);
+    let mut log = [0u8; <MeasurementLog>::WIRE_MAX];
+    let log_sent = match MEASUREMENT_LOG.with(|l| l.encode(&mut log)) {
+        Some(Some(len)) => session.send_frame(&mut UartIo, &log[..len]).is_ok(),
+        _ => false,
+    };
+    uart_newline();
+    if sent.is_ok() && log_sent {
+        uart_puts("[SESSION] Sent authenticated measurement and log frames\r\n\r\n");
+    }
+}
+
+// ============================================================================
+// Network Load (net-load)
+// ============================================================================
+
+/// Where a loaded image may go, with the access U-mode's PMP entries give
+/// it there ([`configure_pmp`]).  U_RAM stops at the U-mode stack, which
+/// `launch_umode` still places at `_u_stack_top`, and U_RODATA at the
+/// boot-info page, which it writes.
+#[cfg(feature = "net-load")]
+fn u_load_regions() -> [elf::Region; 3] {
+    let ram = layout::u_ram_below_stack();
+    let code = layout::u_code();
+    let rodata = riscv_rot_cfi::memory_map::U_RODATA.base as u32;
+    [
+        elf::Region { start: code.base as u32, len: code.size as u32, flags: PF_R | PF_X },
+        elf::Region { start: rodata, len: layout::u_boot_info().base as u32 - rodata, flags: PF_R },
+        elf::Region { start: ram.base as u32, len: ram.size as u32, flags: PF_R | PF_W },
+    ]
+}
+
+/// Frame buffer for [`net_load`], kept off the 4K M-mode stack.
+#[cfg(feature = "net-load")]
+static LOAD_FRAME: IrqCell<[u8; MAX_PAYLOAD]> = IrqCell::new([0; MAX_PAYLOAD]);
+
+/// Receive the U-mode firmware from the host (see [`riscv_rot_cfi::netload`])
+/// in place of the linked image, and return its entry point.  Halts
+/// if the transfer or the image is refused, after telling the host why.
+#[cfg(feature = "net-load")]
+fn net_load() -> usize {
+    uart_puts("[LOAD] Waiting for 16-byte host nonce...\r\n");
+    let mut nonce = [0u8; 16];
+    for b in nonce.iter_mut() {
+        *b = uart_getc();
+    }
+    let mut secret = device_secret();
+    let mut session = Session::new(&secret, &nonce, Role::Device);
+    secure_zero(&mut secret);
+    let regions = u_load_regions();
+    let result = LOAD_FRAME
+        .with(|buf| receive_image(&mut session, buf, &regions))
+        .unwrap_or(Err(LoadError::BadRequest));
+    let status = match result {
+        Ok(_) => 0,
+        Err(e) => e.status(),
+    };
+    let _ = session.send_frame(&mut UartIo, &[status]);
+    uart_newline();
+    match result {
+        Ok(entry) => {
+            let _ = write!(UartWriter, "[LOAD] U-mode image loaded, entry {:#010x}\r\n\r\n", entry);
+            entry as usize
+        }
+        Err(e) => {
+            let _ = write!(UartWriter, "[LOAD] FAIL: {}\r\n", e);
+            panic!("loaded image refused");
+        }
+    }
+}
+
+/// Read the request frame, clear the U-mode regions, then stream the file
+/// into them.
+#[cfg(feature = "net-load")]
+fn receive_image(
+    session: &mut Session,
+    buf: &mut [u8; MAX_PAYLOAD],
+    regions: &[elf::Region],
+) -> Result<u32, LoadError> {
+    let n = session.recv_frame(&mut UartIo, buf)?;
+    let request = LoadRequest::parse(&buf[..n]).ok_or(LoadError::BadRequest)?;
+    let mut loader = Loader::new(request, regions)?;
+
+    // Nothing of the linked image may survive into the measurement.
+    for r in regions {
+        // SAFETY: the U-mode regions; nothing in M-mode lives there, and
+        // U-mode hasn't started.
+        unsafe { core::ptr::write_bytes(r.start as *mut u8, 0, r.len as usize) };
+    }
+    while !loader.is_complete() {
+        let n = session.recv_frame(&mut UartIo, buf)?;
+        loader.feed(&buf[..n], |addr, bytes| {
+            // SAFETY: the loader only writes ranges Image::place checked
+            // lie inside `regions`.
+            unsafe { core::ptr::copy_nonoverlapping(bytes.as_ptr(), addr as *mut u8, bytes.len()) }
+        })?;
+    }
+    loader.finish()
+}
+
+// ============================================================================
+// Memory Layout (linker-script symbols)
+// ============================================================================
+
+/// Stack bounds and sizes exported by `memory.x` / `link.x`.
+///
+/// A linker-script symbol has an address but no storage: for `_m_stack_top`
+/// the address *is* the boundary, and for an absolute symbol such as
+/// `_m_stack_size = 4K` the address *is* the value.  So these are only ever
+/// used via `addr_of!` — reading through them would load whatever happens
+/// to live at address 0x1000.
+mod layout {
+    use core::ptr::addr_of;
+    use riscv_rot_cfi::region::Region;
+    
+    extern "C" {
+        static _m_stack_bottom: u8;
+        static _m_stack_size: u8;
+        static _m_shadow_stack_bottom: u8;
+        static _m_shadow_stack_size: u8;
+        static _m_sw_shadow_stack_bottom: u8;
+        static _m_sw_shadow_stack_size: u8;
+        static _u_stack_bottom: u8;
+        static _u_stack_size: u8;
+        static _u_stack_top: u8;
+        #[cfg(not(feature = "net-load"))]
+        static _u_heap_start: u8;
+        static _u_rodata_start: u8;
+        static _u_rodata_end: u8;
+        static _u_shadow_stack_bottom: u8;
+        static _u_shadow_stack_size: u8;
+        static _u_sw_shadow_stack_bottom: u8;
+        static _u_sw_shadow_stack_size: u8;
+        static _u_ram_start: u8;
+        static _u_ram_end: u8;
+        static _u_code_start: u8;
+        static _u_code_end: u8;
+        static _m_data_start: u8;
+        static _m_data_end: u8;
+        static _m_data_load: u8;
+        static _m_bss_start: u8;
+        static _m_bss_end: u8;
+        static _u_entry_point: u8;
+        static _u_boot_info: u8;
+        static _u_boot_info_size: u8;
+        #[cfg(feature = "vectored-traps")]
+        static _trap_vector: u8;
+    }
+
+    /// One stack as reserved by `link.x`: `bottom .. bottom + size`.
+    pub struct Stack {
+        pub name: &'static str,
+        pub bottom: usize,
+        pub size: usize,
+    }
+
+    impl Stack {
+        pub fn top(&self) -> usize {
+            self.bottom + self.size
+        }
+    }
+
+    /// U_RAM, from `memory.x`.
+    ///
+    /// M-mode is not subject to the (unlocked) U-mode PMP entries, so a
+    /// service must check any buffer U-mode hands it against this
+    /// ([`Region::contains_range`]) before touching it — otherwise U-mode
+    /// could point it at M_RAM.
+    pub fn u_ram() -> Region {
+        Region::from_bounds(addr_of!(_u_ram_start) as usize, addr_of!(_u_ram_end) as usize)
+    }
+
+    /// M-mode `.data`: its RAM range and where `_start` copies it from.
+    pub struct DataImage {
+        pub start: usize,
+        pub end: usize,
+        pub load: usize,
+    }
+
+    pub fn data() -> DataImage {
+        DataImage {
+            start: addr_of!(_m_data_start) as usize,
+            end: addr_of!(_m_data_end) as usize,
+            load: addr_of!(_m_data_load) as usize,
+        }
+    }
+
+    /// Where U-mode starts: `_u_entry_point` (`link.x`, defaults to
+    /// `_u_entry`).
+    #[cfg(not(feature = "net-load"))]
+    pub fn u_entry_point() -> usize {
+        addr_of!(_u_entry_point) as usize
+    }
+
+    /// U_RAM up to the bottom of the U-mode stack.
+    #[cfg(feature = "net-load")]
+    pub fn u_ram_below_stack() -> Region {
+        Region::from_bounds(addr_of!(_u_ram_start) as usize, addr_of!(_u_stack_bottom) as usize)
+    }
+
+    /// The U-mode heap's span: from the end of the linked `.u_bss` up to
+    /// the bottom of the U-mode stack.
+    #[cfg(not(feature = "net-load"))]
+    pub fn u_heap() -> Region {
+        Region::from_bounds(addr_of!(_u_heap_start) as usize, addr_of!(_u_stack_bottom) as usize)
+    }
+
+    /// Top of the U-mode stack, where U-mode starts.
+    pub fn u_stack_top() -> usize {
+        addr_of!(_u_stack_top) as usize
+    }
+
+    /// The U_CODE region, from `memory.x`.
+    pub fn u_code() -> Region {
+        Region::from_bounds(addr_of!(_u_code_start) as usize, addr_of!(_u_code_end) as usize)
+    }
+
+    /// Where U-mode's read-only data is: the linked `.u_rodata`, or in
+    /// `net-load` builds the whole of U_RODATA below the boot-info page,
+    /// which the loader clears and a loaded image's rodata goes in.
+    #[cfg(feature = "measure-rodata")]
+    pub fn u_rodata() -> Region {
+        if cfg!(feature = "net-load") {
+            Region::from_bounds(riscv_rot_cfi::memory_map::U_RODATA.base, u_boot_info().base)
+        } else {
+            Region::from_bounds(addr_of!(_u_rodata_start) as usize, addr_of!(_u_rodata_end) as usize)
+        }
+    }
+
+    /// The boot-info page at the top of U_RODATA (`link.x`).
+    pub fn u_boot_info() -> Region {
+        Region::new(addr_of!(_u_boot_info) as usize, addr_of!(_u_boot_info_size) as usize)
+    }
+
+    /// The trap entry `_start` points mtvec at: `_trap_vector` in
+    /// `vectored-traps` builds, else `_trap_handler`.
+    pub fn trap_entry() -> usize {
+        #[cfg(feature = "vectored-traps")]
+        return addr_of!(_trap_vector) as usize;
+        #[cfg(not(feature = "vectored-traps"))]
+        return super::_trap_handler as *const () as usize;
+    }
+
+    /// M-mode `.bss`.
+    pub fn bss() -> Region {
+        Region::from_bounds(addr_of!(_m_bss_start) as usize, addr_of!(_m_bss_end) as usize)
+    }
+
+    /// Bottom of the M-mode or U-mode software shadow stack.
+    #[cfg(any(feature = "shadow-trace", feature = "ss-crosscheck"))]
+    pub fn sw_shadow_stack_bottom(umode: bool) -> usize {
+        if umode {
+            addr_of!(_u_sw_shadow_stack_bottom) as usize
+        } else {
+            addr_of!(_m_sw_shadow_stack_bottom) as usize
+        }
+    }
+
+    /// Lowest address of the M-mode stack, which M-mode code and the trap
+    /// handler share.
+    #[cfg(feature = "stack-headroom")]
+    pub fn m_stack_bottom() -> usize {
+        addr_of!(_m_stack_bottom) as usize
+    }
+
+    /// Every stack in the image, M-mode first.
+    pub fn stacks() -> [Stack; 6] {
+        macro_rules! stack {
+            ($name:expr, $bottom:ident, $size:ident) => {
+                Stack {
+                    name: $name,
+                    bottom: addr_of!($bottom) as usize,
+                    size: addr_of!($size) as usize,
+                }
+            };
+        }
+        [
+            stack!("M-mode stack", _m_stack_bottom, _m_stack_size),
+            stack!("M-mode HW shadow stack", _m_shadow_stack_bottom, _m_shadow_stack_size),
+            stack!("M-mode SW shadow stack", _m_sw_shadow_stack_bottom, _m_sw_shadow_stack_size),
+            stack!("U-mode stack", _u_stack_bottom, _u_stack_size),
+            stack!("U-mode HW shadow stack", _u_shadow_stack_bottom, _u_shadow_stack_size),
+            stack!("U-mode SW shadow stack", _u_sw_shadow_stack_bottom, _u_sw_shadow_stack_size),
+        ]
+    }
+}
+
+/// Print the stack layout the linker actually produced.
+fn report_stacks() {
+    uart_puts("[LAYOUT] Stacks (from link.x):\r\n");
+    for s in layout::stacks() {
+        let _ = write!(
+            UartWriter,
+            "  {:<24}{:>6} bytes  {:#010x}..{:#010x}\r\n",
+            s.name,
+            s.size,
+            s.bottom,
+            s.top()
+        );
+    }
+    #[cfg(feature = "stack-headroom")]
+    let _ = write!(UartWriter, "  M-mode stack headroom now: {} bytes\r\n", m_stack_remaining());
+    uart_newline();
+}
+
+/// Stack below the current frame before a push would leave the M-mode
+/// stack: `sp - _m_stack_bottom`, 0 if sp is already past it.
+///
+/// Nothing else catches an M-mode overflow.  Below the stack is `.noinit`
+/// and `.bss`, M-mode's own PMP-unrestricted RAM, so an overflowing frame
+/// would quietly overwrite kernel state.
+#[cfg(feature = "stack-headroom")]
+#[inline(always)]
+fn m_stack_remaining() -> usize {
+    let sp: usize;
+    // SAFETY: reads sp, nothing else.
+    unsafe { asm!("mv {}, sp", out(reg) sp, options(nomem, nostack, preserves_flags)) };
+    sp.saturating_sub(layout::m_stack_bottom())
+}
+
+/// Stack the deepest crypto chain needs below its caller: `Point::mul` ->
+/// `Point::add` -> `Modulus::mul` for an ECDSA quote, `hmac_sha256` ->
+/// `HmacSha256::new` -> `compress` for HMAC, both about 1.4K in release
+/// builds.
+#[cfg(feature = "stack-headroom")]
+const CRYPTO_STACK: usize = 1536;
+
+/// Halt unless at least `min` bytes of M-mode stack remain below the
+/// caller.  For the entry of deep paths, so running short is a diagnosed
+/// stop at a known place rather than an overflow into `.bss`.
+#[cfg(feature = "stack-headroom")]
+#[track_caller]
+fn check_stack_headroom(min: usize) {
+    let remaining = m_stack_remaining();
+    if remaining < min {
+        panic!("M-mode stack headroom {} bytes, {} needed", remaining, min);
+    }
+}
+
+// ============================================================================
+// User-Memory Access (mstatus.MPRV)
+// ============================================================================
+
+/// Reading and writing U-mode memory from M-mode *as U-mode*.
+///
+/// M-mode ignores every unlocked PMP entry, so a plain load from a
+/// syscall handler succeeds wherever the pointer goes — including M_RAM.
+/// With mstatus.MPRV = 1, loads and stores use the privilege in
+/// mstatus.MPP instead; with MPP = U they get exactly U-mode's PMP view,
+/// and a pointer U-mode couldn't use itself raises an access fault.
+///
+/// MPRV applies to *every* data access, including stack spills, and the
+/// M-mode stack is not U-accessible.  So the MPRV window can't wrap
+/// ordinary Rust code: [`read_u8`] / [`write_u8`] are asm routines that
+/// set MPRV around exactly one load or store.  If PMP refuses that
+/// access, `_trap_handler` sees a load/store access fault whose mepc lies
+/// in `_uaccess_start.._uaccess_end` and resumes at `_uaccess_fault`,
+/// which restores mstatus and returns -1 — a bad pointer is an error
+/// result rather than a silent read of M-mode memory or a halt.
+///
+/// mstatus bookkeeping, in order:
+///   1. save mstatus in a register (t1)
+///   2. write it back with MPP = U and MPRV = 1
+///   3. the access — may trap: trap entry sets MPP = M, so the nested
+///      handler's own frame accesses are M-privileged; its mret returns to
+///      M (MPRV is left set) at `_uaccess_fault`
+///   4. write the saved mstatus back (normal path or fixup), clearing MPRV
+///
+/// The nested trap clobbers the outer trap's mepc and MPP, which is why
+/// every trap frame holds its own copies and `_trap_return` reloads both.
+mod uaccess {
+    use core::arch::global_asm;
+
+    use super::csr;
+
+    /// mstatus.MPRV (bit 17).
+    const MSTATUS_MPRV: u32 = 1 << 17;
+    /// mstatus.MPP (bits 12:11); 0b00 = U.
+    const MSTATUS_MPP: u32 = 3 << 11;
+
+    global_asm!(
+        ".section .text.uaccess, \"ax\"",
+        ".balign 4",
+        ".globl _uaccess_start",
+        "_uaccess_start:",
+
+        // uaccess_lbu(a0 = addr) -> a0 = byte, or -1
+        ".globl uaccess_lbu",
+        "uaccess_lbu:",
+        "csrr   t1, mstatus",
+        "li     t0, ~{mpp}",
+        "and    t2, t1, t0",
+        "li     t0, {mprv}",
+        "or     t2, t2, t0",
+        "csrw   mstatus, t2",
+        "lbu    a0, 0(a0)",
+        "csrw   mstatus, t1",
+        "ret",
+
+        // uaccess_sb(a0 = addr, a1 = byte) -> a0 = 0, or -1
+        ".globl uaccess_sb",
+        "uaccess_sb:",
+        "csrr   t1, mstatus",
+        "li     t0, ~{mpp}",
+        "and    t2, t1, t0",
+        "li     t0, {mprv}",
+        "or     t2, t2, t0",
+        "csrw   mstatus, t2",
+        "sb     a1, 0(a0)",
+        "csrw   mstatus, t1",
+        "li     a0, 0",
+        "ret",
+
+        ".globl _uaccess_end",
+        "_uaccess_end:",
+
+        // Fixup: the access faulted.  t1 still holds the saved mstatus
+        // (the nested trap restored it with the rest of the frame).
+        ".globl _uaccess_fault",
+        "_uaccess_fault:",
+        "csrw   mstatus, t1",
+        "li     a0, -1",
+        "ret",
+        mpp = const MSTATUS_MPP,
+        mprv = const MSTATUS_MPRV,
+    );
+
+    extern "C" {
+        fn uaccess_lbu(addr: usize) -> isize;
+        fn uaccess_sb(addr: usize, byte: u8) -> isize;
+    }
+
+    /// Read one byte at `addr` with U-mode's permissions.
+    pub fn read_u8(addr: usize) -> Option<u8> {
+        // SAFETY: the routine touches only `addr`, under U-mode PMP, and a
+        // refused access comes back as -1 via the trap-handler fixup.
+        let r = unsafe { uaccess_lbu(addr) };
+        (r >= 0).then_some(r as u8)
+    }
+
+    /// Write one byte at `addr` with U-mode's permissions.
+    pub fn write_u8(addr: usize, byte: u8) -> bool {
+        // SAFETY: as for read_u8.
+        unsafe { uaccess_sb(addr, byte) == 0 }
+    }
+
+    /// Fill `dst` from U-mode memory at `src`.  `false` (with `dst`
+    /// partially written) if any byte isn't U-readable.
+    pub fn copy_from_user(dst: &mut [u8], src: usize) -> bool {
+        for (i, d) in dst.iter_mut().enumerate() {
+            match read_u8(src.wrapping_add(i)) {
+                Some(b) => *d = b,
+                None => return false,
+            }
+        }
+        true
+    }
+
+    /// Copy `src` to U-mode memory at `dst`.  `false` (with a prefix
+    /// written) if any byte isn't U-writable.
+    pub fn copy_to_user(dst: usize, src: &[u8]) -> bool {
+        src.iter()
+            .enumerate()
+            .all(|(i, &b)| write_u8(dst.wrapping_add(i), b))
+    }
+
+    /// Run `f` with loads and stores at U-mode privilege (MPRV = 1,
+    /// MPP = U), restoring mstatus afterwards.
+    ///
+    /// # Safety
+    ///
+    /// *Every* data access `f` makes is checked against U-mode's PMP view
+    /// — its own stack frame and spills included, and the M-mode stack is
+    /// not U-accessible.  Only the [`read_u8`]/[`write_u8`] routines have a
+    /// fault fixup, so an access U-mode may not make is a fatal trap.  In
+    /// practice `f` must be a register-only `asm!` block; prefer the
+    /// copy helpers.
+    #[allow(dead_code)]
+    #[inline(always)]
+    pub unsafe fn with_mprv<T>(f: impl FnOnce() -> T) -> T {
+        let saved = csr::read::<{ csr::MSTATUS }>();
+        csr::write::<{ csr::MSTATUS }>(
+            (saved & !(MSTATUS_MPP as usize)) | MSTATUS_MPRV as usize,
+        );
+        let r = f();
+        csr::write::<{ csr::MSTATUS }>(saved);
+        r
+    }
+}
+
+// ============================================================================
+// Syscall Registration
+// ============================================================================
+
+/// A registered service's entry: it takes its arguments from the caller's
+/// trap frame and leaves its results there, for `_trap_return` to reload.
+type SyscallHandler = fn(&mut TrapFrame);
+
+/// The services [`sys_dispatch`] serves, by a7 ([`syscall`]).  A new one
+/// is one more entry.  Missing are only those the asm serves itself:
+/// `yield` (a `wfi`), and the ones that rewrite the whole U-mode context
+/// through `_context_switch` (task_spawn, task_wait, task_exit,
+/// upcall_return).
+static SYSCALLS: &[Syscall<SyscallHandler>] = &[
+    Syscall { number: 0, handler: |f| f.a0 = sys_putc(f.a0) },
+    Syscall { number: 1, handler: |f| f.a0 = sys_puts(f.a0, f.a1) },
+    Syscall { number: 2, handler: |f| sys_exit(f.a0) },
+    Syscall { number: 3, handler: |f| (f.a0, f.a1) = sys_get_random(f.a0, f.a1) },
+    Syscall { number: 4, handler: |f| f.a0 = sys_getc() },
+    Syscall { number: 5, handler: |f| f.a0 = sys_quote(f.a0, f.a1, f.a2) },
+    Syscall { number: 7, handler: |f| f.a0 = sys_perf_counters(f.a0) },
+    Syscall { number: 8, handler: |f| f.a0 = sys_pcr_read(f.a0, f.a1) },
+    Syscall { number: 9, handler: |f| f.a0 = sys_measure() },
+    Syscall { number: 10, handler: |f| f.a0 = sys_seal(f.a0, f.a1) },
+    Syscall { number: 11, handler: |f| f.a0 = sys_pcr_extend(f.a0, f.a1) },
+    #[cfg(feature = "nested-trap-test")]
+    Syscall { number: 12, handler: |f| f.a0 = sys_nested_test() },
+    #[cfg(feature = "app-isolation-test")]
+    Syscall { number: 13, handler: |f| f.a0 = sys_app_switch(f.a0) },
+    Syscall { number: 14, handler: |f| {
+        let t = sys_time();
+        (f.a0, f.a1) = (t as usize, (t >> 32) as usize)
+    } },
+    #[cfg(feature = "shadow-trace")]
+    Syscall { number: 15, handler: |f| f.a0 = sys_shadow_trace(f.a0, f.a1, f.a2) },
+    #[cfg(feature = "timer-upcall")]
+    Syscall { number: 19, handler: |f| f.a0 = sys_timer_upcall(f.a0, f.a1) },
+    #[cfg(feature = "ss-crosscheck")]
+    Syscall { number: 21, handler: |f| f.a0 = sys_ss_divergence(f.a0, f.a1, f.a2) },
+    Syscall { number: 22, handler: |f| f.a0 = sys_sbrk(f.a0) },
+    Syscall { number: 23, handler: |f| f.a0 = sys_dump_log() },
+    #[cfg(feature = "stack-canary")]
+    Syscall { number: 24, handler: |f| sys_stack_canary_fail(f.a0, f.a1) },
+    #[cfg(feature = "policy-seal")]
+    Syscall { number: 25, handler: |f| f.a0 = sys_seal_policy(f.a0, f.a1, f.a2, f.a3, f.a4) },
+    #[cfg(feature = "policy-seal")]
+    Syscall { number: 26, handler: |f| f.a0 = sys_unseal_policy(f.a0, f.a1, f.a2, f.a3) },
+];
+
+const _: () = assert!(
+    matches!(syscall::check(SYSCALLS), Ok(())),
+    "SYSCALLS: a number is registered twice, or has the monitor bit"
+);
+
+/// A number this build registers nothing for, for
+/// [`u_unknown_syscall_test`].
+const SYSCALL_UNUSED: u32 = syscall::past_last(SYSCALLS);
+
+/// The numbers `_trap_handler` serves itself, ahead of [`SYSCALLS`]:
+/// yield, task_spawn, task_wait, task_exit and upcall_return.
+const ASM_SYSCALLS: [u32; 5] = [6, 16, 17, 18, 20];
+
+const _: () = {
+    let mut i = 0;
+    while i < ASM_SYSCALLS.len() {
+        assert!(
+            ASM_SYSCALLS[i] < SYSCALL_UNUSED,
+            "SYSCALL_UNUSED: the asm serves that number itself"
+        );
+        i += 1;
+    }
+};
+
+/// Every service call the asm doesn't serve itself: find a7 in
+/// [`SYSCALLS`] and run its handler on the caller's `frame`.  A number
+/// nobody registered, including one whose feature is compiled out, gets
+/// [`sys_unsupported`].  Called from `_trap_handler` through
+/// `_call_m_isr`, so the frame is all the caller gets back.
+#[no_mangle]
+extern "C" fn sys_dispatch(frame: &mut TrapFrame) {
+    match syscall::lookup(SYSCALLS, frame.a7 as u32) {
+        Some(handler) => handler(frame),
+        None => frame.a0 = sys_unsupported(frame.a7),
+    }
+}
+
+/// Ecalls from S-mode or M-mode that [`trap_foreign_ecall`] rejected.
+/// Saturates: a caller stuck in a loop can't wrap it back to 0.
+static FOREIGN_ECALLS: AtomicU32 = AtomicU32::new(0);
+
+/// An ecall from S-mode (mcause 9) or M-mode (11).  The services are
+/// U-mode's.  The one exception for S-mode is the SBI `set_timer` call
+/// ([`sbi_set_timer`]), which a hosted S-mode kernel needs for its timer.
+/// Any other S-mode call gets [`SYSCALL_NOT_SUPPORTED`], which is also SBI's
+/// "not supported".  M-mode code calls the kernel directly, so an M-mode
+/// ecall is a bug and is refused with [`SYSCALL_ERR`].  Either way the
+/// caller resumes after its ecall with a warning on the console, instead
+/// of the machine halting in the unknown-trap path.
+#[no_mangle]
+extern "C" fn trap_foreign_ecall(frame: &mut TrapFrame) {
+    let mode = TrapCause::from_mcause(frame.mcause).ecall_mode();
+    if mode == Some(PrivMode::Supervisor) {
+        if let SbiCall::SetTimer(deadline) = SbiCall::decode(frame.a7, frame.a6, frame.a0, frame.a1) {
+            sbi_set_timer(deadline);
+            (frame.a0, frame.a1) = (sbi::SBI_SUCCESS, 0);
+            return;
+        }
+    }
+    let count = FOREIGN_ECALLS.load(Ordering::Relaxed).saturating_add(1);
+    FOREIGN_ECALLS.store(count, Ordering::Relaxed);
+    let _ = write!(
+        UartWriter,
+        "[WARN] ecall from {:?} mode rejected (a7 = {}, at {:#010x}, #{})\r\n",
+        mode.unwrap_or(PrivMode::Machine),
+        frame.a7,
+        frame.mepc.wrapping_sub(4),
+        count
+    );
+    frame.a0 = match mode {
+        Some(PrivMode::Supervisor) => SYSCALL_NOT_SUPPORTED,
+        _ => SYSCALL_ERR,
+    };
+}
+
+/// Issue an ecall from M-mode and check that it comes back refused with
+/// [`SYSCALL_ERR`] and resumes right after the ecall.  a7 = 1 would be
+/// `puts` from U-mode.  Nothing may run it for M-mode.
+fn check_m_ecall_rejected() {
+    let a0: usize;
+    // SAFETY: the trap handler preserves every register but a0.
+    unsafe { asm!("ecall", inout("a0") 0usize => a0, in("a7") 1usize) };
+    uart_puts(if a0 == SYSCALL_ERR {
+        "[TRAP] M-mode ecall rejected and resumed: PASS\r\n\r\n"
+    } else {
+        "[TRAP] M-mode ecall: FAIL (not rejected)\r\n\r\n"
+    });
+}
+
+// ============================================================================
+// Console Services (syscalls 0, 1, 2, 4)
+// ============================================================================
+
+/// Syscall 0: print one character on the console.
+fn sys_putc(c: usize) -> usize {
+    uart_putc(c as u8);
+    0
+}
+
+/// Syscall 1: print `len` bytes from `ptr` on the console.
+///
+/// The buffer is read with U-mode's permissions ([`uaccess`]); output
+/// stops with [`SYSCALL_ERR`] at the first byte the caller couldn't read
+/// itself.
+fn sys_puts(ptr: usize, len: usize) -> usize {
+    for i in 0..len {
+        match uaccess::read_u8(ptr.wrapping_add(i)) {
+            Some(b) => uart_putc(b),
+            None => return SYSCALL_ERR,
+        }
+    }
+    0
+}
+
+/// Syscall 4: the next received byte, or [`SYSCALL_ERR`] if there is none
+/// yet.  Never blocks: this runs with interrupts masked, so the RX
+/// interrupt couldn't deliver anything while it waited.  Callers retry
+/// (yielding in between).
+fn sys_getc() -> usize {
+    let queued = if CONSOLE_RX_IRQ.load(Ordering::Relaxed) {
+        RX_RING.with(|rx| rx.pop()).flatten()
+    } else {
+        CONSOLE.try_getc()
+    };
+    queued.map_or(SYSCALL_ERR, usize::from)
+}
+
+/// Syscall 2: stop QEMU via the test finisher ([`test_device`]): code 0
+/// passes, anything else fails with that code (its low 16 bits, or 1 if
+/// those are 0).
+///
+/// Drains the console first: the finisher ends the run immediately, and
+/// boot output may still be queued for the TX interrupt.
+fn sys_exit(code: usize) -> ! {
+    uart_flush();
+    if code == 0 {
+        // A clean shutdown: the next boot starts counting afresh.
+        BOOT_RECORD.set(BootRecord::cleared());
+        test_device::pass()
+    }
+    test_device::fail(code as u16)
+}
+
+// ============================================================================
+// Performance Counters (syscall 7)
 // ============================================================================
 
-/// Measure a firmware image (simplified stub).
-///
-/// In a real RoT, this would compute SHA-256/384 over the U-mode code region
-/// and compare against a known-good measurement stored in OTP/fuses.
-///
-/// This function demonstrates full CFI protection on an M-mode function:
-///   - Landing pad (forward-edge)
-///   - HW + SW shadow stack (backward-edge)
-#[unsafe(naked)]
-#[no_mangle]
-pub unsafe extern "C" fn rot_measure_firmware(base: u32, size: u32) -> u32 {
-    naked_asm!(
-        // Forward-edge: landing pad
-        ".4byte 0x00000017",        // lpad 0
+/// mcycle, read hi-lo-hi ([`perf::read_split`]).
+fn read_mcycle() -> u64 {
+    perf::read_split(
+        || csr::read::<{ csr::MCYCLEH }>() as u32,
+        || csr::read::<{ csr::MCYCLE }>() as u32,
+    )
+}
+
+/// Busy-wait at least `n` mcycle counts, for hardware that needs a pause
+/// between register writes.  Each read is a `csrr` in `asm!`, which the
+/// compiler can't drop or hoist, so the loop can't turn into a no-op.
+///
+/// If mcycle doesn't count (inhibited, or not implemented) the wait ends
+/// after [`perf::SPIN_STALL_LIMIT`] reads instead of hanging the boot;
+/// that many CSR reads is still a pause.
+fn delay_cycles(n: u64) {
+    let _ = perf::spin_for(n, read_mcycle);
+}
+
+/// mcycle and minstret, each read hi-lo-hi ([`perf::read_split`]).
+/// Cycles first: the instruction count includes the reads of mcycle.
+fn read_counters() -> Sample {
+    Sample {
+        cycles: read_mcycle(),
+        instret: perf::read_split(
+            || csr::read::<{ csr::MINSTRETH }>() as u32,
+            || csr::read::<{ csr::MINSTRET }>() as u32,
+        ),
+    }
+}
+
+/// Let U-mode read `cycle` and `instret` itself ([`perf::MCOUNTEREN_U`]).
+/// mcounteren is WARL: returns the bits that stuck, and U-mode falls back
+/// to syscall 7 for the rest.
+fn enable_u_counters() -> u32 {
+    // SAFETY: mcounteren only gates U-mode reads of the counters.
+    unsafe { csr::write_readback::<{ csr::MCOUNTEREN }>(perf::MCOUNTEREN_U as usize) as u32 }
+}
+
+/// Syscall 7: write a [`Sample`] of the counters to the 16 bytes at
+/// `out`, with U-mode's permissions ([`uaccess`]).  The sample is taken
+/// inside the trap, so it includes the ecall's entry path but not its
+/// return.  [`SYSCALL_ERR`] if the caller couldn't write `out` itself.
+fn sys_perf_counters(out: usize) -> usize {
+    if uaccess::copy_to_user(out, &read_counters().to_bytes()) {
+        0
+    } else {
+        SYSCALL_ERR
+    }
+}
+
+// ============================================================================
+// Randomness (syscall 3: get_random)
+// ============================================================================
+
+/// The kernel DRBG, instantiated by [`seed_drbg`] before U-mode starts.
+static DRBG: IrqCell<Option<HmacDrbg>> = IrqCell::new(None);
+
+/// Timing samples taken for the boot seed.
+const JITTER_SAMPLES: u32 = 256;
+
+/// Seed [`DRBG`] from loop jitter and the `.bss` fold `_start` took, and
+/// say how good the seed is.  The credit is crude (see [`drbg`]): it
+/// flags a seed that is obviously weak, it doesn't prove one strong.
+fn seed_drbg(sram_fold: u32, sram_varied: u32) {
+    let mut pool = SeedPool::new();
+    pool.add_sram(sram_fold, sram_varied);
+    for i in 0..JITTER_SAMPLES {
+        let start = csr::read::<{ csr::MCYCLE }>() as u32;
+        let mut x = i;
+        for _ in 0..8 {
+            x = x.rotate_left(5) ^ start;
+        }
+        core::hint::black_box(x);
+        let end = csr::read::<{ csr::MCYCLE }>() as u32;
+        pool.add_jitter(end.wrapping_sub(start));
+    }
+    let bits = pool.bits();
+    let low = pool.is_low();
+    let mut secret = device_secret();
+    let drbg = pool.instantiate(&secret);
+    secure_zero(&mut secret);
+    DRBG.with(|d| *d = Some(drbg));
+    let _ = write!(
+        UartWriter,
+        "[RNG] DRBG seeded: {} bits credited ({} from .bss)\r\n",
+        bits,
+        sram_varied.min(drbg::SRAM_FOLD_BITS)
+    );
+    if low {
+        let _ = write!(
+            UartWriter,
+            "[WARN] low boot entropy: {} bits < {}; randomness is predictable until reseeded\r\n",
+            bits,
+            drbg::MIN_SEED_BITS
+        );
+    }
+}
+
+/// Mix `extra` into the DRBG, e.g. samples from a hardware entropy
+/// source once a later stage has one.
+#[allow(dead_code)] // the virt board has no entropy source to feed it
+fn reseed(extra: &[u8]) {
+    DRBG.with(|d| {
+        if let Some(d) = d {
+            d.reseed(extra);
+        }
+    });
+}
+
+/// Syscall 3: fill `len` bytes at `buf` from the DRBG.  Returns 0 or
+/// [`SYSCALL_ERR`] for a0, and the bytes written for a1: a buffer that
+/// stops being writable part way through keeps the bytes before the fault.
+fn sys_get_random(buf: usize, len: usize) -> (usize, usize) {
+    let mut chunk = [0u8; 32];
+    let mut done = 0;
+    while done < len {
+        let n = (len - done).min(chunk.len());
+        if DRBG.with(|d| d.as_mut().map(|d| d.generate(&mut chunk[..n]))).flatten().is_none()
+            || !uaccess::copy_to_user(buf.wrapping_add(done), &chunk[..n])
+        {
+            secure_zero(&mut chunk);
+            return (SYSCALL_ERR, done);
+        }
+        done += n;
+    }
+    secure_zero(&mut chunk);
+    (0, done)
+}
+
+/// Syscall 14: the current mtime.  U-mode can't read `time` itself
+/// (mcounteren.TM stays clear), and on RV32 the value needs both return
+/// registers.
+fn sys_time() -> u64 {
+    clint::now()
+}
+
+/// The U-mode heap break ([`riscv_rot_cfi::heap`]).  No heap until
+/// [`init_u_heap`] sets it up just before launch.
+static U_HEAP: IrqCell<Break> = IrqCell::new(Break::none());
+
+/// Give U-mode its heap: the U_RAM between the linked image and the
+/// U-mode stack.  A net-loaded image lays out U_RAM itself, so the kernel
+/// can't tell which part of it is free, and gets no heap.
+fn init_u_heap() {
+    #[cfg(not(feature = "net-load"))]
+    {
+        let heap = layout::u_heap();
+        U_HEAP.with(|brk| *brk = Break::new(heap.base, heap.end()));
+        let _ = write!(
+            UartWriter,
+            "[HEAP] U-mode heap {:#010x}..{:#010x} ({} bytes), grown by sbrk (syscall 22)\r\n",
+            heap.base,
+            heap.end(),
+            heap.size
+        );
+    }
+    #[cfg(feature = "net-load")]
+    uart_puts("[HEAP] No U-mode heap: the loaded image owns U_RAM\r\n");
+}
+
+/// Syscall 22: move the U-mode heap break by `increment` (signed) and
+/// return the old break.  [`SYSCALL_ERR`] if the new break would leave the
+/// heap: below its start, or past the U-mode stack's bottom.  The break
+/// stays put then.  Application 0's only: the heap is in its part of
+/// U_RAM.
+fn sys_sbrk(increment: usize) -> usize {
+    if CURRENT_APP.load(Ordering::Relaxed) != 0 {
+        return SYSCALL_ERR;
+    }
+    U_HEAP.with(|heap| heap.sbrk(increment as isize)).flatten().unwrap_or(SYSCALL_ERR)
+}
+
+// ============================================================================
+// Attestation (syscall 5: quote)
+// ============================================================================
+
+/// Check word over U_CODE taken in Phase 3 ([`rot_measure_firmware`]),
+/// reported in every quote alongside the PCRs.
+static MEASUREMENT: AtomicU32 = AtomicU32::new(0);
+
+/// Syscall error return (`-1` in a0).
+const SYSCALL_ERR: usize = usize::MAX;
+
+/// Return for a syscall number with no service in this build (`-2` in
+/// a0), so a caller built against a newer service set can tell "not here"
+/// from "refused".
+const SYSCALL_NOT_SUPPORTED: usize = usize::MAX - 1;
+
+/// Every ecall whose a7 matches no syscall in this build, including the
+/// feature-gated ones that are compiled out.  Warns on the console and
+/// returns [`SYSCALL_NOT_SUPPORTED`].
+fn sys_unsupported(a7: usize) -> usize {
+    let _ = write!(UartWriter, "[WARN] unknown syscall {}\r\n", a7);
+    SYSCALL_NOT_SUPPORTED
+}
+
+/// Syscall 5: write a signed quote over the boot check word, a digest of
+/// the PCRs as they stand and the caller's 32-byte nonce to `out`,
+/// returning its length.
+///
+/// Signs with ECDSA P-256 under the `ecdsa-attest` feature, HMAC-SHA256
+/// otherwise (layout in [`attest`]).  Returns [`SYSCALL_ERR`] without
+/// writing anything if either buffer is outside U_RAM or `out` is too
+/// small.  Called from `_trap_handler` on the M-mode stack.
+///
+/// Both buffers are accessed with U-mode's permissions ([`uaccess`]); the
+/// quote is built in M-mode memory and only copied out once complete.
+fn sys_quote(nonce: usize, out: usize, out_len: usize) -> usize {
+    let u_ram = layout::u_ram();
+    if !u_ram.contains_range(nonce, NONCE_LEN) || !u_ram.contains_range(out, out_len) {
+        return SYSCALL_ERR;
+    }
+    #[cfg(feature = "stack-headroom")]
+    check_stack_headroom(CRYPTO_STACK);
+    let mut nonce_buf = [0u8; NONCE_LEN];
+    if !uaccess::copy_from_user(&mut nonce_buf, nonce) {
+        return SYSCALL_ERR;
+    }
+    let measurement = MEASUREMENT.load(Ordering::Relaxed);
+    let Some(pcrs) = MEASUREMENT_LOG.with(|log| log.pcr_digest()) else {
+        return SYSCALL_ERR;
+    };
+    let mut quote = [0u8; QUOTE_MAX];
+    let buf = &mut quote[..out_len.min(QUOTE_MAX)];
+
+    let mut secret = device_secret();
+    #[cfg(feature = "ecdsa-attest")]
+    let len = attest::ecdsa_quote(&attest::ecdsa_quote_key(&secret), measurement, &pcrs.0, &nonce_buf, buf);
+    #[cfg(not(feature = "ecdsa-attest"))]
+    let len = {
+        let mut key = attest::hmac_quote_key(&secret);
+        let len = attest::hmac_quote(&key, measurement, &pcrs.0, &nonce_buf, buf);
+        secure_zero(&mut key);
+        len
+    };
+    secure_zero(&mut secret);
+
+    match len {
+        Some(len) if uaccess::copy_to_user(out, &quote[..len]) => len,
+        _ => SYSCALL_ERR,
+    }
+}
+
+/// The device key, from [`keyslot::SLOT_ATTEST`]: what quote keys,
+/// session keys, the DRBG and the monitor token are derived from.
+/// [`provision_device_key`] writes it before anything reads it, and only
+/// M-mode may write that slot.
+fn device_secret() -> [u8; KEY_LEN] {
+    match KEY_SLOTS.with(|slots| slots.key(keyslot::SLOT_ATTEST).copied()) {
+        Some(Some(key)) => key,
+        _ => panic!("device key read before it was provisioned"),
+    }
+}
+
+/// Read the device key out of OTP.
+///
+/// Stub: QEMU's virt machine has no fuses, so this returns
+/// [`OTP_DEVICE_KEY`].  A real part reads its fuse bank here; nothing
+/// else in the kernel would change.
+fn otp_device_key() -> [u8; KEY_LEN] {
+    OTP_DEVICE_KEY
+}
+
+/// Write the device key from OTP into [`keyslot::SLOT_ATTEST`], first
+/// thing at boot: the slot is blank after every cold boot, and U-mode
+/// may never write it ([`mon_provision_key`]).
+fn provision_device_key() {
+    let mut key = otp_device_key();
+    let written = KEY_SLOTS.with(|slots| slots.provision(keyslot::SLOT_ATTEST, &key));
+    secure_zero(&mut key);
+    if written != Some(Ok(())) {
+        panic!("device key slot refused the OTP key");
+    }
+    uart_puts("[KEYS] Device key provisioned from OTP into slot 0\r\n");
+}
+
+/// Largest quote any build produces.
+const QUOTE_MAX: usize = attest::QuoteAlg::EcdsaP256.quote_len();
+
+/// Syscall 9: the boot-time check word over U_CODE, the XOR of its words
+/// ([`rot_measure_firmware`]).  The SHA-256 measurement is in
+/// [`PCR_FIRMWARE`].
+fn sys_measure() -> usize {
+    MEASUREMENT.load(Ordering::Relaxed) as usize
+}
+
+/// Syscall 10: seal `data` under RoT key `key_id` ([`rot_seal_secret`]).
+fn sys_seal(data: usize, key_id: usize) -> usize {
+    // SAFETY: rot_seal_secret only needs the M-mode SW shadow stack in
+    // SW_SS_REG, which _call_m_isr sets up.
+    unsafe { rot_seal_secret(data as u32, key_id as u32) as usize }
+}
+
+/// The boot measurement log and PCR bank.  Filled in Phase 3, which then
+/// locks the boot PCRs; U-mode extends the runtime ones by syscall.
+static MEASUREMENT_LOG: IrqCell<MeasurementLog> = IrqCell::new(MeasurementLog::new());
+
+/// Syscall 11: extend runtime PCR `pcr` with the 32-byte digest at
+/// `digest`.  [`SYSCALL_ERR`] if the PCR is locked (the boot PCRs always
+/// are), out of range, or the digest isn't readable by the caller.
+fn sys_pcr_extend(pcr: usize, digest: usize) -> usize {
+    let mut buf = [0u8; 32];
+    if pcr >= PCR_COUNT || !uaccess::copy_from_user(&mut buf, digest) {
+        return SYSCALL_ERR;
+    }
+    match MEASUREMENT_LOG.with(|log| log.extend(pcr as u8, Digest(buf), "U-mode")) {
+        Some(Ok(())) => 0,
+        _ => SYSCALL_ERR,
+    }
+}
+
+/// Syscall 23: print the measurement log as it stands, runtime extends
+/// included, with [`dump_log`].  Returns the number of entries.
+fn sys_dump_log() -> usize {
+    dump_log().unwrap_or(SYSCALL_ERR)
+}
+
+/// Syscall 8: copy PCR `pcr`'s 32-byte value to `out`.  Read-only, so
+/// every PCR can be read, locked or not.  [`SYSCALL_ERR`] if the PCR is out
+/// of range or `out` isn't writable by the caller.
+fn sys_pcr_read(pcr: usize, out: usize) -> usize {
+    if pcr >= PCR_COUNT {
+        return SYSCALL_ERR;
+    }
+    match MEASUREMENT_LOG.with(|log| log.pcr(pcr as u8).copied()) {
+        Some(Some(value)) if uaccess::copy_to_user(out, value.as_bytes()) => 0,
+        _ => SYSCALL_ERR,
+    }
+}
+
+/// Spins [`sys_nested_test`] waits for its timer interrupt before giving
+/// up — far longer than an already-expired deadline takes to fire.
+#[cfg(feature = "nested-trap-test")]
+const NESTED_TEST_SPINS: u32 = 1_000_000;
+
+/// Syscall 12 (`nested-trap-test` builds): take a timer interrupt inside
+/// this service.  Arms an already-expired deadline, opens the interrupt
+/// window and waits for [`irq_timer`] to run on top of the ecall's frame.
+///
+/// Returns bit 0 = the interrupt was taken, bit 1 = it overwrote the mepc
+/// CSR (so the ecall only resumes correctly from its frame copy); 3 is a
+/// pass.  U-mode then checks it came back to the right place, in U-mode.
+#[cfg(feature = "nested-trap-test")]
+fn sys_nested_test() -> usize {
+    let mepc = csr::read::<{ csr::MEPC }>();
+    let ticks = TIMER_TICKS.load(Ordering::Relaxed);
+    clint::arm(clint::now());
+    enable_irq_source(IrqSource::Timer);
+    enable_interrupts();
+    let mut spins = 0;
+    while TIMER_TICKS.load(Ordering::Relaxed) == ticks && spins < NESTED_TEST_SPINS {
+        spins += 1;
+        core::hint::spin_loop();
+    }
+    disable_interrupts();
+    disable_irq_source(IrqSource::Timer);
+    clint::disarm();
+    let taken = TIMER_TICKS.load(Ordering::Relaxed) != ticks;
+    let clobbered = csr::read::<{ csr::MEPC }>() != mepc;
+    taken as usize | (clobbered as usize) << 1
+}
+
+// ============================================================================
+// Monitor Calls (a7 bit 31)
+// ============================================================================
+
+/// Capability token for monitor calls, generated just before launch and
+/// handed to U-mode in a0.  Zero until then, which matches nothing.
+static MONITOR_TOKEN: AtomicU32 = AtomicU32::new(0);
+
+/// Monitor-call handlers, indexed by monitor number.  Each takes the
+/// call's a0 and a2.
+static MONITOR_CALLS: &[fn(usize, usize) -> usize] = &[
+    mon_lock_pcr,      // monitor::LOCK_PCR
+    mon_provision_key, // monitor::PROVISION_KEY
+    #[cfg(feature = "ecall-audit")]
+    mon_dump_audit, // monitor::DUMP_AUDIT
+];
+
+/// Every ecall with a7 bit 31 set ([`monitor`]): check the token in a1,
+/// then dispatch on a7[30:0] through [`MONITOR_CALLS`].  [`SYSCALL_ERR`]
+/// for a wrong token or an unknown number — the token is checked first, so
+/// a caller without it can't even probe which numbers exist.
+#[no_mangle]
+extern "C" fn sys_monitor(arg: usize, token: usize, a7: usize, arg2: usize) -> usize {
+    if !monitor::token_matches(token as u32, MONITOR_TOKEN.load(Ordering::Relaxed)) {
+        return SYSCALL_ERR;
+    }
+    let handler = monitor::monitor_number(a7 as u32).and_then(|n| MONITOR_CALLS.get(n as usize));
+    match handler {
+        Some(handler) => handler(arg, arg2),
+        None => SYSCALL_ERR,
+    }
+}
+
+/// Monitor call [`monitor::LOCK_PCR`]: lock PCR `pcr` against further
+/// extends for the rest of the boot.
+fn mon_lock_pcr(pcr: usize, _: usize) -> usize {
+    if pcr >= PCR_COUNT {
+        return SYSCALL_ERR;
+    }
+    match MEASUREMENT_LOG.with(|log| log.lock(pcr as u8)) {
+        Some(Ok(())) => 0,
+        _ => SYSCALL_ERR,
+    }
+}
+
+/// The device key slots ([`keyslot`]).  Their own input section within
+/// `.bss`, so the map shows them in M_RAM, which U-mode's PMP entries
+/// don't cover.  Blank at every cold boot: this stands in for OTP, which
+/// a real device would program once at manufacturing.
+#[link_section = ".bss.m_keyslots"]
+static KEY_SLOTS: IrqCell<KeySlots> = IrqCell::new(KeySlots::new());
+
+/// Monitor call [`monitor::PROVISION_KEY`]: copy the key at `key` (read
+/// with U-mode's permissions) into key slot `slot`.  Refused if the slot
+/// is out of range, already written or [`keyslot::SLOT_ATTEST`], or the
+/// key isn't readable by the caller.
+fn mon_provision_key(slot: usize, key: usize) -> usize {
+    let mut buf = [0u8; KEY_LEN];
+    let ret = if !uaccess::copy_from_user(&mut buf, key) {
+        SYSCALL_ERR
+    } else {
+        match KEY_SLOTS.with(|slots| slots.provision_user(slot, &buf)) {
+            Some(Ok(())) => 0,
+            _ => SYSCALL_ERR,
+        }
+    };
+    secure_zero(&mut buf);
+    ret
+}
+
+/// Records in [`ECALL_AUDIT`].
+#[cfg(feature = "ecall-audit")]
+const AUDIT_LEN: usize = 32;
+
+/// The ecall audit trail ([`riscv_rot_cfi::audit`]).  In `.bss`, so in
+/// M_RAM, which no PMP entry grants U-mode: [`check_audit_log_denied`]
+/// confirms that at boot, and `u_audit_test` that the dump can't be
+/// pointed at it.
+#[cfg(feature = "ecall-audit")]
+static ECALL_AUDIT: IrqCell<AuditLog<AUDIT_LEN>> = IrqCell::new(AuditLog::new());
+
+/// Record a U-mode ecall in [`ECALL_AUDIT`].  Called from `_handle_ecall`
+/// for every one, after mepc is advanced and before it is dispatched, so
+/// the record's a7 and a0 are what the caller passed.
+#[cfg(feature = "ecall-audit")]
+#[no_mangle]
+extern "C" fn audit_ecall(frame: &TrapFrame) {
+    let time = csr::read::<{ csr::MCYCLE }>() as u32;
+    ECALL_AUDIT.with(|log| log.record(frame.a7 as u32, frame.a0 as u32, time));
+}
+
+/// Monitor call [`monitor::DUMP_AUDIT`]: print the latest `max` records
+/// of [`ECALL_AUDIT`], oldest first, this call's own included.  If `buf`
+/// isn't 0, they are first copied there as [`EcallRecord::to_bytes`]
+/// (written with U-mode's permissions); nothing is printed if that fails.
+/// Returns how many records there were.
+#[cfg(feature = "ecall-audit")]
+fn mon_dump_audit(buf: usize, max: usize) -> usize {
+    let mut records = [EcallRecord::default(); AUDIT_LEN];
+    let Some((held, recorded)) = ECALL_AUDIT.with(|log| {
+        let mut held = 0;
+        for (slot, record) in records.iter_mut().zip(log.latest(max)) {
+            *slot = record;
+            held += 1;
+        }
+        (held, log.recorded())
+    }) else {
+        return SYSCALL_ERR;
+    };
+    let records = &records[..held];
+    if buf != 0
+        && !records
+            .iter()
+            .enumerate()
+            .all(|(i, r)| uaccess::copy_to_user(buf.wrapping_add(i * EcallRecord::LEN), &r.to_bytes()))
+    {
+        return SYSCALL_ERR;
+    }
+    let _ = write!(UartWriter, "[AUDIT] Latest {} of {} ecalls:\r\n", held, recorded);
+    for r in records {
+        let _ = write!(
+            UartWriter,
+            "[AUDIT]   #{} a7={:#x} a0={:#010x} mcycle={:#010x}\r\n",
+            r.seq, r.number, r.arg0, r.time
+        );
+    }
+    held
+}
+
+/// Check that U-mode can't reach [`ECALL_AUDIT`]: for each of its words,
+/// the PMP entry that decides an access, if any, grants nothing.  Stops the boot if
+/// one does, since U-mode could then rewrite its own trail.
+#[cfg(feature = "ecall-audit")]
+fn check_audit_log_denied() {
+    let start = &ECALL_AUDIT as *const _ as usize;
+    let end = start + size_of_val(&ECALL_AUDIT);
+    let _ = write!(UartWriter, "[AUDIT] Ecall audit log: {} records at {:#010x}..{:#010x}", AUDIT_LEN, start, end);
+    let granted = (start..end).step_by(4).find_map(|addr| {
+        BOOT_PMP
+            .matching_entry(addr as u64)
+            .filter(|e| e.cfg.readable() || e.cfg.writable() || e.cfg.executable())
+    });
+    if let Some(entry) = granted {
+        let _ = write!(UartWriter, " — FAIL: PMP entry {} grants U {}\r\n", entry.index, entry.cfg);
+        panic!("ecall audit log is U-accessible");
+    }
+    uart_puts(" — M-mode only\r\n");
+}
+
+/// Generate [`MONITOR_TOKEN`]: derived from the device secret and the
+/// cycle count at this point of the boot, never zero.
+///
+/// QEMU's cycle counter makes this repeatable from run to run; a real
+/// device would draw it from its TRNG.
+fn generate_monitor_token() -> u32 {
+    let cycles = csr::read::<{ csr::MCYCLE }>() as u32;
+    let mut secret = device_secret();
+    let mut okm = hkdf_sha256(&cycles.to_le_bytes(), &secret, b"rot monitor token");
+    secure_zero(&mut secret);
+    let token = u32::from_le_bytes([okm[0], okm[1], okm[2], okm[3]]).max(1);
+    secure_zero(&mut okm);
+    MONITOR_TOKEN.store(token, Ordering::Relaxed);
+    token
+}
+
+/// Oldest firmware version the RoT will launch.  Stands in for a fused
+/// monotonic counter, which a real device would bump (irreversibly) when
+/// a security fix ships.
+const MIN_FIRMWARE_VERSION: u32 = 1;
+
+/// Parse the firmware header at the start of U_CODE and halt unless it is
+/// well-formed, its version is at least [`MIN_FIRMWARE_VERSION`] — a
+/// downgraded image is never launched — and the image holds exactly the
+/// landing pads the header declares.
+fn verify_firmware_header() {
+    match firmware::verify(u_code(), MIN_FIRMWARE_VERSION) {
+        Ok(h) => {
+            let _ = write!(
+                UartWriter,
+                "[MEASURE] Firmware header: version {} (min {}), {} bytes, {} landing pads — OK\r\n",
+                h.version, MIN_FIRMWARE_VERSION, h.length, h.lpad_count
+            );
+        }
+        Err(e) => {
+            let _ = write!(UartWriter, "[MEASURE] Firmware header: FAIL: {}\r\n", e);
+            panic!("firmware image refused");
+        }
+    }
+}
+
+/// PMP entry covering U_CODE.
+const PMP_ENTRY_U_CODE: usize = 3;
+
+/// U_CODE as linked (`_u_code_start` .. `_u_code_end`, `memory.x`): what
+/// the firmware header is parsed from and the measurement covers.
+fn u_code() -> &'static [u8] {
+    let code = layout::u_code();
+    // SAFETY: U_CODE is mapped and readable from M-mode (unlocked PMP
+    // entry), and nothing writes it after the image is loaded.
+    unsafe { core::slice::from_raw_parts(code.base as *const u8, code.size) }
+}
+
+/// Read back U_CODE's PMP entry and halt unless it grants exactly R+X,
+/// over exactly the linked U_CODE region.
+///
+/// Belt and braces on top of the W^X layout in [`configure_pmp`]: checks
+/// what the hardware actually holds, so an edit that makes U-mode code
+/// writable (a code-injection path) stops the boot before anything is
+/// measured or launched.  The range check ties the measured bytes to the
+/// one region U-mode can execute: if `memory.x` and the PMP setup
+/// disagree, the measurement would miss code or read past it.
+fn verify_u_code_pmp() {
+    let pmpcfg0 = csr::read::<{ csr::PMPCFG0 }>() as u32;
+    let cfg = PmpCfg::from_regs(&[pmpcfg0], PMP_ENTRY_U_CODE).unwrap_or(PmpCfg(0));
+    let _ = write!(UartWriter, "[MEASURE] U_CODE PMP entry {}: {}", PMP_ENTRY_U_CODE, cfg);
+    if !cfg.is_rx_only() {
+        uart_puts(" — FAIL: expected R-X (no W)\r\n");
+        panic!("U_CODE PMP entry is not R-X");
+    }
+    uart_puts(" — OK (W^X)\r\n");
+
+    let code = layout::u_code();
+    let linked = (code.base as u64, code.size as u64);
+    let pmp = cfg.region(csr::read::<{ csr::PMPADDR3 }>() as u32, csr::read::<{ csr::PMPADDR2 }>() as u32);
+    let _ = write!(UartWriter, "[MEASURE] U_CODE linked {:#010x}..{:#010x}", code.base, code.end());
+    if pmp != Some(linked) {
+        let (base, size) = pmp.unwrap_or((0, 0));
+        let _ = write!(UartWriter, " — FAIL: PMP entry covers {:#010x} +{:#x}\r\n", base, size);
+        panic!("U_CODE PMP entry does not match the linked region");
+    }
+    uart_puts(" — matches PMP entry\r\n");
+}
+
+/// Digests print as eight space-separated 4-byte groups.
+const DIGEST_LAYOUT: HexLayout = HexLayout::grouped(4);
+
+/// Self-measurement: SHA-256 the whole ROM into [`PCR_ROM`], the first
+/// link of the chain, before anything else is measured.
+///
+/// A stage can't vouch for itself — corrupted code can lie about its own
+/// hash — so on a real device the immutable boot ROM below this stage
+/// would take this measurement before jumping here.  Doing it ourselves
+/// at least puts the RoT image on record.
+fn measure_rom(log: &mut MeasurementLog) {
+    use riscv_rot_cfi::memory_map::ROM;
+
+    // SAFETY: the ROM is mapped and readable (PMP entry 0 is locked R-X,
+    // which allows M-mode loads); nothing can write it.
+    let rom = unsafe { core::slice::from_raw_parts(ROM.base as *const u8, ROM.size) };
+    let digest = sha256(rom);
+    let _ = write!(
+        UartWriter,
+        "[MEASURE] ROM self-measurement (SHA-256, {}K @ {:#010x}):\r\n  ",
+        ROM.size / 1024,
+        ROM.base
+    );
+    uart_put_hex_bytes(&digest, DIGEST_LAYOUT.wrapped(16, "  "));
+    uart_puts("\r\n  (On a real device the immutable boot ROM below us measures this)\r\n");
+    if log.extend(PCR_ROM, Digest(digest), "ROM").is_err() {
+        uart_puts("  WARNING: measurement log full, ROM not recorded\r\n");
+    }
+}
+
+/// `measure-rodata` builds: SHA-256 U-mode's read-only data
+/// ([`layout::u_rodata`]) into [`PCR_RODATA`].  Its tables and strings
+/// steer the firmware as much as its code does.  The stack canary, new
+/// every boot, is hashed as zero.  False if the log is full.
+#[cfg(feature = "measure-rodata")]
+fn measure_u_rodata(log: &mut MeasurementLog) -> bool {
+    let rodata = layout::u_rodata();
+    // SAFETY: U_RODATA is mapped and readable from M-mode, and U-mode,
+    // the only other user, hasn't started.
+    let bytes = unsafe { core::slice::from_raw_parts(rodata.base as *const u8, rodata.size) };
+    #[cfg(feature = "stack-canary")]
+    let hole = {
+        let at = core::ptr::addr_of!(U_STACK_CANARY) as usize;
+        at.wrapping_sub(rodata.base)..at.wrapping_sub(rodata.base) + 4
+    };
+    #[cfg(not(feature = "stack-canary"))]
+    let hole = 0..0;
+    let digest = digest_with_hole::<Sha256, DIGEST_LEN>(bytes, hole);
+    let _ = write!(
+        UartWriter,
+        "[MEASURE] U_RODATA {:#010x}..{:#010x} (SHA-256):\r\n  ",
+        rodata.base,
+        rodata.end()
+    );
+    uart_put_hex_bytes(&digest.0, DIGEST_LAYOUT.wrapped(16, "  "));
+    uart_puts("\r\n  (Real RoT would compare against its own golden hash)\r\n");
+    if log.extend(PCR_RODATA, digest, "U_RODATA").is_err() {
+        uart_puts("  WARNING: measurement log full, U_RODATA not recorded\r\n");
+        return false;
+    }
+    true
+}
+
+/// Page size of the per-page U_CODE measurement.
+#[cfg(feature = "page-measure")]
+const MEASURE_PAGE_SIZE: usize = 4096;
+
+/// Enough page digests for all of U_CODE (128K).
+#[cfg(feature = "page-measure")]
+const U_CODE_PAGES: usize = 128 * 1024 / MEASURE_PAGE_SIZE;
+
+/// U_CODE's per-page digests from the boot measurement.  Static rather
+/// than on the 4K M-mode stack.
+#[cfg(feature = "page-measure")]
+static U_CODE_PAGE_DIGESTS: IrqCell<[Digest<DIGEST_LEN>; U_CODE_PAGES]> =
+    IrqCell::new([Digest::ZERO; U_CODE_PAGES]);
+
+/// Measure U_CODE page by page ([`pages`]), print each page's digest and
+/// extend their Merkle root into PCR_FIRMWARE after the whole-image
+/// digest.  A verifier holding the golden image's page digests can then
+/// tell which pages changed, not just that something did.
+#[cfg(feature = "page-measure")]
+fn measure_u_code_pages(log: &mut MeasurementLog) {
+    let code = u_code();
+    U_CODE_PAGE_DIGESTS.with(|digests| {
+        let root = match pages::measure_pages::<Sha256, DIGEST_LEN>(code, MEASURE_PAGE_SIZE, digests) {
+            Ok(root) => root,
+            Err(e) => {
+                let _ = write!(UartWriter, "  WARNING: per-page measurement failed ({:?})\r\n", e);
+                return;
+            }
+        };
+        let _ = write!(
+            UartWriter,
+            "[MEASURE] U_CODE per-page digests ({}K pages, RFC 6962 leaves):\r\n",
+            MEASURE_PAGE_SIZE / 1024
+        );
+        let count = pages::page_count(code.len(), MEASURE_PAGE_SIZE);
+        for (i, digest) in digests[..count].iter().enumerate() {
+            let _ = write!(UartWriter, "  page {:2} @ {:#010x}: ", i, code.as_ptr() as usize + i * MEASURE_PAGE_SIZE);
+            uart_put_hex_bytes(digest.as_bytes(), DIGEST_LAYOUT);
+            uart_newline();
+        }
+        if log.extend(PCR_FIRMWARE, root, "U_CODE page root").is_err() {
+            uart_puts("  WARNING: measurement log full, page root not recorded\r\n");
+        }
+    });
+}
+
+/// Print each log entry and the resulting PCR values.
+fn report_measurement_log(log: &MeasurementLog) {
+    uart_puts("[MEASURE] Measurement log (SHA-256, extended into PCRs):\r\n");
+    for e in log.entries() {
+        let _ = write!(UartWriter, "  PCR{} <- ", e.pcr);
+        uart_put_hex_bytes(e.digest.as_bytes(), DIGEST_LAYOUT);
+        let _ = write!(UartWriter, "  {}\r\n", e.desc);
+    }
+    for i in 0..PCR_COUNT as u8 {
+        let Some(pcr) = log.pcr(i) else { continue };
+        if *pcr == Digest::ZERO {
+            continue;
+        }
+        let _ = write!(UartWriter, "  PCR{} =  ", i);
+        uart_put_hex_bytes(pcr.as_bytes(), DIGEST_LAYOUT);
+        uart_newline();
+    }
+    uart_newline();
+}
+
+/// Print the measurement log in its `MLOG` text form (see the
+/// [`measure`](riscv_rot_cfi::measure) docs), for a host verifier reading
+/// the console.  Its `MLOG TLV` line carries the same records as the
+/// `secure-session` log frame.  Returns the number of entries, or `None`
+/// if the log is held further up the stack.
+fn dump_log() -> Option<usize> {
+    MEASUREMENT_LOG.with(|log| {
+        let _ = log.write_text(&mut UartWriter);
+        log.entries().len()
+    })
+}
+
+/// Report how quotes will be signed (and, for ECDSA, the key to verify with).
+fn report_quote_signer() {
+    #[cfg(feature = "ecdsa-attest")]
+    {
+        let mut secret = device_secret();
+        let pk = attest::ecdsa_quote_key(&secret).public_key().to_sec1();
+        secure_zero(&mut secret);
+        uart_puts("[ATTEST] Quotes signed with ECDSA P-256; device public key:\r\n  ");
+        // Unbroken, so it can be pasted straight into a verifier.
+        uart_put_hex_bytes(&pk, HexLayout::PLAIN);
+        uart_puts("\r\n\r\n");
+    }
+    #[cfg(not(feature = "ecdsa-attest"))]
+    uart_puts("[ATTEST] Quotes signed with HMAC-SHA256 (verifier shares the derived key)\r\n\r\n");
+}
+
+// ============================================================================
+// PMP Configuration
+// ============================================================================
+
+/// The PMP configuration [`configure_pmp`] programs at boot, computed at
+/// compile time ([`riscv_rot_cfi::pmp_map`], which lists the entries).
+///
+/// RISC-V PMP rules (RV32, 16 entries available):
+///   - Entries are checked in priority order (0 = highest)
+///   - First matching entry wins
+///   - M-mode bypasses PMP unless the entry is Locked (L bit)
+///   - With no matching entry: M-mode = full access, U-mode = no access
+///
+/// Our strategy:
+///   - Lock M-mode code as RX (prevents runtime code injection into RoT)
+///   - Leave M-mode data/shadow stacks unlocked (M-mode needs RW, U-mode
+///     gets no access by default since no PMP entry grants it)
+///   - Grant U-mode specific permissions via unlocked entries, application
+///     0's in entries 3-6 (see [`switch_to_app`])
+///
+/// On real Zicfiss hardware, the HW shadow stack pages would have the SS
+/// PTE attribute so only sspush/sspop can write them.  With PMP-only (no
+/// MMU), we grant RW and rely on spatial isolation + CFI enforcement.
+const BOOT_PMP: PmpConfig = compute_pmp_config(cfg!(feature = "quarantine-policy"));
+
+/// Establish memory isolation: program [`BOOT_PMP`].  `pmp-dry-run`
+/// builds print the values instead of writing them, and stop the boot.
+fn configure_pmp() {
+    uart_puts("[PMP] Configuring Physical Memory Protection...\r\n");
+    if cfg!(feature = "pmp-dry-run") {
+        uart_puts("[PMP] Dry run: computed values, no CSR written:\r\n");
+        print_pmp_config(&BOOT_PMP);
+        uart_puts("[PMP] Dry run complete, halting before launch.\r\n");
+        uart_flush();
+        test_device::pass();
+    }
+    apply_pmp_config(&BOOT_PMP);
+    // BOOT_PMP already holds application 0's entries.  Going through
+    // switch_to_app as well records it as the current application the
+    // way every later switch does, and nothing has locked an application
+    // entry yet, so this can't fail.
+    if switch_to_app(0).is_err() {
+        panic!("application PMP entries locked");
+    }
+
+    // Report what was programmed, from the same data
+    print_pmp_config(&BOOT_PMP);
+    uart_puts("[PMP] Configuration complete.\r\n\r\n");
+}
+
+/// Write every register of `config`: the addresses first, then the config
+/// bytes that enable them.  Only for boot, before anything is locked but
+/// what `config` locks itself; later changes go through
+/// [`program_pmp_entries`].
+fn apply_pmp_config(config: &PmpConfig) {
+    for (entry, &pmpaddr) in config.pmpaddr.iter().enumerate() {
+        write_pmpaddr(entry, pmpaddr);
+    }
+    // SAFETY: the one locked entry in the boot configuration (ROM, R-X)
+    // grants M-mode what it executes; every other entry is unlocked, so
+    // M-mode keeps full access to the rest.
+    unsafe {
+        csr::write::<{ csr::PMPCFG0 }>(config.pmpcfg[0] as usize);
+        csr::write::<{ csr::PMPCFG1 }>(config.pmpcfg[1] as usize);
+        csr::write::<{ csr::PMPCFG2 }>(config.pmpcfg[2] as usize);
+        csr::write::<{ csr::PMPCFG3 }>(config.pmpcfg[3] as usize);
+    }
+}
+
+/// Print each entry of `config` that isn't OFF (mode, permissions, lock
+/// bit, decoded range and raw pmpaddr), then the pmpcfg words.  OFF
+/// entries are only counted.
+fn print_pmp_config(config: &PmpConfig) {
+    let mut off = 0;
+    for i in 0..PMP_ENTRY_COUNT {
+        match config.region(i) {
+            Some((base, size)) => {
+                let _ = write!(
+                    UartWriter,
+                    "  pmp{}: {} {:#010x} +{:#x} (pmpaddr {:#010x})\r\n",
+                    i,
+                    config.entry(i).cfg,
+                    base,
+                    size,
+                    config.pmpaddr[i]
+                );
+            }
+            None => off += 1,
+        }
+    }
+    let [c0, c1, c2, c3] = config.pmpcfg;
+    let _ = write!(
+        UartWriter,
+        "  pmpcfg0-3: {:#010x} {:#010x} {:#010x} {:#010x}\r\n  ({} of {} entries OFF)\r\n",
+        c0, c1, c2, c3, off, PMP_ENTRY_COUNT
+    );
+}
+
+/// pmpcfg0-3.
+fn read_pmpcfgs() -> [u32; 4] {
+    [
+        csr::read::<{ csr::PMPCFG0 }>(),
+        csr::read::<{ csr::PMPCFG1 }>(),
+        csr::read::<{ csr::PMPCFG2 }>(),
+        csr::read::<{ csr::PMPCFG3 }>(),
+    ]
+    .map(|v| v as u32)
+}
+
+/// Program `entries` — pmpaddr and config byte each — all or nothing.
+///
+/// Writes to a locked entry are ignored, so if one of `entries` is locked,
+/// or its pmpaddr is (the lower bound of a locked TOR entry above it),
+/// nothing is written and that entry is returned.  Address registers go
+/// in before the config bytes that enable them, with interrupts masked so
+/// no handler sees a half-written set.
+///
+/// Takes NAPOT entries ([`Napot`]) and TOR pairs ([`pmp_tor`]) alike.
+///
+/// [`pmp_tor`]: riscv_rot_cfi::pmp::pmp_tor
+fn program_pmp_entries(entries: &[PmpEntry]) -> Result<(), usize> {
+    critical_section(|| {
+        let mut cfgs = read_pmpcfgs();
+        let cfg = |cfgs: &[u32; 4], i| PmpCfg::from_regs(cfgs, i);
+        for e in entries {
+            if cfg(&cfgs, e.index).is_some_and(PmpCfg::locked)
+                || cfg(&cfgs, e.index + 1).is_some_and(PmpCfg::locks_previous_addr)
+            {
+                return Err(e.index);
+            }
+        }
+        for e in entries {
+            write_pmpaddr(e.index, e.pmpaddr);
+            cfgs[e.index / 4] = with_cfg(cfgs[e.index / 4], e.index, e.cfg);
+        }
+        // SAFETY: the entries written are unlocked, so they don't apply
+        // to M-mode and can't cut off the kernel; every other byte is
+        // written back as it was read.
+        unsafe {
+            csr::write::<{ csr::PMPCFG0 }>(cfgs[0] as usize);
+            csr::write::<{ csr::PMPCFG1 }>(cfgs[1] as usize);
+            csr::write::<{ csr::PMPCFG2 }>(cfgs[2] as usize);
+            csr::write::<{ csr::PMPCFG3 }>(cfgs[3] as usize);
+        }
+        Ok(())
+    })
+}
+
+/// `lock-u-pmp` builds: the U-mode entries [`lock_u_pmp`] locks.  The
+/// shared ones (UART, stack guard, upper U_RAM, U_RECOVERY) never change
+/// after [`configure_pmp`].  The application entries are only locked when
+/// nothing reprograms them after launch: a single application, and no
+/// quarantine.
+#[cfg(feature = "lock-u-pmp")]
+const U_PMP_LOCKED: &[usize] = if APPS.len() == 1 && !cfg!(feature = "quarantine-policy") {
+    &[3, 4, 5, 6, 7, 8, 9, 10]
+} else {
+    &[7, 8, 9, 10]
+};
+
+/// Set the lock bit on the [`U_PMP_LOCKED`] entries that are in use, once
+/// the PMP configuration is final (`lock-u-pmp` builds), and halt unless
+/// the hardware kept it.
+///
+/// A locked entry can't be rewritten before the next reset, not even by
+/// M-mode, so a compromised M-mode path can no longer widen what U-mode
+/// reaches.  The price is that the entry now applies to M-mode as well:
+/// M-mode keeps exactly the access U-mode has.  That is enough for the
+/// kernel after launch: it reads user buffers through [`uaccess`], with
+/// U-mode permissions anyway, and the only direct writes into U-mode
+/// memory (net-load's image) are done by then.  M-mode loses write access
+/// to U_CODE and U_RODATA and execute on U_RAM, and faults on the stack
+/// guard page, like U-mode.
+#[cfg(feature = "lock-u-pmp")]
+fn lock_u_pmp() {
+    let cfg = |cfgs: &[u32; 4], i| PmpCfg::from_regs(cfgs, i).unwrap_or(PmpCfg(0));
+    let in_use = |c: PmpCfg| c.region(0, 0).is_some();
+    critical_section(|| {
+        let mut cfgs = read_pmpcfgs();
+        for &i in U_PMP_LOCKED {
+            let c = cfg(&cfgs, i);
+            if in_use(c) {
+                cfgs[i / 4] = with_cfg(cfgs[i / 4], i, c.lock());
+            }
+        }
+        // SAFETY: only lock bits change, and each entry locked grants
+        // M-mode what it still needs (see above); locked entries 0 and 15
+        // ignore the write.
+        unsafe {
+            csr::write::<{ csr::PMPCFG0 }>(cfgs[0] as usize);
+            csr::write::<{ csr::PMPCFG1 }>(cfgs[1] as usize);
+            csr::write::<{ csr::PMPCFG2 }>(cfgs[2] as usize);
+            csr::write::<{ csr::PMPCFG3 }>(cfgs[3] as usize);
+        }
+    });
+
+    let cfgs = read_pmpcfgs();
+    uart_puts("[PMP] Locked U-mode entries:");
+    for &i in U_PMP_LOCKED {
+        let c = cfg(&cfgs, i);
+        if !in_use(c) {
+            continue;
+        }
+        let _ = write!(UartWriter, " {}", i);
+        if !c.locked() {
+            uart_puts(" — FAIL: lock bit did not stick\r\n");
+            panic!("PMP entry not locked");
+        }
+    }
+    uart_puts(" (M-mode bound by them too)\r\n");
+}
+
+/// Print every PMP entry as the hardware holds it: mode, permissions,
+/// lock bit and the decoded address range — ground truth to hold against
+/// [`configure_pmp`]'s own listing when a region isn't isolating as
+/// expected.  OFF entries are only counted.
+fn dump_pmp() {
+    let pmpaddr = [
+        csr::read::<{ csr::PMPADDR0 }>(),
+        csr::read::<{ csr::PMPADDR1 }>(),
+        csr::read::<{ csr::PMPADDR2 }>(),
+        csr::read::<{ csr::PMPADDR3 }>(),
+        csr::read::<{ csr::PMPADDR4 }>(),
+        csr::read::<{ csr::PMPADDR5 }>(),
+        csr::read::<{ csr::PMPADDR6 }>(),
+        csr::read::<{ csr::PMPADDR7 }>(),
+        csr::read::<{ csr::PMPADDR8 }>(),
+        csr::read::<{ csr::PMPADDR9 }>(),
+        csr::read::<{ csr::PMPADDR10 }>(),
+        csr::read::<{ csr::PMPADDR11 }>(),
+        csr::read::<{ csr::PMPADDR12 }>(),
+        csr::read::<{ csr::PMPADDR13 }>(),
+        csr::read::<{ csr::PMPADDR14 }>(),
+        csr::read::<{ csr::PMPADDR15 }>(),
+    ]
+    .map(|v| v as u32);
+    uart_puts("[PMP] Read-back (pmpcfg0-3, pmpaddr0-15):\r\n");
+    let config = PmpConfig { pmpaddr, pmpcfg: read_pmpcfgs() };
+    print_pmp_config(&config);
+    if config != BOOT_PMP {
+        uart_puts("  (differs from the computed boot configuration)\r\n");
+    }
+    uart_newline();
+}
+
+// ============================================================================
+// U-mode Applications (per-application PMP entries)
+// ============================================================================
+//
+// PMP entries 3-6 hold the running application's code, rodata, RAM and
+// shadow stacks; switch_to_app swaps them for another application's.  The
+// rest are shared: UART (7), the stack guard (8) and the upper half of
+// U_RAM (9), where the one U-mode stack lives.  So far applications are
+// isolated in their `ram` and `shadow` regions, not in their stacks.
+
+/// `app-isolation-test` and `sched-demo` builds: application 1, sharing
+/// APP_MAIN's code and rodata but with RAM and shadow-stack regions of its
+/// own in U_APP1.
+#[cfg(any(feature = "app-isolation-test", feature = "sched-demo"))]
+const APP_1: AppRegions = AppRegions {
+    ram: Napot::new(0x8006_0000, 32 * 1024, PMP_R | PMP_W),
+    shadow: Napot::new(0x8006_8000, 8 * 1024, PMP_R | PMP_W),
+    ..APP_MAIN
+};
+
+#[cfg(not(any(feature = "app-isolation-test", feature = "sched-demo")))]
+const APPS: [AppRegions; 1] = [APP_MAIN];
+#[cfg(any(feature = "app-isolation-test", feature = "sched-demo"))]
+const APPS: [AppRegions; 2] = [APP_MAIN, APP_1];
+
+const fn apps_isolated(apps: &[AppRegions]) -> bool {
+    let mut i = 0;
+    while i < apps.len() {
+        let mut j = i + 1;
+        while j < apps.len() {
+            if !apps[i].isolated_from(&apps[j]) {
+                return false;
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    true
+}
+
+const _: () = assert!(apps_isolated(&APPS), "two applications can write the same memory");
+
+/// Why [`switch_to_app`] refused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AppSwitchError {
+    /// No application with that index.
+    NoSuchApp,
+    /// This application entry is locked, so it can't be reprogrammed.
+    Locked(usize),
+    /// The application was terminated for a CFI violation
+    /// ([`FAULTED_APPS`]).
+    Faulted,
+}
+
+/// Application whose regions are in the application entries.
+static CURRENT_APP: AtomicU32 = AtomicU32::new(0);
+
+/// Regions [`switch_to_app`] programs without execute permission: bit
+/// `4 * app + i` for `APPS[app].regions()[i]`.  Only the
+/// `quarantine-policy` violation handler sets bits, and none is cleared
+/// before the next reset.
+static QUARANTINED: AtomicU32 = AtomicU32::new(0);
+const _: () = assert!(APPS.len() * APP_PMP_ENTRIES.len() <= 32, "QUARANTINED has a bit per region");
+
+/// Applications terminated for a CFI violation, bit `app`: set by the
+/// `cfi-contain` violation handler, never cleared before the next reset.
+/// [`switch_to_app`] refuses them.
+static FAULTED_APPS: AtomicU32 = AtomicU32::new(0);
+const _: () = assert!(APPS.len() <= 32, "FAULTED_APPS has a bit per application");
+
+/// Bit for application `app`'s region `i` in [`QUARANTINED`].
+const fn quarantine_bit(app: usize, i: usize) -> u32 {
+    1 << (app * APP_PMP_ENTRIES.len() + i)
+}
+
+/// Program application `idx`'s regions into the application PMP entries,
+/// so U-mode runs as `idx` after the next `mret`.
+///
+/// All or nothing, like [`program_pmp_entries`]: if any of the entries is
+/// locked none is touched.  A faulted application is refused.
+fn switch_to_app(idx: usize) -> Result<(), AppSwitchError> {
+    let app = APPS.get(idx).ok_or(AppSwitchError::NoSuchApp)?;
+    if FAULTED_APPS.load(Ordering::Relaxed) & 1 << idx != 0 {
+        return Err(AppSwitchError::Faulted);
+    }
+    critical_section(|| {
+        let quarantined = QUARANTINED.load(Ordering::Relaxed);
+        let entries: [PmpEntry; 4] = core::array::from_fn(|i| {
+            let region = app.regions()[i];
+            let region = if quarantined & quarantine_bit(idx, i) != 0 { region.without_exec() } else { region };
+            PmpEntry { index: APP_PMP_ENTRIES[i], pmpaddr: region.pmpaddr(), cfg: region.cfg() }
+        });
+        program_pmp_entries(&entries).map_err(AppSwitchError::Locked)?;
+        CURRENT_APP.store(idx as u32, Ordering::Relaxed);
+        Ok(())
+    })
+}
+
+/// Write `pmpaddr<entry>`.  The pmpaddr CSRs have no indexed form, hence
+/// the match.
+fn write_pmpaddr(entry: usize, value: u32) {
+    let value = value as usize;
+    // SAFETY: callers only rewrite unlocked entries, which M-mode ignores.
+    unsafe {
+        match entry {
+            0 => csr::write::<{ csr::PMPADDR0 }>(value),
+            1 => csr::write::<{ csr::PMPADDR1 }>(value),
+            2 => csr::write::<{ csr::PMPADDR2 }>(value),
+            3 => csr::write::<{ csr::PMPADDR3 }>(value),
+            4 => csr::write::<{ csr::PMPADDR4 }>(value),
+            5 => csr::write::<{ csr::PMPADDR5 }>(value),
+            6 => csr::write::<{ csr::PMPADDR6 }>(value),
+            7 => csr::write::<{ csr::PMPADDR7 }>(value),
+            8 => csr::write::<{ csr::PMPADDR8 }>(value),
+            9 => csr::write::<{ csr::PMPADDR9 }>(value),
+            10 => csr::write::<{ csr::PMPADDR10 }>(value),
+            11 => csr::write::<{ csr::PMPADDR11 }>(value),
+            12 => csr::write::<{ csr::PMPADDR12 }>(value),
+            13 => csr::write::<{ csr::PMPADDR13 }>(value),
+            14 => csr::write::<{ csr::PMPADDR14 }>(value),
+            15 => csr::write::<{ csr::PMPADDR15 }>(value),
+            _ => panic!("no PMP entry {}", entry),
+        }
+    }
+}
+
+/// Syscall 13 (`app-isolation-test` builds): switch to application
+/// `idx`, taking effect as the ecall returns.  0, or [`SYSCALL_ERR`] if
+/// the switch was refused.
+#[cfg(feature = "app-isolation-test")]
+fn sys_app_switch(idx: usize) -> usize {
+    match switch_to_app(idx) {
+        Ok(()) => 0,
+        Err(_) => SYSCALL_ERR,
+    }
+}
+
+// ============================================================================
+// Task Scheduler (sched-demo: syscalls 16-18)
+// ============================================================================
+//
+// Two U-mode tasks, preempted round-robin by the machine timer.  Task 0 is
+// the firmware launched at boot, running as application 0; task_spawn
+// starts task 1 as application 1, on stacks in that application's
+// regions.  Every switch happens in _context_switch, on the way out of a
+// trap from U-mode: the handler rewrites the context _trap_return is about
+// to restore.  docs/architecture.md lists the invariants this relies on.
+
+/// Time slice: 10 ms of mtime (10 MHz on virt).
+#[cfg(feature = "sched-demo")]
+const SCHED_TICK: u64 = 100_000;
+
+/// Task 1's stacks, split the way application 0's are: the data stack at
+/// the top of its RAM, and its shadow region halved into the hardware
+/// shadow stack (growing down) and the software one (growing up).
+#[cfg(feature = "sched-demo")]
+const TASK1_STACK_TOP: usize = (APP_1.ram.base + APP_1.ram.size) as usize;
+#[cfg(feature = "sched-demo")]
+const TASK1_SHADOW_STACK_TOP: usize = (APP_1.shadow.base + APP_1.shadow.size / 2) as usize;
+#[cfg(feature = "sched-demo")]
+const TASK1_SW_SHADOW_STACK_BOTTOM: usize = TASK1_SHADOW_STACK_TOP;
+
+#[cfg(feature = "sched-demo")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TaskState {
+    /// No task in this slot: task 1 before task_spawn, or once waited for.
+    Free,
+    /// Running, or due to run at a tick.
+    Ready,
+    /// Task 0, blocked in task_wait until task 1 exits.
+    Waiting,
+    /// Task 1 exited with this code, which task_wait hasn't collected.
+    Exited(usize),
+}
+
+/// A U-mode context as saved while its task isn't running.  The running
+/// task's lives in the trap frame, the registers and the CSRs instead.
+#[cfg(feature = "sched-demo")]
+#[derive(Clone, Copy)]
+struct Task {
+    frame: TrapFrame,
+    regs: CalleeSaved,
+    /// The hardware shadow-stack pointer.
+    ssp: usize,
+    /// For MPELP: a task preempted between an indirect jump and its
+    /// landing pad still owes the landing pad when it resumes.
+    mstatush: usize,
+    /// The application it runs as ([`switch_to_app`]).
+    app: usize,
+    state: TaskState,
+}
+
+#[cfg(feature = "sched-demo")]
+impl Task {
+    const fn new(app: usize, state: TaskState) -> Task {
+        // SAFETY: TrapFrame and CalleeSaved are plain words, for which all
+        // zeroes is a valid value.
+        let (frame, regs) = unsafe { (core::mem::zeroed(), core::mem::zeroed()) };
+        Task { frame, regs, ssp: 0, mstatush: 0, app, state }
+    }
+}
+
+#[cfg(feature = "sched-demo")]
+struct Scheduler {
+    tasks: [Task; 2],
+    /// The task whose context is live.
+    current: usize,
+}
+
+#[cfg(feature = "sched-demo")]
+impl Scheduler {
+    const fn new() -> Scheduler {
+        Scheduler { tasks: [Task::new(0, TaskState::Ready), Task::new(1, TaskState::Free)], current: 0 }
+    }
+
+    /// Both tasks can run, so the time slice matters.
+    fn preempting(&self) -> bool {
+        self.tasks.iter().all(|t| t.state == TaskState::Ready)
+    }
+
+    /// Save the live context into the current task and load task `to`'s
+    /// in its place.  `regs` and `frame` are what `_context_switch` pops and
+    /// `_trap_return` restores; the rest is in CSRs and the application
+    /// entries.
+    fn switch(&mut self, to: usize, regs: &mut CalleeSaved, frame: &mut TrapFrame) {
+        let out = &mut self.tasks[self.current];
+        // mstatush first: any trap on the way (a missing ssp CSR is one)
+        // overwrites MPELP.
+        out.mstatush = csr::read::<{ csr::MSTATUSH }>();
+        out.ssp = read_ssp();
+        out.frame = *frame;
+        out.regs = *regs;
+        out.app = CURRENT_APP.load(Ordering::Relaxed) as usize;
+
+        let next = &self.tasks[to];
+        *frame = next.frame;
+        *regs = next.regs;
+        if let Err(e) = switch_to_app(next.app) {
+            panic!("task {} can't run as application {}: {:?}", to, next.app, e);
+        }
+        // SAFETY: the task's own values, saved when it was switched out
+        // (or set up by task_spawn).  Nothing may trap once mstatush is
+        // written.
+        unsafe {
+            write_ssp(next.ssp);
+            csr::write::<{ csr::MSTATUSH }>(next.mstatush);
+        }
+        self.current = to;
+    }
+
+    /// End task 1, the running task, with exit code `code` and switch to
+    /// task 0, handing it the code if it is waiting.
+    fn end_task1(&mut self, code: usize, regs: &mut CalleeSaved, frame: &mut TrapFrame) {
+        if self.tasks[0].state == TaskState::Waiting {
+            self.tasks[0].state = TaskState::Ready;
+            self.tasks[0].frame.a0 = code; // task_wait's result
+            self.tasks[1].state = TaskState::Free;
+        } else {
+            self.tasks[1].state = TaskState::Exited(code);
+        }
+        self.switch(0, regs, frame);
+        self.rearm();
+    }
+
+    /// Arm the next tick while both tasks can run, and stop the timer
+    /// otherwise: `yield` waits in wfi whenever MTIE is set, so MTIE must
+    /// never outlive the deadline.
+    fn rearm(&self) {
+        if self.preempting() {
+            clint::arm(clint::now() + SCHED_TICK);
+            enable_irq_source(IrqSource::Timer);
+        } else {
+            clint::disarm();
+            disable_irq_source(IrqSource::Timer);
+        }
+    }
+}
+
+#[cfg(feature = "sched-demo")]
+static SCHED: IrqCell<Scheduler> = IrqCell::new(Scheduler::new());
+
+/// Timer interrupt in `sched-demo` builds, from `_context_switch`: count it,
+/// then hand the hart to the other task if both can run.  Only a tick
+/// taken in U-mode switches.  One that lands in an M-mode service only
+/// re-arms, because the frame below it belongs to the service.
+#[cfg(feature = "sched-demo")]
+#[no_mangle]
+extern "C" fn sched_tick(regs: &mut CalleeSaved, frame: &mut TrapFrame) {
+    irq_timer();
+    SCHED.with(|s| {
+        let from_umode = frame.mstatus & (3 << 11) == 0; // mstatus.MPP = U
+        if s.preempting() && from_umode {
+            s.switch(1 - s.current, regs, frame);
+        }
+        s.rearm();
+    });
+}
+
+/// Syscall 16 (`sched-demo` builds): start task 1 at `entry` (a0) with
+/// `arg` (a1) in its a0, as application 1, and start the time slice.  0,
+/// or [`SYSCALL_ERR`] if the caller is task 1, task 1 already exists,
+/// `entry` fails [`Napot::check_entry`] against application 1's code, or
+/// application 1 was terminated for a CFI violation.
+///
+/// Task 1 starts with FP off (FS = Off), so it can't read task 0's FP
+/// registers and has no FP state to switch.
+#[cfg(feature = "sched-demo")]
+#[no_mangle]
+extern "C" fn sys_task_spawn(regs: &mut CalleeSaved, frame: &mut TrapFrame) {
+    let (entry, arg) = (frame.a0, frame.a1);
+    frame.a0 = SCHED
+        .with(|s| {
+            if s.current != 0
+                || s.tasks[1].state != TaskState::Free
+                || APP_1.code.check_entry(entry as u32).is_err()
+                || FAULTED_APPS.load(Ordering::Relaxed) & 1 << 1 != 0
+            {
+                return SYSCALL_ERR;
+            }
+            let task = &mut s.tasks[1];
+            *task = Task::new(1, TaskState::Ready);
+            task.frame.mepc = entry;
+            task.frame.a0 = arg;
+            task.frame.sp = TASK1_STACK_TOP;
+            task.frame.mstatus = frame.mstatus & !MSTATUS_FS; // MPP = U, as the caller's
+            task.ssp = TASK1_SHADOW_STACK_TOP;
+            if cfg!(feature = "sw-ss-s11") {
+                // gp is the image's global pointer here: shared, like the code
+                task.regs.gp = regs.gp;
+                task.regs.s[11] = TASK1_SW_SHADOW_STACK_BOTTOM;
+            } else {
+                task.regs.gp = TASK1_SW_SHADOW_STACK_BOTTOM;
+            }
+            s.rearm();
+            0
+        })
+        .unwrap_or(SYSCALL_ERR);
+}
+
+/// Syscall 17 (`sched-demo` builds): task 0 waits for task 1 to exit and
+/// gets its exit code, while task 1 runs alone.  [`SYSCALL_ERR`] at once
+/// if there is no task 1 to wait for, or the caller is task 1.
+#[cfg(feature = "sched-demo")]
+#[no_mangle]
+extern "C" fn sys_task_wait(regs: &mut CalleeSaved, frame: &mut TrapFrame) {
+    SCHED.with(|s| {
+        match s.tasks[1].state {
+            _ if s.current != 0 => frame.a0 = SYSCALL_ERR,
+            TaskState::Exited(code) => {
+                frame.a0 = code;
+                s.tasks[1].state = TaskState::Free;
+            }
+            TaskState::Ready => {
+                s.tasks[0].state = TaskState::Waiting;
+                s.switch(1, regs, frame);
+            }
+            TaskState::Free | TaskState::Waiting => frame.a0 = SYSCALL_ERR,
+        }
+        s.rearm();
+    });
+}
+
+/// Syscall 18 (`sched-demo` builds): end task 1 with exit code a0 and
+/// switch to task 0, handing it the code if it is waiting.  Task 0 ends
+/// the run with `exit` (syscall 2) instead, and gets [`SYSCALL_ERR`] here.
+#[cfg(feature = "sched-demo")]
+#[no_mangle]
+extern "C" fn sys_task_exit(regs: &mut CalleeSaved, frame: &mut TrapFrame) {
+    SCHED.with(|s| {
+        if s.current != 1 {
+            frame.a0 = SYSCALL_ERR;
+            return;
+        }
+        s.end_task1(frame.a0, regs, frame);
+    });
+}
+
+// ============================================================================
+// Timer Upcall (timer-upcall: syscalls 19-20)
+// ============================================================================
+//
+// U-mode registers a handler and a period; each tick taken in U-mode then
+// runs the handler on top of the code it interrupted, like a signal, and
+// the handler's return resumes that code.  Entering and leaving both
+// happen in _context_switch, as task switches do.  sched-demo owns the
+// timer when both are built, and then syscalls 19-20 aren't dispatched.
+// docs/architecture.md has the ABI and the re-entrancy rules.
+
+/// The upcall syscalls are dispatched: `timer-upcall` builds without
+/// `sched-demo`.
+const TIMER_UPCALL: bool = cfg!(feature = "timer-upcall") && !cfg!(feature = "sched-demo");
+
+/// Shortest period accepted, in mtime ticks (100 µs on virt): a shorter
+/// one could keep the hart in the handler.
+#[cfg(feature = "timer-upcall")]
+const UPCALL_MIN_PERIOD: usize = 1_000;
+
+/// mstatush.MPELP (bit 9): the mret target must start with a landing pad.
+#[cfg(any(feature = "timer-upcall", feature = "sig-label-demo"))]
+const MSTATUSH_MPELP: usize = 1 << 9;
+
+/// A context an upcall interrupted, as [`Task`] saves one.  It stays in
+/// M-mode memory rather than on the U-mode stack, so the handler can't
+/// change the mepc or mstatus it is resumed with.
+#[cfg(feature = "timer-upcall")]
+#[derive(Clone, Copy)]
+struct Interrupted {
+    frame: TrapFrame,
+    regs: CalleeSaved,
+    ssp: usize,
+    mstatush: usize,
+}
+
+#[cfg(feature = "timer-upcall")]
+struct Upcall {
+    /// The registered handler; 0 = none.
+    handler: usize,
+    /// mtime ticks between deadlines.
+    period: u64,
+    /// Deadlines since the handler was last entered.
+    ticks: usize,
+    /// What the running handler interrupted; `None` outside the handler.
+    saved: Option<Interrupted>,
+}
+
+#[cfg(feature = "timer-upcall")]
+impl Upcall {
+    /// Make the live context enter the handler on top of `from`, with the
+    /// ticks it covers in a0 and [`_u_upcall_return`] as its return
+    /// address.  mepc gets the handler and MPELP is set, so with Zicfilp
+    /// the entry must be `lpad TIMER_UPCALL` (the label in t2).
+    fn enter(&mut self, from: &Interrupted, regs: &mut CalleeSaved, frame: &mut TrapFrame) {
+        *frame = from.frame;
+        *regs = from.regs;
+        frame.mepc = self.handler;
+        frame.a0 = self.ticks;
+        frame.ra = _u_upcall_return as *const () as usize;
+        frame.t2 = (cfi_labels::TIMER_UPCALL << 12) as usize;
+        frame.sp = from.frame.sp & !15;
+        // Skip the software shadow-stack slot at the pointer: code
+        // interrupted between a push's store and its increment, or between
+        // a pop's decrement and its load, still owns it.
+        if cfg!(feature = "sw-ss-s11") {
+            regs.s[11] += 4;
+        } else {
+            regs.gp += 4;
+        }
+        self.ticks = 0;
+        // SAFETY: the interrupted code's own shadow stack, which the
+        // handler's pushes and pops go on top of.  Nothing may trap once
+        // mstatush is written.
+        unsafe {
+            write_ssp(from.ssp);
+            csr::write::<{ csr::MSTATUSH }>(from.mstatush | MSTATUSH_MPELP);
+        }
+    }
+}
+
+#[cfg(feature = "timer-upcall")]
+static UPCALL: IrqCell<Upcall> = IrqCell::new(Upcall { handler: 0, period: 0, ticks: 0, saved: None });
+
+/// Timer interrupt in `timer-upcall` builds, from `_context_switch`.  The
+/// deadline is disarmed and counted as in [`irq_timer`].  With a handler
+/// registered the next one is armed at once, and the tick is delivered if
+/// it was taken in U-mode outside the handler.  A tick in the handler
+/// waits for `upcall_return`, and one in an M-mode service for the next
+/// tick taken in U-mode; either way it shows in the next entry's a0.
+#[cfg(feature = "timer-upcall")]
+#[no_mangle]
+extern "C" fn upcall_tick(regs: &mut CalleeSaved, frame: &mut TrapFrame) {
+    irq_timer();
+    UPCALL.with(|u| {
+        if u.handler == 0 {
+            return;
+        }
+        clint::arm(clint::now() + u.period);
+        u.ticks += 1;
+        let from_umode = frame.mstatus & (3 << 11) == 0; // mstatus.MPP = U
+        if from_umode && u.saved.is_none() {
+            // mstatush first: a missing ssp CSR traps and overwrites MPELP.
+            let mstatush = csr::read::<{ csr::MSTATUSH }>();
+            let from = Interrupted { frame: *frame, regs: *regs, ssp: read_ssp(), mstatush };
+            u.saved = Some(from);
+            u.enter(&from, regs, frame);
+        }
+    });
+}
+
+/// Syscall 19 (`timer-upcall` builds): enter `handler` every `period`
+/// mtime ticks, or stop with `handler` = 0.  0, or [`SYSCALL_ERR`] if
+/// `handler` fails [`Napot::check_entry`] against the running
+/// application's code or `period` is under [`UPCALL_MIN_PERIOD`].
+///
+/// Stopping from inside the handler is allowed: the running upcall still
+/// ends with `upcall_return`.
+#[cfg(feature = "timer-upcall")]
+fn sys_timer_upcall(handler: usize, period: usize) -> usize {
+    let code = APPS[CURRENT_APP.load(Ordering::Relaxed) as usize].code;
+    if handler != 0 && (code.check_entry(handler as u32).is_err() || period < UPCALL_MIN_PERIOD) {
+        return SYSCALL_ERR;
+    }
+    UPCALL
+        .with(|u| {
+            u.handler = handler;
+            u.period = period as u64;
+            u.ticks = 0;
+            if handler == 0 {
+                clint::disarm();
+                disable_irq_source(IrqSource::Timer);
+            } else {
+                clint::arm(clint::now() + u.period);
+                enable_irq_source(IrqSource::Timer);
+            }
+            0
+        })
+        .unwrap_or(SYSCALL_ERR)
+}
+
+/// Syscall 20 (`timer-upcall` builds): end the running upcall.  Resumes
+/// the interrupted context as it was, or, if ticks came in while the
+/// handler ran, enters the handler again on top of the same context.
+/// [`SYSCALL_ERR`] if no upcall is running.
+#[cfg(feature = "timer-upcall")]
+#[no_mangle]
+extern "C" fn sys_upcall_return(regs: &mut CalleeSaved, frame: &mut TrapFrame) {
+    UPCALL.with(|u| {
+        let Some(from) = u.saved else {
+            frame.a0 = SYSCALL_ERR;
+            return;
+        };
+        if u.handler != 0 && u.ticks > 0 {
+            u.enter(&from, regs, frame);
+            return;
+        }
+        u.saved = None;
+        *frame = from.frame;
+        *regs = from.regs;
+        // SAFETY: the interrupted code's own values, saved on entry.
+        // Nothing may trap once mstatush is written.
+        unsafe {
+            write_ssp(from.ssp);
+            csr::write::<{ csr::MSTATUSH }>(from.mstatush);
+        }
+    });
+}
+
+/// `timer-upcall` builds: the return address an upcall handler is
+/// entered with.  Ends the upcall with syscall 20, which resumes the
+/// interrupted code and doesn't come back here.
+///
+/// # Safety
+///
+/// U-mode code, only reached by a handler's return.
+#[cfg(feature = "timer-upcall")]
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".u_text"]
+unsafe extern "C" fn _u_upcall_return() {
+    naked_asm!("li     a7, 20", "ecall", "ebreak")
+}
+
+// ============================================================================
+// CFI Initialization
+// ============================================================================
+
+/// Enable hardware CFI extensions via menvcfg and senvcfg CSRs, and report
+/// which of them actually took effect.
+///
+/// menvcfg (0x30A) controls CFI for S/U-mode:
+///   Bit 2 (LPE) — Landing Pad Enable (Zicfilp)
+///   Bit 3 (SSE) — Shadow Stack Enable (Zicfiss)
+///
+/// The two extensions are independent, so each bit is set on its own and
+/// menvcfg is read back to see which stuck (the bits are WARL — a core
+/// without Zicfiss silently drops SSE).  On hardware without the CSRs at
+/// all, the write *and* the read-back trap; see [`csr::set_readback`].
+fn enable_cfi() -> CfiStatus {
+    uart_puts("[CFI] Enabling hardware CFI extensions...\r\n");
+
+    let menvcfg_set =
+        |bits: u32| unsafe { csr::set_readback::<{ csr::MENVCFG }>(bits as usize) as u32 };
+    let lpe = menvcfg_set(ENVCFG_LPE) & ENVCFG_LPE;
+    report_envcfg_bit("menvcfg", "LPE (bit 2)", lpe != 0);
+    let sse = menvcfg_set(ENVCFG_SSE) & ENVCFG_SSE;
+    report_envcfg_bit("menvcfg", "SSE (bit 3)", sse != 0);
+
+    // Also enable in senvcfg for U-mode if running S-mode software
+    // (In our M-mode-only RoT, menvcfg is sufficient for U-mode, but
+    //  we set senvcfg too for forward-compatibility with S-mode kernels)
+    let s_bits = unsafe {
+        csr::set_readback::<{ csr::SENVCFG }>((ENVCFG_LPE | ENVCFG_SSE) as usize) as u32
+    };
+    report_envcfg_bit("senvcfg", "LPE (bit 2)", s_bits & ENVCFG_LPE != 0);
+    report_envcfg_bit("senvcfg", "SSE (bit 3)", s_bits & ENVCFG_SSE != 0);
+
+    // Initialize M-mode hardware shadow stack pointer (ssp).  The
+    // read-back tells us whether the CSR exists independently of whether
+    // menvcfg.SSE stuck.
+    let ssp_top: usize;
+    unsafe { asm!("la {0}, _m_shadow_stack_top", out(reg) ssp_top) };
+    let ssp = unsafe { csr::write_readback::<{ csr::SSP }>(ssp_top) } == ssp_top;
+    if ssp {
+        uart_puts("  ssp: initialized to _m_shadow_stack_top (M-mode)\r\n");
+    } else {
+        uart_puts("  ssp: not present\r\n");
+    }
+
+    let status = CfiStatus::from_menvcfg(lpe | sse, ssp);
+    if status.ssp_without_sse() {
+        uart_puts("  WARNING: ssp exists but menvcfg.SSE did not stick —\r\n");
+        uart_puts("           hardware shadow stack is NOT enforced\r\n");
+    }
+    let _ = write!(UartWriter, "[CFI] {}\r\n\r\n", status);
+    status
+}
+
+fn report_envcfg_bit(csr: &str, bit: &str, stuck: bool) {
+    let _ = write!(
+        UartWriter,
+        "  {}: set {} — {}\r\n",
+        csr,
+        bit,
+        if stuck { "on" } else { "did not stick" }
+    );
+}
+
+// ============================================================================
+// M-Mode Trap Handler
+// ============================================================================
+
+/// The frame `_trap_handler` pushes, lowest address first.  Only the asm
+/// ever builds one: it allocates `size_of::<TrapFrame>()` and addresses
+/// every slot by `offset_of!` on this struct, so adding a field moves the
+/// offsets and the frame size together.  The `sched-demo` scheduler keeps
+/// a copy per task.
+#[repr(C, align(16))] // the RISC-V ABI keeps sp 16-byte aligned
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+struct TrapFrame {
+    /// The caller-saved integer registers.  a7 sits among a0-a2 so the
+    /// ecall path's arguments and syscall number share one cache line.
+    ra: usize,
+    t0: usize,
+    t1: usize,
+    t2: usize,
+    a0: usize,
+    a1: usize,
+    a2: usize,
+    a7: usize,
+    a3: usize,
+    a4: usize,
+    a5: usize,
+    a6: usize,
+    t3: usize,
+    t4: usize,
+    t5: usize,
+    t6: usize,
+    /// f0-f31 (`fp` builds).
+    #[cfg(feature = "fp")]
+    f: [u32; 32],
+    #[cfg(feature = "fp")]
+    fcsr: usize,
+    /// mstatus.FS at trap entry; 0 = nothing saved.
+    #[cfg(feature = "fp")]
+    fs: usize,
+    /// The per-trap state: mepc, mstatus, mcause and the interrupted sp.
+    /// A nested trap overwrites the CSRs, so each trap keeps its own copy
+    /// here and returns through it.
+    mepc: usize,
+    mstatus: usize,
+    mcause: usize,
+    sp: usize,
+}
+
+const TRAP_FRAME_SIZE: usize = size_of::<TrapFrame>();
+/// The FP slots exist only in `fp` builds; elsewhere the asm that uses
+/// them sits under `.if {fp}` and these are placeholders.
+#[cfg(feature = "fp")]
+const TRAP_FRAME_FP: [usize; 3] = [offset_of!(TrapFrame, f), offset_of!(TrapFrame, fcsr), offset_of!(TrapFrame, fs)];
+#[cfg(not(feature = "fp"))]
+const TRAP_FRAME_FP: [usize; 3] = [0; 3];
+const TRAP_FRAME_F: usize = TRAP_FRAME_FP[0];
+const TRAP_FRAME_FCSR: usize = TRAP_FRAME_FP[1];
+const TRAP_FRAME_FS: usize = TRAP_FRAME_FP[2];
+const TRAP_FRAME_MEPC: usize = offset_of!(TrapFrame, mepc);
+const TRAP_FRAME_MSTATUS: usize = offset_of!(TrapFrame, mstatus);
+const TRAP_FRAME_MCAUSE: usize = offset_of!(TrapFrame, mcause);
+const TRAP_FRAME_SP: usize = offset_of!(TrapFrame, sp);
+
+// `addi sp, sp, -{frame}` and every `{slot}(sp)` take a 12-bit signed
+// immediate; `_call_m_isr` also reaches the frame 16 bytes further up.
+const _: () = assert!(TRAP_FRAME_SIZE + 16 <= 2047, "trap frame too big for an immediate offset");
+const _: () = assert!(TRAP_FRAME_SIZE == if cfg!(feature = "fp") { 224 } else { 80 });
+
+/// mstatus.FS (bits 14:13): Off / Initial / Clean / Dirty.
+const MSTATUS_FS: usize = 3 << 13;
+
+/// The callee-saved registers, which the trap frame leaves out: the Rust
+/// a trap calls preserves them.  `_context_switch` (`sched-demo`,
+/// `timer-upcall`, `misalign-fixup`, `ss-emulate` and `cfi-contain`
+/// builds) pushes them just below the frame, where the scheduler, the
+/// upcall code, the misaligned-access fixup, shadow-stack emulation and
+/// the CFI containment can read and rewrite them along with it.
+#[repr(C, align(16))]
+#[derive(Clone, Copy)]
+#[allow(dead_code)] // only the builds with a _context_switch push one
+struct CalleeSaved {
+    s: [usize; 12],
+    gp: usize,
+    tp: usize,
+}
+
+/// Alignment mtvec's base needs.  Its low two bits are the MODE field,
+/// so a base that isn't 4-byte aligned isn't an error on the write: the
+/// core enters at the base with those bits cleared, in whatever mode they
+/// spelled.  `link.x` aligns `.text.trap` and asserts it.
+const MTVEC_ALIGN: usize = 4;
+
+/// mtvec.MODE = vectored: interrupt cause N enters at base + 4*N.
+const MTVEC_VECTORED: usize = 1;
+
+/// Alignment of `_trap_vector` (`vectored-traps` builds).  The privileged
+/// spec allows a core to demand more than 4 in vectored mode; 64 is what
+/// those that do ask for, and covers the table's 12 entries.
+const MTVEC_VECTOR_ALIGN: usize = 64;
+
+/// Halt unless mtvec reads back as `_start` wrote it: the trap entry,
+/// aligned, with the build's mode.  mtvec is WARL, so a core without
+/// vectored mode, or one wanting a larger vector alignment, drops bits
+/// silently, and the first trap would go somewhere else.
+fn check_mtvec() {
+    let entry = layout::trap_entry();
+    let wrote = if cfg!(feature = "vectored-traps") { entry | MTVEC_VECTORED } else { entry };
+    let mtvec = csr::read::<{ csr::MTVEC }>();
+    if mtvec != wrote || !entry.is_multiple_of(MTVEC_ALIGN) {
+        let _ = write!(UartWriter, "[TRAP] FAIL: mtvec = {:#010x}, wrote {:#010x}\r\n", mtvec, wrote);
+        panic!("mtvec does not hold the trap entry");
+    }
+}
+
+/// Unified M-mode trap handler.
+///
+/// Handles:
+///   - **Ecalls from U-mode** (mcause = 8): service requests from application
+///   - **Ecalls from S-mode or M-mode** (mcause = 9/11): rejected by
+///     [`trap_foreign_ecall`], and the caller resumes after the ecall
+///   - **Illegal instructions** (mcause = 2): skip faulting instruction
+///     (graceful degradation for unsupported CSR accesses during boot);
+///     in `ss-emulate` builds a shadow-stack instruction from U-mode is
+///     carried out by [`trap_ss_emulate`] instead
+///   - **Load/store access faults** (mcause = 5/7) inside the [`uaccess`]
+///     routines: the access returns an error instead; any other is fatal
+///   - **Misaligned loads/stores** (mcause = 4/6, `misalign-fixup`
+///     builds): from U-mode, emulated by [`trap_misaligned`]
+///   - **CFI violations**:
+///     - Software-check exception (mcause = 18): Zicfiss shadow stack mismatch
+///     - Instruction access fault (mcause = 1): Zicfilp landing pad violation
+///     - fatal, unless `quarantine-policy` contains it
+///       ([`trap_cfi_quarantine`])
+///   - **Breakpoint** (mcause = 3): the expected ebreak from
+///     `u_fault_inject_test`, `u_rop_attack_demo` or `u_breakpoint_test`
+///     in their test builds; any other halts through [`trap_breakpoint`]
+///   - **Machine external interrupt** (mcause = 0x8000000B): PLIC
+///     sources, dispatched by [`irq_external`]
+///   - **Machine timer interrupt** (mcause = 0x80000007): one-shot
+///     CLINT deadlines, handled by [`irq_timer`]; in `sched-demo` builds
+///     the time slice, which may switch tasks (`sched_tick`), and in
+///     `timer-upcall` builds the upcall tick (`upcall_tick`)
+///   - **Anything else**: fatal — decoded and reported by [`trap_fatal`]
+///
+/// Ecall ABI:
+///   a7 = syscall number
+///     0 = uart_putc(a0 = char)
+///     1 = uart_puts(a0 = ptr, a1 = len)
+///     2 = exit(a0 = code)                  [0 = pass, non-zero = fail]
+///     3 = get_random(a0 = &buf, a1 = len) -> (0 | -1, bytes written)
+///     4 = getc() -> byte | -1              [-1: nothing received yet]
+///     5 = quote(a0 = &nonce[32], a1 = &out, a2 = out_len) -> len | -1
+///     6 = yield()                          [wfi if the timer can wake us]
+///     7 = perf_counters(a0 = &out[16]) -> 0 | -1
+///     8 = pcr_read(a0 = pcr, a1 = &out[32]) -> 0 | -1
+///     9 = measure() -> firmware measurement
+///    10 = seal(a0 = data, a1 = key_id) -> sealed value
+///    11 = pcr_extend(a0 = pcr, a1 = &digest[32]) -> 0 | -1  [-1: locked]
+///    12 = nested_test() -> 3               [nested-trap-test builds]
+///    13 = app_switch(a0 = idx) -> 0 | -1   [app-isolation-test builds]
+///    14 = time() -> mtime                  [u64: low half a0, high a1]
+///    15 = shadow_trace(a0, a1, a2) -> 0    [shadow-trace builds]
+///    16 = task_spawn(a0 = entry, a1 = arg) -> 0 | -1  [sched-demo builds]
+///    17 = task_wait() -> task 1's exit code | -1      [sched-demo builds]
+///    18 = task_exit(a0 = code)             [sched-demo builds, task 1 only]
+///    19 = timer_upcall(a0 = handler, a1 = period) -> 0 | -1  [timer-upcall builds]
+///    20 = upcall_return()                  [timer-upcall builds, from the handler]
+///    21 = ss_divergence(a0, a1, a2) -> 0   [ss-crosscheck builds]
+///    22 = sbrk(a0 = increment) -> old break | -1  [-1: outside the heap]
+///    23 = dump_log() -> entries printed    [MLOG lines on the console]
+///    24 = stack_canary_fail(a0 = found, a1 = ra) -> halts  [stack-canary builds]
+///    25 = seal_policy(a0 = &data, a1 = len, a2 = pcr_mask, a3 = &out, a4 = out_len)
+///         -> blob len | -1                 [policy-seal builds]
+///    26 = unseal_policy(a0 = &blob, a1 = len, a2 = &out, a3 = out_len)
+///         -> data len | -1                 [policy-seal builds]
+///     any other number (or one compiled out) -> -2 [`SYSCALL_NOT_SUPPORTED`]
+///   a7 bit 31 set = monitor call ([`monitor`]), a7[30:0] = number:
+///     0 = lock_pcr(a0 = pcr, a1 = token) -> 0 | -1
+///     1 = provision_key(a0 = slot, a1 = token, a2 = &[u8; 32]) -> 0 | -1
+///   Return value in a0; a pair (3, 14) in a0 and a1.  All other
+///   registers are preserved.
+///
+/// FP state (`fp` builds, F extension): mstatus.FS tracks whether the FP
+/// registers may differ from any saved copy — Off (FP disabled; any FP
+/// instruction traps), Initial, Clean or Dirty.  On entry:
+///   - FS = Off: U-mode can't have FP state, nothing is saved;
+///   - otherwise (Initial / Clean / Dirty): f0-f31 and fcsr go into the
+///     frame, whatever the state — M-mode code may use FP and a clean
+///     register file isn't backed by any other copy here.
+///
+/// On return they are reloaded and FS is set back to its entry value: the
+/// reload itself marks FS Dirty, but the registers then hold exactly what
+/// the interrupted code left, so a Clean context stays Clean.
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".text.trap"]
+unsafe extern "C" fn _trap_handler() {
+    naked_asm!(
+        // Switch to the trap stack.  mscratch holds its top while U-mode
+        // runs and 0 while M-mode does, so a trap from U-mode never
+        // writes through U-mode's sp, and one from M-mode (boot, or
+        // nested inside a service) stays on the stack it interrupted.
+        "csrrw  sp, mscratch, sp",
+        "bnez   sp, 85f",
+        "csrrw  sp, mscratch, sp",
+        "85:",
+        // Save the caller-saved registers.  The asm services only touch a
+        // few of them, but services written in Rust (e.g. quote) are free
+        // to clobber any.  `fp` builds reserve the FP area above them
+        // (see TrapFrame).
+        "addi   sp, sp, -{frame}",
+        "sw     t0, {t0_slot}(sp)",
+        "la     t0, _trap_dispatch",
+
+        // ── Frame save, shared with the vector stubs ───────────────
+        // Entered on the trap stack with the frame allocated and t0
+        // saved; t0 holds where to continue once the rest of the frame
+        // is written.
+        "_trap_save:",
+        "sw     ra, {ra_slot}(sp)",
+        "sw     t1, {t1_slot}(sp)",
+        "sw     t2, {t2_slot}(sp)",
+        "sw     a0, {a0_slot}(sp)",
+        "sw     a1, {a1_slot}(sp)",
+        "sw     a2, {a2_slot}(sp)",
+        "sw     a7, {a7_slot}(sp)",
+        "sw     a3, {a3_slot}(sp)",
+        "sw     a4, {a4_slot}(sp)",
+        "sw     a5, {a5_slot}(sp)",
+        "sw     a6, {a6_slot}(sp)",
+        "sw     t3, {t3_slot}(sp)",
+        "sw     t4, {t4_slot}(sp)",
+        "sw     t5, {t5_slot}(sp)",
+        "sw     t6, {t6_slot}(sp)",
+
+        // This trap's CSRs and the interrupted sp.  Handlers update the
+        // mepc slot, not the CSR; _trap_return reloads both CSRs from
+        // here, so a nested trap (an interrupt inside a service, a
+        // faulting uaccess) can't send this one back to the wrong place.
+        "csrr   t1, mepc",
+        "sw     t1, {mepc_slot}(sp)",
+        "csrr   t1, mcause",
+        "sw     t1, {mcause_slot}(sp)",
+        "csrr   t1, mstatus",
+        "sw     t1, {mstatus_slot}(sp)",
+        "srli   t1, t1, 11",            // mstatus.MPP
+        "andi   t1, t1, 3",
+        "addi   t1, t1, -3",
+        "csrrw  t2, mscratch, zero",    // in M-mode from here on
+        "bnez   t1, 86f",
+        "addi   t2, sp, {frame}",       // from M-mode: sp above the frame
+        "86:",
+        "sw     t2, {sp_slot}(sp)",     // from U-mode: U's sp (was in mscratch)
+
+        // fp builds: save f0-f31 + fcsr unless mstatus.FS = Off, and
+        // record FS so the return path knows what to restore
+        ".if {fp}",
+        "csrr   t1, mstatus",
+        "li     t2, {fs_mask}",
+        "and    t1, t1, t2",
+        "sw     t1, {fs_slot}(sp)",
+        "beqz   t1, 80f",
+        ".option push",
+        ".option arch, +f",
+        "fsw    f0, {f_slot}(sp)",
+        "fsw    f1, {f_slot}+4(sp)",
+        "fsw    f2, {f_slot}+8(sp)",
+        "fsw    f3, {f_slot}+12(sp)",
+        "fsw    f4, {f_slot}+16(sp)",
+        "fsw    f5, {f_slot}+20(sp)",
+        "fsw    f6, {f_slot}+24(sp)",
+        "fsw    f7, {f_slot}+28(sp)",
+        "fsw    f8, {f_slot}+32(sp)",
+        "fsw    f9, {f_slot}+36(sp)",
+        "fsw    f10, {f_slot}+40(sp)",
+        "fsw    f11, {f_slot}+44(sp)",
+        "fsw    f12, {f_slot}+48(sp)",
+        "fsw    f13, {f_slot}+52(sp)",
+        "fsw    f14, {f_slot}+56(sp)",
+        "fsw    f15, {f_slot}+60(sp)",
+        "fsw    f16, {f_slot}+64(sp)",
+        "fsw    f17, {f_slot}+68(sp)",
+        "fsw    f18, {f_slot}+72(sp)",
+        "fsw    f19, {f_slot}+76(sp)",
+        "fsw    f20, {f_slot}+80(sp)",
+        "fsw    f21, {f_slot}+84(sp)",
+        "fsw    f22, {f_slot}+88(sp)",
+        "fsw    f23, {f_slot}+92(sp)",
+        "fsw    f24, {f_slot}+96(sp)",
+        "fsw    f25, {f_slot}+100(sp)",
+        "fsw    f26, {f_slot}+104(sp)",
+        "fsw    f27, {f_slot}+108(sp)",
+        "fsw    f28, {f_slot}+112(sp)",
+        "fsw    f29, {f_slot}+116(sp)",
+        "fsw    f30, {f_slot}+120(sp)",
+        "fsw    f31, {f_slot}+124(sp)",
+        "csrr   t1, fcsr",
+        "sw     t1, {fcsr_slot}(sp)",
+        ".option pop",
+        "80:",
+        ".endif",
+        // t0 is exempt from landing-pad checks, like t2
+        "jr     t0",
+
+        // ── Dispatch on mcause (direct mode, and vectored exceptions) ─
+        "_trap_dispatch:",
+        "lw     t0, {mcause_slot}(sp)",
+
+        // Check for environment call from U-mode (cause = 8)
+        "li     t1, 8",
+        "beq    t0, t1, _handle_ecall",
+
+        // Environment call from S-mode (9) or M-mode (11): no services
+        // for either, rejected rather than halting
+        "li     t1, 9",
+        "beq    t0, t1, _handle_foreign_ecall",
+        "li     t1, 11",
+        "beq    t0, t1, _handle_foreign_ecall",
+
+        // Check for illegal instruction (cause = 2) — skip it
+        "li     t1, 2",
+        "beq    t0, t1, _handle_illegal",
+
+        // Check for software-check exception (cause = 18) — CFI violation
+        "li     t1, 18",
+        "beq    t0, t1, _handle_cfi_violation",
+
+        // Check for instruction access fault (cause = 1) — landing pad violation
+        "li     t1, 1",
+        "beq    t0, t1, _handle_cfi_violation",
+
+        // Load / store access fault (cause = 5 / 7) — fixed up if it came
+        // from a uaccess routine, fatal otherwise
+        "li     t1, 5",
+        "beq    t0, t1, _handle_access_fault",
+        "li     t1, 7",
+        "beq    t0, t1, _handle_access_fault",
+
+        // misalign-fixup builds: load / store address misaligned (cause =
+        // 4 / 6) — redone a byte at a time if it came from U-mode
+        ".if {misalign}",
+        "li     t1, 4",
+        "beq    t0, t1, _handle_misaligned",
+        "li     t1, 6",
+        "beq    t0, t1, _handle_misaligned",
+        ".endif",
+
+        // Breakpoint (cause = 3): a shadow-stack mismatch's ebreak, or
+        // one of the tests' deliberate ones
+        "li     t1, 3",
+        "beq    t0, t1, _handle_breakpoint",
+
+        // Machine timer interrupt (Interrupt bit | 7)
+        "li     t1, 0x80000007",
+        "beq    t0, t1, _handle_timer_irq",
+
+        // Machine external interrupt (Interrupt bit | 11) — PLIC
+        "li     t1, 0x8000000B",
+        "beq    t0, t1, _handle_external_irq",
+
+        // Unknown trap — halt
+        "j      _handle_unknown_trap",
+
+        // ── Ecall handler ──────────────────────────────────────────
+        "_handle_ecall:",
+        // Advance the saved mepc past the 4-byte ecall instruction (ecall
+        // has no compressed form).  _trap_return reloads mepc and
+        // mstatus (MPP = U) from the frame, so the mret resumes U-mode at
+        // the instruction after its ecall whatever the service did.
+        "lw     t0, {mepc_slot}(sp)",
+        "addi   t0, t0, 4",
+        "sw     t0, {mepc_slot}(sp)",
+
+        // ecall-audit: record it before anything is served, yield and
+        // monitor calls included.  Like _call_m_isr without the return:
+        // the caller is U-mode, so M-mode's SW shadow stack goes live for
+        // the call.  Only the frame has to survive it, and the loads
+        // below come from there.
+        ".if {audit}",
+        "addi   sp, sp, -16",
+        "sw     x{ss}, 0(sp)",
+        "la     x{ss}, _m_sw_shadow_stack_bottom",
+        "addi   a0, sp, 16",
+        "call   audit_ecall",
+        "lw     x{ss}, 0(sp)",
+        "addi   sp, sp, 16",
+        ".endif",
+
+        // Dispatch on a7 (syscall number)
+        "lw     a7, {a7_slot}(sp)",
+        "lw     a0, {a0_slot}(sp)",
+        "lw     a1, {a1_slot}(sp)",
+
+        // a7 bit 31 set: monitor call — its own namespace, in Rust
+        "bltz   a7, _monitor_call",
+
+        // syscall 6: yield() — wait for an interrupt, then return
+        "li     t1, 6",
+        "bne    a7, t1, 73f",
+        // Only the timer is guaranteed to fire again: the console
+        // interrupt goes quiet once the TX ring is empty, and waiting on
+        // it could park the hart in wfi for good.  Until timers land,
+        // yield is an immediate return.
+        "csrr   t0, mie",
+        "andi   t0, t0, {mtie}",
+        "beqz   t0, _trap_return",
+        "wfi",
+        "j      _trap_return",
+
+        // syscalls 16-18: task_spawn(a0 = entry, a1 = arg), task_wait(),
+        // task_exit(a0 = code) (sched-demo) — Rust, through the task
+        // switch: they read and write the frame themselves
+        "73:",
+        ".if {sched}",
+        "li     t1, 16",
+        "bne    a7, t1, 74f",
+        "la     t2, sys_task_spawn",
+        "j      _context_switch",
+        "74:",
+        "li     t1, 17",
+        "bne    a7, t1, 75f",
+        "la     t2, sys_task_wait",
+        "j      _context_switch",
+        "75:",
+        "li     t1, 18",
+        "bne    a7, t1, 79f",
+        "la     t2, sys_task_exit",
+        "j      _context_switch",
+        ".endif",
+
+        // syscall 20: upcall_return() (timer-upcall) — Rust, rewrites the
+        // context through the switch
+        "79:",
+        ".if {upcall}",
+        "li     t1, 20",
+        "bne    a7, t1, _registered_syscall",
+        "la     t2, sys_upcall_return",
+        "j      _context_switch",
+        ".endif",
+
+        // Everything else: the registered services (SYSCALLS), found and
+        // run by sys_dispatch on the frame it is handed.  It writes the
+        // results (or -2 for a number nobody registered) into the frame,
+        // which _trap_return reloads.
+        "_registered_syscall:",
+        "mv     a0, sp",
+        "la     t2, sys_dispatch",
+        "j      _call_m_isr",
+
+        // ── Monitor call (a0, a2 = arguments, a1 = token, a7 = number) ──
+        "_monitor_call:",
+        "lw     a3, {a2_slot}(sp)",
+        "mv     a2, a7",
+        "la     t2, sys_monitor",
+        "j      _call_m_service",
+
+        // ── Access fault: uaccess fixup ─────────────────────────────
+        // A U-mode-privileged (MPRV) access in [_uaccess_start,
+        // _uaccess_end) that PMP refused resumes at _uaccess_fault, which
+        // makes the routine return -1.  In `stack-guard-test` builds the
+        // one store in u_stack_guard_test that is meant to hit U_GUARD
+        // resumes at u_guard_overrun_caught, and in `app-isolation-test`
+        // builds the probe load in u_app_isolation_test resumes at
+        // u_app_probe_caught.  Anything else is fatal.
+        "_handle_access_fault:",
+        "lw     t0, {mepc_slot}(sp)",
+        ".if {app_test}",
+        "la     t1, u_app_probe_load",
+        "bne    t0, t1, 84f",
+        "la     t0, u_app_probe_caught",
+        "sw     t0, {mepc_slot}(sp)",
+        "j      _trap_return",
+        "84:",
+        ".endif",
+        ".if {guard_test}",
+        "la     t1, u_guard_overrun_store",
+        "bne    t0, t1, 89f",
+        "la     t0, u_guard_overrun_caught",
+        "sw     t0, {mepc_slot}(sp)",
+        "j      _trap_return",
+        "89:",
+        ".endif",
+        "la     t1, _uaccess_start",
+        "bltu   t0, t1, _handle_fatal_trap",
+        "la     t1, _uaccess_end",
+        "bgeu   t0, t1, _handle_fatal_trap",
+        "la     t0, _uaccess_fault",
+        "sw     t0, {mepc_slot}(sp)",
+        "j      _trap_return",
+
+        // ── Misaligned load / store (misalign-fixup) ───────────────
+        // The destination or source may be any register, callee-saved
+        // ones included, so this goes through the context switch, which
+        // hands trap_misaligned the CalleeSaved block as well.
+        ".if {misalign}",
+        "_handle_misaligned:",
+        "la     t2, trap_misaligned",
+        "j      _context_switch",
+        ".endif",
+
+        // ── External interrupt: claim/complete loop in Rust ────────
+        // mepc already points at the interrupted instruction.
+        "_handle_external_irq:",
+        "la     t2, irq_external",
+        "j      _call_m_isr",
+
+        // ── Timer interrupt: CLINT driver in Rust ──────────────────
+        // sched-demo builds: the time slice, which may switch tasks;
+        // timer-upcall builds: the upcall tick, which may enter the handler
+        "_handle_timer_irq:",
+        ".if {sched}",
+        "la     t2, sched_tick",
+        "j      _context_switch",
+        ".elseif {upcall}",
+        "la     t2, upcall_tick",
+        "j      _context_switch",
+        ".else",
+        "la     t2, irq_timer",
+        "j      _call_m_isr",
+        ".endif",
+
+        // ── Vector table (vectored-traps builds) ───────────────────
+        // With mtvec.MODE = 1, exceptions enter at the base and interrupt
+        // cause N at base + 4*N.  The timer and external entries skip the
+        // mcause compare chain: their stubs start the frame and hand
+        // _trap_save their handler.  Every other entry re-dispatches on
+        // mcause.  Each entry is one 4-byte `j`, so RVC stays off here.
+        // Only causes 0-11 are listed: the platform's local interrupts
+        // (16+) are never enabled in mie.
+        ".if {vectored}",
+        ".pushsection .text.trap_vector, \"ax\", @progbits",
+        ".balign {vector_align}",
+        ".globl _trap_vector",
+        "_trap_vector:",
+        ".option push",
+        ".option norvc",
+        "j      _trap_handler",   // 0: exceptions
+        "j      _trap_handler",   // 1: supervisor software
+        "j      _trap_handler",   // 2: reserved
+        "j      _trap_handler",   // 3: machine software
+        "j      _trap_handler",   // 4: reserved
+        "j      _trap_handler",   // 5: supervisor timer
+        "j      _trap_handler",   // 6: reserved
+        "j      _vec_timer",      // 7: machine timer
+        "j      _trap_handler",   // 8: reserved
+        "j      _trap_handler",   // 9: supervisor external
+        "j      _trap_handler",   // 10: reserved
+        "j      _vec_external",   // 11: machine external
+        ".option pop",
+        "_vec_timer:",
+        "csrrw  sp, mscratch, sp",     // to the trap stack, as above
+        "bnez   sp, 87f",
+        "csrrw  sp, mscratch, sp",
+        "87:",
+        "addi   sp, sp, -{frame}",
+        "sw     t0, {t0_slot}(sp)",
+        "la     t0, _handle_timer_irq",
+        "j      _trap_save",
+        "_vec_external:",
+        "csrrw  sp, mscratch, sp",
+        "bnez   sp, 88f",
+        "csrrw  sp, mscratch, sp",
+        "88:",
+        "addi   sp, sp, -{frame}",
+        "sw     t0, {t0_slot}(sp)",
+        "la     t0, _handle_external_irq",
+        "j      _trap_save",
+        ".popsection",
+        ".endif",
+
+        // ── Rust service call (t2 = service, a0..a2 = arguments) ───
+        // Services may handle key material; they run on the trap stack
+        // the frame is on (never U-mode's), with the M-mode SW shadow
+        // stack in SW_SS_REG.  The call goes through t2, which Zicfilp
+        // treats as a software-guarded branch (no landing pad needed in
+        // Rust code).  A service may take a nested trap — a faulting
+        // uaccess read, or an interrupt if it sets mstatus.MIE — which
+        // pushes its own frame below this one; the CSRs it clobbers are
+        // reloaded from this frame in _trap_return.
+        //
+        // _call_m_service returns the result in the caller's a0;
+        // _call_m_isr (interrupts, sys_dispatch) leaves every register as
+        // the frame holds it.  A trap from M-mode (MPP = M: boot, or
+        // nested) already has the M-mode SW shadow stack live in
+        // SW_SS_REG, so it is kept.
+        "_call_m_service:",
+        "li     t3, 1",
+        "j      70f",
+        "_call_m_isr:",
+        "li     t3, 0",
+        "70:",
+        "addi   sp, sp, -16",
+        "sw     x{ss}, 0(sp)",
+        "sw     t3, 4(sp)",
+        "lw     t1, 16+{mstatus_slot}(sp)",
+        "li     t4, 3 << 11",     // mstatus.MPP
+        "and    t1, t1, t4",
+        "beq    t1, t4, 72f",
+        "la     x{ss}, _m_sw_shadow_stack_bottom",
+        "72:",
+        "jalr   ra, t2, 0",
+        "lw     t3, 4(sp)",
+        "lw     x{ss}, 0(sp)",
+        "addi   sp, sp, 16",
+        "beqz   t3, _trap_return",
+        "sw     a0, {a0_slot}(sp)",      // result -> a0 on return
+        "j      _trap_return",
+
+        // ── Context switch (sched-demo, timer-upcall, misalign-fixup,
+        // ss-emulate, cfi-contain; t2 = handler(regs, frame)) ──
+        // Like _call_m_isr, but the handler may swap the whole U-mode
+        // context for another: a task's, or an upcall's (entering the
+        // handler or resuming what it interrupted).  The frame holds only
+        // part of it (misalign-fixup and ss-emulate only rewrite one
+        // register of it):
+        // the callee-saved registers are still live, so they go into a
+        // CalleeSaved block below the frame first, and the handler gets
+        // pointers to both.  It rewrites them (and ssp, mstatush and the
+        // application entries) in place, and the pops here plus
+        // _trap_return then resume whichever task it picked.  The block
+        // is a multiple of 16 bytes, so sp stays ABI-aligned.
+        ".if {sched} | {upcall} | {misalign} | {ss_emulate} | {contain}",
+        "_context_switch:",
+        "addi   sp, sp, -{regs}",
+        "sw     s0, {s_slot}(sp)",
+        "sw     s1, {s_slot}+4(sp)",
+        "sw     s2, {s_slot}+8(sp)",
+        "sw     s3, {s_slot}+12(sp)",
+        "sw     s4, {s_slot}+16(sp)",
+        "sw     s5, {s_slot}+20(sp)",
+        "sw     s6, {s_slot}+24(sp)",
+        "sw     s7, {s_slot}+28(sp)",
+        "sw     s8, {s_slot}+32(sp)",
+        "sw     s9, {s_slot}+36(sp)",
+        "sw     s10, {s_slot}+40(sp)",
+        "sw     s11, {s_slot}+44(sp)",
+        "sw     gp, {gp_slot}(sp)",
+        "sw     tp, {tp_slot}(sp)",
+        "mv     a0, sp",
+        "addi   a1, sp, {regs}",
+        "lw     t1, {regs}+{mstatus_slot}(sp)",
+        "li     t4, 3 << 11",     // mstatus.MPP
+        "and    t1, t1, t4",
+        "beq    t1, t4, 76f",
+        "la     x{ss}, _m_sw_shadow_stack_bottom",
+        "76:",
+        "jalr   ra, t2, 0",
+        "lw     s0, {s_slot}(sp)",
+        "lw     s1, {s_slot}+4(sp)",
+        "lw     s2, {s_slot}+8(sp)",
+        "lw     s3, {s_slot}+12(sp)",
+        "lw     s4, {s_slot}+16(sp)",
+        "lw     s5, {s_slot}+20(sp)",
+        "lw     s6, {s_slot}+24(sp)",
+        "lw     s7, {s_slot}+28(sp)",
+        "lw     s8, {s_slot}+32(sp)",
+        "lw     s9, {s_slot}+36(sp)",
+        "lw     s10, {s_slot}+40(sp)",
+        "lw     s11, {s_slot}+44(sp)",
+        "lw     gp, {gp_slot}(sp)",
+        "lw     tp, {tp_slot}(sp)",
+        "addi   sp, sp, {regs}",
+        "j      _trap_return",
+        ".endif",
+
+        // ── Ecall from S-mode or M-mode ────────────────────────────
+        // Resume after the ecall with trap_foreign_ecall's error in a0
+        "_handle_foreign_ecall:",
+        "lw     t0, {mepc_slot}(sp)",
+        "addi   t0, t0, 4",
+        "sw     t0, {mepc_slot}(sp)",
+        "mv     a0, sp",
+        "la     t2, trap_foreign_ecall",
+        "j      _call_m_isr",
+
+        // ── Illegal instruction handler ────────────────────────────
+        // Skip 2-byte (compressed) or 4-byte instruction
+        "_handle_illegal:",
+        // ss-emulate builds: from U-mode it may be a shadow-stack
+        // instruction on a core without Zimop, which trap_ss_emulate
+        // carries out (or skips, if it isn't one).  It may read or write
+        // any register, so this goes through the context switch.
+        ".if {ss_emulate}",
+        "lw     t0, {mstatus_slot}(sp)",
+        "li     t1, 3 << 11",           // mstatus.MPP
+        "and    t0, t0, t1",
+        "bnez   t0, 42f",
+        "la     t2, trap_ss_emulate",
+        "j      _context_switch",
+        "42:",
+        ".endif",
+        "lw     t0, {mepc_slot}(sp)",
+        "lhu    t1, 0(t0)",
+        "andi   t1, t1, 0x3",
+        "li     t2, 0x3",
+        "bne    t1, t2, 40f",
+        "addi   t0, t0, 4",       // 4-byte instruction
+        "j      41f",
+        "40: addi t0, t0, 2",     // 2-byte compressed
+        "41: sw t0, {mepc_slot}(sp)",
+        "j      _trap_return",
+
+        // ── CFI violation handler ──────────────────────────────────
+        // On real hardware this is a security-critical event.
+        // Options: halt, reset, log + quarantine, etc.
+        "_handle_cfi_violation:",
+        // wx-selftest builds: u_wx_selftest's jump into U_RAM, the one
+        // trap here whose saved ra is u_wx_return, is recorded by
+        // trap_wx_selftest and resumes at u_wx_fault_caught instead of
+        // halting.
+        ".if {wx_test}",
+        "lw     t0, {ra_slot}(sp)",
+        "la     t1, u_wx_return",
+        "bne    t0, t1, 87f",
+        "lw     a0, {mcause_slot}(sp)",
+        "lw     a1, {mepc_slot}(sp)",
+        "la     t0, u_wx_fault_caught",
+        "sw     t0, {mepc_slot}(sp)",
+        "la     t2, trap_wx_selftest",
+        "j      _call_m_service",
+        "87:",
+        ".endif",
+        // sig-label-demo builds: the demo's mislabelled call into
+        // u_sig_scale is refused through trap_sig_mismatch, which hands
+        // the refusal back to the call site instead of halting.  The
+        // call resumes at its return address.
+        ".if {sig_demo}",
+        "lw     t0, {mepc_slot}(sp)",
+        "la     t1, u_sig_scale",
+        "bne    t0, t1, 88f",
+        "lw     a0, {mcause_slot}(sp)",
+        "mv     a1, t0",
+        "csrr   a2, mtval",
+        "lw     a3, {t2_slot}(sp)",     // t2: the caller's label
+        "lw     t0, {ra_slot}(sp)",
+        "sw     t0, {mepc_slot}(sp)",
+        "la     t2, trap_sig_mismatch",
+        "j      _call_m_service",
+        "88:",
+        ".endif",
+        // cfi-contain builds: a violation in U-mode goes to
+        // trap_cfi_contain, which ends the offending context instead of
+        // the system; it may hand the hart to another task, hence the
+        // context switch.  One in M-mode falls through and halts.  This
+        // comes first, so it decides when quarantine-policy is built too.
+        ".if {contain}",
+        "lw     t0, {mstatus_slot}(sp)",
+        "li     t1, 3 << 11",           // mstatus.MPP
+        "and    t0, t0, t1",
+        "bnez   t0, 86f",
+        "la     t2, trap_cfi_contain",
+        "j      _context_switch",
+        "86:",
+        ".endif",
+        // quarantine-policy builds: a violation in U-mode goes to
+        // trap_cfi_quarantine on the trap stack.  If it takes the
+        // region's execute permission away it points this frame's mepc
+        // at the recovery entry and returns; otherwise it halts.
+        ".if {quarantine}",
+        "lw     t0, {mstatus_slot}(sp)",
+        "li     t1, 3 << 11",           // mstatus.MPP
+        "and    t0, t0, t1",
+        "bnez   t0, 89f",
+        "lw     a0, {mcause_slot}(sp)",
+        "lw     a1, {mepc_slot}(sp)",
+        "csrr   a2, mtval",
+        "addi   a3, sp, {mepc_slot}",
+        "la     t2, trap_cfi_quarantine",
+        "j      _call_m_isr",
+        "89:",
+        ".endif",
+        // Hard fault — flag it ("CFI!"), report and halt the system
+        "la     sp, _m_stack_top",
+        "csrr   a0, mcause",
+        "csrr   a1, mepc",
+        "csrr   a2, mtval",
+        "j      trap_cfi_violation",
+
+        // ── Breakpoints ────────────────────────────────────────────
+        // Only the tests' own ebreaks are expected.  Resume the
+        // fault-inject test at its "caught" path, which reports PASS and
+        // unwinds normally.  The ROP demo's mismatch is reported from
+        // here, then the demo returns through the shadow-stack copy of
+        // ra.  The breakpoint test gets its ebreak's address back in a0,
+        // as recorded by trap_breakpoint_test.  Any other breakpoint —
+        // in production, a shadow-stack mismatch — halts.
+        "_handle_breakpoint:",
+        "lw     t0, {mepc_slot}(sp)",
+        ".if {fault_inject}",
+        "la     t1, u_fault_inject_trap",
+        "bne    t0, t1, 91f",
+        "la     t0, u_fault_inject_caught",
+        "sw     t0, {mepc_slot}(sp)",
+        "j      _trap_return",
+        "91:",
+        ".endif",
+        ".if {rop_demo}",
+        "la     t1, u_rop_trap",
+        "bne    t0, t1, 91f",
+        "la     t0, u_rop_recovered",
+        "sw     t0, {mepc_slot}(sp)",
+        "lw     a0, {ra_slot}(sp)",     // ra: the forged return address
+        "lw     a1, {t0_slot}(sp)",     // t0: the shadow-stack copy
+        "la     t2, trap_rop_blocked",
+        "j      _call_m_isr",
+        "91:",
+        ".endif",
+        ".if {bp_test}",
+        "la     t1, u_breakpoint_trap",
+        "bne    t0, t1, 91f",
+        "la     t1, u_breakpoint_resume",
+        "sw     t1, {mepc_slot}(sp)",
+        "mv     a0, t0",
+        "la     t2, trap_breakpoint_test",
+        "j      _call_m_service",
+        "91:",
+        ".endif",
+        // cfi-contain builds: from U-mode this is the software shadow
+        // stack refusing a return, contained like the hardware faults.
+        ".if {contain}",
+        "lw     t0, {mstatus_slot}(sp)",
+        "li     t1, 3 << 11",           // mstatus.MPP
+        "and    t0, t0, t1",
+        "bnez   t0, 91f",
+        "la     t2, trap_cfi_contain",
+        "j      _context_switch",
+        "91:",
+        ".endif",
+        "la     sp, _m_stack_top",
+        "csrr   a0, mcause",
+        "csrr   a1, mepc",
+        "csrr   a2, mtval",
+        "j      trap_breakpoint",
+
+        // ── Unknown trap ───────────────────────────────────────────
+        "_handle_unknown_trap:",
+
+        // ── Fatal trap: decode mcause in Rust, then halt ───────────
+        // The interrupted sp may belong to U-mode (or be garbage), so
+        // switch to a fresh M-mode stack — we never return from here.
+        "_handle_fatal_trap:",
+        "la     sp, _m_stack_top",
+        "csrr   a0, mcause",
+        "csrr   a1, mepc",
+        "csrr   a2, mtval",
+        "j      trap_fatal",
+
+        // ── Trap return ────────────────────────────────────────────
+        "_trap_return:",
+        // This trap's own mepc and mstatus, whatever nested traps left in
+        // the CSRs.  Returning to U-mode empties the trap stack, so its
+        // top goes back into mscratch for the next trap.
+        "lw     t0, {mepc_slot}(sp)",
+        "csrw   mepc, t0",
+        "lw     t0, {mstatus_slot}(sp)",
+        "csrw   mstatus, t0",
+        "srli   t0, t0, 11",      // mstatus.MPP
+        "andi   t0, t0, 3",
+        "li     t1, 3",
+        "beq    t0, t1, 82f",
+        "addi   t0, sp, {frame}",
+        "csrw   mscratch, t0",
+        "82:",
+        // fp builds: restore the FP state saved on entry, then put FS back
+        // to its entry value — the restore itself would leave it Dirty
+        ".if {fp}",
+        "lw     t0, {fs_slot}(sp)",
+        "beqz   t0, 81f",
+        ".option push",
+        ".option arch, +f",
+        "flw    f0, {f_slot}(sp)",
+        "flw    f1, {f_slot}+4(sp)",
+        "flw    f2, {f_slot}+8(sp)",
+        "flw    f3, {f_slot}+12(sp)",
+        "flw    f4, {f_slot}+16(sp)",
+        "flw    f5, {f_slot}+20(sp)",
+        "flw    f6, {f_slot}+24(sp)",
+        "flw    f7, {f_slot}+28(sp)",
+        "flw    f8, {f_slot}+32(sp)",
+        "flw    f9, {f_slot}+36(sp)",
+        "flw    f10, {f_slot}+40(sp)",
+        "flw    f11, {f_slot}+44(sp)",
+        "flw    f12, {f_slot}+48(sp)",
+        "flw    f13, {f_slot}+52(sp)",
+        "flw    f14, {f_slot}+56(sp)",
+        "flw    f15, {f_slot}+60(sp)",
+        "flw    f16, {f_slot}+64(sp)",
+        "flw    f17, {f_slot}+68(sp)",
+        "flw    f18, {f_slot}+72(sp)",
+        "flw    f19, {f_slot}+76(sp)",
+        "flw    f20, {f_slot}+80(sp)",
+        "flw    f21, {f_slot}+84(sp)",
+        "flw    f22, {f_slot}+88(sp)",
+        "flw    f23, {f_slot}+92(sp)",
+        "flw    f24, {f_slot}+96(sp)",
+        "flw    f25, {f_slot}+100(sp)",
+        "flw    f26, {f_slot}+104(sp)",
+        "flw    f27, {f_slot}+108(sp)",
+        "flw    f28, {f_slot}+112(sp)",
+        "flw    f29, {f_slot}+116(sp)",
+        "flw    f30, {f_slot}+120(sp)",
+        "flw    f31, {f_slot}+124(sp)",
+        "lw     t1, {fcsr_slot}(sp)",
+        "csrw   fcsr, t1",
+        ".option pop",
+        "li     t1, {fs_mask}",
+        "csrc   mstatus, t1",
+        "csrs   mstatus, t0",
+        "81:",
+        ".endif",
+        "lw     ra, {ra_slot}(sp)",
+        "lw     t0, {t0_slot}(sp)",
+        "lw     t1, {t1_slot}(sp)",
+        "lw     t2, {t2_slot}(sp)",
+        "lw     a0, {a0_slot}(sp)",
+        "lw     a1, {a1_slot}(sp)",
+        "lw     a2, {a2_slot}(sp)",
+        "lw     a7, {a7_slot}(sp)",
+        "lw     a3, {a3_slot}(sp)",
+        "lw     a4, {a4_slot}(sp)",
+        "lw     a5, {a5_slot}(sp)",
+        "lw     a6, {a6_slot}(sp)",
+        "lw     t3, {t3_slot}(sp)",
+        "lw     t4, {t4_slot}(sp)",
+        "lw     t5, {t5_slot}(sp)",
+        "lw     t6, {t6_slot}(sp)",
+        "lw     sp, {sp_slot}(sp)",
+        "mret",
+        fault_inject = const cfg!(feature = "fault-inject") as u32,
+        rop_demo = const cfg!(feature = "rop-demo") as u32,
+        bp_test = const cfg!(feature = "breakpoint-test") as u32,
+        guard_test = const cfg!(feature = "stack-guard-test") as u32,
+        app_test = const cfg!(feature = "app-isolation-test") as u32,
+        quarantine = const cfg!(feature = "quarantine-policy") as u32,
+        contain = const cfg!(feature = "cfi-contain") as u32,
+        wx_test = const cfg!(feature = "wx-selftest") as u32,
+        sig_demo = const cfg!(feature = "sig-label-demo") as u32,
+        sched = const cfg!(feature = "sched-demo") as u32,
+        upcall = const TIMER_UPCALL as u32,
+        misalign = const cfg!(feature = "misalign-fixup") as u32,
+        ss_emulate = const cfg!(feature = "ss-emulate") as u32,
+        audit = const cfg!(feature = "ecall-audit") as u32,
+        regs = const size_of::<CalleeSaved>(),
+        s_slot = const offset_of!(CalleeSaved, s),
+        gp_slot = const offset_of!(CalleeSaved, gp),
+        tp_slot = const offset_of!(CalleeSaved, tp),
+        mtie = const MIE_MTIE,
+        frame = const TRAP_FRAME_SIZE,
+        fp = const cfg!(feature = "fp") as u32,
+        fs_mask = const MSTATUS_FS,
+        fs_slot = const TRAP_FRAME_FS,
+        fcsr_slot = const TRAP_FRAME_FCSR,
+        f_slot = const TRAP_FRAME_F,
+        ra_slot = const offset_of!(TrapFrame, ra),
+        t0_slot = const offset_of!(TrapFrame, t0),
+        t1_slot = const offset_of!(TrapFrame, t1),
+        t2_slot = const offset_of!(TrapFrame, t2),
+        a0_slot = const offset_of!(TrapFrame, a0),
+        a1_slot = const offset_of!(TrapFrame, a1),
+        a2_slot = const offset_of!(TrapFrame, a2),
+        a7_slot = const offset_of!(TrapFrame, a7),
+        a3_slot = const offset_of!(TrapFrame, a3),
+        a4_slot = const offset_of!(TrapFrame, a4),
+        a5_slot = const offset_of!(TrapFrame, a5),
+        a6_slot = const offset_of!(TrapFrame, a6),
+        t3_slot = const offset_of!(TrapFrame, t3),
+        t4_slot = const offset_of!(TrapFrame, t4),
+        t5_slot = const offset_of!(TrapFrame, t5),
+        t6_slot = const offset_of!(TrapFrame, t6),
+        mepc_slot = const TRAP_FRAME_MEPC,
+        mstatus_slot = const TRAP_FRAME_MSTATUS,
+        mcause_slot = const TRAP_FRAME_MCAUSE,
+        sp_slot = const TRAP_FRAME_SP,
+        vectored = const cfg!(feature = "vectored-traps") as u32,
+        vector_align = const MTVEC_VECTOR_ALIGN,
+        ss = const SW_SS_REG,
+    )
+}
+
+/// CFI violation: print the `CFI!` marker, then report and halt like any
+/// other fatal trap.  Entered from `_trap_handler` on a fresh M-mode stack.
+#[no_mangle]
+extern "C" fn trap_cfi_violation(mcause: usize, mepc: usize, mtval: usize) -> ! {
+    uart_puts("CFI!\n");
+    trap_fatal(mcause, mepc, mtval)
+}
+
+/// `shadow-trace`: report a software shadow-stack mismatch that an
+/// epilogue is about to return through.  `ss` is the shadow-stack pointer
+/// after the pop, so the depth counts the frames still below this one.
+#[cfg(feature = "shadow-trace")]
+fn report_shadow_mismatch(umode: bool, shadow_ra: usize, ra: usize, ss: usize) {
+    let depth = ss.wrapping_sub(layout::sw_shadow_stack_bottom(umode)) / 4;
+    let _ = write!(
+        UartWriter,
+        "[SHADOW] {}-mode mismatch: shadow ra {:#010x}, stack ra {:#010x}, depth {} — returning to stack ra\r\n",
+        if umode { "U" } else { "M" },
+        shadow_ra,
+        ra,
+        depth
+    );
+}
+
+/// Syscall 15 (`shadow-trace` builds), from [`_u_shadow_trace`].
+#[cfg(feature = "shadow-trace")]
+fn sys_shadow_trace(shadow_ra: usize, ra: usize, ss: usize) -> usize {
+    report_shadow_mismatch(true, shadow_ra, ra, ss);
+    0
+}
+
+#[cfg(feature = "shadow-trace")]
+#[no_mangle]
+extern "C" fn shadow_trace_m(shadow_ra: usize, ra: usize, ss: usize) {
+    report_shadow_mismatch(false, shadow_ra, ra, ss);
+}
+
+/// `shadow-trace` builds: the M-mode epilogues' mismatch path.  Entered by
+/// `jal t0` with t1 = shadow ra, ra = the stack's ra; reports through
+/// [`shadow_trace_m`] and returns to t0 with ra, t0, a0 and a1 (the
+/// return value) as they were.  The other caller-saved registers are
+/// dead in an epilogue.
+///
+/// # Safety
+///
+/// Only for the `99:` mismatch tails, with [`SW_SS_REG`] still pointing
+/// into the M-mode software shadow stack.
+#[cfg(feature = "shadow-trace")]
+#[unsafe(naked)]
+#[no_mangle]
+unsafe extern "C" fn _m_shadow_trace() {
+    naked_asm!(
+        "addi   sp, sp, -16",
+        "sw     ra, 0(sp)",
+        "sw     t0, 4(sp)",
+        "sw     a0, 8(sp)",
+        "sw     a1, 12(sp)",
+        "mv     a0, t1",
+        "mv     a1, ra",
+        "mv     a2, x{ss}",
+        "call   shadow_trace_m",
+        "lw     ra, 0(sp)",
+        "lw     t0, 4(sp)",
+        "lw     a0, 8(sp)",
+        "lw     a1, 12(sp)",
+        "addi   sp, sp, 16",
+        "jr     t0",                // t0: exempt from landing-pad checks
+        ss = const SW_SS_REG,
+    )
+}
+
+/// `shadow-trace` builds: the U-mode epilogues' mismatch path, as
+/// [`_m_shadow_trace`] but reporting by syscall 15.  The ecall preserves
+/// everything but a0, so only the argument registers are saved.
+///
+/// # Safety
+///
+/// U-mode code, only for the `99:` mismatch tails.
+#[cfg(feature = "shadow-trace")]
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".u_text"]
+unsafe extern "C" fn _u_shadow_trace() {
+    naked_asm!(
+        "addi   sp, sp, -16",
+        "sw     a0, 0(sp)",
+        "sw     a1, 4(sp)",
+        "sw     a2, 8(sp)",
+        "sw     a7, 12(sp)",
+        "mv     a0, t1",
+        "mv     a1, ra",
+        "mv     a2, x{ss}",
+        "li     a7, 15",
+        "ecall",
+        "lw     a0, 0(sp)",
+        "lw     a1, 4(sp)",
+        "lw     a2, 8(sp)",
+        "lw     a7, 12(sp)",
+        "addi   sp, sp, 16",
+        "jr     t0",
+        ss = const SW_SS_REG,
+    )
+}
+
+/// Shadow-stack divergences [`sys_ss_divergence`] has reported.
+#[cfg(feature = "ss-crosscheck")]
+static SS_DIVERGENCES: AtomicU32 = AtomicU32::new(0);
+
+/// Syscall 21 (`ss-crosscheck` builds), from [`_u_ss_crosscheck`]: at a
+/// U-mode return the hardware shadow stack's top, `hw_ra`, wasn't the
+/// software one's, `sw_ra`.  The software stack is the authority, so the
+/// report names the hardware entry as the one that was wrong:
+///
+/// ```text
+///   [SS-XCHECK] divergence #1: SW ra 0x80020104 (authoritative), HW ra 0x80020230, SW depth 3
+/// ```
+///
+/// `ss` is the software shadow-stack pointer after the pop.
+#[cfg(feature = "ss-crosscheck")]
+fn sys_ss_divergence(sw_ra: usize, hw_ra: usize, ss: usize) -> usize {
+    let n = SS_DIVERGENCES.fetch_add(1, Ordering::Relaxed) + 1;
+    let depth = ss.wrapping_sub(layout::sw_shadow_stack_bottom(true)) / 4;
+    let _ = write!(
+        UartWriter,
+        "[SS-XCHECK] divergence #{}: SW ra {:#010x} (authoritative), HW ra {:#010x}, SW depth {}\r\n\
+         [SS-XCHECK] dropping the HW entry, returning to SW ra\r\n",
+        n, sw_ra, hw_ra, depth
+    );
+    0
+}
+
+/// `ss-crosscheck` builds: the U-mode epilogues' hardware shadow-stack
+/// pop, in place of `sspopchk ra`.  Entered by `jal t0` once the
+/// software compare has passed, so ra is the software stack's return
+/// address — the authoritative one.
+///
+/// Reads the hardware top (`ssrdp`, then a load: shadow-stack memory
+/// takes ordinary loads) and compares.  If they agree it runs the usual
+/// `sspopchk ra`.  If not it reports through syscall 21
+/// ([`sys_ss_divergence`]), then pops the hardware entry with
+/// `sspopchk t0` against its own value, so the hardware stack stays in
+/// step without faulting, and returns to t0 with ra unchanged.  Without
+/// an active hardware shadow stack `ssrdp` reads 0 and there is nothing
+/// to check.  a0, a1 (the return value) and ra are preserved.
+///
+/// # Safety
+///
+/// U-mode code, only in place of an epilogue's `sspopchk ra`.
+#[cfg(feature = "ss-crosscheck")]
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".u_text"]
+unsafe extern "C" fn _u_ss_crosscheck() {
+    naked_asm!(
+        "li     t1, 0",
+        ".4byte {ssrdp_t1}",        // ssrdp t1 (0 if inactive)
+        "beqz   t1, 1f",
+        "lw     t1, 0(t1)",         // the hardware top
+        "bne    t1, ra, 2f",
+        "1:",
+        ".4byte {sspopchk_ra}",     // sspopchk ra (HW)
+        "jr     t0",                // t0: exempt from landing-pad checks
+
+        // ── Divergence: report, then drop the hardware entry ──
+        "2:",
+        "addi   sp, sp, -16",
+        "sw     a0, 0(sp)",
+        "sw     a1, 4(sp)",
+        "sw     a2, 8(sp)",
+        "sw     a7, 12(sp)",
+        "mv     a0, ra",
+        "mv     a1, t1",
+        "mv     a2, x{ss}",
+        "li     a7, 21",
+        "ecall",
+        "lw     a0, 0(sp)",
+        "lw     a1, 4(sp)",
+        "lw     a2, 8(sp)",
+        "lw     a7, 12(sp)",
+        "addi   sp, sp, 16",
+        "mv     t2, t0",
+        "mv     t0, t1",
+        ".4byte {sspopchk_t0}",     // sspopchk t0: its own entry, matches
+        "jr     t2",                // t2: software-guarded, no landing pad
+        ss = const SW_SS_REG,
+        ssrdp_t1 = const cfi_encoding::SSRDP_T1,
+        sspopchk_ra = const cfi_encoding::SSPOPCHK_RA,
+        sspopchk_t0 = const cfi_encoding::SSPOPCHK_T0,
+    )
+}
+
+/// `quarantine-policy`: contain a forward-edge CFI violation from U-mode
+/// instead of halting.
+///
+/// The jump target ([`trap::forward_edge_target`]) must lie in an
+/// executable region of the running application that isn't already
+/// quarantined.  That region loses execute permission, now and on every
+/// later [`switch_to_app`], and the trap returns to
+/// [`u_quarantine_recovery`] through `frame_mepc`.  Anything else (a
+/// shadow-stack fault, a target outside the application's code, a second
+/// violation in the same region) is reported and halts like the default
+/// policy.
+///
+/// [`trap::forward_edge_target`]: riscv_rot_cfi::trap::forward_edge_target
+#[cfg(feature = "quarantine-policy")]
+#[no_mangle]
+extern "C" fn trap_cfi_quarantine(mcause: usize, mepc: usize, mtval: usize, frame_mepc: *mut usize) {
+    let app = CURRENT_APP.load(Ordering::Relaxed) as usize;
+    let target = riscv_rot_cfi::trap::forward_edge_target(mcause, mepc, mtval);
+    let region = target.and_then(|t| APPS[app].code_region_of(t as u32));
+    let (Some(target), Some(i)) = (target, region) else {
+        trap_cfi_violation(mcause, mepc, mtval)
+    };
+    let bit = quarantine_bit(app, i);
+    if QUARANTINED.fetch_or(bit, Ordering::Relaxed) & bit != 0 {
+        trap_cfi_violation(mcause, mepc, mtval)
+    }
+    // Entries 3-6 can't be locked while a U-mode application is running:
+    // nothing locks them, and the launch switched to app 0 through them.
+    if switch_to_app(app).is_err() {
+        trap_cfi_violation(mcause, mepc, mtval)
+    }
+    let code = APPS[app].regions()[i];
+    uart_puts("CFI!\n");
+    let _ = write!(
+        UartWriter,
+        "[CFI] quarantine: {} to {:#010x} (mepc {:#010x})\r\n\
+         [CFI]   application {} region {:#010x}+{:#x} -> no-execute (PMP entry {})\r\n\
+         [CFI]   resuming U-mode at u_quarantine_recovery\r\n",
+        TrapCause::from_mcause(mcause),
+        target,
+        mepc,
+        app,
+        code.base,
+        code.size,
+        APP_PMP_ENTRIES[i]
+    );
+    // SAFETY: `frame_mepc` is this trap's frame slot, which
+    // _trap_return loads into mepc.
+    unsafe { frame_mepc.write(u_quarantine_recovery as *const () as usize) };
+}
+
+/// Exit code of a context [`trap_cfi_contain`] terminated: task 1's,
+/// handed to `task_wait`, or the run's, from [`u_cfi_signal`].
+#[cfg(feature = "cfi-contain")]
+const CFI_FAULT_EXIT: usize = 0xcf;
+
+/// `cfi-contain`: terminate the U-mode context that violated CFI instead
+/// of halting.  From `_context_switch`, for a hardware CFI fault or the
+/// software shadow stack's `ebreak`.
+///
+/// The origin decides ([`trap::containment`]): a violation anywhere but
+/// U-mode is reported and halts, as without the feature.  Otherwise the
+/// running application is marked in [`FAULTED_APPS`], so it is never
+/// switched to again, and the violation is logged.  Then, if task 1 of
+/// `sched-demo` was running, it ends with [`CFI_FAULT_EXIT`] and the
+/// scheduler resumes task 0.  Anything else is the main application:
+/// its context is abandoned and the trap returns to [`u_cfi_signal`],
+/// with mcause, mepc and mtval in a0-a2.
+#[cfg(feature = "cfi-contain")]
+#[no_mangle]
+extern "C" fn trap_cfi_contain(regs: &mut CalleeSaved, frame: &mut TrapFrame) {
+    // Before any nested trap overwrites it.
+    let mtval = csr::read::<{ csr::MTVAL }>();
+    let (mcause, mepc) = (frame.mcause, frame.mepc);
+    if trap::containment(frame.mstatus) == Containment::Fatal {
+        trap_cfi_violation(mcause, mepc, mtval)
+    }
+    let app = CURRENT_APP.load(Ordering::Relaxed) as usize;
+    FAULTED_APPS.fetch_or(1 << app, Ordering::Relaxed);
+    uart_puts("CFI!\n");
+    let _ = write!(
+        UartWriter,
+        "[CFI] contained: {} in U-mode at {:#010x} (mtval {:#010x})\r\n\
+         [CFI]   application {} terminated\r\n",
+        TrapCause::from_mcause(mcause),
+        mepc,
+        mtval,
+        app
+    );
+
+    #[cfg(feature = "sched-demo")]
+    {
+        let ended = SCHED.with(|s| {
+            let task1 = s.current == 1;
+            if task1 {
+                s.end_task1(CFI_FAULT_EXIT, regs, frame);
+            }
+            task1
+        });
+        if ended == Some(true) {
+            uart_puts("[CFI]   task 1 ended, resuming task 0\r\n");
+            return;
+        }
+    }
+    #[cfg(not(feature = "sched-demo"))]
+    let _ = regs;
+
+    // The signal runs in the main application's code.
+    if app != 0 && switch_to_app(0).is_err() {
+        trap_cfi_violation(mcause, mepc, mtval)
+    }
+    uart_puts("[CFI]   resuming U-mode at u_cfi_signal\r\n");
+    frame.mepc = u_cfi_signal as *const () as usize;
+    (frame.a0, frame.a1, frame.a2) = (mcause, mepc, mtval);
+}
+
+/// `rop-demo`: the demo's forged return address was caught by the
+/// software shadow-stack compare.  Reports the two addresses; the trap
+/// then resumes at `u_rop_recovered`.  Called from `_trap_handler` on the
+/// trap stack, with the trapping ra and t0.
+#[cfg(feature = "rop-demo")]
+#[no_mangle]
+extern "C" fn trap_rop_blocked(forged: usize, shadow: usize) {
+    let _ = write!(
+        UartWriter,
+        "[ROP] return to {:#010x} refused: the shadow stack holds {:#010x}\r\n\
+         [ROP] ROP blocked: PASS\r\n",
+        forged, shadow
+    );
+}
+
+/// `sig-label-demo`: the demo's call into [`u_sig_scale`] with another
+/// signature's label was refused.  Anything but a landing-pad fault at
+/// that entry is reported and halts like any other violation.  Otherwise
+/// the call returns -1 to its caller, with MPELP cleared: the return
+/// address isn't a landing pad.  Called from `_trap_handler` on the trap
+/// stack, with the trapping t2.
+#[cfg(feature = "sig-label-demo")]
+#[no_mangle]
+extern "C" fn trap_sig_mismatch(mcause: usize, mepc: usize, mtval: usize, t2: usize) -> usize {
+    if riscv_rot_cfi::trap::forward_edge_target(mcause, mepc, mtval) != Some(mepc) {
+        trap_cfi_violation(mcause, mepc, mtval)
+    }
+    // SAFETY: only the landing-pad state the trap saved changes, and the
+    // trap resumes at the caller's return address, which has no lpad.
+    unsafe { csr::clear::<{ csr::MSTATUSH }>(MSTATUSH_MPELP) };
+    let _ = write!(
+        UartWriter,
+        "[SIGLABEL] call to u_sig_scale (label {:#07x}) with label {:#07x} refused: {}\r\n",
+        riscv_rot_cfi::sig_label!(fn(u32, u32) -> u32),
+        t2 >> 12,
+        TrapCause::from_mcause(mcause)
+    );
+    usize::MAX
+}
+
+/// Breakpoint nothing expected: print the `BREAKPOINT!` marker, then
+/// report and halt like any other fatal trap.  Entered from
+/// `_trap_handler` on a fresh M-mode stack.
+///
+/// The software shadow-stack epilogues end a mismatch in `ebreak`, so
+/// outside the tests this is normally a refused return, not a debugger.
+#[no_mangle]
+extern "C" fn trap_breakpoint(mcause: usize, mepc: usize, mtval: usize) -> ! {
+    uart_puts("BREAKPOINT!\n");
+    let _ = write!(
+        UartWriter,
+        "[TRAP] unexpected ebreak at {:#010x} (shadow-stack mismatch?)\r\n",
+        mepc
+    );
+    trap_fatal(mcause, mepc, mtval)
+}
+
+/// `breakpoint-test`: address of the last breakpoint
+/// [`trap_breakpoint_test`] took.
+#[cfg(feature = "breakpoint-test")]
+static BREAKPOINT_PC: AtomicUsize = AtomicUsize::new(0);
+
+/// `breakpoint-test`: `u_breakpoint_test`'s ebreak at `pc`.  Records and
+/// reports it; the trap then resumes at `u_breakpoint_resume` with the
+/// recorded address in a0, for the test to check.
+#[cfg(feature = "breakpoint-test")]
+#[no_mangle]
+extern "C" fn trap_breakpoint_test(pc: usize) -> usize {
+    BREAKPOINT_PC.store(pc, Ordering::Relaxed);
+    let _ = write!(UartWriter, "[BREAKPOINT] ebreak at {:#010x} recorded, resuming\r\n", pc);
+    BREAKPOINT_PC.load(Ordering::Relaxed)
+}
+
+/// `wx-selftest`: address of the U_RAM fetch [`trap_wx_selftest`] saw
+/// fault.
+#[cfg(feature = "wx-selftest")]
+static WX_FAULT_PC: AtomicUsize = AtomicUsize::new(0);
+
+/// `wx-selftest`: `u_wx_selftest`'s jump into U_RAM trapped with `mcause`
+/// at `pc`.  Records and reports it; the trap then resumes at
+/// `u_wx_fault_caught` with the address in a0 if it was the instruction
+/// access fault W^X calls for, or 0 if it was any other trap.
+#[cfg(feature = "wx-selftest")]
+#[no_mangle]
+extern "C" fn trap_wx_selftest(mcause: usize, pc: usize) -> usize {
+    let cause = TrapCause::from_mcause(mcause);
+    let _ = write!(UartWriter, "[WX] fetch from U_RAM at {:#010x}: {}, resuming\r\n", pc, cause);
+    if cause != TrapCause::InstructionAccessFault {
+        return 0;
+    }
+    WX_FAULT_PC.store(pc, Ordering::Relaxed);
+    WX_FAULT_PC.load(Ordering::Relaxed)
+}
+
+/// Report a trap the kernel cannot recover from, then halt (or reset,
+/// see [`fatal_stop`]).
+///
+/// Entered from `_trap_handler` (on a fresh M-mode stack) with the raw trap
+/// CSRs.  Prints e.g. `trap: SoftwareCheck` instead of leaving the reader
+/// to decode mcause by hand.
+#[no_mangle]
+extern "C" fn trap_fatal(mcause: usize, mepc: usize, mtval: usize) -> ! {
+    let cause = TrapCause::from_mcause(mcause);
+    uart_puts("\r\n[TRAP] fatal ");
+    uart_puts(if cause.is_interrupt() { "interrupt" } else { "exception" });
+    let _ = write!(UartWriter, "\r\n  trap: {}\r\n", cause);
+    uart_puts("  mcause: ");
+    uart_put_hex32(mcause as u32);
+    uart_puts("  mepc: ");
+    uart_put_hex32(mepc as u32);
+    uart_puts("  mtval: ");
+    uart_put_hex32(mtval as u32);
+    uart_newline();
+    fatal_stop()
+}
+
+// ============================================================================
+// Misaligned Access Fixup (misalign-fixup)
+// ============================================================================
+
+/// Misaligned accesses [`trap_misaligned`] has emulated.
+#[cfg(feature = "misalign-fixup")]
+static MISALIGN_FIXUPS: AtomicU32 = AtomicU32::new(0);
+
+/// Load / store address misaligned (mcause 4 / 6) in `misalign-fixup`
+/// builds, from `_context_switch`: redo the access a byte at a time with
+/// U-mode's permissions ([`uaccess`]), write a load's result into its
+/// destination register and step mepc over the instruction.  The first
+/// fixup of the boot is reported, the rest are silent.
+///
+/// Fatal, as without the feature, unless the trap came from U-mode and
+/// the instruction is one [`misalign`] decodes and U-mode may read and
+/// the bytes lie where U-mode may access them.
+#[cfg(feature = "misalign-fixup")]
+#[no_mangle]
+extern "C" fn trap_misaligned(regs: &mut CalleeSaved, frame: &mut TrapFrame) {
+    // Before any uaccess fault overwrites it.
+    let mtval = csr::read::<{ csr::MTVAL }>();
+    let mepc = frame.mepc;
+    if fixup_misaligned(regs, frame).is_none() {
+        trap_fatal(frame.mcause, mepc, mtval);
+    }
+    if MISALIGN_FIXUPS.fetch_add(1, Ordering::Relaxed) == 0 {
+        let _ = write!(
+            UartWriter,
+            "[MISALIGN] emulated a misaligned access at {:#010x} (address {:#010x}); further ones silently\r\n",
+            mepc, mtval
+        );
+    }
+}
+
+#[cfg(feature = "misalign-fixup")]
+fn fixup_misaligned(regs: &mut CalleeSaved, frame: &mut TrapFrame) -> Option<()> {
+    if frame.mstatus & (3 << 11) != 0 {
+        return None; // MPP != U: M-mode code must not rely on the fixup
+    }
+    let mut insn = [0u8; 4];
+    uaccess::copy_from_user(&mut insn[..2], frame.mepc).then_some(())?;
+    if misalign::insn_len(u16::from_le_bytes([insn[0], insn[1]])) == 4 {
+        uaccess::copy_from_user(&mut insn[2..], frame.mepc + 2).then_some(())?;
+    }
+    let op = misalign::decode(u32::from_le_bytes(insn))?;
+    let addr = op.addr(trapped_reg(regs, frame, op.rs1).map_or(0, |r| *r));
+    let mut buf = [0u8; 4];
+    let bytes = &mut buf[..op.size()];
+    match op.access {
+        Access::Load { rd, signed, .. } if frame.mcause == 4 => {
+            uaccess::copy_from_user(bytes, addr).then_some(())?;
+            if let Some(r) = trapped_reg(regs, frame, rd) {
+                *r = misalign::load_value(bytes, signed) as usize;
+            }
+        }
+        Access::Store { rs2, .. } if frame.mcause == 6 => {
+            let value = trapped_reg(regs, frame, rs2).map_or(0, |r| *r);
+            bytes.copy_from_slice(&value.to_le_bytes()[..op.size()]);
+            uaccess::copy_to_user(addr, bytes).then_some(())?;
+        }
+        _ => return None, // the instruction isn't the access that trapped
+    }
+    frame.mepc += op.insn_len;
+    Some(())
+}
+
+/// Where the trapped context's `x<n>` is kept while the trap runs: the
+/// frame for the caller-saved registers and sp, the [`CalleeSaved`] block
+/// for the rest.  `None` for x0.
+#[cfg(any(feature = "misalign-fixup", feature = "ss-emulate"))]
+fn trapped_reg<'a>(regs: &'a mut CalleeSaved, frame: &'a mut TrapFrame, n: u8) -> Option<&'a mut usize> {
+    Some(match n {
+        1 => &mut frame.ra,
+        2 => &mut frame.sp,
+        3 => &mut regs.gp,
+        4 => &mut regs.tp,
+        5 => &mut frame.t0,
+        6 => &mut frame.t1,
+        7 => &mut frame.t2,
+        8 | 9 => &mut regs.s[n as usize - 8],
+        10 => &mut frame.a0,
+        11 => &mut frame.a1,
+        12 => &mut frame.a2,
+        13 => &mut frame.a3,
+        14 => &mut frame.a4,
+        15 => &mut frame.a5,
+        16 => &mut frame.a6,
+        17 => &mut frame.a7,
+        18..=27 => &mut regs.s[n as usize - 16],
+        28 => &mut frame.t3,
+        29 => &mut frame.t4,
+        30 => &mut frame.t5,
+        31 => &mut frame.t6,
+        _ => return None,
+    })
+}
+
+// ============================================================================
+// Shadow-Stack Emulation (ss-emulate)
+// ============================================================================
+
+/// Whether U-mode's `ssp` is [`EMULATED_SSP`] rather than the CSR: set by
+/// [`launch_umode`] when Phase 1 found no `ssp` CSR.
+#[cfg(feature = "ss-emulate")]
+static SSP_EMULATED: AtomicBool = AtomicBool::new(false);
+
+/// The `ssp` [`trap_ss_emulate`] pushes and pops at, in place of the CSR.
+#[cfg(feature = "ss-emulate")]
+static EMULATED_SSP: AtomicUsize = AtomicUsize::new(0);
+
+/// Shadow-stack instructions [`trap_ss_emulate`] has emulated.
+#[cfg(feature = "ss-emulate")]
+static SS_EMULATIONS: AtomicU32 = AtomicU32::new(0);
+
+/// The trapped context's shadow-stack pointer, for the scheduler and the
+/// upcall code to save: the `ssp` CSR, or the emulated one if that is
+/// what U-mode has.
+#[cfg(any(feature = "sched-demo", feature = "timer-upcall"))]
+fn read_ssp() -> usize {
+    #[cfg(feature = "ss-emulate")]
+    if SSP_EMULATED.load(Ordering::Relaxed) {
+        return EMULATED_SSP.load(Ordering::Relaxed);
+    }
+    csr::read::<{ csr::SSP }>()
+}
+
+/// Restore a pointer [`read_ssp`] saved.
+///
+/// # Safety
+///
+/// As for writing the `ssp` CSR: `ssp` must be the shadow stack of the
+/// context about to resume.
+#[cfg(any(feature = "sched-demo", feature = "timer-upcall"))]
+unsafe fn write_ssp(ssp: usize) {
+    #[cfg(feature = "ss-emulate")]
+    if SSP_EMULATED.load(Ordering::Relaxed) {
+        EMULATED_SSP.store(ssp, Ordering::Relaxed);
+        return;
+    }
+    unsafe { csr::write::<{ csr::SSP }>(ssp) }
+}
+
+/// Illegal instruction from U-mode in `ss-emulate` builds, from
+/// `_context_switch`.  On a core with neither Zicfiss nor Zimop every
+/// prologue's `sspush ra` and epilogue's `sspopchk ra` lands here: carry
+/// it out against [`EMULATED_SSP`] with U-mode's permissions
+/// ([`uaccess`]), in the lower half of the running application's shadow
+/// region, and step mepc over it.  The first emulation of the boot is
+/// reported, the rest are silent.
+///
+/// An `sspopchk` that finds another return address on top is the
+/// software-check exception Zicfiss would raise: a CFI violation.  So is
+/// a push or pop outside the region.  Anything [`ss_emulate::decode`]
+/// doesn't know is skipped, as it is without the feature; one U-mode
+/// can't read is fatal.
+#[cfg(feature = "ss-emulate")]
+#[no_mangle]
+extern "C" fn trap_ss_emulate(regs: &mut CalleeSaved, frame: &mut TrapFrame) {
+    let mepc = frame.mepc;
+    let mut insn = [0u8; 4];
+    let readable = uaccess::copy_from_user(&mut insn[..2], mepc)
+        && (insn_len(u16::from_le_bytes([insn[0], insn[1]])) == 2
+            || uaccess::copy_from_user(&mut insn[2..], mepc + 2));
+    if !readable {
+        trap_fatal(frame.mcause, mepc, csr::read::<{ csr::MTVAL }>());
+    }
+    let insn = u32::from_le_bytes(insn);
+    let Some((op, len)) = ss_emulate::decode(insn) else {
+        frame.mepc += insn_len(insn as u16);
+        return;
+    };
+    if let Err(fault) = emulate_ss(op, regs, frame) {
+        let _ = match fault {
+            SsFault::Mismatch { expected, found } => write!(
+                UartWriter,
+                "[SS-EMU] sspopchk at {:#010x}: shadow stack holds {:#010x}, register {:#010x}\r\n",
+                mepc, found, expected
+            ),
+            SsFault::Bounds { addr } => write!(
+                UartWriter,
+                "[SS-EMU] shadow-stack access at {:#010x} outside the shadow stack ({:#010x})\r\n",
+                mepc, addr
+            ),
+        };
+        trap_cfi_violation(18, mepc, riscv_rot_cfi::trap::SOFTWARE_CHECK_SHADOW_STACK);
+    }
+    frame.mepc += len;
+    if SS_EMULATIONS.fetch_add(1, Ordering::Relaxed) == 0 {
+        let _ = write!(
+            UartWriter,
+            "[SS-EMU] emulated {:?} at {:#010x}; further ones silently\r\n",
+            op, mepc
+        );
+    }
+}
+
+#[cfg(feature = "ss-emulate")]
+fn emulate_ss(op: SsInsn, regs: &mut CalleeSaved, frame: &mut TrapFrame) -> Result<(), SsFault> {
+    let shadow = APPS[CURRENT_APP.load(Ordering::Relaxed) as usize].shadow;
+    let bounds = Region::new(shadow.base as usize, shadow.size as usize / 2);
+    let mut ss = ShadowStack::new(bounds, EMULATED_SSP.load(Ordering::Relaxed));
+    let mut reg = |n| trapped_reg(regs, frame, n).map_or(0, |r| *r);
+    match op {
+        SsInsn::Push { rs } => ss.push(reg(rs), |addr, value| uaccess::copy_to_user(addr, &value.to_le_bytes()))?,
+        SsInsn::PopChk { rs } => ss.pop_check(reg(rs), |addr| {
+            let mut entry = [0u8; ss_emulate::ENTRY_LEN];
+            uaccess::copy_from_user(&mut entry, addr).then(|| usize::from_le_bytes(entry))
+        })?,
+        SsInsn::ReadPointer { rd } => {
+            if let Some(r) = trapped_reg(regs, frame, rd) {
+                *r = ss.ssp;
+            }
+        }
+        SsInsn::Csr { op, rd, src } => {
+            let value = match src {
+                CsrSrc::Reg(rs) => reg(rs),
+                CsrSrc::Imm(imm) => imm as usize,
+            };
+            let old = ss.ssp;
+            if let Some(new) = op.apply(old, src, value) {
+                ss.ssp = new;
+            }
+            if let Some(r) = trapped_reg(regs, frame, rd) {
+                *r = old;
+            }
+        }
+    }
+    EMULATED_SSP.store(ss.ssp, Ordering::Relaxed);
+    Ok(())
+}
+
+// ============================================================================
+// Stack Canaries (stack-canary)
+// ============================================================================
+//
+// Every `#[cfi_target]` frame holds a canary below its saved ra, checked
+// after the body returns and before the shadow-stack compare.  A linear
+// overflow up the stack has to go through it on the way to ra, so it is
+// caught, and reported as such, even where the shadow stack would catch
+// it later.  The hand-written naked functions keep their frames as they
+// are.
+
+/// The canary in M-mode frames.  Set from the DRBG at boot, before any
+/// `#[cfi_target]` function runs.
+#[cfg(feature = "stack-canary")]
+#[export_name = "_m_stack_canary"]
+static M_STACK_CANARY: AtomicU32 = AtomicU32::new(0);
+
+/// The canary in U-mode frames: in U_RODATA, so U-mode can load it but
+/// not rewrite it, and distinct from M-mode's, so reading it tells U-mode
+/// nothing about that one.
+#[cfg(feature = "stack-canary")]
+#[export_name = "_u_stack_canary"]
+#[link_section = ".u_rodata.canary"]
+static U_STACK_CANARY: AtomicU32 = AtomicU32::new(0);
+
+/// Draw both canaries from the DRBG.  Must run before the first
+/// `#[cfi_target]` call and, in `lock-u-pmp` builds, before U_RODATA is
+/// locked.
+#[cfg(feature = "stack-canary")]
+fn seed_stack_canaries() {
+    let mut words = [0u8; 8];
+    if DRBG.with(|d| d.as_mut().map(|d| d.generate(&mut words))).flatten().is_none() {
+        panic!("no DRBG for the stack canaries");
+    }
+    M_STACK_CANARY.store(u32::from_le_bytes([words[0], words[1], words[2], words[3]]), Ordering::Relaxed);
+    U_STACK_CANARY.store(u32::from_le_bytes([words[4], words[5], words[6], words[7]]), Ordering::Relaxed);
+    secure_zero(&mut words);
+    uart_puts("[CANARY] stack canaries seeded from the DRBG\r\n");
+}
+
+#[cfg(feature = "stack-canary")]
+fn report_canary(umode: bool, found: usize, ra: usize) -> ! {
+    uart_puts("CANARY!\n");
+    let _ = write!(
+        UartWriter,
+        "[CANARY] {}-mode stack canary overwritten: found {:#010x}, saved ra {:#010x} — stack overflow\r\n",
+        if umode { "U" } else { "M" },
+        found,
+        ra
+    );
+    fatal_stop()
+}
+
+/// `stack-canary` builds: an M-mode `#[cfi_target]` frame's canary
+/// changed while its body ran.  Entered by `j` from the epilogue, with
+/// the frame's canary slot and saved ra.
+#[cfg(feature = "stack-canary")]
+#[no_mangle]
+extern "C" fn _m_canary_fail(found: usize, ra: usize) -> ! {
+    report_canary(false, found, ra)
+}
+
+/// Syscall 24 (`stack-canary` builds), from [`_u_canary_fail`].
+#[cfg(feature = "stack-canary")]
+fn sys_stack_canary_fail(found: usize, ra: usize) -> ! {
+    report_canary(true, found, ra)
+}
+
+/// `stack-canary` builds: the U-mode epilogues' canary failure, as
+/// [`_m_canary_fail`] but reporting by syscall 24.
+///
+/// # Safety
+///
+/// U-mode code, only for the `97:` canary tails.
+#[cfg(feature = "stack-canary")]
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".u_text"]
+unsafe extern "C" fn _u_canary_fail() {
+    naked_asm!(
+        "li     a7, 24",
+        "ecall",
+    )
+}
+
+/// `stack-canary-test`: a classic overflow.  Fills a 16-byte local
+/// buffer, and everything above it up to `top`, with 0x41414141 — a copy
+/// whose length nobody checked.  `_u_entry` passes its own sp, so the
+/// write runs through this function's `#[cfi_target]` frame: the canary,
+/// then the saved x{ss} and ra.  The epilogue's canary check must halt
+/// before the shadow-stack compare sees the forged ra.
+///
+/// # Safety
+///
+/// U-mode code, and it never returns.
+#[cfg(feature = "stack-canary-test")]
+#[cfi_target(label = cfi_labels::UNLABELED)]
+#[no_mangle]
+#[link_section = ".u_text"]
+pub unsafe extern "C" fn u_canary_victim(top: usize) {
+    let mut buf = [0u32; 4];
+    // SAFETY: none; the point is the overflow.  Only this frame and the
+    // trampoline's, both dead once the canary check halts, are written.
+    unsafe {
+        asm!(
+            "1:",
+            "sw     {v}, 0({p})",
+            "addi   {p}, {p}, 4",
+            "bltu   {p}, {top}, 1b",
+            p = inout(reg) buf.as_mut_ptr() => _,
+            top = in(reg) top,
+            v = in(reg) 0x4141_4141u32,
+        );
+    }
+    core::hint::black_box(&buf);
+}
+
+// ============================================================================
+// M-Mode Protected Functions (with full CFI)
+// ============================================================================
+
+/// Measure a firmware image: its SHA-256 digest into `digest`, which boot
+/// extends into [`PCR_FIRMWARE`], and the XOR of its little-endian words
+/// as the return value, the 32-bit check word syscall 9 reports.
+///
+/// A real RoT would also compare the digest against a known-good one
+/// stored in OTP/fuses.
+///
+/// This function demonstrates full CFI protection on an M-mode function,
+/// written as ordinary Rust under [`cfi_target`]:
+///   - Landing pad (forward-edge)
+///   - HW + SW shadow stack (backward-edge)
+///
+/// Any `size` is measured in full by both: SHA-256 pads its last block
+/// itself, and [`xor_words`] folds a tail shorter than a word in
+/// zero-padded.  A region that runs past the top of the address space
+/// halts before either reads it; one ending exactly at 2^32 is measured.
+///
+/// # Safety
+///
+/// `base..base+size` must be readable memory, and [`SW_SS_REG`] must
+/// point into a valid software shadow stack.
+#[cfi_target(label = cfi_labels::CRYPTO)]
+#[no_mangle]
+pub unsafe extern "C" fn rot_measure_firmware(base: u32, size: u32, digest: &mut [u8; DIGEST_LEN]) -> u32 {
+    if base as u64 + size as u64 > 1 << 32 {
+        panic!("[MEASURE] FAIL: {base:#010x} + {size:#x} wraps the address space");
+    }
+    let region = core::slice::from_raw_parts(base as *const u8, size as usize);
+    *digest = sha256(region);
+    xor_words(region)
+}
+
+/// Seal a secret using the hardware-bound key (stub).
+///
+/// In a real RoT with a key manager, this would use the device identity
+/// key (DevID) or a derived key to encrypt/HMAC the data.  Here the key is
+/// key slot `key_id` once `provision_key` has written it ([`KEY_SLOTS`]).
+/// Demonstrates a labeled landing pad: on Zicfilp hardware only indirect
+/// callers that set `t2` to the [`cfi_labels::CRYPTO`] label can reach it.
+///
+/// # Safety
+///
+/// [`SW_SS_REG`] must point into a valid software shadow stack.
+#[cfi_target(label = cfi_labels::CRYPTO)]
+#[no_mangle]
+pub unsafe extern "C" fn rot_seal_secret(data: u32, key_id: u32) -> u32 {
+    // Stub: XOR data with a word derived from the provisioned key, or with
+    // key_id while that slot is blank, as a placeholder for real crypto
+    let mask = KEY_SLOTS.with(|slots| slots.seal_key(key_id as usize).map(|key| hmac_sha256(key, b"rot seal")));
+    match mask.flatten() {
+        Some(mut tag) => {
+            let word = u32::from_le_bytes([tag[0], tag[1], tag[2], tag[3]]);
+            secure_zero(&mut tag);
+            data ^ word
+        }
+        None => data ^ key_id,
+    }
+}
+
+// ============================================================================
+// Policy Sealing (policy-seal)
+// ============================================================================
+//
+// Secrets sealed to a set of PCRs ([`riscv_rot_cfi::seal`]): the blob only
+// opens while those PCRs hold what they held at sealing time, so only on
+// the same ROM and firmware.  The boot PCRs are locked by Phase 4, so a
+// blob sealed to them opens for the rest of this boot and on every boot
+// of the same images; one sealed to PCR2 stops opening as soon as U-mode
+// extends it.
+
+/// Largest secret syscall 25 seals, and syscall 26 unseals.
+#[cfg(feature = "policy-seal")]
+const POLICY_SEAL_MAX: usize = 64;
+
+/// The key slot policy seals are derived from.
+#[cfg(feature = "policy-seal")]
+const POLICY_SEAL_SLOT: usize = 1;
+
+/// The secret policy seals are derived from: [`POLICY_SEAL_SLOT`] once
+/// provisioned, the device key while it is blank.  Sealing derives its
+/// keys from either through HKDF ([`seal::seal`]), so the quote keys
+/// never seal.
+#[cfg(feature = "policy-seal")]
+fn policy_seal_secret() -> [u8; KEY_LEN] {
+    KEY_SLOTS.with(|slots| slots.seal_key(POLICY_SEAL_SLOT).copied()).flatten().unwrap_or_else(device_secret)
+}
+
+/// Seal `data` to the PCRs in `pcr_mask` as they stand, with a nonce from
+/// the DRBG, writing the blob into `out` ([`seal::seal`]).
+#[cfg(feature = "policy-seal")]
+fn rot_seal_to_policy(data: &[u8], pcr_mask: u32, out: &mut [u8]) -> Result<usize, SealError> {
+    let entropy = |nonce: &mut [u8; seal::NONCE_LEN]| {
+        DRBG.with(|d| d.as_mut().map(|d| d.generate(nonce))).flatten().is_some()
+    };
+    let mut secret = policy_seal_secret();
+    let result = seal::seal(&secret, &pcr_values(), pcr_mask, entropy, data, out);
+    secure_zero(&mut secret);
+    result
+}
+
+/// Open `blob` against the PCRs as they stand, writing the secret into
+/// `out` ([`seal::unseal`]).  [`SealError::PolicyMismatch`] if a PCR the
+/// blob was sealed to has changed since.
+#[cfg(feature = "policy-seal")]
+fn rot_unseal_to_policy(blob: &[u8], out: &mut [u8]) -> Result<usize, SealError> {
+    let mut secret = policy_seal_secret();
+    let result = seal::unseal(&secret, &pcr_values(), blob, out);
+    secure_zero(&mut secret);
+    result
+}
+
+/// Syscall 25 (`policy-seal` builds): seal the `len` bytes at `data` to
+/// the PCRs in `mask` ([`rot_seal_to_policy`]) and copy the blob to the
+/// `out_len` bytes at `out`.  Returns the blob's length, `len` +
+/// [`seal::OVERHEAD`].  [`SYSCALL_ERR`] if `len` is over
+/// [`POLICY_SEAL_MAX`], the mask selects no PCR that exists, `out` is
+/// too small, or a buffer isn't the caller's.
+#[cfg(feature = "policy-seal")]
+fn sys_seal_policy(data: usize, len: usize, mask: usize, out: usize, out_len: usize) -> usize {
+    let mut secret = [0u8; POLICY_SEAL_MAX];
+    let mut blob = [0u8; POLICY_SEAL_MAX + seal::OVERHEAD];
+    if len > POLICY_SEAL_MAX || !uaccess::copy_from_user(&mut secret[..len], data) {
+        secure_zero(&mut secret);
+        return SYSCALL_ERR;
+    }
+    let result = rot_seal_to_policy(&secret[..len], mask as u32, &mut blob);
+    secure_zero(&mut secret);
+    match result {
+        Ok(n) if n <= out_len && uaccess::copy_to_user(out, &blob[..n]) => n,
+        _ => SYSCALL_ERR,
+    }
+}
+
+/// Syscall 26 (`policy-seal` builds): open the `len`-byte blob at `blob`
+/// ([`rot_unseal_to_policy`]) and copy the secret to the `out_len` bytes
+/// at `out`.  Returns the secret's length.  [`SYSCALL_ERR`] if the blob
+/// is malformed or altered, a PCR it was sealed to has changed, `out` is
+/// too small, or a buffer isn't the caller's.
+#[cfg(feature = "policy-seal")]
+fn sys_unseal_policy(blob: usize, len: usize, out: usize, out_len: usize) -> usize {
+    let mut sealed = [0u8; POLICY_SEAL_MAX + seal::OVERHEAD];
+    let mut secret = [0u8; POLICY_SEAL_MAX];
+    if len > sealed.len() || !uaccess::copy_from_user(&mut sealed[..len], blob) {
+        return SYSCALL_ERR;
+    }
+    let ret = match rot_unseal_to_policy(&sealed[..len], &mut secret) {
+        Ok(n) if n <= out_len && uaccess::copy_to_user(out, &secret[..n]) => n,
+        _ => SYSCALL_ERR,
+    };
+    secure_zero(&mut secret);
+    ret
+}
+
+/// Phase 4 (`policy-seal` builds): seal a secret to PCR0 and PCR1 and
+/// open it again, then check that the same blob is refused against a
+/// copy of the PCRs with PCR1 changed — what a different firmware image
+/// would have measured.  Halts if either comes out otherwise.
+#[cfg(feature = "policy-seal")]
+fn policy_seal_demo() {
+    const SECRET: &[u8] = b"policy-bound demo secret";
+    let mask = 1 << PCR_ROM | 1 << PCR_FIRMWARE;
+    let mut blob = [0u8; POLICY_SEAL_MAX + seal::OVERHEAD];
+    let mut out = [0u8; POLICY_SEAL_MAX];
+
+    let Ok(len) = rot_seal_to_policy(SECRET, mask, &mut blob) else {
+        panic!("policy seal failed");
+    };
+    let _ = write!(
+        UartWriter,
+        "  seal_to_policy({} bytes, PCR mask {:#x}) = {}-byte blob\r\n",
+        SECRET.len(), mask, len
+    );
+    let opened = rot_unseal_to_policy(&blob[..len], &mut out);
+    let ok = matches!(opened, Ok(n) if out[..n] == *SECRET);
+    secure_zero(&mut out);
+    uart_puts(if ok { "  unseal_to_policy(current PCRs) = OK\r\n" } else { "  unseal_to_policy(current PCRs) = FAIL\r\n" });
+
+    let mut pcrs = pcr_values();
+    pcrs[PCR_FIRMWARE as usize][0] ^= 1;
+    let mut secret = policy_seal_secret();
+    let changed = seal::unseal(&secret, &pcrs, &blob[..len], &mut out);
+    secure_zero(&mut secret);
+    let refused = changed == Err(SealError::PolicyMismatch);
+    uart_puts(if refused {
+        "  unseal_to_policy(PCR1 changed) = refused (policy mismatch)\r\n"
+    } else {
+        "  unseal_to_policy(PCR1 changed) = opened: FAIL\r\n"
+    });
+    if !ok || !refused {
+        panic!("policy sealing does not follow the PCRs");
+    }
+}
+
+// ============================================================================
+// U-Mode Launch
+// ============================================================================
+
+/// Halt unless U-mode can start at `entry`: halfword-aligned and inside
+/// U_CODE as PMP entry 3 actually grants it, executable.
+///
+/// The last check before the mret, whichever path chose the entry (the
+/// linked `_u_entry` or a net-loaded ELF's `e_entry`): an mret to a
+/// wrong mepc would otherwise only show up as an instruction fault in
+/// U-mode, with the boot already reported as launched.
+fn verify_u_entry(entry: usize) {
+    let cfg = PmpCfg::from_regs(&[csr::read::<{ csr::PMPCFG0 }>() as u32], PMP_ENTRY_U_CODE).unwrap_or(PmpCfg(0));
+    let region = cfg.region(csr::read::<{ csr::PMPADDR3 }>() as u32, csr::read::<{ csr::PMPADDR2 }>() as u32);
+    if let Err(e) = cfg.check_entry(region, entry as u32) {
+        let (base, size) = region.unwrap_or((0, 0));
+        let _ = write!(
+            UartWriter,
+            "[LAUNCH] FAIL: entry {:#010x} {} (PMP entry {}: {} {:#010x} +{:#x})\r\n",
+            entry, e, PMP_ENTRY_U_CODE, cfg, base, size
+        );
+        panic!("U-mode entry point is not in U_CODE");
+    }
+}
+
+/// Leave M-mode: `mret` into `mode` at `pc`, with `sp` as the stack
+/// pointer and `args` in a0-a2.  Every `mret` outside the trap
+/// handler goes through here.  The handler's own `mret` puts back the
+/// interrupted context's saved mstatus and mepc, so it has nothing to
+/// choose.
+///
+/// M-mode interrupts are masked first, as an interrupt taken before the
+/// `mret` would overwrite mepc and MPP.  [`privilege::mret_mstatus`] then
+/// sets MPP to `mode` and sets MPIE.  `mret` copies MPIE into MIE, so a
+/// lower mode starts with MIE set (it takes M-mode interrupts either way),
+/// and a return to M-mode gets back the interrupt state it had here.
+///
+/// # Safety
+///
+/// `pc` must be code that `mode` can run, prepared for this `sp` and
+/// `args`.  So must everything else it relies on: PMP, the shadow-stack
+/// pointers, and mscratch for the trap stack.
+#[inline(always)]
+unsafe fn return_to(mode: PrivMode, pc: usize, sp: usize, args: [usize; 3]) -> ! {
+    let mstatus = csr::read_clear::<{ csr::MSTATUS }>(MSTATUS_MIE);
+    csr::write::<{ csr::MSTATUS }>(privilege::mret_mstatus(mstatus, mode) & !MSTATUS_MIE);
+    csr::write::<{ csr::MEPC }>(pc);
+    asm!(
+        "mv     sp, {sp}",
+        "mret",
+        sp = in(reg) sp,
+        in("a0") args[0],
+        in("a1") args[1],
+        in("a2") args[2],
+        options(noreturn),
+    )
+}
+
+/// Drop privilege from M-mode to U-mode.
+///
+/// Initializes the U-mode shadow stack pointers and the trap stack, then
+/// hands over to [`return_to`], which sets MPP = User, mepc = `entry` and
+/// sp = `_u_stack_top`, and executes mret.
+///
+/// The hardware shadow-stack pointer is set only if Phase 1 found the
+/// `ssp` CSR (`cfi.ssp`).  On a core without Zicfiss the write would
+/// raise illegal-instruction.  The handler would skip it, but only
+/// because mscratch is still 0 at that point, so the trap stays on this
+/// stack.  Skipping the write keeps the launch sequence off the trap path
+/// altogether.
+///
+/// After mret:
+///   - Privilege level = U-mode
+///   - PMP enforcement active for all U-mode memory accesses
+///   - CFI enforcement active (Zicfilp landing pads + Zicfiss shadow stack)
+///   - U-mode cannot access M-mode memory regions
+fn launch_umode(args: EntryArgs, entry: usize, cfi: &CfiStatus) {
+    verify_u_entry(entry);
+    uart_puts("[LAUNCH] Dropping to U-mode...\r\n");
+    let _ = write!(UartWriter, "  mepc  -> {:#010x} (U-mode entry point, in U_CODE)\r\n", entry);
+    let _ = write!(
+        UartWriter,
+        "  PMP   -> application {} (entries 3-6)\r\n",
+        CURRENT_APP.load(Ordering::Relaxed)
+    );
+    uart_puts("  MPP   -> 0b00 (User mode)\r\n");
+    uart_puts("  sp    -> _u_stack_top\r\n");
+    uart_puts(if cfi.ssp {
+        "  ssp   -> _u_shadow_stack_top\r\n"
+    } else if cfg!(feature = "ss-emulate") {
+        "  ssp   -> _u_shadow_stack_top (emulated: no ssp CSR)\r\n"
+    } else {
+        "  ssp   -> not set (no ssp CSR: Zicfiss absent)\r\n"
+    });
+    uart_puts(if cfg!(feature = "sw-ss-s11") {
+        "  s11   -> _u_sw_shadow_stack_bottom\r\n"
+    } else {
+        "  gp    -> _u_sw_shadow_stack_bottom\r\n"
+    });
+    uart_puts("  a0    -> monitor-call token\r\n");
+    let _ = write!(UartWriter, "  a1    -> hartid {}\r\n", args.hartid);
+    let _ = write!(UartWriter, "  a2    -> boot info @ {:#010x} (U_RODATA, read-only)\r\n", args.boot_info);
+    #[cfg(feature = "fp")]
+    enable_fp();
+    uart_newline();
+
+    // Masked from here to the mret: mscratch is about to name the stack
+    // this still runs on as the trap stack.
+    disable_interrupts();
+    #[cfg(feature = "ss-emulate")]
+    if !cfi.ssp {
+        let top: usize;
+        unsafe { asm!("la {0}, _u_shadow_stack_top", out(reg) top) };
+        EMULATED_SSP.store(top, Ordering::Relaxed);
+        SSP_EMULATED.store(true, Ordering::Relaxed);
+    }
+    unsafe {
+        if cfi.ssp {
+            // Set U-mode hardware shadow stack pointer
+            asm!(
+                "la     t0, _u_shadow_stack_top",
+                "csrw   {ssp}, t0",
+                ssp = const csr::SSP,
+                out("t0") _,
+            );
+        }
+        asm!(
+            // Set U-mode software shadow stack pointer (SW_SS_REG)
+            "la     x{ss}, _u_sw_shadow_stack_bottom",
+
+            // Boot is done with the M-mode stack: from here on it is the
+            // trap stack, its top parked in mscratch while U-mode runs
+            "la     t0, _m_stack_top",
+            "csrw   mscratch, t0",
+            ss = const SW_SS_REG,
+            out("t0") _,
+        );
+        return_to(PrivMode::User, entry, layout::u_stack_top(), args.regs())
+    }
+}
+
+/// The PCRs as they stand.
+fn pcr_values() -> [[u8; 32]; PCR_COUNT] {
+    let mut pcrs = [[0; 32]; PCR_COUNT];
+    MEASUREMENT_LOG.with(|log| {
+        for (i, pcr) in pcrs.iter_mut().enumerate() {
+            if let Some(value) = log.pcr(i as u8) {
+                *pcr = *value.as_bytes();
+            }
+        }
+    });
+    pcrs
+}
+
+/// Fill in the boot-info page ([`riscv_rot_cfi::boot_info`]) for
+/// `hartid`, with the PCRs as they stand, and return its address.
+/// Before `lock_u_pmp`: a locked U_RODATA entry is read-only for M-mode
+/// too.
+fn write_boot_info(hartid: u32) -> usize {
+    let page = layout::u_boot_info();
+    assert!(size_of::<BootInfo>() <= page.size, "boot info does not fit its page");
+    let pcrs = pcr_values();
+    // SAFETY: the page is reserved for this (`link.x`) and nothing else
+    // is linked or loaded there.  U-mode's PMP entry for U_RODATA is
+    // unlocked, so it doesn't bind M-mode, and U-mode hasn't started.
+    unsafe { (page.base as *mut BootInfo).write(BootInfo::new(hartid, pcrs)) };
+    page.base
+}
+
+/// misa.F (bit 5): single-precision floating point.
+#[cfg(feature = "fp")]
+const MISA_F: usize = 1 << 5;
+
+/// misa.S (bit 18): S-mode, and with it `sfence.vma`.
+const MISA_S: usize = 1 << 18;
+
+/// Barrier between the last PMP write and the drop to U-mode.
+///
+/// PMP CSR writes take effect immediately: the privileged spec has the
+/// hart check every later access against the new values, with no fence.
+/// So on this M/U-only kernel the barrier changes nothing today, and is
+/// defensive.  It covers two things the spec does allow:
+///
+/// - A hart with address translation may cache PMP results with its
+///   translations.  The spec then requires `sfence.vma` with x0, x0
+///   after a PMP change.  Without S-mode (misa.S clear) the instruction
+///   doesn't exist, so it is only issued when misa reports S.
+/// - `fence rw, rw` orders M-mode's own loads and stores, such as the
+///   boot-info page and the PMP configuration read-back, before anything
+///   U-mode does.
+///
+/// If paging is ever added, this call is where the ordering has to hold.
+fn pmp_barrier() {
+    // SAFETY: plain fences; neither changes architectural state.
+    unsafe {
+        asm!("fence rw, rw");
+        if csr::read::<{ csr::MISA }>() & MISA_S != 0 {
+            asm!("sfence.vma zero, zero");
+        }
+    }
+}
+
+/// Let U-mode use FP (`fp` builds): mstatus.FS = Initial if misa reports
+/// the F extension.  FS stays Off otherwise, and the trap handler then
+/// never touches FP state.
+#[cfg(feature = "fp")]
+fn enable_fp() {
+    if csr::read::<{ csr::MISA }>() & MISA_F != 0 {
+        // FS = Initial (0b01)
+        unsafe { csr::set::<{ csr::MSTATUS }>(1 << 13) };
+        uart_puts("  FS    -> Initial (F present; FP context saved across traps)\r\n");
+    } else {
+        uart_puts("  FS    -> Off (no F in misa)\r\n");
+    }
+}
+
+// ============================================================================
+// U-Mode Entry Point & Application
+// ============================================================================
+
+/// U-mode ecall wrappers.
+///
+/// These run in U-mode and use `ecall` to request services from M-mode.
+/// `_u_entry` issues its ecalls directly in assembly; the Rust-level API
+/// is used by the `u-repl` shell.  All `#[inline(always)]`, so no call
+/// leaves U_CODE.
+#[allow(dead_code)]
+mod umode_syscalls {
+    /// Print a single character via M-mode UART service.
+    #[inline(always)]
+    pub fn sys_putc(c: u8) {
+        unsafe {
+            core::arch::asm!(
+                "li a7, 0",
+                "ecall",
+                in("a0") c as u32,
+                lateout("a0") _,
+                lateout("a7") _,
+            );
+        }
+    }
+
+    /// Print bytes via M-mode UART service.
+    #[inline(always)]
+    pub fn sys_puts(s: &[u8]) {
+        unsafe {
+            core::arch::asm!(
+                "li a7, 1",
+                "ecall",
+                in("a0") s.as_ptr(),
+                in("a1") s.len(),
+                lateout("a0") _,
+                lateout("a1") _,
+                lateout("a7") _,
+            );
+        }
+    }
+
+    /// Exit the system.
+    #[inline(always)]
+    pub fn sys_exit(code: u32) -> ! {
+        unsafe {
+            core::arch::asm!(
+                "li a7, 2",
+                "ecall",
+                in("a0") code,
+                options(noreturn),
+            );
+        }
+    }
+
+    /// Get a signed attestation quote over `nonce`, written to `out`.
+    /// Returns the quote length, or `None` if `out` is too small.
+    #[inline(always)]
+    pub fn sys_quote(nonce: &[u8; 32], out: &mut [u8]) -> Option<usize> {
+        let ret: usize;
+        unsafe {
+            core::arch::asm!(
+                "li a7, 5",
+                "ecall",
+                inlateout("a0") nonce.as_ptr() => ret,
+                in("a1") out.as_mut_ptr(),
+                in("a2") out.len(),
+                lateout("a7") _,
+            );
+        }
+        (ret != usize::MAX).then_some(ret)
+    }
+
+    /// Give up the CPU until the next interrupt.
+    #[inline(always)]
+    pub fn sys_yield() {
+        unsafe {
+            core::arch::asm!(
+                "li a7, 6",
+                "ecall",
+                lateout("a0") _,
+                lateout("a7") _,
+            );
+        }
+    }
+
+    /// Next received console byte, if any.
+    #[inline(always)]
+    pub fn sys_getc() -> Option<u8> {
+        let ret: usize;
+        unsafe {
+            core::arch::asm!(
+                "li a7, 4",
+                "ecall",
+                lateout("a0") ret,
+                lateout("a7") _,
+            );
+        }
+        (ret != usize::MAX).then_some(ret as u8)
+    }
+
+    /// mcycle and minstret, as [`Sample`](riscv_rot_cfi::perf::Sample)
+    /// bytes.  Works whatever mcounteren allows.
+    #[inline(always)]
+    pub fn sys_perf_counters(out: &mut [u8; riscv_rot_cfi::perf::SAMPLE_LEN]) -> bool {
+        let ret: usize;
+        unsafe {
+            core::arch::asm!(
+                "li a7, 7",
+                "ecall",
+                inlateout("a0") out.as_mut_ptr() => ret,
+                lateout("a7") _,
+            );
+        }
+        ret == 0
+    }
+
+    /// Fill `buf` from the kernel DRBG.  `(ok, written)`: on failure the
+    /// first `written` bytes were still filled.
+    #[inline(always)]
+    pub fn sys_get_random(buf: &mut [u8]) -> (bool, usize) {
+        let (status, written): (usize, usize);
+        unsafe {
+            core::arch::asm!(
+                "li a7, 3",
+                "ecall",
+                inlateout("a0") buf.as_mut_ptr() => status,
+                inlateout("a1") buf.len() => written,
+                lateout("a7") _,
+            );
+        }
+        (status == 0, written)
+    }
+
+    /// The current mtime, from M-mode: reading `time` directly would trap.
+    #[inline(always)]
+    pub fn sys_time() -> u64 {
+        let (lo, hi): (u32, u32);
+        unsafe {
+            core::arch::asm!(
+                "li a7, 14",
+                "ecall",
+                lateout("a0") lo,
+                lateout("a1") hi,
+                lateout("a7") _,
+            );
+        }
+        (hi as u64) << 32 | lo as u64
+    }
+
+    /// The `cycle` counter, read directly (`rdcycleh` / `rdcycle`,
+    /// hi-lo-hi).  Raises an illegal-instruction exception unless
+    /// mcounteren.CY is set; see [`sys_perf_counters`].
+    #[inline(always)]
+    pub fn rdcycle() -> u64 {
+        let (hi, lo): (u32, u32);
+        unsafe {
+            core::arch::asm!(
+                "1:",
+                "rdcycleh {hi}",
+                "rdcycle  {lo}",
+                "rdcycleh {t}",
+                "bne      {hi}, {t}, 1b",
+                hi = out(reg) hi,
+                lo = out(reg) lo,
+                t = out(reg) _,
+                options(nomem, nostack),
+            );
+        }
+        (hi as u64) << 32 | lo as u64
+    }
+
+    /// The `instret` counter, read directly like [`rdcycle`]
+    /// (mcounteren.IR).
+    #[inline(always)]
+    pub fn rdinstret() -> u64 {
+        let (hi, lo): (u32, u32);
+        unsafe {
+            core::arch::asm!(
+                "1:",
+                "rdinstreth {hi}",
+                "rdinstret  {lo}",
+                "rdinstreth {t}",
+                "bne        {hi}, {t}, 1b",
+                hi = out(reg) hi,
+                lo = out(reg) lo,
+                t = out(reg) _,
+                options(nomem, nostack),
+            );
+        }
+        (hi as u64) << 32 | lo as u64
+    }
+
+    /// The boot-time firmware measurement.
+    #[inline(always)]
+    pub fn sys_measure() -> u32 {
+        let ret: u32;
+        unsafe {
+            core::arch::asm!(
+                "li a7, 9",
+                "ecall",
+                lateout("a0") ret,
+                lateout("a7") _,
+            );
+        }
+        ret
+    }
+
+    /// Seal `data` under RoT key `key_id`.
+    #[inline(always)]
+    pub fn sys_seal(data: u32, key_id: u32) -> u32 {
+        let ret: u32;
+        unsafe {
+            core::arch::asm!(
+                "li a7, 10",
+                "ecall",
+                inlateout("a0") data => ret,
+                in("a1") key_id,
+                lateout("a7") _,
+            );
+        }
+        ret
+    }
+
+    /// Read PCR `pcr` into `out`.  `false` if there is no such PCR.
+    #[inline(always)]
+    pub fn sys_pcr_read(pcr: u32, out: &mut [u8; 32]) -> bool {
+        let ret: usize;
+        unsafe {
+            core::arch::asm!(
+                "li a7, 8",
+                "ecall",
+                inlateout("a0") pcr => ret,
+                in("a1") out.as_mut_ptr(),
+                lateout("a7") _,
+            );
+        }
+        ret == 0
+    }
+
+    /// Extend runtime PCR `pcr` with `digest`.  `false` if it is locked.
+    #[inline(always)]
+    pub fn sys_pcr_extend(pcr: u32, digest: &[u8; 32]) -> bool {
+        let ret: usize;
+        unsafe {
+            core::arch::asm!(
+                "li a7, 11",
+                "ecall",
+                inlateout("a0") pcr => ret,
+                in("a1") digest.as_ptr(),
+                lateout("a7") _,
+            );
+        }
+        ret == 0
+    }
+
+    /// Monitor call: lock PCR `pcr`, authorized by the boot-time `token`.
+    #[inline(always)]
+    pub fn mon_lock_pcr(pcr: u32, token: u32) -> bool {
+        let ret: usize;
+        unsafe {
+            core::arch::asm!(
+                "ecall",
+                inlateout("a0") pcr => ret,
+                in("a1") token,
+                in("a7") riscv_rot_cfi::monitor::monitor_call(riscv_rot_cfi::monitor::LOCK_PCR),
+            );
+        }
+        ret == 0
+    }
+}
+
+/// Interactive U-mode command shell (`u-repl` builds).
+///
+/// `_u_entry` hands over to [`u_repl`](repl::u_repl) after its self-tests
+/// instead of exiting.  It reads a line with getc (echo, backspace),
+/// then runs one of:
+///
+/// ```text
+///   help            list commands
+///   measure         firmware measurement (syscall 9)
+///   perf            cycle and instruction counters (syscall 7, rdcycle)
+///   seal <hex>      seal a 32-bit value under key 1 (syscall 10)
+///   quote <hex>     signed quote over a nonce of up to 32 bytes (syscall 5)
+///   exit            stop QEMU (syscall 2)
+/// ```
+///
+/// Everything here must stay inside U-mode's PMP view: functions go in
+/// `.u_text`, strings in `.u_rodata` (via `u_str!`), buffers in `.u_bss`.
+/// So the code avoids anything that would call into ROM — `core::fmt`,
+/// slice comparison (`memcmp`), zeroed local arrays (`memset`),
+/// panicking indexing, checked arithmetic (debug builds' overflow panics;
+/// hence the `wrapping_*`) — and `match` on integers, which LLVM may
+/// lower to a jump table whose targets have no landing pads.  Check with
+/// `llvm-objdump -d -j .u_text` that no call leaves U_CODE.
+#[cfg(feature = "u-repl")]
+mod repl {
+    use core::cell::UnsafeCell;
+
+    use riscv_rot_cfi::perf::SAMPLE_LEN;
+
+    use super::umode_syscalls::{rdcycle, rdinstret, sys_exit, sys_get_random, sys_getc, sys_measure,
+                                sys_perf_counters, sys_putc, sys_puts, sys_quote, sys_seal,
+                                sys_time, sys_yield};
+    use super::QUOTE_MAX;
+
+    /// A byte-string literal placed in `.u_rodata`.
+    macro_rules! u_str {
+        ($s:literal) => {{
+            #[link_section = ".u_rodata"]
+            static S: [u8; $s.len()] = *$s;
+            &S
+        }};
+    }
+
+    /// Longest input line.
+    const LINE_MAX: usize = 80;
+    /// Nonce length for `quote`.
+    const NONCE_LEN: usize = 32;
+    /// Key used by `seal`.
+    const SEAL_KEY_ID: u32 = 1;
+
+    const BS: u8 = 0x08;
+    const DEL: u8 = 0x7f;
+
+    /// A buffer in `.u_bss`.  That section is not zeroed at boot, so the
+    /// contents start undefined; every use writes before it reads.
+    struct Scratch<const N: usize>(UnsafeCell<[u8; N]>);
+
+    // SAFETY: U-mode is single-threaded and takes no upcalls; each buffer
+    // is borrowed by one call chain at a time (see `u_repl`).
+    unsafe impl<const N: usize> Sync for Scratch<N> {}
+
+    impl<const N: usize> Scratch<N> {
+        const fn new() -> Self {
+            Scratch(UnsafeCell::new([0; N]))
+        }
+    }
+
+    #[link_section = ".u_bss"]
+    static LINE: Scratch<LINE_MAX> = Scratch::new();
+    #[link_section = ".u_bss"]
+    static NONCE: Scratch<NONCE_LEN> = Scratch::new();
+    #[link_section = ".u_bss"]
+    static QUOTE: Scratch<QUOTE_MAX> = Scratch::new();
+    #[link_section = ".u_bss"]
+    static SAMPLE: Scratch<SAMPLE_LEN> = Scratch::new();
+
+    #[no_mangle]
+    #[link_section = ".u_text"]
+    pub extern "C" fn u_repl() -> ! {
+        sys_puts(u_str!(b"\r\nRoT shell - type 'help'\r\n"));
+        // SAFETY: the only reference to LINE; `run` uses NONCE and QUOTE.
+        let line = unsafe { &mut *LINE.0.get() };
+        loop {
+            sys_puts(u_str!(b"rot> "));
+            let len = read_line(line);
+            run(line, len);
+        }
+    }
+
+    /// Blocking read of one byte: poll getc, yielding in between.
+    #[link_section = ".u_text"]
+    fn getc() -> u8 {
+        loop {
+            if let Some(c) = sys_getc() {
+                return c;
+            }
+            sys_yield();
+        }
+    }
+
+    /// Read one line into `buf`, echoing it; returns its length.
+    /// Backspace / DEL erase, other control characters and anything past
+    /// [`LINE_MAX`] are ignored.
+    #[link_section = ".u_text"]
+    fn read_line(buf: &mut [u8; LINE_MAX]) -> usize {
+        let mut len = 0;
+        loop {
+            let c = getc();
+            if c == b'\r' || c == b'\n' {
+                sys_puts(u_str!(b"\r\n"));
+                return len;
+            }
+            if c == BS || c == DEL {
+                if len > 0 {
+                    len = len.wrapping_sub(1);
+                    sys_puts(u_str!(b"\x08 \x08"));
+                }
+            } else if (0x20..DEL).contains(&c) && len < LINE_MAX {
+                buf[len] = c;
+                len = len.wrapping_add(1);
+                sys_putc(c);
+            }
+        }
+    }
+
+    /// `line[i]`, or 0 past `len`.
+    #[link_section = ".u_text"]
+    fn at(line: &[u8; LINE_MAX], len: usize, i: usize) -> u8 {
+        if i < len && i < LINE_MAX {
+            line[i]
+        } else {
+            0
+        }
+    }
+
+    /// Bounds `(start, end)` of the space-separated word at or after `from`.
+    #[link_section = ".u_text"]
+    fn word(line: &[u8; LINE_MAX], len: usize, from: usize) -> (usize, usize) {
+        let mut start = from;
+        while start < len && at(line, len, start) == b' ' {
+            start = start.wrapping_add(1);
+        }
+        let mut end = start;
+        while end < len && at(line, len, end) != b' ' {
+            end = end.wrapping_add(1);
+        }
+        (start, end)
+    }
+
+    /// The word `line[start..end]` equals `name`.
+    #[link_section = ".u_text"]
+    fn is(line: &[u8; LINE_MAX], len: usize, (start, end): (usize, usize), name: &[u8]) -> bool {
+        if end.wrapping_sub(start) != name.len() {
+            return false;
+        }
+        let mut i = 0;
+        while i < name.len() {
+            if at(line, len, start.wrapping_add(i)) != name[i] {
+                return false;
+            }
+            i = i.wrapping_add(1);
+        }
+        true
+    }
+
+    #[link_section = ".u_text"]
+    fn hex_digit(c: u8) -> Option<u8> {
+        if c.is_ascii_digit() {
+            Some(c.wrapping_sub(b'0'))
+        } else if (b'a'..=b'f').contains(&c) {
+            Some(c.wrapping_sub(b'a' - 10))
+        } else if (b'A'..=b'F').contains(&c) {
+            Some(c.wrapping_sub(b'A' - 10))
+        } else {
+            None
+        }
+    }
+
+    /// Skip an optional `0x` prefix.
+    #[link_section = ".u_text"]
+    fn skip_0x(line: &[u8; LINE_MAX], len: usize, (start, end): (usize, usize)) -> usize {
+        if end.wrapping_sub(start) > 2
+            && at(line, len, start) == b'0'
+            && (at(line, len, start.wrapping_add(1)) | 0x20) == b'x'
+        {
+            start.wrapping_add(2)
+        } else {
+            start
+        }
+    }
+
+    /// Parse 1–8 hex digits.
+    #[link_section = ".u_text"]
+    fn parse_u32(line: &[u8; LINE_MAX], len: usize, w: (usize, usize)) -> Option<u32> {
+        let mut i = skip_0x(line, len, w);
+        if i == w.1 || w.1.wrapping_sub(i) > 8 {
+            return None;
+        }
+        let mut v = 0u32;
+        while i < w.1 {
+            v = (v << 4) | hex_digit(at(line, len, i))? as u32;
+            i = i.wrapping_add(1);
+        }
+        Some(v)
+    }
+
+    /// Parse an even number of hex digits (at most 2 × [`NONCE_LEN`]) into
+    /// `out`, zero-padding on the right.  Every byte of `out` is written.
+    #[link_section = ".u_text"]
+    fn parse_nonce(
+        line: &[u8; LINE_MAX],
+        len: usize,
+        w: (usize, usize),
+        out: &mut [u8; NONCE_LEN],
+    ) -> bool {
+        let mut i = skip_0x(line, len, w);
+        let digits = w.1.wrapping_sub(i);
+        if digits == 0 || digits & 1 != 0 || digits > 2 * NONCE_LEN {
+            return false;
+        }
+        let mut n = 0;
+        while n < NONCE_LEN {
+            out[n] = if i < w.1 {
+                let (Some(hi), Some(lo)) =
+                    (hex_digit(at(line, len, i)), hex_digit(at(line, len, i.wrapping_add(1))))
+                else {
+                    return false;
+                };
+                (hi << 4) | lo
+            } else {
+                0
+            };
+            n = n.wrapping_add(1);
+            i = i.wrapping_add(2);
+        }
+        true
+    }
+
+    #[link_section = ".u_text"]
+    fn put_hex_byte(b: u8) {
+        let digit = |n: u8| if n < 10 { b'0' | n } else { n.wrapping_add(b'a' - 10) };
+        sys_putc(digit(b >> 4));
+        sys_putc(digit(b & 0xf));
+    }
+
+    #[link_section = ".u_text"]
+    fn put_hex32(v: u32) {
+        sys_puts(u_str!(b"0x"));
+        put_hex_byte((v >> 24) as u8);
+        put_hex_byte((v >> 16) as u8);
+        put_hex_byte((v >> 8) as u8);
+        put_hex_byte(v as u8);
+        sys_puts(u_str!(b"\r\n"));
+    }
+
+    #[link_section = ".u_text"]
+    fn put_hex64(v: u64) {
+        sys_puts(u_str!(b"0x"));
+        put_hex_byte((v >> 56) as u8);
+        put_hex_byte((v >> 48) as u8);
+        put_hex_byte((v >> 40) as u8);
+        put_hex_byte((v >> 32) as u8);
+        put_hex_byte((v >> 24) as u8);
+        put_hex_byte((v >> 16) as u8);
+        put_hex_byte((v >> 8) as u8);
+        put_hex_byte(v as u8);
+        sys_puts(u_str!(b"\r\n"));
+    }
+
+    /// The little-endian `u64` at `sample[start..start + 8]`.  Built a
+    /// byte at a time with constant shifts: a variable 64-bit shift would
+    /// be a libcall into ROM.
+    #[link_section = ".u_text"]
+    fn le64(sample: &[u8; SAMPLE_LEN], start: usize) -> u64 {
+        let mut v = 0u64;
+        let mut i = 8usize;
+        while i > 0 {
+            i = i.wrapping_sub(1);
+            let j = start.wrapping_add(i);
+            v = (v << 8) | if j < SAMPLE_LEN { sample[j] as u64 } else { 0 };
+        }
+        v
+    }
+
+    /// Run the command in `line[..len]`.
+    #[link_section = ".u_text"]
+    fn run(line: &[u8; LINE_MAX], len: usize) {
+        let cmd = word(line, len, 0);
+        let arg = word(line, len, cmd.1);
+        if cmd.0 == cmd.1 {
+            // empty line
+        } else if is(line, len, cmd, u_str!(b"help")) {
+            sys_puts(u_str!(
+                b"  help            this list\r\n\
+                  \x20 measure         firmware measurement\r\n\
+                  \x20 seal <hex>      seal a 32-bit value under key 1\r\n\
+                  \x20 quote <hex>     signed quote over a nonce (up to 32 bytes)\r\n\
+                  \x20 perf            cycle and instruction counters\r\n\
+                  \x20 time            mtime\r\n\
+                  \x20 random          32 bytes from the kernel DRBG\r\n\
+                  \x20 exit            stop the system\r\n"
+            ));
+        } else if is(line, len, cmd, u_str!(b"measure")) {
+            sys_puts(u_str!(b"  measurement: "));
+            put_hex32(sys_measure());
+        } else if is(line, len, cmd, u_str!(b"perf")) {
+            // SAFETY: the only reference to SAMPLE.
+            let sample = unsafe { &mut *SAMPLE.0.get() };
+            if !sys_perf_counters(sample) {
+                sys_puts(u_str!(b"  perf: failed\r\n"));
+                return;
+            }
+            sys_puts(u_str!(b"  cycles:    "));
+            put_hex64(le64(sample, 0));
+            sys_puts(u_str!(b"  instret:   "));
+            put_hex64(le64(sample, 8));
+            // The direct reads trap unless the kernel set mcounteren; it
+            // reports which it did in the launch summary.
+            sys_puts(u_str!(b"  rdcycle:   "));
+            put_hex64(rdcycle());
+            sys_puts(u_str!(b"  rdinstret: "));
+            put_hex64(rdinstret());
+        } else if is(line, len, cmd, u_str!(b"time")) {
+            sys_puts(u_str!(b"  mtime: "));
+            put_hex64(sys_time());
+        } else if is(line, len, cmd, u_str!(b"random")) {
+            // SAFETY: the only reference to NONCE.
+            let buf = unsafe { &mut *NONCE.0.get() };
+            let (ok, written) = sys_get_random(buf);
+            if !ok {
+                sys_puts(u_str!(b"  random: failed\r\n"));
+                return;
+            }
+            sys_puts(u_str!(b"  random: "));
+            let mut i = 0;
+            while i < written && i < NONCE_LEN {
+                put_hex_byte(buf[i]);
+                i = i.wrapping_add(1);
+            }
+            sys_puts(u_str!(b"\r\n"));
+        } else if is(line, len, cmd, u_str!(b"seal")) {
+            match parse_u32(line, len, arg) {
+                Some(data) => {
+                    sys_puts(u_str!(b"  sealed: "));
+                    put_hex32(sys_seal(data, SEAL_KEY_ID));
+                }
+                None => sys_puts(u_str!(b"  usage: seal <hex, up to 8 digits>\r\n")),
+            }
+        } else if is(line, len, cmd, u_str!(b"quote")) {
+            // SAFETY: the only references to NONCE and QUOTE.
+            let (nonce, quote) = unsafe { (&mut *NONCE.0.get(), &mut *QUOTE.0.get()) };
+            if !parse_nonce(line, len, arg, nonce) {
+                sys_puts(u_str!(b"  usage: quote <hex, even length, up to 64 digits>\r\n"));
+                return;
+            }
+            match sys_quote(nonce, quote) {
+                Some(n) => {
+                    sys_puts(u_str!(b"  quote:"));
+                    let mut i = 0;
+                    while i < n && i < QUOTE_MAX {
+                        if i % 32 == 0 {
+                            sys_puts(u_str!(b"\r\n    "));
+                        }
+                        put_hex_byte(quote[i]);
+                        i = i.wrapping_add(1);
+                    }
+                    sys_puts(u_str!(b"\r\n"));
+                }
+                None => sys_puts(u_str!(b"  quote failed\r\n")),
+            }
+        } else if is(line, len, cmd, u_str!(b"exit")) {
+            sys_exit(0);
+        } else {
+            sys_puts(u_str!(b"  unknown command - try 'help'\r\n"));
+        }
+    }
+}
+
+/// U-mode indirect call target: add 100.
+/// Has a landing pad for forward-edge CFI protection.
+///
+/// # Safety
+///
+/// U-mode code (`.u_text`): only meant to be called from U-mode firmware.
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".u_text"]
+pub unsafe extern "C" fn u_add_100(x: u32) -> u32 {
+    cfi_target_asm!(cfi_labels::DISPATCH;
+        "addi   a0, a0, 100",
+        "ret",
+    )
+}
+
+/// U-mode indirect call target: double the value.
+/// Full forward + backward CFI protection (non-leaf), from [`cfi_target`].
+///
+/// # Safety
+///
+/// U-mode code: [`SW_SS_REG`] must point into the U-mode software shadow
+/// stack.
+#[cfi_target(label = cfi_labels::DISPATCH)]
+#[no_mangle]
+#[link_section = ".u_text"]
+pub unsafe extern "C" fn u_double(x: u32) -> u32 {
+    x << 1
+}
+
+/// `sig-label-demo` indirect call target, labelled by its signature:
+/// `fn(u32) -> u32`.
+///
+/// # Safety
+///
+/// U-mode code: [`SW_SS_REG`] must point into the U-mode software shadow
+/// stack.
+#[cfg(feature = "sig-label-demo")]
+#[cfi_target(sig)]
+#[no_mangle]
+#[link_section = ".u_text"]
+pub unsafe extern "C" fn u_sig_negate(x: u32) -> u32 {
+    x.wrapping_neg()
+}
+
+/// `sig-label-demo` indirect call target, labelled by its signature:
+/// `fn(u32, u32) -> u32`.
+///
+/// # Safety
+///
+/// U-mode code: [`SW_SS_REG`] must point into the U-mode software shadow
+/// stack.
+#[cfg(feature = "sig-label-demo")]
+#[cfi_target(sig)]
+#[no_mangle]
+#[link_section = ".u_text"]
+pub unsafe extern "C" fn u_sig_scale(x: u32, k: u32) -> u32 {
+    x.wrapping_mul(k)
+}
+
+/// U-mode signature-label demo: a label derived from the target's type
+/// keeps a pointer from reaching a function of another signature.
+///
+/// Called from `_u_entry` in `sig-label-demo` builds, after the
+/// `DISPATCH` calls.  [`u_sig_negate`] and [`u_sig_scale`] take, from
+/// `#[cfi_target(sig)]`, the labels the call sites set up with
+/// `sig_label!`.  Checks, in order:
+///
+///   1. `negate(5)` through a pointer, t2 = `sig_label!(fn(u32) -> u32)`,
+///      returns -5;
+///   2. `scale(6, 7)`, t2 = `sig_label!(fn(u32, u32) -> u32)`, returns 42;
+///   3. `scale(6, 7)` with negate's label: on Zicfilp hardware the landing
+///      pad refuses it and [`trap_sig_mismatch`] returns -1 to the call
+///      site.  Without Zicfilp nothing checks the label, so it returns 42
+///      and the demo says so.
+///
+/// Prints PASS, or FAIL and exits with the failing step as the code.
+///
+/// # Safety
+///
+/// U-mode code: [`SW_SS_REG`] must point into the U-mode software shadow
+/// stack.
+#[cfg(feature = "sig-label-demo")]
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".u_text"]
+pub unsafe extern "C" fn u_sig_label_test() {
+    cfi_target_asm!(cfi_labels::UNLABELED;
+        ".pushsection .u_rodata.sig_label, \"a\"",
+        "u_sig_msg_refused:",
+        ".ascii \"[U-MODE] signature labels: mismatched call refused: PASS\\r\\n\"",
+        "u_sig_msg_unchecked:",
+        ".ascii \"[U-MODE] signature labels: mismatch not checked (no Zicfilp): PASS\\r\\n\"",
+        "u_sig_msg_fail:",
+        ".ascii \"[U-MODE] signature labels: FAIL\\r\\n\"",
+        "u_sig_msg_end:",
+        ".popsection",
+
+        ".4byte {sspush}",          // sspush ra (HW)
+        "addi   sp, sp, -16",
+        "sw     ra, 12(sp)",
+        "sw     ra, 0(x{ss})",         // sw_sspush
+        "addi   x{ss}, x{ss}, 4",
+
+        // The targets are Rust: t4 is set after each call, not before.
+        // 1. negate(5) with its own label -> -5
+        "la     t1, u_sig_negate",
+        "li     a0, 5",
+        "lui    t2, {unary}",
+        "jalr   ra, t1, 0",
+        "li     t4, 1",
+        "li     t0, -5",
+        "bne    a0, t0, 90f",
+
+        // 2. scale(6, 7) with its own label -> 42
+        "la     t1, u_sig_scale",
+        "li     a0, 6",
+        "li     a1, 7",
+        "lui    t2, {binary}",
+        "jalr   ra, t1, 0",
+        "li     t4, 2",
+        "li     t0, 42",
+        "bne    a0, t0, 90f",
+
+        // 3. scale(6, 7) with negate's label -> -1 if refused, 42 if not
+        "la     t1, u_sig_scale",
+        "li     a0, 6",
+        "li     a1, 7",
+        "lui    t2, {unary}",
+        "jalr   ra, t1, 0",
+        "li     t4, 3",
+        "la     t1, u_sig_msg_refused",
+        "li     t3, u_sig_msg_unchecked - u_sig_msg_refused",
+        "li     t0, -1",
+        "beq    a0, t0, 80f",
+        "li     t0, 42",
+        "bne    a0, t0, 90f",
+        "la     t1, u_sig_msg_unchecked",
+        "li     t3, u_sig_msg_fail - u_sig_msg_unchecked",
+        "80:",
+        "mv     a0, t1",
+        "mv     a1, t3",
+        "li     a7, 1",
+        "ecall",
+
+        "addi   x{ss}, x{ss}, -4",        // sw_sspopchk
+        "lw     t0, 0(x{ss})",
+        "lw     ra, 12(sp)",
+        "bne    t0, ra, 99f",
+        "98:",
+        "addi   sp, sp, 16",
+        ".if {ss_xcheck}",
+        "jal    t0, _u_ss_crosscheck",
+        ".else",
+        ".4byte {sspopchk}",        // sspopchk ra (HW)
+        ".endif",
+        "ret",
+        "99:",                      // Shadow stack mismatch
+        ".if {shadow_trace}",
+        "mv     t1, t0",            // report, then carry on with ra
+        "jal    t0, _u_shadow_trace",
+        "j      98b",
+        ".else",
+        "ebreak",
+        ".endif",
+
+        "90:",
+        "la     a0, u_sig_msg_fail",
+        "li     a1, u_sig_msg_end - u_sig_msg_fail",
+        "li     a7, 1",
+        "ecall",
+        "mv     a0, t4",
+        "li     a7, 2",
+        "ecall",
+        ss = const SW_SS_REG,
+        sspush = const cfi_encoding::SSPUSH_RA,
+        sspopchk = const cfi_encoding::SSPOPCHK_RA,
+        ss_xcheck = const cfg!(feature = "ss-crosscheck") as u32,
+        shadow_trace = const cfg!(feature = "shadow-trace") as u32,
+        unary = const riscv_rot_cfi::sig_label!(fn(u32) -> u32),
+        binary = const riscv_rot_cfi::sig_label!(fn(u32, u32) -> u32),
+    )
+}
+
+/// U-mode regression test: the trap handler must preserve every register
+/// across an ecall.
+///
+/// Loads a distinct sentinel (`0xC0DE_0000 + N * 0x0101` for xN) into every
+/// integer register, issues one ecall, then dumps the registers and
+/// compares each against the table.  The whole sequence is a single naked
+/// asm block, so the compiler has no chance to use a register between the
+/// sentinel load and the check.
+///
+/// Exceptions to the table:
+///   - `sp` can't hold a sentinel (the dump below is written through it),
+///     so its pre-ecall value is stashed in `.u_bss` and compared
+///   - `a7` = 0 selects `uart_putc`, so the live service path is exercised;
+///     its sentinel byte `0x0A` prints a newline
+///   - `a0` is the syscall return register and is not checked
+///
+/// The first mismatch is reported as `[REGSAVE] FAIL: xN = got, expected
+/// want` and the system exits with code 1; otherwise it prints PASS and
+/// returns to the caller with callee-saved state restored.
+///
+/// # Safety
+///
+/// U-mode code: [`SW_SS_REG`] must point into the U-mode software shadow
+/// stack.
+#[cfg(feature = "regsave-test")]
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".u_text"]
+pub unsafe extern "C" fn u_regsave_test() {
+    cfi_target_asm!(cfi_labels::UNLABELED;
+        // ── Sentinel table and messages (U-mode readable) ──
+        ".pushsection .u_rodata.regsave, \"a\"",
+        ".balign 4",
+        "u_regsave_sentinels:",
+        ".set u_regsave_n, 0",
+        ".rept 17",                 // x0..x16
+        ".word 0xC0DE0000 + u_regsave_n * 0x0101",
+        ".set u_regsave_n, u_regsave_n + 1",
+        ".endr",
+        ".word 0",                  // x17 = a7: syscall 0 (uart_putc)
+        ".set u_regsave_n, 18",
+        ".rept 14",                 // x18..x31
+        ".word 0xC0DE0000 + u_regsave_n * 0x0101",
+        ".set u_regsave_n, u_regsave_n + 1",
+        ".endr",
+        "u_regsave_msg_start:",
+        ".ascii \"[REGSAVE] sentinel ecall (uart_putc):\"",
+        "u_regsave_msg_pass:",
+        ".ascii \"[REGSAVE] PASS: x1-x31 preserved across ecall\\r\\n\"",
+        "u_regsave_msg_fail:",
+        ".ascii \"[REGSAVE] FAIL: x\"",
+        "u_regsave_msg_got:",
+        ".ascii \" = 0x\"",
+        "u_regsave_msg_want:",
+        ".ascii \", expected 0x\"",
+        "u_regsave_msg_end:",
+        ".popsection",
+
+        ".pushsection .u_bss.regsave, \"aw\", @nobits",
+        ".balign 4",
+        "u_regsave_sp:",
+        ".zero 4",
+        ".popsection",
+
+        ".4byte {sspush}",          // sspush ra (HW)
+        "sw     ra, 0(x{ss})",         // sw_sspush
+        "addi   x{ss}, x{ss}, 4",
+
+        // Frame: 0..127 post-ecall register dump (xN at 4*N),
+        // 128.. caller state clobbered by the sentinels
+        "addi   sp, sp, -192",
+        "sw     ra, 128(sp)",
+        "sw     gp, 132(sp)",
+        "sw     tp, 136(sp)",
+        "sw     s0, 140(sp)",
+        "sw     s1, 144(sp)",
+        "sw     s2, 148(sp)",
+        "sw     s3, 152(sp)",
+        "sw     s4, 156(sp)",
+        "sw     s5, 160(sp)",
+        "sw     s6, 164(sp)",
+        "sw     s7, 168(sp)",
+        "sw     s8, 172(sp)",
+        "sw     s9, 176(sp)",
+        "sw     s10, 180(sp)",
+        "sw     s11, 184(sp)",
+
+        "la     a0, u_regsave_msg_start",
+        "li     a1, u_regsave_msg_pass - u_regsave_msg_start",
+        "li     a7, 1",
+        "ecall",
+
+        "la     t0, u_regsave_sp",
+        "sw     sp, 0(t0)",
+
+        // ── Load sentinels; t6 (the table base) goes last ──
+        "la     t6, u_regsave_sentinels",
+        "lw     ra,   4(t6)",
+        "lw     gp,  12(t6)",
+        "lw     tp,  16(t6)",
+        "lw     t0,  20(t6)",
+        "lw     t1,  24(t6)",
+        "lw     t2,  28(t6)",
+        "lw     s0,  32(t6)",
+        "lw     s1,  36(t6)",
+        "lw     a0,  40(t6)",
+        "lw     a1,  44(t6)",
+        "lw     a2,  48(t6)",
+        "lw     a3,  52(t6)",
+        "lw     a4,  56(t6)",
+        "lw     a5,  60(t6)",
+        "lw     a6,  64(t6)",
+        "lw     a7,  68(t6)",
+        "lw     s2,  72(t6)",
+        "lw     s3,  76(t6)",
+        "lw     s4,  80(t6)",
+        "lw     s5,  84(t6)",
+        "lw     s6,  88(t6)",
+        "lw     s7,  92(t6)",
+        "lw     s8,  96(t6)",
+        "lw     s9, 100(t6)",
+        "lw     s10, 104(t6)",
+        "lw     s11, 108(t6)",
+        "lw     t3, 112(t6)",
+        "lw     t4, 116(t6)",
+        "lw     t5, 120(t6)",
+        "lw     t6, 124(t6)",
+
+        "ecall",
+
+        // ── Dump everything before touching a single register ──
+        "sw     ra,   4(sp)",
+        "sw     sp,   8(sp)",
+        "sw     gp,  12(sp)",
+        "sw     tp,  16(sp)",
+        "sw     t0,  20(sp)",
+        "sw     t1,  24(sp)",
+        "sw     t2,  28(sp)",
+        "sw     s0,  32(sp)",
+        "sw     s1,  36(sp)",
+        "sw     a0,  40(sp)",
+        "sw     a1,  44(sp)",
+        "sw     a2,  48(sp)",
+        "sw     a3,  52(sp)",
+        "sw     a4,  56(sp)",
+        "sw     a5,  60(sp)",
+        "sw     a6,  64(sp)",
+        "sw     a7,  68(sp)",
+        "sw     s2,  72(sp)",
+        "sw     s3,  76(sp)",
+        "sw     s4,  80(sp)",
+        "sw     s5,  84(sp)",
+        "sw     s6,  88(sp)",
+        "sw     s7,  92(sp)",
+        "sw     s8,  96(sp)",
+        "sw     s9, 100(sp)",
+        "sw     s10, 104(sp)",
+        "sw     s11, 108(sp)",
+        "sw     t3, 112(sp)",
+        "sw     t4, 116(sp)",
+        "sw     t5, 120(sp)",
+        "sw     t6, 124(sp)",
+
+        // ── Compare x1..x31 (t1 = N, t2 = got, t3 = expected) ──
+        "la     t0, u_regsave_sentinels",
+        "li     t1, 1",
+        "1:",
+        "slli   t4, t1, 2",
+        "add    t5, sp, t4",
+        "lw     t2, 0(t5)",
+        "add    t5, t0, t4",
+        "lw     t3, 0(t5)",
+        "li     t4, 2",
+        "bne    t1, t4, 2f",
+        "la     t3, u_regsave_sp",  // x2: expected = stashed sp
+        "lw     t3, 0(t3)",
+        "2:",
+        "li     t4, 10",
+        "beq    t1, t4, 3f",        // x10 (a0): return value, not checked
+        "bne    t2, t3, 50f",
+        "3:",
+        "addi   t1, t1, 1",
+        "li     t4, 32",
+        "blt    t1, t4, 1b",
+
+        // ── PASS: restore caller state and return ──
+        "la     a0, u_regsave_msg_pass",
+        "li     a1, u_regsave_msg_fail - u_regsave_msg_pass",
+        "li     a7, 1",
+        "ecall",
+
+        "lw     ra, 128(sp)",
+        "lw     gp, 132(sp)",
+        "lw     tp, 136(sp)",
+        "lw     s0, 140(sp)",
+        "lw     s1, 144(sp)",
+        "lw     s2, 148(sp)",
+        "lw     s3, 152(sp)",
+        "lw     s4, 156(sp)",
+        "lw     s5, 160(sp)",
+        "lw     s6, 164(sp)",
+        "lw     s7, 168(sp)",
+        "lw     s8, 172(sp)",
+        "lw     s9, 176(sp)",
+        "lw     s10, 180(sp)",
+        "lw     s11, 184(sp)",
+        "addi   sp, sp, 192",
+
+        "addi   x{ss}, x{ss}, -4",        // sw_sspopchk
+        "lw     t0, 0(x{ss})",
+        "bne    t0, ra, 99f",
+        "98:",
+        ".if {ss_xcheck}",
+        "jal    t0, _u_ss_crosscheck",
+        ".else",
+        ".4byte {sspopchk}",        // sspopchk ra (HW)
+        ".endif",
+        "ret",
+
+        // ── FAIL: report the first mismatch and exit(1) ──
+        "50:",
+        "mv     s2, t1",
+        "mv     s3, t2",
+        "mv     s4, t3",
+        "la     a0, u_regsave_msg_fail",
+        "li     a1, u_regsave_msg_got - u_regsave_msg_fail",
+        "li     a7, 1",
+        "ecall",
+        // Register number in decimal (1..31)
+        "li     t5, 10",
+        "divu   a0, s2, t5",
+        "beqz   a0, 51f",
+        "addi   a0, a0, 0x30",
+        "li     a7, 0",
+        "ecall",
+        "51:",
+        "remu   a0, s2, t5",
+        "addi   a0, a0, 0x30",
+        "li     a7, 0",
+        "ecall",
+        "la     a0, u_regsave_msg_got",
+        "li     a1, u_regsave_msg_want - u_regsave_msg_got",
+        "li     a7, 1",
+        "ecall",
+        "mv     a0, s3",
+        "jal    ra, 80f",
+        "la     a0, u_regsave_msg_want",
+        "li     a1, u_regsave_msg_end - u_regsave_msg_want",
+        "li     a7, 1",
+        "ecall",
+        "mv     a0, s4",
+        "jal    ra, 80f",
+        "li     a0, 0x0A",          // '\n'
+        "li     a7, 0",
+        "ecall",
+        "li     a0, 1",
+        "li     a7, 2",
+        "ecall",
+        "99:",                      // Shadow stack mismatch
+        ".if {shadow_trace}",
+        "mv     t1, t0",            // report, then carry on with ra
+        "jal    t0, _u_shadow_trace",
+        "j      98b",
+        ".else",
+        "ebreak",
+        ".endif",
+
+        // ── Print a0 as 8 hex digits via uart_putc ──
+        "80:",
+        "mv     t3, a0",
+        "li     t4, 28",
+        "81:",
+        "srl    a0, t3, t4",
+        "andi   a0, a0, 0xF",
+        "li     t5, 10",
+        "blt    a0, t5, 82f",
+        "addi   a0, a0, 0x27",      // 'a' - '0' - 10
+        "82:",
+        "addi   a0, a0, 0x30",      // '0'
+        "li     a7, 0",
+        "ecall",
+        "addi   t4, t4, -4",
+        "bgez   t4, 81b",
+        "ret",
+        ss = const SW_SS_REG,
+        sspush = const cfi_encoding::SSPUSH_RA,
+        sspopchk = const cfi_encoding::SSPOPCHK_RA,
+        ss_xcheck = const cfg!(feature = "ss-crosscheck") as u32,
+        shadow_trace = const cfg!(feature = "shadow-trace") as u32,
+    )
+}
+
+/// U-mode fault-injection test: a corrupted software shadow-stack slot
+/// must be caught by the `sw_sspopchk` compare.
+///
+/// Pushes `ra` to both shadow stacks as usual, then overwrites the saved
+/// software slot (`-4(x{ss})`) with a bogus return address before the
+/// pop/check.  The `bne t0, ra` mismatch branch must fire and reach the
+/// `ebreak`; the trap handler recognises that one breakpoint (cause 3 at
+/// `u_fault_inject_trap`) and resumes at `u_fault_inject_caught`, which
+/// prints `shadow stack corruption detected: PASS` and unwinds normally.
+/// The HW shadow stack was never touched, so its `sspopchk` still passes.
+///
+/// If the corruption goes unnoticed it prints FAIL and exits with code 1.
+///
+/// # Safety
+///
+/// U-mode code: [`SW_SS_REG`] must point into the U-mode software shadow
+/// stack.
+#[cfg(feature = "fault-inject")]
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".u_text"]
+pub unsafe extern "C" fn u_fault_inject_test() {
+    cfi_target_asm!(cfi_labels::UNLABELED;
+        ".pushsection .u_rodata.fault_inject, \"a\"",
+        "u_fault_inject_msg_pass:",
+        ".ascii \"[FAULT-INJECT] shadow stack corruption detected: PASS\\r\\n\"",
+        "u_fault_inject_msg_fail:",
+        ".ascii \"[FAULT-INJECT] shadow stack corruption NOT detected: FAIL\\r\\n\"",
+        "u_fault_inject_msg_end:",
+        ".popsection",
+
+        ".4byte {sspush}",          // sspush ra (HW)
+        "addi   sp, sp, -16",
+        "sw     ra, 12(sp)",
+        "sw     x{ss}, 8(sp)",
+        "sw     ra, 0(x{ss})",         // sw_sspush
+        "addi   x{ss}, x{ss}, 4",
+
+        // ── Inject: overwrite the saved SW shadow slot ──
+        "li     t0, 0xBAD0BAD0",
+        "sw     t0, -4(x{ss})",
+
+        "addi   x{ss}, x{ss}, -4",        // sw_sspopchk
+        "lw     t0, 0(x{ss})",
+        "lw     ra, 12(sp)",
+        "bne    t0, ra, u_fault_inject_trap",
+
+        // ── Mismatch missed: FAIL, exit(1) ──
+        "la     a0, u_fault_inject_msg_fail",
+        "li     a1, u_fault_inject_msg_end - u_fault_inject_msg_fail",
+        "li     a7, 1",
+        "ecall",
+        "li     a0, 1",
+        "li     a7, 2",
+        "ecall",
+
+        ".globl u_fault_inject_trap",
+        "u_fault_inject_trap:",
+        "ebreak",
+
+        // ── Resumed here by the trap handler (ra intact) ──
+        ".globl u_fault_inject_caught",
+        "u_fault_inject_caught:",
+        "la     a0, u_fault_inject_msg_pass",
+        "li     a1, u_fault_inject_msg_fail - u_fault_inject_msg_pass",
+        "li     a7, 1",
+        "ecall",
+        "lw     x{ss}, 8(sp)",
+        "addi   sp, sp, 16",
+        ".if {ss_xcheck}",
+        "jal    t0, _u_ss_crosscheck",
+        ".else",
+        ".4byte {sspopchk}",        // sspopchk ra (HW)
+        ".endif",
+        "ret",
+        ss = const SW_SS_REG,
+        sspush = const cfi_encoding::SSPUSH_RA,
+        sspopchk = const cfi_encoding::SSPOPCHK_RA,
+        ss_xcheck = const cfg!(feature = "ss-crosscheck") as u32,
+    )
+}
+
+/// U-mode breakpoint test: an `ebreak` outside any CFI check must be
+/// reported with its address and resumed, not halt the system.
+///
+/// Executes the `ebreak` at `u_breakpoint_trap`.  The trap handler
+/// recognises it (cause 3 at that address), records and reports the PC
+/// ([`trap_breakpoint_test`]) and resumes at `u_breakpoint_resume` with the
+/// recorded address in a0.  The test prints `ebreak reported at its
+/// address: PASS` if that is `u_breakpoint_trap`, else FAIL and exits with
+/// code 1.
+///
+/// # Safety
+///
+/// U-mode code: [`SW_SS_REG`] must point into the U-mode software shadow
+/// stack.
+#[cfg(feature = "breakpoint-test")]
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".u_text"]
+pub unsafe extern "C" fn u_breakpoint_test() {
+    cfi_target_asm!(cfi_labels::UNLABELED;
+        ".pushsection .u_rodata.breakpoint_test, \"a\"",
+        "u_breakpoint_msg_pass:",
+        ".ascii \"[BREAKPOINT] ebreak reported at its address: PASS\\r\\n\"",
+        "u_breakpoint_msg_fail:",
+        ".ascii \"[BREAKPOINT] ebreak reported at the wrong address: FAIL\\r\\n\"",
+        "u_breakpoint_msg_end:",
+        ".popsection",
+
+        ".4byte {sspush}",          // sspush ra (HW)
+        "addi   sp, sp, -16",
+        "sw     ra, 12(sp)",
+        "sw     x{ss}, 8(sp)",
+        "sw     ra, 0(x{ss})",         // sw_sspush
+        "addi   x{ss}, x{ss}, 4",
+
+        "li     a0, 0",
+        ".globl u_breakpoint_trap",
+        "u_breakpoint_trap:",
+        "ebreak",
+        "j      2f",                   // not resumed at u_breakpoint_resume
+
+        // ── Resumed here by the trap handler, a0 = recorded PC ──
+        ".globl u_breakpoint_resume",
+        "u_breakpoint_resume:",
+        "la     t0, u_breakpoint_trap",
+        "bne    a0, t0, 2f",
+        "la     a0, u_breakpoint_msg_pass",
+        "li     a1, u_breakpoint_msg_fail - u_breakpoint_msg_pass",
+        "li     a7, 1",
+        "ecall",
+        "lw     x{ss}, 8(sp)",
+        "addi   sp, sp, 16",
+        ".if {ss_xcheck}",
+        "jal    t0, _u_ss_crosscheck",
+        ".else",
+        ".4byte {sspopchk}",        // sspopchk ra (HW)
+        ".endif",
+        "ret",
+
+        // ── FAIL, exit(1) ──
+        "2:",
+        "la     a0, u_breakpoint_msg_fail",
+        "li     a1, u_breakpoint_msg_end - u_breakpoint_msg_fail",
+        "li     a7, 1",
+        "ecall",
+        "li     a0, 1",
+        "li     a7, 2",
+        "ecall",
+        ss = const SW_SS_REG,
+        sspush = const cfi_encoding::SSPUSH_RA,
+        sspopchk = const cfi_encoding::SSPOPCHK_RA,
+        ss_xcheck = const cfg!(feature = "ss-crosscheck") as u32,
+    )
+}
+
+/// U-mode ROP demonstration: a return address overwritten on the data
+/// stack must not be returned to.
+///
+/// `u_rop_victim` saves ra to the data stack and both shadow stacks like
+/// any function, then plays the attacker: it overwrites its own saved
+/// ra slot, 12(sp), with the address of `u_rop_gadget` — what a stack
+/// buffer overflow would do.  The shadow copies are left alone.  Its
+/// epilogue reloads ra from the stack and compares it with the software
+/// shadow copy; `bne t0, ra` must go to the mismatch `ebreak`
+/// (`u_rop_trap`).  The trap handler reports "ROP blocked: PASS"
+/// ([`trap_rop_blocked`]) and resumes at `u_rop_recovered`, which returns
+/// through the shadow copy still in t0, so the HW `sspopchk` also passes.
+///
+/// Reaching the gadget prints FAIL and exits with code 1.
+///
+/// # Safety
+///
+/// U-mode code: [`SW_SS_REG`] must point into the U-mode software shadow
+/// stack.
+#[cfg(feature = "rop-demo")]
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".u_text"]
+pub unsafe extern "C" fn u_rop_attack_demo() {
+    cfi_target_asm!(cfi_labels::UNLABELED;
+        ".pushsection .u_rodata.rop_demo, \"a\"",
+        "u_rop_msg_fail:",
+        ".ascii \"[ROP] gadget reached: FAIL\\r\\n\"",
+        "u_rop_msg_end:",
+        ".popsection",
+
+        ".4byte {sspush}",          // sspush ra (HW)
+        "addi   sp, sp, -16",
+        "sw     ra, 12(sp)",
+        "sw     ra, 0(x{ss})",         // sw_sspush
+        "addi   x{ss}, x{ss}, 4",
+
+        "call   u_rop_victim",
+
+        "addi   x{ss}, x{ss}, -4",        // sw_sspopchk
+        "lw     t0, 0(x{ss})",
+        "lw     ra, 12(sp)",
+        "bne    t0, ra, 99f",
+        "98:",
+        "addi   sp, sp, 16",
+        ".if {ss_xcheck}",
+        "jal    t0, _u_ss_crosscheck",
+        ".else",
+        ".4byte {sspopchk}",        // sspopchk ra (HW)
+        ".endif",
+        "ret",
+        "99:",                      // Shadow stack mismatch
+        ".if {shadow_trace}",
+        "mv     t1, t0",            // report, then carry on with ra
+        "jal    t0, _u_shadow_trace",
+        "j      98b",
+        ".else",
+        "ebreak",
+        ".endif",
+
+        // ── The victim ──
+        "u_rop_victim:",
+        ".4byte {sspush}",          // sspush ra (HW)
+        "addi   sp, sp, -16",
+        "sw     ra, 12(sp)",
+        "sw     ra, 0(x{ss})",         // sw_sspush
+        "addi   x{ss}, x{ss}, 4",
+
+        // ── Attack: point the saved ra at the gadget ──
+        "la     t0, u_rop_gadget",
+        "sw     t0, 12(sp)",
+
+        "addi   x{ss}, x{ss}, -4",        // sw_sspopchk
+        "lw     t0, 0(x{ss})",
+        "lw     ra, 12(sp)",            // the forged address
+        "bne    t0, ra, u_rop_trap",
+        // Not caught: return into the gadget (a Zicfiss core's sspopchk
+        // would still fault here)
+        "addi   sp, sp, 16",
+        ".if {ss_xcheck}",
+        "jal    t0, _u_ss_crosscheck",
+        ".else",
+        ".4byte {sspopchk}",        // sspopchk ra (HW)
+        ".endif",
+        "ret",
+
+        ".globl u_rop_trap",
+        "u_rop_trap:",
+        "ebreak",
+
+        // ── Resumed here by the trap handler: t0 = the genuine ra ──
+        ".globl u_rop_recovered",
+        "u_rop_recovered:",
+        "mv     ra, t0",
+        "addi   sp, sp, 16",
+        ".if {ss_xcheck}",
+        "jal    t0, _u_ss_crosscheck",
+        ".else",
+        ".4byte {sspopchk}",        // sspopchk ra (HW)
+        ".endif",
+        "ret",
+
+        // ── The gadget: only an unchecked return gets here ──
+        "u_rop_gadget:",
+        "la     a0, u_rop_msg_fail",
+        "li     a1, u_rop_msg_end - u_rop_msg_fail",
+        "li     a7, 1",
+        "ecall",
+        "li     a0, 1",
+        "li     a7, 2",
+        "ecall",
+        ss = const SW_SS_REG,
+        sspush = const cfi_encoding::SSPUSH_RA,
+        sspopchk = const cfi_encoding::SSPOPCHK_RA,
+        ss_xcheck = const cfg!(feature = "ss-crosscheck") as u32,
+        shadow_trace = const cfg!(feature = "shadow-trace") as u32,
+    )
+}
+
+/// U-mode stack-guard test: a runaway write up the data stack must fault
+/// on the U_GUARD page, not reach the shadow stacks above it.
+///
+/// Takes a 64-byte buffer below sp and fills words upward from its start
+/// until a store faults, counting the bytes written.  The trap handler
+/// resumes the one store at `u_guard_overrun_store` at
+/// `u_guard_overrun_caught`, and the count must then equal the distance
+/// to `_u_stack_top`.  Any other stopping point — a missing guard lets the
+/// loop run through U_SHADOW and U_SW_SHADOW first — is a FAIL.
+///
+/// Prints PASS, or FAIL and exits with code 1.
+///
+/// # Safety
+///
+/// U-mode code: [`SW_SS_REG`] must point into the U-mode software shadow
+/// stack.  Everything on the stack above the buffer is overwritten, so
+/// this must be called from `_u_entry`, which keeps nothing there; ra
+/// stays in a register throughout.
+#[cfg(feature = "stack-guard-test")]
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".u_text"]
+pub unsafe extern "C" fn u_stack_guard_test() {
+    cfi_target_asm!(cfi_labels::UNLABELED;
+        ".pushsection .u_rodata.stack_guard, \"a\"",
+        "u_guard_msg_pass:",
+        ".ascii \"[GUARD] stack overrun faulted at U_GUARD: PASS\\r\\n\"",
+        "u_guard_msg_fail:",
+        ".ascii \"[GUARD] stack overrun not stopped at the stack top: FAIL\\r\\n\"",
+        "u_guard_msg_end:",
+        ".popsection",
+
+        ".4byte {sspush}",          // sspush ra (HW)
+        "sw     ra, 0(x{ss})",         // sw_sspush
+        "addi   x{ss}, x{ss}, 4",
+
+        "addi   sp, sp, -64",
+        "mv     t0, sp",
+        "la     t1, _u_stack_top",
+        "sub    t2, t1, t0",           // bytes up to the guard
+        "li     t3, 0",                // bytes written
+        "li     t4, 0x5A5A5A5A",
+        ".globl u_guard_overrun_store",
+        "u_guard_overrun_store:",
+        "sw     t4, 0(t0)",
+        "addi   t0, t0, 4",
+        "addi   t3, t3, 4",
+        "j      u_guard_overrun_store",
+
+        // ── Resumed here by the trap handler ──
+        ".globl u_guard_overrun_caught",
+        "u_guard_overrun_caught:",
+        "addi   sp, sp, 64",
+        "bne    t3, t2, 90f",
+        "la     a0, u_guard_msg_pass",
+        "li     a1, u_guard_msg_fail - u_guard_msg_pass",
+        "li     a7, 1",
+        "ecall",
+        "addi   x{ss}, x{ss}, -4",        // sw_sspopchk
+        "lw     t0, 0(x{ss})",
+        "bne    t0, ra, 99f",
+        "98:",
+        ".if {ss_xcheck}",
+        "jal    t0, _u_ss_crosscheck",
+        ".else",
+        ".4byte {sspopchk}",        // sspopchk ra (HW)
+        ".endif",
+        "ret",
+
+        // ── FAIL, exit(1) ──
+        "90:",
+        "la     a0, u_guard_msg_fail",
+        "li     a1, u_guard_msg_end - u_guard_msg_fail",
+        "li     a7, 1",
+        "ecall",
+        "li     a0, 1",
+        "li     a7, 2",
+        "ecall",
+        "99:",                      // Shadow stack mismatch
+        ".if {shadow_trace}",
+        "mv     t1, t0",            // report, then carry on with ra
+        "jal    t0, _u_shadow_trace",
+        "j      98b",
+        ".else",
+        "ebreak",
+        ".endif",
+        ss = const SW_SS_REG,
+        sspush = const cfi_encoding::SSPUSH_RA,
+        sspopchk = const cfi_encoding::SSPOPCHK_RA,
+        ss_xcheck = const cfg!(feature = "ss-crosscheck") as u32,
+        shadow_trace = const cfg!(feature = "shadow-trace") as u32,
+    )
+}
+
+/// U-mode W^X test: U_RAM is writable, so nothing written there may run.
+///
+/// Writes `lpad 0; ret` — a complete function any indirect call may
+/// enter — to a word-aligned buffer on the stack, which is in U_RAM,
+/// and calls it through t0.  The fetch must fault with an instruction
+/// access fault (mcause 1).  The trap handler resumes the one call
+/// returning to `u_wx_return` at `u_wx_fault_caught`, with the faulting
+/// address in a0 ([`trap_wx_selftest`]), and it must be the buffer's.
+/// Reaching `u_wx_return`, with the bytes run, or any other trap is a
+/// FAIL.
+///
+/// `u_wx_fault_caught` starts with a landing pad, listed like the
+/// others: if ELP was set by the call, the `mret` restores it.
+///
+/// Prints PASS, or FAIL and exits with the failing step as the code
+/// (1 = executed, 2 = wrong fault).
+///
+/// # Safety
+///
+/// U-mode code: [`SW_SS_REG`] must point into the U-mode software shadow
+/// stack.
+#[cfg(feature = "wx-selftest")]
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".u_text"]
+pub unsafe extern "C" fn u_wx_selftest() {
+    cfi_target_asm!(cfi_labels::UNLABELED;
+        ".pushsection .u_rodata.wx_selftest, \"a\"",
+        "u_wx_msg_pass:",
+        ".ascii \"[U-MODE] W^X enforced, no execution from U_RAM: PASS\\r\\n\"",
+        "u_wx_msg_fail:",
+        ".ascii \"[U-MODE] W^X: FAIL\\r\\n\"",
+        "u_wx_msg_end:",
+        ".popsection",
+
+        ".4byte {sspush}",          // sspush ra (HW)
+        "sw     ra, 0(x{ss})",         // sw_sspush
+        "addi   x{ss}, x{ss}, 4",
+        "addi   sp, sp, -16",
+        "sw     ra, 12(sp)",
+
+        // lpad 0; ret, at sp (16-byte aligned), made visible to fetch
+        "li     t1, {lpad}",
+        "sw     t1, 0(sp)",
+        "li     t1, {ret}",
+        "sw     t1, 4(sp)",
+        ".option push",
+        ".option arch, +zifencei",
+        "fence.i",
+        ".option pop",
+        "mv     t0, sp",
+        "li     a0, 0",
+        "jalr   ra, 0(t0)",
+        ".globl u_wx_return",
+        "u_wx_return:",
+        "li     t4, 1",                // the bytes ran
+        "j      90f",
+
+        // ── Resumed here by the trap handler, a0 = faulting address ──
+        ".balign 4",                // lpad: 4-byte aligned
+        ".globl u_wx_fault_caught",
+        "u_wx_fault_caught:",
+        "7771:",
+        ".4byte {lpad}",
+        ".pushsection .u_lpad_sites, \"a\"",
+        ".4byte 7771b",
+        ".popsection",
+        "li     t4, 2",
+        "bne    a0, t0, 90f",
+        "lw     ra, 12(sp)",
+        "addi   sp, sp, 16",
+        "la     a0, u_wx_msg_pass",
+        "li     a1, u_wx_msg_fail - u_wx_msg_pass",
+        "li     a7, 1",
+        "ecall",
+        "addi   x{ss}, x{ss}, -4",        // sw_sspopchk
+        "lw     t0, 0(x{ss})",
+        "bne    t0, ra, 99f",
+        "98:",
+        ".if {ss_xcheck}",
+        "jal    t0, _u_ss_crosscheck",
+        ".else",
+        ".4byte {sspopchk}",        // sspopchk ra (HW)
+        ".endif",
+        "ret",
+
+        // ── FAIL, exit(t4) ──
+        "90:",
+        "la     a0, u_wx_msg_fail",
+        "li     a1, u_wx_msg_end - u_wx_msg_fail",
+        "li     a7, 1",
+        "ecall",
+        "mv     a0, t4",
+        "li     a7, 2",
+        "ecall",
+        "99:",                      // Shadow stack mismatch
+        ".if {shadow_trace}",
+        "mv     t1, t0",            // report, then carry on with ra
+        "jal    t0, _u_shadow_trace",
+        "j      98b",
+        ".else",
+        "ebreak",
+        ".endif",
+        lpad = const cfi_labels::lpad(0),
+        ret = const RET_INSN,
+        ss = const SW_SS_REG,
+        sspush = const cfi_encoding::SSPUSH_RA,
+        sspopchk = const cfi_encoding::SSPOPCHK_RA,
+        ss_xcheck = const cfg!(feature = "ss-crosscheck") as u32,
+        shadow_trace = const cfg!(feature = "shadow-trace") as u32,
+    )
+}
+
+/// `ret` (`jalr x0, 0(ra)`).
+#[cfg(feature = "wx-selftest")]
+const RET_INSN: u32 = 0x0000_8067;
+
+/// Where U-mode resumes after [`trap_cfi_quarantine`] has quarantined a
+/// code region (`quarantine-policy` builds).
+///
+/// It lives in `.u_recovery` (U_RECOVERY, PMP entry 10), apart from any
+/// application's code, so it stays executable whatever was quarantined.
+/// Entered by `mret` with the faulting context's registers and stacks,
+/// none of which it trusts: it makes no calls, so it needs neither
+/// stack, and reads nothing but its own message.  It reports, then parks
+/// in a yield loop; M-mode keeps servicing interrupts and the console.
+/// Starts with `lpad` like every `mret` entry: a landing-pad fault can
+/// leave ELP set across the return.
+///
+/// # Safety
+///
+/// Only for `mret` from the quarantine handler, into U-mode.
+#[cfg(feature = "quarantine-policy")]
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".u_recovery"]
+pub unsafe extern "C" fn u_quarantine_recovery() -> ! {
+    cfi_target_asm!(@outside_u_code cfi_labels::UNLABELED;
+        ".pushsection .u_rodata.quarantine, \"a\"",
+        "u_quarantine_msg:",
+        ".ascii \"[U-MODE] recovery: code region quarantined, parked\\r\\n\"",
+        "u_quarantine_msg_end:",
+        ".popsection",
+
+        "la     a0, u_quarantine_msg",
+        "li     a1, u_quarantine_msg_end - u_quarantine_msg",
+        "li     a7, 1",
+        "ecall",
+        "1:",
+        "li     a7, 6",             // yield
+        "ecall",
+        "j      1b",
+    )
+}
+
+/// Where the main application resumes after [`trap_cfi_contain`] has
+/// terminated it (`cfi-contain` builds): the CFI violation's signal, with
+/// mcause, mepc and mtval in a0-a2.
+///
+/// Entered by `mret` with the faulting context's registers and stacks,
+/// none of which it trusts: it makes no calls, so it needs neither
+/// stack.  It reports, then ends the run with `exit`
+/// ([`CFI_FAULT_EXIT`]), which drains the console first.  Starts with
+/// `lpad` like every `mret` entry: a landing-pad fault can leave ELP set
+/// across the return.
+///
+/// # Safety
+///
+/// Only for `mret` from the containment handler, into U-mode.
+#[cfg(feature = "cfi-contain")]
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".u_text"]
+pub unsafe extern "C" fn u_cfi_signal() -> ! {
+    cfi_target_asm!(cfi_labels::UNLABELED;
+        ".pushsection .u_rodata.cfi_signal, \"a\"",
+        "u_cfi_signal_msg:",
+        ".ascii \"[U-MODE] CFI violation signalled: terminating\\r\\n\"",
+        "u_cfi_signal_msg_end:",
+        ".popsection",
+
+        "la     a0, u_cfi_signal_msg",
+        "li     a1, u_cfi_signal_msg_end - u_cfi_signal_msg",
+        "li     a7, 1",
+        "ecall",
+        "li     a0, {code}",
+        "li     a7, 2",             // exit
+        "ecall",
+        "1:",
+        "j      1b",
+        code = const CFI_FAULT_EXIT,
+    )
+}
+
+/// U-mode nested-trap test: syscall 12 takes a timer interrupt inside its
+/// service, and the ecall must still come back exactly as a plain one.
+///
+/// Loads sentinels into s0–s3 and t3–t6, issues the ecall, then checks:
+///
+///   1. a0 = 3: the interrupt was taken and overwrote the mepc CSR;
+///   2. every sentinel survived both traps;
+///   3. the hart is back in U-mode — a `csrr mscratch` traps and is
+///      skipped, leaving its destination unchanged (in M-mode it would
+///      read the trap-stack pointer).
+///
+/// Prints PASS, or FAIL and exits with the failing step as the code.
+///
+/// # Safety
+///
+/// U-mode code: [`SW_SS_REG`] must point into the U-mode software shadow
+/// stack.
+#[cfg(feature = "nested-trap-test")]
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".u_text"]
+pub unsafe extern "C" fn u_nested_trap_test() {
+    cfi_target_asm!(cfi_labels::UNLABELED;
+        ".pushsection .u_rodata.nested_trap, \"a\"",
+        "u_nested_msg_pass:",
+        ".ascii \"[NESTED] timer interrupt inside an ecall: PASS\\r\\n\"",
+        "u_nested_msg_fail:",
+        ".ascii \"[NESTED] timer interrupt inside an ecall: FAIL\\r\\n\"",
+        "u_nested_msg_end:",
+        ".popsection",
+
+        ".4byte {sspush}",          // sspush ra (HW)
+        "addi   sp, sp, -32",
+        "sw     ra, 28(sp)",
+        "sw     s0, 24(sp)",
+        "sw     s1, 20(sp)",
+        "sw     s2, 16(sp)",
+        "sw     s3, 12(sp)",
+        "sw     ra, 0(x{ss})",         // sw_sspush
+        "addi   x{ss}, x{ss}, 4",
+
+        "li     s0, 0x5E000008",
+        "li     s1, 0x5E000009",
+        "li     s2, 0x5E000012",
+        "li     s3, 0x5E000013",
+        "li     t3, 0x5E000028",
+        "li     t4, 0x5E000029",
+        "li     t5, 0x5E000030",
+        "li     t6, 0x5E000031",
+        "li     a7, 12",
+        "ecall",
+
+        // 1. both traps happened
+        "li     a1, 1",
+        "li     t0, 3",
+        "bne    a0, t0, 90f",
+
+        // 2. sentinels intact
+        "li     a1, 2",
+        "li     t0, 0x5E000008",
+        "bne    s0, t0, 90f",
+        "li     t0, 0x5E000009",
+        "bne    s1, t0, 90f",
+        "li     t0, 0x5E000012",
+        "bne    s2, t0, 90f",
+        "li     t0, 0x5E000013",
+        "bne    s3, t0, 90f",
+        "li     t0, 0x5E000028",
+        "bne    t3, t0, 90f",
+        "li     t0, 0x5E000029",
+        "bne    t4, t0, 90f",
+        "li     t0, 0x5E000030",
+        "bne    t5, t0, 90f",
+        "li     t0, 0x5E000031",
+        "bne    t6, t0, 90f",
+
+        // 3. still U-mode
+        "li     a1, 3",
+        "li     t0, 0x5E",
+        "csrr   t0, mscratch",
+        "li     t1, 0x5E",
+        "bne    t0, t1, 90f",
+
+        "la     a0, u_nested_msg_pass",
+        "li     a1, u_nested_msg_fail - u_nested_msg_pass",
+        "li     a7, 1",
+        "ecall",
+        "lw     s0, 24(sp)",
+        "lw     s1, 20(sp)",
+        "lw     s2, 16(sp)",
+        "lw     s3, 12(sp)",
+        "lw     ra, 28(sp)",
+        "addi   sp, sp, 32",
+        "addi   x{ss}, x{ss}, -4",        // sw_sspopchk
+        "lw     t0, 0(x{ss})",
+        "bne    t0, ra, 99f",
+        "98:",
+        ".if {ss_xcheck}",
+        "jal    t0, _u_ss_crosscheck",
+        ".else",
+        ".4byte {sspopchk}",        // sspopchk ra (HW)
+        ".endif",
+        "ret",
+
+        // ── FAIL: exit(step) ──
+        "90:",
+        "mv     s0, a1",
+        "la     a0, u_nested_msg_fail",
+        "li     a1, u_nested_msg_end - u_nested_msg_fail",
+        "li     a7, 1",
+        "ecall",
+        "mv     a0, s0",
+        "li     a7, 2",
+        "ecall",
+        "99:",                      // Shadow stack mismatch
+        ".if {shadow_trace}",
+        "mv     t1, t0",            // report, then carry on with ra
+        "jal    t0, _u_shadow_trace",
+        "j      98b",
+        ".else",
+        "ebreak",
+        ".endif",
+        ss = const SW_SS_REG,
+        sspush = const cfi_encoding::SSPUSH_RA,
+        sspopchk = const cfi_encoding::SSPOPCHK_RA,
+        ss_xcheck = const cfg!(feature = "ss-crosscheck") as u32,
+        shadow_trace = const cfg!(feature = "shadow-trace") as u32,
+    )
+}
+
+/// U-mode application-isolation test: after [`switch_to_app`], each
+/// application can read its own RAM and not the other's.
+///
+/// Application 0 (this firmware) and application 1 share code, rodata and
+/// the stack, so the test runs straight through the switches.  Steps:
+///
+///   1. as app 0, a load from app 1's RAM (U_APP1) faults;
+///   2. syscall 13 switches to app 1;
+///   3. as app 1, that load succeeds ...
+///   4. ... and one from app 0's RAM (`_u_ram_start`) faults;
+///   5. switching back to app 0 succeeds;
+///   6. app 1's RAM faults again and app 0's is readable;
+///   7. switching to a third, nonexistent app fails.
+///
+/// Loads go through one probe instruction, `u_app_probe_load`; the
+/// access-fault handler resumes it at `u_app_probe_caught`.
+///
+/// Prints PASS, or FAIL and exits with the failing step as the code.
+///
+/// # Safety
+///
+/// U-mode code, running as application 0: [`SW_SS_REG`] must point into
+/// the U-mode software shadow stack.
+#[cfg(feature = "app-isolation-test")]
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".u_text"]
+pub unsafe extern "C" fn u_app_isolation_test() {
+    cfi_target_asm!(cfi_labels::UNLABELED;
+        ".pushsection .u_rodata.app_isolation, \"a\"",
+        "u_app_msg_pass:",
+        ".ascii \"[APPS] app 0 and app 1 can't read each other's RAM: PASS\\r\\n\"",
+        "u_app_msg_fail:",
+        ".ascii \"[APPS] application isolation: FAIL\\r\\n\"",
+        "u_app_msg_end:",
+        ".popsection",
+
+        ".4byte {sspush}",          // sspush ra (HW)
+        "addi   sp, sp, -16",
+        "sw     ra, 12(sp)",
+        "sw     s0, 8(sp)",
+        "sw     s1, 4(sp)",
+        "sw     ra, 0(x{ss})",         // sw_sspush
+        "addi   x{ss}, x{ss}, 4",
+
+        "li     s1, {app1_ram}",
+
+        // 1. app 0 can't read app 1's RAM
+        "li     a1, 1",
+        "mv     a0, s1",
+        "jal    t0, 80f",
+        "beqz   a0, 90f",
+
+        // 2. switch to app 1
+        "li     a1, 2",
+        "li     a0, 1",
+        "li     a7, 13",
+        "ecall",
+        "bnez   a0, 90f",
+
+        // 3. app 1 can read its RAM ...
+        "li     a1, 3",
+        "mv     a0, s1",
+        "jal    t0, 80f",
+        "bnez   a0, 90f",
+
+        // 4. ... but not app 0's
+        "li     a1, 4",
+        "la     a0, _u_ram_start",
+        "jal    t0, 80f",
+        "beqz   a0, 90f",
+
+        // 5. switch back to app 0
+        "li     a1, 5",
+        "li     a0, 0",
+        "li     a7, 13",
+        "ecall",
+        "bnez   a0, 90f",
+
+        // 6. app 1's RAM is out of reach again, app 0's back
+        "li     a1, 6",
+        "mv     a0, s1",
+        "jal    t0, 80f",
+        "beqz   a0, 90f",
+        "la     a0, _u_ram_start",
+        "jal    t0, 80f",
+        "bnez   a0, 90f",
+
+        // 7. no app 2
+        "li     a1, 7",
+        "li     a0, 2",
+        "li     a7, 13",
+        "ecall",
+        "li     t1, -1",
+        "bne    a0, t1, 90f",
+
+        "la     a0, u_app_msg_pass",
+        "li     a1, u_app_msg_fail - u_app_msg_pass",
+        "li     a7, 1",
+        "ecall",
+        "lw     s0, 8(sp)",
+        "lw     s1, 4(sp)",
+        "lw     ra, 12(sp)",
+        "addi   sp, sp, 16",
+        "addi   x{ss}, x{ss}, -4",        // sw_sspopchk
+        "lw     t0, 0(x{ss})",
+        "bne    t0, ra, 99f",
+        "98:",
+        ".if {ss_xcheck}",
+        "jal    t0, _u_ss_crosscheck",
+        ".else",
+        ".4byte {sspopchk}",        // sspopchk ra (HW)
+        ".endif",
+        "ret",
+
+        // ── Probe: a0 = address -> a0 = 1 if a load from it faulted ──
+        // Called with `jal t0`; the trap handler preserves t1.
+        "80:",
+        "li     t1, 1",
+        ".globl u_app_probe_load",
+        "u_app_probe_load:",
+        "lw     t2, 0(a0)",
+        "li     t1, 0",
+        ".globl u_app_probe_caught",
+        "u_app_probe_caught:",
+        "mv     a0, t1",
+        "jr     t0",
+
+        // ── FAIL: exit(step) ──
+        "90:",
+        "mv     s0, a1",
+        "la     a0, u_app_msg_fail",
+        "li     a1, u_app_msg_end - u_app_msg_fail",
+        "li     a7, 1",
+        "ecall",
+        "mv     a0, s0",
+        "li     a7, 2",
+        "ecall",
+        "99:",                      // Shadow stack mismatch
+        ".if {shadow_trace}",
+        "mv     t1, t0",            // report, then carry on with ra
+        "jal    t0, _u_shadow_trace",
+        "j      98b",
+        ".else",
+        "ebreak",
+        ".endif",
+        ss = const SW_SS_REG,
+        app1_ram = const APP_1.ram.base,
+        sspush = const cfi_encoding::SSPUSH_RA,
+        sspopchk = const cfi_encoding::SSPOPCHK_RA,
+        ss_xcheck = const cfg!(feature = "ss-crosscheck") as u32,
+        shadow_trace = const cfg!(feature = "shadow-trace") as u32,
+    )
+}
+
+/// Rounds of [`u_sched_task`]: lines each task prints.
+#[cfg(feature = "sched-demo")]
+const SCHED_ROUNDS: u32 = 4;
+
+/// mtime ticks between a task's lines: 2.5 time slices, so the rounds of
+/// the two tasks interleave.
+#[cfg(feature = "sched-demo")]
+const SCHED_PRINT_PERIOD: u32 = 250_000;
+
+/// U-mode scheduler demo, as task 0: start task 1 and run the same loop
+/// alongside it, each task printing its id while the timer switches
+/// between them.  Steps:
+///
+///   1. task_spawn starts task 1 at `u_sched_worker`;
+///   2. a second task_spawn is refused: the slot is taken;
+///   3. task 0's own rounds of [`u_sched_task`] pass;
+///   4. task_wait returns task 1's exit code, its rounds' result: 0;
+///   5. a second task_wait is refused: task 1 is gone.
+///
+/// Prints PASS, or FAIL and exits with the failing step as the code.
+///
+/// # Safety
+///
+/// U-mode code, running as task 0: [`SW_SS_REG`] must point into the
+/// U-mode software shadow stack.
+#[cfg(feature = "sched-demo")]
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".u_text"]
+pub unsafe extern "C" fn u_sched_demo() {
+    cfi_target_asm!(cfi_labels::UNLABELED;
+        ".pushsection .u_rodata.sched, \"a\"",
+        "u_sched_msg_pass:",
+        ".ascii \"[SCHED] two tasks preempted and resumed intact: PASS\\r\\n\"",
+        "u_sched_msg_fail:",
+        ".ascii \"[SCHED] FAIL\\r\\n\"",
+        "u_sched_msg_end:",
+        ".popsection",
+
+        ".4byte {sspush}",          // sspush ra (HW)
+        "addi   sp, sp, -16",
+        "sw     ra, 12(sp)",
+        "sw     s0, 8(sp)",
+        "sw     ra, 0(x{ss})",         // sw_sspush
+        "addi   x{ss}, x{ss}, 4",
+
+        // 1. start task 1: u_sched_worker(1)
+        "li     s0, 1",
+        "la     a0, u_sched_worker",
+        "li     a1, 1",
+        "li     a7, 16",
+        "ecall",
+        "bnez   a0, 90f",
+
+        // 2. there is only one task 1
+        "li     s0, 2",
+        "la     a0, u_sched_worker",
+        "li     a1, 1",
+        "li     a7, 16",
+        "ecall",
+        "li     t1, -1",
+        "bne    a0, t1, 90f",
+
+        // 3. task 0's rounds, with task 1 running in the other slices
+        "li     s0, 3",
+        "li     a0, 0",
+        "call   u_sched_task",
+        "bnez   a0, 90f",
+
+        // 4. wait for task 1: its rounds passed too
+        "li     s0, 4",
+        "li     a7, 17",
+        "ecall",
+        "bnez   a0, 90f",
+
+        // 5. nothing left to wait for
+        "li     s0, 5",
+        "li     a7, 17",
+        "ecall",
+        "li     t1, -1",
+        "bne    a0, t1, 90f",
+
+        "la     a0, u_sched_msg_pass",
+        "li     a1, u_sched_msg_fail - u_sched_msg_pass",
+        "li     a7, 1",
+        "ecall",
+        "lw     s0, 8(sp)",
+        "lw     ra, 12(sp)",
+        "addi   sp, sp, 16",
+        "addi   x{ss}, x{ss}, -4",        // sw_sspopchk
+        "lw     t0, 0(x{ss})",
+        "bne    t0, ra, 99f",
+        "98:",
+        ".if {ss_xcheck}",
+        "jal    t0, _u_ss_crosscheck",
+        ".else",
+        ".4byte {sspopchk}",        // sspopchk ra (HW)
+        ".endif",
+        "ret",
+
+        // ── FAIL: exit(step) ──
+        "90:",
+        "la     a0, u_sched_msg_fail",
+        "li     a1, u_sched_msg_end - u_sched_msg_fail",
+        "li     a7, 1",
+        "ecall",
+        "mv     a0, s0",
+        "li     a7, 2",
+        "ecall",
+        "99:",                      // Shadow stack mismatch
+        ".if {shadow_trace}",
+        "mv     t1, t0",            // report, then carry on with ra
+        "jal    t0, _u_shadow_trace",
+        "j      98b",
+        ".else",
+        "ebreak",
+        ".endif",
+        ss = const SW_SS_REG,
+        sspush = const cfi_encoding::SSPUSH_RA,
+        sspopchk = const cfi_encoding::SSPOPCHK_RA,
+        ss_xcheck = const cfg!(feature = "ss-crosscheck") as u32,
+        shadow_trace = const cfg!(feature = "shadow-trace") as u32,
+    )
+}
+
+/// Task 1's entry (`sched-demo` builds), with its id from task_spawn in
+/// a0: run the demo rounds, then exit with their result.
+///
+/// # Safety
+///
+/// Only reachable via `mret` from the scheduler, on task 1's stacks.
+#[cfg(feature = "sched-demo")]
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".u_text"]
+pub unsafe extern "C" fn u_sched_worker(id: usize) -> ! {
+    cfi_target_asm!(cfi_labels::UNLABELED;
+        "call   u_sched_task",
+        "li     a7, 18",            // task_exit(result)
+        "ecall",
+        // Should not reach here
+        "70: wfi",
+        "j      70b",
+    )
+}
+
+/// One task's demo rounds: print the task's id, then spin on `time` for
+/// [`SCHED_PRINT_PERIOD`], [`SCHED_ROUNDS`] times over.  Sentinels in
+/// s3-s10 and tp, different for each task, must survive every switch.
+///
+/// Returns 0; 1 if a sentinel changed; or 2 if no two successive `time`
+/// readings were half a time slice apart, i.e. the task never saw the
+/// other one run.
+///
+/// # Safety
+///
+/// U-mode code: [`SW_SS_REG`] must point into the running task's software
+/// shadow stack.
+#[cfg(feature = "sched-demo")]
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".u_text"]
+pub unsafe extern "C" fn u_sched_task(id: usize) -> usize {
+    cfi_target_asm!(cfi_labels::UNLABELED;
+        ".pushsection .u_rodata.sched_task, \"a\"",
+        "u_sched_msg_task0:",
+        ".ascii \"[SCHED] task 0 running\\r\\n\"",
+        "u_sched_msg_task1:",
+        ".ascii \"[SCHED] task 1 running\\r\\n\"",
+        ".popsection",
+
+        ".4byte {sspush}",          // sspush ra (HW)
+        "addi   sp, sp, -64",
+        "sw     ra, 60(sp)",
+        "sw     s0, 56(sp)",
+        "sw     s1, 52(sp)",
+        "sw     s2, 48(sp)",
+        "sw     s3, 44(sp)",
+        "sw     s4, 40(sp)",
+        "sw     s5, 36(sp)",
+        "sw     s6, 32(sp)",
+        "sw     s7, 28(sp)",
+        "sw     s8, 24(sp)",
+        "sw     s9, 20(sp)",
+        "sw     s10, 16(sp)",
+        "sw     tp, 12(sp)",
+        "sw     ra, 0(x{ss})",         // sw_sspush
+        "addi   x{ss}, x{ss}, 4",
+
+        "mv     s0, a0",            // task id
+        "li     s1, {rounds}",
+        "li     s2, 0",             // slices seen go to the other task
+        // Sentinels: 0x5C0000 | register number, task id in bits 8-15
+        "slli   t5, s0, 8",
+        "li     s3, 0x5C000013",
+        "xor    s3, s3, t5",
+        "li     s4, 0x5C000014",
+        "xor    s4, s4, t5",
+        "li     s5, 0x5C000015",
+        "xor    s5, s5, t5",
+        "li     s6, 0x5C000016",
+        "xor    s6, s6, t5",
+        "li     s7, 0x5C000017",
+        "xor    s7, s7, t5",
+        "li     s8, 0x5C000018",
+        "xor    s8, s8, t5",
+        "li     s9, 0x5C000019",
+        "xor    s9, s9, t5",
+        "li     s10, 0x5C00001A",
+        "xor    s10, s10, t5",
+        "li     tp, 0x5C000004",
+        "xor    tp, tp, t5",
+
+        // One round: print this task's line, then spin for the period.
+        // t3 = start of the spin, t4 = the last `time` reading (low
+        // halves: the differences wrap correctly)
+        "10:",
+        "li     t0, u_sched_msg_task1 - u_sched_msg_task0",
+        "mul    t1, s0, t0",
+        "la     a0, u_sched_msg_task0",
+        "add    a0, a0, t1",
+        "mv     a1, t0",
+        "li     a7, 1",
+        "ecall",
+        "li     a7, 14",
+        "ecall",
+        "mv     t3, a0",
+        "mv     t4, a0",
+        "20:",
+        "li     a7, 14",
+        "ecall",
+        "sub    t0, a0, t4",
+        "mv     t4, a0",
+        "li     t1, {half_slice}",
+        "bltu   t0, t1, 30f",
+        "addi   s2, s2, 1",         // a slice went by elsewhere
+        "30:",
+        "sub    t0, a0, t3",
+        "li     t1, {period}",
+        "bltu   t0, t1, 20b",
+        "addi   s1, s1, -1",
+        "bnez   s1, 10b",
+
+        // Sentinels intact?
+        "li     a0, 1",
+        "slli   t5, s0, 8",
+        "li     t0, 0x5C000013",
+        "xor    t0, t0, t5",
+        "bne    s3, t0, 40f",
+        "li     t0, 0x5C000014",
+        "xor    t0, t0, t5",
+        "bne    s4, t0, 40f",
+        "li     t0, 0x5C000015",
+        "xor    t0, t0, t5",
+        "bne    s5, t0, 40f",
+        "li     t0, 0x5C000016",
+        "xor    t0, t0, t5",
+        "bne    s6, t0, 40f",
+        "li     t0, 0x5C000017",
+        "xor    t0, t0, t5",
+        "bne    s7, t0, 40f",
+        "li     t0, 0x5C000018",
+        "xor    t0, t0, t5",
+        "bne    s8, t0, 40f",
+        "li     t0, 0x5C000019",
+        "xor    t0, t0, t5",
+        "bne    s9, t0, 40f",
+        "li     t0, 0x5C00001A",
+        "xor    t0, t0, t5",
+        "bne    s10, t0, 40f",
+        "li     t0, 0x5C000004",
+        "xor    t0, t0, t5",
+        "bne    tp, t0, 40f",
+        // ... and preempted at least once?
+        "li     a0, 2",
+        "beqz   s2, 40f",
+        "li     a0, 0",
+
+        "40:",
+        "lw     s0, 56(sp)",
+        "lw     s1, 52(sp)",
+        "lw     s2, 48(sp)",
+        "lw     s3, 44(sp)",
+        "lw     s4, 40(sp)",
+        "lw     s5, 36(sp)",
+        "lw     s6, 32(sp)",
+        "lw     s7, 28(sp)",
+        "lw     s8, 24(sp)",
+        "lw     s9, 20(sp)",
+        "lw     s10, 16(sp)",
+        "lw     tp, 12(sp)",
+        "lw     ra, 60(sp)",
+        "addi   sp, sp, 64",
+        "addi   x{ss}, x{ss}, -4",        // sw_sspopchk
+        "lw     t0, 0(x{ss})",
+        "bne    t0, ra, 99f",
+        "98:",
+        ".if {ss_xcheck}",
+        "jal    t0, _u_ss_crosscheck",
+        ".else",
+        ".4byte {sspopchk}",        // sspopchk ra (HW)
+        ".endif",
+        "ret",
+        "99:",                      // Shadow stack mismatch
+        ".if {shadow_trace}",
+        "mv     t1, t0",            // report, then carry on with ra
+        "jal    t0, _u_shadow_trace",
+        "j      98b",
+        ".else",
+        "ebreak",
+        ".endif",
+        ss = const SW_SS_REG,
+        rounds = const SCHED_ROUNDS,
+        period = const SCHED_PRINT_PERIOD,
+        half_slice = const SCHED_TICK / 2,
+        sspush = const cfi_encoding::SSPUSH_RA,
+        sspopchk = const cfi_encoding::SSPOPCHK_RA,
+        ss_xcheck = const cfg!(feature = "ss-crosscheck") as u32,
+        shadow_trace = const cfg!(feature = "shadow-trace") as u32,
+    )
+}
+
+/// U-mode monitor-call test: the token gates `lock_pcr`, and the monitor
+/// and service namespaces stay apart.
+///
+/// Called first thing in `_u_entry`, with the token `launch_umode` left
+/// in a0.  On PCR2 (runtime, unlocked at launch) it checks, in order:
+///
+///   1. service 11 (pcr_extend) succeeds;
+///   2. monitor `lock_pcr` with a wrong token is refused, and the PCR
+///      still takes an extend afterwards;
+///   3. monitor 11 — the pcr_extend number with bit 31 set — is refused
+///      even with the right token: there is no such monitor call;
+///   4. a7 = 0x80000000 — putc's number with bit 31 set — reaches
+///      `lock_pcr`, which with the right token succeeds;
+///   5. service 11 is now refused: the PCR is locked.
+///
+/// Prints PASS, or FAIL and exits with the failing step as the code.
+///
+/// # Safety
+///
+/// U-mode code; a0 must hold the monitor-call token.
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".u_text"]
+pub unsafe extern "C" fn u_monitor_test(token: u32) {
+    cfi_target_asm!(cfi_labels::UNLABELED;
+        ".pushsection .u_rodata.monitor, \"a\"",
+        "u_monitor_digest:",
+        ".fill 32, 1, 0x5a",
+        "u_monitor_msg_pass:",
+        ".ascii \"[MONITOR] token-gated lock_pcr, namespaces disjoint: PASS\\r\\n\"",
+        "u_monitor_msg_fail:",
+        ".ascii \"[MONITOR] FAIL\\r\\n\"",
+        "u_monitor_msg_end:",
+        ".popsection",
+
+        "mv     t6, a0",            // token (ecalls preserve t6)
+        "li     t5, -1",            // SYSCALL_ERR
+
+        // 1. extend PCR2 -> 0
+        "li     t4, 1",
+        "li     a0, {pcr}",
+        "la     a1, u_monitor_digest",
+        "li     a7, 11",
+        "ecall",
+        "bnez   a0, 90f",
+
+        // 2. lock_pcr(PCR2) with a wrong token -> -1, PCR2 still open
+        "li     t4, 2",
+        "li     a0, {pcr}",
+        "not    a1, t6",
+        "li     a7, {lock_pcr}",
+        "ecall",
+        "bne    a0, t5, 90f",
+        "li     a0, {pcr}",
+        "la     a1, u_monitor_digest",
+        "li     a7, 11",
+        "ecall",
+        "bnez   a0, 90f",
+
+        // 3. monitor 11 (no such call) with the right token -> -1
+        "li     t4, 3",
+        "li     a0, {pcr}",
+        "mv     a1, t6",
+        "li     a7, {lock_pcr} | 11",
+        "ecall",
+        "bne    a0, t5, 90f",
+
+        // 4. lock_pcr(PCR2) with the right token -> 0
+        "li     t4, 4",
+        "li     a0, {pcr}",
+        "mv     a1, t6",
+        "li     a7, {lock_pcr}",
+        "ecall",
+        "bnez   a0, 90f",
+
+        // 5. extend PCR2 -> -1 (locked)
+        "li     t4, 5",
+        "li     a0, {pcr}",
+        "la     a1, u_monitor_digest",
+        "li     a7, 11",
+        "ecall",
+        "bne    a0, t5, 90f",
+
+        "la     a0, u_monitor_msg_pass",
+        "li     a1, u_monitor_msg_fail - u_monitor_msg_pass",
+        "li     a7, 1",
+        "ecall",
+        "ret",
+
+        "90:",
+        "la     a0, u_monitor_msg_fail",
+        "li     a1, u_monitor_msg_end - u_monitor_msg_fail",
+        "li     a7, 1",
+        "ecall",
+        "mv     a0, t4",
+        "li     a7, 2",
+        "ecall",
+        pcr = const PCR_RUNTIME,
+        lock_pcr = const monitor::monitor_call(monitor::LOCK_PCR),
+    )
+}
+
+/// U-mode key-provisioning test: `provision_key` writes a blank slot
+/// once, and sealing switches to the key it wrote.
+///
+/// Called from `_u_entry` after [`u_monitor_test`], with the token in a0.
+/// Uses seal slot 3 and leaves slot 1, the REPL's, blank.  Checks, in
+/// order:
+///
+///   1. `seal(DATA, 3)` is the blank-slot stub, `DATA ^ 3`;
+///   2. `provision_key(3)` with a wrong token is refused;
+///   3. `provision_key` of slot 4 (out of range) is refused;
+///   4. `provision_key(3)` with the right token succeeds;
+///   5. `seal(DATA, 3)` now differs from the stub;
+///   6. a second `provision_key(3)`, with another key, is refused;
+///   7. `seal(DATA, 3)` is as in step 5: the first key stayed;
+///   8. `provision_key(2)` with the key read from [`KEY_SLOTS`] itself (M_RAM,
+///      which U-mode can't read) is refused, and slot 2 still seals as
+///      the stub;
+///   9. `provision_key` of [`keyslot::SLOT_ATTEST`], the key quotes are
+///      signed with, is refused.
+///
+/// Prints PASS, or FAIL and exits with the failing step as the code.
+///
+/// # Safety
+///
+/// U-mode code; a0 must hold the monitor-call token.
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".u_text"]
+pub unsafe extern "C" fn u_provision_test(token: u32) {
+    cfi_target_asm!(cfi_labels::UNLABELED;
+        ".pushsection .u_rodata.provision, \"a\"",
+        "u_provision_key:",
+        ".fill 32, 1, 0xc3",
+        "u_provision_key2:",
+        ".fill 32, 1, 0x3c",
+        "u_provision_msg_pass:",
+        ".ascii \"[PROVISION] key slot written once, seal uses it: PASS\\r\\n\"",
+        "u_provision_msg_fail:",
+        ".ascii \"[PROVISION] FAIL\\r\\n\"",
+        "u_provision_msg_end:",
+        ".popsection",
+
+        "mv     t6, a0",            // token (ecalls preserve t6)
+        "li     t5, -1",            // SYSCALL_ERR
+
+        // 1. seal(DATA, 3) -> DATA ^ 3
+        "li     t4, 1",
+        "li     a0, {data}",
+        "li     a1, 3",
+        "li     a7, 10",
+        "ecall",
+        "li     t0, {data} ^ 3",
+        "bne    a0, t0, 90f",
+
+        // 2. provision_key(3) with a wrong token -> -1
+        "li     t4, 2",
+        "li     a0, 3",
+        "not    a1, t6",
+        "la     a2, u_provision_key",
+        "li     a7, {provision}",
+        "ecall",
+        "bne    a0, t5, 90f",
+
+        // 3. provision_key(SLOT_COUNT) -> -1
+        "li     t4, 3",
+        "li     a0, {slots}",
+        "mv     a1, t6",
+        "la     a2, u_provision_key",
+        "li     a7, {provision}",
+        "ecall",
+        "bne    a0, t5, 90f",
+
+        // 4. provision_key(3) -> 0
+        "li     t4, 4",
+        "li     a0, 3",
+        "mv     a1, t6",
+        "la     a2, u_provision_key",
+        "li     a7, {provision}",
+        "ecall",
+        "bnez   a0, 90f",
+
+        // 5. seal(DATA, 3) != DATA ^ 3; keep it in t3
+        "li     t4, 5",
+        "li     a0, {data}",
+        "li     a1, 3",
+        "li     a7, 10",
+        "ecall",
+        "li     t0, {data} ^ 3",
+        "beq    a0, t0, 90f",
+        "mv     t3, a0",
+
+        // 6. provision_key(3) again, another key -> -1
+        "li     t4, 6",
+        "li     a0, 3",
+        "mv     a1, t6",
+        "la     a2, u_provision_key2",
+        "li     a7, {provision}",
+        "ecall",
+        "bne    a0, t5, 90f",
+
+        // 7. seal(DATA, 3) unchanged
+        "li     t4, 7",
+        "li     a0, {data}",
+        "li     a1, 3",
+        "li     a7, 10",
+        "ecall",
+        "bne    a0, t3, 90f",
+
+        // 8. provision_key(2) from KEY_SLOTS itself -> -1; slot 2 still blank
+        "li     t4, 8",
+        "li     a0, 2",
+        "mv     a1, t6",
+        "la     a2, {key_slots}",
+        "li     a7, {provision}",
+        "ecall",
+        "bne    a0, t5, 90f",
+        "li     a0, {data}",
+        "li     a1, 2",
+        "li     a7, 10",
+        "ecall",
+        "li     t0, {data} ^ 2",
+        "bne    a0, t0, 90f",
+
+        // 9. provision_key(SLOT_ATTEST) -> -1
+        "li     t4, 9",
+        "li     a0, {attest}",
+        "mv     a1, t6",
+        "la     a2, u_provision_key",
+        "li     a7, {provision}",
+        "ecall",
+        "bne    a0, t5, 90f",
+
+        "la     a0, u_provision_msg_pass",
+        "li     a1, u_provision_msg_fail - u_provision_msg_pass",
+        "li     a7, 1",
+        "ecall",
+        "ret",
+
+        "90:",
+        "la     a0, u_provision_msg_fail",
+        "li     a1, u_provision_msg_end - u_provision_msg_fail",
+        "li     a7, 1",
+        "ecall",
+        "mv     a0, t4",
+        "li     a7, 2",
+        "ecall",
+        data = const 0x5EA1_0000u32,
+        slots = const keyslot::SLOT_COUNT,
+        attest = const keyslot::SLOT_ATTEST,
+        key_slots = sym KEY_SLOTS,
+        provision = const monitor::monitor_call(monitor::PROVISION_KEY),
+    )
+}
+
+/// U-mode `pcr_read` test: the boot PCRs are readable from U-mode, and
+/// nothing else is.
+///
+/// Called from `_u_entry` after [`u_monitor_test`].  Checks, in order:
+///
+///   1. `pcr_read(PCR0)` into a stack buffer succeeds;
+///   2. the value isn't all zero — Phase 3 extended PCR0 with the ROM
+///      digest before launch;
+///   3. `pcr_read(PCR_COUNT)` is refused;
+///   4. `pcr_read(PCR0)` into `.u_rodata`, which U-mode can't write, is
+///      refused.
+///
+/// Then prints the value grouped like the boot log, so the line reads the
+/// same as the `PCR0 =` one Phase 3 printed, and returns.  On a failure it
+/// prints FAIL and exits with the failing step as the code.
+///
+/// # Safety
+///
+/// U-mode code, with 48 bytes of U stack to spare.
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".u_text"]
+pub unsafe extern "C" fn u_pcr_read_test() {
+    cfi_target_asm!(cfi_labels::UNLABELED;
+        ".pushsection .u_rodata.pcr_read, \"a\"",
+        "u_pcr_read_msg:",
+        ".ascii \"[U-MODE] pcr_read: PCR0 = \"",
+        "u_pcr_read_msg_fail:",
+        ".ascii \"[U-MODE] pcr_read: FAIL\\r\\n\"",
+        "u_pcr_read_msg_end:",
+        "u_pcr_read_hex:",
+        ".ascii \"0123456789abcdef\"",
+        ".popsection",
+
+        "addi   sp, sp, -48",       // 0(sp): the 32-byte PCR buffer
+        "li     t5, -1",            // SYSCALL_ERR
+
+        // 1. read PCR0 -> 0
+        "li     t4, 1",
+        "li     a0, {pcr0}",
+        "mv     a1, sp",
+        "li     a7, 8",
+        "ecall",
+        "bnez   a0, 90f",
+
+        // 2. not all zero
+        "li     t4, 2",
+        "mv     t0, sp",
+        "addi   t1, sp, 32",
+        "li     t3, 0",
+        "1: lbu  t2, 0(t0)",
+        "or     t3, t3, t2",
+        "addi   t0, t0, 1",
+        "bne    t0, t1, 1b",
+        "beqz   t3, 90f",
+
+        // 3. no PCR {count} -> -1
+        "li     t4, 3",
+        "li     a0, {count}",
+        "mv     a1, sp",
+        "li     a7, 8",
+        "ecall",
+        "bne    a0, t5, 90f",
+
+        // 4. read-only destination -> -1
+        "li     t4, 4",
+        "li     a0, {pcr0}",
+        "la     a1, u_pcr_read_msg",
+        "li     a7, 8",
+        "ecall",
+        "bne    a0, t5, 90f",
+
+        // Print the value: 8 groups of 4 bytes
+        "la     a0, u_pcr_read_msg",
+        "li     a1, u_pcr_read_msg_fail - u_pcr_read_msg",
+        "li     a7, 1",
+        "ecall",
+        "mv     t0, sp",
+        "addi   t1, sp, 32",
+        "la     t2, u_pcr_read_hex",
+        "li     a7, 0",
+        "2: lbu  t3, 0(t0)",
+        "srli   a0, t3, 4",
+        "add    a0, t2, a0",
+        "lbu    a0, 0(a0)",
+        "ecall",
+        "andi   a0, t3, 15",
+        "add    a0, t2, a0",
+        "lbu    a0, 0(a0)",
+        "ecall",
+        "addi   t0, t0, 1",
+        "beq    t0, t1, 3f",
+        "sub    a0, t0, sp",
+        "andi   a0, a0, 3",
+        "bnez   a0, 2b",
+        "li     a0, 0x20",          // ' ' between groups
+        "ecall",
+        "j      2b",
+        "3: li   a0, 0x0D",
+        "ecall",
+        "li     a0, 0x0A",
+        "ecall",
+        "addi   sp, sp, 48",
+        "ret",
+
+        "90:",
+        "la     a0, u_pcr_read_msg_fail",
+        "li     a1, u_pcr_read_msg_end - u_pcr_read_msg_fail",
+        "li     a7, 1",
+        "ecall",
+        "mv     a0, t4",
+        "li     a7, 2",
+        "ecall",
+        pcr0 = const PCR_ROM,
+        count = const PCR_COUNT,
+    )
+}
+
+/// U-mode boot-info test: the [`BootInfo`] `launch_umode` passed in a2
+/// is well-formed and agrees with what U-mode can check for itself.
+///
+/// Called from `_u_entry` after [`u_pcr_read_test`], with the hartid and
+/// boot-info pointer from entry in a0 and a1.  Checks, in order:
+///
+///   1. the magic and version;
+///   2. its hartid is the one in a1 at entry;
+///   3. its U_RAM region holds the stack pointer;
+///   4. its PCR0 is what `pcr_read(0)` returns now (PCR0 is locked).
+///
+/// Then prints the hartid and PCR0 as it read them, and PASS.  On a
+/// failure it prints FAIL and exits with the failing step as the code.
+/// Borrows [`u_pcr_read_test`]'s hex digits.
+///
+/// # Safety
+///
+/// U-mode code, with 32 bytes of U stack to spare; a1 must point to
+/// readable memory.
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".u_text"]
+pub unsafe extern "C" fn u_boot_info_test(hartid: u32, info: *const BootInfo) {
+    cfi_target_asm!(cfi_labels::UNLABELED;
+        ".pushsection .u_rodata.boot_info_test, \"a\"",
+        "u_boot_info_msg:",
+        ".ascii \"[U-MODE] boot info: hartid 0x\"",
+        "u_boot_info_msg_pcr:",
+        ".ascii \", PCR0 = \"",
+        "u_boot_info_msg_pass:",
+        ".ascii \"[U-MODE] boot info matches entry, stack and pcr_read: PASS\\r\\n\"",
+        "u_boot_info_msg_fail:",
+        ".ascii \"[U-MODE] boot info: FAIL\\r\\n\"",
+        "u_boot_info_msg_end:",
+        ".popsection",
+
+        // Ecalls preserve everything but a0.
+        "mv     t3, a0",            // hartid
+        "mv     t6, a1",            // &BootInfo
+        "addi   sp, sp, -32",       // PCR buffer
+
+        // 1. magic, version
+        "li     t4, 1",
+        "lw     t0, {magic}(t6)",
+        "li     t1, {boot_magic}",
+        "bne    t0, t1, 90f",
+        "lw     t0, {version}(t6)",
+        "li     t1, {boot_version}",
+        "bne    t0, t1, 90f",
+
+        // 2. hartid as at entry
+        "li     t4, 2",
+        "lw     t0, {hartid}(t6)",
+        "bne    t0, t3, 90f",
+
+        // 3. sp - U_RAM.base < U_RAM.size
+        "li     t4, 3",
+        "lw     t0, {u_ram}(t6)",
+        "lw     t1, {u_ram} + 4(t6)",
+        "sub    t0, sp, t0",
+        "bgeu   t0, t1, 90f",
+
+        // 4. PCR0 == pcr_read(0)
+        "li     t4, 4",
+        "li     a0, {pcr0}",
+        "mv     a1, sp",
+        "li     a7, 8",
+        "ecall",
+        "bnez   a0, 90f",
+        "mv     t0, sp",
+        "addi   t1, t6, {pcrs}",
+        "addi   t2, sp, 32",
+        "1:",
+        "lw     a0, 0(t0)",
+        "lw     a1, 0(t1)",
+        "bne    a0, a1, 90f",
+        "addi   t0, t0, 4",
+        "addi   t1, t1, 4",
+        "bne    t0, t2, 1b",
+
+        // Print the hartid, eight hex digits from the top...
+        "la     a0, u_boot_info_msg",
+        "li     a1, u_boot_info_msg_pcr - u_boot_info_msg",
+        "li     a7, 1",
+        "ecall",
+        "la     t5, u_pcr_read_hex",
+        "li     a7, 0",             // putc
+        "li     t2, 28",
+        "2:",
+        "srl    a0, t3, t2",
+        "andi   a0, a0, 15",
+        "add    a0, a0, t5",
+        "lbu    a0, 0(a0)",
+        "ecall",
+        "addi   t2, t2, -4",
+        "bgez   t2, 2b",
+        // ...then PCR0 from the boot info, two digits a byte
+        "la     a0, u_boot_info_msg_pcr",
+        "li     a1, u_boot_info_msg_pass - u_boot_info_msg_pcr",
+        "li     a7, 1",
+        "ecall",
+        "li     a7, 0",
+        "addi   t0, t6, {pcrs}",
+        "addi   t1, t0, 32",
+        "3:",
+        "lbu    a1, 0(t0)",
+        "srli   a0, a1, 4",
+        "add    a0, a0, t5",
+        "lbu    a0, 0(a0)",
+        "ecall",
+        "andi   a0, a1, 15",
+        "add    a0, a0, t5",
+        "lbu    a0, 0(a0)",
+        "ecall",
+        "addi   t0, t0, 1",
+        "bne    t0, t1, 3b",
+        "li     a0, 0x0D",
+        "ecall",
+        "li     a0, 0x0A",
+        "ecall",
+
+        "addi   sp, sp, 32",
+        "la     a0, u_boot_info_msg_pass",
+        "li     a1, u_boot_info_msg_fail - u_boot_info_msg_pass",
+        "li     a7, 1",
+        "ecall",
+        "ret",
+
+        "90:",
+        "la     a0, u_boot_info_msg_fail",
+        "li     a1, u_boot_info_msg_end - u_boot_info_msg_fail",
+        "li     a7, 1",
+        "ecall",
+        "mv     a0, t4",
+        "li     a7, 2",
+        "ecall",
+        magic = const offset_of!(BootInfo, magic),
+        version = const offset_of!(BootInfo, version),
+        hartid = const offset_of!(BootInfo, hartid),
+        u_ram = const offset_of!(BootInfo, regions) + boot_info::REGION_U_RAM * size_of::<BootRegion>(),
+        pcrs = const offset_of!(BootInfo, pcrs),
+        boot_magic = const boot_info::BOOT_INFO_MAGIC,
+        boot_version = const boot_info::BOOT_INFO_VERSION,
+        pcr0 = const PCR_ROM,
+    )
+}
+
+/// U-mode unknown-syscall test: numbers with no service come back as
+/// [`SYSCALL_NOT_SUPPORTED`], not with a0 as it was.
+///
+/// Called from `_u_entry` after [`u_pcr_read_test`].  Checks, in order:
+///
+///   1. [`SYSCALL_UNUSED`], one past the last number this build
+///      registers, with a0 holding 0 beforehand, returns -2;
+///   2. syscall 0x7fffffff, the largest number without the monitor-call
+///      bit, returns -2;
+///   3. the registers other than a0 are as they were: a1 and t6 still
+///      hold what was put there.
+///
+/// Each call also prints the M-mode `[WARN] unknown syscall` line.  Prints
+/// PASS, or FAIL and exits with the failing step as the code.
+///
+/// # Safety
+///
+/// U-mode code.
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".u_text"]
+pub unsafe extern "C" fn u_unknown_syscall_test() {
+    cfi_target_asm!(cfi_labels::UNLABELED;
+        ".pushsection .u_rodata.unknown_syscall, \"a\"",
+        "u_unknown_msg_pass:",
+        ".ascii \"[U-MODE] unknown syscalls return NotSupported: PASS\\r\\n\"",
+        "u_unknown_msg_fail:",
+        ".ascii \"[U-MODE] unknown syscall: FAIL\\r\\n\"",
+        "u_unknown_msg_end:",
+        ".popsection",
+
+        "li     t5, {not_supported}",
+
+        // 1. one past the last syscall -> -2
+        "li     t4, 1",
+        "li     a0, 0",
+        "li     a1, 0x5a5a",
+        "li     t6, 0x1234",
+        "li     a7, {unused}",
+        "ecall",
+        "bne    a0, t5, 90f",
+
+        // 2. largest non-monitor number -> -2
+        "li     t4, 2",
+        "li     a0, 0",
+        "li     a7, 0x7fffffff",
+        "ecall",
+        "bne    a0, t5, 90f",
+
+        // 3. a1 and t6 untouched
+        "li     t4, 3",
+        "li     t0, 0x5a5a",
+        "bne    a1, t0, 90f",
+        "li     t0, 0x1234",
+        "bne    t6, t0, 90f",
+
+        "la     a0, u_unknown_msg_pass",
+        "li     a1, u_unknown_msg_fail - u_unknown_msg_pass",
+        "li     a7, 1",
+        "ecall",
+        "ret",
+
+        "90:",
+        "la     a0, u_unknown_msg_fail",
+        "li     a1, u_unknown_msg_end - u_unknown_msg_fail",
+        "li     a7, 1",
+        "ecall",
+        "mv     a0, t4",
+        "li     a7, 2",
+        "ecall",
+        not_supported = const SYSCALL_NOT_SUPPORTED as i32,
+        unused = const SYSCALL_UNUSED,
+    )
+}
+
+/// U-mode heap test: `sbrk` hands out the memory between `.u_bss` and the
+/// stack, and running out is an error rather than an overlap.
+///
+/// Called from `_u_entry` after [`u_unknown_syscall_test`].  Checks, in
+/// order:
+///
+///   1. `sbrk(0)` returns the heap start, not -1;
+///   2. `sbrk(64)` returns the start, and the 64 bytes take a store and
+///      read it back;
+///   3. `sbrk(0)` is now start + 64;
+///   4. `sbrk(0x10000000)` is refused and leaves the break where it was;
+///   5. `sbrk(1024)` repeated until refused stops at the stack: the final
+///      break is at most `_u_stack_bottom` and less than 1024 below it, and
+///      the heap's last byte is usable;
+///   6. shrinking back to the start works, `sbrk(-1)` below it is refused,
+///      and `sbrk(0)` returns the start again: the heap is left empty.
+///
+/// Prints PASS, or FAIL and exits with the failing step as the code.
+///
+/// # Safety
+///
+/// U-mode code.
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".u_text"]
+pub unsafe extern "C" fn u_sbrk_test() {
+    cfi_target_asm!(cfi_labels::UNLABELED;
+        ".pushsection .u_rodata.sbrk, \"a\"",
+        "u_sbrk_msg_pass:",
+        ".ascii \"[HEAP] sbrk grows to the stack and refuses past it: PASS\\r\\n\"",
+        "u_sbrk_msg_fail:",
+        ".ascii \"[HEAP] FAIL\\r\\n\"",
+        "u_sbrk_msg_end:",
+        ".popsection",
+
+        "li     t5, -1",            // SYSCALL_ERR
+
+        // 1. sbrk(0) -> start; keep it in t3
+        "li     t4, 1",
+        "li     a0, 0",
+        "li     a7, 22",
+        "ecall",
+        "beq    a0, t5, 90f",
+        "mv     t3, a0",
+
+        // 2. sbrk(64) -> start, and the memory works
+        "li     t4, 2",
+        "li     a0, 64",
+        "li     a7, 22",
+        "ecall",
+        "bne    a0, t3, 90f",
+        "sw     t3, 0(t3)",
+        "sw     t4, 60(t3)",
+        "lw     t0, 0(t3)",
+        "bne    t0, t3, 90f",
+        "lw     t0, 60(t3)",
+        "bne    t0, t4, 90f",
+
+        // 3. sbrk(0) -> start + 64
+        "li     t4, 3",
+        "li     a0, 0",
+        "li     a7, 22",
+        "ecall",
+        "addi   t0, t3, 64",
+        "bne    a0, t0, 90f",
+
+        // 4. far too much -> -1, break unchanged
+        "li     t4, 4",
+        "li     a0, 0x10000000",
+        "li     a7, 22",
+        "ecall",
+        "bne    a0, t5, 90f",
+        "li     a0, 0",
+        "li     a7, 22",
+        "ecall",
+        "addi   t0, t3, 64",
+        "bne    a0, t0, 90f",
+
+        // 5. exhaust in 1K steps; the break ends just below the stack
+        "li     t4, 5",
+        "10:",
+        "li     a0, 1024",
+        "li     a7, 22",
+        "ecall",
+        "bne    a0, t5, 10b",
+        "li     a0, 0",
+        "li     a7, 22",
+        "ecall",
+        "mv     t2, a0",            // final break
+        "la     t0, _u_stack_bottom",
+        "bltu   t0, t2, 90f",       // past the stack bottom
+        "sub    t1, t0, t2",
+        "li     t0, 1024",
+        "bgeu   t1, t0, 90f",       // refused with 1K still free
+        "li     t0, 0xa5",
+        "sb     t0, -1(t2)",
+        "lbu    t1, -1(t2)",
+        "bne    t0, t1, 90f",
+
+        // 6. back to the start; nothing below it
+        "li     t4, 6",
+        "sub    a0, t3, t2",
+        "li     a7, 22",
+        "ecall",
+        "bne    a0, t2, 90f",
+        "li     a0, -1",
+        "li     a7, 22",
+        "ecall",
+        "bne    a0, t5, 90f",
+        "li     a0, 0",
+        "li     a7, 22",
+        "ecall",
+        "bne    a0, t3, 90f",
+
+        "la     a0, u_sbrk_msg_pass",
+        "li     a1, u_sbrk_msg_fail - u_sbrk_msg_pass",
+        "li     a7, 1",
+        "ecall",
+        "ret",
+
+        "90:",
+        "la     a0, u_sbrk_msg_fail",
+        "li     a1, u_sbrk_msg_end - u_sbrk_msg_fail",
+        "li     a7, 1",
+        "ecall",
+        "mv     a0, t4",
+        "li     a7, 2",
+        "ecall",
+    )
+}
+
+/// U-mode log-dump test: `dump_log` prints the measurement log and says
+/// how many entries it printed.
+///
+/// Called from `_u_entry` after [`u_sbrk_test`].  Checks, in order:
+///
+///   1. `dump_log()` returns at least 2, for the boot entries ROM and
+///      U_CODE (and not -1);
+///   2. a second `dump_log()` returns the same count: printing the log
+///      leaves it as it was.
+///
+/// Both dumps appear on the console as `MLOG` blocks.  Prints PASS, or
+/// FAIL and exits with the failing step as the code.
+///
+/// # Safety
+///
+/// U-mode code.
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".u_text"]
+pub unsafe extern "C" fn u_dump_log_test() {
+    cfi_target_asm!(cfi_labels::UNLABELED;
+        ".pushsection .u_rodata.dump_log, \"a\"",
+        "u_dump_log_msg_pass:",
+        ".ascii \"[U-MODE] dump_log printed the measurement log: PASS\\r\\n\"",
+        "u_dump_log_msg_fail:",
+        ".ascii \"[U-MODE] dump_log: FAIL\\r\\n\"",
+        "u_dump_log_msg_end:",
+        ".popsection",
+
+        // 1. at least the two boot entries; keep the count in t3
+        "li     t4, 1",
+        "li     a7, 23",
+        "ecall",
+        "li     t0, 2",
+        "blt    a0, t0, 90f",
+        "mv     t3, a0",
+
+        // 2. the same count again
+        "li     t4, 2",
+        "li     a7, 23",
+        "ecall",
+        "bne    a0, t3, 90f",
+
+        "la     a0, u_dump_log_msg_pass",
+        "li     a1, u_dump_log_msg_fail - u_dump_log_msg_pass",
+        "li     a7, 1",
+        "ecall",
+        "ret",
+
+        "90:",
+        "la     a0, u_dump_log_msg_fail",
+        "li     a1, u_dump_log_msg_end - u_dump_log_msg_fail",
+        "li     a7, 1",
+        "ecall",
+        "mv     a0, t4",
+        "li     a7, 2",
+        "ecall",
+    )
+}
+
+/// U-mode audit-trail test: the ecalls it makes show up in
+/// [`ECALL_AUDIT`] in the order it made them, and U-mode can't get at the
+/// trail other than through `dump_audit`.
+///
+/// Called from `_u_entry` in `ecall-audit` builds, after
+/// [`u_dump_log_test`], with the token in a0.  Makes three ecalls to
+/// unregistered service 24, with markers in a0, then checks, in order:
+///
+///   1. `dump_audit(buf, 4)` returns 4;
+///   2. the records are the three ecalls, markers and all, then the dump
+///      itself (monitor number, a0 = buf);
+///   3. their sequence numbers are consecutive and their times don't go
+///      back;
+///   4. `dump_audit` into the trail itself is refused: M_RAM isn't
+///      U-writable;
+///   5. `dump_audit` with a wrong token is refused.
+///
+/// Prints PASS, or FAIL and exits with the failing step as the code.
+///
+/// # Safety
+///
+/// U-mode code, with 64 bytes of U stack to spare; a0 must hold the
+/// monitor-call token.
+#[cfg(feature = "ecall-audit")]
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".u_text"]
+pub unsafe extern "C" fn u_audit_test(token: u32) {
+    cfi_target_asm!(cfi_labels::UNLABELED;
+        ".pushsection .u_rodata.audit, \"a\"",
+        "u_audit_msg_pass:",
+        ".ascii \"[U-MODE] ecall audit trail in order, M-mode only: PASS\\r\\n\"",
+        "u_audit_msg_fail:",
+        ".ascii \"[U-MODE] ecall audit: FAIL\\r\\n\"",
+        "u_audit_msg_end:",
+        ".popsection",
+
+        "mv     t6, a0",            // token (ecalls preserve t6)
+        "li     t5, -1",            // SYSCALL_ERR
+        "addi   sp, sp, -64",       // 0(sp): four 16-byte records
+
+        "li     a0, {mark} + 1",
+        "li     a7, {unused}",
+        "ecall",
+        "li     a0, {mark} + 2",
+        "li     a7, {unused}",
+        "ecall",
+        "li     a0, {mark} + 3",
+        "li     a7, {unused}",
+        "ecall",
+
+        // 1. dump_audit(buf, 4) -> 4
+        "li     t4, 1",
+        "mv     a0, sp",
+        "mv     a1, t6",
+        "li     a2, 4",
+        "li     a7, {dump_audit}",
+        "ecall",
+        "li     t0, 4",
+        "bne    a0, t0, 90f",
+
+        // 2. (number, a0) of each: (24, mark + 1..3), then the dump
+        "li     t4, 2",
+        "li     t1, {unused}",
+        "li     t2, {mark} + 1",
+        "mv     t3, sp",
+        "addi   a1, sp, 48",
+        "1:",
+        "lw     t0, 4(t3)",
+        "bne    t0, t1, 90f",
+        "lw     t0, 8(t3)",
+        "bne    t0, t2, 90f",
+        "addi   t2, t2, 1",
+        "addi   t3, t3, 16",
+        "bne    t3, a1, 1b",
+        "lw     t0, 4(t3)",
+        "li     t1, {dump_audit}",
+        "bne    t0, t1, 90f",
+        "lw     t0, 8(t3)",
+        "bne    t0, sp, 90f",
+
+        // 3. seq + 1 and time - previous >= 0 from each record to the next
+        "li     t4, 3",
+        "mv     t3, sp",
+        "2:",
+        "lw     t0, 0(t3)",
+        "lw     t1, 16(t3)",
+        "addi   t0, t0, 1",
+        "bne    t0, t1, 90f",
+        "lw     t0, 12(t3)",
+        "lw     t1, 28(t3)",
+        "sub    t1, t1, t0",
+        "bltz   t1, 90f",
+        "addi   t3, t3, 16",
+        "bne    t3, a1, 2b",
+
+        // 4. dump_audit into the trail itself -> -1
+        "li     t4, 4",
+        "la     a0, {audit_log}",
+        "mv     a1, t6",
+        "li     a2, 1",
+        "li     a7, {dump_audit}",
+        "ecall",
+        "bne    a0, t5, 90f",
+
+        // 5. dump_audit with a wrong token -> -1
+        "li     t4, 5",
+        "mv     a0, sp",
+        "not    a1, t6",
+        "li     a2, 1",
+        "li     a7, {dump_audit}",
+        "ecall",
+        "bne    a0, t5, 90f",
 
-        // Backward-edge: push ra
-        ".4byte 0x60100073",        // sspush ra (HW)
-        "addi   sp, sp, -16",
-        "sw     ra, 12(sp)",
-        "sw     gp, 8(sp)",
-        "sw     ra, 0(gp)",         // sw_sspush
-        "addi   gp, gp, 4",
-
-        // Simplified measurement: XOR all words in the region
-        // (Real RoT would use a proper hash function)
-        "li     a2, 0",             // accumulator
-        "add    a1, a0, a1",        // end = base + size
-        "60:",
-        "beq    a0, a1, 61f",
-        "lw     t0, 0(a0)",
-        "xor    a2, a2, t0",
-        "addi   a0, a0, 4",
-        "j      60b",
-        "61:",
-        "mv     a0, a2",            // return measurement
-
-        // Backward-edge: pop and check
-        "addi   gp, gp, -4",
-        "lw     t0, 0(gp)",
-        "lw     ra, 12(sp)",
-        "bne    t0, ra, 99f",
-        "lw     gp, 8(sp)",
-        "addi   sp, sp, 16",
-        ".4byte 0x60500073",        // sspopchk ra (HW)
+        "addi   sp, sp, 64",
+        "la     a0, u_audit_msg_pass",
+        "li     a1, u_audit_msg_fail - u_audit_msg_pass",
+        "li     a7, 1",
+        "ecall",
         "ret",
 
-        "99: ebreak",               // Shadow stack mismatch
+        "90:",
+        "la     a0, u_audit_msg_fail",
+        "li     a1, u_audit_msg_end - u_audit_msg_fail",
+        "li     a7, 1",
+        "ecall",
+        "mv     a0, t4",
+        "li     a7, 2",
+        "ecall",
+        mark = const 0xa0d1_0000u32,
+        unused = const 24,
+        dump_audit = const monitor::monitor_call(monitor::DUMP_AUDIT),
+        audit_log = sym ECALL_AUDIT,
     )
 }
 
-/// Seal a secret using the hardware-bound key (stub).
+/// U-mode misaligned-access test: loads and stores at odd addresses give
+/// the same results as aligned ones.  On a hart that traps on them, each
+/// one goes through [`trap_misaligned`].
 ///
-/// In a real RoT with a key manager, this would use the device identity
-/// key (DevID) or a derived key to encrypt/HMAC the data.
-/// Demonstrates a labeled landing pad (only callers with label=0xR07
-/// can reach this function on Zicfilp hardware).
+/// Called from `_u_entry` in `misalign-fixup` builds, on an 8-byte
+/// buffer in `.u_bss`.  Checks, in order:
+///
+///   1. `sw` of 0x8899aabb at buf+1 lands in bytes 1-4, little-endian;
+///   2. `lw` from buf+1 reads it back;
+///   3. `c.lw` into s1, a callee-saved register, from buf+1 does too;
+///   4. `lh` from buf+3 sign-extends 0x8899, and `lhu` doesn't;
+///   5. `sh` of 0x1234 at buf+5 lands in bytes 5-6.
+///
+/// Prints PASS, or FAIL and exits with the failing step as the code.
+///
+/// # Safety
+///
+/// U-mode code, with 16 bytes of U stack to spare.
+#[cfg(feature = "misalign-fixup")]
 #[unsafe(naked)]
 #[no_mangle]
-pub unsafe extern "C" fn rot_seal_secret(data: u32, key_id: u32) -> u32 {
-    naked_asm!(
-        // Forward-edge: labeled landing pad (label = 0xR07 conceptually)
-        // Using label 7 for demo
-        ".4byte {lpad_7}",          // lpad 7
+#[link_section = ".u_text"]
+pub unsafe extern "C" fn u_misalign_test() {
+    cfi_target_asm!(cfi_labels::UNLABELED;
+        ".pushsection .u_rodata.misalign, \"a\"",
+        "u_misalign_msg_pass:",
+        ".ascii \"[U-MODE] misaligned lw/lh/lhu/sw/sh, c.lw: PASS\\r\\n\"",
+        "u_misalign_msg_fail:",
+        ".ascii \"[U-MODE] misaligned access: FAIL\\r\\n\"",
+        "u_misalign_msg_end:",
+        ".popsection",
+        ".pushsection .u_bss.misalign, \"aw\", @nobits",
+        ".balign 4",
+        "u_misalign_buf:",
+        ".zero 8",
+        ".popsection",
 
-        // Backward-edge: shadow stacks
-        ".4byte 0x60100073",        // sspush ra (HW)
         "addi   sp, sp, -16",
-        "sw     ra, 12(sp)",
-        "sw     gp, 8(sp)",
-        "sw     ra, 0(gp)",         // sw_sspush
-        "addi   gp, gp, 4",
+        "sw     s1, 12(sp)",
+        "la     a1, u_misalign_buf",
+        "sw     zero, 0(a1)",
+        "sw     zero, 4(a1)",
 
-        // Stub: XOR data with key_id as a placeholder for real crypto
-        "xor    a0, a0, a1",
+        // 1. sw at buf+1, checked byte by byte
+        "li     t4, 1",
+        "li     t5, 0x8899aabb",
+        "sw     t5, 1(a1)",
+        "lbu    t0, 1(a1)",
+        "li     t1, 0xbb",
+        "bne    t0, t1, 90f",
+        "lbu    t0, 2(a1)",
+        "li     t1, 0xaa",
+        "bne    t0, t1, 90f",
+        "lbu    t0, 3(a1)",
+        "li     t1, 0x99",
+        "bne    t0, t1, 90f",
+        "lbu    t0, 4(a1)",
+        "li     t1, 0x88",
+        "bne    t0, t1, 90f",
+        "lbu    t0, 0(a1)",
+        "bnez   t0, 90f",
 
-        // Backward-edge: pop and check
-        "addi   gp, gp, -4",
-        "lw     t0, 0(gp)",
-        "lw     ra, 12(sp)",
-        "bne    t0, ra, 99f",
-        "lw     gp, 8(sp)",
+        // 2. lw from buf+1
+        "li     t4, 2",
+        "lw     a0, 1(a1)",
+        "bne    a0, t5, 90f",
+
+        // 3. c.lw into s1
+        "li     t4, 3",
+        "addi   a2, a1, 1",
+        "c.lw   s1, 0(a2)",
+        "bne    s1, t5, 90f",
+
+        // 4. lh / lhu from buf+3
+        "li     t4, 4",
+        "lh     a0, 3(a1)",
+        "li     t1, 0xffff8899",
+        "bne    a0, t1, 90f",
+        "lhu    a0, 3(a1)",
+        "li     t1, 0x8899",
+        "bne    a0, t1, 90f",
+
+        // 5. sh at buf+5
+        "li     t4, 5",
+        "li     t1, 0x1234",
+        "sh     t1, 5(a1)",
+        "lbu    t0, 5(a1)",
+        "li     t1, 0x34",
+        "bne    t0, t1, 90f",
+        "lbu    t0, 6(a1)",
+        "li     t1, 0x12",
+        "bne    t0, t1, 90f",
+
+        "la     a0, u_misalign_msg_pass",
+        "li     a1, u_misalign_msg_fail - u_misalign_msg_pass",
+        "li     a7, 1",
+        "ecall",
+        "lw     s1, 12(sp)",
         "addi   sp, sp, 16",
-        ".4byte 0x60500073",        // sspopchk ra (HW)
         "ret",
 
-        "99: ebreak",
-        lpad_7 = const ((7u32 << 12) | 0x17),
+        "90:",
+        "la     a0, u_misalign_msg_fail",
+        "li     a1, u_misalign_msg_end - u_misalign_msg_fail",
+        "li     a7, 1",
+        "ecall",
+        "mv     a0, t4",
+        "li     a7, 2",
+        "ecall",
     )
 }
 
-// ============================================================================
-// U-Mode Launch
-// ============================================================================
-
-/// Drop privilege from M-mode to U-mode.
+/// U-mode shadow-stack test: `sspush ra` moves ssp down one entry and
+/// `sspopchk ra` moves it back.  On a core without Zimop each of the
+/// three goes through [`trap_ss_emulate`]; with Zicfiss the hardware
+/// does the same.
 ///
-/// Sets up mstatus.MPP = 0 (User mode), sets mepc to the U-mode entry
-/// point, initializes the U-mode stack and shadow stack pointers, then
-/// executes mret to enter U-mode.
+/// Called from `_u_entry` in `ss-emulate` builds.  Checks, in order:
 ///
-/// After mret:
-///   - Privilege level = U-mode
-///   - PMP enforcement active for all U-mode memory accesses
-///   - CFI enforcement active (Zicfilp landing pads + Zicfiss shadow stack)
-///   - U-mode cannot access M-mode memory regions
-fn launch_umode() {
-    uart_puts("[LAUNCH] Dropping to U-mode...\r\n");
-    uart_puts("  mepc  -> _u_entry (U-mode entry point)\r\n");
-    uart_puts("  MPP   -> 0b00 (User mode)\r\n");
-    uart_puts("  sp    -> _u_stack_top\r\n");
-    uart_puts("  ssp   -> _u_shadow_stack_top\r\n");
-    uart_puts("  gp    -> _u_sw_shadow_stack_bottom\r\n\r\n");
+///   1. after `sspush ra`, `ssrdp` reads the old ssp minus 4;
+///   2. after `sspopchk ra` (a match), it reads the old ssp again.
+///
+/// Prints PASS, or FAIL and exits with the failing step as the code.  An
+/// `ssrdp` that reads 0 means no shadow stack is active (Zimop without
+/// Zicfiss, where all three are NOPs): nothing to test.
+///
+/// # Safety
+///
+/// U-mode code.
+#[cfg(feature = "ss-emulate")]
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".u_text"]
+pub unsafe extern "C" fn u_ss_emulate_test() {
+    cfi_target_asm!(cfi_labels::UNLABELED;
+        ".pushsection .u_rodata.ss_emulate, \"a\"",
+        "u_ss_emulate_msg_pass:",
+        ".ascii \"[U-MODE] sspush / sspopchk / ssrdp: PASS\\r\\n\"",
+        "u_ss_emulate_msg_fail:",
+        ".ascii \"[U-MODE] shadow-stack instructions: FAIL\\r\\n\"",
+        "u_ss_emulate_msg_none:",
+        ".ascii \"[U-MODE] shadow-stack instructions: no shadow stack, skipped\\r\\n\"",
+        "u_ss_emulate_msg_end:",
+        ".popsection",
 
-    unsafe {
-        asm!(
-            // Set mstatus.MPP = 0b00 (U-mode)
-            // MPP is bits [12:11] of mstatus
-            "csrr   t0, mstatus",
-            "li     t1, ~(3 << 11)",   // Clear MPP bits
-            "and    t0, t0, t1",
-            // MPP = 0 means User mode (already cleared)
-            "csrw   mstatus, t0",
+        ".4byte {ssrdp_t1}",            // ssrdp t1 (0 if inactive)
+        "beqz   t1, 91f",
+        "mv     t3, t1",
 
-            // Set mepc to U-mode entry point
-            "la     t0, _u_entry",
-            "csrw   mepc, t0",
+        // 1. sspush ra
+        "li     t4, 1",
+        ".4byte {sspush_ra}",
+        ".4byte {ssrdp_t1}",
+        "addi   t1, t1, 4",
+        "bne    t1, t3, 90f",
 
-            // Set U-mode stack pointer
-            "la     sp, _u_stack_top",
+        // 2. sspopchk ra
+        "li     t4, 2",
+        ".4byte {sspopchk_ra}",
+        ".4byte {ssrdp_t1}",
+        "bne    t1, t3, 90f",
 
-            // Set U-mode hardware shadow stack pointer
-            "la     t0, _u_shadow_stack_top",
-            "csrw   0x011, t0",        // csrw ssp, t0
+        "la     a0, u_ss_emulate_msg_pass",
+        "li     a1, u_ss_emulate_msg_fail - u_ss_emulate_msg_pass",
+        "li     a7, 1",
+        "ecall",
+        "ret",
 
-            // Set U-mode software shadow stack pointer (gp)
-            "la     gp, _u_sw_shadow_stack_bottom",
+        "91:",
+        "la     a0, u_ss_emulate_msg_none",
+        "li     a1, u_ss_emulate_msg_end - u_ss_emulate_msg_none",
+        "li     a7, 1",
+        "ecall",
+        "ret",
 
-            // Enter U-mode
-            "mret",
-            options(noreturn),
-        );
-    }
+        "90:",
+        "la     a0, u_ss_emulate_msg_fail",
+        "li     a1, u_ss_emulate_msg_none - u_ss_emulate_msg_fail",
+        "li     a7, 1",
+        "ecall",
+        "mv     a0, t4",
+        "li     a7, 2",
+        "ecall",
+        ssrdp_t1 = const cfi_encoding::SSRDP_T1,
+        sspush_ra = const cfi_encoding::SSPUSH_RA,
+        sspopchk_ra = const cfi_encoding::SSPOPCHK_RA,
+    )
 }
 
-// ============================================================================
-// U-Mode Entry Point & Application
-// ============================================================================
-
-/// U-mode ecall wrappers.
-///
-/// These run in U-mode and use `ecall` to request services from M-mode.
-/// Currently unused because `_u_entry` issues ecalls directly in assembly;
-/// retained as the Rust-level API for future U-mode applications.
-#[allow(dead_code)]
-mod umode_syscalls {
-    /// Print a single character via M-mode UART service.
-    #[inline(always)]
-    pub fn sys_putc(c: u8) {
-        unsafe {
-            core::arch::asm!(
-                "li a7, 0",
-                "ecall",
-                in("a0") c as u32,
-                lateout("a0") _,
-                lateout("a7") _,
-            );
-        }
-    }
-
-    /// Print a string via M-mode UART service.
-    #[inline(always)]
-    pub fn sys_puts(s: &str) {
-        unsafe {
-            core::arch::asm!(
-                "li a7, 1",
-                "ecall",
-                in("a0") s.as_ptr(),
-                in("a1") s.len(),
-                lateout("a0") _,
-                lateout("a1") _,
-                lateout("a7") _,
-            );
-        }
-    }
+/// Upcalls [`u_timer_upcall`] has been entered for, counting coalesced
+/// ticks.  `.u_bss` isn't zeroed at boot; [`u_timer_upcall_test`] clears
+/// it first.
+#[cfg(feature = "timer-upcall")]
+#[link_section = ".u_bss"]
+static U_UPCALL_TICKS: AtomicU32 = AtomicU32::new(0);
 
-    /// Exit the system.
-    #[inline(always)]
-    pub fn sys_exit(code: u32) -> ! {
-        unsafe {
-            core::arch::asm!(
-                "li a7, 2",
-                "ecall",
-                in("a0") code,
-                options(noreturn),
-            );
-        }
-    }
-}
+/// Timer upcall mtime period for [`u_timer_upcall_test`]: 1 ms.
+#[cfg(feature = "timer-upcall")]
+const U_UPCALL_PERIOD: usize = 10_000;
 
-/// U-mode indirect call target: add 100.
-/// Has a landing pad for forward-edge CFI protection.
-#[unsafe(naked)]
+/// U-mode timer upcall handler for [`u_timer_upcall_test`]: count the
+/// ticks this entry covers.
+///
+/// # Safety
+///
+/// Only entered by the upcall delivery, with [`SW_SS_REG`] pointing into
+/// the U-mode software shadow stack.
+#[cfg(feature = "timer-upcall")]
+#[cfi_target(label = cfi_labels::TIMER_UPCALL)]
 #[no_mangle]
 #[link_section = ".u_text"]
-pub unsafe extern "C" fn u_add_100(x: u32) -> u32 {
-    naked_asm!(
-        ".4byte 0x00000017",        // lpad 0
-        "addi   a0, a0, 100",
-        "ret",
-    )
+pub unsafe extern "C" fn u_timer_upcall(ticks: u32) {
+    U_UPCALL_TICKS.fetch_add(ticks, Ordering::Relaxed);
 }
 
-/// U-mode indirect call target: double the value.
-/// Full forward + backward CFI protection (non-leaf).
+/// U-mode timer upcall test: a registered handler runs on timer ticks and
+/// the interrupted code resumes intact.
+///
+/// Called from `_u_entry` in `timer-upcall` builds.  Checks, in order:
+///
+///   1. `timer_upcall` refuses a handler in `.u_rodata`, which isn't
+///      executable;
+///   2. it accepts [`u_timer_upcall`] with a 1 ms period;
+///   3. waiting in `yield`, the handler counts 3 ticks within 100 ms,
+///      and the sentinels in a3-a6 and t3-t4 survive every upcall;
+///   4. `timer_upcall(0)` stops the ticks;
+///   5. `upcall_return` outside the handler is refused.
+///
+/// Prints PASS, or FAIL and exits with the failing step as the code.
+///
+/// # Safety
+///
+/// U-mode code.
+#[cfg(feature = "timer-upcall")]
 #[unsafe(naked)]
 #[no_mangle]
 #[link_section = ".u_text"]
-pub unsafe extern "C" fn u_double(x: u32) -> u32 {
-    naked_asm!(
-        ".4byte 0x00000017",        // lpad 0
-        ".4byte 0x60100073",        // sspush ra (HW)
-        "addi   sp, sp, -16",
-        "sw     ra, 12(sp)",
-        "sw     gp, 8(sp)",
-        "sw     ra, 0(gp)",         // sw_sspush
-        "addi   gp, gp, 4",
+pub unsafe extern "C" fn u_timer_upcall_test() {
+    cfi_target_asm!(cfi_labels::UNLABELED;
+        ".pushsection .u_rodata.timer_upcall, \"a\"",
+        "u_upcall_msg_pass:",
+        ".ascii \"[U-MODE] timer upcalls delivered, context preserved: PASS\\r\\n\"",
+        "u_upcall_msg_fail:",
+        ".ascii \"[U-MODE] timer upcall: FAIL\\r\\n\"",
+        "u_upcall_msg_end:",
+        ".popsection",
 
-        "slli   a0, a0, 1",         // x * 2
+        "li     t5, -1",            // SYSCALL_ERR
 
-        "addi   gp, gp, -4",        // sw_sspopchk
-        "lw     t0, 0(gp)",
-        "lw     ra, 12(sp)",
-        "bne    t0, ra, 99f",
-        "lw     gp, 8(sp)",
-        "addi   sp, sp, 16",
-        ".4byte 0x60500073",        // sspopchk ra (HW)
+        // 1. non-executable handler -> -1
+        "li     t4, 1",
+        "la     a0, u_upcall_msg_pass",
+        "li     a1, {period}",
+        "li     a7, 19",
+        "ecall",
+        "bne    a0, t5, 90f",
+
+        // 2. register the handler -> 0
+        "li     t4, 2",
+        "la     t0, {ticks}",
+        "sw     zero, 0(t0)",
+        "la     a0, u_timer_upcall",
+        "li     a1, {period}",
+        "li     a7, 19",
+        "ecall",
+        "bnez   a0, 90f",
+
+        // 3. 3 ticks within 100 ms, sentinels intact
+        "li     t4, 3",
+        "li     a7, 14",            // time(): low half is enough
+        "ecall",
+        "mv     t6, a0",
+        "li     a3, 0x0C000013",
+        "li     a4, 0x0C000014",
+        "li     a5, 0x0C000015",
+        "li     a6, 0x0C000016",
+        "li     t3, 0x0C000028",
+        "1: li   a7, 6",            // yield: the tick wakes the wfi
+        "ecall",
+        "li     t0, 0x0C000013",
+        "bne    a3, t0, 90f",
+        "li     t0, 0x0C000014",
+        "bne    a4, t0, 90f",
+        "li     t0, 0x0C000015",
+        "bne    a5, t0, 90f",
+        "li     t0, 0x0C000016",
+        "bne    a6, t0, 90f",
+        "li     t0, 0x0C000028",
+        "bne    t3, t0, 90f",
+        "la     t0, {ticks}",
+        "lw     t0, 0(t0)",
+        "li     t1, 3",
+        "bgeu   t0, t1, 2f",
+        "li     a7, 14",
+        "ecall",
+        "sub    a0, a0, t6",
+        "li     t1, 100 * {period}",
+        "bltu   a0, t1, 1b",
+        "j      90f",
+
+        // 4. stop -> 0
+        "2: li   t4, 4",
+        "li     a0, 0",
+        "li     a7, 19",
+        "ecall",
+        "bnez   a0, 90f",
+
+        // 5. no upcall running -> -1
+        "li     t4, 5",
+        "li     a7, 20",
+        "ecall",
+        "bne    a0, t5, 90f",
+
+        "la     a0, u_upcall_msg_pass",
+        "li     a1, u_upcall_msg_fail - u_upcall_msg_pass",
+        "li     a7, 1",
+        "ecall",
         "ret",
 
-        "99: ebreak",
+        "90:",
+        "la     a0, u_upcall_msg_fail",
+        "li     a1, u_upcall_msg_end - u_upcall_msg_fail",
+        "li     a7, 1",
+        "ecall",
+        "mv     a0, t4",
+        "li     a7, 2",
+        "ecall",
+        period = const U_UPCALL_PERIOD,
+        ticks = sym U_UPCALL_TICKS,
     )
 }
 
+/// Anti-rollback version of the U-mode firmware linked into this image,
+/// recorded in its header.
+const FIRMWARE_VERSION: u32 = 1;
+
+// U-mode firmware header (see riscv_rot_cfi::firmware), first in U_CODE.
+// The length and landing-pad count come from link.x (`_u_text_size`,
+// `_u_lpad_count`): only the linker knows them.
+global_asm!(
+    ".pushsection .u_text.header, \"a\", @progbits",
+    ".balign 4",
+    ".4byte {magic}",
+    ".4byte {version}",
+    ".4byte _u_text_size",
+    ".4byte _u_lpad_count",
+    ".popsection",
+    magic = const firmware::MAGIC,
+    version = const FIRMWARE_VERSION,
+);
+
 /// U-mode dispatch table — function pointers with landing pads.
 #[repr(C)]
 #[allow(dead_code)]
@@ -708,27 +9286,147 @@ struct UDispatch {
 ///   - All indirect call targets must have landing pads (Zicfilp)
 ///   - All return addresses checked via shadow stack (Zicfiss)
 ///   - System services via ecall to M-mode
+///
+/// # Safety
+///
+/// Only reachable via `mret` from `launch_umode`, which sets up the U-mode
+/// stack and shadow-stack pointers it relies on.
 #[unsafe(naked)]
 #[no_mangle]
 #[link_section = ".u_text"]
 pub unsafe extern "C" fn _u_entry() -> ! {
-    naked_asm!(
-        // Landing pad (we arrive here via mret, but good practice)
-        ".4byte 0x00000017",        // lpad 0
+    // The landing pad isn't needed for mret, but costs nothing.
+    cfi_target_asm!(cfi_labels::UNLABELED;
+        // ── Test: token-gated monitor calls (token in a0 from M-mode;
+        // hartid and the boot info in a1 and a2, kept for later) ──
+        "mv     s1, a0",
+        "mv     s2, a1",
+        "mv     s3, a2",
+        "call   u_monitor_test",
+
+        // ── Test: write-once key provisioning (monitor call 1) ──
+        "mv     a0, s1",
+        "call   u_provision_test",
+
+        // ── Test: boot PCRs readable, bounds checked (syscall 8) ──
+        "call   u_pcr_read_test",
+
+        // ── Test: the boot info M-mode handed over in a1/a2 ──
+        "mv     a0, s2",
+        "mv     a1, s3",
+        "call   u_boot_info_test",
+
+        // ── Test: unknown syscall numbers return NotSupported ──
+        "call   u_unknown_syscall_test",
+
+        // ── Test: sbrk grows the heap up to the stack, no further ──
+        "call   u_sbrk_test",
+
+        // ── Test: dump the measurement log for a host verifier ──
+        "call   u_dump_log_test",
+
+        // ── Test: ecalls land in the audit trail in order (ecall-audit) ──
+        ".if {audit}",
+        "mv     a0, s1",
+        "call   u_audit_test",
+        ".endif",
+
+        // ── Test: misaligned loads and stores (misalign-fixup) ──
+        ".if {misalign}",
+        "call   u_misalign_test",
+        ".endif",
+
+        // ── Test: explicit sspush / sspopchk move ssp (ss-emulate) ──
+        ".if {ss_emulate}",
+        "call   u_ss_emulate_test",
+        ".endif",
+
+        // ── Test: a buffer overflow trips the stack canary; halts ──
+        // (stack-canary-test)
+        ".if {canary_test}",
+        "mv     a0, sp",
+        "call   u_canary_victim",
+        ".endif",
 
         // ── Test: Indirect call through function pointer ──
-        // Call u_add_100(42) via pointer
+        // Call u_add_100(42) via pointer; t2 carries the expected label
         "la     t1, u_add_100",
         "li     a0, 42",
+        "lui    t2, {dispatch}",
         "jalr   ra, t1, 0",
         // a0 should now be 142
 
         // ── Test: Call u_double via pointer ──
         "la     t1, u_double",
         "li     a0, 25",
+        "lui    t2, {dispatch}",
         "jalr   ra, t1, 0",
         // a0 should now be 50
 
+        // ── Test: signature-derived labels (sig-label-demo) ──
+        ".if {sig_demo}",
+        "call   u_sig_label_test",
+        ".endif",
+
+        // ── Test: trap handler preserves registers (regsave-test) ──
+        ".if {regsave}",
+        "call   u_regsave_test",
+        ".endif",
+
+        // ── Test: SW shadow-stack corruption is caught (fault-inject) ──
+        ".if {fault_inject}",
+        "call   u_fault_inject_test",
+        ".endif",
+
+        // ── Test: a forged return address is refused (rop-demo) ──
+        ".if {rop_demo}",
+        "call   u_rop_attack_demo",
+        ".endif",
+
+        // ── Test: a stray ebreak is reported and resumed (breakpoint-test) ──
+        ".if {bp_test}",
+        "call   u_breakpoint_test",
+        ".endif",
+
+        // ── Test: stack overrun stops at U_GUARD (stack-guard-test) ──
+        ".if {guard_test}",
+        "call   u_stack_guard_test",
+        ".endif",
+
+        // ── Test: no execution from U_RAM (wx-selftest) ──
+        ".if {wx_test}",
+        "call   u_wx_selftest",
+        ".endif",
+
+        // ── Test: interrupt inside a service (nested-trap-test) ──
+        ".if {nested_test}",
+        "call   u_nested_trap_test",
+        ".endif",
+
+        // ── Test: per-application PMP regions (app-isolation-test) ──
+        ".if {app_test}",
+        "call   u_app_isolation_test",
+        ".endif",
+
+        // ── Test: timer upcalls into a U-mode handler (timer-upcall) ──
+        ".if {upcall}",
+        "call   u_timer_upcall_test",
+        ".endif",
+
+        // ── Demo: two tasks preempted by the timer (sched-demo) ──
+        ".if {sched}",
+        "call   u_sched_demo",
+        ".endif",
+
+        // ── Test: yield to M-mode and resume right after the ecall ──
+        "li     a7, 6",
+        "ecall",
+
+        // ── Interactive shell (u-repl builds; never returns) ──
+        ".if {repl}",
+        "call   u_repl",
+        ".endif",
+
         // ── Print success via ecall ──
         // sys_putc('O')
         "li     a0, 0x4F",
@@ -751,6 +9449,23 @@ pub unsafe extern "C" fn _u_entry() -> ! {
         // Should not reach here
         "70: wfi",
         "j      70b",
+        regsave = const cfg!(feature = "regsave-test") as u32,
+        fault_inject = const cfg!(feature = "fault-inject") as u32,
+        rop_demo = const cfg!(feature = "rop-demo") as u32,
+        bp_test = const cfg!(feature = "breakpoint-test") as u32,
+        nested_test = const cfg!(feature = "nested-trap-test") as u32,
+        guard_test = const cfg!(feature = "stack-guard-test") as u32,
+        wx_test = const cfg!(feature = "wx-selftest") as u32,
+        app_test = const cfg!(feature = "app-isolation-test") as u32,
+        sched = const cfg!(feature = "sched-demo") as u32,
+        upcall = const TIMER_UPCALL as u32,
+        misalign = const cfg!(feature = "misalign-fixup") as u32,
+        ss_emulate = const cfg!(feature = "ss-emulate") as u32,
+        canary_test = const cfg!(feature = "stack-canary-test") as u32,
+        audit = const cfg!(feature = "ecall-audit") as u32,
+        sig_demo = const cfg!(feature = "sig-label-demo") as u32,
+        repl = const cfg!(feature = "u-repl") as u32,
+        dispatch = const cfi_labels::DISPATCH,
     )
 }
 
@@ -758,6 +9473,12 @@ pub unsafe extern "C" fn _u_entry() -> ! {
 // Boot Sequence (_start)
 // ============================================================================
 
+/// Reset entry point.
+///
+/// # Safety
+///
+/// Must only be entered by the hart at reset; it assumes nothing and sets
+/// up the stack, trap vector, BSS, .data and shadow-stack pointer itself.
 #[unsafe(naked)]
 #[no_mangle]
 #[link_section = ".text.init"]
@@ -767,95 +9488,426 @@ pub unsafe extern "C" fn _start() -> ! {
         "la     sp, _m_stack_top",
 
         // ── 2. Install trap handler ──
+        // mscratch = 0 first: traps stay on the current stack until
+        // launch_umode hands the handler its trap stack.  Direct mode
+        // (MODE = 0) by default; vectored-traps builds point mtvec at the
+        // vector table with MODE = 1.
+        "csrw   mscratch, zero",
+        ".if {vectored}",
+        "la     t0, _trap_vector",
+        "ori    t0, t0, {mtvec_vectored}",
+        ".else",
         "la     t0, _trap_handler",
+        ".endif",
         "csrw   mtvec, t0",
 
         // ── 3. Zero M-mode BSS ──
+        // Fold what was there first into the DRBG seed (FNV-1a over the
+        // words), counting the words that differ from the one before
+        // them: s0 = fold, s1 = count, kept for rot_main.
         "la     t0, _m_bss_start",
         "la     t1, _m_bss_end",
+        "li     s0, {fnv_basis}",
+        "li     s1, 0",
+        "li     t3, 0",
+        "li     t4, {fnv_prime}",
         "1: beq  t0, t1, 2f",
+        "lw     t2, 0(t0)",
+        "xor    s0, s0, t2",
+        "mul    s0, s0, t4",
+        "beq    t2, t3, 7f",
+        "addi   s1, s1, 1",
+        "7: mv   t3, t2",
         "sw     zero, 0(t0)",
         "addi   t0, t0, 4",
         "j      1b",
         "2:",
 
         // ── 4. Copy M-mode .data from ROM to RAM ──
+        // link.x keeps all three symbols word-aligned and the length a
+        // multiple of 4, but nothing enforces it: copy words while both
+        // pointers are aligned, then the remaining 0-3 bytes one at a
+        // time.  A misaligned start or load address copies everything
+        // bytewise; an inverted range copies nothing (pre_main reports
+        // both).
         "la     t0, _m_data_start",
         "la     t1, _m_data_end",
         "la     t2, _m_data_load",
-        "3: beq  t0, t1, 4f",
+        "bgeu   t0, t1, 4f",
+        "or     t3, t0, t2",
+        "andi   t3, t3, 3",
+        "bnez   t3, 6f",
+        "sub    t4, t1, t0",          // t4 = end of the whole words
+        "andi   t4, t4, -4",
+        "add    t4, t4, t0",
+        "3: beq  t0, t4, 6f",
         "lw     t3, 0(t2)",
         "sw     t3, 0(t0)",
         "addi   t0, t0, 4",
         "addi   t2, t2, 4",
         "j      3b",
+        "6: beq  t0, t1, 4f",
+        "lbu    t3, 0(t2)",
+        "sb     t3, 0(t0)",
+        "addi   t0, t0, 1",
+        "addi   t2, t2, 1",
+        "j      6b",
         "4:",
 
-        // ── 5. Initialize M-mode software shadow stack (gp) ──
-        "la     gp, _m_sw_shadow_stack_bottom",
+        // ── 5. Initialize M-mode software shadow stack (SW_SS_REG) ──
+        "la     x{ss}, _m_sw_shadow_stack_bottom",
 
-        // ── 6. Jump to Rust main (M-mode init) ──
+        // ── 6. Early Rust setup, then main (M-mode init) ──
+        "call   pre_main",
+        "mv     a0, s0",
+        "mv     a1, s1",
         "call   rot_main",
 
         // ── 7. Should not return ──
         "5: wfi",
         "j      5b",
+        vectored = const cfg!(feature = "vectored-traps") as u32,
+        mtvec_vectored = const MTVEC_VECTORED,
+        ss = const SW_SS_REG,
+        fnv_basis = const FNV_OFFSET_BASIS,
+        fnv_prime = const FNV_PRIME,
     )
 }
 
+/// 32-bit FNV-1a parameters for `_start`'s `.bss` fold.
+const FNV_OFFSET_BASIS: u32 = 0x811c_9dc5;
+const FNV_PRIME: u32 = 0x0100_0193;
+
+// ============================================================================
+// Boot Record (warm-reset loop counter)
+// ============================================================================
+
+/// A static in `.noinit`, RAM that `_start` neither loads nor zeroes, so
+/// it keeps its contents across a warm reset.  The initializer never
+/// reaches memory: every access is volatile, so the compiler can't assume
+/// it either.
+struct NoInit<T>(UnsafeCell<T>);
+
+// SAFETY: one hart; the record is only touched from boot code and the
+// (non-returning) fatal paths, never concurrently.
+unsafe impl<T> Sync for NoInit<T> {}
+
+impl<T: Copy> NoInit<T> {
+    const fn new(value: T) -> Self {
+        NoInit(UnsafeCell::new(value))
+    }
+
+    fn get(&self) -> T {
+        unsafe { self.0.get().read_volatile() }
+    }
+
+    fn set(&self, value: T) {
+        unsafe { self.0.get().write_volatile(value) }
+    }
+}
+
+/// Boot attempts since the last cold boot or clean shutdown
+/// ([`riscv_rot_cfi::boot_record`]).
+#[link_section = ".noinit"]
+static BOOT_RECORD: NoInit<BootRecord> = NoInit::new(BootRecord::cleared());
+
+/// Count this boot in the boot record; after [`MAX_FAILED_BOOTS`] failed
+/// ones in a row, halt in recovery instead of going on.
+fn check_boot_record() {
+    let (record, mode) = BOOT_RECORD.get().next_boot();
+    BOOT_RECORD.set(record);
+    match mode {
+        BootMode::Normal { attempt: 1 } => {}
+        BootMode::Normal { attempt } => {
+            let _ = write!(
+                UartWriter,
+                "[BOOT] Warm reset: attempt {} ({} failed, recovery after {})\r\n",
+                attempt,
+                attempt - 1,
+                MAX_FAILED_BOOTS
+            );
+        }
+        BootMode::Recovery { failed } => recovery_halt(failed),
+    }
+}
+
+/// Park the hart for good, without resetting: the fault persists across
+/// boots, so another attempt would only fail the same way.
+fn recovery_halt(failed: u32) -> ! {
+    uart_puts("\r\n################################################################\r\n");
+    uart_puts("  RoT RECOVERY HALT\r\n");
+    let _ = write!(UartWriter, "  {} consecutive boots failed; not resetting again.\r\n", failed);
+    uart_puts("  Power-cycle (or reflash) to clear.\r\n");
+    uart_puts("################################################################\r\n");
+    uart_flush();
+    loop {
+        unsafe { asm!("wfi") };
+    }
+}
+
+/// End of every fatal path (panic, fatal trap): reset the system in
+/// `reset-on-panic` builds — the boot record stops a reset loop — halt
+/// otherwise, failing the QEMU run with [`test_device::FATAL_CODE`].
+fn fatal_stop() -> ! {
+    if cfg!(feature = "reset-on-panic") {
+        let _ = write!(
+            UartWriter,
+            "  RESETTING (boot attempt {} of {})\r\n",
+            BOOT_RECORD.get().attempts(),
+            MAX_FAILED_BOOTS
+        );
+        uart_flush();
+        test_device::reset();
+    }
+    uart_puts("  SYSTEM HALTED — security invariant violated\r\n");
+    uart_flush();
+    test_device::fail(test_device::FATAL_CODE)
+}
+
 // ============================================================================
 // M-Mode Main — Root of Trust Initialization
 // ============================================================================
 
+/// First Rust code after `_start`: bring up the console, then (debug
+/// builds) log the section bounds `_start` just worked from and check
+/// them, so a linker-script edit that breaks `.data` shows up at boot
+/// rather than as a corrupted static later.  Finally count the boot in
+/// the boot record, which may halt here in recovery.
+#[no_mangle]
+extern "C" fn pre_main() {
+    CONSOLE.init();
+    if cfg!(debug_assertions) {
+        check_sections();
+    }
+    check_boot_record();
+}
+
+fn check_sections() {
+    let data = layout::data();
+    // An inverted .bss range already panics in Region::from_bounds.
+    let bss = layout::bss();
+    let _ = write!(
+        UartWriter,
+        "[BOOT] .data {:#010x}..{:#010x} ({} bytes) from {:#010x}\r\n\
+         [BOOT] .bss  {:#010x}..{:#010x} ({} bytes)\r\n",
+        data.start,
+        data.end,
+        data.end.wrapping_sub(data.start),
+        data.load,
+        bss.base,
+        bss.end(),
+        bss.size
+    );
+    if (data.start | data.end | data.load) & 3 != 0 {
+        uart_puts("[BOOT] .data not word-aligned: copied bytewise\r\n");
+    }
+    debug_assert!(data.start <= data.end, ".data range inverted");
+    let len = data.end - data.start;
+    debug_assert!(
+        data.load + len <= data.start || data.end <= data.load,
+        ".data load image overlaps its RAM copy"
+    );
+    debug_assert!(data.end <= bss.base, ".data overlaps .bss");
+}
+
+/// How far `rot_main` has got ([`riscv_rot_cfi::boot_phase`]).
+static BOOT_FLOW: IrqCell<BootFlow> = IrqCell::new(BootFlow::new());
+
+/// Start boot phase `phase`.  Halts unless it is the phase after the
+/// current one and that one completed.
+fn enter_phase(phase: BootPhase) {
+    match BOOT_FLOW.with(|flow| flow.enter(phase)) {
+        Some(Ok(())) => {}
+        refused => panic!("boot phase {} refused: {:?}", phase.number(), refused),
+    }
+}
+
+/// Mark the current boot phase completed.
+fn complete_phase() {
+    BOOT_FLOW.with(BootFlow::complete);
+}
+
+/// `boot-phase-test`: try to launch straight after Phase 2, skipping the
+/// measurement.  [`enter_phase`] must halt; coming back is a FAIL.
+#[cfg(feature = "boot-phase-test")]
+fn check_launch_refused() {
+    uart_puts("[BOOT] entering Phase 5 before Phase 3 (expected to halt)...\r\n");
+    enter_phase(BootPhase::Launch);
+    uart_puts("[BOOT] out-of-order launch accepted: FAIL\r\n");
+    fatal_stop()
+}
+
+/// Entered from `_start` with the power-up `.bss` fold: its FNV-1a hash
+/// and how many words differed from their predecessor.
 #[no_mangle]
-pub extern "C" fn rot_main() -> ! {
+pub extern "C" fn rot_main(sram_fold: u32, sram_varied: u32) -> ! {
     uart_puts("================================================================\r\n");
     uart_puts("  RISC-V Root of Trust — CFI + PMP Isolation Demo\r\n");
     uart_puts("  RV32IMAC + Zicfilp + Zicfiss + PMP\r\n");
     uart_puts("================================================================\r\n\r\n");
 
+    report_stacks();
+    check_mtvec();
+    provision_device_key();
+    seed_drbg(sram_fold, sram_varied);
+    #[cfg(feature = "stack-canary")]
+    seed_stack_canaries();
+
     // ── Phase 1: Enable hardware CFI ──
+    enter_phase(BootPhase::CfiInit);
     uart_puts("── Phase 1: CFI Initialization ─────────────────────────────\r\n");
-    enable_cfi();
+    let cfi = enable_cfi();
+    enable_sstc();
+    check_m_ecall_rejected();
+    complete_phase();
 
     // ── Phase 2: Configure PMP ──
+    enter_phase(BootPhase::PmpConfig);
     uart_puts("── Phase 2: PMP Configuration ──────────────────────────────\r\n");
     configure_pmp();
+    dump_pmp();
+    #[cfg(feature = "ecall-audit")]
+    check_audit_log_denied();
+    enable_console_irq();
+    complete_phase();
+    #[cfg(feature = "boot-phase-test")]
+    check_launch_refused();
 
     // ── Phase 3: Measure U-mode firmware ──
+    enter_phase(BootPhase::Measure);
     uart_puts("── Phase 3: Firmware Measurement ───────────────────────────\r\n");
+    #[cfg(feature = "net-load")]
+    let entry = net_load();
+    #[cfg(not(feature = "net-load"))]
+    let entry = layout::u_entry_point();
+    verify_u_code_pmp();
+    verify_firmware_header();
     uart_puts("[MEASURE] Computing firmware measurement over U_CODE region...\r\n");
+    #[cfg(feature = "stack-headroom")]
+    check_stack_headroom(CRYPTO_STACK);
     {
+        let code = u_code();
+        let mut digest = [0u8; DIGEST_LEN];
+        // SAFETY: `code` is the mapped U_CODE region.
         let measurement = unsafe {
-            rot_measure_firmware(0x8002_0000, 128 * 1024)
+            rot_measure_firmware(code.as_ptr() as u32, code.len() as u32, &mut digest)
         };
-        uart_puts("  Measurement (XOR hash): ");
+        uart_puts("  Measurement (SHA-256):\r\n  ");
+        uart_put_hex_bytes(&digest, DIGEST_LAYOUT.wrapped(16, "  "));
+        uart_puts("\r\n  Check word (XOR of words, syscall 9): ");
         uart_put_hex32(measurement);
         uart_newline();
         uart_puts("  (Real RoT would compare against OTP-stored golden hash)\r\n\r\n");
+        MEASUREMENT.store(measurement, Ordering::Relaxed);
+
+        let measured = MEASUREMENT_LOG.with(|log| {
+            measure_rom(log);
+
+            let measured = log.extend(PCR_FIRMWARE, Digest(digest), "U_CODE").is_ok();
+            #[cfg(feature = "measure-rodata")]
+            let measured = measured && measure_u_rodata(log);
+            if measured {
+                #[cfg(feature = "page-measure")]
+                measure_u_code_pages(log);
+                report_measurement_log(log);
+            }
+            // The boot PCRs are final: nothing U-mode does may extend them.
+            let _ = log.lock(PCR_ROM);
+            let _ = log.lock(PCR_FIRMWARE);
+            #[cfg(not(feature = "measure-rodata"))]
+            let _ = write!(
+                UartWriter,
+                "[MEASURE] PCR{} and PCR{} locked; PCR{}+ open to U-mode\r\n\r\n",
+                PCR_ROM, PCR_FIRMWARE, PCR_RUNTIME
+            );
+            #[cfg(feature = "measure-rodata")]
+            {
+                let _ = log.lock(PCR_RODATA);
+                let _ = write!(
+                    UartWriter,
+                    "[MEASURE] PCR{}, PCR{} and PCR{} locked; the rest open to U-mode\r\n\r\n",
+                    PCR_ROM, PCR_FIRMWARE, PCR_RODATA
+                );
+            }
+            measured
+        });
+        dump_log();
+        uart_newline();
+        report_quote_signer();
+
+        #[cfg(feature = "secure-session")]
+        host_session_report(measurement);
+
+        // U-mode only runs firmware that is in the log.
+        if measured == Some(true) {
+            complete_phase();
+        }
     }
 
     // ── Phase 4: Seal a secret using RoT key ──
+    enter_phase(BootPhase::Seal);
     uart_puts("── Phase 4: Secret Sealing (RoT Key Service) ───────────────\r\n");
     {
         let sealed = unsafe { rot_seal_secret(0xDEAD_BEEF, 1) };
         uart_puts("  seal(0xDEADBEEF, key_id=1) = ");
         uart_put_hex32(sealed);
         uart_newline();
-        uart_puts("  (Stub: XOR-based, real RoT uses AES-GCM/HMAC)\r\n\r\n");
+        uart_puts("  (Stub: XOR-based, real RoT uses AES-GCM/HMAC)\r\n");
+        #[cfg(feature = "policy-seal")]
+        policy_seal_demo();
+        uart_newline();
     }
+    complete_phase();
 
     // ── Phase 5: Launch U-mode ──
+    enter_phase(BootPhase::Launch);
     uart_puts("── Phase 5: U-Mode Launch ──────────────────────────────────\r\n");
+    let hartid = csr::read::<{ csr::MHARTID }>() as u32;
+    let boot_info = write_boot_info(hartid);
+    #[cfg(feature = "lock-u-pmp")]
+    lock_u_pmp();
+    init_u_heap();
     uart_puts("[LAUNCH] Security state summary:\r\n");
-    uart_puts("  - Hardware CFI: Zicfilp (landing pads) + Zicfiss (shadow stack)\r\n");
-    uart_puts("  - Software CFI: gp-based shadow stack (fallback for non-Zicfiss)\r\n");
-    uart_puts("  - PMP: 8 entries isolating M-mode / U-mode regions\r\n");
+    let _ = write!(UartWriter, "  - Hardware CFI: {}\r\n", cfi);
+    uart_puts(if cfg!(feature = "sw-ss-s11") {
+        "  - Software CFI: s11-based shadow stack (fallback for non-Zicfiss)\r\n"
+    } else {
+        "  - Software CFI: gp-based shadow stack (fallback for non-Zicfiss)\r\n"
+    });
+    let _ = write!(
+        UartWriter,
+        "  - PMP: {} entries isolating M-mode / U-mode regions\r\n",
+        BOOT_PMP.active_count()
+    );
+    if cfg!(feature = "lock-u-pmp") {
+        uart_puts("  - PMP lock: U-mode entries locked until reset (M-mode bound too)\r\n");
+    }
+    uart_puts(if cfg!(feature = "cfi-contain") {
+        "  - CFI violations: U-mode ones end the offending context, M-mode ones are fatal\r\n"
+    } else if cfg!(feature = "quarantine-policy") {
+        "  - CFI violations: quarantine (U-mode code region -> no-execute, resume at recovery)\r\n"
+    } else {
+        "  - CFI violations: fatal (halt, or reset with reset-on-panic)\r\n"
+    });
+    let counters = enable_u_counters();
+    let _ = write!(
+        UartWriter,
+        "  - Counters: U-mode rdcycle {}, rdinstret {} (else syscall 7)\r\n",
+        if counters & perf::MCOUNTEREN_CY != 0 { "on" } else { "off" },
+        if counters & perf::MCOUNTEREN_IR != 0 { "on" } else { "off" },
+    );
     uart_puts("  - Privilege: Dropping from M-mode -> U-mode via mret\r\n");
     uart_puts("  - W^X: U-mode code is RX, U-mode data is RW (no RWX)\r\n");
-    uart_puts("  - U-mode services: ecall to M-mode for UART, crypto, etc.\r\n\r\n");
+    uart_puts("  - U-mode services: ecall to M-mode for UART, crypto, etc.\r\n");
+    uart_puts("  - Monitor calls: a7 bit 31, token-gated (token handed over in a0)\r\n\r\n");
+    let token = generate_monitor_token();
+    if TX_IRQ_READY.load(Ordering::Relaxed) {
+        enable_console_rx_irq();
+    }
 
-    launch_umode();
+    // Last PMP write (configure_pmp, lock_u_pmp) to mret: see pmp_barrier.
+    pmp_barrier();
+    launch_umode(EntryArgs { token, hartid, boot_info }, entry, &cfi);
 
     // Never reached — launch_umode() does mret
     unreachable!()
@@ -865,10 +9917,21 @@ pub extern "C" fn rot_main() -> ! {
 // Panic Handler
 // ============================================================================
 
+/// Set on entry to the panic handler.  A panic raised while reporting one
+/// (the console, the location formatting, `fatal_stop`'s reset path) finds
+/// it set and fails the run at once, instead of recursing until the stack
+/// runs out.
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    if PANICKING.swap(true, Ordering::Relaxed) {
+        test_device::fail(test_device::FATAL_CODE)
+    }
     uart_puts("\r\n!!! ROOT OF TRUST PANIC !!!\r\n");
-    if let Some(loc) = info.location() {
+    // `panic-minimal` builds stop at the banner: no location formatting.
+    let verbose = cfg!(feature = "panic-verbose") && !cfg!(feature = "panic-minimal");
+    if let Some(loc) = info.location().filter(|_| verbose) {
         uart_puts("  at ");
         uart_puts(loc.file());
         uart_puts(":");
@@ -890,8 +9953,5 @@ fn panic(info: &PanicInfo) -> ! {
         }
         uart_newline();
     }
-    uart_puts("  SYSTEM HALTED — security invariant violated\r\n");
-    loop {
-        unsafe { asm!("wfi") };
-    }
+    fatal_stop()
 }
@@ -0,0 +1,701 @@
+//! Measurement log and PCRs.
+//!
+//! Each boot-time measurement is a digest *extended* into one of
+//! [`PCR_COUNT`] platform configuration registers:
+//!
+//! ```text
+//!   PCR[i] = H(PCR[i] || digest)      (PCRs start at all-zero)
+//! ```
+//!
+//! so a PCR commits to every digest extended into it, in order.  The log
+//! keeps the individual digests alongside, so a verifier can replay it and
+//! check the result against the PCR values.
+//!
+//! `H` is the log's [`Hasher`], SHA-256 unless the type says otherwise;
+//! digests are `D` bytes, its output length.
+//!
+//! A PCR can be *locked*: from then on it refuses further extends, so its
+//! value is final for the rest of the boot.
+//!
+//! For a host verifier the log is serialised as [`tlv`](crate::tlv)
+//! records ([`MeasurementLog::encode`]):
+//!
+//! ```text
+//!   tag  len    field
+//!   1    4      count    number of entries, big-endian
+//!   2    1 + D  entry    PCR index (one byte), then the digest
+//!   2    1 + D  entry    ... one record per entry, in log order
+//! ```
+//!
+//! A verifier decodes an entry record with [`LogEntry::from_record`] and
+//! skips tags it doesn't know.  The descriptions are for the console only
+//! and aren't sent.
+//!
+//! The console gets the whole log, descriptions included, in a text form
+//! a host tool can pick out of the rest of the output
+//! ([`MeasurementLog::write_text`], read back by [`parse_text_line`]):
+//!
+//! ```text
+//!   MLOG BEGIN <count>
+//!   MLOG <index> <pcr> <digest> <desc>      one line per entry, index from 0
+//!   MLOG TLV <hex>                          the records above, as hex
+//!   MLOG END
+//! ```
+//!
+//! Fields are separated by single spaces.  `count`, `index` and `pcr` are
+//! decimal.  `digest` is `2 * D` and `hex` twice the encoding's length in
+//! lowercase hex digits with no spaces.  `desc` is the rest of the line
+//! and may contain spaces.  Lines end in `\r\n`.  Any line that doesn't
+//! start with `MLOG ` is other console output.
+
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::Range;
+
+use crate::collections::FixedVec;
+use crate::digest::{Digest, Hasher};
+use crate::hex::{decode_hex, HexBytes, HexLayout};
+use crate::sha256::{Sha256, DIGEST_LEN};
+use crate::tlv::{self, Tlv, TlvError, TlvWriter, HEADER_LEN};
+
+/// Number of PCRs.
+pub const PCR_COUNT: usize = 4;
+
+/// Default log capacity.
+pub const LOG_CAPACITY: usize = 16;
+
+/// PCR for the RoT's own code (ROM) — the root of the chain.
+pub const PCR_ROM: u8 = 0;
+
+/// PCR for the U-mode firmware (U_CODE).
+pub const PCR_FIRMWARE: u8 = 1;
+
+/// First PCR left for runtime measurements by U-mode.
+pub const PCR_RUNTIME: u8 = 2;
+
+/// PCR for U-mode's read-only data (U_RODATA), in builds that measure
+/// it.  The last PCR: it is a runtime PCR in builds that don't.
+pub const PCR_RODATA: u8 = PCR_COUNT as u8 - 1;
+
+/// Tag of the wire form's entry-count record.
+pub const TAG_COUNT: u8 = 1;
+
+/// Tag of the wire form's entry records.
+pub const TAG_ENTRY: u8 = 2;
+
+/// Size of one serialised SHA-256 log entry, record header included.
+pub const ENTRY_WIRE_LEN: usize = LogEntry::<DIGEST_LEN>::WIRE_LEN;
+
+/// One measurement.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LogEntry<const D: usize = DIGEST_LEN> {
+    pub pcr: u8,
+    pub digest: Digest<D>,
+    /// What was measured, for humans reading the log.
+    pub desc: &'static str,
+}
+
+impl<const D: usize> LogEntry<D> {
+    /// Size of the serialised entry, record header included.
+    pub const WIRE_LEN: usize = HEADER_LEN + 1 + D;
+
+    /// Append the entry's record, PCR index then digest, to `w`.
+    pub fn encode(&self, w: &mut TlvWriter<'_>) -> Result<(), TlvError> {
+        w.push_with(TAG_ENTRY, 1 + D, |v| {
+            v[0] = self.pcr;
+            v[1..].copy_from_slice(self.digest.as_bytes());
+        })
+    }
+
+    /// The entry an entry record holds, with an empty description.  `None`
+    /// for another tag or a value that isn't `1 + D` bytes.
+    pub fn from_record(rec: &Tlv<'_>) -> Option<Self> {
+        match (rec.tag, rec.value) {
+            (TAG_ENTRY, [pcr, digest @ ..]) => {
+                Some(LogEntry { pcr: *pcr, digest: Digest(digest.try_into().ok()?), desc: "" })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Errors from [`MeasurementLog::extend`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogError {
+    /// PCR index ≥ [`PCR_COUNT`].
+    BadPcr,
+    /// The log is full.  Nothing was extended, so PCRs and log still agree.
+    Full,
+    /// The PCR is locked.
+    Locked,
+}
+
+/// PCR bank plus the log of everything extended into it, up to `N`
+/// entries of `D`-byte digests from `H`.
+pub struct MeasurementLog<const N: usize = LOG_CAPACITY, H = Sha256, const D: usize = DIGEST_LEN> {
+    pcrs: [Digest<D>; PCR_COUNT],
+    /// Bit `i` set: PCR `i` is locked.
+    locked: u8,
+    entries: FixedVec<LogEntry<D>, N>,
+    /// The log never holds a hasher, only builds one per extend.
+    hasher: PhantomData<fn() -> H>,
+}
+
+// By hand: derived impls would want `H: Clone` etc., which the log doesn't.
+impl<const N: usize, H, const D: usize> Clone for MeasurementLog<N, H, D> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<const N: usize, H, const D: usize> Copy for MeasurementLog<N, H, D> {}
+
+impl<const N: usize, H, const D: usize> fmt::Debug for MeasurementLog<N, H, D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MeasurementLog")
+            .field("pcrs", &self.pcrs)
+            .field("locked", &self.locked)
+            .field("entries", &self.entries)
+            .finish()
+    }
+}
+
+impl<const N: usize, H: Hasher<D> + Default, const D: usize> Default for MeasurementLog<N, H, D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize, H: Hasher<D> + Default, const D: usize> MeasurementLog<N, H, D> {
+    /// Longest [`encode`](Self::encode) output, for a full log.
+    pub const WIRE_MAX: usize = HEADER_LEN + 4 + N * LogEntry::<D>::WIRE_LEN;
+
+    pub const fn new() -> Self {
+        MeasurementLog {
+            pcrs: [Digest::ZERO; PCR_COUNT],
+            locked: 0,
+            entries: FixedVec::new(),
+            hasher: PhantomData,
+        }
+    }
+
+    /// Record `digest` and extend it into `pcr`.
+    pub fn extend(
+        &mut self,
+        pcr: u8,
+        digest: Digest<D>,
+        desc: &'static str,
+    ) -> Result<(), LogError> {
+        let slot = self.pcrs.get_mut(pcr as usize).ok_or(LogError::BadPcr)?;
+        if self.locked & (1 << pcr) != 0 {
+            return Err(LogError::Locked);
+        }
+        self.entries
+            .push(LogEntry { pcr, digest, desc })
+            .map_err(|_| LogError::Full)?;
+        *slot = extend_pcr::<H, D>(slot, &digest);
+        Ok(())
+    }
+
+    /// Lock `pcr` against further extends.  Locking twice is not an error.
+    pub fn lock(&mut self, pcr: u8) -> Result<(), LogError> {
+        if pcr as usize >= PCR_COUNT {
+            return Err(LogError::BadPcr);
+        }
+        self.locked |= 1 << pcr;
+        Ok(())
+    }
+
+    pub fn is_locked(&self, pcr: u8) -> bool {
+        (pcr as usize) < PCR_COUNT && self.locked & (1 << pcr) != 0
+    }
+
+    /// Current value of `pcr`.
+    pub fn pcr(&self, pcr: u8) -> Option<&Digest<D>> {
+        self.pcrs.get(pcr as usize)
+    }
+
+    pub fn entries(&self) -> &[LogEntry<D>] {
+        self.entries.as_slice()
+    }
+
+    /// One digest over every PCR, for an attestation quote
+    /// ([`attest`](crate::attest)).  A verifier gets the same value from
+    /// [`digest_pcrs`](Self::digest_pcrs) over its replay of the log.
+    pub fn pcr_digest(&self) -> Digest<D> {
+        Self::digest_pcrs(&self.pcrs)
+    }
+
+    /// `H(PCR[0] || PCR[1] || ... )`, all [`PCR_COUNT`] in index order.
+    pub fn digest_pcrs(pcrs: &[Digest<D>; PCR_COUNT]) -> Digest<D> {
+        let mut h = H::default();
+        for pcr in pcrs {
+            h.update(pcr.as_bytes());
+        }
+        let mut out = Digest::ZERO;
+        h.finalize(&mut out.0);
+        out
+    }
+
+    /// Serialise the log into `out` (format in the module docs), returning
+    /// the length written, or `None` if `out` is too small.
+    pub fn encode(&self, out: &mut [u8]) -> Option<usize> {
+        let mut w = TlvWriter::new(out);
+        w.push_u32(TAG_COUNT, self.entries().len() as u32).ok()?;
+        for e in self.entries() {
+            e.encode(&mut w).ok()?;
+        }
+        Some(w.len())
+    }
+
+    /// Write the log to `w` in its text form (format in the module docs).
+    pub fn write_text(&self, w: &mut impl fmt::Write) -> fmt::Result {
+        let entries = self.entries();
+        write!(w, "{} BEGIN {}\r\n", TEXT_TAG, entries.len())?;
+        for (i, e) in entries.iter().enumerate() {
+            let digest = HexBytes::new(e.digest.as_bytes(), HexLayout::PLAIN);
+            write!(w, "{} {} {} {} {}\r\n", TEXT_TAG, i, e.pcr, digest, e.desc)?;
+        }
+        // The same records `encode` writes, a piece at a time, so the dump
+        // needs no WIRE_MAX buffer on the stack.
+        fn hex(bytes: &[u8]) -> HexBytes<'_> {
+            HexBytes::new(bytes, HexLayout::PLAIN)
+        }
+        let count = (entries.len() as u32).to_be_bytes();
+        write!(w, "{} TLV {}{}", TEXT_TAG, hex(&tlv::header(TAG_COUNT, 4)), hex(&count))?;
+        for e in entries {
+            let head = tlv::header(TAG_ENTRY, (1 + D) as u16);
+            write!(w, "{}{}{}", hex(&head), hex(&[e.pcr]), hex(e.digest.as_bytes()))?;
+        }
+        write!(w, "\r\n{} END\r\n", TEXT_TAG)
+    }
+
+    /// Recompute the PCRs from the log alone, as a verifier would.
+    pub fn replay(entries: &[LogEntry<D>]) -> [Digest<D>; PCR_COUNT] {
+        let mut pcrs = [Digest::ZERO; PCR_COUNT];
+        for e in entries {
+            if let Some(p) = pcrs.get_mut(e.pcr as usize) {
+                *p = extend_pcr::<H, D>(p, &e.digest);
+            }
+        }
+        pcrs
+    }
+}
+
+/// First word of every line of the text form.
+pub const TEXT_TAG: &str = "MLOG";
+
+/// One line of the text form, as [`parse_text_line`] reads it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextRecord<'a, const D: usize = DIGEST_LEN> {
+    /// `MLOG BEGIN <count>`: `count` entry lines follow.
+    Begin { count: usize },
+    /// `MLOG <index> <pcr> <digest> <desc>`.
+    Entry { index: usize, pcr: u8, digest: Digest<D>, desc: &'a str },
+    /// `MLOG TLV <hex>`: the wire form, for [`decode_hex`].
+    Tlv { hex: &'a str },
+    /// `MLOG END`: the log is complete.
+    End,
+}
+
+/// Read one line of the text form.  A trailing `\r` or `\n` is ignored.
+/// `None` if the line isn't a well-formed log line: other console output,
+/// or an `MLOG` line that lost characters on the way.
+pub fn parse_text_line<const D: usize>(line: &str) -> Option<TextRecord<'_, D>> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    let rest = line.strip_prefix(TEXT_TAG)?.strip_prefix(' ')?;
+    if rest == "END" {
+        return Some(TextRecord::End);
+    }
+    if let Some(count) = rest.strip_prefix("BEGIN ") {
+        return Some(TextRecord::Begin { count: parse_decimal(count)? });
+    }
+    if let Some(hex) = rest.strip_prefix("TLV ") {
+        let digits = hex.bytes().all(|c| matches!(c, b'0'..=b'9' | b'a'..=b'f'));
+        return (!hex.is_empty() && hex.len().is_multiple_of(2) && digits).then_some(TextRecord::Tlv { hex });
+    }
+    let mut fields = rest.splitn(4, ' ');
+    let index = parse_decimal(fields.next()?)?;
+    let pcr = parse_decimal(fields.next()?)?.try_into().ok()?;
+    let mut digest = Digest::ZERO;
+    if !decode_hex(fields.next()?, &mut digest.0) {
+        return None;
+    }
+    let desc = fields.next()?;
+    Some(TextRecord::Entry { index, pcr, digest, desc })
+}
+
+/// Digits only: `usize::from_str` would also take a leading `+`.
+fn parse_decimal(s: &str) -> Option<usize> {
+    if s.is_empty() || !s.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// Digest of `data` with the bytes in `hole` read as zero.
+///
+/// For a region that holds a value drawn afresh every boot (the U-mode
+/// stack canary in U_RODATA): with the value masked, the region's
+/// measurement is the same each boot and can be compared with a golden
+/// one.  `hole` is clamped to `data`.
+pub fn digest_with_hole<H: Hasher<D> + Default, const D: usize>(data: &[u8], hole: Range<usize>) -> Digest<D> {
+    let end = hole.end.min(data.len());
+    let start = hole.start.min(end);
+    let mut h = H::default();
+    h.update(&data[..start]);
+    let zeros = [0u8; 64];
+    let mut left = end - start;
+    while left > 0 {
+        let n = left.min(zeros.len());
+        h.update(&zeros[..n]);
+        left -= n;
+    }
+    h.update(&data[end..]);
+    let mut out = Digest::ZERO;
+    h.finalize(&mut out.0);
+    out
+}
+
+/// XOR of `data` read as little-endian 32-bit words: the quick firmware
+/// measurement syscall 9 reports, alongside the SHA-256 one in the log.
+///
+/// A tail shorter than a word is read zero-padded, so every byte of
+/// `data` is covered whatever its length or alignment.
+pub fn xor_words(data: &[u8]) -> u32 {
+    let (words, rest) = data.as_chunks::<4>();
+    let mut tail = [0u8; 4];
+    tail[..rest.len()].copy_from_slice(rest);
+    words.iter().fold(u32::from_le_bytes(tail), |acc, w| acc ^ u32::from_le_bytes(*w))
+}
+
+fn extend_pcr<H: Hasher<D> + Default, const D: usize>(pcr: &Digest<D>, digest: &Digest<D>) -> Digest<D> {
+    let mut h = H::default();
+    h.update(pcr.as_bytes());
+    h.update(digest.as_bytes());
+    let mut out = Digest::ZERO;
+    h.finalize(&mut out.0);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sha256::sha256;
+
+    #[test]
+    fn extend_matches_definition() {
+        let mut log: MeasurementLog = MeasurementLog::new();
+        let d = sha256(b"rom");
+        log.extend(PCR_ROM, Digest(d), "ROM").unwrap();
+
+        let mut buf = [0u8; 64];
+        buf[32..].copy_from_slice(&d);
+        assert_eq!(log.pcr(PCR_ROM), Some(&Digest(sha256(&buf))));
+        assert_eq!(log.pcr(PCR_FIRMWARE), Some(&Digest::ZERO));
+    }
+
+    #[test]
+    fn holes_read_as_zero() {
+        let mut data = [0x5au8; 200];
+        let d = digest_with_hole::<Sha256, DIGEST_LEN>(&data, 0..0);
+        assert_eq!(d, Sha256::digest(&data));
+
+        let masked = digest_with_hole::<Sha256, DIGEST_LEN>(&data, 100..104);
+        assert_ne!(masked, d);
+        data[100..104].fill(0);
+        assert_eq!(masked, Sha256::digest(&data));
+        // Whatever the hole holds, the digest is the same.
+        data[100..104].copy_from_slice(&[1, 2, 3, 4]);
+        assert_eq!(digest_with_hole::<Sha256, DIGEST_LEN>(&data, 100..104), masked);
+        // A byte next to the hole still counts.
+        data[104] ^= 1;
+        assert_ne!(digest_with_hole::<Sha256, DIGEST_LEN>(&data, 100..104), masked);
+
+        // Clamped: a hole off the end masks nothing, one across it the tail.
+        assert_eq!(digest_with_hole::<Sha256, DIGEST_LEN>(&data, 300..304), Sha256::digest(&data));
+        let mut tail = data;
+        tail[150..].fill(0);
+        assert_eq!(digest_with_hole::<Sha256, DIGEST_LEN>(&data, 150..1000), Sha256::digest(&tail));
+    }
+
+    #[test]
+    fn altering_rodata_changes_its_pcr() {
+        let measure = |rodata: &[u8]| {
+            let mut log: MeasurementLog = MeasurementLog::new();
+            log.extend(PCR_FIRMWARE, Sha256::digest(b"code"), "U_CODE").unwrap();
+            log.extend(PCR_RODATA, digest_with_hole::<Sha256, DIGEST_LEN>(rodata, 8..12), "U_RODATA").unwrap();
+            log
+        };
+        let mut rodata = *b"policy: deny-all\0canary..";
+        let golden = measure(&rodata);
+        assert_ne!(golden.pcr(PCR_RODATA), Some(&Digest::ZERO));
+
+        // A new canary: same PCR.
+        rodata[8..12].copy_from_slice(b"CANA");
+        assert_eq!(measure(&rodata).pcr(PCR_RODATA), golden.pcr(PCR_RODATA));
+
+        // The policy edited: the rodata PCR moves, the code PCR doesn't.
+        rodata[0..6].copy_from_slice(b"POLICY");
+        let tampered = measure(&rodata);
+        assert_ne!(tampered.pcr(PCR_RODATA), golden.pcr(PCR_RODATA));
+        assert_eq!(tampered.pcr(PCR_FIRMWARE), golden.pcr(PCR_FIRMWARE));
+        assert_ne!(PCR_RODATA, PCR_FIRMWARE);
+        assert!(PCR_RODATA >= PCR_RUNTIME && (PCR_RODATA as usize) < PCR_COUNT);
+    }
+
+    #[test]
+    fn xor_words_covers_a_partial_tail() {
+        assert_eq!(xor_words(&[]), 0);
+        assert_eq!(xor_words(&[1, 2, 3, 4, 0x10, 0x20, 0x30, 0x40]), 0x4433_2211);
+        // A tail of one to three bytes is zero-padded, not dropped.
+        assert_eq!(xor_words(&[1, 2, 3, 4, 0xaa]), 0x0403_02ab);
+        assert_eq!(xor_words(&[0xaa, 0xbb, 0xcc]), 0x00cc_bbaa);
+
+        // Every byte counts, at every length: the last one in particular.
+        let image: std::vec::Vec<u8> = (1..=64u8).collect();
+        for len in 1..image.len() {
+            let mut changed = image[..len].to_vec();
+            for i in 0..len {
+                changed[i] ^= 0x80;
+                assert_ne!(xor_words(&changed), xor_words(&image[..len]), "len {len}, byte {i}");
+                changed[i] ^= 0x80;
+            }
+        }
+    }
+
+    #[test]
+    fn the_log_digest_covers_a_partial_tail() {
+        let image = [0x5au8; 4 * 17 + 3];
+        let mut log: MeasurementLog = MeasurementLog::new();
+        log.extend(PCR_FIRMWARE, Digest(sha256(&image)), "U_CODE").unwrap();
+        let mut changed = image;
+        changed[image.len() - 1] ^= 1;
+        let mut other: MeasurementLog = MeasurementLog::new();
+        other.extend(PCR_FIRMWARE, Digest(sha256(&changed)), "U_CODE").unwrap();
+        assert_ne!(log.pcr(PCR_FIRMWARE), other.pcr(PCR_FIRMWARE));
+    }
+
+    #[test]
+    fn pcr_digest_covers_every_pcr() {
+        let mut log: MeasurementLog = MeasurementLog::new();
+        log.extend(PCR_ROM, Sha256::digest(b"rom"), "ROM").unwrap();
+        log.extend(PCR_FIRMWARE, Sha256::digest(b"fw"), "U_CODE").unwrap();
+        let mut all = Vec::new();
+        for i in 0..PCR_COUNT as u8 {
+            all.extend_from_slice(log.pcr(i).unwrap().as_bytes());
+        }
+        assert_eq!(log.pcr_digest(), Digest(sha256(&all)));
+        let replayed = MeasurementLog::<LOG_CAPACITY>::replay(log.entries());
+        assert_eq!(MeasurementLog::<LOG_CAPACITY>::digest_pcrs(&replayed), log.pcr_digest());
+
+        // A runtime extend moves it too.
+        let before = log.pcr_digest();
+        log.extend(PCR_COUNT as u8 - 1, Sha256::digest(b"rt"), "rt").unwrap();
+        assert_ne!(log.pcr_digest(), before);
+    }
+
+    #[test]
+    fn replay_reproduces_pcrs() {
+        let mut log: MeasurementLog = MeasurementLog::new();
+        log.extend(0, Sha256::digest(b"a"), "a").unwrap();
+        log.extend(2, Sha256::digest(b"b"), "b").unwrap();
+        log.extend(0, Sha256::digest(b"c"), "c").unwrap();
+        let pcrs = MeasurementLog::<LOG_CAPACITY>::replay(log.entries());
+        for i in 0..PCR_COUNT as u8 {
+            assert_eq!(Some(&pcrs[i as usize]), log.pcr(i));
+        }
+    }
+
+    /// A verifier's read of the wire form: the count, and the entries in
+    /// order, skipping records it doesn't know.
+    fn decode_wire<const D: usize>(msg: &[u8]) -> (Option<u32>, Vec<LogEntry<D>>) {
+        let mut count = None;
+        let mut entries = Vec::new();
+        for rec in crate::tlv::TlvReader::new(msg) {
+            let rec = rec.unwrap();
+            match rec.tag {
+                TAG_COUNT => count = rec.as_u32(),
+                TAG_ENTRY => entries.push(LogEntry::from_record(&rec).unwrap()),
+                _ => {}
+            }
+        }
+        (count, entries)
+    }
+
+    #[test]
+    fn wire_form_is_tlv_records() {
+        let mut log: MeasurementLog<2> = MeasurementLog::new();
+        log.extend(PCR_FIRMWARE, Digest([0xaa; 32]), "fw").unwrap();
+        log.extend(3, Digest([0xbb; 32]), "rt").unwrap();
+
+        let mut out = [0u8; MeasurementLog::<2>::WIRE_MAX];
+        assert_eq!(log.encode(&mut out), Some(7 + 2 * ENTRY_WIRE_LEN));
+        assert_eq!(out[..11], [TAG_COUNT, 0, 4, 0, 0, 0, 2, TAG_ENTRY, 0, 33, 1]);
+        assert_eq!(out[11..43], [0xaa; 32]);
+        assert_eq!(out[43..47], [TAG_ENTRY, 0, 33, 3]);
+        assert_eq!(out[47..], [0xbb; 32]);
+
+        // A record from a later format doesn't get in the way.
+        let mut msg = out.to_vec();
+        msg.extend_from_slice(&crate::tlv::header(0x40, 2));
+        msg.extend_from_slice(b"xy");
+        let (count, entries) = decode_wire::<DIGEST_LEN>(&msg);
+        assert_eq!(count, Some(2));
+        let sent: Vec<_> = log.entries().iter().map(|e| (e.pcr, e.digest)).collect();
+        assert_eq!(entries.iter().map(|e| (e.pcr, e.digest)).collect::<Vec<_>>(), sent);
+        assert_eq!(MeasurementLog::<2>::replay(&entries)[3], *log.pcr(3).unwrap());
+
+        // An entry record one byte short.
+        let short = Tlv { tag: TAG_ENTRY, value: &out[10..42] };
+        assert_eq!(LogEntry::<DIGEST_LEN>::from_record(&short), None);
+
+        assert_eq!(log.encode(&mut out[..MeasurementLog::<2>::WIRE_MAX - 1]), None);
+        let empty: MeasurementLog<2> = MeasurementLog::new();
+        assert_eq!(empty.encode(&mut out[..7]), Some(7));
+        assert_eq!(out[..7], [TAG_COUNT, 0, 4, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn errors_leave_state_untouched() {
+        let mut log: MeasurementLog<1> = MeasurementLog::new();
+        assert_eq!(log.extend(PCR_COUNT as u8, Digest([1; 32]), "bad"), Err(LogError::BadPcr));
+        log.extend(0, Digest([1; 32]), "ok").unwrap();
+        let pcr0 = *log.pcr(0).unwrap();
+        assert_eq!(log.extend(0, Digest([2; 32]), "full"), Err(LogError::Full));
+        assert_eq!(log.pcr(0), Some(&pcr0));
+        assert_eq!(log.entries().len(), 1);
+    }
+
+    #[test]
+    fn locked_pcr_refuses_extend() {
+        let mut log: MeasurementLog = MeasurementLog::new();
+        log.extend(PCR_RUNTIME, Digest([1; 32]), "before").unwrap();
+        let before = *log.pcr(PCR_RUNTIME).unwrap();
+        log.lock(PCR_RUNTIME).unwrap();
+        log.lock(PCR_RUNTIME).unwrap();
+        assert!(log.is_locked(PCR_RUNTIME));
+        assert_eq!(log.extend(PCR_RUNTIME, Digest([2; 32]), "after"), Err(LogError::Locked));
+        assert_eq!(log.pcr(PCR_RUNTIME), Some(&before));
+        assert_eq!(log.entries().len(), 1);
+        // Other PCRs are unaffected.
+        log.extend(PCR_RUNTIME + 1, Digest([3; 32]), "other").unwrap();
+        assert_eq!(log.lock(PCR_COUNT as u8), Err(LogError::BadPcr));
+        assert!(!log.is_locked(PCR_COUNT as u8));
+    }
+
+    #[test]
+    fn text_form_parses_back() {
+        let mut log: MeasurementLog = MeasurementLog::new();
+        log.extend(PCR_ROM, Sha256::digest(b"rom"), "ROM").unwrap();
+        log.extend(PCR_FIRMWARE, Digest([0x0f; 32]), "U_CODE page root").unwrap();
+        log.extend(PCR_RUNTIME, Sha256::digest(b"rt"), "U-mode").unwrap();
+        let mut text = String::new();
+        log.write_text(&mut text).unwrap();
+        assert!(text.starts_with("MLOG BEGIN 3\r\nMLOG 0 0 "));
+        assert!(text.contains(&format!("\r\nMLOG 1 1 {} U_CODE page root\r\n", "0f".repeat(32))));
+        assert!(text.contains(" U-mode\r\nMLOG TLV 01000400000003020021"));
+        assert!(text.ends_with("\r\nMLOG END\r\n"));
+
+        // A host reading the console: other output mixed in, log lines
+        // split on "\r\n".
+        let console = format!("[BOOT] hello\r\n{}[U-MODE] done\r\n", text);
+        let mut count = None;
+        let mut entries = Vec::new();
+        let mut wire = Vec::new();
+        let mut ended = false;
+        for line in console.split_inclusive('\n') {
+            match parse_text_line::<DIGEST_LEN>(line) {
+                Some(TextRecord::Begin { count: n }) => count = Some(n),
+                Some(TextRecord::Entry { index, pcr, digest, desc }) => {
+                    assert_eq!(index, entries.len());
+                    assert_eq!(desc, log.entries()[index].desc);
+                    entries.push(LogEntry { pcr, digest, desc: "" });
+                }
+                Some(TextRecord::Tlv { hex }) => {
+                    wire.resize(hex.len() / 2, 0);
+                    assert!(decode_hex(hex, &mut wire));
+                }
+                Some(TextRecord::End) => ended = true,
+                None => assert!(!line.starts_with("MLOG")),
+            }
+        }
+        assert!(ended);
+        assert_eq!(count, Some(entries.len()));
+        // The TLV line is the wire form, and holds the same entries.
+        let mut out = [0u8; MeasurementLog::<LOG_CAPACITY>::WIRE_MAX];
+        let n = log.encode(&mut out).unwrap();
+        assert_eq!(wire, out[..n]);
+        assert_eq!(decode_wire(&wire), (Some(entries.len() as u32), entries.clone()));
+        // The parsed entries replay to the device's PCRs.
+        let pcrs = MeasurementLog::<LOG_CAPACITY>::replay(&entries);
+        for i in 0..PCR_COUNT as u8 {
+            assert_eq!(Some(&pcrs[i as usize]), log.pcr(i));
+        }
+
+        let mut empty = String::new();
+        MeasurementLog::<1>::new().write_text(&mut empty).unwrap();
+        assert_eq!(empty, "MLOG BEGIN 0\r\nMLOG TLV 01000400000000\r\nMLOG END\r\n");
+    }
+
+    #[test]
+    fn malformed_text_lines_are_refused() {
+        let digest = "ab".repeat(32);
+        let ok = format!("MLOG 2 1 {} a b c", digest);
+        assert_eq!(
+            parse_text_line::<DIGEST_LEN>(&ok),
+            Some(TextRecord::Entry { index: 2, pcr: 1, digest: Digest([0xab; 32]), desc: "a b c" })
+        );
+        for bad in [
+            String::new(),
+            "MLOG".into(),
+            "MLOGEND".into(),
+            "MLOG BEGIN".into(),
+            "MLOG BEGIN +3".into(),
+            "mlog END".into(),
+            "MLOG TLV".into(),
+            "MLOG TLV ".into(),
+            "MLOG TLV 0".into(),                             // odd length
+            "MLOG TLV 0A".into(),                            // upper case
+            "MLOG TLV 0g".into(),
+            format!("MLOG 2 1 {}", digest),                  // no desc
+            format!("MLOG 2 1 {} ", &digest[1..]),           // short digest
+            format!("MLOG 2 256 {} x", digest),              // PCR not a u8
+            format!("MLOG -2 1 {} x", digest),
+            format!("MLOG 2 1  {} x", digest),               // doubled space
+        ] {
+            assert_eq!(parse_text_line::<DIGEST_LEN>(&bad), None, "{:?}", bad);
+        }
+    }
+
+    /// A 4-byte "hash": the byte sums of the input, by position mod 4.
+    #[derive(Default)]
+    struct Sum4([u8; 4]);
+
+    impl Hasher<4> for Sum4 {
+        fn update(&mut self, data: &[u8]) {
+            for (i, b) in data.iter().enumerate() {
+                self.0[i % 4] = self.0[i % 4].wrapping_add(*b);
+            }
+        }
+
+        fn finalize(self, out: &mut [u8; 4]) {
+            *out = self.0;
+        }
+    }
+
+    #[test]
+    fn other_hashers_swap_in() {
+        let mut log: MeasurementLog<2, Sum4, 4> = MeasurementLog::new();
+        log.extend(1, Digest([1, 2, 3, 4]), "a").unwrap();
+        log.extend(1, Digest([10, 20, 30, 40]), "b").unwrap();
+        // Sum4(0 || d) = d, then Sum4(d1 || d2) = d1 + d2.
+        assert_eq!(log.pcr(1), Some(&Digest([11, 22, 33, 44])));
+        assert_eq!(MeasurementLog::<2, Sum4, 4>::replay(log.entries())[1], Digest([11, 22, 33, 44]));
+
+        let mut out = [0u8; MeasurementLog::<2, Sum4, 4>::WIRE_MAX];
+        assert_eq!(out.len(), 7 + 2 * 8);
+        assert_eq!(log.encode(&mut out), Some(out.len()));
+        assert_eq!(out, [1, 0, 4, 0, 0, 0, 2, 2, 0, 5, 1, 1, 2, 3, 4, 2, 0, 5, 1, 10, 20, 30, 40]);
+    }
+}
@@ -0,0 +1,136 @@
+//! The physical memory map, as data.
+//!
+//! The same regions appear in three places: `memory.x` (the linker's
+//! `MEMORY` block), the PMP map ([`crate::pmp_map`]) and the doc comments
+//! describing them.  [`MEMORY_MAP`] is the table the other two are checked
+//! against.  Its host tests check that:
+//!
+//! - no two regions overlap;
+//! - MMIO stays out of RAM;
+//! - every entry matches `memory.x`, which the test reads;
+//! - each PMP entry covers the regions it is meant to.
+//!
+//! Moving U_RAM without moving U_SHADOW then fails `cargo test` with the
+//! two region names, instead of showing up as a fault under QEMU.
+
+use crate::region::Region;
+
+pub const ROM: Region = Region::new(0x8000_0000, 64 * 1024);
+pub const M_RAM: Region = Region::new(0x8001_0000, 32 * 1024);
+pub const M_SHADOW: Region = Region::new(0x8001_8000, 4 * 1024);
+pub const M_SW_SHADOW: Region = Region::new(0x8001_9000, 4 * 1024);
+pub const U_CODE: Region = Region::new(0x8002_0000, 128 * 1024);
+pub const U_RODATA: Region = Region::new(0x8004_0000, 32 * 1024);
+pub const U_RAM: Region = Region::new(0x8004_8000, 60 * 1024);
+pub const U_GUARD: Region = Region::new(0x8005_7000, 4 * 1024);
+pub const U_SHADOW: Region = Region::new(0x8005_8000, 4 * 1024);
+pub const U_SW_SHADOW: Region = Region::new(0x8005_9000, 4 * 1024);
+pub const U_APP1: Region = Region::new(0x8006_0000, 64 * 1024);
+pub const U_RECOVERY: Region = Region::new(0x8007_0000, 4 * 1024);
+pub const UART_MMIO: Region = Region::new(0x1000_0000, 4 * 1024);
+pub const TEST_FINISH: Region = Region::new(0x0010_0000, 4 * 1024);
+
+/// Every region in `memory.x`, by its `MEMORY` name, in that order.
+pub const MEMORY_MAP: [(&str, Region); 14] = [
+    ("ROM", ROM),
+    ("M_RAM", M_RAM),
+    ("M_SHADOW", M_SHADOW),
+    ("M_SW_SHADOW", M_SW_SHADOW),
+    ("U_CODE", U_CODE),
+    ("U_RODATA", U_RODATA),
+    ("U_RAM", U_RAM),
+    ("U_GUARD", U_GUARD),
+    ("U_SHADOW", U_SHADOW),
+    ("U_SW_SHADOW", U_SW_SHADOW),
+    ("U_APP1", U_APP1),
+    ("U_RECOVERY", U_RECOVERY),
+    ("UART_MMIO", UART_MMIO),
+    ("TEST_FINISH", TEST_FINISH),
+];
+
+/// The interrupt controllers.  Not in `memory.x`: nothing is linked
+/// there, and no PMP entry grants them, so only M-mode reaches them.
+pub const CLINT: Region = Region::new(0x0200_0000, 64 * 1024);
+pub const PLIC: Region = Region::new(0x0c00_0000, 64 * 1024 * 1024);
+
+/// The MMIO regions; everything else in [`MEMORY_MAP`] is RAM or ROM.
+pub const MMIO: [Region; 4] = [UART_MMIO, TEST_FINISH, CLINT, PLIC];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pmp::napot_decode;
+    use crate::pmp_map::compute_pmp_config;
+    use std::vec::Vec;
+
+    #[test]
+    fn no_two_regions_overlap() {
+        for (i, (a, ra)) in MEMORY_MAP.iter().enumerate() {
+            assert!(ra.size != 0, "{a} is empty");
+            for (b, rb) in &MEMORY_MAP[i + 1..] {
+                assert!(!ra.overlaps(rb), "{a} {ra:x?} overlaps {b} {rb:x?}");
+            }
+        }
+    }
+
+    #[test]
+    fn mmio_stays_out_of_memory() {
+        // All of the on-chip memory, ROM to the last U region.
+        let memory = Region::from_bounds(ROM.base, U_RECOVERY.end());
+        for (i, mmio) in MMIO.iter().enumerate() {
+            assert!(!memory.overlaps(mmio), "MMIO {mmio:x?} inside memory");
+            assert!(!MEMORY_MAP.iter().any(|(_, r)| r != mmio && r.overlaps(mmio)));
+            assert!(!MMIO[i + 1..].iter().any(|other| other.overlaps(mmio)));
+        }
+    }
+
+    /// `NAME : ORIGIN = 0x..., LENGTH = nK` lines of a `MEMORY` block.
+    fn parse_memory_x(script: &str) -> Vec<(&str, Region)> {
+        script
+            .lines()
+            .filter_map(|line| {
+                let (name, rest) = line.split_once(':')?;
+                let (origin, length) = rest.split_once(',')?;
+                let origin = origin.trim().strip_prefix("ORIGIN = 0x")?;
+                let length = length.trim().strip_prefix("LENGTH = ")?.strip_suffix('K')?;
+                let base = usize::from_str_radix(origin, 16).ok()?;
+                Some((name.trim(), Region::new(base, length.parse::<usize>().ok()? * 1024)))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matches_memory_x() {
+        let linked = parse_memory_x(include_str!("../memory.x"));
+        assert_eq!(linked, MEMORY_MAP, "memory.x and MEMORY_MAP disagree");
+    }
+
+    #[test]
+    fn pmp_entries_cover_their_regions() {
+        let config = compute_pmp_config(true);
+        // Every entry is NAPOT, or holds a NAPOT address while OFF (M_RAM
+        // and M_SHADOW), so decode the address whatever the config says.
+        let pmp = |entry: usize| {
+            let (base, size) = napot_decode(config.pmpaddr[entry]);
+            Region::new(base as usize, size as usize)
+        };
+        let span = |first: Region, last: Region| Region::from_bounds(first.base, last.end());
+        let expect = [
+            (0, ROM),
+            (1, M_RAM),
+            (2, span(M_SHADOW, M_SW_SHADOW)),
+            (3, U_CODE),
+            (4, U_RODATA),
+            (7, UART_MMIO),
+            (8, U_GUARD),
+            (10, U_RECOVERY),
+        ];
+        for (entry, region) in expect {
+            assert_eq!(pmp(entry), region, "PMP entry {entry}");
+        }
+        // U_RAM and the guard page above it, in two halves; then both
+        // U-mode shadow stacks.
+        assert_eq!(span(pmp(5), pmp(9)), span(U_RAM, U_GUARD));
+        assert_eq!(pmp(6), span(U_SHADOW, U_SW_SHADOW));
+    }
+}
@@ -0,0 +1,207 @@
+//! Decoding the loads and stores a misaligned-access fixup emulates.
+//!
+//! A hart without misaligned-access support raises load or store/AMO
+//! address misaligned (mcause 4 / 6) instead.  The `misalign-fixup`
+//! build's trap handler then redoes the access a byte at a time: it
+//! [`decode`]s the instruction at mepc, reads or writes the bytes,
+//! sign-extends a load with [`load_value`] into the destination register
+//! and steps mepc over the instruction.
+//!
+//! Only the integer loads and stores that can be misaligned on RV32 are
+//! decoded: `lh`, `lhu`, `lw`, `sh`, `sw` and the compressed `c.lw`,
+//! `c.sw`, `c.lwsp`, `c.swsp`.  Byte accesses can't be misaligned. FP
+//! loads and stores and AMOs (which must be atomic) aren't emulated, so
+//! they stay fatal.
+
+/// What a decoded instruction does with memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    /// Load `len` bytes into `rd`, sign-extended if `signed`.
+    Load { rd: u8, len: usize, signed: bool },
+    /// Store the low `len` bytes of `rs2`.
+    Store { rs2: u8, len: usize },
+}
+
+/// A decoded load or store: the access at `x[rs1] + offset`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemOp {
+    pub access: Access,
+    /// Base register.
+    pub rs1: u8,
+    pub offset: i32,
+    /// Length of the instruction itself: 2 (compressed) or 4 bytes.
+    pub insn_len: usize,
+}
+
+impl MemOp {
+    /// The address accessed, given the base register's value.
+    pub const fn addr(&self, base: usize) -> usize {
+        base.wrapping_add(self.offset as usize)
+    }
+
+    /// Bytes accessed.
+    pub const fn size(&self) -> usize {
+        match self.access {
+            Access::Load { len, .. } | Access::Store { len, .. } => len,
+        }
+    }
+}
+
+/// Length of the instruction whose low halfword is `low`: 4 if its low two
+/// bits are `11`, else 2 (compressed).
+pub const fn insn_len(low: u16) -> usize {
+    if low & 0b11 == 0b11 {
+        4
+    } else {
+        2
+    }
+}
+
+const OP_LOAD: u32 = 0x03;
+const OP_STORE: u32 = 0x23;
+
+/// Decode `insn` (a compressed one in its low halfword), or `None` if it
+/// isn't a load or store this module emulates.
+pub fn decode(insn: u32) -> Option<MemOp> {
+    if insn_len(insn as u16) == 2 {
+        return decode_compressed(insn as u16);
+    }
+    let funct3 = (insn >> 12) & 7;
+    let rs1 = ((insn >> 15) & 31) as u8;
+    match insn & 0x7f {
+        OP_LOAD => {
+            let (len, signed) = match funct3 {
+                0b001 => (2, true),  // lh
+                0b010 => (4, true),  // lw
+                0b101 => (2, false), // lhu
+                _ => return None,
+            };
+            let rd = ((insn >> 7) & 31) as u8;
+            let offset = (insn as i32) >> 20;
+            Some(MemOp { access: Access::Load { rd, len, signed }, rs1, offset, insn_len: 4 })
+        }
+        OP_STORE => {
+            let len = match funct3 {
+                0b001 => 2, // sh
+                0b010 => 4, // sw
+                _ => return None,
+            };
+            let rs2 = ((insn >> 20) & 31) as u8;
+            let offset = ((insn as i32) >> 25) << 5 | ((insn >> 7) & 31) as i32;
+            Some(MemOp { access: Access::Store { rs2, len }, rs1, offset, insn_len: 4 })
+        }
+        _ => None,
+    }
+}
+
+/// `sp`, the base of `c.lwsp` / `c.swsp`.
+const SP: u8 = 2;
+
+fn decode_compressed(insn: u16) -> Option<MemOp> {
+    let insn = insn as u32;
+    let bits = |lo: u32, n: u32| (insn >> lo) & ((1 << n) - 1);
+    // rd' / rs1' / rs2': x8-x15
+    let creg = |lo: u32| 8 + bits(lo, 3) as u8;
+    match (bits(13, 3), insn & 0b11) {
+        // c.lw / c.sw: uimm[5:3] = [12:10], uimm[2] = [6], uimm[6] = [5]
+        (0b010 | 0b110, 0b00) => {
+            let offset = (bits(10, 3) << 3 | bits(6, 1) << 2 | bits(5, 1) << 6) as i32;
+            let access = if bits(15, 1) == 0 {
+                Access::Load { rd: creg(2), len: 4, signed: true }
+            } else {
+                Access::Store { rs2: creg(2), len: 4 }
+            };
+            Some(MemOp { access, rs1: creg(7), offset, insn_len: 2 })
+        }
+        // c.lwsp: uimm[5] = [12], uimm[4:2] = [6:4], uimm[7:6] = [3:2]
+        (0b010, 0b10) => {
+            let rd = bits(7, 5) as u8;
+            if rd == 0 {
+                return None; // reserved
+            }
+            let offset = (bits(12, 1) << 5 | bits(4, 3) << 2 | bits(2, 2) << 6) as i32;
+            Some(MemOp { access: Access::Load { rd, len: 4, signed: true }, rs1: SP, offset, insn_len: 2 })
+        }
+        // c.swsp: uimm[5:2] = [12:9], uimm[7:6] = [8:7]
+        (0b110, 0b10) => {
+            let offset = (bits(9, 4) << 2 | bits(7, 2) << 6) as i32;
+            Some(MemOp { access: Access::Store { rs2: bits(2, 5) as u8, len: 4 }, rs1: SP, offset, insn_len: 2 })
+        }
+        _ => None,
+    }
+}
+
+/// The register value a load of `bytes` (little-endian, 1 to 4 of them)
+/// produces.
+pub fn load_value(bytes: &[u8], signed: bool) -> u32 {
+    let mut word = [0u8; 4];
+    word[..bytes.len()].copy_from_slice(bytes);
+    let value = u32::from_le_bytes(word);
+    let shift = 32 - 8 * bytes.len() as u32;
+    if signed && shift > 0 {
+        ((value << shift) as i32 >> shift) as u32
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(rd: u8, len: usize, signed: bool, rs1: u8, offset: i32, insn_len: usize) -> Option<MemOp> {
+        Some(MemOp { access: Access::Load { rd, len, signed }, rs1, offset, insn_len })
+    }
+
+    fn store(rs2: u8, len: usize, rs1: u8, offset: i32, insn_len: usize) -> Option<MemOp> {
+        Some(MemOp { access: Access::Store { rs2, len }, rs1, offset, insn_len })
+    }
+
+    // Encodings from the assembler.
+
+    #[test]
+    fn decodes_loads() {
+        assert_eq!(decode(0x0015_a503), load(10, 4, true, 11, 1, 4)); // lw a0, 1(a1)
+        assert_eq!(decode(0xffd1_1283), load(5, 2, true, 2, -3, 4)); // lh t0, -3(sp)
+        assert_eq!(decode(0x7ff7_d903), load(18, 2, false, 15, 2047, 4)); // lhu s2, 2047(a5)
+    }
+
+    #[test]
+    fn decodes_stores() {
+        assert_eq!(decode(0xfec4_2fa3), store(12, 4, 8, -1, 4)); // sw a2, -1(s0)
+        assert_eq!(decode(0x01bf_92a3), store(27, 2, 31, 5, 4)); // sh s11, 5(t6)
+    }
+
+    #[test]
+    fn decodes_compressed() {
+        assert_eq!(decode(0x41c8), load(10, 4, true, 11, 4, 2)); // c.lw a0, 4(a1)
+        assert_eq!(decode(0xdcfc), store(15, 4, 9, 124, 2)); // c.sw a5, 124(s1)
+        assert_eq!(decode(0x50fe), load(1, 4, true, 2, 252, 2)); // c.lwsp ra, 252(sp)
+        assert_eq!(decode(0xc26e), store(27, 4, 2, 4, 2)); // c.swsp s11, 4(sp)
+    }
+
+    #[test]
+    fn leaves_the_rest_alone() {
+        assert_eq!(decode(0x00a5_80a3), None); // sb a0, 1(a1): never misaligned
+        assert_eq!(decode(0x0025_2007), None); // flw ft0, 2(a0)
+        assert_eq!(decode(0x00b5_252f), None); // amoadd.w a0, a1, (a0)
+        assert_eq!(decode(0x4002), None); // c.lwsp x0: reserved
+        assert_eq!(decode(0x0000_0073), None); // ecall
+    }
+
+    #[test]
+    fn address_wraps() {
+        let op = decode(0xffd1_1283).unwrap(); // lh t0, -3(sp)
+        assert_eq!(op.addr(0x8005_0001), 0x8004_fffe);
+        assert_eq!(op.addr(1), usize::MAX - 1);
+        assert_eq!(op.size(), 2);
+    }
+
+    #[test]
+    fn loads_extend_by_signedness() {
+        assert_eq!(load_value(&[0x34, 0x82], true), 0xffff_8234);
+        assert_eq!(load_value(&[0x34, 0x82], false), 0x8234);
+        assert_eq!(load_value(&[0x34, 0x72], true), 0x7234);
+        assert_eq!(load_value(&[0x78, 0x56, 0x34, 0x92], true), 0x9234_5678);
+    }
+}
@@ -0,0 +1,87 @@
+//! Monitor-call namespace.
+//!
+//! Ecalls carry their call number in `a7`.  Bit 31 splits the number
+//! space in two:
+//!
+//! ```text
+//!   a7[31] = 0   service call   a7 = service number (putc, quote, …)
+//!   a7[31] = 1   monitor call   a7[30:0] = monitor number
+//!                               a0, a2 = arguments, a1 = capability token
+//! ```
+//!
+//! Monitor calls are privileged maintenance operations (locking a PCR,
+//! provisioning a key).  They have a handler table of their own and are
+//! only carried out when `a1` holds the token the RoT generated at boot
+//! and handed to the firmware it launched, so code that can merely issue
+//! ecalls — replaying or fuzzing service calls — can't reach them.  The
+//! two namespaces can't collide: service `n` and monitor `n` differ in
+//! bit 31.
+
+/// `a7` bit that selects the monitor namespace.
+pub const MONITOR_BIT: u32 = 1 << 31;
+
+/// Monitor call 0: lock PCR `a0` against further extends.
+pub const LOCK_PCR: u32 = 0;
+
+/// Monitor call 1: write the 32-byte key at `a2` into key slot `a0`, if
+/// that slot is still blank and isn't the attestation key's
+/// ([`crate::keyslot`]).
+pub const PROVISION_KEY: u32 = 1;
+
+/// Monitor call 2 (`ecall-audit` builds): print the ecall audit trail
+/// ([`crate::audit`]) and copy its latest `a2` records to `a0`.
+pub const DUMP_AUDIT: u32 = 2;
+
+/// Whether `a7` names a monitor call.
+pub const fn is_monitor(a7: u32) -> bool {
+    a7 & MONITOR_BIT != 0
+}
+
+/// Monitor number of `a7`, or `None` for a service call.
+pub const fn monitor_number(a7: u32) -> Option<u32> {
+    if is_monitor(a7) {
+        Some(a7 & !MONITOR_BIT)
+    } else {
+        None
+    }
+}
+
+/// `a7` for monitor call `number`.
+pub const fn monitor_call(number: u32) -> u32 {
+    assert!(number & MONITOR_BIT == 0, "monitor number overlaps MONITOR_BIT");
+    number | MONITOR_BIT
+}
+
+/// Whether `token` matches the boot-time `expected` value.  A zero
+/// `expected` (no token generated yet) matches nothing.  Constant time,
+/// so a caller can't recover the token by timing refusals.
+pub fn token_matches(token: u32, expected: u32) -> bool {
+    expected != 0 && crate::hmac::ct_eq(&token.to_le_bytes(), &expected.to_le_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn namespaces_are_disjoint() {
+        for n in [0, 1, 11, 0x7fff_ffff] {
+            assert!(!is_monitor(n));
+            assert_eq!(monitor_number(n), None);
+            let a7 = monitor_call(n);
+            assert_ne!(a7, n);
+            assert!(is_monitor(a7));
+            assert_eq!(monitor_number(a7), Some(n));
+        }
+        assert_eq!(monitor_call(LOCK_PCR), 0x8000_0000);
+        assert_eq!(monitor_call(PROVISION_KEY), 0x8000_0001);
+        assert_eq!(monitor_call(DUMP_AUDIT), 0x8000_0002);
+    }
+
+    #[test]
+    fn token_check() {
+        assert!(token_matches(0x1234_5678, 0x1234_5678));
+        assert!(!token_matches(0x1234_5679, 0x1234_5678));
+        assert!(!token_matches(0, 0));
+    }
+}
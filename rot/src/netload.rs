@@ -0,0 +1,315 @@
+//! `net-load` transfer protocol: U-mode firmware over the authenticated UART.
+//!
+//! Instead of running the firmware linked into U_CODE, a `net-load` RoT
+//! receives a U-mode ELF from the host at boot, before anything is
+//! measured:
+//!
+//! ```text
+//!   host → RoT   16 bytes  nonce: HKDF salt for the session (see frame)
+//!   host → RoT   frame     request: "LD" | size u32 BE | SHA-256(file)
+//!   host → RoT   frames    the ELF file, in order, until `size` bytes
+//!   RoT → host   frame     status: one byte, LoadError::status (0 = loaded)
+//! ```
+//!
+//! Every frame is authenticated under the session key, so only a holder
+//! of the device secret can hand the RoT firmware, and the whole file must
+//! then match the digest the request announced.  The loaded image
+//! still has to pass the firmware-header check and is measured like the
+//! linked one.
+
+use core::fmt;
+
+use crate::elf::{ElfError, Image, Region, HEAD_LEN};
+use crate::frame::FrameError;
+use crate::hmac::ct_eq;
+use crate::sha256::{Sha256, DIGEST_LEN};
+
+/// First two bytes of a load request.
+pub const REQUEST_MAGIC: [u8; 2] = *b"LD";
+
+/// Length of a load request payload.
+pub const REQUEST_LEN: usize = 2 + 4 + DIGEST_LEN;
+
+/// Largest file the RoT will accept: the U-mode regions together, plus
+/// room for headers.
+pub const MAX_FILE_LEN: u32 = 256 * 1024;
+
+/// A parsed load request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LoadRequest {
+    pub size: u32,
+    pub digest: [u8; DIGEST_LEN],
+}
+
+impl LoadRequest {
+    pub fn parse(payload: &[u8]) -> Option<LoadRequest> {
+        if payload.len() != REQUEST_LEN || payload[..2] != REQUEST_MAGIC {
+            return None;
+        }
+        let size = u32::from_be_bytes([payload[2], payload[3], payload[4], payload[5]]);
+        let mut digest = [0; DIGEST_LEN];
+        digest.copy_from_slice(&payload[6..]);
+        Some(LoadRequest { size, digest })
+    }
+}
+
+/// Why a load failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadError {
+    /// A frame didn't arrive intact (see [`FrameError`]).
+    Frame(FrameError),
+    /// The first frame isn't a well-formed request, or asks for more than
+    /// [`MAX_FILE_LEN`] bytes.
+    BadRequest,
+    /// The file was refused by the ELF checks.
+    Elf(ElfError),
+    /// More bytes than the request announced.
+    Overrun,
+    /// The file doesn't hash to the announced digest.
+    DigestMismatch,
+}
+
+impl LoadError {
+    /// The status byte sent back to the host.
+    pub const fn status(&self) -> u8 {
+        match self {
+            LoadError::Frame(_) => 1,
+            LoadError::BadRequest => 2,
+            LoadError::Elf(_) => 3,
+            LoadError::Overrun => 4,
+            LoadError::DigestMismatch => 5,
+        }
+    }
+}
+
+impl From<FrameError> for LoadError {
+    fn from(e: FrameError) -> Self {
+        LoadError::Frame(e)
+    }
+}
+
+impl From<ElfError> for LoadError {
+    fn from(e: ElfError) -> Self {
+        LoadError::Elf(e)
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Frame(e) => write!(f, "frame error ({:?})", e),
+            LoadError::BadRequest => f.write_str("bad load request"),
+            LoadError::Elf(e) => write!(f, "ELF refused: {}", e),
+            LoadError::Overrun => f.write_str("more data than announced"),
+            LoadError::DigestMismatch => f.write_str("file digest mismatch"),
+        }
+    }
+}
+
+/// Streams a file into memory as its frames arrive.
+///
+/// The first [`HEAD_LEN`] bytes (or the whole file, if shorter) are held
+/// back until both header tables can be parsed and every segment placed;
+/// from then on each chunk is written straight to its load address.
+pub struct Loader<'r> {
+    request: LoadRequest,
+    regions: &'r [Region],
+    head: [u8; HEAD_LEN],
+    image: Option<Image>,
+    received: u32,
+    hash: Sha256,
+}
+
+impl<'r> Loader<'r> {
+    /// A loader for `request`, placing segments only inside `regions`.
+    pub fn new(request: LoadRequest, regions: &'r [Region]) -> Result<Loader<'r>, LoadError> {
+        if request.size > MAX_FILE_LEN {
+            return Err(LoadError::BadRequest);
+        }
+        Ok(Loader { request, regions, head: [0; HEAD_LEN], image: None, received: 0, hash: Sha256::new() })
+    }
+
+    /// All announced bytes have arrived.
+    pub fn is_complete(&self) -> bool {
+        self.received == self.request.size
+    }
+
+    /// Take the next `chunk` of the file.  `write(addr, bytes)` stores
+    /// segment contents; it is only called once the headers are checked.
+    pub fn feed(&mut self, chunk: &[u8], mut write: impl FnMut(u32, &[u8])) -> Result<(), LoadError> {
+        if chunk.len() as u64 > (self.request.size - self.received) as u64 {
+            return Err(LoadError::Overrun);
+        }
+        self.hash.update(chunk);
+        let offset = self.received;
+        self.received += chunk.len() as u32;
+
+        if let Some(image) = &self.image {
+            image.copy(offset, chunk, write);
+            return Ok(());
+        }
+        let head_len = HEAD_LEN.min(self.request.size as usize);
+        let start = offset as usize;
+        let take = chunk.len().min(head_len.saturating_sub(start));
+        if take > 0 {
+            self.head[start..start + take].copy_from_slice(&chunk[..take]);
+        }
+        // Past the head with no image: the headers were refused.  Checking
+        // them again refuses them again, so every later chunk fails too.
+        if start + take >= head_len {
+            let head = &self.head[..head_len];
+            let image = Image::parse(head)?;
+            image.place(self.regions)?;
+            if image.file_len() > self.request.size {
+                return Err(ElfError::Truncated.into());
+            }
+            image.copy(0, head, &mut write);
+            image.copy((start + take) as u32, &chunk[take..], &mut write);
+            self.image = Some(image);
+        }
+        Ok(())
+    }
+
+    /// Check the whole file arrived and matches the request's digest, and
+    /// return the entry point.
+    pub fn finish(self) -> Result<u32, LoadError> {
+        let image = match self.image {
+            Some(image) if self.received == self.request.size => image,
+            _ => return Err(ElfError::Truncated.into()),
+        };
+        if !ct_eq(&self.hash.finalize(), &self.request.digest) {
+            return Err(LoadError::DigestMismatch);
+        }
+        Ok(image.entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elf::tests::{elf, text, CODE, RAM};
+    use crate::elf::{Segment, PF_R, PF_W};
+    use crate::sha256::sha256;
+    use crate::test_util::Rng;
+    use std::vec::Vec;
+
+    fn request(file: &[u8]) -> LoadRequest {
+        let mut p = Vec::from(REQUEST_MAGIC);
+        p.extend_from_slice(&(file.len() as u32).to_be_bytes());
+        p.extend_from_slice(&sha256(file));
+        LoadRequest::parse(&p).unwrap()
+    }
+
+    /// Feed `file` in `chunk`-byte pieces; the memory it writes, as
+    /// (address, bytes) runs, and the result.
+    fn load(file: &[u8], req: LoadRequest, chunk: usize) -> (Vec<(u32, Vec<u8>)>, Result<u32, LoadError>) {
+        let regions = [CODE, RAM];
+        let mut mem = Vec::new();
+        let mut loader = Loader::new(req, &regions).unwrap();
+        for c in file.chunks(chunk) {
+            if let Err(e) = loader.feed(c, |a, b| mem.push((a, b.to_vec()))) {
+                return (mem, Err(e));
+            }
+        }
+        (mem, loader.finish())
+    }
+
+    fn flatten(runs: &[(u32, Vec<u8>)]) -> Vec<(u32, u8)> {
+        runs.iter().flat_map(|(a, b)| b.iter().enumerate().map(move |(i, &x)| (a + i as u32, x))).collect()
+    }
+
+    #[test]
+    fn request_format() {
+        let mut p = [0u8; REQUEST_LEN];
+        p[..2].copy_from_slice(b"LD");
+        p[2..6].copy_from_slice(&0x1234u32.to_be_bytes());
+        p[6..].fill(0xAB);
+        assert_eq!(LoadRequest::parse(&p), Some(LoadRequest { size: 0x1234, digest: [0xAB; 32] }));
+        assert_eq!(LoadRequest::parse(&p[..REQUEST_LEN - 1]), None);
+        p[0] = b'X';
+        assert_eq!(LoadRequest::parse(&p), None);
+        let big = LoadRequest { size: MAX_FILE_LEN + 1, digest: [0; 32] };
+        assert!(matches!(Loader::new(big, &[]), Err(LoadError::BadRequest)));
+    }
+
+    #[test]
+    fn loads_the_same_whatever_the_chunking() {
+        let data = Segment { offset: 0x400, vaddr: RAM.start, filesz: 0x300, memsz: 0x400, flags: PF_R | PF_W };
+        let file = elf(CODE.start + 4, &[text(0x100, 0x300), data]);
+        let (whole, entry) = load(&file, request(&file), file.len());
+        assert_eq!(entry, Ok(CODE.start + 4));
+        for chunk in [1, 7, 64, HEAD_LEN - 1, HEAD_LEN, 1000] {
+            let (runs, r) = load(&file, request(&file), chunk);
+            assert_eq!(r, Ok(CODE.start + 4));
+            assert_eq!(flatten(&runs), flatten(&whole), "chunk {}", chunk);
+        }
+        let mem = flatten(&whole);
+        assert_eq!(mem.len(), 0x600);
+        assert!(mem[..0x300].iter().enumerate().all(|(i, &m)| m == (CODE.start + i as u32, 1)));
+        assert!(mem[0x300..].iter().enumerate().all(|(i, &m)| m == (RAM.start + i as u32, 2)));
+    }
+
+    #[test]
+    fn refuses_bad_transfers() {
+        let file = elf(CODE.start, &[text(0x100, 0x300)]);
+        let mut req = request(&file);
+        req.digest[0] ^= 1;
+        assert_eq!(load(&file, req, 256).1, Err(LoadError::DigestMismatch));
+
+        // More bytes than announced overrun; too few never complete, or
+        // can't even hold the segments.
+        let mut padded = file.clone();
+        padded.extend_from_slice(&[0; 8]);
+        assert_eq!(load(&padded, request(&file), 256).1, Err(LoadError::Overrun));
+        let req = LoadRequest { size: file.len() as u32 + 1, ..request(&file) };
+        assert_eq!(load(&file, req, 256).1, Err(LoadError::Elf(ElfError::Truncated)));
+        let req = LoadRequest { size: file.len() as u32 - 1, ..request(&file) };
+        assert_eq!(load(&file, req, 256).1, Err(LoadError::Elf(ElfError::Truncated)));
+
+        // Nothing is written for an image that doesn't place.
+        let file = elf(0x8001_0000, &[Segment { vaddr: 0x8001_0000, ..text(0x100, 0x300) }]);
+        let (mem, r) = load(&file, request(&file), 64);
+        assert_eq!(r, Err(LoadError::Elf(ElfError::Unplaced(0x8001_0000))));
+        assert!(mem.is_empty());
+
+        // A caller that carries on after the refusal keeps getting it.
+        let regions = [CODE, RAM];
+        let mut loader = Loader::new(request(&file), &regions).unwrap();
+        let (head, rest) = file.split_at(HEAD_LEN);
+        assert!(loader.feed(head, |_, _| {}).is_err());
+        let again = loader.feed(&rest[..8], |_, _| panic!("write after a refusal"));
+        assert_eq!(again, Err(LoadError::Elf(ElfError::Unplaced(0x8001_0000))));
+    }
+
+    /// Corrupted files, wrong announced sizes and random chunking never
+    /// panic the loader or make it write outside its regions, even for a
+    /// caller that keeps feeding after an error.
+    #[test]
+    fn fuzz_feed_never_panics() {
+        let mut rng = Rng(0x9e37_79b9);
+        let data = Segment { offset: 0x400, vaddr: RAM.start, filesz: 0x100, memsz: 0x200, flags: PF_R | PF_W };
+        let valid = elf(CODE.start, &[text(0x100, 0x300), data]);
+        let regions = [CODE, RAM];
+        for _ in 0..5_000 {
+            let mut file = valid.clone();
+            rng.mutate(&mut file);
+            let size = match rng.below(4) {
+                0 => rng.below(2 * file.len()) as u32,
+                _ => file.len() as u32,
+            };
+            let Ok(mut loader) = Loader::new(LoadRequest { size, digest: sha256(&file) }, &regions) else {
+                continue;
+            };
+            let mut rest = &file[..];
+            while !rest.is_empty() {
+                let (chunk, tail) = rest.split_at(1 + rng.below(rest.len().min(HEAD_LEN + 100)));
+                rest = tail;
+                let _ = loader.feed(chunk, |addr, b| {
+                    let inside = |r: &Region| addr >= r.start && (addr - r.start) as u64 + b.len() as u64 <= r.len as u64;
+                    assert!(regions.iter().any(inside), "write {:#x}+{} outside every region", addr, b.len());
+                });
+            }
+            let _ = loader.finish();
+        }
+    }
+}
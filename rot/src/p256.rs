@@ -0,0 +1,624 @@
+//! ECDSA over NIST P-256 (FIPS 186-4) with RFC 6979 deterministic nonces.
+//!
+//! Lets the RoT sign attestation quotes with a device private key, so a
+//! verifier only needs the matching public key instead of a shared secret.
+//!
+//! Arithmetic is on eight 32-bit limbs (least-significant first) in
+//! Montgomery form, for both the field (mod p) and the scalars (mod n).
+//! Points use projective coordinates with the Renes–Costello–Batina
+//! complete addition formulas for `a = -3`, so there are no special cases
+//! for doubling or the identity.  Scalar multiplication is a fixed
+//! 256-step double-and-add-always with a constant-time select, and the
+//! nonce comes from HMAC-DRBG (RFC 6979) rather than an RNG.
+
+use crate::erase::{secure_zero, secure_zero_words};
+use crate::hmac::{hmac_sha256, HmacSha256};
+use crate::sha256::{sha256, DIGEST_LEN};
+
+/// Private key / scalar length in bytes.
+pub const SCALAR_LEN: usize = 32;
+
+/// Signature length in bytes: `r || s`, each big-endian.
+pub const SIGNATURE_LEN: usize = 64;
+
+/// SEC1 uncompressed public key length: `0x04 || x || y`.
+pub const PUBLIC_KEY_LEN: usize = 65;
+
+/// 256-bit integer, least-significant limb first.
+type U256 = [u32; 8];
+
+const ZERO: U256 = [0; 8];
+const ONE: U256 = [1, 0, 0, 0, 0, 0, 0, 0];
+
+// ── Curve parameters (FIPS 186-4 D.1.2.3) ───────────────────────────
+
+const P: U256 = hex256("ffffffff00000001000000000000000000000000ffffffffffffffffffffffff");
+const N: U256 = hex256("ffffffff00000000ffffffffffffffffbce6faada7179e84f3b9cac2fc632551");
+const B: U256 = hex256("5ac635d8aa3a93e7b3ebbd55769886bc651d06b0cc53b0f63bce3c3e27d2604b");
+const GX: U256 = hex256("6b17d1f2e12c4247f8bce6e563a440f277037d812deb33a0f4a13945d898c296");
+const GY: U256 = hex256("4fe342e2fe1a7f9b8ee7eb4a7c0f9e162bce33576b315ececbb6406837bf51f5");
+
+/// Field arithmetic, mod p.  p ≡ -1 mod 2^32, so -p⁻¹ mod 2^32 = 1.
+const FP: Modulus = Modulus::new(P, 0x0000_0001);
+
+/// Scalar arithmetic, mod n.
+const FN: Modulus = Modulus::new(N, 0xee00_bc4f);
+
+/// `b` and the generator, in Montgomery form.
+const B_M: U256 = FP.to_mont(&B);
+const G: Point = Point { x: FP.to_mont(&GX), y: FP.to_mont(&GY), z: FP.one };
+
+// ── Limb helpers ────────────────────────────────────────────────────
+
+/// Big-endian bytes to limbs.
+const fn u256(b: [u8; 32]) -> U256 {
+    let mut r = ZERO;
+    let mut i = 0;
+    while i < 8 {
+        let o = 28 - 4 * i;
+        r[i] = u32::from_be_bytes([b[o], b[o + 1], b[o + 2], b[o + 3]]);
+        i += 1;
+    }
+    r
+}
+
+/// 64 hex digits (big-endian) to limbs, for the curve constants.
+const fn hex256(s: &str) -> U256 {
+    let s = s.as_bytes();
+    assert!(s.len() == 64);
+    let mut b = [0u8; 32];
+    let mut i = 0;
+    while i < 64 {
+        let c = s[i];
+        let v = match c {
+            b'0'..=b'9' => c - b'0',
+            b'a'..=b'f' => c - b'a' + 10,
+            _ => panic!("bad hex digit"),
+        };
+        b[i / 2] |= v << (4 * (1 - i % 2));
+        i += 1;
+    }
+    u256(b)
+}
+
+/// Limbs to big-endian bytes.
+fn to_bytes(a: &U256) -> [u8; 32] {
+    let mut b = [0u8; 32];
+    for (chunk, limb) in b.as_chunks_mut::<4>().0.iter_mut().zip(a.iter().rev()) {
+        *chunk = limb.to_be_bytes();
+    }
+    b
+}
+
+/// `a + b`, returning the carry (0 or 1).
+const fn adc(a: &U256, b: &U256) -> (U256, u32) {
+    let mut r = ZERO;
+    let mut carry = 0u64;
+    let mut i = 0;
+    while i < 8 {
+        let s = a[i] as u64 + b[i] as u64 + carry;
+        r[i] = s as u32;
+        carry = s >> 32;
+        i += 1;
+    }
+    (r, carry as u32)
+}
+
+/// `a - b`, returning the borrow (0 or 1).
+const fn sbb(a: &U256, b: &U256) -> (U256, u32) {
+    let mut r = ZERO;
+    let mut borrow = 0u64;
+    let mut i = 0;
+    while i < 8 {
+        let d = (a[i] as u64).wrapping_sub(b[i] as u64).wrapping_sub(borrow);
+        r[i] = d as u32;
+        borrow = d >> 63;
+        i += 1;
+    }
+    (r, borrow as u32)
+}
+
+/// All-ones if `bit` is 1, zero if it is 0.
+const fn mask(bit: u32) -> u32 {
+    0u32.wrapping_sub(bit)
+}
+
+/// `a` where `m` is all-ones, `b` where it is zero — without branching.
+const fn select(m: u32, a: &U256, b: &U256) -> U256 {
+    let mut r = ZERO;
+    let mut i = 0;
+    while i < 8 {
+        r[i] = (a[i] & m) | (b[i] & !m);
+        i += 1;
+    }
+    r
+}
+
+fn is_zero(a: &U256) -> bool {
+    a.iter().fold(0, |acc, l| acc | l) == 0
+}
+
+/// `a < b`.
+fn lt(a: &U256, b: &U256) -> bool {
+    sbb(a, b).1 == 1
+}
+
+// ── Modular arithmetic (Montgomery, R = 2^256) ──────────────────────
+
+struct Modulus {
+    m: U256,
+    /// -m⁻¹ mod 2^32.
+    m_inv: u32,
+    /// R² mod m, for conversion into Montgomery form.
+    r2: U256,
+    /// R mod m: 1 in Montgomery form.
+    one: U256,
+}
+
+impl Modulus {
+    /// `m` must be odd and above 2^255 (true of both p and n).
+    const fn new(m: U256, m_inv: u32) -> Modulus {
+        // R mod m = 2^256 - m, since m < 2^256 < 2m.
+        let one = sbb(&ZERO, &m).0;
+        // R² mod m by doubling R mod m another 256 times.
+        let mut r2 = one;
+        let mut i = 0;
+        while i < 256 {
+            let (d, carry) = adc(&r2, &r2);
+            let (s, borrow) = sbb(&d, &m);
+            r2 = select(mask(carry | (borrow ^ 1)), &s, &d);
+            i += 1;
+        }
+        Modulus { m, m_inv, r2, one }
+    }
+
+    /// Reduce an integer below 2^256 into `0..m`.
+    fn reduce(&self, a: &U256) -> U256 {
+        let (s, borrow) = sbb(a, &self.m);
+        select(mask(borrow ^ 1), &s, a)
+    }
+
+    fn add(&self, a: &U256, b: &U256) -> U256 {
+        let (s, carry) = adc(a, b);
+        let (d, borrow) = sbb(&s, &self.m);
+        select(mask(carry | (borrow ^ 1)), &d, &s)
+    }
+
+    fn sub(&self, a: &U256, b: &U256) -> U256 {
+        let (d, borrow) = sbb(a, b);
+        let (s, _) = adc(&d, &self.m);
+        select(mask(borrow), &s, &d)
+    }
+
+    /// Montgomery product `a · b · R⁻¹ mod m` (CIOS).
+    const fn mul(&self, a: &U256, b: &U256) -> U256 {
+        let m = &self.m;
+        let mut t = [0u32; 10];
+        let mut i = 0;
+        while i < 8 {
+            let mut c = 0u64;
+            let mut j = 0;
+            while j < 8 {
+                let s = t[j] as u64 + a[j] as u64 * b[i] as u64 + c;
+                t[j] = s as u32;
+                c = s >> 32;
+                j += 1;
+            }
+            let s = t[8] as u64 + c;
+            t[8] = s as u32;
+            t[9] = (s >> 32) as u32;
+
+            let q = t[0].wrapping_mul(self.m_inv);
+            let s = t[0] as u64 + q as u64 * m[0] as u64;
+            let mut c = s >> 32;
+            let mut j = 1;
+            while j < 8 {
+                let s = t[j] as u64 + q as u64 * m[j] as u64 + c;
+                t[j - 1] = s as u32;
+                c = s >> 32;
+                j += 1;
+            }
+            let s = t[8] as u64 + c;
+            t[7] = s as u32;
+            t[8] = t[9] + (s >> 32) as u32;
+            i += 1;
+        }
+
+        let r = [t[0], t[1], t[2], t[3], t[4], t[5], t[6], t[7]];
+        let (d, borrow) = sbb(&r, m);
+        select(mask(t[8] | (borrow ^ 1)), &d, &r)
+    }
+
+    const fn to_mont(&self, a: &U256) -> U256 {
+        self.mul(a, &self.r2)
+    }
+
+    /// Montgomery reduction: leave Montgomery form (`a · R⁻¹ mod m`).
+    fn redc(&self, a: &U256) -> U256 {
+        self.mul(a, &ONE)
+    }
+
+    /// `a⁻¹` by Fermat (`a^(m-2)`), Montgomery form in and out.  The
+    /// exponent is public, so the square-and-multiply may branch on it.
+    fn inv(&self, a: &U256) -> U256 {
+        let e = sbb(&self.m, &[2, 0, 0, 0, 0, 0, 0, 0]).0;
+        let mut r = self.one;
+        for i in (0..256).rev() {
+            r = self.mul(&r, &r);
+            if (e[i / 32] >> (i % 32)) & 1 == 1 {
+                r = self.mul(&r, a);
+            }
+        }
+        r
+    }
+}
+
+// ── Curve points ────────────────────────────────────────────────────
+
+/// Projective point `(X : Y : Z)`, coordinates in Montgomery form.
+#[derive(Clone, Copy)]
+struct Point {
+    x: U256,
+    y: U256,
+    z: U256,
+}
+
+const IDENTITY: Point = Point { x: ZERO, y: FP.one, z: ZERO };
+
+impl Point {
+    /// Complete addition (RCB 2015, algorithm 4 for `a = -3`); also
+    /// correct for `P + P` and when either operand is the identity.
+    fn add(&self, q: &Point) -> Point {
+        let f = &FP;
+        let xx = f.mul(&self.x, &q.x);
+        let yy = f.mul(&self.y, &q.y);
+        let zz = f.mul(&self.z, &q.z);
+        let xy = f.sub(
+            &f.mul(&f.add(&self.x, &self.y), &f.add(&q.x, &q.y)),
+            &f.add(&xx, &yy),
+        );
+        let yz = f.sub(
+            &f.mul(&f.add(&self.y, &self.z), &f.add(&q.y, &q.z)),
+            &f.add(&yy, &zz),
+        );
+        let xz = f.sub(
+            &f.mul(&f.add(&self.x, &self.z), &f.add(&q.x, &q.z)),
+            &f.add(&xx, &zz),
+        );
+
+        let bzz = f.sub(&xz, &f.mul(&B_M, &zz));
+        let bzz3 = f.add(&f.add(&bzz, &bzz), &bzz);
+        let yy_m_bzz3 = f.sub(&yy, &bzz3);
+        let yy_p_bzz3 = f.add(&yy, &bzz3);
+
+        let zz3 = f.add(&f.add(&zz, &zz), &zz);
+        let bxz = f.sub(&f.mul(&B_M, &xz), &f.add(&zz3, &xx));
+        let bxz3 = f.add(&f.add(&bxz, &bxz), &bxz);
+        let xx3_m_zz3 = f.sub(&f.add(&f.add(&xx, &xx), &xx), &zz3);
+
+        Point {
+            x: f.sub(&f.mul(&yy_p_bzz3, &xy), &f.mul(&yz, &bxz3)),
+            y: f.add(&f.mul(&yy_p_bzz3, &yy_m_bzz3), &f.mul(&xx3_m_zz3, &bxz3)),
+            z: f.add(&f.mul(&yy_m_bzz3, &yz), &f.mul(&xy, &xx3_m_zz3)),
+        }
+    }
+
+    fn select(m: u32, a: &Point, b: &Point) -> Point {
+        Point {
+            x: select(m, &a.x, &b.x),
+            y: select(m, &a.y, &b.y),
+            z: select(m, &a.z, &b.z),
+        }
+    }
+
+    /// `k · self`: one doubling and one addition per bit, whatever the bit.
+    fn mul(&self, k: &U256) -> Point {
+        let mut r = IDENTITY;
+        for i in (0..256).rev() {
+            r = r.add(&r);
+            let sum = r.add(self);
+            r = Point::select(mask((k[i / 32] >> (i % 32)) & 1), &sum, &r);
+        }
+        r
+    }
+
+    /// Affine `(x, y)` in normal form, or `None` for the identity.
+    fn to_affine(self) -> Option<(U256, U256)> {
+        if is_zero(&self.z) {
+            return None;
+        }
+        let zi = FP.inv(&self.z);
+        Some((
+            FP.redc(&FP.mul(&self.x, &zi)),
+            FP.redc(&FP.mul(&self.y, &zi)),
+        ))
+    }
+}
+
+// ── RFC 6979 nonce generation (HMAC-DRBG, SHA-256) ──────────────────
+
+struct Rfc6979 {
+    k: [u8; DIGEST_LEN],
+    v: [u8; DIGEST_LEN],
+}
+
+impl Rfc6979 {
+    /// Section 3.2 steps b–g.  `x` is the private key and `h` the hash
+    /// already reduced mod n (`bits2octets`).
+    fn new(x: &[u8; SCALAR_LEN], h: &[u8; SCALAR_LEN]) -> Rfc6979 {
+        let mut d = Rfc6979 { k: [0x00; DIGEST_LEN], v: [0x01; DIGEST_LEN] };
+        for sep in [0x00u8, 0x01] {
+            let mut mac = HmacSha256::new(&d.k);
+            mac.update(&d.v);
+            mac.update(&[sep]);
+            mac.update(x);
+            mac.update(h);
+            d.k = mac.finalize();
+            d.v = hmac_sha256(&d.k, &d.v);
+        }
+        d
+    }
+
+    /// Step h: the next candidate `k` in `1..n`.  The DRBG is stepped past
+    /// each candidate, so calling again (e.g. because `r` or `s` came out
+    /// zero) continues the RFC's retry sequence.
+    fn next_k(&mut self) -> U256 {
+        loop {
+            self.v = hmac_sha256(&self.k, &self.v);
+            let k = u256(self.v);
+
+            let mut mac = HmacSha256::new(&self.k);
+            mac.update(&self.v);
+            mac.update(&[0x00]);
+            self.k = mac.finalize();
+            self.v = hmac_sha256(&self.k, &self.v);
+
+            if !is_zero(&k) && lt(&k, &N) {
+                return k;
+            }
+        }
+    }
+}
+
+impl Drop for Rfc6979 {
+    /// Its state determines every nonce it would produce next.
+    fn drop(&mut self) {
+        secure_zero(&mut self.k);
+        secure_zero(&mut self.v);
+    }
+}
+
+// ── Keys and signatures ─────────────────────────────────────────────
+
+/// ECDSA P-256 private key.
+pub struct SigningKey {
+    d: U256,
+}
+
+/// ECDSA P-256 public key (an affine point known to be on the curve).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PublicKey {
+    x: U256,
+    y: U256,
+}
+
+impl SigningKey {
+    /// Private scalar from big-endian bytes.  `None` unless `0 < d < n`.
+    pub fn from_bytes(bytes: &[u8; SCALAR_LEN]) -> Option<SigningKey> {
+        let d = u256(*bytes);
+        if is_zero(&d) || !lt(&d, &N) {
+            return None;
+        }
+        Some(SigningKey { d })
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        let (x, y) = G.mul(&self.d).to_affine().expect("0 < d < n");
+        PublicKey { x, y }
+    }
+
+    /// Sign `message` (hashed with SHA-256).
+    pub fn sign(&self, message: &[u8]) -> [u8; SIGNATURE_LEN] {
+        self.sign_prehash(&sha256(message))
+    }
+
+    /// Sign a SHA-256 digest, returning `r || s`.
+    pub fn sign_prehash(&self, hash: &[u8; DIGEST_LEN]) -> [u8; SIGNATURE_LEN] {
+        let e = FN.reduce(&u256(*hash));
+        let mut d = to_bytes(&self.d);
+        let mut drbg = Rfc6979::new(&d, &to_bytes(&e));
+        secure_zero(&mut d);
+        loop {
+            let mut k = drbg.next_k();
+            let (x, _) = G.mul(&k).to_affine().expect("0 < k < n");
+            let r = FN.reduce(&x);
+            if is_zero(&r) {
+                secure_zero_words(&mut k);
+                continue;
+            }
+
+            // s = k⁻¹ · (e + r·d) mod n
+            let rd = FN.mul(&FN.to_mont(&r), &FN.to_mont(&self.d));
+            let sum = FN.add(&rd, &FN.to_mont(&e));
+            let s = FN.redc(&FN.mul(&FN.inv(&FN.to_mont(&k)), &sum));
+            secure_zero_words(&mut k);
+            if is_zero(&s) {
+                continue;
+            }
+
+            let mut sig = [0u8; SIGNATURE_LEN];
+            sig[..32].copy_from_slice(&to_bytes(&r));
+            sig[32..].copy_from_slice(&to_bytes(&s));
+            return sig;
+        }
+    }
+}
+
+impl Drop for SigningKey {
+    fn drop(&mut self) {
+        secure_zero_words(&mut self.d);
+    }
+}
+
+impl PublicKey {
+    /// Parse a SEC1 uncompressed point, rejecting anything off the curve.
+    pub fn from_sec1(bytes: &[u8; PUBLIC_KEY_LEN]) -> Option<PublicKey> {
+        if bytes[0] != 0x04 {
+            return None;
+        }
+        let (xb, yb) = bytes[1..].split_at(32);
+        let x = u256(xb.try_into().ok()?);
+        let y = u256(yb.try_into().ok()?);
+        if !lt(&x, &P) || !lt(&y, &P) {
+            return None;
+        }
+
+        // y² = x³ - 3x + b
+        let (xm, ym) = (FP.to_mont(&x), FP.to_mont(&y));
+        let x3 = FP.mul(&FP.mul(&xm, &xm), &xm);
+        let three_x = FP.add(&FP.add(&xm, &xm), &xm);
+        let rhs = FP.add(&FP.sub(&x3, &three_x), &B_M);
+        if FP.mul(&ym, &ym) != rhs {
+            return None;
+        }
+        Some(PublicKey { x, y })
+    }
+
+    /// SEC1 uncompressed encoding: `0x04 || x || y`.
+    pub fn to_sec1(&self) -> [u8; PUBLIC_KEY_LEN] {
+        let mut out = [0u8; PUBLIC_KEY_LEN];
+        out[0] = 0x04;
+        out[1..33].copy_from_slice(&to_bytes(&self.x));
+        out[33..].copy_from_slice(&to_bytes(&self.y));
+        out
+    }
+
+    /// Verify `r || s` over `message` (hashed with SHA-256).
+    pub fn verify(&self, message: &[u8], sig: &[u8; SIGNATURE_LEN]) -> bool {
+        self.verify_prehash(&sha256(message), sig)
+    }
+
+    /// Verify `r || s` over a SHA-256 digest.
+    pub fn verify_prehash(&self, hash: &[u8; DIGEST_LEN], sig: &[u8; SIGNATURE_LEN]) -> bool {
+        let (rb, sb) = sig.split_at(32);
+        let r = u256(rb.try_into().unwrap());
+        let s = u256(sb.try_into().unwrap());
+        if is_zero(&r) || !lt(&r, &N) || is_zero(&s) || !lt(&s, &N) {
+            return false;
+        }
+
+        let e = FN.reduce(&u256(*hash));
+        let w = FN.inv(&FN.to_mont(&s));
+        let u1 = FN.redc(&FN.mul(&FN.to_mont(&e), &w));
+        let u2 = FN.redc(&FN.mul(&FN.to_mont(&r), &w));
+
+        let q = Point { x: FP.to_mont(&self.x), y: FP.to_mont(&self.y), z: FP.one };
+        match G.mul(&u1).add(&q.mul(&u2)).to_affine() {
+            Some((x, _)) => FN.reduce(&x) == r,
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::hex;
+
+    /// RFC 6979 A.2.5 key pair.
+    const D: &str = "c9afa9d845ba75166b5c215767b1d6934e50c3db36e89b127b8a622b120f6721";
+    const UX: &str = "60fed4ba255a9d31c961eb74c6356d68c049b8923b61fa6ce669622e60f29fb6";
+    const UY: &str = "7903fe1008b8bc99a41ae9e95628bc64f2f1b20c2d7e9f5177a3c294d4462299";
+
+    fn key() -> SigningKey {
+        SigningKey::from_bytes(&hex::<32>(D)).unwrap()
+    }
+
+    fn sig(r: &str, s: &str) -> [u8; SIGNATURE_LEN] {
+        let mut out = [0u8; SIGNATURE_LEN];
+        out[..32].copy_from_slice(&hex::<32>(r));
+        out[32..].copy_from_slice(&hex::<32>(s));
+        out
+    }
+
+    #[test]
+    fn generator_is_on_curve() {
+        let one = SigningKey::from_bytes(&to_bytes(&ONE)).unwrap();
+        let pk = one.public_key();
+        assert_eq!((pk.x, pk.y), (GX, GY));
+        assert_eq!(PublicKey::from_sec1(&pk.to_sec1()), Some(pk));
+    }
+
+    #[test]
+    fn rfc6979_public_key() {
+        let mut sec1 = [0u8; PUBLIC_KEY_LEN];
+        sec1[0] = 0x04;
+        sec1[1..33].copy_from_slice(&hex::<32>(UX));
+        sec1[33..].copy_from_slice(&hex::<32>(UY));
+        assert_eq!(key().public_key().to_sec1(), sec1);
+    }
+
+    #[test]
+    fn rfc6979_nonce() {
+        let h = FN.reduce(&u256(sha256(b"sample")));
+        let mut drbg = Rfc6979::new(&hex::<32>(D), &to_bytes(&h));
+        assert_eq!(
+            to_bytes(&drbg.next_k()),
+            hex::<32>("a6e3c57dd01abe90086538398355dd4c3b17aa873382b0f24d6129493d8aad60")
+        );
+    }
+
+    #[test]
+    fn rfc6979_sha256_signatures() {
+        let key = key();
+        assert_eq!(
+            key.sign(b"sample"),
+            sig(
+                "efd48b2aacb6a8fd1140dd9cd45e81d69d2c877b56aaf991c34d0ea84eaf3716",
+                "f7cb1c942d657c41d436c7a1b6e29f65f3e900dbb9aff4064dc4ab2f843acda8"
+            )
+        );
+        assert_eq!(
+            key.sign(b"test"),
+            sig(
+                "f1abb023518351cd71d881567b1ea663ed3efcf6c5132b354f28d3b0b7d38367",
+                "019f4113742a2b14bd25926b49c649155f267e60d3814b4c0cc84250e46f0083"
+            )
+        );
+    }
+
+    #[test]
+    fn verify_accepts_valid_and_rejects_tampered() {
+        let pk = key().public_key();
+        let good = key().sign(b"sample");
+        assert!(pk.verify(b"sample", &good));
+        assert!(!pk.verify(b"samplf", &good));
+
+        let mut bad = good;
+        bad[63] ^= 1;
+        assert!(!pk.verify(b"sample", &bad));
+        assert!(!pk.verify(b"sample", &[0u8; SIGNATURE_LEN]));
+    }
+
+    #[test]
+    fn verify_randomized_signature() {
+        // Non-deterministic signature from an independent implementation
+        // (Python `cryptography`), so verify isn't only checked against
+        // our own signer.
+        let good = sig(
+            "07ee5a507c9034bc537c0bd9cc6e45ae345195fb7e8a04186c6c7d7292d0a43f",
+            "7cbfbc351bee4ad143d63528a254b17bcb22e6a7668ba4c6f5cc5d96f83f3818",
+        );
+        assert!(key().public_key().verify(b"rot attestation quote", &good));
+    }
+
+    #[test]
+    fn rejects_out_of_range_keys() {
+        assert!(SigningKey::from_bytes(&[0u8; 32]).is_none());
+        assert!(SigningKey::from_bytes(&to_bytes(&N)).is_none());
+
+        let mut sec1 = key().public_key().to_sec1();
+        sec1[64] ^= 1;
+        assert!(PublicKey::from_sec1(&sec1).is_none());
+    }
+}
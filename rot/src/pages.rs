@@ -0,0 +1,185 @@
+//! Per-page measurement: find out *where* an image changed.
+//!
+//! One digest over a whole region only tells a verifier that something
+//! changed.  [`measure_pages`] splits the image into fixed-size pages,
+//! digests each page, and builds a Merkle tree over those digests.  The
+//! root works as the whole-image measurement.  A verifier holding golden
+//! per-page digests passes both lists to [`differing_pages`] to get the
+//! indices of the pages that changed.  During a partial update it can
+//! also check just the pages that were rewritten.
+//!
+//! Hashing follows RFC 6962 (Certificate Transparency).  Leaves and inner
+//! nodes use different prefixes, so no inner node ever has the same hash
+//! as a page:
+//!
+//! ```text
+//!   leaf(page)  = H(0x00 || page)       (the last page may be short)
+//!   node(l, r)  = H(0x01 || l || r)
+//!   root(p)     = p[0]                  one page
+//!               = node(root(p[..k]), root(p[k..]))
+//!                                       k = largest power of two < len
+//!   root([])    = H("")
+//! ```
+
+use crate::digest::{Digest, Hasher};
+
+/// Why [`measure_pages`] couldn't measure an image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PageError {
+    /// `page_size` was 0.
+    ZeroPageSize,
+    /// The output slice has room for fewer digests than there are pages.
+    TooManyPages { pages: usize },
+}
+
+/// Number of `page_size`-byte pages it takes to cover `size` bytes.  The
+/// last one may be partial.
+pub const fn page_count(size: usize, page_size: usize) -> usize {
+    size.div_ceil(page_size)
+}
+
+/// Digest each `page_size`-byte page of `image` into `out[i]`, and return
+/// the Merkle root over those digests.  Only the first
+/// [`page_count`]`(image.len(), page_size)` entries of `out` are written.
+pub fn measure_pages<H: Hasher<D> + Default, const D: usize>(
+    image: &[u8],
+    page_size: usize,
+    out: &mut [Digest<D>],
+) -> Result<Digest<D>, PageError> {
+    if page_size == 0 {
+        return Err(PageError::ZeroPageSize);
+    }
+    let pages = page_count(image.len(), page_size);
+    if pages > out.len() {
+        return Err(PageError::TooManyPages { pages });
+    }
+    for (page, digest) in image.chunks(page_size).zip(out.iter_mut()) {
+        *digest = leaf::<H, D>(page);
+    }
+    Ok(merkle_root::<H, D>(&out[..pages]))
+}
+
+/// Merkle root over `leaves`, which are page digests from [`measure_pages`].
+pub fn merkle_root<H: Hasher<D> + Default, const D: usize>(leaves: &[Digest<D>]) -> Digest<D> {
+    match leaves.len() {
+        0 => H::digest(&[]),
+        1 => leaves[0],
+        n => {
+            let k = 1 << (usize::BITS - 1 - (n - 1).leading_zeros());
+            let (l, r) = leaves.split_at(k);
+            node::<H, D>(&merkle_root::<H, D>(l), &merkle_root::<H, D>(r))
+        }
+    }
+}
+
+/// Indices of the pages whose digests differ between `golden` and
+/// `measured`.  When one list is longer, its extra pages count as
+/// differing, since the other side has no such page.
+pub fn differing_pages<'a, const D: usize>(
+    golden: &'a [Digest<D>],
+    measured: &'a [Digest<D>],
+) -> impl Iterator<Item = usize> + 'a {
+    (0..golden.len().max(measured.len())).filter(move |&i| golden.get(i) != measured.get(i))
+}
+
+fn leaf<H: Hasher<D> + Default, const D: usize>(page: &[u8]) -> Digest<D> {
+    let mut h = H::default();
+    h.update(&[0x00]);
+    h.update(page);
+    let mut out = Digest::ZERO;
+    h.finalize(&mut out.0);
+    out
+}
+
+fn node<H: Hasher<D> + Default, const D: usize>(l: &Digest<D>, r: &Digest<D>) -> Digest<D> {
+    let mut h = H::default();
+    h.update(&[0x01]);
+    h.update(l.as_bytes());
+    h.update(r.as_bytes());
+    let mut out = Digest::ZERO;
+    h.finalize(&mut out.0);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sha256::{sha256, Sha256, DIGEST_LEN};
+
+    type D32 = Digest<DIGEST_LEN>;
+
+    fn measure(image: &[u8], page_size: usize, out: &mut [D32]) -> Result<D32, PageError> {
+        measure_pages::<Sha256, DIGEST_LEN>(image, page_size, out)
+    }
+
+    fn image() -> [u8; 1000] {
+        core::array::from_fn(|i| (i * 7) as u8)
+    }
+
+    #[test]
+    fn leaves_and_root_follow_rfc6962() {
+        let img = image();
+        let mut pages = [D32::ZERO; 4];
+        let root = measure(&img, 256, &mut pages).unwrap();
+
+        let mut buf = [0u8; 257];
+        buf[1..].copy_from_slice(&img[..256]);
+        assert_eq!(pages[0], Digest(sha256(&buf)));
+        // The last page is the 232-byte tail, unpadded.
+        buf[1..233].copy_from_slice(&img[768..]);
+        assert_eq!(pages[3], Digest(sha256(&buf[..233])));
+
+        let node = |l: &D32, r: &D32| {
+            let mut buf = [0u8; 65];
+            buf[0] = 1;
+            buf[1..33].copy_from_slice(l.as_bytes());
+            buf[33..].copy_from_slice(r.as_bytes());
+            Digest(sha256(&buf))
+        };
+        assert_eq!(root, node(&node(&pages[0], &pages[1]), &node(&pages[2], &pages[3])));
+
+        // Three pages: the odd one is promoted, not duplicated.
+        let mut three = [D32::ZERO; 3];
+        let root = measure(&img[..600], 256, &mut three).unwrap();
+        assert_eq!(root, node(&node(&three[0], &three[1]), &three[2]));
+
+        // One page: the root is its digest.
+        let root = measure(&img[..10], 256, &mut three).unwrap();
+        assert_eq!(root, three[0]);
+        assert_eq!(merkle_root::<Sha256, DIGEST_LEN>(&[]), Digest(sha256(b"")));
+    }
+
+    #[test]
+    fn a_change_is_localized_to_its_page() {
+        let golden_img = image();
+        let mut golden = [D32::ZERO; 8];
+        let golden_root = measure(&golden_img, 128, &mut golden).unwrap();
+
+        let mut img = golden_img;
+        img[2 * 128 + 5] ^= 1;
+        img[999] ^= 0x80; // last page, partial
+        let mut measured = [D32::ZERO; 8];
+        let root = measure(&img, 128, &mut measured).unwrap();
+        assert_ne!(root, golden_root);
+
+        let mut diff = differing_pages(&golden, &measured);
+        assert_eq!(diff.next(), Some(2));
+        assert_eq!(diff.next(), Some(7));
+        assert_eq!(diff.next(), None);
+
+        assert_eq!(differing_pages(&golden, &golden).count(), 0);
+        // A page missing on one side counts as a difference.
+        assert_eq!(differing_pages(&golden[..6], &golden).collect::<Vec<_>>(), [6, 7]);
+    }
+
+    #[test]
+    fn bad_arguments() {
+        let img = image();
+        let mut pages = [D32::ZERO; 3];
+        assert_eq!(measure(&img, 0, &mut pages), Err(PageError::ZeroPageSize));
+        assert_eq!(measure(&img, 256, &mut pages), Err(PageError::TooManyPages { pages: 4 }));
+        assert_eq!(page_count(1000, 256), 4);
+        assert_eq!(page_count(1024, 256), 4);
+        assert_eq!(page_count(0, 256), 0);
+    }
+}
@@ -0,0 +1,189 @@
+//! Cycle and retired-instruction counters for profiling U-mode code.
+//!
+//! Syscall 7 (`perf_counters`) writes a [`Sample`] of mcycle and minstret
+//! into a U-mode buffer.  The kernel also sets the CY and IR bits of
+//! mcounteren ([`MCOUNTEREN_U`]), so U-mode can use the unprivileged
+//! `rdcycle` / `rdinstret` aliases directly, with no trap.  The ecall is
+//! the portable fallback for a core where those bits don't stick.
+//!
+//! On RV32 each counter is two 32-bit CSRs.  The low half can wrap
+//! between the two reads, so [`read_split`] reads hi-lo-hi and retries if
+//! the high half moved, the same way `clint::now` reads mtime.
+//!
+//! The kernel also busy-waits on mcycle ([`spin_for`]), for devices that
+//! need a pause between register writes.
+
+/// mcounteren.CY: U-mode may read `cycle` / `cycleh`.
+pub const MCOUNTEREN_CY: u32 = 1 << 0;
+/// mcounteren.TM: U-mode may read `time` / `timeh`.
+pub const MCOUNTEREN_TM: u32 = 1 << 1;
+/// mcounteren.IR: U-mode may read `instret` / `instreth`.
+pub const MCOUNTEREN_IR: u32 = 1 << 2;
+
+/// The counters delegated to U-mode: cycles and instructions.  Not
+/// `time`: mtime lives in the CLINT and the kernel owns the timer.
+pub const MCOUNTEREN_U: u32 = MCOUNTEREN_CY | MCOUNTEREN_IR;
+
+/// Bytes of a [`Sample`] as syscall 7 writes it.
+pub const SAMPLE_LEN: usize = 16;
+
+/// One reading of both counters.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Sample {
+    pub cycles: u64,
+    pub instret: u64,
+}
+
+impl Sample {
+    /// `cycles` then `instret`, each little-endian: two `u64`s in U-mode
+    /// memory order.
+    pub fn to_bytes(&self) -> [u8; SAMPLE_LEN] {
+        let mut out = [0; SAMPLE_LEN];
+        out[..8].copy_from_slice(&self.cycles.to_le_bytes());
+        out[8..].copy_from_slice(&self.instret.to_le_bytes());
+        out
+    }
+
+    /// The inverse of [`to_bytes`](Sample::to_bytes).
+    pub fn from_bytes(bytes: &[u8; SAMPLE_LEN]) -> Sample {
+        let (cycles, instret) = bytes.split_at(8);
+        Sample {
+            cycles: u64::from_le_bytes(cycles.try_into().unwrap()),
+            instret: u64::from_le_bytes(instret.try_into().unwrap()),
+        }
+    }
+}
+
+/// A 64-bit counter from its two 32-bit halves.  The high half is read
+/// before and after the low one; if it changed, the low half wrapped in
+/// between and the pair is read again.
+pub fn read_split(mut hi: impl FnMut() -> u32, mut lo: impl FnMut() -> u32) -> u64 {
+    loop {
+        let h = hi();
+        let l = lo();
+        if hi() == h {
+            return (h as u64) << 32 | l as u64;
+        }
+    }
+}
+
+/// Reads in a row that may return the same value before [`spin_for`]
+/// gives up on the counter.  A running mcycle moves on every read, so
+/// this only trips on a stopped one.
+pub const SPIN_STALL_LIMIT: u32 = 1000;
+
+/// Spin until `now` has advanced `n` counts past its first read.  Returns
+/// whether it did: `false` if the counter stopped, [`SPIN_STALL_LIMIT`]
+/// reads in a row returning the same value (mcycle with
+/// `mcountinhibit.CY` set, say), rather than spinning forever.
+///
+/// The loop compares the elapsed count (`now - start`, wrapping), not an
+/// end value, so it waits the full `n` even if the counter wraps in the
+/// middle.  `now` must read the hardware each time it is called (a CSR
+/// read in `asm!` does): a cached value looks like a stopped counter.
+pub fn spin_for(n: u64, mut now: impl FnMut() -> u64) -> bool {
+    let start = now();
+    let mut last = start;
+    let mut stalled = 0;
+    loop {
+        let t = now();
+        if t.wrapping_sub(start) >= n {
+            return true;
+        }
+        if t != last {
+            (last, stalled) = (t, 0);
+        } else {
+            stalled += 1;
+            if stalled == SPIN_STALL_LIMIT {
+                return false;
+            }
+        }
+        core::hint::spin_loop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    /// A counter that advances on every half read, as a free-running
+    /// counter does between two CSR reads.
+    struct Counter {
+        value: Cell<u64>,
+    }
+
+    impl Counter {
+        fn tick(&self) -> u64 {
+            let v = self.value.get();
+            self.value.set(v.wrapping_add(1));
+            v
+        }
+        fn hi(&self) -> u32 {
+            (self.tick() >> 32) as u32
+        }
+        fn lo(&self) -> u32 {
+            self.tick() as u32
+        }
+    }
+
+    #[test]
+    fn split_read_survives_low_half_wrap() {
+        // No carry between the reads: one pass.
+        let c = Counter { value: Cell::new(0x1_2345_0000) };
+        assert_eq!(read_split(|| c.hi(), || c.lo()), 0x1_2345_0001);
+        assert_eq!(c.value.get(), 0x1_2345_0003);
+
+        // Read hi at ...ffff, lo at 2_0000_0000: the wrap is caught by the
+        // second hi and the pair re-read.  Naively joining the first two
+        // reads would give 1_0000_0000, four billion counts in the past.
+        let c = Counter { value: Cell::new(0x1_ffff_ffff) };
+        assert_eq!(read_split(|| c.hi(), || c.lo()), 0x2_0000_0003);
+        assert_eq!(c.value.get(), 0x2_0000_0005);
+    }
+
+    #[test]
+    fn spins_for_the_full_count_across_a_wrap() {
+        for start in [0, 1000, u64::MAX - 10, u64::MAX] {
+            let c = Counter { value: Cell::new(start) };
+            assert!(spin_for(25, || c.tick()));
+            // The first read, then reads until one is 25 past it.
+            assert_eq!(c.value.get().wrapping_sub(start), 26, "start {start:#x}");
+        }
+
+        let c = Counter { value: Cell::new(u64::MAX) };
+        assert!(spin_for(0, || c.tick()));
+        assert_eq!(c.value.get(), 1);
+    }
+
+    #[test]
+    fn a_stopped_counter_ends_the_wait() {
+        let reads = Cell::new(0u32);
+        let stopped = || {
+            reads.set(reads.get() + 1);
+            42
+        };
+        assert!(!spin_for(10, stopped));
+        // The first read, then the limit's worth of unchanged ones.
+        assert_eq!(reads.get(), SPIN_STALL_LIMIT + 1);
+
+        // A slow counter that moves every few reads still gets there.
+        let reads = Cell::new(0u64);
+        let slow = || {
+            reads.set(reads.get() + 1);
+            reads.get() / 100
+        };
+        assert!(spin_for(50, slow));
+    }
+
+    #[test]
+    fn sample_layout() {
+        let s = Sample { cycles: 0x0102_0304_0506_0708, instret: 0x1112_1314_1516_1718 };
+        let b = s.to_bytes();
+        assert_eq!(b[..8], [8, 7, 6, 5, 4, 3, 2, 1]);
+        assert_eq!(b[8..], [0x18, 0x17, 0x16, 0x15, 0x14, 0x13, 0x12, 0x11]);
+        assert_eq!(Sample::from_bytes(&b), s);
+        assert_eq!(MCOUNTEREN_U, 0b101);
+        assert_eq!(MCOUNTEREN_U & MCOUNTEREN_TM, 0);
+    }
+}
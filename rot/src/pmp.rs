@@ -0,0 +1,800 @@
+//! PMP configuration bytes.
+//!
+//! Each PMP entry has an 8-bit config field; RV32 packs four of them into
+//! each `pmpcfgN` CSR (entry `4N` in bits 7:0, `4N+3` in bits 31:24):
+//!
+//! ```text
+//!   bit  7   6:5   4:3   2   1   0
+//!        L   —     A     X   W   R
+//! ```
+//!
+//! Each `pmpaddrN` holds bits 33:2 of an address.  What range it covers
+//! depends on the entry's A field ([`PmpCfg::region`]):
+//!
+//! ```text
+//!   TOR    [pmpaddr(N-1) << 2, pmpaddrN << 2)
+//!   NA4    [pmpaddrN << 2, +4)
+//!   NAPOT  pmpaddrN = (base >> 2) | (size/8 - 1): the number of trailing
+//!          one bits t gives size = 2^(t+3), and base is what's left
+//! ```
+//!
+//! TOR needs no alignment but costs two entries: a TOR entry's range
+//! starts where the *previous* entry's pmpaddr points, whatever that
+//! entry's own mode.  So [`pmp_tor`] gives the region an entry of its own
+//! for the lower bound, left OFF so it matches nothing by itself.
+//!
+//! [`AppRegions`] is the set of NAPOT regions one U-mode application runs
+//! with; the kernel reprograms a fixed group of entries from it whenever
+//! it switches application.
+//!
+//! [`PmpConfig`] is a whole PMP configuration as data: every `pmpaddr` and
+//! `pmpcfg` value, built from [`PmpEntry`]s without writing a CSR.  The
+//! kernel's boot configuration is one ([`crate::pmp_map`]); so is what it
+//! reads back from the hardware.
+
+use core::fmt;
+
+/// Read permission.
+pub const PMP_R: u32 = 0x01;
+/// Write permission.
+pub const PMP_W: u32 = 0x02;
+/// Execute permission.
+pub const PMP_X: u32 = 0x04;
+/// Address-matching mode field (A).
+pub const PMP_A_MASK: u32 = 0x18;
+/// A = TOR (top of range).
+pub const PMP_TOR: u32 = 0x08;
+/// A = NA4 (naturally aligned four-byte region).
+pub const PMP_NA4: u32 = 0x10;
+/// A = NAPOT (naturally aligned power-of-two region).
+pub const PMP_NAPOT: u32 = 0x18;
+/// Lock bit — locks the entry and makes it apply to M-mode too.
+pub const PMP_L: u32 = 0x80;
+/// Bits 6:5, reserved: zero in every valid config byte.
+pub const PMP_RESERVED: u32 = 0x60;
+
+/// Why [`PmpCfg::check`] rejected a config byte.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PmpCfgError {
+    /// W without R, which the privileged spec reserves.
+    WriteWithoutRead,
+    /// A reserved bit (6:5) is set.
+    ReservedBits,
+}
+
+/// Why [`PmpCfg::check_entry`] refused an entry point.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryError {
+    /// Odd address: no instruction starts there, even with RVC.
+    Misaligned,
+    /// The entry is OFF or doesn't grant X.
+    NotExecutable,
+    /// Outside the entry's region.
+    OutsideRegion,
+}
+
+/// One entry's config byte.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PmpCfg(pub u8);
+
+impl PmpCfg {
+    /// Config byte of `entry`, taken from the `pmpcfg` CSR values
+    /// `regs[0] = pmpcfg0, regs[1] = pmpcfg1, …`.
+    pub fn from_regs(regs: &[u32], entry: usize) -> Option<PmpCfg> {
+        let reg = regs.get(entry / 4)?;
+        Some(PmpCfg((reg >> (8 * (entry % 4))) as u8))
+    }
+
+    pub const fn readable(self) -> bool {
+        self.0 as u32 & PMP_R != 0
+    }
+
+    pub const fn writable(self) -> bool {
+        self.0 as u32 & PMP_W != 0
+    }
+
+    pub const fn executable(self) -> bool {
+        self.0 as u32 & PMP_X != 0
+    }
+
+    pub const fn locked(self) -> bool {
+        self.0 as u32 & PMP_L != 0
+    }
+
+    /// The same byte with [`PMP_L`] set.
+    pub const fn lock(self) -> PmpCfg {
+        PmpCfg(self.0 | PMP_L as u8)
+    }
+
+    /// The byte, if it is one the spec defines.  Every A encoding is
+    /// (OFF, TOR, NA4, NAPOT); what is reserved is W without R, and bits
+    /// 6:5.
+    pub const fn check(self) -> Result<PmpCfg, PmpCfgError> {
+        let cfg = self.0 as u32;
+        if cfg & PMP_RESERVED != 0 {
+            Err(PmpCfgError::ReservedBits)
+        } else if cfg & (PMP_R | PMP_W) == PMP_W {
+            Err(PmpCfgError::WriteWithoutRead)
+        } else {
+            Ok(self)
+        }
+    }
+
+    /// A locked TOR entry also locks the previous entry's pmpaddr, its
+    /// lower bound: writes to it are ignored.
+    pub const fn locks_previous_addr(self) -> bool {
+        self.locked() && self.0 as u32 & PMP_A_MASK == PMP_TOR
+    }
+
+    /// Grants exactly R+X: a code region that cannot be written.
+    pub const fn is_rx_only(self) -> bool {
+        self.0 as u32 & (PMP_R | PMP_W | PMP_X) == PMP_R | PMP_X
+    }
+
+    /// The address range this entry matches, as `(base, size)` in bytes,
+    /// given its own `pmpaddr` and the previous entry's (used by TOR; 0
+    /// for entry 0).  `None` if the entry is OFF.  64-bit, because RV32
+    /// PMP addresses are 34 bits wide.
+    pub const fn region(self, pmpaddr: u32, prev_pmpaddr: u32) -> Option<(u64, u64)> {
+        let addr = (pmpaddr as u64) << 2;
+        match self.0 as u32 & PMP_A_MASK {
+            PMP_TOR => {
+                let base = (prev_pmpaddr as u64) << 2;
+                Some((base, addr.saturating_sub(base)))
+            }
+            PMP_NA4 => Some((addr, 4)),
+            PMP_NAPOT => Some(napot_decode(pmpaddr)),
+            _ => None,
+        }
+    }
+
+    /// Whether U-mode can start executing at `entry` under this entry,
+    /// whose range is `region` (from [`region`](PmpCfg::region)): the
+    /// address is halfword-aligned, and inside the region, which grants X.
+    pub const fn check_entry(self, region: Option<(u64, u64)>, entry: u32) -> Result<(), EntryError> {
+        if entry & 1 != 0 {
+            return Err(EntryError::Misaligned);
+        }
+        let Some((base, size)) = region else {
+            return Err(EntryError::NotExecutable);
+        };
+        if !self.executable() {
+            return Err(EntryError::NotExecutable);
+        }
+        if (entry as u64) < base || entry as u64 - base >= size {
+            return Err(EntryError::OutsideRegion);
+        }
+        Ok(())
+    }
+}
+
+/// Why a region can't be one NAPOT entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NapotError {
+    /// `size` isn't a power of two of at least 8.
+    BadSize,
+    /// `base` isn't a multiple of `size`.
+    Misaligned,
+}
+
+/// NAPOT `pmpaddr` value for the `size`-byte region at `base`:
+/// `(base >> 2) | (size/8 - 1)`, or why the region has none.  Without the
+/// checks a misaligned base would just encode a different region.
+pub const fn try_napot_addr(base: u32, size: u32) -> Result<u32, NapotError> {
+    if size < 8 || !size.is_power_of_two() {
+        return Err(NapotError::BadSize);
+    }
+    if base & (size - 1) != 0 {
+        return Err(NapotError::Misaligned);
+    }
+    Ok((base >> 2) | ((size >> 3) - 1))
+}
+
+/// [`try_napot_addr`] for a region that must be valid: a compile error
+/// when evaluated in a const context, a panic otherwise.
+pub const fn napot_addr(base: u32, size: u32) -> u32 {
+    match try_napot_addr(base, size) {
+        Ok(pmpaddr) => pmpaddr,
+        Err(NapotError::BadSize) => panic!("NAPOT size must be a power of two >= 8"),
+        Err(NapotError::Misaligned) => panic!("NAPOT base must be size-aligned"),
+    }
+}
+
+/// [`napot_addr`] for a named entry: the panic message, and so the
+/// compile error, is `region`, e.g. `"PMP entry 8 (U_GUARD)"`.  A const
+/// panic can't format, so the reason is the line it points at.
+pub const fn napot_addr_for(region: &'static str, base: u32, size: u32) -> u32 {
+    match try_napot_addr(base, size) {
+        Ok(pmpaddr) => pmpaddr,
+        // Size not a power of two >= 8
+        Err(NapotError::BadSize) => panic!("{}", region),
+        // Base not a multiple of size
+        Err(NapotError::Misaligned) => panic!("{}", region),
+    }
+}
+
+/// `(base, size)` of a NAPOT `pmpaddr` value — the inverse of the
+/// `pmpaddr = (base >> 2) | (size/8 - 1)` encoding.  The lowest clear bit
+/// marks the size; `0x7fff_ffff` is the whole 34-bit space.
+pub const fn napot_decode(pmpaddr: u32) -> (u64, u64) {
+    let ones = pmpaddr.trailing_ones();
+    let size = 8u64 << ones;
+    let base = ((pmpaddr as u64) & !((1u64 << ones) - 1)) << 2;
+    (base, size)
+}
+
+/// `pmpcfg` value `reg` with the config byte of entry `entry` (its slot,
+/// `entry % 4`) replaced by `cfg`.
+pub const fn with_cfg(reg: u32, entry: usize, cfg: PmpCfg) -> u32 {
+    let shift = 8 * (entry % 4);
+    (reg & !(0xff << shift)) | (cfg.0 as u32) << shift
+}
+
+/// `pmpcfg` value for four consecutive entries, `cfgs[0]` in bits 7:0,
+/// or the first invalid byte ([`PmpCfg::check`]) and its slot.
+pub const fn try_pack_pmpcfg(cfgs: [u8; 4]) -> Result<u32, (usize, PmpCfgError)> {
+    let mut reg = 0;
+    let mut slot = 0;
+    while slot < cfgs.len() {
+        if let Err(e) = PmpCfg(cfgs[slot]).check() {
+            return Err((slot, e));
+        }
+        reg |= (cfgs[slot] as u32) << (8 * slot);
+        slot += 1;
+    }
+    Ok(reg)
+}
+
+/// [`try_pack_pmpcfg`] for a configuration that must be valid: an
+/// invalid byte is a compile error when evaluated in a const context, a
+/// panic (a boot-time halt) otherwise.
+pub const fn pack_pmpcfg(cfgs: [u8; 4]) -> u32 {
+    match try_pack_pmpcfg(cfgs) {
+        Ok(reg) => reg,
+        Err((_, PmpCfgError::WriteWithoutRead)) => panic!("PMP config grants W without R"),
+        Err((_, PmpCfgError::ReservedBits)) => panic!("PMP config sets reserved bits 6:5"),
+    }
+}
+
+/// Number of PMP entries the kernel programs.
+pub const PMP_ENTRY_COUNT: usize = 16;
+
+/// One entry's register values: `pmpaddr<index>` and its config byte.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PmpEntry {
+    pub index: usize,
+    pub pmpaddr: u32,
+    pub cfg: PmpCfg,
+}
+
+/// The two entries for the TOR region `[lo, hi)` with `perm` (some of
+/// R/W/X, optionally [`PMP_L`]): `start_entry` holds `lo` and is OFF,
+/// `start_entry + 1` is the TOR entry ending at `hi`.  Both bounds must
+/// be 4-byte aligned and `lo < hi`; like [`napot_addr`], anything else is
+/// a compile error in a const context and a panic otherwise.
+pub const fn pmp_tor(start_entry: usize, lo: u32, hi: u32, perm: u32) -> [PmpEntry; 2] {
+    assert!(start_entry + 1 < PMP_ENTRY_COUNT, "TOR region needs two entries");
+    assert!(lo & 3 == 0 && hi & 3 == 0, "TOR bounds must be 4-byte aligned");
+    assert!(lo < hi, "TOR region is empty");
+    let cfg = PmpCfg((PMP_TOR | (perm & (PMP_L | PMP_R | PMP_W | PMP_X))) as u8);
+    if cfg.check().is_err() {
+        panic!("TOR region grants W without R");
+    }
+    [
+        PmpEntry { index: start_entry, pmpaddr: lo >> 2, cfg: PmpCfg(0) },
+        PmpEntry { index: start_entry + 1, pmpaddr: hi >> 2, cfg },
+    ]
+}
+
+/// Every PMP register value: `pmpaddr0-15` and `pmpcfg0-3`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PmpConfig {
+    pub pmpaddr: [u32; PMP_ENTRY_COUNT],
+    pub pmpcfg: [u32; PMP_ENTRY_COUNT / 4],
+}
+
+impl PmpConfig {
+    /// Every entry OFF, every address 0: the reset state.
+    pub const OFF: PmpConfig = PmpConfig { pmpaddr: [0; PMP_ENTRY_COUNT], pmpcfg: [0; PMP_ENTRY_COUNT / 4] };
+
+    /// The same configuration with `entry` programmed.  An index past the
+    /// last entry or an invalid config byte ([`PmpCfg::check`]) is a
+    /// compile error in a const context and a panic otherwise.
+    pub const fn with(mut self, entry: PmpEntry) -> PmpConfig {
+        assert!(entry.index < PMP_ENTRY_COUNT, "no such PMP entry");
+        if entry.cfg.check().is_err() {
+            panic!("invalid PMP config byte");
+        }
+        self.pmpaddr[entry.index] = entry.pmpaddr;
+        self.pmpcfg[entry.index / 4] = with_cfg(self.pmpcfg[entry.index / 4], entry.index, entry.cfg);
+        self
+    }
+
+    /// [`with`](PmpConfig::with) for each of `entries`, in order.
+    pub const fn with_all(mut self, entries: &[PmpEntry]) -> PmpConfig {
+        let mut i = 0;
+        while i < entries.len() {
+            self = self.with(entries[i]);
+            i += 1;
+        }
+        self
+    }
+
+    /// Entry `index`'s register values.
+    pub const fn entry(&self, index: usize) -> PmpEntry {
+        PmpEntry { index, pmpaddr: self.pmpaddr[index], cfg: PmpCfg((self.pmpcfg[index / 4] >> (8 * (index % 4))) as u8) }
+    }
+
+    /// The range entry `index` matches, as [`PmpCfg::region`] decodes it
+    /// (TOR takes the previous entry's address), or `None` if it is OFF.
+    pub const fn region(&self, index: usize) -> Option<(u64, u64)> {
+        let prev = if index == 0 { 0 } else { self.pmpaddr[index - 1] };
+        self.entry(index).cfg.region(self.pmpaddr[index], prev)
+    }
+
+    /// How many entries match a range: those that aren't OFF.  The lower
+    /// bound of a TOR pair is OFF itself and doesn't count.
+    pub const fn active_count(&self) -> usize {
+        let mut n = 0;
+        let mut i = 0;
+        while i < PMP_ENTRY_COUNT {
+            if self.region(i).is_some() {
+                n += 1;
+            }
+            i += 1;
+        }
+        n
+    }
+
+    /// The entry that decides a U-mode access to the 4-byte word at `addr`:
+    /// the lowest-numbered one whose range contains it, as the hardware
+    /// picks.  `None` if no entry matches, which denies U-mode.
+    pub const fn matching_entry(&self, addr: u64) -> Option<PmpEntry> {
+        let mut i = 0;
+        while i < PMP_ENTRY_COUNT {
+            if let Some((base, size)) = self.region(i) {
+                if addr >= base && addr - base < size {
+                    return Some(self.entry(i));
+                }
+            }
+            i += 1;
+        }
+        None
+    }
+}
+
+/// A NAPOT region and the access an entry for it grants U-mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Napot {
+    pub base: u32,
+    pub size: u32,
+    /// Some of [`PMP_R`] | [`PMP_W`] | [`PMP_X`].
+    pub perm: u32,
+}
+
+impl Napot {
+    pub const fn new(base: u32, size: u32, perm: u32) -> Napot {
+        // Reject what napot_addr would, when the region is built rather
+        // than when it is first programmed.
+        napot_addr(base, size);
+        assert!(perm & (PMP_R | PMP_W) != PMP_W, "NAPOT region grants W without R");
+        Napot { base, size, perm }
+    }
+
+    pub const fn pmpaddr(&self) -> u32 {
+        napot_addr(self.base, self.size)
+    }
+
+    /// The region as entry `index`.
+    pub const fn entry(&self, index: usize) -> PmpEntry {
+        PmpEntry { index, pmpaddr: self.pmpaddr(), cfg: self.cfg() }
+    }
+
+    /// Config byte: NAPOT with `perm`, never locked.
+    pub const fn cfg(&self) -> PmpCfg {
+        PmpCfg((PMP_NAPOT | (self.perm & (PMP_R | PMP_W | PMP_X))) as u8)
+    }
+
+    pub const fn contains(&self, addr: u32) -> bool {
+        addr >= self.base && (addr - self.base) < self.size
+    }
+
+    /// [`PmpCfg::check_entry`] for an entry programmed from this region.
+    pub const fn check_entry(&self, entry: u32) -> Result<(), EntryError> {
+        self.cfg().check_entry(Some((self.base as u64, self.size as u64)), entry)
+    }
+
+    /// The same region without execute permission.
+    pub const fn without_exec(&self) -> Napot {
+        Napot { perm: self.perm & !PMP_X, ..*self }
+    }
+
+    pub const fn overlaps(&self, other: &Napot) -> bool {
+        (self.base as u64) < other.base as u64 + other.size as u64
+            && (other.base as u64) < self.base as u64 + self.size as u64
+    }
+}
+
+/// The memory one U-mode application runs with: the regions the kernel
+/// programs into the per-application PMP entries before entering it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AppRegions {
+    pub code: Napot,
+    pub rodata: Napot,
+    pub ram: Napot,
+    pub shadow: Napot,
+}
+
+impl AppRegions {
+    /// The regions in entry order: code, rodata, ram, shadow.
+    pub const fn regions(&self) -> [Napot; 4] {
+        [self.code, self.rodata, self.ram, self.shadow]
+    }
+
+    /// Whether `self` can write anything `other` can reach.  Two
+    /// applications may share code and rodata, but running one must not
+    /// let it change what the other reads.
+    pub const fn writes_into(&self, other: &AppRegions) -> bool {
+        let (mine, theirs) = (self.regions(), other.regions());
+        let mut i = 0;
+        while i < mine.len() {
+            let mut j = 0;
+            while j < theirs.len() {
+                if mine[i].perm & PMP_W != 0 && mine[i].overlaps(&theirs[j]) {
+                    return true;
+                }
+                j += 1;
+            }
+            i += 1;
+        }
+        false
+    }
+
+    /// Index into [`regions`](AppRegions::regions) of the executable
+    /// region holding `addr`.
+    pub const fn code_region_of(&self, addr: u32) -> Option<usize> {
+        let regions = self.regions();
+        let mut i = 0;
+        while i < regions.len() {
+            if regions[i].perm & PMP_X != 0 && regions[i].contains(addr) {
+                return Some(i);
+            }
+            i += 1;
+        }
+        None
+    }
+
+    /// Neither application can write what the other reaches.
+    pub const fn isolated_from(&self, other: &AppRegions) -> bool {
+        !self.writes_into(other) && !other.writes_into(self)
+    }
+}
+
+impl fmt::Display for NapotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            NapotError::BadSize => "size is not a power of two >= 8",
+            NapotError::Misaligned => "base is not size-aligned",
+        })
+    }
+}
+
+impl fmt::Display for EntryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            EntryError::Misaligned => "is not halfword-aligned",
+            EntryError::NotExecutable => "is not in an executable region",
+            EntryError::OutsideRegion => "is outside the region",
+        })
+    }
+}
+
+impl fmt::Display for PmpCfg {
+    /// e.g. `R-X NAPOT` or `R-X NAPOT locked`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let perm = |on, c| if on { c } else { '-' };
+        let mode = match self.0 as u32 & PMP_A_MASK {
+            PMP_TOR => "TOR",
+            PMP_NA4 => "NA4",
+            PMP_NAPOT => "NAPOT",
+            _ => "OFF",
+        };
+        write!(
+            f,
+            "{}{}{} {}",
+            perm(self.readable(), 'R'),
+            perm(self.writable(), 'W'),
+            perm(self.executable(), 'X'),
+            mode
+        )?;
+        if self.locked() {
+            f.write_str(" locked")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::string::ToString;
+
+    #[test]
+    fn extracts_entry_bytes() {
+        let regs = [0x1f1b_189d, 0x0000_0019];
+        assert_eq!(PmpCfg::from_regs(&regs, 0), Some(PmpCfg(0x9d)));
+        assert_eq!(PmpCfg::from_regs(&regs, 3), Some(PmpCfg(0x1f)));
+        assert_eq!(PmpCfg::from_regs(&regs, 4), Some(PmpCfg(0x19)));
+        assert_eq!(PmpCfg::from_regs(&regs, 8), None);
+    }
+
+    #[test]
+    fn rx_only() {
+        assert!(PmpCfg((PMP_NAPOT | PMP_R | PMP_X) as u8).is_rx_only());
+        assert!(!PmpCfg((PMP_NAPOT | PMP_R | PMP_W | PMP_X) as u8).is_rx_only());
+        assert!(!PmpCfg((PMP_NAPOT | PMP_X) as u8).is_rx_only());
+    }
+
+    #[test]
+    fn napot_addr_encodes_size_in_low_bits() {
+        assert_eq!(napot_addr(0x8000_0000, 8), 0x2000_0000);
+        assert_eq!(napot_addr(0x8000_0000, 16), 0x2000_0001);
+        assert_eq!(napot_addr(0x1000_0000, 4 * 1024), 0x0400_01ff);
+        assert_eq!(napot_addr(0x8001_0000, 32 * 1024), 0x2000_4fff);
+        assert_eq!(napot_addr(0x8000_0000, 64 * 1024), 0x2000_1fff);
+        assert_eq!(napot_addr(0x8000_0000, 1 << 31), 0x2fff_ffff);
+        for shift in 3..32 {
+            let size = 1u32 << shift;
+            let base = 0x8000_0000u32 & !(size - 1);
+            assert_eq!(napot_decode(napot_addr(base, size)), (base as u64, size as u64));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "size-aligned")]
+    fn napot_addr_rejects_misaligned_base() {
+        napot_addr(0x8000_1000, 64 * 1024);
+    }
+
+    #[test]
+    #[should_panic(expected = "power of two")]
+    fn napot_addr_rejects_odd_size() {
+        napot_addr(0x8000_0000, 24);
+    }
+
+    #[test]
+    fn try_napot_addr_says_why() {
+        assert_eq!(try_napot_addr(0x8005_7000, 4 * 1024), Ok(0x2001_5dff));
+        assert_eq!(try_napot_addr(0x8005_7000, 8 * 1024), Err(NapotError::Misaligned));
+        assert_eq!(try_napot_addr(0x8000_0000, 24), Err(NapotError::BadSize));
+        assert_eq!(try_napot_addr(0x8000_0000, 4), Err(NapotError::BadSize));
+        assert_eq!(try_napot_addr(0x8000_0000, 0), Err(NapotError::BadSize));
+    }
+
+    #[test]
+    #[should_panic(expected = "PMP entry 8 (U_GUARD)")]
+    fn napot_addr_for_names_the_region() {
+        assert_eq!(napot_addr_for("PMP entry 7 (UART)", 0x1000_0000, 4 * 1024), 0x0400_01ff);
+        napot_addr_for("PMP entry 8 (U_GUARD)", 0x8005_7000, 8 * 1024);
+    }
+
+    #[test]
+    fn napot_decode_known_encodings() {
+        // The kernel's own layout: ROM 64K, M_RAM 32K, UART 4K.
+        assert_eq!(napot_decode(0x2000_1fff), (0x8000_0000, 64 * 1024));
+        assert_eq!(napot_decode(0x2000_4fff), (0x8001_0000, 32 * 1024));
+        assert_eq!(napot_decode(0x0400_01ff), (0x1000_0000, 4 * 1024));
+        // Smallest NAPOT region (8 bytes) and the whole address space.
+        assert_eq!(napot_decode(0x2000_0000), (0x8000_0000, 8));
+        assert_eq!(napot_decode(0x7fff_ffff), (0, 1 << 34));
+    }
+
+    #[test]
+    fn region_by_mode() {
+        let cfg = |a| PmpCfg((a | PMP_R) as u8);
+        assert_eq!(cfg(PMP_TOR).region(0x2000_4000, 0x2000_0000), Some((0x8000_0000, 0x1_0000)));
+        assert_eq!(cfg(PMP_NA4).region(0x0400_0000, 0), Some((0x1000_0000, 4)));
+        assert_eq!(cfg(PMP_NAPOT).region(0x2000_1fff, 0), Some((0x8000_0000, 0x1_0000)));
+        assert_eq!(cfg(0).region(0x2000_1fff, 0), None);
+    }
+
+    #[test]
+    fn entry_point_checks() {
+        let rx = PmpCfg((PMP_NAPOT | PMP_R | PMP_X) as u8);
+        let region = Some((0x8002_0000, 0x1_0000));
+        assert_eq!(rx.check_entry(region, 0x8002_0000), Ok(()));
+        assert_eq!(rx.check_entry(region, 0x8002_fffe), Ok(()));
+        assert_eq!(rx.check_entry(region, 0x8002_0002), Ok(()));
+        assert_eq!(rx.check_entry(region, 0x8002_0001), Err(EntryError::Misaligned));
+        assert_eq!(rx.check_entry(region, 0x8001_fffe), Err(EntryError::OutsideRegion));
+        assert_eq!(rx.check_entry(region, 0x8003_0000), Err(EntryError::OutsideRegion));
+        assert_eq!(rx.check_entry(None, 0x8002_0000), Err(EntryError::NotExecutable));
+        let r = PmpCfg((PMP_NAPOT | PMP_R) as u8);
+        assert_eq!(r.check_entry(region, 0x8002_0000), Err(EntryError::NotExecutable));
+
+        let code = Napot::new(0x8002_0000, 0x1_0000, PMP_R | PMP_X);
+        assert_eq!(code.check_entry(0x8002_0100), Ok(()));
+        assert_eq!(code.without_exec().check_entry(0x8002_0100), Err(EntryError::NotExecutable));
+        assert_eq!(code.check_entry(0x8003_0000), Err(EntryError::OutsideRegion));
+        assert_eq!(EntryError::OutsideRegion.to_string(), "is outside the region");
+    }
+
+    #[test]
+    fn lock_keeps_mode_and_permissions() {
+        let cfg = PmpCfg((PMP_NAPOT | PMP_R | PMP_W) as u8);
+        assert!(!cfg.locked());
+        let locked = cfg.lock();
+        assert!(locked.locked());
+        assert_eq!(locked.0 as u32, PMP_L | PMP_NAPOT | PMP_R | PMP_W);
+        assert_eq!(locked.region(0x2001_1fff, 0), cfg.region(0x2001_1fff, 0));
+        assert_eq!(locked.lock(), locked);
+    }
+
+    #[test]
+    fn with_cfg_replaces_one_slot() {
+        let reg = 0x1f1b_189d;
+        assert_eq!(with_cfg(reg, 0, PmpCfg(0)), 0x1f1b_1800);
+        assert_eq!(with_cfg(reg, 3, PmpCfg(0x9b)), 0x9b1b_189d);
+        assert_eq!(with_cfg(reg, 5, PmpCfg(0xaa)), 0x1f1b_aa9d);
+    }
+
+    #[test]
+    fn cfg_validation() {
+        for cfg in [0, PMP_R, PMP_R | PMP_W, PMP_R | PMP_X, PMP_X, PMP_L | PMP_NAPOT | PMP_R | PMP_X] {
+            assert_eq!(PmpCfg(cfg as u8).check(), Ok(PmpCfg(cfg as u8)));
+        }
+        for a in [0, PMP_TOR, PMP_NA4, PMP_NAPOT] {
+            assert!(PmpCfg((a | PMP_R) as u8).check().is_ok());
+        }
+        assert_eq!(PmpCfg(PMP_W as u8).check(), Err(PmpCfgError::WriteWithoutRead));
+        assert_eq!(PmpCfg((PMP_NAPOT | PMP_W | PMP_X) as u8).check(), Err(PmpCfgError::WriteWithoutRead));
+        assert_eq!(PmpCfg(0x20).check(), Err(PmpCfgError::ReservedBits));
+        assert_eq!(PmpCfg((0x40 | PMP_R) as u8).check(), Err(PmpCfgError::ReservedBits));
+    }
+
+    #[test]
+    fn packs_pmpcfg() {
+        let rx_locked = (PMP_L | PMP_NAPOT | PMP_R | PMP_X) as u8;
+        let rw = (PMP_NAPOT | PMP_R | PMP_W) as u8;
+        assert_eq!(pack_pmpcfg([rx_locked, 0, 0, rw]), 0x1b00_009d);
+        assert_eq!(try_pack_pmpcfg([1, 2, 3, 0]), Err((1, PmpCfgError::WriteWithoutRead)));
+        assert_eq!(try_pack_pmpcfg([0, 0, 0, 0x60]), Err((3, PmpCfgError::ReservedBits)));
+        // Round-trips through from_regs, entry by entry.
+        let reg = pack_pmpcfg([rx_locked, rw, 0, PMP_R as u8]);
+        let slots: [u8; 4] = core::array::from_fn(|e| PmpCfg::from_regs(&[reg], e).unwrap().0);
+        assert_eq!(slots, [rx_locked, rw, 0, PMP_R as u8]);
+    }
+
+    #[test]
+    #[should_panic(expected = "W without R")]
+    fn pack_pmpcfg_rejects_write_only() {
+        pack_pmpcfg([0, PMP_W as u8, 0, 0]);
+    }
+
+    #[test]
+    fn tor_region() {
+        // M_RAM's 32K, as a TOR pair instead of one NAPOT entry.
+        let [bound, top] = pmp_tor(11, 0x8001_0000, 0x8001_8000, PMP_R | PMP_W);
+        assert_eq!(bound, PmpEntry { index: 11, pmpaddr: 0x2000_4000, cfg: PmpCfg(0) });
+        assert_eq!(top.index, 12);
+        assert_eq!(top.pmpaddr, 0x2000_6000);
+        assert_eq!(top.cfg.to_string(), "RW- TOR");
+        // The lower bound entry matches nothing itself; the TOR entry's
+        // range comes from its predecessor's pmpaddr.
+        assert_eq!(bound.cfg.region(bound.pmpaddr, 0), None);
+        assert_eq!(top.cfg.region(top.pmpaddr, bound.pmpaddr), Some((0x8001_0000, 0x8000)));
+        // No alignment beyond 4 bytes: a range NAPOT can't express.
+        let [b, t] = pmp_tor(0, 0x8002_0004, 0x8002_3000, PMP_R | PMP_X | PMP_L);
+        assert_eq!(t.cfg.region(t.pmpaddr, b.pmpaddr), Some((0x8002_0004, 0x2ffc)));
+        assert!(t.cfg.locked() && t.cfg.is_rx_only());
+        assert!(t.cfg.locks_previous_addr() && !top.cfg.locks_previous_addr());
+        assert!(!PmpCfg((PMP_L | PMP_NAPOT | PMP_R) as u8).locks_previous_addr());
+        let reg = pack_pmpcfg([b.cfg.0, t.cfg.0, 0, 0]);
+        assert_eq!(PmpCfg::from_regs(&[reg], 1), Some(t.cfg));
+    }
+
+    #[test]
+    #[should_panic(expected = "4-byte aligned")]
+    fn tor_rejects_unaligned_bound() {
+        pmp_tor(3, 0x8001_0002, 0x8001_8000, PMP_R);
+    }
+
+    #[test]
+    #[should_panic(expected = "two entries")]
+    fn tor_needs_a_second_entry() {
+        pmp_tor(15, 0x8001_0000, 0x8001_8000, PMP_R);
+    }
+
+    #[test]
+    fn napot_region_entry() {
+        let ram = Napot::new(0x8004_8000, 32 * 1024, PMP_R | PMP_W);
+        assert_eq!(ram.pmpaddr(), 0x2001_2fff);
+        assert_eq!(ram.cfg().to_string(), "RW- NAPOT");
+        assert_eq!(ram.cfg().region(ram.pmpaddr(), 0), Some((0x8004_8000, 32 * 1024)));
+        // Lock and mode bits in `perm` are not passed through.
+        assert!(!Napot { perm: PMP_L | PMP_TOR | PMP_R, ..ram }.cfg().locked());
+        assert!(ram.overlaps(&Napot::new(0x8004_f000, 4 * 1024, PMP_R)));
+        assert!(!ram.overlaps(&Napot::new(0x8005_0000, 4 * 1024, PMP_R)));
+    }
+
+    #[test]
+    fn app_isolation() {
+        let code = Napot::new(0x8002_0000, 128 * 1024, PMP_R | PMP_X);
+        let rodata = Napot::new(0x8004_0000, 32 * 1024, PMP_R);
+        let shadow = |base| Napot::new(base, 8 * 1024, PMP_R | PMP_W);
+        let a = AppRegions {
+            code,
+            rodata,
+            ram: Napot::new(0x8004_8000, 32 * 1024, PMP_R | PMP_W),
+            shadow: shadow(0x8005_8000),
+        };
+        let b = AppRegions { ram: Napot::new(0x8006_0000, 32 * 1024, PMP_R | PMP_W), shadow: shadow(0x8006_8000), ..a };
+        // Shared read-only code and rodata are fine ...
+        assert!(a.isolated_from(&b));
+        // ... shared writable memory isn't, whichever side writes it.
+        assert!(!a.isolated_from(&AppRegions { shadow: a.shadow, ..b }));
+        let b_reads_a = AppRegions { rodata: Napot::new(0x8004_8000, 4 * 1024, PMP_R), ..b };
+        assert!(a.writes_into(&b_reads_a) && !b_reads_a.writes_into(&a));
+        assert!(!a.isolated_from(&b_reads_a));
+    }
+
+    #[test]
+    fn quarantine_lookup() {
+        let code = Napot::new(0x8002_0000, 128 * 1024, PMP_R | PMP_X);
+        let app = AppRegions {
+            code,
+            rodata: Napot::new(0x8004_0000, 32 * 1024, PMP_R),
+            ram: Napot::new(0x8004_8000, 32 * 1024, PMP_R | PMP_W),
+            shadow: Napot::new(0x8005_8000, 8 * 1024, PMP_R | PMP_W),
+        };
+        assert_eq!(app.code_region_of(0x8002_0000), Some(0));
+        assert_eq!(app.code_region_of(0x8003_fffe), Some(0));
+        // Readable but not executable, or outside every region.
+        assert_eq!(app.code_region_of(0x8004_0000), None);
+        assert_eq!(app.code_region_of(0x8004_8010), None);
+        assert_eq!(app.code_region_of(0x8001_fffc), None);
+        assert!(code.contains(0x8003_ffff) && !code.contains(0x8004_0000));
+        assert!(!Napot::new(0xffff_f000, 4096, PMP_R).contains(0));
+
+        let stripped = code.without_exec();
+        assert_eq!(stripped.cfg().to_string(), "R-- NAPOT");
+        assert_eq!(stripped.pmpaddr(), code.pmpaddr());
+        assert_eq!(AppRegions { code: stripped, ..app }.code_region_of(0x8002_0000), None);
+    }
+
+    #[test]
+    fn config_as_data() {
+        let uart = Napot::new(0x1000_0000, 4096, PMP_R | PMP_W);
+        let config = PmpConfig::OFF
+            .with(uart.entry(5))
+            .with_all(&pmp_tor(6, 0x8000_0000, 0x8000_0100, PMP_R | PMP_X | PMP_L));
+        assert_eq!(config.pmpaddr[5], 0x0400_01ff);
+        assert_eq!(config.pmpcfg, [0, 0x8d00_1b00, 0, 0]);
+        assert_eq!(config.entry(5), uart.entry(5));
+        assert_eq!(config.region(5), Some((0x1000_0000, 4096)));
+        // The TOR entry's lower bound is entry 6, which is OFF itself.
+        assert_eq!(config.region(6), None);
+        assert_eq!(config.region(7), Some((0x8000_0000, 0x100)));
+        assert_eq!(config.region(0), None);
+        assert_eq!(config.active_count(), 2);
+        assert_eq!(PmpConfig::OFF.active_count(), 0);
+        assert_eq!(PmpConfig::OFF.with(PmpConfig::OFF.entry(3)), PmpConfig::OFF);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid PMP config byte")]
+    fn config_rejects_reserved_bytes() {
+        let _ = PmpConfig::OFF.with(PmpEntry { index: 0, pmpaddr: 0, cfg: PmpCfg((PMP_NAPOT | PMP_W) as u8) });
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(PmpCfg((PMP_NAPOT | PMP_R | PMP_X) as u8).to_string(), "R-X NAPOT");
+        assert_eq!(
+            PmpCfg((PMP_L | PMP_NAPOT | PMP_R | PMP_X) as u8).to_string(),
+            "R-X NAPOT locked"
+        );
+        assert_eq!(PmpCfg((PMP_TOR | PMP_R | PMP_W) as u8).to_string(), "RW- TOR");
+        assert_eq!(PmpCfg(0).to_string(), "--- OFF");
+    }
+}
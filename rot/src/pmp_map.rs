@@ -0,0 +1,157 @@
+//! The kernel's boot-time PMP map, as register values.
+//!
+//! [`compute_pmp_config`] returns every `pmpaddr` and `pmpcfg` value the
+//! kernel programs at boot.  It writes no CSR, so the exact bit patterns
+//! can be unit-tested on the host, and printed without being applied (the
+//! `pmp-dry-run` build).  The kernel evaluates it in a `const`, so a bad
+//! region or config byte still fails the build, with the entry's name
+//! ([`napot_addr_for`]).
+//!
+//! ```text
+//!   0  ROM            64K @ 0x8000_0000  locked R-X
+//!   1  M_RAM          32K @ 0x8001_0000  OFF (address only; U gets no access)
+//!   2  M_SHADOW        8K @ 0x8001_8000  OFF (likewise)
+//!   3  U_CODE        128K @ 0x8002_0000  R-X  \
+//!   4  U_RODATA       32K @ 0x8004_0000  R--   | application 0
+//!   5  U_RAM low      32K @ 0x8004_8000  RW-   | (APP_MAIN)
+//!   6  U_SHADOW        8K @ 0x8005_8000  RW-  /
+//!   7  UART            4K @ 0x1000_0000  RW-
+//!   8  U_GUARD         4K @ 0x8005_7000  --- (NAPOT: overrides entry 9)
+//!   9  U_RAM high     32K @ 0x8005_0000  RW-
+//!  10  U_RECOVERY      4K @ 0x8007_0000  R-X with quarantine, else OFF
+//! ```
+//!
+//! Entries 11-15 are OFF.
+
+use crate::pmp::{
+    napot_addr_for, AppRegions, Napot, PmpCfg, PmpConfig, PmpEntry, PMP_L, PMP_NAPOT, PMP_R, PMP_W, PMP_X,
+};
+
+/// The PMP entries an application owns, in [`AppRegions::regions`] order.
+pub const APP_PMP_ENTRIES: [usize; 4] = [3, 4, 5, 6];
+
+/// The U-mode firmware in U_CODE: application 0, entered at boot.
+pub const APP_MAIN: AppRegions = AppRegions {
+    code: Napot::new(0x8002_0000, 128 * 1024, PMP_R | PMP_X),
+    rodata: Napot::new(0x8004_0000, 32 * 1024, PMP_R),
+    ram: Napot::new(0x8004_8000, 32 * 1024, PMP_R | PMP_W),
+    shadow: Napot::new(0x8005_8000, 8 * 1024, PMP_R | PMP_W),
+};
+
+const fn entry(index: usize, pmpaddr: u32, cfg: u32) -> PmpEntry {
+    PmpEntry { index, pmpaddr, cfg: PmpCfg(cfg as u8) }
+}
+
+/// The boot configuration, with application 0 in the application
+/// entries.  `recovery` enables entry 10 (`quarantine-policy` builds).
+pub const fn compute_pmp_config(recovery: bool) -> PmpConfig {
+    let app = APP_MAIN.regions();
+    PmpConfig::OFF
+        // Locked: M-mode can't write its own code either.
+        .with(entry(0, napot_addr_for("PMP entry 0 (ROM)", 0x8000_0000, 64 * 1024), PMP_L | PMP_NAPOT | PMP_R | PMP_X))
+        // Unlocked entries don't bind M-mode, and with no entry granting
+        // them U-mode has no access: these two only need their address.
+        .with(entry(1, napot_addr_for("PMP entry 1 (M_RAM)", 0x8001_0000, 32 * 1024), 0))
+        .with(entry(2, napot_addr_for("PMP entry 2 (M_SHADOW)", 0x8001_8000, 8 * 1024), 0))
+        .with(app[0].entry(APP_PMP_ENTRIES[0]))
+        .with(app[1].entry(APP_PMP_ENTRIES[1]))
+        .with(app[2].entry(APP_PMP_ENTRIES[2]))
+        .with(app[3].entry(APP_PMP_ENTRIES[3]))
+        .with(entry(7, napot_addr_for("PMP entry 7 (UART)", 0x1000_0000, 4 * 1024), PMP_NAPOT | PMP_R | PMP_W))
+        // Active with no permissions, not OFF: an OFF entry matches
+        // nothing, and entry 9 would grant the page.
+        .with(entry(8, napot_addr_for("PMP entry 8 (U_GUARD)", 0x8005_7000, 4 * 1024), PMP_NAPOT))
+        .with(entry(
+            9,
+            napot_addr_for("PMP entry 9 (U_RAM upper half)", 0x8005_0000, 32 * 1024),
+            PMP_NAPOT | PMP_R | PMP_W,
+        ))
+        .with(entry(
+            10,
+            napot_addr_for("PMP entry 10 (U_RECOVERY)", 0x8007_0000, 4 * 1024),
+            if recovery { PMP_NAPOT | PMP_R | PMP_X } else { 0 },
+        ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pmp::PMP_ENTRY_COUNT;
+    use std::string::ToString;
+
+    #[test]
+    fn boot_config_bit_patterns() {
+        let config = compute_pmp_config(false);
+        assert_eq!(
+            config.pmpaddr,
+            [
+                0x2000_1fff, 0x2000_4fff, 0x2000_63ff, 0x2000_bfff, 0x2001_0fff, 0x2001_2fff, 0x2001_63ff, 0x0400_01ff,
+                0x2001_5dff, 0x2001_4fff, 0x2001_c1ff, 0, 0, 0, 0, 0,
+            ]
+        );
+        assert_eq!(config.pmpcfg, [0x1d00_009d, 0x1b1b_1b19, 0x0000_1b18, 0]);
+
+        // quarantine-policy only adds entry 10's config byte.
+        let recovery = compute_pmp_config(true);
+        assert_eq!(recovery.pmpaddr, config.pmpaddr);
+        assert_eq!(recovery.pmpcfg, [0x1d00_009d, 0x1b1b_1b19, 0x001d_1b18, 0]);
+    }
+
+    #[test]
+    fn boot_config_regions() {
+        let config = compute_pmp_config(true);
+        let expect: [(usize, u64, u64, &str); 9] = [
+            (0, 0x8000_0000, 64 * 1024, "R-X NAPOT locked"),
+            (3, 0x8002_0000, 128 * 1024, "R-X NAPOT"),
+            (4, 0x8004_0000, 32 * 1024, "R-- NAPOT"),
+            (5, 0x8004_8000, 32 * 1024, "RW- NAPOT"),
+            (6, 0x8005_8000, 8 * 1024, "RW- NAPOT"),
+            (7, 0x1000_0000, 4 * 1024, "RW- NAPOT"),
+            (8, 0x8005_7000, 4 * 1024, "--- NAPOT"),
+            (9, 0x8005_0000, 32 * 1024, "RW- NAPOT"),
+            (10, 0x8007_0000, 4 * 1024, "R-X NAPOT"),
+        ];
+        for (i, base, size, cfg) in expect {
+            assert_eq!(config.region(i), Some((base, size)), "entry {}", i);
+            assert_eq!(config.entry(i).cfg.to_string(), cfg, "entry {}", i);
+        }
+        let off = (0..PMP_ENTRY_COUNT).filter(|&i| config.region(i).is_none()).count();
+        assert_eq!(off, PMP_ENTRY_COUNT - expect.len());
+        assert_eq!(config.active_count(), expect.len());
+        assert_eq!(compute_pmp_config(false).active_count(), expect.len() - 1);
+
+        // The application entries are APP_MAIN's, as switch_to_app(0)
+        // would program them.
+        for (i, r) in APP_MAIN.regions().iter().enumerate() {
+            assert_eq!(config.entry(APP_PMP_ENTRIES[i]), r.entry(APP_PMP_ENTRIES[i]));
+        }
+        // Only the ROM entry is locked.
+        assert!((0..PMP_ENTRY_COUNT).all(|i| config.entry(i).cfg.locked() == (i == 0)));
+    }
+
+    #[test]
+    fn guard_page_sits_inside_upper_u_ram() {
+        let config = compute_pmp_config(false);
+        let (guard, guard_size) = config.region(8).unwrap();
+        let (hi, hi_size) = config.region(9).unwrap();
+        assert!(guard >= hi && guard + guard_size <= hi + hi_size);
+        // Lower U_RAM and the upper half are contiguous.
+        let (lo, lo_size) = config.region(5).unwrap();
+        assert_eq!(lo + lo_size, hi);
+    }
+
+    #[test]
+    fn first_match_decides() {
+        let config = compute_pmp_config(false);
+        // M_RAM and M_SHADOW: their entries are OFF, so nothing matches.
+        assert_eq!(config.matching_entry(0x8001_0000), None);
+        assert_eq!(config.matching_entry(0x8001_9ffc), None);
+        // The guard page is entry 8's, though entry 9 covers it as well.
+        assert_eq!(config.matching_entry(0x8005_7100).map(|e| e.index), Some(8));
+        assert_eq!(config.matching_entry(0x8005_0000).map(|e| e.index), Some(9));
+        assert_eq!(config.matching_entry(0x8000_0000).map(|e| e.index), Some(0));
+        // U_RECOVERY is OFF without quarantine-policy.
+        assert_eq!(config.matching_entry(0x8007_0000), None);
+        assert_eq!(compute_pmp_config(true).matching_entry(0x8007_0000).map(|e| e.index), Some(10));
+    }
+}
@@ -0,0 +1,107 @@
+//! Privilege modes and the mstatus an `mret` needs.
+//!
+//! `mret` reads where it is going from mstatus instead of from operands:
+//!
+//! ```text
+//!   privilege   <- MPP   (bits 12:11)
+//!   MIE         <- MPIE  (bit 7)
+//!   MPIE        <- 1
+//!   MPP         <- U     (the least-privileged mode)
+//!   pc          <- mepc
+//! ```
+//!
+//! That means whoever sets up an `mret` controls two things.  MPP selects
+//! the mode. MPIE selects whether M-mode interrupts are enabled
+//! afterwards, since MIE is copied from it.  [`mret_mstatus`] computes
+//! both from a [`PrivMode`], and the kernel's `return_to` is the one place
+//! that writes the result and executes the `mret`.
+//!
+//! For a lower mode MIE hardly matters.  M-mode interrupts are always
+//! taken while the hart runs below M, whatever MIE says.  Still,
+//! [`mret_mstatus`] sets MPIE for those modes, so the next trap records
+//! interrupts as on (MPIE <- MIE) instead of inheriting a stale value.
+//! A return to M-mode keeps the interrupt state the caller had.
+
+/// mstatus.MPP (bits 12:11): the mode `mret` returns to.
+pub const MSTATUS_MPP: usize = 3 << MPP_SHIFT;
+
+/// mstatus.MPIE (bit 7): the MIE that `mret` restores.
+pub const MSTATUS_MPIE: usize = 1 << 7;
+
+/// mstatus.MIE (bit 3): M-mode interrupts enabled.
+pub const MSTATUS_MIE: usize = 1 << 3;
+
+const MPP_SHIFT: u32 = 11;
+
+/// A RISC-V privilege mode, as encoded in MPP.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(usize)]
+pub enum PrivMode {
+    User = 0,
+    Supervisor = 1,
+    Machine = 3,
+}
+
+impl PrivMode {
+    /// The mode in `mstatus`'s MPP field.  The reserved encoding 2 reads
+    /// as `None`.
+    pub const fn from_mpp(mstatus: usize) -> Option<Self> {
+        match (mstatus & MSTATUS_MPP) >> MPP_SHIFT {
+            0 => Some(PrivMode::User),
+            1 => Some(PrivMode::Supervisor),
+            3 => Some(PrivMode::Machine),
+            _ => None,
+        }
+    }
+}
+
+/// `mstatus` with MPP and MPIE set for an `mret` into `mode`.  All other
+/// bits are copied unchanged.  MPIE is set for User and Supervisor.  For
+/// Machine it is a copy of `mstatus`'s MIE, so pass the value read
+/// before interrupts were masked for the return.
+pub const fn mret_mstatus(mstatus: usize, mode: PrivMode) -> usize {
+    let mpie = match mode {
+        PrivMode::Machine => mstatus & MSTATUS_MIE != 0,
+        PrivMode::User | PrivMode::Supervisor => true,
+    };
+    let mstatus = (mstatus & !(MSTATUS_MPP | MSTATUS_MPIE)) | (mode as usize) << MPP_SHIFT;
+    if mpie {
+        mstatus | MSTATUS_MPIE
+    } else {
+        mstatus
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mpp_round_trips() {
+        for mode in [PrivMode::User, PrivMode::Supervisor, PrivMode::Machine] {
+            for mstatus in [0, usize::MAX, 0x1800, MSTATUS_MIE] {
+                assert_eq!(PrivMode::from_mpp(mret_mstatus(mstatus, mode)), Some(mode));
+            }
+        }
+        assert_eq!(PrivMode::from_mpp(2 << 11), None);
+    }
+
+    #[test]
+    fn mpie_by_mode() {
+        // Lower modes: MPIE set whatever MIE was.
+        assert_eq!(mret_mstatus(0, PrivMode::User), MSTATUS_MPIE);
+        assert_eq!(mret_mstatus(0x1800, PrivMode::Supervisor), 0x0800 | MSTATUS_MPIE);
+        // Machine: MPIE follows the caller's MIE.
+        assert_eq!(mret_mstatus(MSTATUS_MIE, PrivMode::Machine), 0x1800 | MSTATUS_MPIE | MSTATUS_MIE);
+        assert_eq!(mret_mstatus(MSTATUS_MPIE, PrivMode::Machine), 0x1800);
+    }
+
+    #[test]
+    fn other_bits_untouched() {
+        let others = !(MSTATUS_MPP | MSTATUS_MPIE);
+        for mode in [PrivMode::User, PrivMode::Supervisor, PrivMode::Machine] {
+            assert_eq!(mret_mstatus(usize::MAX, mode) & others, others);
+            assert_eq!(mret_mstatus(0, mode) & others, 0);
+        }
+    }
+}
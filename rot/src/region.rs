@@ -0,0 +1,157 @@
+//! Address ranges and the bounds checks on them.
+//!
+//! A [`Region`] is `base .. base + size`.  It is how the kernel names the
+//! parts of its memory map (U_RAM, U_CODE, the U-mode heap, `.bss`), and
+//! every "is this pointer inside that memory" question goes through
+//! [`Region::contains`] or [`Region::contains_range`] instead of being
+//! written out at each call site.
+//!
+//! The checks never compute `addr + len`.  A buffer that starts inside a
+//! region but whose length wraps the address space would pass a naive
+//! `addr + len <= end`, and the part past the wrap would be memory the
+//! caller has no business touching.  [`Region::contains_range`] works
+//! with the offset into the region instead, which can't overflow.
+//!
+//! [`Region::new`] refuses a region whose end would not fit in a `usize`,
+//! so [`Region::end`] is always exact.  Nothing in the memory map reaches
+//! the top of the address space.
+
+/// `base .. base + size`.  `base + size` fits in a `usize`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub base: usize,
+    pub size: usize,
+}
+
+impl Region {
+    pub const fn new(base: usize, size: usize) -> Region {
+        assert!(base.checked_add(size).is_some(), "region wraps the address space");
+        Region { base, size }
+    }
+
+    /// `start .. end`, as the linker script's symbol pairs give it.
+    pub const fn from_bounds(start: usize, end: usize) -> Region {
+        assert!(start <= end, "region ends before it starts");
+        Region { base: start, size: end - start }
+    }
+
+    /// One past the last byte.
+    pub const fn end(&self) -> usize {
+        self.base + self.size
+    }
+
+    pub const fn contains(&self, addr: usize) -> bool {
+        addr >= self.base && addr - self.base < self.size
+    }
+
+    /// Whether `addr .. addr + len` lies entirely inside the region.  An
+    /// empty range counts as inside anywhere from `base` to `end`
+    /// inclusive.
+    pub const fn contains_range(&self, addr: usize, len: usize) -> bool {
+        if addr < self.base {
+            return false;
+        }
+        let offset = addr - self.base;
+        offset <= self.size && len <= self.size - offset
+    }
+
+    /// Whether the two regions share a byte.  Empty regions share none.
+    pub const fn overlaps(&self, other: &Region) -> bool {
+        self.base < other.end() && other.base < self.end() && self.size != 0 && other.size != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RAM: Region = Region::new(0x8004_8000, 0x8000);
+
+    #[test]
+    fn bounds() {
+        assert_eq!(RAM.end(), 0x8005_0000);
+        assert_eq!(Region::from_bounds(0x8004_8000, 0x8005_0000), RAM);
+        assert!(RAM.contains(RAM.base));
+        assert!(RAM.contains(RAM.end() - 1));
+        assert!(!RAM.contains(RAM.end()));
+        assert!(!RAM.contains(RAM.base - 1));
+        assert!(!RAM.contains(0));
+        assert!(!RAM.contains(usize::MAX));
+
+        let empty = Region::new(0x1000, 0);
+        assert!(!empty.contains(0x1000));
+        assert!(empty.contains_range(0x1000, 0));
+        assert!(!empty.contains_range(0x1000, 1));
+    }
+
+    #[test]
+    fn ranges() {
+        assert!(RAM.contains_range(RAM.base, RAM.size));
+        assert!(RAM.contains_range(RAM.base + 16, 32));
+        assert!(RAM.contains_range(RAM.end() - 1, 1));
+        assert!(!RAM.contains_range(RAM.end() - 1, 2));
+        assert!(!RAM.contains_range(RAM.base - 1, 2));
+        assert!(!RAM.contains_range(RAM.base, RAM.size + 1));
+        // Empty ranges: inside up to and including the end, not past it.
+        assert!(RAM.contains_range(RAM.base, 0));
+        assert!(RAM.contains_range(RAM.end(), 0));
+        assert!(!RAM.contains_range(RAM.end() + 1, 0));
+        assert!(!RAM.contains_range(RAM.base - 1, 0));
+    }
+
+    #[test]
+    fn lengths_that_wrap_are_refused() {
+        // addr + len wraps to just past base: a naive end check passes.
+        let wrap = usize::MAX - (RAM.base + 16) + RAM.base + 2;
+        assert!(!RAM.contains_range(RAM.base + 16, wrap));
+        assert!(!RAM.contains_range(RAM.base, usize::MAX));
+        assert!(!RAM.contains_range(RAM.end() - 1, usize::MAX));
+        assert!(!RAM.contains_range(usize::MAX, 1));
+        assert!(!RAM.contains_range(usize::MAX, usize::MAX));
+    }
+
+    #[test]
+    fn regions_at_the_edges_of_the_address_space() {
+        let low = Region::new(0, 0x100);
+        assert!(low.contains(0));
+        assert!(low.contains_range(0, 0x100));
+        assert!(!low.contains_range(0xff, 2));
+
+        let high = Region::new(usize::MAX - 0xff, 0xff);
+        assert_eq!(high.end(), usize::MAX);
+        assert!(high.contains(usize::MAX - 1));
+        assert!(!high.contains(usize::MAX));
+        assert!(high.contains_range(usize::MAX - 1, 1));
+        assert!(!high.contains_range(usize::MAX - 1, 2));
+        assert!(high.contains_range(usize::MAX, 0));
+
+        let all = Region::new(0, usize::MAX);
+        assert!(all.contains_range(0, usize::MAX));
+        assert!(all.contains_range(1, usize::MAX - 1));
+        assert!(!all.contains_range(1, usize::MAX));
+    }
+
+    #[test]
+    fn overlaps() {
+        let next = Region::new(RAM.end(), 0x1000);
+        assert!(!RAM.overlaps(&next) && !next.overlaps(&RAM));
+        let straddle = Region::new(RAM.end() - 4, 8);
+        assert!(RAM.overlaps(&straddle) && straddle.overlaps(&next));
+        let inside = Region::new(RAM.base + 0x100, 0x10);
+        assert!(RAM.overlaps(&inside) && inside.overlaps(&RAM));
+        assert!(RAM.overlaps(&RAM));
+        assert!(!RAM.overlaps(&Region::new(RAM.base + 0x100, 0)));
+    }
+
+    #[test]
+    #[should_panic(expected = "wraps")]
+    fn a_region_that_wraps_is_refused() {
+        Region::new(usize::MAX - 0xff, 0x100);
+    }
+
+    #[test]
+    #[should_panic(expected = "ends before")]
+    fn inverted_bounds_are_refused() {
+        Region::from_bounds(0x2000, 0x1000);
+    }
+}
@@ -0,0 +1,165 @@
+//! The S-mode timer: Sstc, or the SBI `set_timer` call.
+//!
+//! An S-mode kernel hosted on this M-mode layer programs its own timer in
+//! one of two ways, and which one it gets depends on the hardware:
+//!
+//! - **Sstc.**  With menvcfg.STCE set, S-mode writes `stimecmp` itself
+//!   and the hart raises mip.STIP whenever `time >= stimecmp`, with no
+//!   trap to M-mode.  STCE is WARL: on a hart without Sstc it reads back
+//!   clear, which is how the boot tells the two apart
+//!   ([`STimerPath::from_menvcfgh`]).
+//! - **SBI.**  Without Sstc, S-mode calls the SBI TIME extension's
+//!   `set_timer(stime_value)`.  M-mode keeps the deadline in an
+//!   [`STimer`], arms `mtimecmp` for it and clears STIP.  When the machine
+//!   timer fires past the deadline, it sets STIP for S-mode.
+//!
+//! `set_timer` is served on either path, as the SBI spec asks.  With
+//! Sstc, M-mode writes `stimecmp` on S-mode's behalf.
+//!
+//! On RV32, `stime_value` is 64 bits split across a0 (low) and a1
+//! (high).  The SBI result is an error code in a0 and a value in a1.
+
+/// menvcfgh bit 31 (menvcfg bit 63): STCE, S-mode timer compare (Sstc).
+pub const ENVCFGH_STCE: u32 = 1 << 31;
+
+/// mip / mie bit 5: the supervisor timer interrupt.
+pub const MIP_STIP: usize = 1 << 5;
+
+/// SBI TIME extension ID ("TIME").
+pub const EID_TIME: usize = 0x5449_4D45;
+/// `sbi_set_timer`, function 0 of [`EID_TIME`].
+pub const FID_SET_TIMER: usize = 0;
+
+pub const SBI_SUCCESS: usize = 0;
+/// -2, like the kernel's own `SYSCALL_NOT_SUPPORTED`.
+pub const SBI_ERR_NOT_SUPPORTED: usize = -2isize as usize;
+
+/// How S-mode programs its timer on this hart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum STimerPath {
+    /// `stimecmp` (Sstc), enabled by menvcfg.STCE.
+    Sstc,
+    /// The SBI `set_timer` call, served on `mtimecmp`.
+    Sbi,
+}
+
+impl STimerPath {
+    /// The path from menvcfgh as read back after setting STCE.
+    pub const fn from_menvcfgh(menvcfgh: u32) -> STimerPath {
+        if menvcfgh & ENVCFGH_STCE != 0 {
+            STimerPath::Sstc
+        } else {
+            STimerPath::Sbi
+        }
+    }
+}
+
+/// An S-mode ecall, decoded from a7 (extension), a6 (function), a0, a1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SbiCall {
+    /// `set_timer(stime_value)`.
+    SetTimer(u64),
+    /// Anything else: [`SBI_ERR_NOT_SUPPORTED`].
+    Unsupported,
+}
+
+impl SbiCall {
+    pub const fn decode(a7: usize, a6: usize, a0: usize, a1: usize) -> SbiCall {
+        if a7 == EID_TIME && a6 == FID_SET_TIMER {
+            SbiCall::SetTimer((a1 as u64) << 32 | a0 as u64)
+        } else {
+            SbiCall::Unsupported
+        }
+    }
+}
+
+/// S-mode's timer deadline on the SBI path, kept by M-mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct STimer {
+    deadline: Option<u64>,
+}
+
+impl STimer {
+    pub const fn new() -> STimer {
+        STimer { deadline: None }
+    }
+
+    /// `set_timer(deadline)`: replaces any earlier deadline.  A
+    /// deadline of `u64::MAX` never comes, which is how S-mode cancels
+    /// one.
+    pub fn set(&mut self, deadline: u64) {
+        self.deadline = (deadline != u64::MAX).then_some(deadline);
+    }
+
+    /// Whether the deadline has passed at `now`.  It fires once: a
+    /// deadline that has passed is cleared.
+    pub fn expire(&mut self, now: u64) -> bool {
+        match self.deadline {
+            Some(deadline) if now >= deadline => {
+                self.deadline = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub const fn deadline(&self) -> Option<u64> {
+        self.deadline
+    }
+}
+
+impl Default for STimer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_from_stce() {
+        assert_eq!(STimerPath::from_menvcfgh(ENVCFGH_STCE), STimerPath::Sstc);
+        assert_eq!(STimerPath::from_menvcfgh(u32::MAX), STimerPath::Sstc);
+        // WARL: a hart without Sstc reads the bit back clear.
+        assert_eq!(STimerPath::from_menvcfgh(0), STimerPath::Sbi);
+        assert_eq!(STimerPath::from_menvcfgh(!ENVCFGH_STCE), STimerPath::Sbi);
+    }
+
+    #[test]
+    fn set_timer_decodes() {
+        assert_eq!(SbiCall::decode(EID_TIME, FID_SET_TIMER, 0x89ab_cdef, 0x0123_4567), SbiCall::SetTimer(0x0123_4567_89ab_cdef));
+        assert_eq!(SbiCall::decode(EID_TIME, 1, 0, 0), SbiCall::Unsupported);
+        // The legacy set_timer (EID 0) and the kernel's own numbers aren't SBI.
+        assert_eq!(SbiCall::decode(0, 0, 0, 0), SbiCall::Unsupported);
+        assert_eq!(SbiCall::decode(11, FID_SET_TIMER, 0, 0), SbiCall::Unsupported);
+        assert_eq!(SBI_ERR_NOT_SUPPORTED, usize::MAX - 1);
+    }
+
+    #[test]
+    fn deadline_fires_once() {
+        let mut t = STimer::new();
+        assert!(!t.expire(u64::MAX));
+        t.set(1000);
+        assert!(!t.expire(999));
+        assert!(t.expire(1000));
+        assert!(!t.expire(2000));
+        assert_eq!(t.deadline(), None);
+    }
+
+    #[test]
+    fn later_calls_replace_and_cancel() {
+        let mut t = STimer::new();
+        t.set(1000);
+        t.set(5000);
+        assert!(!t.expire(1000));
+        assert_eq!(t.deadline(), Some(5000));
+        t.set(u64::MAX);
+        assert_eq!(t.deadline(), None);
+        assert!(!t.expire(u64::MAX));
+        // A deadline already in the past fires at the next check.
+        t.set(0);
+        assert!(t.expire(0));
+    }
+}
@@ -0,0 +1,301 @@
+//! Sealing to a PCR policy.
+//!
+//! A sealed blob can only be opened while the PCRs it was sealed to still
+//! hold the values they had at sealing time: the same ROM, the same
+//! firmware, the same runtime measurements.  The policy is a PCR mask
+//! (bit `i` selects PCR `i`), and the selected values go into the key:
+//!
+//! ```text
+//!   policy    = SHA-256(mask || PCR[i] for each i in mask, ascending)
+//!   enc, mac  = HKDF-SHA256(salt = policy, ikm = secret, info = POLICY_SEAL_INFO)
+//!               64 bytes: the encryption key, then the MAC key
+//!   stream    = HMAC(enc, nonce || 0) || HMAC(enc, nonce || 1) || ...
+//! ```
+//!
+//! Blob layout (multi-byte fields big-endian, see [`wire`](crate::wire)):
+//!
+//! ```text
+//!   offset   size  field
+//!   0        4     magic       "PSL1"
+//!   4        4     mask        the policy's PCRs
+//!   8        16    nonce
+//!   24       len   ciphertext  data XOR stream
+//!   24+len   32    tag         HMAC(mac, bytes 0 .. 24+len)
+//! ```
+//!
+//! [`unseal`] rebuilds the policy from the blob's mask and the PCRs as
+//! they are *now*.  If any selected PCR has moved, so have the keys, the
+//! tag doesn't verify and nothing is decrypted.  A blob that was altered
+//! fails the same way, and the caller can't tell the two apart.
+//!
+//! The nonce must be fresh for each seal: two blobs under the same policy
+//! and nonce share a key stream.  [`seal`] asks the caller's entropy
+//! source for it (the kernel's is its DRBG) and fails with
+//! [`SealError::NoEntropy`] rather than seal under a nonce it didn't get.
+
+use crate::erase::secure_zero;
+use crate::hmac::{ct_eq, hkdf_expand, hkdf_extract, hmac_sha256, HmacSha256, TAG_LEN};
+use crate::measure::PCR_COUNT;
+use crate::sha256::{Sha256, DIGEST_LEN};
+use crate::wire::write_u32_be;
+
+/// Blob start marker (and format version).
+pub const SEAL_MAGIC: [u8; 4] = *b"PSL1";
+
+/// HKDF `info` for the policy-sealing keys.
+pub const POLICY_SEAL_INFO: &[u8] = b"rot-policy-seal-v1";
+
+/// Nonce length.
+pub const NONCE_LEN: usize = 16;
+
+/// Bytes before the ciphertext.
+pub const HEADER_LEN: usize = 8 + NONCE_LEN;
+
+/// Blob length minus data length.
+pub const OVERHEAD: usize = HEADER_LEN + TAG_LEN;
+
+/// Why sealing or unsealing failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SealError {
+    /// The mask selects a PCR that doesn't exist.
+    BadMask,
+    /// The output buffer is too small.
+    BufferTooSmall,
+    /// Too short to be a blob, or the wrong magic.
+    Malformed,
+    /// The tag doesn't verify: a selected PCR has changed since sealing,
+    /// or the blob was altered.
+    PolicyMismatch,
+    /// No nonce: the entropy source couldn't supply one (the kernel's
+    /// DRBG before it is seeded).
+    NoEntropy,
+}
+
+/// The policy digest for the PCRs in `mask`, as they are in `pcrs`.
+pub fn policy_digest(pcrs: &[[u8; DIGEST_LEN]; PCR_COUNT], mask: u32) -> Result<[u8; DIGEST_LEN], SealError> {
+    if mask >> PCR_COUNT != 0 {
+        return Err(SealError::BadMask);
+    }
+    let mut h = Sha256::new();
+    h.update(&mask.to_be_bytes());
+    for (i, pcr) in pcrs.iter().enumerate() {
+        if mask & (1 << i) != 0 {
+            h.update(pcr);
+        }
+    }
+    Ok(h.finalize())
+}
+
+/// The encryption and MAC keys for `policy`.
+fn keys(secret: &[u8], policy: &[u8; DIGEST_LEN]) -> [u8; 2 * DIGEST_LEN] {
+    let mut prk = hkdf_extract(policy, secret);
+    let mut okm = [0u8; 2 * DIGEST_LEN];
+    hkdf_expand(&prk, POLICY_SEAL_INFO, &mut okm);
+    secure_zero(&mut prk);
+    okm
+}
+
+/// XOR `buf` with the key stream for `nonce`.
+fn apply_stream(enc: &[u8], nonce: &[u8], buf: &mut [u8]) {
+    let mut block_in = [0u8; NONCE_LEN + 4];
+    block_in[..NONCE_LEN].copy_from_slice(nonce);
+    for (i, chunk) in buf.chunks_mut(DIGEST_LEN).enumerate() {
+        write_u32_be(block_in.last_chunk_mut().unwrap(), i as u32);
+        let mut block = hmac_sha256(enc, &block_in);
+        for (b, k) in chunk.iter_mut().zip(&block) {
+            *b ^= k;
+        }
+        secure_zero(&mut block);
+    }
+}
+
+fn tag(mac: &[u8], body: &[u8]) -> [u8; TAG_LEN] {
+    let mut h = HmacSha256::new(mac);
+    h.update(body);
+    h.finalize()
+}
+
+/// Seal `data` under `secret` to the PCRs in `mask`, writing the blob
+/// into `out` and returning its length ([`OVERHEAD`] + `data.len()`).
+/// The nonce comes from `entropy`, which fills it and returns `true`, or
+/// returns `false` if it has nothing to give.  It is only asked once the
+/// other arguments have been checked.  `out` is untouched on an error.
+pub fn seal(
+    secret: &[u8],
+    pcrs: &[[u8; DIGEST_LEN]; PCR_COUNT],
+    mask: u32,
+    entropy: impl FnOnce(&mut [u8; NONCE_LEN]) -> bool,
+    data: &[u8],
+    out: &mut [u8],
+) -> Result<usize, SealError> {
+    let policy = policy_digest(pcrs, mask)?;
+    let len = OVERHEAD + data.len();
+    let blob = out.get_mut(..len).ok_or(SealError::BufferTooSmall)?;
+    let mut nonce = [0u8; NONCE_LEN];
+    if !entropy(&mut nonce) {
+        return Err(SealError::NoEntropy);
+    }
+    let mut keys = keys(secret, &policy);
+    let (enc, mac) = keys.split_at(DIGEST_LEN);
+
+    blob[..4].copy_from_slice(&SEAL_MAGIC);
+    write_u32_be(blob[4..].first_chunk_mut().unwrap(), mask);
+    blob[8..HEADER_LEN].copy_from_slice(&nonce);
+    let (body, tag_out) = blob.split_at_mut(HEADER_LEN + data.len());
+    body[HEADER_LEN..].copy_from_slice(data);
+    apply_stream(enc, &nonce, &mut body[HEADER_LEN..]);
+    tag_out.copy_from_slice(&tag(mac, body));
+    secure_zero(&mut keys);
+    Ok(len)
+}
+
+/// The PCR mask a blob was sealed to, if it looks like a blob.
+pub fn sealed_mask(blob: &[u8]) -> Option<u32> {
+    if blob.len() < OVERHEAD || blob[..4] != SEAL_MAGIC {
+        return None;
+    }
+    Some(u32::from_be_bytes([blob[4], blob[5], blob[6], blob[7]]))
+}
+
+/// Open `blob` under `secret` against the PCRs as they are in `pcrs`,
+/// writing the data into `out` and returning its length.  `out` is
+/// untouched on an error: the tag is checked before anything is
+/// decrypted.
+pub fn unseal(
+    secret: &[u8],
+    pcrs: &[[u8; DIGEST_LEN]; PCR_COUNT],
+    blob: &[u8],
+    out: &mut [u8],
+) -> Result<usize, SealError> {
+    let mask = sealed_mask(blob).ok_or(SealError::Malformed)?;
+    let policy = policy_digest(pcrs, mask)?;
+    let (body, found) = blob.split_at(blob.len() - TAG_LEN);
+    let data = out.get_mut(..body.len() - HEADER_LEN).ok_or(SealError::BufferTooSmall)?;
+    let mut keys = keys(secret, &policy);
+    let (enc, mac) = keys.split_at(DIGEST_LEN);
+
+    let result = if ct_eq(&tag(mac, body), found) {
+        data.copy_from_slice(&body[HEADER_LEN..]);
+        apply_stream(enc, &body[8..HEADER_LEN], data);
+        Ok(data.len())
+    } else {
+        Err(SealError::PolicyMismatch)
+    };
+    secure_zero(&mut keys);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: [u8; 32] = [0x42; 32];
+    const NONCE: [u8; NONCE_LEN] = [7; NONCE_LEN];
+
+    fn pcrs() -> [[u8; DIGEST_LEN]; PCR_COUNT] {
+        core::array::from_fn(|i| [i as u8 + 1; DIGEST_LEN])
+    }
+
+    fn nonce(n: &mut [u8; NONCE_LEN]) -> bool {
+        *n = NONCE;
+        true
+    }
+
+    fn sealed(mask: u32, data: &[u8]) -> std::vec::Vec<u8> {
+        let mut blob = std::vec![0u8; OVERHEAD + data.len()];
+        assert_eq!(seal(&SECRET, &pcrs(), mask, nonce, data, &mut blob), Ok(blob.len()));
+        blob
+    }
+
+    #[test]
+    fn round_trip() {
+        for data in [&b""[..], b"k", b"a policy-bound key of more than one 32-byte block"] {
+            let blob = sealed(0b0011, data);
+            assert_eq!(&blob[..8], b"PSL1\0\0\0\x03");
+            assert_eq!(&blob[8..HEADER_LEN], &NONCE);
+            if data.len() > 1 {
+                assert_ne!(&blob[HEADER_LEN..HEADER_LEN + data.len()], data);
+            }
+            let mut out = [0u8; 64];
+            assert_eq!(unseal(&SECRET, &pcrs(), &blob, &mut out), Ok(data.len()));
+            assert_eq!(&out[..data.len()], data);
+        }
+    }
+
+    #[test]
+    fn changing_a_selected_pcr_makes_unseal_fail() {
+        let blob = sealed(0b0011, b"secret");
+        let mut out = [0xeeu8; 16];
+
+        let mut changed = pcrs();
+        changed[1][31] ^= 1;
+        assert_eq!(unseal(&SECRET, &changed, &blob, &mut out), Err(SealError::PolicyMismatch));
+        assert_eq!(out, [0xee; 16]);
+
+        // A PCR outside the policy may change freely.
+        let mut other = pcrs();
+        other[2] = [0; DIGEST_LEN];
+        assert_eq!(unseal(&SECRET, &other, &blob, &mut out), Ok(6));
+        assert_eq!(&out[..6], b"secret");
+    }
+
+    #[test]
+    fn altered_blobs_and_other_secrets_are_refused() {
+        let blob = sealed(0b0001, b"secret");
+        let mut out = [0u8; 16];
+        for i in 0..blob.len() {
+            let mut bad = blob.clone();
+            bad[i] ^= 0x80;
+            assert!(unseal(&SECRET, &pcrs(), &bad, &mut out).is_err(), "byte {i}");
+        }
+        // Widening the mask to take in another PCR is an alteration too.
+        let mut widened = blob.clone();
+        widened[7] = 0b0011;
+        assert_eq!(unseal(&SECRET, &pcrs(), &widened, &mut out), Err(SealError::PolicyMismatch));
+        assert_eq!(unseal(&[0x43; 32], &pcrs(), &blob, &mut out), Err(SealError::PolicyMismatch));
+    }
+
+    #[test]
+    fn policies_differ_by_mask_and_value() {
+        let p = pcrs();
+        let a = policy_digest(&p, 0b0001).unwrap();
+        assert_ne!(a, policy_digest(&p, 0b0011).unwrap());
+        assert_ne!(policy_digest(&p, 0).unwrap(), a);
+        // The mask goes in: PCR0 alone isn't PCR1 alone with PCR1 = PCR0.
+        let mut same = p;
+        same[1] = same[0];
+        assert_ne!(policy_digest(&same, 0b0001).unwrap(), policy_digest(&same, 0b0010).unwrap());
+        assert_eq!(policy_digest(&p, 1 << PCR_COUNT), Err(SealError::BadMask));
+    }
+
+    #[test]
+    fn no_nonce_no_blob() {
+        let mut out = [0u8; OVERHEAD + 6];
+        assert_eq!(seal(&SECRET, &pcrs(), 1, |_| false, b"secret", &mut out), Err(SealError::NoEntropy));
+        assert_eq!(out, [0; OVERHEAD + 6]);
+        // A bad request is refused before any entropy is spent on it.
+        let asked = core::cell::Cell::new(false);
+        let spy = |_: &mut [u8; NONCE_LEN]| {
+            asked.set(true);
+            true
+        };
+        assert_eq!(seal(&SECRET, &pcrs(), 1, spy, b"too long", &mut out), Err(SealError::BufferTooSmall));
+        assert!(!asked.get());
+    }
+
+    #[test]
+    fn bad_buffers() {
+        let mut small = [0u8; OVERHEAD + 5];
+        assert_eq!(seal(&SECRET, &pcrs(), 1, nonce, b"secret", &mut small), Err(SealError::BufferTooSmall));
+        assert_eq!(small, [0; OVERHEAD + 5]);
+        assert_eq!(seal(&SECRET, &pcrs(), 1 << 31, nonce, b"", &mut small), Err(SealError::BadMask));
+
+        let blob = sealed(1, b"secret");
+        assert_eq!(unseal(&SECRET, &pcrs(), &blob, &mut [0u8; 5]), Err(SealError::BufferTooSmall));
+        assert_eq!(unseal(&SECRET, &pcrs(), &blob[..OVERHEAD - 1], &mut [0u8; 8]), Err(SealError::Malformed));
+        let mut wrong_magic = blob.clone();
+        wrong_magic[3] = b'2';
+        assert_eq!(unseal(&SECRET, &pcrs(), &wrong_magic, &mut [0u8; 8]), Err(SealError::Malformed));
+        assert_eq!(sealed_mask(&blob), Some(1));
+    }
+}
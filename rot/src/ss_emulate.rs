@@ -0,0 +1,307 @@
+//! Zicfiss emulated in the trap handler, for cores that trap on it.
+//!
+//! The shadow-stack instructions are Zimop encodings, so what a core
+//! without Zicfiss does with them depends on Zimop:
+//!
+//! ```text
+//!   Zicfiss            the hardware shadow stack
+//!   Zimop, no Zicfiss  a NOP: nothing checked, nothing trapped
+//!   neither            illegal instruction
+//! ```
+//!
+//! In the last case the `ss-emulate` build's trap handler does what the
+//! hardware would have: it [`decode`]s the instruction at mepc, pushes or
+//! pops-and-compares against a [`ShadowStack`] whose pointer M-mode keeps
+//! in place of the `ssp` CSR, and steps mepc over it.  The same `sspush`
+//! / `sspopchk` then check returns on the first kind of core and the
+//! last.  The software sequence next to them stays: on a Zimop core
+//! nothing traps, so there is nothing to emulate, and the emulated stack
+//! lies in memory U-mode can write anyway.
+//!
+//! Decoded: `sspush` / `sspopchk` of ra or t0, their compressed forms
+//! (`c.sspush ra`, `c.sspopchk t0`), `ssrdp`, and the CSR instructions
+//! on `ssp`.  `ssamoswap` isn't: nothing here uses it.
+//!
+//! The emulation is stricter than the hardware on one point: a push or
+//! pop that would leave the shadow-stack region is a fault
+//! ([`SsFault::Bounds`]) rather than an access to whatever lies beyond.
+
+use crate::cfi_encoding::{self, RA, T0};
+use crate::misalign::insn_len;
+use crate::region::Region;
+
+/// The `ssp` CSR.
+pub const CSR_SSP: u32 = 0x011;
+
+/// Bytes per shadow-stack entry: XLEN on RV32.
+pub const ENTRY_LEN: usize = 4;
+
+/// `c.sspush ra` (`c.mop.1`).
+pub const C_SSPUSH_RA: u16 = 0x6081;
+
+/// `c.sspopchk t0` (`c.mop.5`).
+pub const C_SSPOPCHK_T0: u16 = 0x6281;
+
+/// A shadow-stack instruction the trap handler emulates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SsInsn {
+    /// Push `x[rs]`.
+    Push { rs: u8 },
+    /// Pop the top entry and fault unless it equals `x[rs]`.
+    PopChk { rs: u8 },
+    /// `x[rd]` <- ssp.
+    ReadPointer { rd: u8 },
+    /// `x[rd]` <- ssp, then ssp <- `op`(ssp, `src`).
+    Csr { op: CsrOp, rd: u8, src: CsrSrc },
+}
+
+/// Which CSR instruction: `csrrw`, `csrrs` or `csrrc`, or an immediate
+/// form of one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CsrOp {
+    Write,
+    Set,
+    Clear,
+}
+
+/// A CSR instruction's operand.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CsrSrc {
+    Reg(u8),
+    Imm(u8),
+}
+
+impl CsrOp {
+    /// The new CSR value.  `None` for a set or clear with `x0` or a zero
+    /// immediate, which doesn't write the CSR.
+    pub const fn apply(self, old: usize, src: CsrSrc, value: usize) -> Option<usize> {
+        let nothing = matches!(src, CsrSrc::Reg(0) | CsrSrc::Imm(0));
+        match self {
+            CsrOp::Write => Some(value),
+            CsrOp::Set if !nothing => Some(old | value),
+            CsrOp::Clear if !nothing => Some(old & !value),
+            _ => None,
+        }
+    }
+}
+
+const SYSTEM: u32 = 0b111_0011;
+
+/// `ssrdp` with its rd field cleared.
+const RD_MASK: u32 = 0x1f << 7;
+const SSRDP_X0: u32 = cfi_encoding::ssrdp(1) & !RD_MASK;
+
+/// Decode `insn` (a compressed one in its low halfword) into the
+/// instruction and its length, or `None` if it isn't one this module
+/// emulates.
+pub fn decode(insn: u32) -> Option<(SsInsn, usize)> {
+    if insn_len(insn as u16) == 2 {
+        return match insn as u16 {
+            C_SSPUSH_RA => Some((SsInsn::Push { rs: RA as u8 }, 2)),
+            C_SSPOPCHK_T0 => Some((SsInsn::PopChk { rs: T0 as u8 }, 2)),
+            _ => None,
+        };
+    }
+    let op = match insn {
+        w if w == cfi_encoding::sspush(RA) => SsInsn::Push { rs: RA as u8 },
+        w if w == cfi_encoding::sspush(T0) => SsInsn::Push { rs: T0 as u8 },
+        w if w == cfi_encoding::sspopchk(RA) => SsInsn::PopChk { rs: RA as u8 },
+        w if w == cfi_encoding::sspopchk(T0) => SsInsn::PopChk { rs: T0 as u8 },
+        w if w & !RD_MASK == SSRDP_X0 && w & RD_MASK != 0 => {
+            SsInsn::ReadPointer { rd: (w >> 7 & 0x1f) as u8 }
+        }
+        w if w & 0x7f == SYSTEM && w >> 20 == CSR_SSP => {
+            let rd = (w >> 7 & 0x1f) as u8;
+            let field = (w >> 15 & 0x1f) as u8;
+            let (op, src) = match w >> 12 & 0b111 {
+                0b001 => (CsrOp::Write, CsrSrc::Reg(field)),
+                0b010 => (CsrOp::Set, CsrSrc::Reg(field)),
+                0b011 => (CsrOp::Clear, CsrSrc::Reg(field)),
+                0b101 => (CsrOp::Write, CsrSrc::Imm(field)),
+                0b110 => (CsrOp::Set, CsrSrc::Imm(field)),
+                0b111 => (CsrOp::Clear, CsrSrc::Imm(field)),
+                _ => return None,
+            };
+            SsInsn::Csr { op, rd, src }
+        }
+        _ => return None,
+    };
+    Some((op, 4))
+}
+
+/// Why an emulated instruction faults instead of completing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SsFault {
+    /// `sspopchk` found `found` on top where the register holds
+    /// `expected`: Zicfiss's software-check exception (mtval 3).
+    Mismatch { expected: usize, found: usize },
+    /// The access at `addr` would leave the shadow-stack region, or the
+    /// memory refused it.
+    Bounds { addr: usize },
+}
+
+/// An emulated shadow stack: the pointer the `ssp` CSR would hold, and the
+/// region it may push into.  Grows down, as Zicfiss's does: a push
+/// decrements `ssp`, then stores, so the top entry is at `ssp`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ShadowStack {
+    pub ssp: usize,
+    bounds: Region,
+}
+
+impl ShadowStack {
+    pub const fn new(bounds: Region, ssp: usize) -> ShadowStack {
+        ShadowStack { ssp, bounds }
+    }
+
+    /// `sspush`: `store(addr, value)` at the new top.  On a fault `ssp`
+    /// is unchanged.
+    pub fn push(&mut self, value: usize, store: impl FnOnce(usize, usize) -> bool) -> Result<(), SsFault> {
+        let addr = self.ssp.wrapping_sub(ENTRY_LEN);
+        if !self.holds(addr) || !store(addr, value) {
+            return Err(SsFault::Bounds { addr });
+        }
+        self.ssp = addr;
+        Ok(())
+    }
+
+    /// `sspopchk`: `load(addr)` the top and compare it with `expected`.
+    /// On a fault `ssp` is unchanged.
+    pub fn pop_check(&mut self, expected: usize, load: impl FnOnce(usize) -> Option<usize>) -> Result<(), SsFault> {
+        let addr = self.ssp;
+        let found = match self.holds(addr).then(|| load(addr)).flatten() {
+            Some(found) => found,
+            None => return Err(SsFault::Bounds { addr }),
+        };
+        if found != expected {
+            return Err(SsFault::Mismatch { expected, found });
+        }
+        self.ssp = addr + ENTRY_LEN;
+        Ok(())
+    }
+
+    /// Whether an entry at `addr` lies inside the region, aligned.
+    fn holds(&self, addr: usize) -> bool {
+        addr.is_multiple_of(ENTRY_LEN) && self.bounds.contains_range(addr, ENTRY_LEN)
+    }
+
+    /// Entries pushed, counted from the region's top.
+    pub const fn depth(&self) -> usize {
+        self.bounds.end().saturating_sub(self.ssp) / ENTRY_LEN
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    const REGION: Region = Region::new(0x8005_8000, 16);
+
+    #[test]
+    fn decodes_the_shadow_stack_instructions() {
+        assert_eq!(decode(cfi_encoding::SSPUSH_RA), Some((SsInsn::Push { rs: 1 }, 4)));
+        assert_eq!(decode(cfi_encoding::sspush(T0)), Some((SsInsn::Push { rs: 5 }, 4)));
+        assert_eq!(decode(cfi_encoding::SSPOPCHK_RA), Some((SsInsn::PopChk { rs: 1 }, 4)));
+        assert_eq!(decode(cfi_encoding::SSPOPCHK_T0), Some((SsInsn::PopChk { rs: 5 }, 4)));
+        assert_eq!(decode(cfi_encoding::SSRDP_T1), Some((SsInsn::ReadPointer { rd: 6 }, 4)));
+        assert_eq!(decode(C_SSPUSH_RA as u32), Some((SsInsn::Push { rs: 1 }, 2)));
+        assert_eq!(decode(C_SSPOPCHK_T0 as u32), Some((SsInsn::PopChk { rs: 5 }, 2)));
+        // csrr a0, ssp / csrw ssp, t0 / csrrsi x0, ssp, 4
+        let csr = |funct3: u32, rs1: u32, rd: u32| CSR_SSP << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | SYSTEM;
+        assert_eq!(
+            decode(csr(0b010, 0, 10)),
+            Some((SsInsn::Csr { op: CsrOp::Set, rd: 10, src: CsrSrc::Reg(0) }, 4))
+        );
+        assert_eq!(
+            decode(csr(0b001, 5, 0)),
+            Some((SsInsn::Csr { op: CsrOp::Write, rd: 0, src: CsrSrc::Reg(5) }, 4))
+        );
+        assert_eq!(
+            decode(csr(0b110, 4, 0)),
+            Some((SsInsn::Csr { op: CsrOp::Set, rd: 0, src: CsrSrc::Imm(4) }, 4))
+        );
+    }
+
+    #[test]
+    fn leaves_everything_else() {
+        let csr = |csr: u32, funct3: u32| csr << 20 | funct3 << 12 | 10 << 7 | SYSTEM;
+        for insn in [
+            0x0000_0013,                        // nop
+            0x0000_0017,                        // lpad 0
+            SSRDP_X0,                           // ssrdp x0: a plain MOP
+            cfi_encoding::sspush(RA) | 10 << 7, // MOP.RR.7 with rd set
+            csr(0x300, 0b010),                  // csrr a0, mstatus
+            csr(CSR_SSP, 0b100),                // funct3 100: a MOP, not a CSR op
+            0x0000_0073,                        // ecall
+            0x0000_0001,                        // c.nop
+            0x6181,                             // c.mop.3
+        ] {
+            assert_eq!(decode(insn), None, "{insn:#010x}");
+        }
+    }
+
+    #[test]
+    fn csr_writes() {
+        assert_eq!(CsrOp::Write.apply(0x10, CsrSrc::Reg(0), 0), Some(0));
+        assert_eq!(CsrOp::Set.apply(0x10, CsrSrc::Reg(5), 0x3), Some(0x13));
+        assert_eq!(CsrOp::Clear.apply(0x13, CsrSrc::Imm(3), 0x3), Some(0x10));
+        // csrr and csrrsi / csrrci with 0 only read.
+        assert_eq!(CsrOp::Set.apply(0x10, CsrSrc::Reg(0), 0), None);
+        assert_eq!(CsrOp::Clear.apply(0x10, CsrSrc::Imm(0), 0), None);
+    }
+
+    #[test]
+    fn push_and_pop_check_a_call_chain() {
+        let mut mem = BTreeMap::new();
+        let mut ss = ShadowStack::new(REGION, REGION.end());
+        for ra in [0x8002_0010, 0x8002_0020, 0x8002_0030] {
+            ss.push(ra, |a, v| mem.insert(a, v).is_none()).unwrap();
+        }
+        assert_eq!(ss.ssp, REGION.end() - 12);
+        assert_eq!(ss.depth(), 3);
+        assert_eq!(mem[&ss.ssp], 0x8002_0030);
+        for ra in [0x8002_0030, 0x8002_0020, 0x8002_0010] {
+            ss.pop_check(ra, |a| mem.get(&a).copied()).unwrap();
+        }
+        assert_eq!((ss.ssp, ss.depth()), (REGION.end(), 0));
+    }
+
+    #[test]
+    fn a_forged_return_is_a_mismatch() {
+        let mut mem = BTreeMap::new();
+        let mut ss = ShadowStack::new(REGION, REGION.end());
+        ss.push(0x8002_0010, |a, v| mem.insert(a, v).is_none()).unwrap();
+        let top = ss.ssp;
+        assert_eq!(
+            ss.pop_check(0xdead_beef, |a| mem.get(&a).copied()),
+            Err(SsFault::Mismatch { expected: 0xdead_beef, found: 0x8002_0010 })
+        );
+        // Nothing popped: the report sees the stack as it was.
+        assert_eq!(ss.ssp, top);
+    }
+
+    #[test]
+    fn stays_inside_its_region() {
+        let mut mem = BTreeMap::new();
+        let mut ss = ShadowStack::new(REGION, REGION.end());
+        for i in 0..4 {
+            ss.push(i, |a, v| mem.insert(a, v).is_none()).unwrap();
+        }
+        assert_eq!(ss.push(4, |_, _| true), Err(SsFault::Bounds { addr: REGION.base - 4 }));
+        assert_eq!(ss.ssp, REGION.base);
+
+        // Popping an empty stack reads past the region's top.
+        let mut empty = ShadowStack::new(REGION, REGION.end());
+        assert_eq!(empty.pop_check(0, |_| Some(0)), Err(SsFault::Bounds { addr: REGION.end() }));
+        // ssp is whatever the CSR writes made it: misaligned or outside.
+        let mut skewed = ShadowStack::new(REGION, REGION.end() - 2);
+        assert_eq!(skewed.push(0, |_, _| true), Err(SsFault::Bounds { addr: REGION.end() - 6 }));
+        let mut wild = ShadowStack::new(REGION, 0);
+        assert_eq!(wild.push(0, |_, _| true), Err(SsFault::Bounds { addr: usize::MAX - 3 }));
+        // A store the memory refuses leaves ssp alone.
+        let mut refused = ShadowStack::new(REGION, REGION.end());
+        assert!(refused.push(0, |_, _| false).is_err());
+        assert_eq!(refused.ssp, REGION.end());
+    }
+}
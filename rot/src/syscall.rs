@@ -0,0 +1,166 @@
+//! Service-call registration.
+//!
+//! Each service call is an entry in a table of [`Syscall`]s: its number
+//! and the handler that serves it.  The kernel's dispatcher looks the
+//! caller's a7 up with [`lookup`].  A number that isn't registered comes
+//! back as `None`, and the caller gets `SYSCALL_NOT_SUPPORTED`.  Adding a
+//! service means adding an entry next to its definition, not another arm
+//! in the dispatcher.
+//!
+//! The lookup is a linear scan, bounded by the table's length.  The table
+//! is a few dozen entries at most, and a scan stays correct however the
+//! numbers are spread and whichever features compile entries in or out.
+//! [`check`] runs at compile time over the kernel's table, so two entries
+//! for one number, or an entry in the monitor namespace ([`crate::monitor`]),
+//! fail the build instead of shadowing each other.
+//!
+//! The handler type is a parameter.  The kernel's handlers read their
+//! arguments from the caller's trap frame and write the results back.
+
+use crate::monitor::MONITOR_BIT;
+
+/// One registered service call.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Syscall<H> {
+    /// The a7 value that selects it.
+    pub number: u32,
+    pub handler: H,
+}
+
+/// Why [`check`] rejected a table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TableError {
+    /// The number is registered more than once.
+    Duplicate(u32),
+    /// The number has [`MONITOR_BIT`] set, so the dispatcher would never
+    /// reach it: monitor calls have a table of their own.
+    MonitorBit(u32),
+}
+
+/// The handler registered for `number`, or `None`.
+pub fn lookup<H: Copy>(table: &[Syscall<H>], number: u32) -> Option<H> {
+    table.iter().find(|s| s.number == number).map(|s| s.handler)
+}
+
+/// One past the largest number in `table`: a number nothing registered,
+/// for a test that wants `SYSCALL_NOT_SUPPORTED` back whatever features
+/// the build has.  0 for an empty table.
+pub const fn past_last<H>(table: &[Syscall<H>]) -> u32 {
+    let mut last = None;
+    let mut i = 0;
+    while i < table.len() {
+        let number = table[i].number;
+        last = match last {
+            Some(n) if n >= number => Some(n),
+            _ => Some(number),
+        };
+        i += 1;
+    }
+    match last {
+        Some(n) => n + 1,
+        None => 0,
+    }
+}
+
+/// Whether every entry in `table` can be reached: no number registered
+/// twice, none in the monitor namespace.  A `const fn`, for a
+/// compile-time assert on a static table.
+pub const fn check<H>(table: &[Syscall<H>]) -> Result<(), TableError> {
+    let mut i = 0;
+    while i < table.len() {
+        let number = table[i].number;
+        if number & MONITOR_BIT != 0 {
+            return Err(TableError::MonitorBit(number));
+        }
+        let mut j = i + 1;
+        while j < table.len() {
+            if table[j].number == number {
+                return Err(TableError::Duplicate(number));
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stands in for the kernel's trap frame.
+    #[derive(Default)]
+    struct Frame {
+        a0: usize,
+        a1: usize,
+    }
+
+    type Handler = fn(&mut Frame);
+
+    fn dispatch(table: &[Syscall<Handler>], number: u32, frame: &mut Frame) -> bool {
+        match lookup(table, number) {
+            Some(handler) => {
+                handler(frame);
+                true
+            }
+            None => false,
+        }
+    }
+
+    const TABLE: &[Syscall<Handler>] = &[
+        Syscall { number: 0, handler: |f| f.a0 += 1 },
+        Syscall { number: 7, handler: |f| f.a0 = f.a0.wrapping_mul(f.a1) },
+    ];
+
+    const _: () = assert!(matches!(check(TABLE), Ok(())));
+
+    #[test]
+    fn custom_syscall_is_dispatched() {
+        // Registering a service is one more entry.
+        let custom: Handler = |f| {
+            f.a0 = 0xc0de;
+            f.a1 = 2;
+        };
+        let mut table = TABLE.to_vec();
+        table.push(Syscall { number: 42, handler: custom });
+        assert_eq!(check(&table), Ok(()));
+
+        let mut f = Frame { a0: 6, a1: 7 };
+        assert!(dispatch(&table, 7, &mut f));
+        assert_eq!(f.a0, 42);
+        assert!(dispatch(&table, 42, &mut f));
+        assert_eq!((f.a0, f.a1), (0xc0de, 2));
+        assert!(dispatch(&table, 0, &mut f));
+        assert_eq!(f.a0, 0xc0df);
+    }
+
+    #[test]
+    fn unregistered_numbers_miss() {
+        let mut f = Frame::default();
+        for number in [1, 6, 8, 42, 0x7fff_ffff, u32::MAX] {
+            assert!(!dispatch(TABLE, number, &mut f));
+        }
+        assert_eq!((f.a0, f.a1), (0, 0));
+        assert!(lookup::<Handler>(&[], 0).is_none());
+    }
+
+    #[test]
+    fn past_last_is_unregistered() {
+        assert_eq!(past_last(TABLE), 8);
+        assert!(lookup(TABLE, past_last(TABLE)).is_none());
+        let h: Handler = |_| {};
+        let unordered = [Syscall { number: 9, handler: h }, Syscall { number: 2, handler: h }];
+        assert_eq!(past_last(&unordered), 10);
+        assert_eq!(past_last::<Handler>(&[]), 0);
+    }
+
+    #[test]
+    fn unreachable_entries_are_rejected() {
+        let h: Handler = |_| {};
+        let dup = [Syscall { number: 3, handler: h }, Syscall { number: 4, handler: h }, Syscall { number: 3, handler: h }];
+        assert_eq!(check(&dup), Err(TableError::Duplicate(3)));
+        let monitor = [Syscall { number: MONITOR_BIT | 1, handler: h }];
+        assert_eq!(check(&monitor), Err(TableError::MonitorBit(MONITOR_BIT | 1)));
+        assert_eq!(check::<Handler>(&[]), Ok(()));
+    }
+}
@@ -0,0 +1,297 @@
+//! Tag-length-value records for the host wire formats.
+//!
+//! A record is a one-byte tag, a big-endian 16-bit length and that many
+//! value bytes; a message is records back to back:
+//!
+//! ```text
+//!   offset   size  field
+//!   0        1     tag
+//!   1        2     len    big-endian, see [`wire`](crate::wire)
+//!   3        len   value
+//!   3+len    ...   next record
+//! ```
+//!
+//! A format built from records can grow a field without breaking older
+//! readers: they skip tags they don't know.  Tag numbers belong to the
+//! format using them; this module gives none of them a meaning.
+//!
+//! [`TlvWriter`] appends records to a fixed buffer and refuses one that
+//! doesn't fit, leaving the buffer as it was.  [`TlvReader`] walks a
+//! message from the host or from flash, checking each length against
+//! what is left; on a record that runs off the end it yields an error and
+//! then stops, so a truncated or corrupt message can't be read past.
+
+/// Bytes before the value: tag and length.
+pub const HEADER_LEN: usize = 3;
+
+/// The longest value a record can carry.
+pub const MAX_VALUE_LEN: usize = u16::MAX as usize;
+
+/// The header of a record tagged `tag` with a `len`-byte value.
+pub const fn header(tag: u8, len: u16) -> [u8; HEADER_LEN] {
+    let [hi, lo] = len.to_be_bytes();
+    [tag, hi, lo]
+}
+
+/// Why a record couldn't be written or read.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TlvError {
+    /// The record doesn't fit in what is left of the writer's buffer.
+    BufferTooSmall,
+    /// The value is longer than [`MAX_VALUE_LEN`].
+    ValueTooLong,
+    /// The message ends inside a record's header or value.
+    Truncated,
+}
+
+/// Appends records to a caller-supplied buffer.
+#[derive(Debug)]
+pub struct TlvWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> TlvWriter<'a> {
+    /// An empty message in `buf`.
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        TlvWriter { buf, len: 0 }
+    }
+
+    /// Append a record.  Nothing is written on an error.
+    pub fn push(&mut self, tag: u8, value: &[u8]) -> Result<(), TlvError> {
+        self.push_with(tag, value.len(), |v| v.copy_from_slice(value))
+    }
+
+    /// Append a record with a `len`-byte value that `fill` writes in
+    /// place, for a value that isn't in one piece.  On an error nothing is
+    /// written and `fill` isn't called.
+    pub fn push_with(&mut self, tag: u8, len: usize, fill: impl FnOnce(&mut [u8])) -> Result<(), TlvError> {
+        if len > MAX_VALUE_LEN {
+            return Err(TlvError::ValueTooLong);
+        }
+        let end = self.len + HEADER_LEN + len;
+        let rec = self.buf.get_mut(self.len..end).ok_or(TlvError::BufferTooSmall)?;
+        rec[..HEADER_LEN].copy_from_slice(&header(tag, len as u16));
+        fill(&mut rec[HEADER_LEN..]);
+        self.len = end;
+        Ok(())
+    }
+
+    /// Append a record holding `value` big-endian.
+    pub fn push_u32(&mut self, tag: u8, value: u32) -> Result<(), TlvError> {
+        self.push(tag, &value.to_be_bytes())
+    }
+
+    /// Bytes written so far.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The message written.
+    pub fn finish(self) -> &'a [u8] {
+        &self.buf[..self.len]
+    }
+}
+
+/// One record, borrowed from the message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tlv<'a> {
+    pub tag: u8,
+    pub value: &'a [u8],
+}
+
+impl Tlv<'_> {
+    /// The value as a big-endian `u32`, if it is exactly four bytes.
+    pub fn as_u32(&self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.value.try_into().ok()?))
+    }
+}
+
+/// Iterates over the records of a message.
+///
+/// Yields `Err(TlvError::Truncated)` once for a record that runs off the
+/// end, then `None`.
+#[derive(Clone, Debug)]
+pub struct TlvReader<'a> {
+    rest: &'a [u8],
+    failed: bool,
+}
+
+impl<'a> TlvReader<'a> {
+    pub const fn new(msg: &'a [u8]) -> Self {
+        TlvReader { rest: msg, failed: false }
+    }
+
+    /// The value of the first record tagged `tag`.  `Ok(None)` if the
+    /// message is well formed up to its end and has no such record.
+    pub fn find(msg: &'a [u8], tag: u8) -> Result<Option<&'a [u8]>, TlvError> {
+        for rec in TlvReader::new(msg) {
+            let rec = rec?;
+            if rec.tag == tag {
+                return Ok(Some(rec.value));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<'a> Iterator for TlvReader<'a> {
+    type Item = Result<Tlv<'a>, TlvError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.rest.is_empty() {
+            return None;
+        }
+        let rec = match self.rest {
+            [tag, hi, lo, rest @ ..] => {
+                let len = u16::from_be_bytes([*hi, *lo]) as usize;
+                rest.split_at_checked(len).map(|(value, rest)| (Tlv { tag: *tag, value }, rest))
+            }
+            _ => None,
+        };
+        match rec {
+            Some((rec, rest)) => {
+                self.rest = rest;
+                Some(Ok(rec))
+            }
+            None => {
+                self.failed = true;
+                Some(Err(TlvError::Truncated))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::Rng;
+    use std::vec::Vec;
+
+    fn records(msg: &[u8]) -> Vec<Result<Tlv<'_>, TlvError>> {
+        TlvReader::new(msg).collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut buf = [0u8; 64];
+        let mut w = TlvWriter::new(&mut buf);
+        assert!(w.is_empty());
+        w.push(1, b"RTQ1").unwrap();
+        w.push(2, b"").unwrap();
+        w.push_u32(3, 0x0102_0304).unwrap();
+        assert_eq!(w.len(), 3 * HEADER_LEN + 8);
+        w.push_with(4, 3, |v| v.copy_from_slice(b"abc")).unwrap();
+        let msg = w.finish();
+        assert_eq!(msg, b"\x01\0\x04RTQ1\x02\0\0\x03\0\x04\x01\x02\x03\x04\x04\0\x03abc");
+
+        let recs = records(msg);
+        assert_eq!(
+            recs,
+            [
+                Ok(Tlv { tag: 1, value: b"RTQ1" }),
+                Ok(Tlv { tag: 2, value: b"" }),
+                Ok(Tlv { tag: 3, value: &[1, 2, 3, 4] }),
+                Ok(Tlv { tag: 4, value: b"abc" }),
+            ]
+        );
+        assert_eq!(recs[2].unwrap().as_u32(), Some(0x0102_0304));
+        assert_eq!(recs[0].unwrap().as_u32(), Some(0x5254_5131));
+        assert_eq!(recs[1].unwrap().as_u32(), None);
+        assert!(records(&[]).is_empty());
+    }
+
+    #[test]
+    fn long_values_round_trip() {
+        let value: Vec<u8> = (0..=255u8).cycle().take(MAX_VALUE_LEN).collect();
+        let mut buf = std::vec![0u8; HEADER_LEN + MAX_VALUE_LEN + 1];
+        let mut w = TlvWriter::new(&mut buf);
+        w.push(0xff, &value).unwrap();
+        assert_eq!(w.push(0, &[0; MAX_VALUE_LEN + 1]), Err(TlvError::ValueTooLong));
+        assert_eq!(w.push_with(0, MAX_VALUE_LEN + 1, |_| unreachable!()), Err(TlvError::ValueTooLong));
+        let msg = w.finish();
+        assert_eq!(&msg[..HEADER_LEN], [0xff, 0xff, 0xff]);
+        assert_eq!(records(msg), [Ok(Tlv { tag: 0xff, value: &value })]);
+    }
+
+    #[test]
+    fn a_record_that_does_not_fit_is_not_written() {
+        let mut buf = [0xeeu8; 8];
+        let mut w = TlvWriter::new(&mut buf);
+        w.push(1, b"ab").unwrap();
+        assert_eq!(w.push(2, b"c"), Err(TlvError::BufferTooSmall));
+        assert_eq!(w.push_with(2, 1, |_| unreachable!()), Err(TlvError::BufferTooSmall));
+        assert_eq!(w.len(), 5);
+        w.push(2, b"").unwrap();
+        assert_eq!(w.push(3, b""), Err(TlvError::BufferTooSmall));
+        assert_eq!(buf, [1, 0, 2, b'a', b'b', 2, 0, 0]);
+    }
+
+    #[test]
+    fn find_skips_other_tags() {
+        let mut buf = [0u8; 32];
+        let mut w = TlvWriter::new(&mut buf);
+        w.push(9, b"unknown to the reader").unwrap();
+        w.push(1, b"pcr").unwrap();
+        let msg = w.finish();
+        assert_eq!(TlvReader::find(msg, 1), Ok(Some(&b"pcr"[..])));
+        assert_eq!(TlvReader::find(msg, 2), Ok(None));
+        // A missing tag in a cut-off message isn't reported as absent.
+        assert_eq!(TlvReader::find(&msg[..msg.len() - 1], 2), Err(TlvError::Truncated));
+    }
+
+    #[test]
+    fn truncated_messages_are_rejected_and_end_iteration() {
+        let msg = b"\x01\0\x02ab\x02\0\x03xyz";
+        let first = Ok(Tlv { tag: 1, value: b"ab" });
+        assert_eq!(records(&msg[..5]), [first]);
+        for cut in (1..5).chain(6..msg.len()) {
+            let recs = records(&msg[..cut]);
+            let (last, good) = recs.split_last().unwrap();
+            assert_eq!(*last, Err(TlvError::Truncated), "cut at {cut}");
+            assert_eq!(good, &[first][..usize::from(cut > 5)], "cut at {cut}");
+        }
+        // A length running past the end, even with bytes after it.
+        assert_eq!(records(b"\x07\xff\xffabc"), [Err(TlvError::Truncated)]);
+    }
+
+    #[test]
+    fn fuzzed_messages_never_panic() {
+        let mut rng = Rng(0x7e57_0001);
+        for _ in 0..2000 {
+            let mut buf = [0u8; 48];
+            let mut w = TlvWriter::new(&mut buf);
+            loop {
+                let tag = rng.next_u32() as u8;
+                let len = rng.below(12);
+                if w.push(tag, &rng.bytes(len)).is_err() {
+                    break;
+                }
+            }
+            let len = w.len();
+            let mut msg = buf[..len].to_vec();
+            rng.mutate(&mut msg);
+            let msg = &msg[..rng.below(len + 1)];
+
+            // Every record accepted lies inside the message, and they
+            // account for all of it unless the walk ended in an error.
+            let mut used = 0;
+            let mut failed = false;
+            for rec in TlvReader::new(msg) {
+                match rec {
+                    Ok(rec) => used += HEADER_LEN + rec.value.len(),
+                    Err(e) => {
+                        assert_eq!(e, TlvError::Truncated);
+                        failed = true;
+                    }
+                }
+            }
+            assert!(used <= msg.len());
+            assert_eq!(used == msg.len(), !failed);
+        }
+    }
+}
@@ -0,0 +1,262 @@
+//! Trap cause decoding.
+//!
+//! `mcause` packs two things into one XLEN-wide register:
+//!
+//!   - bit XLEN-1 — **Interrupt**: 1 = asynchronous interrupt, 0 = exception
+//!   - bits XLEN-2..0 — **Exception Code**: meaning depends on the bit above
+//!
+//! [`TrapCause::from_mcause`] turns the raw value into a named variant so
+//! diagnostics can say `trap: EcallFromUMode` rather than `0x00000008`.
+
+use core::fmt;
+
+use crate::privilege::PrivMode;
+
+/// mcause Interrupt bit (bit XLEN-1).
+pub const MCAUSE_INTERRUPT: usize = 1 << (usize::BITS - 1);
+
+/// Software-check `mtval`: a Zicfilp landing-pad fault.
+pub const SOFTWARE_CHECK_LANDING_PAD: usize = 2;
+/// Software-check `mtval`: a Zicfiss shadow-stack fault.
+pub const SOFTWARE_CHECK_SHADOW_STACK: usize = 3;
+
+/// The instruction a forward-edge CFI violation jumped to, if the trap is
+/// one.  For a landing-pad fault (software check, `mtval` = 2) that is
+/// mepc: the target without an `lpad`.  An instruction access fault
+/// leaves the address in mtval instead.  A shadow-stack fault is a
+/// corrupted return address, not a bad target, so it has none.
+pub const fn forward_edge_target(mcause: usize, mepc: usize, mtval: usize) -> Option<usize> {
+    match TrapCause::from_mcause(mcause) {
+        TrapCause::SoftwareCheck if mtval == SOFTWARE_CHECK_LANDING_PAD => Some(mepc),
+        TrapCause::InstructionAccessFault => Some(mtval),
+        _ => None,
+    }
+}
+
+/// What a contained CFI violation costs (`cfi-contain` builds).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Containment {
+    /// Halt the system: the violation was in the kernel.
+    Fatal,
+    /// End the offending U-mode context and keep the system running.
+    Terminate,
+}
+
+/// How to handle a CFI violation, by where it came from: the mode the
+/// trap interrupted, in the saved `mstatus`'s MPP.  Only U-mode code can
+/// be cut off on its own.  A violation in M-mode means the kernel's own
+/// control flow is compromised, and a reserved MPP means the saved state
+/// can't be trusted, so both are fatal.  So is S-mode, which this kernel
+/// never runs.
+pub const fn containment(mstatus: usize) -> Containment {
+    match PrivMode::from_mpp(mstatus) {
+        Some(PrivMode::User) => Containment::Terminate,
+        _ => Containment::Fatal,
+    }
+}
+
+/// Decoded `mcause` value (privileged spec, exception/interrupt codes).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrapCause {
+    // ── Exceptions (Interrupt bit clear) ─────────────────────────────
+    /// 0 — Instruction address misaligned
+    InstructionMisaligned,
+    /// 1 — Instruction access fault (PMP, or Zicfilp landing-pad violation)
+    InstructionAccessFault,
+    /// 2 — Illegal instruction
+    IllegalInstruction,
+    /// 3 — Breakpoint (`ebreak`)
+    Breakpoint,
+    /// 4 — Load address misaligned
+    LoadMisaligned,
+    /// 5 — Load access fault
+    LoadAccessFault,
+    /// 6 — Store/AMO address misaligned
+    StoreMisaligned,
+    /// 7 — Store/AMO access fault
+    StoreAccessFault,
+    /// 8 — Environment call from U-mode
+    EcallFromUMode,
+    /// 9 — Environment call from S-mode
+    EcallFromSMode,
+    /// 11 — Environment call from M-mode
+    EcallFromMMode,
+    /// 12 — Instruction page fault
+    InstructionPageFault,
+    /// 13 — Load page fault
+    LoadPageFault,
+    /// 15 — Store/AMO page fault
+    StorePageFault,
+    /// 18 — Software check (CFI: `mtval` = 2 landing-pad fault,
+    ///      `mtval` = 3 shadow-stack fault)
+    SoftwareCheck,
+    /// 19 — Hardware error
+    HardwareError,
+    /// Any other (reserved / custom) exception code
+    UnknownException(usize),
+
+    // ── Interrupts (Interrupt bit set) ───────────────────────────────
+    /// 1 — Supervisor software interrupt
+    SupervisorSoftwareInterrupt,
+    /// 3 — Machine software interrupt
+    MachineSoftwareInterrupt,
+    /// 5 — Supervisor timer interrupt
+    SupervisorTimerInterrupt,
+    /// 7 — Machine timer interrupt
+    MachineTimerInterrupt,
+    /// 9 — Supervisor external interrupt
+    SupervisorExternalInterrupt,
+    /// 11 — Machine external interrupt
+    MachineExternalInterrupt,
+    /// Any other (reserved / platform) interrupt code
+    UnknownInterrupt(usize),
+}
+
+impl TrapCause {
+    /// Decode a raw `mcause` value.
+    pub const fn from_mcause(mcause: usize) -> TrapCause {
+        let code = mcause & !MCAUSE_INTERRUPT;
+        if mcause & MCAUSE_INTERRUPT != 0 {
+            match code {
+                1 => TrapCause::SupervisorSoftwareInterrupt,
+                3 => TrapCause::MachineSoftwareInterrupt,
+                5 => TrapCause::SupervisorTimerInterrupt,
+                7 => TrapCause::MachineTimerInterrupt,
+                9 => TrapCause::SupervisorExternalInterrupt,
+                11 => TrapCause::MachineExternalInterrupt,
+                _ => TrapCause::UnknownInterrupt(code),
+            }
+        } else {
+            match code {
+                0 => TrapCause::InstructionMisaligned,
+                1 => TrapCause::InstructionAccessFault,
+                2 => TrapCause::IllegalInstruction,
+                3 => TrapCause::Breakpoint,
+                4 => TrapCause::LoadMisaligned,
+                5 => TrapCause::LoadAccessFault,
+                6 => TrapCause::StoreMisaligned,
+                7 => TrapCause::StoreAccessFault,
+                8 => TrapCause::EcallFromUMode,
+                9 => TrapCause::EcallFromSMode,
+                11 => TrapCause::EcallFromMMode,
+                12 => TrapCause::InstructionPageFault,
+                13 => TrapCause::LoadPageFault,
+                15 => TrapCause::StorePageFault,
+                18 => TrapCause::SoftwareCheck,
+                19 => TrapCause::HardwareError,
+                _ => TrapCause::UnknownException(code),
+            }
+        }
+    }
+
+    /// `true` for asynchronous interrupts, `false` for synchronous exceptions.
+    pub const fn is_interrupt(&self) -> bool {
+        matches!(
+            self,
+            TrapCause::SupervisorSoftwareInterrupt
+                | TrapCause::MachineSoftwareInterrupt
+                | TrapCause::SupervisorTimerInterrupt
+                | TrapCause::MachineTimerInterrupt
+                | TrapCause::SupervisorExternalInterrupt
+                | TrapCause::MachineExternalInterrupt
+                | TrapCause::UnknownInterrupt(_)
+        )
+    }
+
+    /// The mode an ecall was made from, or `None` if this isn't an ecall.
+    /// Each mode traps with its own cause (8, 9, 11), so a handler can
+    /// give each one its own service set, or none.
+    pub const fn ecall_mode(&self) -> Option<PrivMode> {
+        match self {
+            TrapCause::EcallFromUMode => Some(PrivMode::User),
+            TrapCause::EcallFromSMode => Some(PrivMode::Supervisor),
+            TrapCause::EcallFromMMode => Some(PrivMode::Machine),
+            _ => None,
+        }
+    }
+
+    /// Variant name, as printed by the trap diagnostics.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            TrapCause::InstructionMisaligned => "InstructionMisaligned",
+            TrapCause::InstructionAccessFault => "InstructionAccessFault",
+            TrapCause::IllegalInstruction => "IllegalInstruction",
+            TrapCause::Breakpoint => "Breakpoint",
+            TrapCause::LoadMisaligned => "LoadMisaligned",
+            TrapCause::LoadAccessFault => "LoadAccessFault",
+            TrapCause::StoreMisaligned => "StoreMisaligned",
+            TrapCause::StoreAccessFault => "StoreAccessFault",
+            TrapCause::EcallFromUMode => "EcallFromUMode",
+            TrapCause::EcallFromSMode => "EcallFromSMode",
+            TrapCause::EcallFromMMode => "EcallFromMMode",
+            TrapCause::InstructionPageFault => "InstructionPageFault",
+            TrapCause::LoadPageFault => "LoadPageFault",
+            TrapCause::StorePageFault => "StorePageFault",
+            TrapCause::SoftwareCheck => "SoftwareCheck",
+            TrapCause::HardwareError => "HardwareError",
+            TrapCause::UnknownException(_) => "UnknownException",
+            TrapCause::SupervisorSoftwareInterrupt => "SupervisorSoftwareInterrupt",
+            TrapCause::MachineSoftwareInterrupt => "MachineSoftwareInterrupt",
+            TrapCause::SupervisorTimerInterrupt => "SupervisorTimerInterrupt",
+            TrapCause::MachineTimerInterrupt => "MachineTimerInterrupt",
+            TrapCause::SupervisorExternalInterrupt => "SupervisorExternalInterrupt",
+            TrapCause::MachineExternalInterrupt => "MachineExternalInterrupt",
+            TrapCause::UnknownInterrupt(_) => "UnknownInterrupt",
+        }
+    }
+}
+
+impl fmt::Display for TrapCause {
+    /// Prints the variant name, e.g. `EcallFromUMode`.  Unknown codes carry
+    /// the raw exception code: `UnknownException(10)`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrapCause::UnknownException(code) | TrapCause::UnknownInterrupt(code) => {
+                write!(f, "{}({})", self.name(), code)
+            }
+            _ => f.write_str(self.name()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ecall_modes() {
+        let mode = |mcause| TrapCause::from_mcause(mcause).ecall_mode();
+        assert_eq!(mode(8), Some(PrivMode::User));
+        assert_eq!(mode(9), Some(PrivMode::Supervisor));
+        assert_eq!(mode(11), Some(PrivMode::Machine));
+        // 10 is reserved (it was ecall from H-mode), and interrupts 9 and
+        // 11 are external interrupts, not ecalls.
+        for mcause in [2, 10, MCAUSE_INTERRUPT | 9, MCAUSE_INTERRUPT | 11] {
+            assert_eq!(mode(mcause), None);
+        }
+    }
+
+    #[test]
+    fn forward_edge_targets() {
+        let (mepc, addr) = (0x8002_0100, 0x8002_0ff0);
+        assert_eq!(forward_edge_target(18, mepc, SOFTWARE_CHECK_LANDING_PAD), Some(mepc));
+        assert_eq!(forward_edge_target(1, mepc, addr), Some(addr));
+        assert_eq!(forward_edge_target(18, mepc, SOFTWARE_CHECK_SHADOW_STACK), None);
+        assert_eq!(forward_edge_target(5, mepc, addr), None);
+        // Interrupt 1 is not exception 1.
+        assert_eq!(forward_edge_target(MCAUSE_INTERRUPT | 1, mepc, addr), None);
+    }
+
+    #[test]
+    fn only_user_mode_violations_are_contained() {
+        const MPP_SHIFT: usize = 11;
+        assert_eq!(containment(0), Containment::Terminate);
+        assert_eq!(containment(3 << MPP_SHIFT), Containment::Fatal);
+        assert_eq!(containment(1 << MPP_SHIFT), Containment::Fatal);
+        assert_eq!(containment(2 << MPP_SHIFT), Containment::Fatal);
+        // Only MPP counts: MPIE, FS and the rest don't change the origin.
+        let others = !(3 << MPP_SHIFT);
+        assert_eq!(containment(others), Containment::Terminate);
+        assert_eq!(containment(others | 3 << MPP_SHIFT), Containment::Fatal);
+    }
+}
@@ -0,0 +1,47 @@
+//! Byte order on the wire.
+//!
+//! Everything the RoT sends a host verifier — attestation quotes, the
+//! measurement log, framed session payloads — puts its multi-byte
+//! integers in big-endian (network) order.  RISC-V is little-endian, so
+//! copying a `u32` out of memory as it lies would give the other order;
+//! the host would then read every field byte-swapped without anything
+//! failing loudly.  Serialisation code therefore writes fields with
+//! `to_be_bytes`, or with [`write_u32_be`] to fill one in place inside a
+//! larger buffer, and never with `to_ne_bytes` or a struct cast.
+//!
+//! Data that stays on the device (the firmware header, syscall buffers
+//! like [`perf::Sample`](crate::perf::Sample)) keeps the native
+//! little-endian layout.
+
+/// Write `value` big-endian into `out`.  To write at an offset, split the
+/// field off the buffer with `first_chunk_mut`.
+pub fn write_u32_be(out: &mut [u8; 4], value: u32) {
+    *out = value.to_be_bytes();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn most_significant_byte_first() {
+        let mut buf = [0u8; 6];
+        write_u32_be(buf[1..].first_chunk_mut().unwrap(), 0x0102_0304);
+        assert_eq!(buf, [0, 1, 2, 3, 4, 0]);
+    }
+
+    #[test]
+    fn differs_from_memory_order() {
+        let mut buf = [0u8; 4];
+        write_u32_be(&mut buf, 0xdead_beef);
+        assert_eq!(buf, [0xde, 0xad, 0xbe, 0xef]);
+        assert_ne!(buf, 0xdead_beefu32.to_le_bytes());
+    }
+
+    #[test]
+    fn short_tail_has_no_field_to_write() {
+        let mut buf = [0u8; 6];
+        assert!(buf[3..].first_chunk_mut::<4>().is_none());
+        assert_eq!(buf, [0; 6]);
+    }
+}
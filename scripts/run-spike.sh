@@ -0,0 +1,17 @@
+#!/bin/sh
+# Build the CFI demo with its HTIF console and run it under Spike.
+#
+# Spike has no 16550 UART or test finisher where the demo expects them, so
+# the `htif` feature sends output and the exit status through tohost
+# instead.  spike exits with the demo's status: 0 after "All tests
+# passed.", 0xff after a panic raised while reporting a panic.
+#
+# SPIKE names the simulator (default: spike on PATH); ISA overrides the
+# extensions it models.  Extra arguments (more --features, say) go to
+# `cargo build`.
+set -e
+root="$(cd "$(dirname "$0")/.." && pwd)"
+isa="${ISA:-rv32imac_zicsr_zicfilp_zicfiss_zimop_zcmop}"
+cd "$root/cfi"
+cargo build --release --features htif "$@"
+exec "${SPIKE:-spike}" --isa="$isa" "$root/target/rv32imac-cfi-none-elf/release/riscv-cfi-baremetal"
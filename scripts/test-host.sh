@@ -0,0 +1,15 @@
+#!/bin/sh
+# Run the target-independent library unit tests on the host.
+#
+# .cargo/config.toml turns on `build-std = ["core"]` for the bare-metal
+# target, and cargo applies it to *every* target in the invocation; a
+# freshly built `core` then clashes with the sysroot `std` the test harness
+# links.  Cargo config can't be unset from the command line, so run from
+# outside the repository, where that config isn't discovered.
+#
+# Features are all enabled so feature-gated library modules get tested too.
+set -e
+root="$(cd "$(dirname "$0")/.." && pwd)"
+cd /
+exec cargo +nightly test --manifest-path "$root/Cargo.toml" \
+    --target-dir "$root/target/host" --target host-tuple --workspace --lib --all-features "$@"